@@ -0,0 +1,139 @@
+// A small record store: structs, pointers, enums, arrays and switches,
+// the way a typical hand-written C file uses them.
+
+#include <stdio.h>
+#include <stdlib.h>
+
+enum kind { KIND_NONE, KIND_INTEGER, KIND_TEXT, KIND_PAIR };
+
+struct record {
+    int id;
+    enum kind kind;
+    int value;
+    int left;
+    int right;
+    char *label;
+};
+
+struct store {
+    struct record records[64];
+    int count;
+    int next_id;
+};
+
+static struct store store;
+
+void store_init(struct store *s) {
+    s->count = 0;
+    s->next_id = 1;
+}
+
+int store_add(struct store *s, enum kind kind, int value, char *label) {
+    if (s->count >= 64) {
+        return -1;
+    }
+    struct record *r = &s->records[s->count];
+    r->id = s->next_id;
+    r->kind = kind;
+    r->value = value;
+    r->left = 0;
+    r->right = 0;
+    r->label = label;
+    s->count = s->count + 1;
+    s->next_id = s->next_id + 1;
+    return r->id;
+}
+
+struct record *store_find(struct store *s, int id) {
+    int i;
+    for (i = 0; i < s->count; i++) {
+        if (s->records[i].id == id) {
+            return &s->records[i];
+        }
+    }
+    return NULL;
+}
+
+int store_pair(struct store *s, int left, int right) {
+    int id = store_add(s, KIND_PAIR, 0, "pair");
+    struct record *r = store_find(s, id);
+    if (r == NULL) {
+        return -1;
+    }
+    r->left = left;
+    r->right = right;
+    return id;
+}
+
+int record_weight(struct store *s, int id, int depth) {
+    struct record *r = store_find(s, id);
+    if (r == NULL || depth > 8) {
+        return 0;
+    }
+    switch (r->kind) {
+    case KIND_INTEGER:
+        return r->value;
+    case KIND_TEXT:
+        return 1;
+    case KIND_PAIR:
+        return record_weight(s, r->left, depth + 1) + record_weight(s, r->right, depth + 1);
+    default:
+        return 0;
+    }
+}
+
+int count_kind(struct store *s, enum kind kind) {
+    int total = 0;
+    int i = 0;
+    while (i < s->count) {
+        if (s->records[i].kind == kind) {
+            total += 1;
+        }
+        i++;
+    }
+    return total;
+}
+
+void store_print(struct store *s) {
+    int i;
+    for (i = 0; i < s->count; i++) {
+        struct record *r = &s->records[i];
+        printf("%d %s %d\n", r->id, r->label, record_weight(s, r->id, 0));
+    }
+}
+
+int *histogram(struct store *s, int buckets) {
+    int *counts = malloc(buckets * sizeof(int));
+    int i;
+    if (counts == NULL) {
+        return NULL;
+    }
+    for (i = 0; i < buckets; i++) {
+        counts[i] = 0;
+    }
+    for (i = 0; i < s->count; i++) {
+        int bucket = s->records[i].value % buckets;
+        if (bucket < 0) {
+            bucket = -bucket;
+        }
+        counts[bucket] += 1;
+    }
+    return counts;
+}
+
+int main(void) {
+    store_init(&store);
+    int a = store_add(&store, KIND_INTEGER, 40, "forty");
+    int b = store_add(&store, KIND_INTEGER, 2, "two");
+    int c = store_add(&store, KIND_TEXT, 0, "text");
+    int p = store_pair(&store, a, b);
+    int q = store_pair(&store, p, c);
+    store_print(&store);
+    int *counts = histogram(&store, 4);
+    if (counts != NULL) {
+        printf("%d %d %d %d\n", counts[0], counts[1], counts[2], counts[3]);
+        free(counts);
+    }
+    printf("%d pairs, weight %d\n", count_kind(&store, KIND_PAIR), record_weight(&store, q, 0));
+    return 0;
+}
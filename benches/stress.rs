@@ -0,0 +1,52 @@
+// stress.rs: Streaming a very large generated file through the lexer,
+// parser and lowering (`cargo bench --bench stress [-- <megabytes>...]`).
+//
+// The file is random programs from gen-fuzz run together until it is the
+// size asked for (8 MB by default). For each size this prints how long the
+// stream took, its throughput, and the most tokens held at once, which
+// should stay the size of the largest declaration rather than grow with
+// the file.
+
+use hello_rust::fuzz;
+use hello_rust::lexer_regex::LexOptions;
+use hello_rust::stream;
+use std::time::Instant;
+
+const DEFAULT_MEGABYTES: usize = 8;
+
+/// Generated programs, one after another, until there are `bytes` of them
+fn generated_source(bytes: usize) -> String {
+    let mut source = String::with_capacity(bytes + 64 * 1024);
+    let mut seed = 0;
+    while source.len() < bytes {
+        source.push_str(&fuzz::generate(seed));
+        seed += 1;
+    }
+    source
+}
+
+fn main() {
+    // cargo passes --bench; anything else is a size
+    let sizes: Vec<usize> = std::env::args().skip(1).filter_map(|arg| arg.parse().ok()).collect();
+    let sizes = if sizes.is_empty() { vec![DEFAULT_MEGABYTES] } else { sizes };
+    for megabytes in sizes {
+        let source = generated_source(megabytes * 1024 * 1024);
+        let start = Instant::now();
+        let mut blocks = 0;
+        let summary = stream::compile(&source, LexOptions::default(), false, |graph| blocks += graph.blocks.len());
+        let seconds = start.elapsed().as_secs_f64();
+        let megabytes = source.len() as f64 / (1024.0 * 1024.0);
+        println!(
+            "{:.1} MB: {:.2}s, {:.2} MB/s, {} tokens, at most {} held at once, \
+             {} function(s), {} block(s), {} error(s)",
+            megabytes,
+            seconds,
+            megabytes / seconds,
+            summary.tokens,
+            summary.peak_tokens,
+            summary.functions,
+            blocks,
+            summary.errors.len()
+        );
+    }
+}
@@ -0,0 +1,85 @@
+// artifact.rs: Compact binary form of a compiled program for external tools.
+//
+// A visualizer or grader can load the AST and diagnostics from an artifact
+// instead of running the lexers and parser again.
+
+use crate::parser::ast::TranslationUnit;
+use crate::scope::ScopeError;
+use crate::type_checker::{TypeError, TypeWarning};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+
+/// File signature written before the encoded artifact
+const ARTIFACT_MAGIC: [u8; 4] = *b"MCAF";
+/// Bumped whenever the AST or diagnostic types change shape
+pub const ARTIFACT_VERSION: u32 = 10;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Artifact {
+    pub version: u32,
+    pub source_file: String,
+    pub ast: TranslationUnit,
+    pub scope_errors: Vec<ScopeError>,
+    pub type_errors: Vec<TypeError>,
+    pub type_warnings: Vec<TypeWarning>,
+}
+
+#[derive(Debug)]
+pub enum ArtifactError {
+    Io(io::Error),
+    Encoding(String),
+    NotAnArtifact,
+    VersionMismatch { found: u32, expected: u32 },
+}
+
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArtifactError::Io(error) => write!(f, "{}", error),
+            ArtifactError::Encoding(message) => write!(f, "malformed artifact: {}", message),
+            ArtifactError::NotAnArtifact => write!(f, "not a compiler artifact"),
+            ArtifactError::VersionMismatch { found, expected } => {
+                write!(f, "written by format v{}, this compiler reads v{}", found, expected)
+            }
+        }
+    }
+}
+
+impl Artifact {
+    pub fn new(source_file: &str, ast: TranslationUnit) -> Self {
+        Artifact {
+            version: ARTIFACT_VERSION,
+            source_file: source_file.to_string(),
+            ast,
+            scope_errors: Vec::new(),
+            type_errors: Vec::new(),
+            type_warnings: Vec::new(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ArtifactError> {
+        let encoded = bincode::serialize(self).map_err(|e| ArtifactError::Encoding(e.to_string()))?;
+        let mut bytes = Vec::with_capacity(ARTIFACT_MAGIC.len() + encoded.len());
+        bytes.extend_from_slice(&ARTIFACT_MAGIC);
+        bytes.extend_from_slice(&encoded);
+        fs::write(path, bytes).map_err(ArtifactError::Io)
+    }
+
+    pub fn load(path: &str) -> Result<Self, ArtifactError> {
+        let bytes = fs::read(path).map_err(ArtifactError::Io)?;
+        let encoded = bytes
+            .strip_prefix(&ARTIFACT_MAGIC[..])
+            .ok_or(ArtifactError::NotAnArtifact)?;
+
+        // The version is the first field, so it can be checked before
+        // decoding a layout this build might not understand
+        let version: u32 = bincode::deserialize(encoded).map_err(|_| ArtifactError::NotAnArtifact)?;
+        if version != ARTIFACT_VERSION {
+            return Err(ArtifactError::VersionMismatch { found: version, expected: ARTIFACT_VERSION });
+        }
+
+        bincode::deserialize(encoded).map_err(|e| ArtifactError::Encoding(e.to_string()))
+    }
+}
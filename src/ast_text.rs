@@ -0,0 +1,430 @@
+// ast_text.rs: `--emit=ast`, a stable text form of the AST.
+//
+// Unlike the `{:#?}` dump, this format doesn't change when Rust's Debug
+// output or the AST's field names do, so tests and graders can compare it
+// across compiler versions. It is an indented s-expression: one statement
+// or top-level item per line, expressions inline. AST_TEXT_VERSION in the
+// header is bumped whenever the output for an existing program changes.
+//
+//     (minic-ast 6
+//       (function int main ()
+//         (decl int x (= (+ (int 1) (int 2))))
+//         (return (id x))))
+
+use crate::diagnostics::Diagnostic;
+use crate::parser::ast::*;
+use crate::plugins::EmitTarget;
+
+pub const AST_TEXT_VERSION: u32 = 6;
+
+pub struct AstTextEmitter;
+
+pub(crate) fn type_name(type_specifier: &TypeSpecifier) -> String {
+    type_specifier.spelling()
+}
+
+pub(crate) fn storage_class_name(storage_class: &StorageClass) -> &'static str {
+    match storage_class {
+        StorageClass::Auto => "auto",
+        StorageClass::Register => "register",
+        StorageClass::Static => "static",
+        StorageClass::Extern => "extern",
+        StorageClass::Typedef => "typedef",
+    }
+}
+
+fn qualifier_name(qualifier: &TypeQualifier) -> &'static str {
+    match qualifier {
+        TypeQualifier::Const => "const",
+        TypeQualifier::Restrict => "restrict",
+    }
+}
+
+/// ` (attr name args...)` for each attribute
+fn attributes(attributes: &[Attribute]) -> String {
+    let mut text = String::new();
+    for attribute in attributes {
+        text.push_str(&format!(" (attr {}", attribute.name));
+        for argument in &attribute.arguments {
+            text.push(' ');
+            text.push_str(&expression(argument));
+        }
+        text.push(')');
+    }
+    text
+}
+
+/// `(function`/`(prototype`, its attributes, specifiers, return type, name and parameters
+fn signature(head: &str, function_attributes: &[Attribute], specifiers: &[FunctionSpecifier], return_type: &str, name: &str, params: &[Parameter], variadic: bool) -> String {
+    let mut text = format!("({}{}", head, attributes(function_attributes));
+    for specifier in specifiers {
+        text.push_str(match specifier {
+            FunctionSpecifier::Inline => " inline",
+            FunctionSpecifier::Noreturn => " _Noreturn",
+        });
+    }
+    text.push_str(&format!(" {} {} {}", return_type, name, parameters(params, variadic)));
+    text
+}
+
+/// A string literal with quotes and backslashes escaped
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Integer(value) => format!("(int {})", value),
+        Constant::Float(value, FloatSuffix::None) => format!("(float {:?})", value),
+        Constant::Float(value, suffix) => format!("(float {:?} {})", value, suffix.suffix()),
+        Constant::Char(value) => format!("(char {})", *value as u32),
+        Constant::Bool(value) => format!("(bool {})", value),
+        Constant::Null => "(null)".to_string(),
+        Constant::EncodedChar(encoding, value) => format!("(char {} {})", encoding.prefix(), *value as u32),
+    }
+}
+
+/// A type as the parser spells it, quoted when a declarator makes it more
+/// than a word or two: "int (*)(void)"
+fn type_spelling(spelling: &str) -> String {
+    if spelling.contains(['(', '[']) { format!("{:?}", spelling) } else { spelling.to_string() }
+}
+
+/// The parameters, with a bare `...` after them for a variadic function
+fn parameters(parameters: &[Parameter], variadic: bool) -> String {
+    let mut parameters: Vec<String> = parameters
+        .iter()
+        .map(|parameter| {
+            let mut text = String::from("(param");
+            for qualifier in &parameter.type_qualifiers {
+                text.push(' ');
+                text.push_str(qualifier_name(qualifier));
+            }
+            text.push_str(&format!(" {}", type_spelling(&parameter.param_type)));
+            if !parameter.name.is_empty() {
+                text.push_str(&format!(" {}", parameter.name));
+            }
+            text.push(')');
+            text
+        })
+        .collect();
+    if variadic {
+        parameters.push("...".to_string());
+    }
+    format!("({})", parameters.join(" "))
+}
+
+pub(crate) fn expression(expr: &Expression) -> String {
+    match expr {
+        Expression::Identifier(name) => format!("(id {})", name),
+        Expression::Constant(value) => constant(value),
+        Expression::Folded(value, name) => format!("(folded {} {})", name, constant(value)),
+        Expression::StringLiteral(text) => format!("(string {})", quote(text)),
+        Expression::EncodedStringLiteral(encoding, text) => format!("(string {} {})", encoding.prefix(), quote(text)),
+        Expression::BinaryOp(left, op, right) => {
+            format!("({} {} {})", op.symbol(), expression(left), expression(right))
+        }
+        Expression::UnaryOp(op, operand) => format!("(prefix {} {})", op.symbol(), expression(operand)),
+        Expression::Assignment(target, op, value) => {
+            format!("(assign {} {} {})", op.symbol(), expression(target), expression(value))
+        }
+        Expression::Conditional(condition, then_value, else_value) => format!(
+            "(?: {} {} {})",
+            expression(condition),
+            expression(then_value),
+            expression(else_value)
+        ),
+        Expression::FunctionCall(callee, arguments) => {
+            let mut text = format!("(call {}", expression(callee));
+            for argument in arguments {
+                text.push(' ');
+                text.push_str(&expression(argument));
+            }
+            text.push(')');
+            text
+        }
+        Expression::ArrayAccess(array, index) => format!("(index {} {})", expression(array), expression(index)),
+        Expression::MemberAccess(object, member) => format!("(. {} {})", expression(object), member),
+        Expression::PointerAccess(object, member) => format!("(-> {} {})", expression(object), member),
+        Expression::PostfixOp(operand, op) => format!("(postfix {} {})", op.symbol(), expression(operand)),
+        Expression::Cast(target, operand) => {
+            format!("(cast {} {})", type_spelling(&target.spelling()), expression(operand))
+        }
+        Expression::SizeofType(operand) => format!("(sizeof-type {})", type_spelling(&operand.spelling())),
+        Expression::Paren(inner) => format!("(paren {})", expression(inner)),
+        Expression::StatementExpr(statements) => {
+            // Statements inside an expression stay on the expression's line
+            let mut printer = Printer { out: String::new() };
+            printer.statements(0, statements);
+            format!("(stmt-expr{})", printer.out.replace('\n', " "))
+        }
+        Expression::Generic(controlling, associations) => {
+            let mut text = format!("(generic {}", expression(controlling));
+            for association in associations {
+                let type_name = association.type_name.as_deref().map_or("default".to_string(), type_spelling);
+                text.push_str(&format!(" ({} {})", type_name, expression(&association.value)));
+            }
+            text.push(')');
+            text
+        }
+        Expression::CompoundLiteral(literal) => {
+            let mut text = format!("(compound {}", literal.type_name);
+            if !literal.array_sizes.is_empty() {
+                text.push_str(&array_sizes(&literal.array_sizes));
+            }
+            let items: Vec<String> = literal.initializers.iter().map(initializer).collect();
+            text.push_str(&format!(" (list {}))", items.join(" ")));
+            text
+        }
+    }
+}
+
+/// ` (array n ...)`, with `_` for a size left out
+fn array_sizes(sizes: &[Option<Expression>]) -> String {
+    let sizes: Vec<String> = sizes.iter().map(|size| size.as_ref().map_or("_".to_string(), expression)).collect();
+    format!(" (array {})", sizes.join(" "))
+}
+
+fn initializer(init: &Initializer) -> String {
+    match &init.kind {
+        InitializerKind::Assignment(value) => expression(value),
+        InitializerKind::List(items) => {
+            let items: Vec<String> = items.iter().map(initializer).collect();
+            format!("(list {})", items.join(" "))
+        }
+        InitializerKind::Designated(Designator::Member(member), value) => {
+            format!("(designated (. {}) {})", member, initializer(value))
+        }
+        InitializerKind::Designated(Designator::Array(index), value) => {
+            format!("(designated (index {}) {})", expression(index), initializer(value))
+        }
+    }
+}
+
+/// `(decl [(attr ...)...] [storage] [const...] type name [(pointer n)] [(array ...)] [(params ...)] [(= init)])`
+pub(crate) fn declaration(declaration: &VariableDeclaration) -> String {
+    let mut text = format!("(decl{}", attributes(&declaration.attributes));
+    if let Some(storage_class) = &declaration.storage_class {
+        text.push(' ');
+        text.push_str(storage_class_name(storage_class));
+    }
+    for qualifier in &declaration.type_qualifiers {
+        text.push(' ');
+        text.push_str(qualifier_name(qualifier));
+    }
+    let declarator = &declaration.declarator;
+    text.push_str(&format!(" {} {}", type_name(&declaration.type_specifier), declarator.name));
+    if declarator.pointer_depth > 0 {
+        text.push_str(&format!(" (pointer {})", declarator.pointer_depth));
+    }
+    if let Some(pointee) = declarator.pointee_type(&declaration.type_specifier) {
+        text.push_str(&format!(" (to {})", type_spelling(&pointee.spelling())));
+    }
+    if !declarator.array_sizes.is_empty() {
+        text.push_str(&array_sizes(&declarator.array_sizes));
+    }
+    if let Some(params) = &declarator.function_params {
+        text.push(' ');
+        text.push_str(&parameters(params, false));
+    }
+    if let Some(init) = &declaration.initializer {
+        text.push_str(&format!(" (= {})", initializer(init)));
+    }
+    text.push(')');
+    text
+}
+
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    fn line(&mut self, depth: usize, text: &str) {
+        self.out.push('\n');
+        self.out.push_str(&"  ".repeat(depth));
+        self.out.push_str(text);
+    }
+
+    fn statement(&mut self, depth: usize, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Declaration(decl) => self.line(depth, &declaration(decl)),
+            StatementKind::Assignment(name, value) => {
+                self.line(depth, &format!("(set {} {})", name, expression(value)))
+            }
+            StatementKind::Return(Some(value)) => self.line(depth, &format!("(return {})", expression(value))),
+            StatementKind::Return(None) => self.line(depth, "(return)"),
+            StatementKind::Expression(expr) => self.line(depth, &format!("(expr {})", expression(expr))),
+            StatementKind::Block(statements) => {
+                self.line(depth, "(block");
+                self.statements(depth + 1, statements);
+                self.out.push(')');
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.line(depth, &format!("(if {}", expression(condition)));
+                self.statement(depth + 1, then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(depth + 1, else_branch);
+                }
+                self.out.push(')');
+            }
+            StatementKind::While(condition, body) => {
+                self.line(depth, &format!("(while {}", expression(condition)));
+                self.statement(depth + 1, body);
+                self.out.push(')');
+            }
+            StatementKind::For(init, condition, update, body) => {
+                self.line(depth, "(for");
+                match init {
+                    Some(init) => self.statement(depth + 1, init),
+                    None => self.line(depth + 1, "_"),
+                }
+                for part in [condition, update] {
+                    let text = part.as_ref().map_or("_".to_string(), expression);
+                    self.line(depth + 1, &text);
+                }
+                self.statement(depth + 1, body);
+                self.out.push(')');
+            }
+            StatementKind::Break => self.line(depth, "(break)"),
+            StatementKind::Switch(value, body) => {
+                self.line(depth, &format!("(switch {}", expression(value)));
+                self.statement(depth + 1, body);
+                self.out.push(')');
+            }
+            StatementKind::Case(value, statement) => {
+                self.line(depth, &format!("(case {}", expression(value)));
+                self.statement(depth + 1, statement);
+                self.out.push(')');
+            }
+            StatementKind::Default(statement) => {
+                self.line(depth, "(default");
+                self.statement(depth + 1, statement);
+                self.out.push(')');
+            }
+            StatementKind::Fallthrough => self.line(depth, "(fallthrough)"),
+            StatementKind::Empty => self.line(depth, "(empty)"),
+            StatementKind::Label(name, statement) => {
+                self.line(depth, &format!("(label {}", name));
+                self.statement(depth + 1, statement);
+                self.out.push(')');
+            }
+            StatementKind::Goto(name) => self.line(depth, &format!("(goto {})", name)),
+        }
+    }
+
+    fn statements(&mut self, depth: usize, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(depth, statement);
+        }
+    }
+
+    fn directive(&mut self, directive: &PreprocessorDirective) {
+        let text = match directive {
+            PreprocessorDirective::Include(path) => format!("(include {})", quote(path)),
+            PreprocessorDirective::Define(name, replacement) => {
+                let mut text = format!("(define {}", name);
+                for item in replacement {
+                    text.push(' ');
+                    match item {
+                        ReplacementItem::Identifier(name) => text.push_str(&format!("(id {})", name)),
+                        ReplacementItem::Constant(value) => text.push_str(&constant(value)),
+                        ReplacementItem::StringLiteral(literal) => {
+                            text.push_str(&format!("(string {})", quote(literal)))
+                        }
+                    }
+                }
+                text.push(')');
+                text
+            }
+            PreprocessorDirective::Ifdef(name) => format!("(ifdef {})", name),
+            PreprocessorDirective::Ifndef(name) => format!("(ifndef {})", name),
+            PreprocessorDirective::Endif => "(endif)".to_string(),
+        };
+        self.line(1, &text);
+    }
+
+    fn external_declaration(&mut self, external: &ExternalDeclaration) {
+        match external {
+            ExternalDeclaration::Variable(decl) => self.line(1, &declaration(decl)),
+            ExternalDeclaration::FunctionDeclaration(function) => {
+                let text = signature(
+                    "prototype",
+                    &function.attributes,
+                    &function.specifiers,
+                    &function.return_type,
+                    &function.name,
+                    &function.parameters,
+                    function.variadic,
+                );
+                self.line(1, &format!("{})", text));
+            }
+            ExternalDeclaration::Function(function) => {
+                let text = signature(
+                    "function",
+                    &function.attributes,
+                    &function.specifiers,
+                    &function.return_type,
+                    &function.name,
+                    &function.parameters,
+                    function.variadic,
+                );
+                self.line(1, &text);
+                self.statements(2, &function.body);
+                self.out.push(')');
+            }
+            ExternalDeclaration::Struct(definition) => {
+                self.line(1, &format!("(struct {}", definition.name));
+                for member in &definition.members {
+                    self.line(2, &declaration(member));
+                }
+                self.out.push(')');
+            }
+            ExternalDeclaration::StructDeclaration(tag, _) => self.line(1, &format!("(declare-struct {})", tag)),
+            ExternalDeclaration::Enum(definition) => {
+                self.line(1, &format!("({}", definition.head()));
+                for enumerator in &definition.enumerators {
+                    match &enumerator.value {
+                        Some(value) => self.line(2, &format!("({} {})", enumerator.name, expression(value))),
+                        None => self.line(2, &format!("({})", enumerator.name)),
+                    }
+                }
+                self.out.push(')');
+            }
+        }
+    }
+}
+
+/// The whole translation unit in the versioned text format, ending in a newline
+pub fn to_text(unit: &TranslationUnit) -> String {
+    let mut printer = Printer { out: format!("(minic-ast {}", AST_TEXT_VERSION) };
+    for directive in &unit.preprocessor_list {
+        printer.directive(directive);
+    }
+    for external in &unit.external_declarations {
+        printer.external_declaration(external);
+    }
+    printer.out.push_str(")\n");
+    printer.out
+}
+
+impl EmitTarget for AstTextEmitter {
+    fn name(&self) -> &str {
+        "ast"
+    }
+
+    fn emit(&self, unit: &TranslationUnit, _source: &str, _diagnostics: &[Diagnostic]) -> String {
+        to_text(unit)
+    }
+}
@@ -0,0 +1,261 @@
+// bench.rs: Throughput of the front end's phases (`bench`).
+//
+// Lexing, parsing and checking (scope analysis and type checking together)
+// are each timed over a few bundled inputs and reported in MB/s; each
+// phase starts from the previous one's output, prepared outside the timing.
+// A small input is run over and over until enough time has passed to
+// measure, and the fastest run taken.
+//
+// Each phase is also timed on a generated program and on one four times
+// its size. A phase that takes much more than four times as long has gone
+// superlinear somewhere, as the parser did when it counted the braces of
+// every token before the current one to see whether it was at file scope.
+//
+// `bench --lexers` times the three lexers instead, on the same inputs, so
+// a change to one can be measured before and after.
+
+use crate::fuzz;
+use crate::lexer_manual;
+use crate::lexer_regex::{self, Token};
+use crate::parser::Parser;
+use crate::parser::ast::TranslationUnit;
+use crate::rules;
+use crate::scope::ScopeAnalyzer;
+use crate::source::SourceFile;
+use crate::type_checker::TypeChecker;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// How long each phase is run over an input, at least
+const MIN_DURATION: Duration = Duration::from_millis(200);
+
+/// How much longer than the input grew a phase may take before it is
+/// reported as superlinear; a bigger input is slower per byte anyway, as
+/// less of it stays in the cache, but a quadratic phase takes 16 times as
+/// long
+pub const SCALING_TOLERANCE: f64 = 2.0;
+
+/// Size of the smaller generated program timed for scaling
+pub const DEFAULT_SCALING_BYTES: usize = 128 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Lex,
+    Parse,
+    Check,
+}
+
+pub const PHASES: [Phase; 3] = [Phase::Lex, Phase::Parse, Phase::Check];
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Lex => "lex",
+            Phase::Parse => "parse",
+            Phase::Check => "check",
+        }
+    }
+}
+
+pub struct Input {
+    pub name: &'static str,
+    pub source: String,
+}
+
+/// The inputs bundled with the compiler
+pub fn inputs() -> Vec<Input> {
+    vec![
+        Input { name: "records.c", source: include_str!("../benches/inputs/records.c").to_string() },
+        Input { name: "comprehensive_valid.c", source: include_str!("../comprehensive_valid.c").to_string() },
+        Input { name: "generated", source: generated(32 * 1024) },
+    ]
+}
+
+lazy_static! {
+    static ref FUZZ_NAME: Regex = Regex::new(r"\b([gf])(\d+)\b").unwrap();
+    static ref MAIN: Regex = Regex::new(r"\bmain\b").unwrap();
+}
+
+/// Programs from gen-fuzz run together until there are `bytes` of them,
+/// each one's globals and functions renamed so no two clash and only the
+/// first keeps `main`
+pub fn generated(bytes: usize) -> String {
+    let mut source = String::with_capacity(bytes);
+    let mut seed = 0;
+    while source.len() < bytes {
+        let program = fuzz::generate(seed);
+        if seed == 0 {
+            source.push_str(&program);
+        } else {
+            let program = FUZZ_NAME.replace_all(&program, format!("${{1}}{}_${{2}}", seed).as_str());
+            source.push_str(&MAIN.replace_all(&program, format!("main{}", seed).as_str()));
+        }
+        seed += 1;
+    }
+    source
+}
+
+fn parse(tokens: Vec<Token>) -> Option<TranslationUnit> {
+    Parser::new(tokens).parse().ok()
+}
+
+fn check(unit: &TranslationUnit, source: &Rc<SourceFile>) {
+    let mut scope_analyzer = ScopeAnalyzer::with_source(source.clone());
+    let _ = scope_analyzer.analyze_translation_unit(unit);
+    let mut type_checker = TypeChecker::new(scope_analyzer);
+    type_checker.set_require_main(false);
+    let _ = type_checker.check_translation_unit(unit);
+}
+
+/// Seconds `run` takes: the fastest of as many runs as fit in
+/// MIN_DURATION, and at least three, as the slower ones were held up by
+/// something else
+fn time(mut run: impl FnMut()) -> f64 {
+    let start = Instant::now();
+    let mut fastest = f64::INFINITY;
+    let mut runs = 0;
+    while runs < 3 || start.elapsed() < MIN_DURATION {
+        let run_start = Instant::now();
+        run();
+        fastest = fastest.min(run_start.elapsed().as_secs_f64());
+        runs += 1;
+    }
+    fastest
+}
+
+/// Seconds one run of `phase` takes over `source`; None if the source
+/// doesn't parse, for the phases after parsing
+pub fn time_phase(phase: Phase, source: &str) -> Option<f64> {
+    let tokens = lexer_regex::lex_with_regex(source);
+    match phase {
+        Phase::Lex => Some(time(|| drop(lexer_regex::lex_with_regex(source)))),
+        Phase::Parse => {
+            parse(tokens.clone())?;
+            Some(time(|| drop(parse(tokens.clone()))))
+        }
+        Phase::Check => {
+            let unit = parse(tokens)?;
+            let source = Rc::new(SourceFile::new("", source));
+            Some(time(|| check(&unit, &source)))
+        }
+    }
+}
+
+/// How much longer each phase took on a program four times the size
+pub struct Scaling {
+    pub bytes: usize,
+    pub ratios: Vec<(Phase, Option<f64>)>,
+}
+
+impl Scaling {
+    pub fn measure(bytes: usize) -> Scaling {
+        let (small, large) = (generated(bytes), generated(4 * bytes));
+        let ratios = PHASES
+            .iter()
+            .map(|&phase| {
+                let ratio = match (time_phase(phase, &small), time_phase(phase, &large)) {
+                    (Some(small_time), Some(large_time)) => Some(large_time / small_time),
+                    _ => None,
+                };
+                (phase, ratio)
+            })
+            .collect();
+        Scaling { bytes, ratios }
+    }
+
+    /// The phases whose time grew by more than the input did
+    pub fn superlinear(&self) -> Vec<Phase> {
+        self.ratios
+            .iter()
+            .filter(|(_, ratio)| ratio.is_some_and(|ratio| ratio > 4.0 * SCALING_TOLERANCE))
+            .map(|(phase, _)| *phase)
+            .collect()
+    }
+
+    pub fn render(&self) -> String {
+        let ratios: Vec<String> = self
+            .ratios
+            .iter()
+            .map(|(phase, ratio)| match ratio {
+                Some(ratio) => format!("{} x{:.1}", phase.name(), ratio),
+                None => format!("{} -", phase.name()),
+            })
+            .collect();
+        let (small, large) = (self.bytes / 1024, self.bytes * 4 / 1024);
+        let mut text = format!("scaling, generated {} KB to {} KB: {}\n", small, large, ratios.join(", "));
+        for phase in self.superlinear() {
+            text.push_str(&format!("  {} grows faster than its input\n", phase.name()));
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lexer {
+    Regex,
+    Manual,
+    Rules,
+}
+
+pub const LEXERS: [Lexer; 3] = [Lexer::Regex, Lexer::Manual, Lexer::Rules];
+
+impl Lexer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lexer::Regex => "regex",
+            Lexer::Manual => "manual",
+            Lexer::Rules => "rules",
+        }
+    }
+}
+
+/// Seconds one run of `lexer` takes over `source`
+pub fn time_lexer(lexer: Lexer, source: &str) -> f64 {
+    let options = lexer_regex::LexOptions::default();
+    match lexer {
+        Lexer::Regex => time(|| drop(lexer_regex::lex_with_regex_positions(source, options))),
+        Lexer::Manual => time(|| drop(lexer_manual::lex_manual_positions(source, options))),
+        Lexer::Rules => time(|| drop(rules::lex(source, options))),
+    }
+}
+
+/// A table of each bundled input's throughput in each lexer
+pub fn render_lexers() -> String {
+    let mut text = format!("{:<24}{:>10}", "input", "size");
+    for lexer in LEXERS {
+        text.push_str(&format!("{:>13}", format!("{} MB/s", lexer.name())));
+    }
+    text.push('\n');
+    for input in inputs() {
+        let megabytes = input.source.len() as f64 / (1024.0 * 1024.0);
+        text.push_str(&format!("{:<24}{:>7.1} KB", input.name, input.source.len() as f64 / 1024.0));
+        for lexer in LEXERS {
+            text.push_str(&format!("{:>13.2}", megabytes / time_lexer(lexer, &input.source)));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// A table of each bundled input's throughput in each phase
+pub fn render_throughput() -> String {
+    let mut text = format!("{:<24}{:>10}", "input", "size");
+    for phase in PHASES {
+        text.push_str(&format!("{:>12}", format!("{} MB/s", phase.name())));
+    }
+    text.push('\n');
+    for input in inputs() {
+        let megabytes = input.source.len() as f64 / (1024.0 * 1024.0);
+        text.push_str(&format!("{:<24}{:>7.1} KB", input.name, input.source.len() as f64 / 1024.0));
+        for phase in PHASES {
+            match time_phase(phase, &input.source) {
+                Some(seconds) => text.push_str(&format!("{:>12.2}", megabytes / seconds)),
+                None => text.push_str(&format!("{:>12}", "-")),
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,591 @@
+// callgraph.rs: `callgraph <file>`, who calls whom, which functions recurse
+// and how deep the stack can get.
+//
+// Frame sizes are estimates from the declared locals (there is no backend
+// yet to ask): each scalar or array is given its usual size on a 64-bit
+// target, sibling blocks share space, and every frame pays 16 bytes for the
+// return address and saved frame pointer, rounded up to 16. Variable length
+// arrays are sized at run time, so those frames are only flagged.
+//
+// Spills are estimated the way a simple code generator would need them, on
+// the x86-64 calling convention: a value an expression holds while it makes
+// a call gets an 8-byte slot, since the call clobbers the registers, and
+// arguments past the sixth go on the stack. An intrinsic is no call; its
+// operation is expanded in place.
+//
+// `--emit=stack-usage` prints the frames in the format of GCC's
+// -fstack-usage `.su` files, one function per line.
+
+use crate::diagnostics::Diagnostic;
+use crate::intrinsics;
+use crate::layout::{align, Layouts};
+use crate::parser::ast::*;
+use crate::plugins::EmitTarget;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Return address and saved frame pointer
+const FRAME_OVERHEAD: usize = 16;
+const FRAME_ALIGNMENT: usize = 16;
+/// Arguments passed in registers; the rest are pushed
+const ARGUMENT_REGISTERS: usize = 6;
+const SLOT_SIZE: usize = 8;
+
+pub struct CallGraph {
+    /// Calls made by each defined function, to defined or external functions
+    pub calls: BTreeMap<String, BTreeSet<String>>,
+    pub frame_sizes: BTreeMap<String, usize>,
+    /// Functions with variable length arrays, whose frames grow at run time
+    pub variable_frames: BTreeSet<String>,
+}
+
+/// Worst-case stack use of calling a function
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackDepth {
+    Bounded { bytes: usize, chain: Vec<String> },
+    Unbounded { cycle: Vec<String> }, // some chain from here reaches this recursion
+}
+
+/// Natural alignment, raised by `__attribute__((aligned(n)))`; a bare
+/// `aligned` means the largest alignment, 16
+fn declaration_alignment(layouts: &Layouts, declaration: &VariableDeclaration) -> usize {
+    let natural = layouts.declaration_size(declaration).min(8);
+    match find_attribute(&declaration.attributes, "aligned") {
+        Some(attribute) => match attribute.arguments.first() {
+            Some(Expression::Constant(Constant::Integer(n))) if *n > 0 => natural.max(*n as usize),
+            Some(_) => natural,
+            None => natural.max(16),
+        },
+        None => natural,
+    }
+}
+
+/// Bytes of locals live at the deepest point of a statement
+fn statement_locals(layouts: &Layouts, statement: &Statement) -> usize {
+    match &statement.kind {
+        StatementKind::Block(statements) => block_locals(layouts, statements),
+        StatementKind::If(_, then_branch, else_branch) => {
+            let else_size = else_branch.as_deref().map_or(0, |branch| statement_locals(layouts, branch));
+            statement_locals(layouts, then_branch).max(else_size)
+        }
+        StatementKind::While(_, body)
+        | StatementKind::Switch(_, body)
+        | StatementKind::Case(_, body)
+        | StatementKind::Default(body)
+        | StatementKind::Label(_, body) => statement_locals(layouts, body),
+        StatementKind::For(init, _, _, body) => {
+            let init_size = init.as_deref().map_or(0, |init| statement_locals(layouts, init));
+            init_size + statement_locals(layouts, body)
+        }
+        StatementKind::Declaration(declaration) if !is_static(declaration) => layouts.declaration_size(declaration),
+        _ => 0,
+    }
+}
+
+/// `static` locals live with the globals, not in the frame
+fn is_static(declaration: &VariableDeclaration) -> bool {
+    matches!(declaration.storage_class, Some(StorageClass::Static))
+}
+
+/// Declarations of a block are live together; nested blocks reuse the space
+/// after them, so only the biggest one counts
+fn block_locals(layouts: &Layouts, statements: &[Statement]) -> usize {
+    let mut own = 0;
+    let mut nested = 0;
+    for statement in statements {
+        match &statement.kind {
+            StatementKind::Declaration(declaration) if is_static(declaration) => {}
+            StatementKind::Declaration(declaration) => {
+                let size = layouts.declaration_size(declaration);
+                own = align(own, declaration_alignment(layouts, declaration)) + size;
+            }
+            _ => nested = nested.max(statement_locals(layouts, statement)),
+        }
+    }
+    own + nested
+}
+
+/// Whether any declaration in these statements is a variable length array
+fn has_variable_length_array(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StatementKind::Declaration(declaration) => declaration.declarator.is_variable_length(),
+        StatementKind::Block(statements) => has_variable_length_array(statements),
+        StatementKind::If(_, then_branch, else_branch) => {
+            has_variable_length_array(std::slice::from_ref(then_branch))
+                || else_branch.as_deref().is_some_and(|branch| has_variable_length_array(std::slice::from_ref(branch)))
+        }
+        StatementKind::While(_, body)
+        | StatementKind::Switch(_, body)
+        | StatementKind::Case(_, body)
+        | StatementKind::Default(body)
+        | StatementKind::Label(_, body) => has_variable_length_array(std::slice::from_ref(body)),
+        StatementKind::For(init, _, _, body) => {
+            init.as_deref().is_some_and(|init| has_variable_length_array(std::slice::from_ref(init)))
+                || has_variable_length_array(std::slice::from_ref(body))
+        }
+        _ => false,
+    })
+}
+
+/// Frame space the calls in an expression need besides the locals
+#[derive(Debug, Clone, Copy, Default)]
+struct Spills {
+    held: Option<usize>,    // values live across the deepest call; None without calls
+    stack_arguments: usize, // bytes of arguments past the registers
+}
+
+impl Spills {
+    fn max(self, other: Spills) -> Spills {
+        Spills {
+            held: self.held.max(other.held),
+            stack_arguments: self.stack_arguments.max(other.stack_arguments),
+        }
+    }
+
+    /// The same, with `values` more held while those calls run
+    fn holding(self, values: usize) -> Spills {
+        Spills { held: self.held.map(|held| held + values), ..self }
+    }
+
+    fn bytes(self) -> usize {
+        self.held.unwrap_or(0) * SLOT_SIZE + self.stack_arguments
+    }
+}
+
+/// Operands are evaluated left to right, so a call in a later one has the
+/// earlier ones to keep
+fn expression_spills(expression: &Expression) -> Spills {
+    match expression {
+        // An intrinsic is expanded in place; it clobbers and pushes nothing
+        Expression::FunctionCall(callee, arguments)
+            if callee.as_function_name().is_some_and(intrinsics::is_intrinsic) =>
+        {
+            most(arguments.iter().enumerate().map(|(index, argument)| expression_spills(argument).holding(index)))
+        }
+        Expression::FunctionCall(callee, arguments) => {
+            let pushed = arguments.len().saturating_sub(ARGUMENT_REGISTERS) * SLOT_SIZE;
+            let mut spills = Spills { held: Some(0), stack_arguments: pushed }.max(expression_spills(callee));
+            for (index, argument) in arguments.iter().enumerate() {
+                spills = spills.max(expression_spills(argument).holding(index));
+            }
+            spills
+        }
+        Expression::BinaryOp(left, _, right)
+        | Expression::Assignment(left, _, right)
+        | Expression::ArrayAccess(left, right) => expression_spills(left).max(expression_spills(right).holding(1)),
+        Expression::Conditional(condition, then_value, else_value) => expression_spills(condition)
+            .max(expression_spills(then_value))
+            .max(expression_spills(else_value)),
+        // Nothing in sizeof is evaluated
+        Expression::UnaryOp(UnaryOperator::Sizeof, _) | Expression::SizeofType(_) => Spills::default(),
+        Expression::UnaryOp(_, operand)
+        | Expression::PostfixOp(operand, _)
+        | Expression::Cast(_, operand)
+        | Expression::Paren(operand)
+        | Expression::MemberAccess(operand, _)
+        | Expression::PointerAccess(operand, _) => expression_spills(operand),
+        Expression::StatementExpr(statements) => most(statements.iter().map(statement_spills)),
+        Expression::Generic(controlling, associations) => expression_spills(controlling)
+            .max(most(associations.iter().map(|association| expression_spills(&association.value)))),
+        Expression::CompoundLiteral(literal) => {
+            most(literal.initializers.iter().flat_map(Initializer::expressions).map(expression_spills))
+        }
+        Expression::Identifier(_)
+        | Expression::Constant(_)
+        | Expression::Folded(..)
+        | Expression::StringLiteral(_)
+        | Expression::EncodedStringLiteral(..) => Spills::default(),
+    }
+}
+
+/// The most of any of them
+fn most(spills: impl IntoIterator<Item = Spills>) -> Spills {
+    spills.into_iter().fold(Spills::default(), Spills::max)
+}
+
+/// The most any one expression of a statement needs; the space is reused
+fn statement_spills(statement: &Statement) -> Spills {
+    match &statement.kind {
+        StatementKind::Declaration(declaration) => {
+            most(declaration.initializer.iter().flat_map(Initializer::expressions).map(expression_spills))
+        }
+        StatementKind::Assignment(_, value) | StatementKind::Expression(value) | StatementKind::Return(Some(value)) => {
+            expression_spills(value)
+        }
+        StatementKind::Block(statements) => most(statements.iter().map(statement_spills)),
+        StatementKind::If(condition, then_branch, else_branch) => expression_spills(condition)
+            .max(statement_spills(then_branch))
+            .max(most(else_branch.as_deref().map(statement_spills))),
+        StatementKind::While(condition, body) | StatementKind::Switch(condition, body) => {
+            expression_spills(condition).max(statement_spills(body))
+        }
+        StatementKind::Case(_, statement) | StatementKind::Default(statement) | StatementKind::Label(_, statement) => {
+            statement_spills(statement)
+        }
+        StatementKind::For(init, condition, update, body) => most(init.as_deref().map(statement_spills))
+            .max(most([condition, update].into_iter().flatten().map(expression_spills)))
+            .max(statement_spills(body)),
+        StatementKind::Return(None)
+        | StatementKind::Break
+        | StatementKind::Fallthrough
+        | StatementKind::Goto(_)
+        | StatementKind::Empty => Spills::default(),
+    }
+}
+
+/// Estimated frame size of a function, in bytes
+pub fn estimate_frame_size(layouts: &Layouts, function: &FunctionDefinition) -> usize {
+    let mut parameters = 0;
+    for parameter in &function.parameters {
+        let size = layouts.type_name_size(&parameter.param_type);
+        parameters = align(parameters, size.min(8)) + size;
+    }
+    let spills = most(function.body.iter().map(statement_spills));
+    // Spill slots go after the locals, at their own alignment
+    let locals = align(parameters + block_locals(layouts, &function.body), SLOT_SIZE);
+    align(FRAME_OVERHEAD + locals + spills.bytes(), FRAME_ALIGNMENT)
+}
+
+fn collect_calls_in_statement(statement: &Statement, calls: &mut BTreeSet<String>) {
+    match &statement.kind {
+        StatementKind::Declaration(declaration) => {
+            if let Some(initializer) = &declaration.initializer {
+                collect_calls_in_initializer(initializer, calls);
+            }
+        }
+        StatementKind::Assignment(_, value) | StatementKind::Expression(value) | StatementKind::Return(Some(value)) => {
+            collect_calls(value, calls)
+        }
+        StatementKind::Block(statements) => {
+            for statement in statements {
+                collect_calls_in_statement(statement, calls);
+            }
+        }
+        StatementKind::If(condition, then_branch, else_branch) => {
+            collect_calls(condition, calls);
+            collect_calls_in_statement(then_branch, calls);
+            if let Some(else_branch) = else_branch {
+                collect_calls_in_statement(else_branch, calls);
+            }
+        }
+        StatementKind::While(condition, body) | StatementKind::Switch(condition, body) => {
+            collect_calls(condition, calls);
+            collect_calls_in_statement(body, calls);
+        }
+        // Case values are constants, so they call nothing
+        StatementKind::Case(_, statement) | StatementKind::Default(statement) | StatementKind::Label(_, statement) => {
+            collect_calls_in_statement(statement, calls)
+        }
+        StatementKind::For(init, condition, update, body) => {
+            if let Some(init) = init {
+                collect_calls_in_statement(init, calls);
+            }
+            for expression in [condition, update].into_iter().flatten() {
+                collect_calls(expression, calls);
+            }
+            collect_calls_in_statement(body, calls);
+        }
+        StatementKind::Return(None)
+        | StatementKind::Break
+        | StatementKind::Fallthrough
+        | StatementKind::Goto(_)
+        | StatementKind::Empty => {}
+    }
+}
+
+fn collect_calls_in_initializer(initializer: &Initializer, calls: &mut BTreeSet<String>) {
+    match &initializer.kind {
+        InitializerKind::Assignment(value) => collect_calls(value, calls),
+        InitializerKind::List(items) => {
+            for item in items {
+                collect_calls_in_initializer(item, calls);
+            }
+        }
+        InitializerKind::Designated(_, value) => collect_calls_in_initializer(value, calls),
+    }
+}
+
+fn collect_calls(expression: &Expression, calls: &mut BTreeSet<String>) {
+    match expression {
+        Expression::CompoundLiteral(literal) => {
+            for size in literal.array_sizes.iter().flatten() {
+                collect_calls(size, calls);
+            }
+            for item in &literal.initializers {
+                collect_calls_in_initializer(item, calls);
+            }
+        }
+        Expression::FunctionCall(callee, arguments) => {
+            // Calls through function pointers can't be followed, and
+            // intrinsics aren't calls
+            if let Some(name) = callee.as_function_name().filter(|name| !intrinsics::is_intrinsic(name)) {
+                calls.insert(name.to_string());
+            }
+            collect_calls(callee, calls);
+            for argument in arguments {
+                collect_calls(argument, calls);
+            }
+        }
+        // The operand of sizeof isn't evaluated, so nothing in it is called
+        Expression::UnaryOp(UnaryOperator::Sizeof, _) | Expression::SizeofType(_) => {}
+        Expression::BinaryOp(left, _, right)
+        | Expression::Assignment(left, _, right)
+        | Expression::ArrayAccess(left, right) => {
+            collect_calls(left, calls);
+            collect_calls(right, calls);
+        }
+        Expression::UnaryOp(_, operand)
+        | Expression::PostfixOp(operand, _)
+        | Expression::Cast(_, operand)
+        | Expression::Paren(operand)
+        | Expression::MemberAccess(operand, _)
+        | Expression::PointerAccess(operand, _) => collect_calls(operand, calls),
+        Expression::Conditional(condition, then_value, else_value) => {
+            collect_calls(condition, calls);
+            collect_calls(then_value, calls);
+            collect_calls(else_value, calls);
+        }
+        Expression::StatementExpr(statements) => {
+            for statement in statements {
+                collect_calls_in_statement(statement, calls);
+            }
+        }
+        Expression::Generic(controlling, associations) => {
+            collect_calls(controlling, calls);
+            for association in associations {
+                collect_calls(&association.value, calls);
+            }
+        }
+        Expression::Identifier(_)
+        | Expression::Constant(_)
+        | Expression::Folded(..)
+        | Expression::StringLiteral(_)
+        | Expression::EncodedStringLiteral(..) => {}
+    }
+}
+
+impl CallGraph {
+    pub fn build(unit: &TranslationUnit) -> CallGraph {
+        let mut calls = BTreeMap::new();
+        let mut frame_sizes = BTreeMap::new();
+        let mut variable_frames = BTreeSet::new();
+        let layouts = Layouts::new(unit);
+        for external in &unit.external_declarations {
+            if let ExternalDeclaration::Function(function) = external {
+                let mut callees = BTreeSet::new();
+                for statement in &function.body {
+                    collect_calls_in_statement(statement, &mut callees);
+                }
+                calls.insert(function.name.clone(), callees);
+                frame_sizes.insert(function.name.clone(), estimate_frame_size(&layouts, function));
+                if has_variable_length_array(&function.body) {
+                    variable_frames.insert(function.name.clone());
+                }
+            }
+        }
+        CallGraph { calls, frame_sizes, variable_frames }
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.calls.contains_key(name)
+    }
+
+    /// Groups of defined functions that call each other in a cycle (Tarjan's
+    /// strongly connected components), including functions calling themselves
+    pub fn recursive_groups(&self) -> Vec<Vec<String>> {
+        struct Tarjan<'a> {
+            graph: &'a CallGraph,
+            index: BTreeMap<&'a str, usize>,
+            low: BTreeMap<&'a str, usize>,
+            stack: Vec<&'a str>,
+            on_stack: BTreeSet<&'a str>,
+            groups: Vec<Vec<String>>,
+        }
+
+        impl<'a> Tarjan<'a> {
+            fn visit(&mut self, name: &'a str) {
+                let index = self.index.len();
+                self.index.insert(name, index);
+                self.low.insert(name, index);
+                self.stack.push(name);
+                self.on_stack.insert(name);
+                for callee in &self.graph.calls[name] {
+                    let callee = callee.as_str();
+                    if !self.graph.is_defined(callee) {
+                        continue;
+                    }
+                    if !self.index.contains_key(callee) {
+                        self.visit(callee);
+                        let low = self.low[name].min(self.low[callee]);
+                        self.low.insert(name, low);
+                    } else if self.on_stack.contains(callee) {
+                        let low = self.low[name].min(self.index[callee]);
+                        self.low.insert(name, low);
+                    }
+                }
+                if self.low[name] == self.index[name] {
+                    let mut group = Vec::new();
+                    while let Some(member) = self.stack.pop() {
+                        self.on_stack.remove(member);
+                        group.push(member.to_string());
+                        if member == name {
+                            break;
+                        }
+                    }
+                    let self_recursive = self.graph.calls[name].contains(name);
+                    if group.len() > 1 || self_recursive {
+                        group.sort();
+                        self.groups.push(group);
+                    }
+                }
+            }
+        }
+
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: BTreeMap::new(),
+            low: BTreeMap::new(),
+            stack: Vec::new(),
+            on_stack: BTreeSet::new(),
+            groups: Vec::new(),
+        };
+        for name in self.calls.keys() {
+            if !tarjan.index.contains_key(name.as_str()) {
+                tarjan.visit(name);
+            }
+        }
+        tarjan.groups.sort();
+        tarjan.groups
+    }
+
+    /// Worst-case stack use of a call to `name`, counting every defined
+    /// function below it; external functions count as zero
+    pub fn stack_depth(&self, name: &str) -> StackDepth {
+        let mut recursive = BTreeMap::new();
+        for group in self.recursive_groups() {
+            for member in &group {
+                recursive.insert(member.clone(), group.clone());
+            }
+        }
+        let mut memo = BTreeMap::new();
+        self.depth_of(name, &recursive, &mut memo)
+    }
+
+    fn depth_of<'a>(
+        &'a self,
+        name: &'a str,
+        recursive: &BTreeMap<String, Vec<String>>,
+        memo: &mut BTreeMap<&'a str, StackDepth>,
+    ) -> StackDepth {
+        if let Some(depth) = memo.get(name) {
+            return depth.clone();
+        }
+        let depth = if let Some(group) = recursive.get(name) {
+            StackDepth::Unbounded { cycle: group.clone() }
+        } else {
+            let mut deepest = StackDepth::Bounded { bytes: 0, chain: Vec::new() };
+            for callee in &self.calls[name] {
+                if !self.is_defined(callee) {
+                    continue;
+                }
+                match self.depth_of(callee, recursive, memo) {
+                    unbounded @ StackDepth::Unbounded { .. } => {
+                        deepest = unbounded;
+                        break;
+                    }
+                    StackDepth::Bounded { bytes, chain } => {
+                        if matches!(deepest, StackDepth::Bounded { bytes: most, .. } if bytes > most) {
+                            deepest = StackDepth::Bounded { bytes, chain };
+                        }
+                    }
+                }
+            }
+            match deepest {
+                StackDepth::Bounded { bytes, mut chain } => {
+                    chain.insert(0, name.to_string());
+                    StackDepth::Bounded { bytes: bytes + self.frame_sizes[name], chain }
+                }
+                unbounded => unbounded,
+            }
+        };
+        memo.insert(name, depth.clone());
+        depth
+    }
+
+    /// The whole report: call graph, recursion and stack estimates
+    pub fn render(&self) -> String {
+        let mut text = String::from("Call graph:\n");
+        for (caller, callees) in &self.calls {
+            let callees: Vec<String> = callees
+                .iter()
+                .map(|callee| {
+                    if self.is_defined(callee) { callee.clone() } else { format!("{} (external)", callee) }
+                })
+                .collect();
+            let callees = if callees.is_empty() { "(no calls)".to_string() } else { callees.join(", ") };
+            text.push_str(&format!("  {} -> {}\n", caller, callees));
+        }
+
+        text.push_str("\nRecursion:\n");
+        let groups = self.recursive_groups();
+        if groups.is_empty() {
+            text.push_str("  none\n");
+        }
+        for group in &groups {
+            if group.len() == 1 {
+                text.push_str(&format!("  direct: {} calls itself\n", group[0]));
+            } else {
+                text.push_str(&format!("  mutual: {}\n", group.join(", ")));
+            }
+        }
+
+        text.push_str("\nStack usage (estimated, bytes):\n");
+        let width = self.calls.keys().map(|name| name.len()).max().unwrap_or(0);
+        for (name, frame) in &self.frame_sizes {
+            let mut worst = match self.stack_depth(name) {
+                StackDepth::Bounded { bytes, chain } => format!("{} via {}", bytes, chain.join(" -> ")),
+                StackDepth::Unbounded { cycle } => format!("unbounded, recursion through {}", cycle.join(", ")),
+            };
+            if self.variable_frames.contains(name) {
+                worst.push_str(", plus variable length arrays");
+            }
+            text.push_str(&format!("  {:<width$}  frame {:>5}  worst case {}\n", name, frame, worst, width = width));
+        }
+        text
+    }
+}
+
+/// `--emit=stack-usage`: `file:line:column:function<TAB>bytes<TAB>qualifier`
+/// for every function defined, where the qualifier is `static` for a fixed
+/// frame and `dynamic` for one with variable length arrays
+pub struct StackUsageEmitter {
+    file: String,
+}
+
+impl StackUsageEmitter {
+    pub fn new(file: &str) -> StackUsageEmitter {
+        StackUsageEmitter { file: file.to_string() }
+    }
+}
+
+impl EmitTarget for StackUsageEmitter {
+    fn name(&self) -> &str {
+        "stack-usage"
+    }
+
+    fn emit(&self, unit: &TranslationUnit, _source: &str, _diagnostics: &[Diagnostic]) -> String {
+        let graph = CallGraph::build(unit);
+        let mut out = String::new();
+        for external in &unit.external_declarations {
+            let ExternalDeclaration::Function(function) = external else {
+                continue;
+            };
+            let (line, column) = function.name_span.map_or((0, 0), |span| (span.line, span.col));
+            let qualifier = if graph.variable_frames.contains(&function.name) { "dynamic" } else { "static" };
+            out.push_str(&format!(
+                "{}:{}:{}:{}\t{}\t{}\n",
+                self.file, line, column, function.name, graph.frame_sizes[&function.name], qualifier
+            ));
+        }
+        out
+    }
+}
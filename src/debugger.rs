@@ -0,0 +1,719 @@
+// debugger.rs: A gdb-like debugger for programs run by the interpreter,
+// with breakpoints by line or function, stepping, printing of variables and
+// expressions, and the call stack.
+//
+// Commands are text, as in gdb, and a Session carries them out against the
+// interpreter's machine. On a terminal the session is shown as a TUI: the
+// source with the current line and breakpoints marked, the locals and call
+// stack beside it, and a console of commands and program output below.
+// Otherwise commands are read a line at a time from stdin, so a session can
+// be scripted.
+//
+// Runs are deterministic, as the interpreter has no clock, input or
+// randomness: `run` starts again from a copy of the machine made before
+// main's first statement, and the program goes exactly the same way again.
+// The reverse commands use that too, winding back through snapshots of the
+// run (interpreter/history.rs) kept within a memory budget.
+
+use crate::fold;
+use crate::interpreter::history::{History, Position};
+use crate::interpreter::{Machine, Status};
+use crate::lexer_regex;
+use crate::parser::ast::Expression;
+use crate::parser::{self, Parser};
+use crate::scope::{ScopeAnalyzer, Symbol, SymbolKind};
+use crate::source::SourceFile;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::rc::Rc;
+
+/// Statements a command runs before giving up on reaching where it was
+/// going, so a program that loops forever doesn't hang the debugger
+const MAX_STEPS: usize = 10_000_000;
+
+/// Megabytes of snapshots kept for the reverse commands, unless
+/// --history-budget says otherwise
+pub const DEFAULT_HISTORY_BUDGET: usize = 64;
+
+const HELP: &str = "\
+Commands (gdb's short forms work too):
+  break LINE|FUNCTION   stop there (b)
+  delete [N]            remove breakpoint N, or all of them (d)
+  info breakpoints      list breakpoints (info b)
+  run                   start the program again from the beginning (r)
+  step                  run one statement, into calls (s)
+  next                  run one statement, over calls (n)
+  finish                run until the current function returns
+  continue              run to the next breakpoint (c)
+  reverse-step          back to the statement before (rs)
+  reverse-next          back to the statement before, over calls (rn)
+  reverse-finish        back to where the current function was called
+  reverse-continue      back to the last breakpoint passed (rc)
+  info history          the snapshots kept for going back
+  print EXPR            show a value (p)
+  whatis EXPR           show a type
+  info locals           the selected frame's locals
+  info args             the selected frame's parameters
+  backtrace             the call stack (bt)
+  frame N, up, down     select a frame of the call stack (f)
+  list [LINE]           source around the current line (l)
+  quit                  leave (q)
+An empty line repeats the last command.
+";
+
+/// How far a command runs the program
+#[derive(Clone, Copy)]
+enum Until {
+    Step,
+    Next,   // back in the frame it started in, or one outside it
+    Finish, // out of the frame it started in
+    Breakpoint,
+}
+
+/// How far a reverse command winds the program back
+#[derive(Clone, Copy, PartialEq)]
+enum Back {
+    Step,
+    Next,
+    Finish,
+    Breakpoint,
+}
+
+struct Breakpoint {
+    number: usize,
+    line: usize,
+}
+
+pub struct Session {
+    source: Rc<SourceFile>,
+    start: Machine, // before main's first statement, for `run`
+    machine: Machine,
+    history: History,
+    ended: bool, // exited, or stopped by an error with its frames left to look at
+    breakpoints: Vec<Breakpoint>,
+    next_breakpoint: usize,
+    selected: usize,      // the frame print and info look at; main's is 0
+    symbols: Vec<Symbol>, // the program's variables, for names not in scope
+    last_command: String,
+    pub quit: bool,
+}
+
+impl Session {
+    /// A session on `source`, keeping up to `history_budget` megabytes of
+    /// snapshots to go back with
+    pub fn new(source: &str, gnu_extensions: bool, history_budget: usize) -> Result<Session, String> {
+        let mut parser = Parser::with_spans(lexer_regex::lex_with_regex_positions(source, Default::default()));
+        parser.set_gnu_extensions(gnu_extensions);
+        let mut unit = parser.parse().map_err(|errors| {
+            errors.iter().map(|error| format!("Parse Error: {:?}", error)).collect::<Vec<_>>().join("\n")
+        })?;
+        fold::fold_constants(&mut unit);
+        let source_file = Rc::new(SourceFile::new("", source));
+        let mut scope_analyzer = ScopeAnalyzer::with_source(source_file.clone());
+        let _ = scope_analyzer.analyze_translation_unit(&unit);
+        let symbols = scope_analyzer
+            .get_all_scopes()
+            .iter()
+            .flat_map(|scope| scope.symbols.borrow().values().cloned().collect::<Vec<_>>())
+            .filter(|symbol| matches!(symbol.kind, SymbolKind::Variable { .. } | SymbolKind::Parameter { .. }))
+            .collect();
+        let machine = Machine::new(&unit, source).map_err(|error| error.render())?;
+        Ok(Session {
+            source: source_file,
+            start: machine.clone(),
+            history: History::new(&machine, history_budget * 1024 * 1024),
+            machine,
+            ended: false,
+            breakpoints: Vec::new(),
+            next_breakpoint: 1,
+            selected: 0,
+            symbols,
+            last_command: String::new(),
+            quit: false,
+        })
+    }
+
+    /// Where the program is stopped, as gdb says it on starting
+    pub fn banner(&self) -> String {
+        format!("{}\n{}", self.frame_description(self.selected), self.source_line(self.current_line()))
+    }
+
+    /// Carry out a command; what it prints, the program's output included
+    pub fn execute(&mut self, command: &str) -> String {
+        let command = match command.trim() {
+            "" => self.last_command.clone(),
+            command => command.to_string(),
+        };
+        // Only moving on is repeated, as in gdb
+        let repeats = [
+            "step", "s", "next", "n", "finish", "fin", "continue", "c", "reverse-step", "rs", "reverse-next", "rn",
+            "reverse-finish", "reverse-continue", "rc",
+        ];
+        self.last_command = if repeats.contains(&command.as_str()) { command.clone() } else { String::new() };
+        let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((&command, ""));
+        let argument = argument.trim();
+        match name {
+            "" => String::new(),
+            "help" | "h" => HELP.to_string(),
+            "break" | "b" => self.set_breakpoint(argument),
+            "delete" | "d" => self.delete_breakpoint(argument),
+            "info" | "i" => match argument {
+                "breakpoints" | "break" | "b" => self.breakpoint_lines(),
+                "locals" => self.locals(false).join("\n") + "\n",
+                "args" => self.locals(true).join("\n") + "\n",
+                "history" => self.history.describe(),
+                _ => "info takes breakpoints, locals, args or history\n".to_string(),
+            },
+            "run" | "r" => {
+                self.machine = self.start.clone();
+                self.ended = false;
+                self.selected = 0;
+                format!("Starting the program again\n{}", self.advance(Until::Breakpoint, true))
+            }
+            "step" | "s" => self.advance(Until::Step, false),
+            "next" | "n" => self.advance(Until::Next, false),
+            "finish" | "fin" => self.advance(Until::Finish, false),
+            "continue" | "c" => self.advance(Until::Breakpoint, false),
+            "reverse-step" | "rs" => self.reverse(Back::Step),
+            "reverse-next" | "rn" => self.reverse(Back::Next),
+            "reverse-finish" => self.reverse(Back::Finish),
+            "reverse-continue" | "rc" => self.reverse(Back::Breakpoint),
+            "print" | "p" => self.print(argument),
+            "whatis" => match parser::parse_expression(argument) {
+                Some(expression) => match self.machine.type_of(self.selected, &expression) {
+                    Some(ty) => format!("type = {}\n", ty),
+                    None => format!("Can't tell the type of '{}'\n", argument),
+                },
+                None => format!("'{}' isn't an expression\n", argument),
+            },
+            "backtrace" | "bt" | "where" => self.backtrace(),
+            "frame" | "f" => match argument.parse::<usize>() {
+                Ok(number) => self.select(self.machine.frames().len().checked_sub(number + 1)),
+                Err(_) => self.select(Some(self.selected)),
+            },
+            "up" => self.select(self.selected.checked_sub(1)),
+            "down" => self.select(Some(self.selected + 1)),
+            "list" | "l" => {
+                let line = argument.parse().ok().or(self.current_line()).unwrap_or(1);
+                self.list(line)
+            }
+            "quit" | "q" => {
+                self.quit = true;
+                String::new()
+            }
+            _ => format!("Undefined command: \"{}\". Try \"help\".\n", name),
+        }
+    }
+
+    /// The selected frame's line
+    pub fn current_line(&self) -> Option<usize> {
+        self.machine.frame_site(self.selected).line
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().map(|breakpoint| breakpoint.line)
+    }
+
+    /// Run until `until` is met, a breakpoint is reached or the program
+    /// ends; `from_start` stops at a breakpoint on the first statement
+    fn advance(&mut self, until: Until, from_start: bool) -> String {
+        if self.ended {
+            return "The program is not being run; 'run' starts it again.\n".to_string();
+        }
+        let mut out = String::new();
+        let depth = self.machine.frames().len();
+        let start = (depth, self.machine.site().line);
+        let mut previous = start;
+        if from_start && let Some(number) = self.breakpoint_at(previous.1) {
+            return format!("Breakpoint {}, {}", number, self.stopped(true));
+        }
+        for _ in 0..MAX_STEPS {
+            let status = self.machine.step();
+            self.history.record(&self.machine);
+            out.push_str(&String::from_utf8_lossy(&self.machine.take_output()));
+            if !out.is_empty() && !out.ends_with('\n') && !matches!(status, Ok(Status::Running)) {
+                out.push('\n');
+            }
+            match status {
+                Ok(Status::Running) => {}
+                Ok(Status::Exited(code)) => {
+                    self.ended = true;
+                    out.push_str(&format!("--- Program exited with code {} ---\n", code));
+                    out.push_str(&self.machine.memory.report());
+                    return out;
+                }
+                Err(error) => {
+                    // Stopped where it went wrong, as gdb stops on a signal
+                    self.ended = true;
+                    out.push_str(&format!("{}\n", error.render()));
+                    out.push_str(&self.machine.memory.render_issues());
+                    out.push_str(&self.stopped(true));
+                    return out;
+                }
+            }
+            let frames = self.machine.frames().len();
+            let line = self.machine.site().line;
+            // Code without a line, like a loop's jump back, isn't stopped in
+            if line.is_none() {
+                continue;
+            }
+            // A line is only reached again once the run has been elsewhere
+            let hit = if (frames, line) != previous { self.breakpoint_at(line) } else { None };
+            previous = (frames, line);
+            if let Some(number) = hit {
+                return format!("{}Breakpoint {}, {}", out, number, self.stopped(true));
+            }
+            // Stepping goes on to another line, as gdb's does
+            let moved = (frames, line) != start;
+            let done = match until {
+                Until::Step => moved,
+                Until::Next => moved && frames <= depth,
+                Until::Finish => frames < depth,
+                Until::Breakpoint => false,
+            };
+            if done {
+                let elsewhere = frames != depth;
+                out.push_str(&self.stopped(elsewhere));
+                return out;
+            }
+        }
+        out.push_str(&format!("Stopped after {} statements\n{}", MAX_STEPS, self.stopped(true)));
+        out
+    }
+
+    /// Wind the program back as far as `back` says; the same stops as
+    /// going forward, met the other way round
+    fn reverse(&mut self, back: Back) -> String {
+        let current = self.machine.position();
+        let depth = current.frames;
+        if back == Back::Finish && depth <= 1 {
+            return "\"finish\" not meaningful in the outermost frame.\n".to_string();
+        }
+        let same = |a: &Position, b: &Position| (a.frames, a.line) == (b.frames, b.line);
+        // Where it stops, once nothing earlier can take its place
+        let mut target: Option<Position> = None;
+        let mut breakpoint = None;
+        let mut later: Option<Position> = None; // the last position seen with a line
+        for position in self.history.earlier(&self.machine) {
+            if position.line.is_none() {
+                continue;
+            }
+            let done = match back {
+                // The first statement of the line before, not the last
+                Back::Step | Back::Next => match target {
+                    None => {
+                        if !same(&position, &current) && (back == Back::Step || position.frames <= depth) {
+                            target = Some(position);
+                        }
+                        false
+                    }
+                    Some(found) if same(&position, &found) => {
+                        target = Some(position);
+                        false
+                    }
+                    Some(_) => back == Back::Step || position.frames <= depth,
+                },
+                Back::Finish => {
+                    target = Some(position).filter(|position| position.frames < depth);
+                    target.is_some()
+                }
+                // A breakpoint was hit where the line changed to its own
+                Back::Breakpoint => {
+                    let hit = later.filter(|later| !same(later, &position));
+                    breakpoint = hit.and_then(|hit| self.breakpoint_at(hit.line));
+                    target = hit.filter(|_| breakpoint.is_some());
+                    later = Some(position);
+                    target.is_some()
+                }
+            };
+            if done {
+                break;
+            }
+        }
+        // The start counts as a change of line, as `run` stops there
+        if back == Back::Breakpoint && target.is_none() {
+            breakpoint = later.and_then(|start| self.breakpoint_at(start.line));
+            target = later.filter(|_| breakpoint.is_some());
+        }
+        let Some(target) = target else {
+            self.machine = self.history.rewind(0);
+            self.ended = false;
+            return format!("No more reverse-execution history.\n{}", self.stopped(true));
+        };
+        self.machine = self.history.rewind(target.steps);
+        self.ended = false;
+        match breakpoint {
+            Some(number) => format!("Breakpoint {}, {}", number, self.stopped(true)),
+            None => self.stopped(target.frames != depth),
+        }
+    }
+
+    /// Where the program stopped: the line, after the function and its
+    /// arguments if `describe`
+    fn stopped(&mut self, describe: bool) -> String {
+        self.selected = self.machine.frames().len().saturating_sub(1);
+        let line = self.source_line(self.current_line());
+        match describe {
+            true => format!("{}\n{}", self.frame_description(self.selected), line),
+            false => line,
+        }
+    }
+
+    fn breakpoint_at(&self, line: Option<usize>) -> Option<usize> {
+        let line = line?;
+        self.breakpoints.iter().find(|breakpoint| breakpoint.line == line).map(|breakpoint| breakpoint.number)
+    }
+
+    /// `break 12` or `break main`; a line without a statement means the
+    /// next one that has one
+    fn set_breakpoint(&mut self, argument: &str) -> String {
+        let wanted = match argument {
+            "" => self.current_line(),
+            _ => match argument.parse::<usize>() {
+                Ok(line) => Some(line),
+                Err(_) => match self.machine.function_line(argument) {
+                    Some(line) => Some(line),
+                    None => return format!("Function \"{}\" not defined.\n", argument),
+                },
+            },
+        };
+        let Some(wanted) = wanted else {
+            return "No line to break at\n".to_string();
+        };
+        let Some(line) = (wanted..=self.source.line_count()).find(|&line| self.machine.has_statement_at(line)) else {
+            return format!("No statement at or after line {}\n", wanted);
+        };
+        if let Some(number) = self.breakpoint_at(Some(line)) {
+            return format!("Breakpoint {} is already at line {}\n", number, line);
+        }
+        let number = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.breakpoints.push(Breakpoint { number, line });
+        format!("Breakpoint {} at line {}\n", number, line)
+    }
+
+    fn delete_breakpoint(&mut self, argument: &str) -> String {
+        if argument.is_empty() {
+            self.breakpoints.clear();
+            return "Deleted all breakpoints\n".to_string();
+        }
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| argument.parse() != Ok(breakpoint.number));
+        match self.breakpoints.len() < count {
+            true => String::new(),
+            false => format!("No breakpoint number {}\n", argument),
+        }
+    }
+
+    fn breakpoint_lines(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "No breakpoints\n".to_string();
+        }
+        self.breakpoints
+            .iter()
+            .map(|breakpoint| format!("{}\tline {}\t{}\n", breakpoint.number, breakpoint.line, self.line_text(breakpoint.line)))
+            .collect()
+    }
+
+    fn print(&self, argument: &str) -> String {
+        let Some(expression) = parser::parse_expression(argument) else {
+            return format!("'{}' isn't an expression\n", argument);
+        };
+        match self.machine.inspect(self.selected, &expression) {
+            Ok(value) => format!("{} = {}\n", argument, value),
+            Err(error) => match &expression {
+                Expression::Identifier(name) => format!("{}\n", self.not_in_scope(name).unwrap_or(error.message)),
+                _ => format!("{}\n", error.message),
+            },
+        }
+    }
+
+    /// Why the symbol table knows a name the selected frame can't see
+    fn not_in_scope(&self, name: &str) -> Option<String> {
+        let symbol = self.symbols.iter().find(|symbol| symbol.name == name)?;
+        let line = symbol.line?;
+        Some(format!("'{}' is declared at line {}, but isn't in scope here", name, line))
+    }
+
+    /// The selected frame's parameters, or the locals after them, as
+    /// `name = value`; a local declared again shows the innermost one
+    fn locals(&self, parameters: bool) -> Vec<String> {
+        let Some(frame) = self.machine.frames().get(self.selected) else {
+            return vec!["No frame selected".to_string()];
+        };
+        let count = self.machine.parameter_count(frame);
+        let names: Vec<&String> = frame.locals.iter().map(|(name, _)| name).collect();
+        let lines: Vec<String> = names
+            .iter()
+            .enumerate()
+            .filter(|&(index, name)| (index < count) == parameters && !names[index + 1..].contains(name))
+            .map(|(_, name)| format!("{} = {}", name, self.value(self.selected, name)))
+            .collect();
+        match (lines.is_empty(), parameters) {
+            (true, true) => vec!["No arguments".to_string()],
+            (true, false) => vec!["No locals".to_string()],
+            (false, _) => lines,
+        }
+    }
+
+    fn value(&self, frame: usize, name: &str) -> String {
+        match self.machine.inspect(frame, &Expression::Identifier(name.to_string())) {
+            Ok(value) => value,
+            Err(error) => format!("<{}>", error.message),
+        }
+    }
+
+    fn frame_description(&self, index: usize) -> String {
+        self.machine.describe_frame(index)
+    }
+
+    fn backtrace(&self) -> String {
+        if self.machine.frames().is_empty() {
+            return "No stack\n".to_string();
+        }
+        self.machine.backtrace()
+    }
+
+    fn select(&mut self, index: Option<usize>) -> String {
+        match index.filter(|&index| index < self.machine.frames().len()) {
+            Some(index) => {
+                self.selected = index;
+                let number = self.machine.frames().len() - 1 - index;
+                format!("#{}  {}\n{}", number, self.frame_description(index), self.source_line(self.current_line()))
+            }
+            None => "No frame there\n".to_string(),
+        }
+    }
+
+    fn list(&self, line: usize) -> String {
+        let first = line.saturating_sub(5).max(1);
+        let last = (first + 9).min(self.source.line_count());
+        (first..=last).map(|line| format!("{}\t{}\n", line, self.line_text(line))).collect()
+    }
+
+    fn line_text(&self, line: usize) -> &str {
+        self.source.line(line).unwrap_or("")
+    }
+
+    /// `12\t    total = total + head->value;`
+    fn source_line(&self, line: Option<usize>) -> String {
+        match line {
+            Some(line) => format!("{}\t{}\n", line, self.line_text(line)),
+            None => "(no line)\n".to_string(),
+        }
+    }
+
+    pub fn locals_panel(&self) -> Vec<String> {
+        if self.machine.frames().is_empty() {
+            return Vec::new();
+        }
+        let mut lines = self.locals(true);
+        lines.retain(|line| line != "No arguments");
+        lines.extend(self.locals(false));
+        lines
+    }
+
+    pub fn stack_panel(&self) -> Vec<String> {
+        self.backtrace().lines().map(|line| line.to_string()).collect()
+    }
+}
+
+/// Debug a program: in the TUI on a terminal, else reading commands from
+/// stdin
+pub fn debug(filename: &str, gnu_extensions: bool, history_budget: usize) -> Result<(), String> {
+    let source = fs::read_to_string(filename).map_err(|error| error.to_string())?;
+    let session = Session::new(&source, gnu_extensions, history_budget)?;
+    let result = if io::stdin().is_terminal() && io::stdout().is_terminal() {
+        let terminal = ratatui::init();
+        let result = Tui::new(filename, session).run(terminal);
+        ratatui::restore();
+        result
+    } else {
+        read_commands(session)
+    };
+    result.map_err(|error| error.to_string())
+}
+
+/// gdb's own way: a prompt, a command, what it printed. Commands are
+/// echoed, as they come from a script rather than being typed.
+fn read_commands(mut session: Session) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", session.banner())?;
+    for command in io::stdin().lock().lines() {
+        let command = command?;
+        write!(stdout, "(minic) {}\n{}", command, session.execute(&command))?;
+        if session.quit {
+            break;
+        }
+    }
+    stdout.flush()
+}
+
+struct Tui {
+    filename: String,
+    session: Session,
+    input: String,
+    console: Vec<String>,
+    history: Vec<String>,
+    recalled: usize, // how far back Up has gone in the history
+    locals: Vec<String>,
+    stack: Vec<String>,
+}
+
+impl Tui {
+    fn new(filename: &str, session: Session) -> Tui {
+        let mut tui = Tui {
+            filename: filename.to_string(),
+            console: session.banner().lines().map(|line| line.to_string()).collect(),
+            session,
+            input: String::new(),
+            history: Vec::new(),
+            recalled: 0,
+            locals: Vec::new(),
+            stack: Vec::new(),
+        };
+        tui.console.push("Type 'help' for the commands".to_string());
+        tui.refresh();
+        tui
+    }
+
+    /// The panels, worked out once per command rather than on every draw
+    fn refresh(&mut self) {
+        self.locals = self.session.locals_panel();
+        self.stack = self.session.stack_panel();
+    }
+
+    fn execute(&mut self, command: &str) {
+        self.console.push(format!("(minic) {}", command));
+        let output = self.session.execute(command);
+        self.console.extend(output.lines().map(|line| line.to_string()));
+        if !command.trim().is_empty() {
+            self.history.push(command.to_string());
+        }
+        self.recalled = 0;
+        self.refresh();
+    }
+
+    /// Returns false once the user asks to quit
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        match code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c' | 'd') if modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => {
+                let command = std::mem::take(&mut self.input);
+                self.execute(&command);
+            }
+            KeyCode::Up if self.recalled < self.history.len() => {
+                self.recalled += 1;
+                self.input = self.history[self.history.len() - self.recalled].clone();
+            }
+            KeyCode::Down if self.recalled > 0 => {
+                self.recalled -= 1;
+                self.input = match self.recalled {
+                    0 => String::new(),
+                    recalled => self.history[self.history.len() - recalled].clone(),
+                };
+            }
+            // With shift, the same the other way, as in Visual Studio
+            KeyCode::F(5) if modifiers.contains(KeyModifiers::SHIFT) => self.execute("reverse-continue"),
+            KeyCode::F(10) if modifiers.contains(KeyModifiers::SHIFT) => self.execute("reverse-next"),
+            KeyCode::F(11) if modifiers.contains(KeyModifiers::SHIFT) => self.execute("reverse-step"),
+            KeyCode::F(5) => self.execute("continue"),
+            KeyCode::F(10) => self.execute("next"),
+            KeyCode::F(11) => self.execute("step"),
+            _ => {}
+        }
+        !self.session.quit
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [body_area, console_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(12)]).areas(frame.area());
+        let [source_area, side_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body_area);
+        let [locals_area, stack_area] =
+            Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(side_area);
+
+        let current = self.session.current_line();
+        let breakpoints: Vec<usize> = self.session.breakpoints().collect();
+        let lines: Vec<Line> = self
+            .session
+            .source
+            .lines()
+            .enumerate()
+            .map(|(index, text)| {
+                let line = index + 1;
+                let marker = match (breakpoints.contains(&line), current == Some(line)) {
+                    (true, true) => Span::styled("●▶", Style::default().fg(Color::Red)),
+                    (true, false) => Span::styled("● ", Style::default().fg(Color::Red)),
+                    (false, true) => Span::styled(" ▶", Style::default().fg(Color::Yellow)),
+                    (false, false) => Span::raw("  "),
+                };
+                let number = Span::styled(format!("{:>4} ", line), Style::default().fg(Color::DarkGray));
+                let style = if current == Some(line) {
+                    Style::default().bg(Color::Blue).fg(Color::White)
+                } else {
+                    Style::default()
+                };
+                Line::from(vec![marker, number, Span::styled(text, style)])
+            })
+            .collect();
+        // Keep the current line roughly centred
+        let visible_height = source_area.height.saturating_sub(2) as usize;
+        let scroll = current.unwrap_or(1).saturating_sub(visible_height / 2);
+        let source = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(format!(" minic debug: {} ", self.filename)))
+            .scroll((scroll as u16, 0));
+        frame.render_widget(source, source_area);
+
+        frame.render_widget(panel(" Locals ", &self.locals), locals_area);
+        frame.render_widget(panel(" Call stack ", &self.stack), stack_area);
+
+        // The end of the console, then the command being typed
+        let height = console_area.height.saturating_sub(2) as usize;
+        let mut lines: Vec<Line> = self
+            .console
+            .iter()
+            .skip(self.console.len().saturating_sub(height.saturating_sub(1)))
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        lines.push(Line::from(vec![
+            Span::styled("(minic) ", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(self.input.as_str()),
+            Span::styled("▏", Style::default().fg(Color::Yellow)),
+        ]));
+        let console = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Console  F5: continue  F10: next  F11: step  (Shift: backwards)  Esc: quit "),
+        );
+        frame.render_widget(console, console_area);
+    }
+
+    fn run(mut self, mut terminal: DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key.code, key.modifiers)
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn panel<'a>(title: &'a str, lines: &'a [String]) -> Paragraph<'a> {
+    Paragraph::new(lines.iter().map(|line| Line::from(line.as_str())).collect::<Vec<_>>())
+        .block(Block::default().borders(Borders::ALL).title(title))
+}
@@ -0,0 +1,321 @@
+// diagnostics.rs: A common shape for the errors and warnings of every phase,
+// so they can be printed, exported or annotated the same way.
+//
+// Phases find their diagnostics in their own order, so a Report puts them
+// back in the order of the source before printing: by file, then by line,
+// under a heading for the function each is in, the way gcc shows them.
+
+use crate::parser::ast::{ExternalDeclaration, ParseError, TranslationUnit};
+use crate::plugins::LintDiagnostic;
+use crate::policy::Violation;
+use crate::scope::ScopeError;
+use crate::lexer_regex::Token;
+use crate::source::{FileId, SourceMap, Spanned, TokenSpan};
+use crate::type_checker::{TypeError, TypeWarning};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Fatal, // an error compiling can't go on past
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub line: Option<usize>,
+    pub message: String,
+    pub file: Option<String>, // the name of a header it is in, if not the diagnostic's file
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: Option<usize>, // 1-based source line, when known
+    pub message: String,
+    pub notes: Vec<Note>,
+    pub name: Option<String>, // of a warning, for suppressing it
+    pub file: Option<FileId>, // None for the file being reported on
+}
+
+impl Diagnostic {
+    pub(crate) fn new(severity: Severity, line: Option<usize>, message: String) -> Self {
+        Diagnostic { severity, line, message, notes: Vec::new(), name: None, file: None }
+    }
+
+    /// A parse error at the token it was found at, when that is known
+    pub fn parse_error(error: &ParseError, span: Option<TokenSpan>) -> Self {
+        match span {
+            Some(span) => {
+                Diagnostic::new(Severity::Error, Some(span.line), format!("Parse error: {:?} at column {}", error, span.col))
+            }
+            None => Diagnostic::from(error),
+        }
+    }
+
+    /// The tokens lexing can't go on past, unterminated strings and block
+    /// comments, as fatal errors on the line each starts on
+    pub fn unterminated(tokens: &[Spanned<Token>]) -> Vec<Diagnostic> {
+        tokens
+            .iter()
+            .filter_map(|spanned| match spanned.token {
+                Token::UnterminatedString { line, .. } | Token::UnterminatedComment { line, .. } => {
+                    let message = spanned.token.lex_error().expect("unterminated tokens are lexer errors");
+                    Some(Diagnostic::new(Severity::Fatal, Some(line), message))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    fn with_note(mut self, line: Option<usize>, message: &str) -> Self {
+        self.notes.push(Note { line, message: message.to_string(), file: None });
+        self
+    }
+
+    /// `ERROR (line N): message`, followed by one indented line per note
+    pub fn render(&self) -> String {
+        let severity = match self.severity {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARNING",
+            Severity::Fatal => "FATAL",
+        };
+        let mut text = match self.line {
+            Some(line) => format!("{} (line {}): {}", severity, line, self.message),
+            None => format!("{}: {}", severity, self.message),
+        };
+        for note in &self.notes {
+            match (&note.file, note.line) {
+                (Some(file), Some(line)) => text.push_str(&format!("\n    note ({}, line {}): {}", file, line, note.message)),
+                (_, Some(line)) => text.push_str(&format!("\n    note (line {}): {}", line, note.message)),
+                (_, None) => text.push_str(&format!("\n    note: {}", note.message)),
+            }
+        }
+        text
+    }
+}
+
+/// `-Werror=<name>`: report the warnings of each of `names` as errors
+pub fn promote_warnings(diagnostics: &mut [Diagnostic], names: &[&str]) {
+    for diagnostic in diagnostics {
+        if diagnostic.severity == Severity::Warning
+            && let Some(name) = diagnostic.name.as_deref().filter(|name| names.contains(name))
+        {
+            diagnostic.message = diagnostic.message.replace(&format!("[warning: {}]", name), &format!("[-Werror={}]", name));
+            diagnostic.severity = Severity::Error;
+        }
+    }
+}
+
+/// The lines a function's definition runs over, from its first token to
+/// its closing brace
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSpan {
+    pub name: String,
+    pub lines: RangeInclusive<usize>,
+}
+
+/// Where each function of `unit` is, for a unit parsed from tokens with spans
+pub fn function_spans(unit: &TranslationUnit) -> Vec<FunctionSpan> {
+    unit.external_declarations
+        .iter()
+        .filter_map(|external| match external {
+            ExternalDeclaration::Function(function) => function.span.map(|span| FunctionSpan {
+                name: function.name.clone(),
+                lines: span.line..=span.end_line,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Diagnostics gathered for printing, in source order, up to a limit on
+/// errors (`--max-errors`)
+pub struct Report {
+    max_errors: Option<usize>,
+    errors: usize,
+    warnings: usize,
+    stopped: bool,
+    out: String,
+}
+
+impl Report {
+    pub fn new(max_errors: Option<usize>) -> Self {
+        Report { max_errors, errors: 0, warnings: 0, stopped: false, out: String::new() }
+    }
+
+    /// Add a file's diagnostics, sorted by line and grouped by function.
+    /// With `file`, each line is prefixed with it; without, indented. Those
+    /// with no known line come after the rest.
+    pub fn add_file(&mut self, file: Option<&str>, diagnostics: &[Diagnostic], functions: &[FunctionSpan]) {
+        let mut sorted: Vec<&Diagnostic> = diagnostics.iter().collect();
+        sorted.sort_by_key(|diagnostic| (diagnostic.line.is_none(), diagnostic.line));
+        let prefix = file.map_or("  ".to_string(), |file| format!("{}: ", file));
+        let mut group = None;
+        for diagnostic in sorted {
+            if self.stopped {
+                return;
+            }
+            let function = diagnostic.line.and_then(|line| functions.iter().find(|span| span.lines.contains(&line)));
+            let heading = match (diagnostic.line, function) {
+                (Some(_), Some(function)) => format!("In function '{}':", function.name),
+                (Some(_), None) => "At top level:".to_string(),
+                (None, _) => "At an unknown line:".to_string(),
+            };
+            if group.as_ref() != Some(&heading) {
+                match file {
+                    Some(file) => self.out.push_str(&format!("{}: {}\n", file, heading)),
+                    None => self.out.push_str(&format!("{}\n", heading)),
+                }
+                group = Some(heading);
+            }
+            for line in diagnostic.render().lines() {
+                self.out.push_str(&format!("{}{}\n", prefix, line));
+            }
+            match diagnostic.severity {
+                Severity::Error | Severity::Fatal => self.errors += 1,
+                Severity::Warning => self.warnings += 1,
+            }
+            self.stopped = self.max_errors.is_some_and(|max| self.errors >= max);
+        }
+    }
+
+    /// Add the diagnostics of several files at once, each file's under its
+    /// name in `sources`, the files in the order they were added. Those
+    /// with no file are the first file's.
+    pub fn add_files(
+        &mut self,
+        sources: &SourceMap,
+        diagnostics: &[Diagnostic],
+        functions: &HashMap<FileId, Vec<FunctionSpan>>,
+    ) {
+        for file in sources.files() {
+            let in_file: Vec<Diagnostic> = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.file.unwrap_or_default() == file.id)
+                .cloned()
+                .collect();
+            let spans = functions.get(&file.id).map_or(&[][..], Vec::as_slice);
+            self.add_file(Some(&file.name), &in_file, spans);
+            if self.stopped {
+                return;
+            }
+        }
+    }
+
+    /// Whether any error has been added
+    pub fn has_errors(&self) -> bool {
+        self.errors > 0
+    }
+
+    /// Whether the limit on errors has been reached
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// The diagnostics added, then "3 errors, 5 warnings emitted"
+    pub fn render(&self) -> String {
+        let count = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+        let mut text = self.out.clone();
+        if self.stopped {
+            text.push_str(&format!("Stopping after {} (--max-errors)\n", count(self.errors, "error")));
+        }
+        text.push_str(&format!("{}, {} emitted\n", count(self.errors, "error"), count(self.warnings, "warning")));
+        text
+    }
+}
+
+fn with_context(message: &str, context: &str) -> String {
+    if context.is_empty() {
+        message.to_string()
+    } else {
+        format!("{} [context: {}]", message, context)
+    }
+}
+
+impl From<&ScopeError> for Diagnostic {
+    fn from(error: &ScopeError) -> Self {
+        match error {
+            ScopeError::UndeclaredVariable { name, line } => {
+                Diagnostic::new(Severity::Error, *line, format!("Undeclared variable '{}' accessed", name))
+            }
+            ScopeError::UndefinedFunctionCalled { name, line } => {
+                Diagnostic::new(Severity::Error, *line, format!("Undefined function '{}' called", name))
+            }
+            ScopeError::VariableRedefinition { name, line, previous_line } => {
+                Diagnostic::new(Severity::Error, *line, format!("Variable '{}' redefined in same scope", name))
+                    .with_note(*previous_line, "previously declared here")
+            }
+            ScopeError::FunctionPrototypeRedefinition { name, line, previous_line } => {
+                Diagnostic::new(Severity::Error, *line, format!("Function '{}' redefined", name))
+                    .with_note(*previous_line, "previously defined here")
+            }
+            ScopeError::ConflictingFunctionDeclaration { name, line, previous_line } => {
+                Diagnostic::new(Severity::Error, *line, format!("Conflicting types for function '{}'", name))
+                    .with_note(*previous_line, "previously declared here")
+            }
+            ScopeError::LabelRedefinition { name, line, previous_line } => {
+                Diagnostic::new(Severity::Error, *line, format!("Label '{}' defined twice in one function", name))
+                    .with_note(*previous_line, "previously defined here")
+            }
+            ScopeError::TagKindMismatch { name, kind, previous_kind, line, previous_line } => {
+                let message = format!(
+                    "Tag '{}' used as '{} {}', but it is '{} {}'",
+                    name,
+                    kind.keyword(),
+                    name,
+                    previous_kind.keyword(),
+                    name
+                );
+                Diagnostic::new(Severity::Error, *line, message).with_note(*previous_line, "previously declared here")
+            }
+            ScopeError::UndefinedLabel { name, line } => {
+                Diagnostic::new(Severity::Error, *line, format!("goto to label '{}', which the function doesn't define", name))
+            }
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        Diagnostic::new(Severity::Error, None, format!("Parse error: {:?}", error))
+    }
+}
+
+impl From<&TypeError> for Diagnostic {
+    fn from(error: &TypeError) -> Self {
+        Diagnostic::new(Severity::Error, error.line, with_context(error.error.message(), &error.context))
+    }
+}
+
+impl From<&LintDiagnostic> for Diagnostic {
+    fn from(diagnostic: &LintDiagnostic) -> Self {
+        let message = format!("{} [lint: {}]", diagnostic.message, diagnostic.lint);
+        Diagnostic::new(Severity::Warning, diagnostic.line, message).named(&diagnostic.lint)
+    }
+}
+
+impl From<&Violation> for Diagnostic {
+    fn from(violation: &Violation) -> Self {
+        let message = format!("{} [policy: {}]", violation.message, violation.banned);
+        Diagnostic::new(Severity::Error, violation.line, message).named(violation.banned.name())
+    }
+}
+
+impl From<&TypeWarning> for Diagnostic {
+    fn from(warning: &TypeWarning) -> Self {
+        let name = warning.warning.name();
+        let message = format!("{} [warning: {}]", with_context(warning.warning.message(), &warning.context), name);
+        let diagnostic = Diagnostic::new(Severity::Warning, warning.line, message).named(name);
+        match warning.warning.note() {
+            Some(note) => diagnostic.with_note(warning.line, &note),
+            None => diagnostic,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// dialect.rs: Language standards selected with --std, and the features each
+// one has. The lexers, parser and type checker all consult the same table.
+
+/// A language standard, oldest first; MiniC is this compiler's own dialect
+/// and accepts everything
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Standard {
+    C89,
+    C99,
+    C11,
+    #[default]
+    MiniC,
+}
+
+/// Something only some standards have
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    LineComments,
+    MixedDeclarations,
+    ForLoopDeclarations,
+    VariableLengthArrays,
+    CompoundLiterals,
+    DesignatedInitializers,
+    GenericSelections,
+    Inline,
+    Restrict,
+    Noreturn,
+}
+
+impl Standard {
+    /// The standard a `--std=` value names
+    pub fn from_name(name: &str) -> Option<Standard> {
+        match name.to_ascii_lowercase().as_str() {
+            "c89" | "c90" | "ansi" => Some(Standard::C89),
+            "c99" => Some(Standard::C99),
+            "c11" => Some(Standard::C11),
+            "minic" => Some(Standard::MiniC),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Standard::C89 => "C89",
+            Standard::C99 => "C99",
+            Standard::C11 => "C11",
+            Standard::MiniC => "MiniC",
+        }
+    }
+
+    pub fn supports(self, feature: Feature) -> bool {
+        self >= feature.introduced_in()
+    }
+
+    /// The error for using `feature` under this standard, if it lacks it
+    pub fn check(self, feature: Feature) -> Result<(), String> {
+        if self.supports(feature) {
+            Ok(())
+        } else {
+            Err(format!("{} requires {}", feature.description(), feature.introduced_in().name()))
+        }
+    }
+
+    /// C99 and C11 make falling off the end of main return 0. MiniC, like
+    /// C89, still wants the return written out.
+    pub fn main_returns_zero(self) -> bool {
+        matches!(self, Standard::C99 | Standard::C11)
+    }
+}
+
+impl Feature {
+    pub fn introduced_in(self) -> Standard {
+        match self {
+            Feature::LineComments
+            | Feature::MixedDeclarations
+            | Feature::ForLoopDeclarations
+            | Feature::VariableLengthArrays
+            | Feature::CompoundLiterals
+            | Feature::DesignatedInitializers
+            | Feature::Inline
+            | Feature::Restrict => Standard::C99,
+            Feature::GenericSelections | Feature::Noreturn => Standard::C11,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Feature::LineComments => "'//' comment",
+            Feature::MixedDeclarations => "declaration after statement",
+            Feature::ForLoopDeclarations => "declaration in a for loop initializer",
+            Feature::VariableLengthArrays => "variable length array",
+            Feature::CompoundLiterals => "compound literal",
+            Feature::DesignatedInitializers => "designated initializer",
+            Feature::GenericSelections => "_Generic",
+            Feature::Inline => "'inline'",
+            Feature::Restrict => "'restrict'",
+            Feature::Noreturn => "'_Noreturn'",
+        }
+    }
+}
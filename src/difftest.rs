@@ -0,0 +1,156 @@
+// difftest.rs: Differential execution: one program run by more than one
+// backend with the same arguments, and any difference in what it printed or
+// how it exited reported (`difftest`).
+//
+// A backend is anything that can run a program: the interpreter, the
+// system C compiler, and code generation once there is one. The first
+// backend given is the reference the others are held to; until this
+// compiler emits code of its own, that is the system compiler, and the
+// interpreter is checked against it.
+
+use crate::fold;
+use crate::ice;
+use crate::interpreter::{Machine, Status};
+use crate::lexer_regex;
+use crate::parser::Parser;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Interpreter steps a program may take before it is taken to hang
+const MAX_STEPS: usize = 1_000_000;
+
+/// What a run printed and its exit code
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub output: String,
+    pub exit_code: i64,
+}
+
+pub trait Backend {
+    fn name(&self) -> &str;
+    /// Run `source` with `arguments` after the program's name; Err if it
+    /// couldn't be built or didn't finish
+    fn run(&self, source: &str, arguments: &[String]) -> Result<Outcome, String>;
+}
+
+pub struct Interpreter {
+    pub gnu_extensions: bool,
+    pub fast_math: bool,
+}
+
+impl Backend for Interpreter {
+    fn name(&self) -> &str {
+        "interpreter"
+    }
+
+    fn run(&self, source: &str, arguments: &[String]) -> Result<Outcome, String> {
+        let mut parser = Parser::with_spans(lexer_regex::lex_with_regex_positions(source, Default::default()));
+        parser.set_gnu_extensions(self.gnu_extensions);
+        let mut unit = parser.parse().map_err(|errors| format!("doesn't parse: {:?}", errors))?;
+        fold::fold_constants(&mut unit);
+        let run = ice::catch(|| {
+            let mut machine = Machine::with_arguments(&unit, source, arguments).map_err(|error| error.render())?;
+            machine.fast_math = self.fast_math;
+            let mut output = Vec::new();
+            let exit_code = loop {
+                if machine.steps() > MAX_STEPS {
+                    return Err(format!("still running after {} steps", MAX_STEPS));
+                }
+                let status = machine.step().map_err(|error| error.render())?;
+                output.extend(machine.take_output());
+                if let Status::Exited(code) = status {
+                    break code;
+                }
+            };
+            Ok(Outcome { output: String::from_utf8_lossy(&output).into_owned(), exit_code })
+        });
+        run.unwrap_or_else(|ice| Err(format!("panicked in {}: {}", ice.phase, ice.message)))
+    }
+}
+
+/// The system C compiler, building into a scratch directory
+pub struct Native {
+    pub compiler: String,
+    pub fast_math: bool, // built with -ffast-math
+    scratch: PathBuf,
+}
+
+impl Native {
+    /// $CC, or `cc`, if it runs; None if there is no C compiler
+    pub fn find(scratch: PathBuf) -> Option<Native> {
+        let compiler = std::env::var("CC").unwrap_or("cc".to_string());
+        let found = Command::new(&compiler).arg("--version").output().is_ok_and(|output| output.status.success());
+        found.then_some(Native { compiler, fast_math: false, scratch })
+    }
+}
+
+impl Backend for Native {
+    fn name(&self) -> &str {
+        &self.compiler
+    }
+
+    fn run(&self, source: &str, arguments: &[String]) -> Result<Outcome, String> {
+        fs::create_dir_all(&self.scratch).map_err(|error| error.to_string())?;
+        let (file, binary) = (self.scratch.join("program.c"), self.scratch.join("program"));
+        fs::write(&file, source).map_err(|error| error.to_string())?;
+        let built = Command::new(&self.compiler)
+            .arg("-w")
+            .args(self.fast_math.then_some("-ffast-math"))
+            .arg("-o")
+            .arg(&binary)
+            .arg(&file)
+            .arg("-lm")
+            .output()
+            .map_err(|error| error.to_string())?;
+        if !built.status.success() {
+            return Err(format!("{} failed: {}", self.compiler, String::from_utf8_lossy(&built.stderr).trim()));
+        }
+        let run = Command::new(&binary).args(arguments).output().map_err(|error| error.to_string())?;
+        let exit_code = run.status.code().ok_or("killed by a signal")?;
+        Ok(Outcome { output: String::from_utf8_lossy(&run.stdout).into_owned(), exit_code: exit_code as i64 })
+    }
+}
+
+/// A program's run by each backend, in the order given
+pub struct Comparison {
+    pub runs: Vec<(String, Result<Outcome, String>)>,
+}
+
+impl Comparison {
+    /// Whether some backend's run differs from the first's, or failed
+    pub fn diverges(&self) -> bool {
+        let Some((_, reference)) = self.runs.first() else {
+            return false;
+        };
+        self.runs.iter().any(|(_, run)| run.is_err() || run != reference)
+    }
+
+    /// Every backend's run, a line each, when they don't all agree; nothing
+    /// if they do
+    pub fn render(&self) -> String {
+        if !self.diverges() {
+            return String::new();
+        }
+        let mut text = String::new();
+        for (name, run) in &self.runs {
+            match run {
+                Ok(outcome) => {
+                    text.push_str(&format!("{}: exited {}, printed {:?}\n", name, outcome.exit_code, outcome.output))
+                }
+                Err(error) => text.push_str(&format!("{}: {}\n", name, error)),
+            }
+        }
+        text
+    }
+}
+
+/// Run `source` with `arguments` by each of `backends`
+pub fn compare(source: &str, arguments: &[String], backends: &[&dyn Backend]) -> Comparison {
+    Comparison {
+        runs: backends
+            .iter()
+            .map(|backend| (backend.name().to_string(), backend.run(source, arguments)))
+            .collect(),
+    }
+}
@@ -0,0 +1,297 @@
+// doc.rs: `doc <file>`, API documentation from `/** ... */` comments, as
+// Markdown or HTML.
+//
+// A doc comment is attached to the declaration at file scope that follows
+// it: a function, its prototype, a struct, an enum or a global. The AST
+// keeps no comments, so they are attached in the tokens: the name a
+// declaration declares is read off the tokens up to its `;` or `{`, and
+// the comment is found again by that name, tags apart from other names as
+// they are in C. A comment that isn't right before a declaration, such as
+// one inside a function, documents nothing.
+//
+//     /**
+//      * The larger of two numbers.
+//      * @param a the first
+//      * @param b the second
+//      * @return a or b, whichever is larger
+//      */
+//     int max(int a, int b);
+//
+// `@param` and `@return` lines are listed apart from the text; a line that
+// follows one without a tag of its own continues it. Function signatures
+// are the symbol table's, so a function declared and defined is listed
+// once, with the comment of either.
+
+use crate::html;
+use crate::lexer_regex::{self, Token};
+use crate::parser::ast::*;
+use crate::pretty;
+use crate::scope::{ScopeAnalyzer, SymbolKind};
+use crate::source::{SourceFile, Spanned};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ItemKind {
+    Function,
+    Struct,
+    Enum,
+    Global,
+}
+
+impl ItemKind {
+    /// Heading of the section listing items of this kind
+    fn heading(&self) -> &'static str {
+        match self {
+            ItemKind::Function => "Functions",
+            ItemKind::Struct => "Structs",
+            ItemKind::Enum => "Enums",
+            ItemKind::Global => "Globals",
+        }
+    }
+
+    /// Struct and enum tags are names of their own, apart from the others
+    fn is_tag(&self) -> bool {
+        matches!(self, ItemKind::Struct | ItemKind::Enum)
+    }
+}
+
+/// A doc comment with its `/**`, `*/` and leading `*`s taken off
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocComment {
+    pub text: String,
+    pub params: Vec<(String, String)>,
+    pub returns: Option<String>,
+}
+
+impl DocComment {
+    pub fn parse(comment: &str) -> DocComment {
+        let body = comment.trim_start_matches("/**").trim_end_matches("*/");
+        let (mut params, mut returns) = (Vec::new(), None);
+        let mut lines = Vec::new();
+        // The description of the tag the last line was, which a line
+        // without one continues
+        let mut last_tag: Option<&mut String> = None;
+        for line in body.lines() {
+            let line = line.trim();
+            let line = line.strip_prefix('*').map_or(line, str::trim_start);
+            if let Some(rest) = line.strip_prefix("@param") {
+                let (name, description) = rest.trim().split_once(char::is_whitespace).unwrap_or((rest.trim(), ""));
+                params.push((name.to_string(), description.trim().to_string()));
+                last_tag = params.last_mut().map(|(_, description)| description);
+            } else if let Some(rest) = line.strip_prefix("@returns").or_else(|| line.strip_prefix("@return")) {
+                last_tag = Some(returns.insert(rest.trim().to_string()));
+            } else if let (Some(description), false) = (last_tag.as_mut(), line.is_empty()) {
+                description.push(' ');
+                description.push_str(line);
+            } else {
+                last_tag = None;
+                lines.push(line);
+            }
+        }
+        DocComment { text: lines.join("\n").trim().to_string(), params, returns }
+    }
+}
+
+/// Something a file declares, as it is documented
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocItem {
+    pub kind: ItemKind,
+    pub name: String,
+    pub signature: String,
+    pub doc: Option<DocComment>,
+}
+
+/// Everything `unit` declares at file scope, in source order, each with
+/// the doc comment before it
+pub fn extract(unit: &TranslationUnit, source: &Rc<SourceFile>) -> Vec<DocItem> {
+    let comments = attached_comments(&lexer_regex::lex_with_regex_positions(source.text(), Default::default()));
+    let mut scope_analyzer = ScopeAnalyzer::with_source(source.clone());
+    let _ = scope_analyzer.analyze_translation_unit(unit);
+    let global_scope = scope_analyzer.get_global_scope();
+
+    let mut items: Vec<DocItem> = Vec::new();
+    for external in &unit.external_declarations {
+        let (kind, name, signature) = match external {
+            ExternalDeclaration::Function(FunctionDefinition { name, return_type, parameters, variadic, .. })
+            | ExternalDeclaration::FunctionDeclaration(FunctionDeclaration { name, return_type, parameters, variadic, .. }) => {
+                let signature = match global_scope.lookup(name).map(|symbol| symbol.kind) {
+                    Some(SymbolKind::Function { return_type, parameters, variadic, .. }) => {
+                        pretty::signature(&return_type, name, &parameters, variadic)
+                    }
+                    _ => pretty::signature(return_type, name, parameters, *variadic),
+                };
+                (ItemKind::Function, name, signature)
+            }
+            ExternalDeclaration::Struct(definition) => {
+                let mut signature = format!("struct {} {{\n", definition.name);
+                for member in &definition.members {
+                    signature.push_str(&format!("    {};\n", pretty::declaration(member)));
+                }
+                signature.push('}');
+                (ItemKind::Struct, &definition.name, signature)
+            }
+            // Documented where it's defined
+            ExternalDeclaration::StructDeclaration(..) => continue,
+            // An anonymous enum has no name to be documented under
+            ExternalDeclaration::Enum(EnumDefinition { name: None, .. }) => continue,
+            ExternalDeclaration::Enum(definition @ EnumDefinition { name: Some(name), .. }) => {
+                let mut signature = format!("{} {{\n", definition.head());
+                for enumerator in &definition.enumerators {
+                    match &enumerator.value {
+                        Some(value) => signature.push_str(&format!("    {} = {},\n", enumerator.name, pretty::expression(value))),
+                        None => signature.push_str(&format!("    {},\n", enumerator.name)),
+                    }
+                }
+                signature.push('}');
+                (ItemKind::Enum, name, signature)
+            }
+            ExternalDeclaration::Variable(declaration) => {
+                (ItemKind::Global, &declaration.declarator.name, pretty::declaration(declaration))
+            }
+        };
+        let doc = comments.get(&(kind.is_tag(), name.clone())).map(|comment| DocComment::parse(comment));
+        // A prototype and its definition are one function
+        match items.iter_mut().find(|item| item.kind == kind && item.name == *name) {
+            Some(item) => item.doc = item.doc.take().or(doc),
+            None => items.push(DocItem { kind, name: name.clone(), signature, doc }),
+        }
+    }
+    items
+}
+
+/// Each doc comment at file scope, by whether the name after it is a tag
+/// and that name
+fn attached_comments(tokens: &[Spanned<Token>]) -> HashMap<(bool, String), String> {
+    let mut comments = HashMap::new();
+    let mut depth = 0usize;
+    let mut pending: Option<&str> = None;
+    let mut head: Vec<&Token> = Vec::new();
+    for spanned in tokens {
+        let token = &spanned.token;
+        match token {
+            Token::BlockComment(text) if depth == 0 && is_doc_comment(text) => {
+                pending = Some(text);
+                head.clear();
+            }
+            Token::Comment(_) | Token::BlockComment(_) | Token::Pragma(_) | Token::Preprocessor(_) => {}
+            Token::BraceL | Token::Semicolon if depth == 0 => {
+                if let (Some(comment), Some(name)) = (pending.take(), declared_name(&head, token)) {
+                    comments.entry(name).or_insert_with(|| comment.to_string());
+                }
+                head.clear();
+                if *token == Token::BraceL {
+                    depth += 1;
+                }
+            }
+            Token::BraceL => depth += 1,
+            Token::BraceR => depth = depth.saturating_sub(1),
+            _ if depth == 0 => head.push(token),
+            _ => {}
+        }
+    }
+    comments
+}
+
+fn is_doc_comment(text: &str) -> bool {
+    text.starts_with("/**") && text != "/**/"
+}
+
+/// What the declaration `head` declares, ended by `end` (`;` or `{`):
+/// a struct or enum tag it defines, or else a function or variable name
+fn declared_name(head: &[&Token], end: &Token) -> Option<(bool, String)> {
+    if *end == Token::BraceL
+        && let [.., Token::Struct | Token::Enum | Token::Union, Token::Identifier(tag)] = head
+    {
+        return Some((true, tag.clone()));
+    }
+    // The name right before the parameter list, or the last one before
+    // any initializer or array size
+    let stop = head
+        .iter()
+        .position(|token| matches!(token, Token::ParenL | Token::AssignOp | Token::BracketL))
+        .unwrap_or(head.len());
+    head[..stop].iter().rev().find_map(|token| match token {
+        Token::Identifier(name) => Some((false, name.clone())),
+        _ => None,
+    })
+}
+
+fn sections(items: &[DocItem]) -> Vec<(ItemKind, Vec<&DocItem>)> {
+    [ItemKind::Function, ItemKind::Struct, ItemKind::Enum, ItemKind::Global]
+        .into_iter()
+        .map(|kind| (kind, items.iter().filter(|item| item.kind == kind).collect::<Vec<_>>()))
+        .filter(|(_, items)| !items.is_empty())
+        .collect()
+}
+
+pub fn render_markdown(title: &str, items: &[DocItem]) -> String {
+    let mut text = format!("# {}\n", title);
+    for (kind, items) in sections(items) {
+        text.push_str(&format!("\n## {}\n", kind.heading()));
+        for item in items {
+            text.push_str(&format!("\n### `{}`\n\n```c\n{}\n```\n", item.name, item.signature));
+            let Some(doc) = &item.doc else {
+                continue;
+            };
+            if !doc.text.is_empty() {
+                text.push_str(&format!("\n{}\n", doc.text));
+            }
+            if !doc.params.is_empty() {
+                text.push_str("\n**Parameters**\n\n");
+                for (name, description) in &doc.params {
+                    text.push_str(&format!("- `{}`: {}\n", name, description));
+                }
+            }
+            if let Some(returns) = &doc.returns {
+                text.push_str(&format!("\n**Returns** {}\n", returns));
+            }
+        }
+    }
+    text
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; max-width: 50em; }
+pre { font-family: monospace; font-size: 14px; background: #fafafa; padding: 1em; }
+h3 code { font-size: 1.1em; }
+.params dt { font-family: monospace; font-weight: bold; }
+";
+
+/// A standalone HTML page of the same documentation
+pub fn render_html(title: &str, items: &[DocItem]) -> String {
+    let mut body = format!("<h1>{}</h1>\n", html::escape(title));
+    for (kind, items) in sections(items) {
+        body.push_str(&format!("<h2>{}</h2>\n", kind.heading()));
+        for item in items {
+            body.push_str(&format!(
+                "<h3 id=\"{}\"><code>{}</code></h3>\n<pre>{}</pre>\n",
+                html::escape(&item.name),
+                html::escape(&item.name),
+                html::escape(&item.signature)
+            ));
+            let Some(doc) = &item.doc else {
+                continue;
+            };
+            for paragraph in doc.text.split("\n\n").filter(|paragraph| !paragraph.is_empty()) {
+                body.push_str(&format!("<p>{}</p>\n", html::escape(paragraph)));
+            }
+            if !doc.params.is_empty() {
+                body.push_str("<h4>Parameters</h4>\n<dl class=\"params\">\n");
+                for (name, description) in &doc.params {
+                    body.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", html::escape(name), html::escape(description)));
+                }
+                body.push_str("</dl>\n");
+            }
+            if let Some(returns) = &doc.returns {
+                body.push_str(&format!("<h4>Returns</h4>\n<p>{}</p>\n", html::escape(returns)));
+            }
+        }
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        html::escape(title),
+        STYLE,
+        body
+    )
+}
@@ -0,0 +1,573 @@
+// explorer.rs: Terminal UI for browsing a program's AST, tokens and symbols.
+//
+// The line shown for a node is where its span starts; an expression, which
+// has none, is shown at the line of the statement it is in.
+
+use crate::lexer_regex::{self, Token};
+use crate::parser::ast::*;
+use crate::parser::Parser;
+use crate::scope::ScopeAnalyzer;
+use crate::source::{SourceFile, TokenSpan};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+struct TreeNode {
+    label: String,
+    line: Option<usize>, // 0-based source line
+    depth: usize,
+    children: Vec<usize>,
+    parent: Option<usize>,
+    expanded: bool,
+}
+
+/// Arena of AST nodes in display form, built from a parsed translation unit
+struct AstTree {
+    nodes: Vec<TreeNode>,
+}
+
+/// The 0-based line a span starts on
+fn start_line(span: Option<TokenSpan>) -> Option<usize> {
+    span.map(|span| span.line - 1)
+}
+
+impl AstTree {
+    fn build(unit: &TranslationUnit) -> Vec<TreeNode> {
+        let mut tree = AstTree { nodes: Vec::new() };
+        let root = tree.add(None, "TranslationUnit".to_string(), Some(0));
+
+        for (index, directive) in unit.preprocessor_list.iter().enumerate() {
+            let label = match directive {
+                PreprocessorDirective::Include(path) => format!("#include <{}>", path),
+                PreprocessorDirective::Define(name, _) => format!("#define {}", name),
+                PreprocessorDirective::Ifdef(name) => format!("#ifdef {}", name),
+                PreprocessorDirective::Ifndef(name) => format!("#ifndef {}", name),
+                PreprocessorDirective::Endif => "#endif".to_string(),
+            };
+            tree.add(Some(root), label, start_line(unit.preprocessor_spans.get(index).copied().flatten()));
+        }
+
+        for declaration in &unit.external_declarations {
+            let line = start_line(declaration.span());
+            match declaration {
+                ExternalDeclaration::Variable(var) => {
+                    tree.add_variable(root, var, None);
+                }
+                ExternalDeclaration::Function(func) => {
+                    let signature = Self::signature(&func.return_type, &func.name, &func.parameters, func.variadic);
+                    let line = start_line(func.name_span).or(line);
+                    let node = tree.add(Some(root), format!("Function {}", signature), line);
+                    tree.add_parameters(node, &func.parameters, line);
+                    tree.add_statements(node, &func.body);
+                }
+                ExternalDeclaration::FunctionDeclaration(decl) => {
+                    let signature = Self::signature(&decl.return_type, &decl.name, &decl.parameters, decl.variadic);
+                    let node = tree.add(Some(root), format!("Prototype {}", signature), line);
+                    tree.add_parameters(node, &decl.parameters, line);
+                }
+                ExternalDeclaration::Struct(definition) => {
+                    let node = tree.add(Some(root), format!("Struct {}", definition.name), line);
+                    for member in &definition.members {
+                        tree.add_variable(node, member, line);
+                    }
+                }
+                ExternalDeclaration::StructDeclaration(tag, _) => {
+                    tree.add(Some(root), format!("Struct {} (declared)", tag), line);
+                }
+                ExternalDeclaration::Enum(definition) => {
+                    let label = definition.name.as_deref().unwrap_or("(anonymous)");
+                    let node = tree.add(Some(root), format!("Enum {}", label), line);
+                    for enumerator in &definition.enumerators {
+                        let enumerator_line = start_line(enumerator.span).or(line);
+                        tree.add(Some(node), format!("Enumerator {}", enumerator.name), enumerator_line);
+                    }
+                }
+            }
+        }
+
+        tree.nodes
+    }
+
+    fn signature(return_type: &str, name: &str, parameters: &[Parameter], variadic: bool) -> String {
+        let mut params: Vec<String> = parameters
+            .iter()
+            .map(|p| format!("{} {}", p.param_type, p.name))
+            .collect();
+        if variadic {
+            params.push("...".to_string());
+        }
+        format!("{} {}({})", return_type, name, params.join(", "))
+    }
+
+    fn add(&mut self, parent: Option<usize>, label: String, line: Option<usize>) -> usize {
+        let index = self.nodes.len();
+        let depth = parent.map_or(0, |p| self.nodes[p].depth + 1);
+        self.nodes.push(TreeNode { label, line, depth, children: Vec::new(), parent, expanded: depth < 2 });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(index);
+        }
+        index
+    }
+
+    fn add_parameters(&mut self, parent: usize, parameters: &[Parameter], line: Option<usize>) {
+        for param in parameters {
+            self.add(Some(parent), format!("Parameter {} {}", param.param_type, param.name), line);
+        }
+    }
+
+    /// A declaration, at its own line or else `outer`
+    fn add_variable(&mut self, parent: usize, var: &VariableDeclaration, outer: Option<usize>) -> usize {
+        let declarator = &var.declarator;
+        let line = start_line(var.span).or(outer);
+        let label = format!(
+            "Declaration {:?}{} {}",
+            var.type_specifier,
+            "*".repeat(declarator.pointer_depth as usize),
+            declarator.name
+        );
+        let node = self.add(Some(parent), label, line);
+        if let Some(Initializer { kind: InitializerKind::Assignment(expr) }) = &var.initializer {
+            self.add_expression(node, expr);
+        }
+        node
+    }
+
+    fn add_statements(&mut self, parent: usize, stmts: &[Statement]) {
+        for stmt in stmts {
+            self.add_statement(parent, stmt);
+        }
+    }
+
+    fn add_statement(&mut self, parent: usize, stmt: &Statement) -> usize {
+        // A statement the parser made up is shown where its parent is
+        let line = start_line(stmt.span).or(self.nodes[parent].line);
+        match &stmt.kind {
+            StatementKind::Declaration(var) => self.add_variable(parent, var, line),
+            StatementKind::Assignment(name, expr) => {
+                let node = self.add(Some(parent), format!("Assignment {}", name), line);
+                self.add_expression(node, expr);
+                node
+            }
+            StatementKind::Return(expr) => {
+                let node = self.add(Some(parent), "Return".to_string(), line);
+                if let Some(expr) = expr {
+                    self.add_expression(node, expr);
+                }
+                node
+            }
+            StatementKind::Expression(expr) => {
+                let node = self.add(Some(parent), "ExpressionStatement".to_string(), line);
+                self.add_expression(node, expr);
+                node
+            }
+            StatementKind::Block(stmts) => {
+                let node = self.add(Some(parent), "Block".to_string(), line);
+                self.add_statements(node, stmts);
+                node
+            }
+            StatementKind::If(cond, then_stmt, else_stmt) => {
+                let node = self.add(Some(parent), "If".to_string(), line);
+                self.add_expression(node, cond);
+                self.add_statement(node, then_stmt);
+                if let Some(else_stmt) = else_stmt {
+                    let else_node = self.add(Some(node), "Else".to_string(), start_line(else_stmt.span).or(line));
+                    self.add_statement(else_node, else_stmt);
+                }
+                node
+            }
+            StatementKind::While(cond, body) => {
+                let node = self.add(Some(parent), "While".to_string(), line);
+                self.add_expression(node, cond);
+                self.add_statement(node, body);
+                node
+            }
+            StatementKind::For(init, cond, update, body) => {
+                let node = self.add(Some(parent), "For".to_string(), line);
+                if let Some(init) = init {
+                    let init_node = self.add(Some(node), "Init".to_string(), line);
+                    self.add_statement(init_node, init);
+                }
+                if let Some(cond) = cond {
+                    let cond_node = self.add(Some(node), "Condition".to_string(), line);
+                    self.add_expression(cond_node, cond);
+                }
+                if let Some(update) = update {
+                    let update_node = self.add(Some(node), "Update".to_string(), line);
+                    self.add_expression(update_node, update);
+                }
+                self.add_statement(node, body);
+                node
+            }
+            StatementKind::Break => self.add(Some(parent), "Break".to_string(), line),
+            StatementKind::Empty => self.add(Some(parent), "Empty".to_string(), line),
+            StatementKind::Switch(value, body) => {
+                let node = self.add(Some(parent), "Switch".to_string(), line);
+                self.add_expression(node, value);
+                self.add_statement(node, body);
+                node
+            }
+            StatementKind::Case(value, stmt) => {
+                let node = self.add(Some(parent), "Case".to_string(), line);
+                self.add_expression(node, value);
+                self.add_statement(node, stmt);
+                node
+            }
+            StatementKind::Default(stmt) => {
+                let node = self.add(Some(parent), "Default".to_string(), line);
+                self.add_statement(node, stmt);
+                node
+            }
+            StatementKind::Fallthrough => self.add(Some(parent), "Fallthrough".to_string(), line),
+            StatementKind::Label(name, stmt) => {
+                let node = self.add(Some(parent), format!("Label: {}", name), line);
+                self.add_statement(node, stmt);
+                node
+            }
+            StatementKind::Goto(name) => self.add(Some(parent), format!("Goto: {}", name), line),
+        }
+    }
+
+    /// Expressions have no spans of their own, so each is shown at the
+    /// line of the statement or declaration it is in
+    fn add_expression(&mut self, parent: usize, expr: &Expression) {
+        let line = self.nodes[parent].line;
+        if let Expression::StatementExpr(stmts) = expr {
+            let node = self.add(Some(parent), "StatementExpr".to_string(), line);
+            self.add_statements(node, stmts);
+            return;
+        }
+        let (label, children): (String, Vec<&Expression>) = match expr {
+            Expression::Identifier(name) => (format!("Identifier {}", name), vec![]),
+            Expression::Constant(constant) => (format!("Constant {:?}", constant), vec![]),
+            Expression::Folded(constant, name) => (format!("Constant {:?} from {}", constant, name), vec![]),
+            Expression::StringLiteral(s) => (format!("StringLiteral {:?}", s), vec![]),
+            Expression::EncodedStringLiteral(encoding, s) => {
+                (format!("StringLiteral {}{:?}", encoding.prefix(), s), vec![])
+            }
+            Expression::BinaryOp(left, op, right) => (format!("BinaryOp {:?}", op), vec![left, right]),
+            Expression::UnaryOp(op, operand) => (format!("UnaryOp {:?}", op), vec![operand]),
+            Expression::Assignment(target, op, value) => {
+                (format!("Assignment {}", op.symbol()), vec![target, value])
+            }
+            Expression::Conditional(cond, then_expr, else_expr) => {
+                ("Conditional".to_string(), vec![cond, then_expr, else_expr])
+            }
+            Expression::FunctionCall(callee, args) => {
+                let label = match callee.as_function_name() {
+                    Some(name) => format!("Call {}", name),
+                    None => "Call".to_string(),
+                };
+                let mut children: Vec<&Expression> = Vec::new();
+                if callee.as_function_name().is_none() {
+                    children.push(callee);
+                }
+                children.extend(args.iter());
+                (label, children)
+            }
+            Expression::ArrayAccess(array, index) => ("ArrayAccess".to_string(), vec![array, index]),
+            Expression::MemberAccess(object, member) => (format!("MemberAccess .{}", member), vec![object]),
+            Expression::PointerAccess(object, member) => (format!("PointerAccess ->{}", member), vec![object]),
+            Expression::PostfixOp(operand, op) => (format!("PostfixOp {:?}", op), vec![operand]),
+            Expression::Cast(target, operand) => (format!("Cast ({})", target.spelling()), vec![operand]),
+            Expression::SizeofType(operand) => (format!("Sizeof ({})", operand.spelling()), operand.expressions()),
+            Expression::Paren(inner) => ("Paren".to_string(), vec![inner]),
+            Expression::StatementExpr(_) => unreachable!("handled above"),
+            Expression::Generic(controlling, associations) => {
+                let labels: Vec<&str> =
+                    associations.iter().map(|a| a.type_name.as_deref().unwrap_or("default")).collect();
+                let mut children: Vec<&Expression> = vec![controlling];
+                children.extend(associations.iter().map(|association| &association.value));
+                (format!("Generic ({})", labels.join(", ")), children)
+            }
+            Expression::CompoundLiteral(literal) => {
+                let label = format!("CompoundLiteral ({}{})", literal.type_name, "[]".repeat(literal.array_sizes.len()));
+                let mut children: Vec<&Expression> = literal.array_sizes.iter().flatten().collect();
+                children.extend(literal.initializers.iter().flat_map(Initializer::expressions));
+                (label, children)
+            }
+        };
+        let node = self.add(Some(parent), label, line);
+        for child in children {
+            self.add_expression(node, child);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Tab {
+    Ast,
+    Tokens,
+    Symbols,
+}
+
+const TABS: [(Tab, &str); 3] = [(Tab::Ast, "AST"), (Tab::Tokens, "Tokens"), (Tab::Symbols, "Symbols")];
+
+struct Explorer {
+    filename: String,
+    source: Rc<SourceFile>,
+    tree: Vec<TreeNode>,
+    parse_errors: Vec<String>,
+    tokens: Vec<String>,
+    symbols: Vec<String>,
+    tab: Tab,
+    ast_state: ListState,
+    tokens_state: ListState,
+    symbols_state: ListState,
+}
+
+impl Explorer {
+    fn new(filename: &str, code: &str) -> Self {
+        let source = Rc::new(SourceFile::new(filename, code));
+        let tokens = lexer_regex::lex_with_regex_positions(code, Default::default());
+        let token_lines = tokens
+            .iter()
+            .map(|spanned| &spanned.token)
+            .filter(|t| !matches!(t, Token::Comment(_) | Token::BlockComment(_) | Token::Pragma(_)))
+            .map(|t| format!("{:<12} {:?}", t.to_string(), t))
+            .collect();
+
+        let mut parser = Parser::with_spans(tokens);
+        let (tree, parse_errors, symbols) = match parser.parse() {
+            Ok(ast) => {
+                let mut scope_analyzer = ScopeAnalyzer::with_source(source.clone());
+                let _ = scope_analyzer.analyze_translation_unit(&ast);
+                let tree = AstTree::build(&ast);
+                (tree, Vec::new(), scope_analyzer.symbol_table_lines())
+            }
+            Err(errors) => {
+                let messages = errors.iter().map(|e| format!("Parse Error: {:?}", e)).collect();
+                (Vec::new(), messages, Vec::new())
+            }
+        };
+
+        let mut ast_state = ListState::default();
+        ast_state.select(Some(0));
+        let mut tokens_state = ListState::default();
+        tokens_state.select(Some(0));
+        let mut symbols_state = ListState::default();
+        symbols_state.select(Some(0));
+
+        Explorer {
+            filename: filename.to_string(),
+            source,
+            tree,
+            parse_errors,
+            tokens: token_lines,
+            symbols,
+            tab: Tab::Ast,
+            ast_state,
+            tokens_state,
+            symbols_state,
+        }
+    }
+
+    /// Indices of tree nodes whose ancestors are all expanded, in display order
+    fn visible_nodes(&self) -> Vec<usize> {
+        let mut visible = Vec::new();
+        if !self.tree.is_empty() {
+            self.collect_visible(0, &mut visible);
+        }
+        visible
+    }
+
+    fn collect_visible(&self, node: usize, visible: &mut Vec<usize>) {
+        visible.push(node);
+        if self.tree[node].expanded {
+            for &child in &self.tree[node].children {
+                self.collect_visible(child, visible);
+            }
+        }
+    }
+
+    fn selected_node(&self) -> Option<usize> {
+        let visible = self.visible_nodes();
+        self.ast_state.selected().and_then(|i| visible.get(i).copied())
+    }
+
+    fn list_len(&self) -> usize {
+        match self.tab {
+            Tab::Ast => self.visible_nodes().len().max(self.parse_errors.len()),
+            Tab::Tokens => self.tokens.len(),
+            Tab::Symbols => self.symbols.len(),
+        }
+    }
+
+    fn current_state(&mut self) -> &mut ListState {
+        match self.tab {
+            Tab::Ast => &mut self.ast_state,
+            Tab::Tokens => &mut self.tokens_state,
+            Tab::Symbols => &mut self.symbols_state,
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let len = self.list_len();
+        if len == 0 {
+            return;
+        }
+        let state = self.current_state();
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        if self.tab != Tab::Ast {
+            return;
+        }
+        let Some(node) = self.selected_node() else { return };
+        if expanded || self.tree[node].expanded {
+            self.tree[node].expanded = expanded;
+        } else if let Some(parent) = self.tree[node].parent {
+            // Collapsing an already-collapsed node moves to its parent
+            let position = self.visible_nodes().iter().position(|&n| n == parent);
+            self.ast_state.select(position);
+        }
+    }
+
+    fn toggle(&mut self) {
+        if self.tab == Tab::Ast
+            && let Some(node) = self.selected_node()
+        {
+            self.tree[node].expanded = !self.tree[node].expanded;
+        }
+    }
+
+    /// Returns false once the user asks to quit
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab => {
+                let index = TABS.iter().position(|(tab, _)| *tab == self.tab).unwrap_or(0);
+                self.tab = TABS[(index + 1) % TABS.len()].0;
+            }
+            KeyCode::Char('1') => self.tab = Tab::Ast,
+            KeyCode::Char('2') => self.tab = Tab::Tokens,
+            KeyCode::Char('3') => self.tab = Tab::Symbols,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-20),
+            KeyCode::PageDown => self.move_selection(20),
+            KeyCode::Left | KeyCode::Char('h') => self.set_expanded(false),
+            KeyCode::Right | KeyCode::Char('l') => self.set_expanded(true),
+            KeyCode::Enter | KeyCode::Char(' ') => self.toggle(),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [tabs_area, body_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let selected_tab = TABS.iter().position(|(tab, _)| *tab == self.tab).unwrap_or(0);
+        let tabs = Tabs::new(TABS.iter().map(|(_, title)| *title))
+            .select(selected_tab)
+            .block(Block::default().borders(Borders::ALL).title(format!(" minic explore: {} ", self.filename)))
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+        frame.render_widget(tabs, tabs_area);
+
+        let highlight = Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD);
+        match self.tab {
+            Tab::Ast => {
+                let [tree_area, source_area] =
+                    Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body_area);
+
+                let items: Vec<ListItem> = if self.parse_errors.is_empty() {
+                    self.visible_nodes()
+                        .into_iter()
+                        .map(|index| {
+                            let node = &self.tree[index];
+                            let marker = if node.children.is_empty() {
+                                "  "
+                            } else if node.expanded {
+                                "▾ "
+                            } else {
+                                "▸ "
+                            };
+                            ListItem::new(format!("{}{}{}", "  ".repeat(node.depth), marker, node.label))
+                        })
+                        .collect()
+                } else {
+                    self.parse_errors.iter().map(|e| ListItem::new(e.as_str())).collect()
+                };
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title(" AST "))
+                    .highlight_style(highlight);
+                frame.render_stateful_widget(list, tree_area, &mut self.ast_state);
+
+                let selected_line = self.selected_node().and_then(|node| self.tree[node].line);
+                let lines: Vec<Line> = self
+                    .source
+                    .lines()
+                    .enumerate()
+                    .map(|(i, text)| {
+                        let number = Span::styled(format!("{:>4} ", i + 1), Style::default().fg(Color::DarkGray));
+                        let style = if Some(i) == selected_line {
+                            Style::default().bg(Color::Blue).fg(Color::White)
+                        } else {
+                            Style::default()
+                        };
+                        Line::from(vec![number, Span::styled(text, style)])
+                    })
+                    .collect();
+                // Keep the highlighted line roughly centred
+                let visible_height = source_area.height.saturating_sub(2) as usize;
+                let scroll = selected_line.unwrap_or(0).saturating_sub(visible_height / 2);
+                let source = Paragraph::new(lines)
+                    .block(Block::default().borders(Borders::ALL).title(" Source "))
+                    .scroll((scroll as u16, 0));
+                frame.render_widget(source, source_area);
+            }
+            Tab::Tokens => {
+                let items: Vec<ListItem> = self.tokens.iter().map(|t| ListItem::new(t.as_str())).collect();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title(" Tokens (regex lexer) "))
+                    .highlight_style(highlight);
+                frame.render_stateful_widget(list, body_area, &mut self.tokens_state);
+            }
+            Tab::Symbols => {
+                let items: Vec<ListItem> = self.symbols.iter().map(|s| ListItem::new(s.as_str())).collect();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title(" Symbols "))
+                    .highlight_style(highlight);
+                frame.render_stateful_widget(list, body_area, &mut self.symbols_state);
+            }
+        }
+
+        let help = Paragraph::new(" Tab/1-3: switch tab  ↑↓/jk: move  ←→/hl: collapse/expand  Enter: toggle  q: quit")
+            .style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, help_area);
+    }
+
+    fn run(mut self, mut terminal: DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key.code)
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Open the explorer on a source file until the user quits
+pub fn explore(filename: &str) -> io::Result<()> {
+    let code = fs::read_to_string(filename)?;
+    let explorer = Explorer::new(filename, &code);
+    let terminal = ratatui::init();
+    let result = explorer.run(terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,255 @@
+// fold.rs: Replace the names of compile-time constants with their values.
+//
+// A `#define N 10` and an enumerator are both names for a number, which
+// every later phase would otherwise have to look up. This pass puts the
+// value in their place as Expression::Folded, which keeps the name too, so
+// diagnostics, `--emit=c` and the explorer can still say where it came from.
+//
+// A #define is textual, so it folds everywhere; one that names another
+// folds to whatever that name does where it is used. An enumerator only
+// folds after its enum is defined, and not where a parameter or local
+// variable of the same name hides it. A #define of anything but a single
+// constant or name is left alone.
+
+use crate::layout::Layouts;
+use crate::parser::ast::*;
+use std::collections::{HashMap, HashSet};
+
+/// Fold every reference to a #define or enumerator constant in the unit
+pub fn fold_constants(unit: &mut TranslationUnit) {
+    let mut folder = Folder::new(&unit.preprocessor_list);
+    for external in &mut unit.external_declarations {
+        match external {
+            ExternalDeclaration::Variable(declaration) => folder.declaration(declaration),
+            ExternalDeclaration::Function(function) => {
+                folder.scopes.push(function.parameters.iter().map(|parameter| parameter.name.clone()).collect());
+                folder.statements(&mut function.body);
+                folder.scopes.pop();
+            }
+            ExternalDeclaration::Struct(definition) => {
+                for member in &mut definition.members {
+                    folder.declaration(member);
+                }
+                folder.layouts.add(definition);
+            }
+            // Each value may use the enumerators before it
+            ExternalDeclaration::Enum(definition) => {
+                for value in definition.enumerators.iter_mut().filter_map(|enumerator| enumerator.value.as_mut()) {
+                    folder.expression(value);
+                }
+                folder.layouts.add_enum(definition);
+            }
+            ExternalDeclaration::FunctionDeclaration(_) | ExternalDeclaration::StructDeclaration(..) => {}
+        }
+    }
+}
+
+/// What a #define stands for, when it is one constant or one name
+enum Replacement {
+    Constant(Constant),
+    Name(String),
+}
+
+struct Folder {
+    defines: HashMap<String, Replacement>,
+    layouts: Layouts,             // the structs and enums defined so far
+    scopes: Vec<HashSet<String>>, // names declared in each enclosing block
+}
+
+impl Folder {
+    fn new(directives: &[PreprocessorDirective]) -> Folder {
+        let mut defines = HashMap::new();
+        for directive in directives {
+            if let PreprocessorDirective::Define(name, replacement) = directive {
+                match replacement.as_slice() {
+                    [ReplacementItem::Constant(value)] => {
+                        defines.insert(name.clone(), Replacement::Constant(value.clone()));
+                    }
+                    [ReplacementItem::Identifier(other)] => {
+                        defines.insert(name.clone(), Replacement::Name(other.clone()));
+                    }
+                    // Redefined as something that doesn't fold
+                    _ => {
+                        defines.remove(name);
+                    }
+                }
+            }
+        }
+        Folder { defines, layouts: Layouts::default(), scopes: Vec::new() }
+    }
+
+    /// The value `name` stands for here, following #defines of other names;
+    /// a #define that leads back to itself stands for nothing
+    fn value_of(&self, name: &str) -> Option<Constant> {
+        let mut name = name;
+        let mut seen = HashSet::new();
+        while let Some(replacement) = self.defines.get(name) {
+            if !seen.insert(name) {
+                return None;
+            }
+            match replacement {
+                Replacement::Constant(value) => return Some(value.clone()),
+                Replacement::Name(other) => name = other,
+            }
+        }
+        if self.scopes.iter().any(|scope| scope.contains(name)) {
+            return None;
+        }
+        self.layouts.enumerator_value(name).map(Constant::Integer)
+    }
+
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+
+    fn statements(&mut self, statements: &mut [Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statement) {
+        match &mut statement.kind {
+            StatementKind::Declaration(declaration) => self.declaration(declaration),
+            StatementKind::Assignment(_, value)
+            | StatementKind::Expression(value)
+            | StatementKind::Return(Some(value)) => {
+                self.expression(value)
+            }
+            StatementKind::Block(statements) => {
+                self.scopes.push(HashSet::new());
+                self.statements(statements);
+                self.scopes.pop();
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementKind::While(value, body)
+            | StatementKind::Switch(value, body)
+            | StatementKind::Case(value, body) => {
+                self.expression(value);
+                self.statement(body);
+            }
+            // The loop's own declaration is in a scope of its own
+            StatementKind::For(init, condition, update, body) => {
+                self.scopes.push(HashSet::new());
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                for value in [condition, update].into_iter().flatten() {
+                    self.expression(value);
+                }
+                self.statement(body);
+                self.scopes.pop();
+            }
+            StatementKind::Default(body) | StatementKind::Label(_, body) => self.statement(body),
+            StatementKind::Return(None)
+            | StatementKind::Break
+            | StatementKind::Fallthrough
+            | StatementKind::Goto(_)
+            | StatementKind::Empty => {}
+        }
+    }
+
+    /// The name is in scope from its own initializer on, as in C
+    fn declaration(&mut self, declaration: &mut VariableDeclaration) {
+        for size in declaration.declarator.array_sizes.iter_mut().flatten() {
+            self.expression(size);
+        }
+        self.declare(&declaration.declarator.name);
+        if let Some(initializer) = &mut declaration.initializer {
+            self.initializer(initializer);
+        }
+    }
+
+    fn initializer(&mut self, initializer: &mut Initializer) {
+        match &mut initializer.kind {
+            InitializerKind::Assignment(value) => self.expression(value),
+            InitializerKind::List(items) => {
+                for item in items {
+                    self.initializer(item);
+                }
+            }
+            InitializerKind::Designated(designator, item) => {
+                if let Designator::Array(index) = designator {
+                    self.expression(index);
+                }
+                self.initializer(item);
+            }
+        }
+    }
+
+    fn type_name(&mut self, type_name: &mut TypeName) {
+        for derivation in &mut type_name.derivations {
+            if let Derivation::Array(Some(size)) = derivation {
+                self.expression(size);
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &mut Expression) {
+        match expression {
+            Expression::Identifier(name) => {
+                if let Some(value) = self.value_of(name) {
+                    *expression = Expression::Folded(value, std::mem::take(name));
+                }
+            }
+            Expression::BinaryOp(left, _, right)
+            | Expression::Assignment(left, _, right)
+            | Expression::ArrayAccess(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.expression(condition);
+                self.expression(then_value);
+                self.expression(else_value);
+            }
+            Expression::FunctionCall(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::Cast(type_name, operand) => {
+                self.type_name(type_name);
+                self.expression(operand);
+            }
+            Expression::SizeofType(type_name) => self.type_name(type_name),
+            Expression::UnaryOp(_, operand)
+            | Expression::PostfixOp(operand, _)
+            | Expression::Paren(operand)
+            | Expression::MemberAccess(operand, _)
+            | Expression::PointerAccess(operand, _) => self.expression(operand),
+            Expression::StatementExpr(statements) => {
+                self.scopes.push(HashSet::new());
+                self.statements(statements);
+                self.scopes.pop();
+            }
+            Expression::Generic(controlling, associations) => {
+                self.expression(controlling);
+                for association in associations {
+                    self.expression(&mut association.value);
+                }
+            }
+            Expression::CompoundLiteral(literal) => {
+                for size in literal.array_sizes.iter_mut().flatten() {
+                    self.expression(size);
+                }
+                for item in &mut literal.initializers {
+                    self.initializer(item);
+                }
+            }
+            Expression::Constant(_)
+            | Expression::Folded(..)
+            | Expression::StringLiteral(_)
+            | Expression::EncodedStringLiteral(..) => {}
+        }
+    }
+}
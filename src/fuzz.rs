@@ -0,0 +1,537 @@
+// fuzz.rs: Random MiniC programs from the grammar, for differential testing
+// (`gen-fuzz`).
+//
+// A seed picks the program: globals, functions that call only the ones
+// before them, and in them declarations, assignments, if/else, bounded for
+// and while loops, breaks, calls and printf. Each program is checked four
+// ways, any difference being a bug in one side or the other:
+//
+//   - lexers: the regex and manual lexers read the same tokens, over the
+//     tokens both know
+//   - round-trip: printed back out (pretty::to_c), the program parses to the
+//     same AST, and printing that gives the same text again
+//   - semantics: scope analysis and type checking find nothing wrong
+//   - run: the interpreter prints what the program compiled by the system C
+//     compiler prints, and exits with the same code (see difftest)
+//
+// Programs are typed as MiniC wants: every condition, of an if, a `?:` or
+// an `&&` or `||` operand, is a comparison or a join of comparisons, and
+// no comparison is used as an int other than printed.
+//
+// Programs are well defined by construction, so a difference can't be put
+// down to undefined behaviour: every value has a bound worked out as it is
+// generated and no operation is picked that could overflow an int, division
+// is only by a nonzero constant, loops count up to a constant, there is no
+// recursion, and calls are made only from statements, so the order operands
+// are evaluated in doesn't matter. Loop counters step with `++`; `--`,
+// compound assignment, `!` and `~` aren't generated yet.
+
+use crate::ast_text;
+use crate::diagnostics::Diagnostic;
+use crate::difftest::{self, Backend, Interpreter};
+use crate::lexer_manual;
+use crate::lexer_regex::{self, Token};
+use crate::parser::Parser;
+use crate::pretty;
+use crate::scope::ScopeAnalyzer;
+use crate::source::SourceFile;
+use crate::type_checker::TypeChecker;
+use std::rc::Rc;
+
+/// Largest value any expression may have, well inside an int
+const MAX_VALUE: i64 = 1_000_000_000;
+
+/// Values variables are kept to, being only ever assigned `% 1000`
+const STORED_BOUND: i64 = 999;
+
+/// SplitMix64: small, and the same numbers for a seed everywhere
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `bound`
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn between(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low + 1) as u64) as i64
+    }
+
+    /// True one time in `n`
+    fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+}
+
+/// An expression as text, with the most its value can be either side of
+/// zero and how tightly its outermost operator binds
+struct Generated {
+    text: String,
+    bound: i64,
+    precedence: u8,
+}
+
+const PRIMARY: u8 = 15;
+const UNARY: u8 = 14;
+const MULTIPLICATIVE: u8 = 13;
+const ADDITIVE: u8 = 12;
+const RELATIONAL: u8 = 10;
+const EQUALITY: u8 = 9;
+const BIT_AND: u8 = 8;
+const BIT_XOR: u8 = 7;
+const BIT_OR: u8 = 6;
+const LOGICAL_AND: u8 = 5;
+const LOGICAL_OR: u8 = 4;
+const CONDITIONAL: u8 = 3;
+
+impl Generated {
+    fn new(text: String, bound: i64, precedence: u8) -> Generated {
+        Generated { text, bound, precedence }
+    }
+
+    /// The text, in parentheses if it binds less tightly than `needed`
+    fn at(&self, needed: u8) -> String {
+        if self.precedence < needed { format!("({})", self.text) } else { self.text.clone() }
+    }
+}
+
+/// `left op right`, left-associative at `precedence`
+fn binary(left: &Generated, op: &str, right: &Generated, bound: i64, precedence: u8) -> Generated {
+    Generated::new(format!("{} {} {}", left.at(precedence), op, right.at(precedence + 1)), bound, precedence)
+}
+
+fn constant(value: i64) -> Generated {
+    Generated::new(value.to_string(), value.abs(), if value < 0 { UNARY } else { PRIMARY })
+}
+
+#[derive(Clone)]
+struct Variable {
+    name: String,
+    bound: i64,
+    assignable: bool, // loop counters are only read
+}
+
+struct Function {
+    name: String,
+    parameters: usize,
+    returns: bool, // int, or void
+}
+
+struct Generator {
+    rng: Rng,
+    out: String,
+    globals: Vec<Variable>,
+    functions: Vec<Function>,
+    names: usize, // for fresh local names
+}
+
+impl Generator {
+    fn line(&mut self, depth: usize, text: &str) {
+        self.out.push_str(&"    ".repeat(depth));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{}{}", prefix, self.names)
+    }
+
+    fn leaf(&mut self, scope: &[Variable]) -> Generated {
+        if !scope.is_empty() && !self.rng.one_in(3) {
+            let variable = &scope[self.rng.below(scope.len() as u64) as usize];
+            Generated::new(variable.name.clone(), variable.bound, PRIMARY)
+        } else {
+            constant(self.rng.between(-20, 99))
+        }
+    }
+
+    /// An int expression of at most `depth` operators deep, with no side
+    /// effects
+    fn expression(&mut self, scope: &[Variable], depth: usize) -> Generated {
+        if depth == 0 || self.rng.one_in(4) {
+            return self.leaf(scope);
+        }
+        let generated = match self.rng.below(9) {
+            0 | 1 => {
+                let (left, right) = (self.expression(scope, depth - 1), self.expression(scope, depth - 1));
+                let op = if self.rng.one_in(2) { "+" } else { "-" };
+                binary(&left, op, &right, left.bound + right.bound, ADDITIVE)
+            }
+            2 => {
+                let left = self.expression(scope, depth - 1);
+                let factor = constant(self.rng.between(2, 9));
+                binary(&left, "*", &factor, left.bound * factor.bound, MULTIPLICATIVE)
+            }
+            3 => {
+                let left = self.expression(scope, depth - 1);
+                let divisor = constant(self.rng.between(1, 9));
+                match self.rng.one_in(2) {
+                    true => binary(&left, "/", &divisor, left.bound, MULTIPLICATIVE),
+                    false => binary(&left, "%", &divisor, divisor.bound, MULTIPLICATIVE),
+                }
+            }
+            4 => {
+                let (left, right) = (self.expression(scope, depth - 1), self.expression(scope, depth - 1));
+                let (op, precedence) = match self.rng.below(3) {
+                    0 => ("&", BIT_AND),
+                    1 => ("^", BIT_XOR),
+                    _ => ("|", BIT_OR),
+                };
+                // A negative operand can set bits above the other's
+                binary(&left, op, &right, 2 * left.bound.max(right.bound) + 1, precedence)
+            }
+            5 | 6 => {
+                let condition = self.condition(scope, depth - 1);
+                let (then_value, else_value) = (self.expression(scope, depth - 1), self.expression(scope, depth - 1));
+                let text = format!(
+                    "{} ? {} : {}",
+                    condition.at(LOGICAL_OR),
+                    then_value.at(LOGICAL_OR),
+                    else_value.at(CONDITIONAL)
+                );
+                Generated::new(text, then_value.bound.max(else_value.bound), CONDITIONAL)
+            }
+            7 => {
+                let operand = self.expression(scope, depth - 1);
+                // `- -x`, not `--x`
+                let space = if operand.at(UNARY).starts_with('-') { " " } else { "" };
+                Generated::new(format!("-{}{}", space, operand.at(UNARY)), operand.bound, UNARY)
+            }
+            _ => {
+                let inner = self.expression(scope, depth - 1);
+                Generated::new(format!("({})", inner.text), inner.bound, PRIMARY)
+            }
+        };
+        if generated.bound > MAX_VALUE { self.leaf(scope) } else { generated }
+    }
+
+    /// A bool expression of at most `depth` operators deep: a comparison
+    /// of ints, or conditions joined by `&&` or `||`, as MiniC wants for
+    /// if, `?:` and the logical operators
+    fn condition(&mut self, scope: &[Variable], depth: usize) -> Generated {
+        if depth == 0 || self.rng.one_in(3) {
+            let operands = depth.saturating_sub(1);
+            let (left, right) = (self.expression(scope, operands), self.expression(scope, operands));
+            let (op, precedence) = match self.rng.below(6) {
+                0 => ("<", RELATIONAL),
+                1 => ("<=", RELATIONAL),
+                2 => (">", RELATIONAL),
+                3 => (">=", RELATIONAL),
+                4 => ("==", EQUALITY),
+                _ => ("!=", EQUALITY),
+            };
+            return binary(&left, op, &right, 1, precedence);
+        }
+        match self.rng.below(5) {
+            0 | 1 => {
+                let (left, right) = (self.condition(scope, depth - 1), self.condition(scope, depth - 1));
+                binary(&left, "&&", &right, 1, LOGICAL_AND)
+            }
+            2 | 3 => {
+                let (left, right) = (self.condition(scope, depth - 1), self.condition(scope, depth - 1));
+                binary(&left, "||", &right, 1, LOGICAL_OR)
+            }
+            _ => {
+                let inner = self.condition(scope, depth - 1);
+                Generated::new(format!("({})", inner.text), 1, PRIMARY)
+            }
+        }
+    }
+
+    /// `expression % 1000`, a value fit to store in a variable or pass
+    fn stored(&mut self, scope: &[Variable]) -> String {
+        let value = self.expression(scope, 3);
+        binary(&value, "%", &constant(STORED_BOUND + 1), STORED_BOUND, MULTIPLICATIVE).text
+    }
+
+    fn arguments(&mut self, scope: &[Variable], count: usize) -> String {
+        let arguments: Vec<String> = (0..count).map(|_| self.stored(scope)).collect();
+        arguments.join(", ")
+    }
+
+    /// Statements of a block, locals declared first. Calls are left out of
+    /// loops, so a program's running time can't multiply up its call chain.
+    fn block(&mut self, scope: &[Variable], depth: usize, in_loop: bool) {
+        let mut scope = scope.to_vec();
+        for _ in 0..self.rng.between(0, 2) {
+            let name = self.fresh("a");
+            let value = self.stored(&scope);
+            self.line(depth, &format!("int {} = {};", name, value));
+            scope.push(Variable { name, bound: STORED_BOUND, assignable: true });
+        }
+        for _ in 0..self.rng.between(1, 4) {
+            self.statement(&scope, depth, in_loop);
+        }
+    }
+
+    fn statement(&mut self, scope: &[Variable], depth: usize, in_loop: bool) {
+        let assignable: Vec<Variable> = scope.iter().filter(|variable| variable.assignable).cloned().collect();
+        let nesting = depth < 4;
+        match self.rng.below(10) {
+            0..=2 if !assignable.is_empty() => {
+                let target = assignable[self.rng.below(assignable.len() as u64) as usize].name.clone();
+                let value = self.stored(scope);
+                self.line(depth, &format!("{} = {};", target, value));
+            }
+            3 | 4 => {
+                // A condition's value printed is 0 or 1
+                let value = if self.rng.one_in(4) { self.condition(scope, 2) } else { self.expression(scope, 3) };
+                if self.rng.one_in(3) {
+                    let other = self.expression(scope, 2);
+                    self.line(depth, &format!("printf(\"%d %d\\n\", {}, {});", value.text, other.text));
+                } else {
+                    self.line(depth, &format!("printf(\"%d\\n\", {});", value.text));
+                }
+            }
+            5 if nesting => {
+                let condition = self.condition(scope, 3);
+                self.line(depth, &format!("if ({}) {{", condition.text));
+                self.block(scope, depth + 1, in_loop);
+                if self.rng.one_in(2) {
+                    self.line(depth, "} else {");
+                    self.block(scope, depth + 1, in_loop);
+                }
+                self.line(depth, "}");
+            }
+            6 if nesting => {
+                let counter = self.fresh("i");
+                let limit = self.rng.between(0, 5);
+                self.line(depth, &format!("for (int {0} = 0; {0} < {1}; {0}++) {{", counter, limit));
+                let mut inner = scope.to_vec();
+                inner.push(Variable { name: counter, bound: 5, assignable: false });
+                self.block(&inner, depth + 1, true);
+                self.line(depth, "}");
+            }
+            7 if nesting => {
+                let counter = self.fresh("w");
+                let limit = self.rng.between(0, 5);
+                self.line(depth, &format!("int {} = 0;", counter));
+                self.line(depth, &format!("while ({} < {}) {{", counter, limit));
+                let mut inner = scope.to_vec();
+                inner.push(Variable { name: counter.clone(), bound: 5, assignable: false });
+                self.block(&inner, depth + 1, true);
+                self.line(depth + 1, &format!("{}++;", counter));
+                self.line(depth, "}");
+            }
+            8 if in_loop => {
+                let condition = self.condition(scope, 2);
+                self.line(depth, &format!("if ({}) break;", condition.text));
+            }
+            9 if !in_loop && !self.functions.is_empty() => {
+                let callee = self.rng.below(self.functions.len() as u64) as usize;
+                let (name, parameters, returns) = {
+                    let function = &self.functions[callee];
+                    (function.name.clone(), function.parameters, function.returns)
+                };
+                let arguments = self.arguments(scope, parameters);
+                match returns && !assignable.is_empty() {
+                    true => {
+                        let target = assignable[self.rng.below(assignable.len() as u64) as usize].name.clone();
+                        self.line(depth, &format!("{} = {}({});", target, name, arguments));
+                    }
+                    false => self.line(depth, &format!("{}({});", name, arguments)),
+                }
+            }
+            _ => {
+                let value = self.expression(scope, 2);
+                self.line(depth, &format!("printf(\"%d\\n\", {});", value.text));
+            }
+        }
+    }
+
+    fn function(&mut self, index: usize) {
+        let name = format!("f{}", index);
+        let parameters = self.rng.below(4) as usize;
+        let returns = !self.rng.one_in(4);
+        let mut scope = self.globals.clone();
+        let list: Vec<String> = (0..parameters)
+            .map(|parameter| {
+                let name = format!("p{}", parameter);
+                scope.push(Variable { name: name.clone(), bound: STORED_BOUND, assignable: true });
+                format!("int {}", name)
+            })
+            .collect();
+        let list = if list.is_empty() { "void".to_string() } else { list.join(", ") };
+        self.line(0, &format!("{} {}({}) {{", if returns { "int" } else { "void" }, name, list));
+        self.block(&scope, 1, false);
+        if returns {
+            let value = self.stored(&scope);
+            self.line(1, &format!("return {};", value));
+        }
+        self.line(0, "}");
+        self.functions.push(Function { name, parameters, returns });
+    }
+
+    fn program(&mut self, seed: u64) {
+        self.line(0, "#include <stdio.h>");
+        self.line(0, &format!("// minic gen-fuzz seed {}", seed));
+        for index in 0..self.rng.between(0, 3) {
+            let name = format!("g{}", index);
+            let value = self.rng.between(-99, 999);
+            self.line(0, &format!("int {} = {};", name, value));
+            self.globals.push(Variable { name, bound: STORED_BOUND, assignable: true });
+        }
+        for index in 0..self.rng.between(0, 4) {
+            self.function(index as usize);
+        }
+        self.line(0, "int main(void) {");
+        let scope = self.globals.clone();
+        self.block(&scope, 1, false);
+        // An exit code from 0 to 99 whatever the sign
+        let value = self.expression(&scope, 2);
+        let code = binary(&value, "%", &constant(100), 99, MULTIPLICATIVE);
+        let code = binary(&code, "+", &constant(100), 199, ADDITIVE);
+        let code = binary(&code, "%", &constant(100), 99, MULTIPLICATIVE);
+        self.line(1, &format!("return {};", code.text));
+        self.line(0, "}");
+    }
+}
+
+/// The program for `seed`
+pub fn generate(seed: u64) -> String {
+    let mut generator =
+        Generator { rng: Rng(seed), out: String::new(), globals: Vec::new(), functions: Vec::new(), names: 0 };
+    generator.program(seed);
+    generator.out
+}
+
+/// Two sides of a check that disagree on a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub check: &'static str, // "lexers", "round-trip", "semantics", "run"
+    pub detail: String,
+}
+
+impl Divergence {
+    fn new(check: &'static str, detail: String) -> Divergence {
+        Divergence { check, detail }
+    }
+}
+
+/// Every check `source` fails. Its run by the interpreter is compared with
+/// one by `reference` when given; without one it only has to finish.
+pub fn check(source: &str, reference: Option<&dyn Backend>) -> Vec<Divergence> {
+    let mut divergences = Vec::new();
+    divergences.extend(check_lexers(source));
+    divergences.extend(check_round_trip(source));
+    divergences.extend(check_semantics(source));
+    let interpreter = Interpreter { gnu_extensions: false, fast_math: false };
+    let backends: Vec<&dyn Backend> = reference.into_iter().chain([&interpreter as &dyn Backend]).collect();
+    let comparison = difftest::compare(source, &[], &backends);
+    if comparison.diverges() {
+        let runs = comparison.render();
+        divergences.push(Divergence::new("run", runs.lines().collect::<Vec<_>>().join("; ")));
+    }
+    divergences
+}
+
+/// A regex token as the manual lexer would read it: the same, or the
+/// manual lexer's stand-in for it, or nothing for the tokens it doesn't
+/// know (which it reports as errors, dropped on its side)
+fn as_manual(token: &Token) -> Option<String> {
+    let shared = matches!(
+        token,
+        Token::Function | Token::Int | Token::Float | Token::String | Token::Bool
+            | Token::Identifier(_) | Token::IntLit(_) | Token::FloatLit(..) | Token::StringLit(_) | Token::BoolLit(_)
+            | Token::Comment(_) | Token::BlockComment(_) | Token::Return | Token::If | Token::Else | Token::While
+            | Token::For | Token::AssignOp | Token::EqualsOp | Token::NotEqualsOp | Token::LessEqOp | Token::GreaterEqOp
+            | Token::LessOp | Token::GreaterOp | Token::AndOp | Token::OrOp | Token::BitAndOp | Token::BitOrOp
+            | Token::ParenL | Token::ParenR | Token::BraceL | Token::BraceR | Token::BracketL | Token::BracketR
+            | Token::Comma | Token::Semicolon | Token::Quotes | Token::Colon | Token::Plus | Token::Minus
+            | Token::Mult | Token::Div | Token::Mod | Token::Xor | Token::Not | Token::BitNot | Token::Question
+            | Token::Dot | Token::Ellipsis | Token::Arrow | Token::PlusPlus | Token::MinusMinus | Token::PlusAssign
+            | Token::MinusAssign | Token::MultAssign | Token::DivAssign | Token::ModAssign | Token::LShiftAssign
+            | Token::RShiftAssign | Token::AndAssign | Token::XorAssign | Token::OrAssign | Token::LShift
+            | Token::RShift | Token::Hash
+            | Token::UnterminatedString { .. } | Token::UnterminatedComment { .. }
+    );
+    if shared {
+        return Some(format!("{:?}", token));
+    }
+    // Keywords MiniC doesn't have are names to it
+    let text = token.to_string();
+    let word = text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    word.then(|| format!("Identifier({:?})", text))
+}
+
+/// The two lexers over the program less its directives, which only the
+/// regex lexer reads
+fn check_lexers(source: &str) -> Option<Divergence> {
+    let code: String = source
+        .lines()
+        .map(|line| if line.trim_start().starts_with('#') { "\n".to_string() } else { format!("{}\n", line) })
+        .collect();
+    let regex: Vec<String> = lexer_regex::lex_with_regex(&code).iter().filter_map(as_manual).collect();
+    let manual: Vec<String> = lexer_manual::lex_manual(&code)
+        .iter()
+        .filter(|token| !matches!(token, lexer_manual::Token::Error(_)))
+        .map(|token| format!("{:?}", token))
+        .collect();
+    let at = (0..regex.len().max(manual.len())).find(|&index| regex.get(index) != manual.get(index))?;
+    let token = |tokens: &[String]| tokens.get(at).cloned().unwrap_or("end of input".to_string());
+    let detail = format!("token {}: regex lexer read {}, manual lexer read {}", at, token(&regex), token(&manual));
+    Some(Divergence::new("lexers", detail))
+}
+
+fn parse(source: &str) -> Result<crate::parser::ast::TranslationUnit, String> {
+    Parser::new(lexer_regex::lex_with_regex(source)).parse().map_err(|errors| format!("{:?}", errors))
+}
+
+/// Scope analysis and type checking, which a program well typed by
+/// construction must pass as it would compiling
+fn check_semantics(source: &str) -> Option<Divergence> {
+    let unit = parse(source).ok()?;
+    let source = Rc::new(SourceFile::new("fuzz.c", source));
+    let mut scope_analyzer = ScopeAnalyzer::with_source(source.clone());
+    let _ = scope_analyzer.analyze_translation_unit(&unit);
+    let mut errors: Vec<Diagnostic> = scope_analyzer.get_errors().iter().map(Diagnostic::from).collect();
+    let mut type_checker = TypeChecker::new(scope_analyzer);
+    let _ = type_checker.check_translation_unit(&unit);
+    errors.extend(type_checker.get_errors().iter().map(Diagnostic::from));
+    let first = errors.first()?;
+    let detail = format!("the checks reject the program with {} error(s), first {}", errors.len(), first.render());
+    Some(Divergence::new("semantics", detail))
+}
+
+fn check_round_trip(source: &str) -> Option<Divergence> {
+    let unit = match parse(source) {
+        Ok(unit) => unit,
+        Err(errors) => return Some(Divergence::new("round-trip", format!("the program doesn't parse: {}", errors))),
+    };
+    let printed = pretty::to_c(&unit);
+    let reparsed = match parse(&printed) {
+        Ok(reparsed) => reparsed,
+        Err(errors) => {
+            let detail = format!("the printed program doesn't parse: {}\n{}", errors, printed);
+            return Some(Divergence::new("round-trip", detail));
+        }
+    };
+    let (before, after) = (ast_text::to_text(&unit), ast_text::to_text(&reparsed));
+    if before != after {
+        let line = before.lines().zip(after.lines()).position(|(before, after)| before != after).unwrap_or(0);
+        return Some(Divergence::new(
+            "round-trip",
+            format!(
+                "the printed program parses to a different AST, from line {} of --emit=ast:\n  {}\n  {}",
+                line + 1,
+                before.lines().nth(line).unwrap_or("").trim(),
+                after.lines().nth(line).unwrap_or("").trim()
+            ),
+        ));
+    }
+    let reprinted = pretty::to_c(&reparsed);
+    if reprinted != printed {
+        return Some(Divergence::new("round-trip", "printing the reparsed program gives different text".to_string()));
+    }
+    None
+}
@@ -0,0 +1,257 @@
+// grade.rs: `grade <file> --spec <spec.toml>`, a student's program marked
+// against a battery of checks, with a score.
+//
+// A spec gives points for each check, and leaves out those it doesn't
+// want:
+//
+//     [compiles]
+//     points = 10
+//
+//     [warnings]
+//     max = 0            # more than this many warnings loses the points
+//     points = 5
+//
+//     [forbidden]
+//     constructs = ["goto", "malloc"]
+//     features = ["recursion", "globals"]
+//     points = 5
+//
+//     [required]
+//     functions = ["factorial"]
+//     points = 5
+//
+//     [[tests]]
+//     name = "factorial of 5"
+//     args = ["5"]
+//     output = "120\n"
+//     exit_code = 0
+//     points = 10
+//
+// The program goes through a Project, so its diagnostics are the ones
+// `project` would report, lints included. A forbidden construct is a
+// keyword or a name spelled as it is in the source; it is looked for among
+// the tokens, so it isn't found in a comment or a string. A forbidden
+// feature is one a policy can ban (policy.rs). Each test runs
+// the program in the interpreter, as difftest does, and passes when what
+// it printed and its exit code are what the spec says; either may be left
+// out.
+
+use crate::diagnostics::Severity;
+use crate::difftest::{self, Backend};
+use crate::lexer_regex::{self, Token};
+use crate::parser::ast::ExternalDeclaration;
+use crate::policy::{Banned, Policy};
+use crate::project::Project;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    pub compiles: Option<Points>,
+    pub warnings: Option<Warnings>,
+    pub forbidden: Option<Forbidden>,
+    pub required: Option<Required>,
+    #[serde(default)]
+    pub tests: Vec<Test>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Points {
+    pub points: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Warnings {
+    #[serde(default)]
+    pub max: usize,
+    pub points: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Forbidden {
+    #[serde(default)]
+    pub constructs: Vec<String>,
+    #[serde(default)]
+    pub features: Vec<Banned>,
+    pub points: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Required {
+    pub functions: Vec<String>,
+    pub points: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Test {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub output: Option<String>,
+    pub exit_code: Option<i64>,
+    pub points: u32,
+}
+
+impl Spec {
+    pub fn parse(text: &str) -> Result<Spec, String> {
+        toml::from_str(text).map_err(|error| error.to_string())
+    }
+}
+
+/// One check and what it earned
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub points: u32,
+    pub max_points: u32,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Grade {
+    pub file: String,
+    pub score: u32,
+    pub max_score: u32,
+    pub checks: Vec<Check>,
+}
+
+impl Grade {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("grades are always serializable")
+    }
+
+    /// One line per check, then the score
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for check in &self.checks {
+            let mark = if check.passed { "PASS" } else { "FAIL" };
+            text.push_str(&format!("{} {:<28} {:>3}/{:<3} {}\n", mark, check.name, check.points, check.max_points, check.detail));
+        }
+        text.push_str(&format!("Score: {}/{}\n", self.score, self.max_score));
+        text
+    }
+}
+
+/// Mark the file at `path`, already in `project`, against `spec`
+pub fn grade(project: &mut Project, path: &Path, spec: &Spec) -> Grade {
+    let source = project.text(path).unwrap_or_default().to_string();
+    let diagnostics = project.diagnostics(path).unwrap_or_default().to_vec();
+    let count = |severity: Severity| diagnostics.iter().filter(|diagnostic| diagnostic.severity == severity).count();
+    let (errors, warnings) = (count(Severity::Error) + count(Severity::Fatal), count(Severity::Warning));
+    let defined: Vec<String> = match project.unit(path) {
+        Some(unit) => unit
+            .external_declarations
+            .iter()
+            .filter_map(|external| match external {
+                ExternalDeclaration::Function(function) => Some(function.name.clone()),
+                _ => None,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut checks = Vec::new();
+    let mut check = |name: String, passed: bool, max_points: u32, detail: String| {
+        let points = if passed { max_points } else { 0 };
+        checks.push(Check { name, passed, points, max_points, detail });
+    };
+    if let Some(compiles) = &spec.compiles {
+        let first = diagnostics.iter().find(|diagnostic| diagnostic.severity != Severity::Warning);
+        let detail = match first {
+            None => "no errors".to_string(),
+            Some(first) => format!("{} error(s), the first: {}", errors, first.render()),
+        };
+        check("compiles".to_string(), errors == 0, compiles.points, detail);
+    }
+    if let Some(limit) = &spec.warnings {
+        let detail = format!("{} warning(s), at most {} allowed", warnings, limit.max);
+        check("warnings".to_string(), errors == 0 && warnings <= limit.max, limit.points, detail);
+    }
+    if let Some(forbidden) = &spec.forbidden {
+        let mut uses: Vec<String> = forbidden_uses(&source, &forbidden.constructs, project.lex_options())
+            .iter()
+            .map(|(construct, line)| format!("'{}' on line {}", construct, line))
+            .collect();
+        let policy = Policy { banned: forbidden.features.clone() };
+        for violation in policy.check(project.unit(path), &source) {
+            match violation.line {
+                Some(line) => uses.push(format!("{} on line {}", violation.banned, line)),
+                None => uses.push(violation.banned.to_string()),
+            }
+        }
+        let detail = if uses.is_empty() {
+            let mut names = forbidden.constructs.clone();
+            names.extend(forbidden.features.iter().map(|banned| banned.to_string()));
+            format!("none of {} used", names.join(", "))
+        } else {
+            uses.join(", ")
+        };
+        check("forbidden constructs".to_string(), uses.is_empty(), forbidden.points, detail);
+    }
+    if let Some(required) = &spec.required {
+        let missing: Vec<&str> =
+            required.functions.iter().filter(|name| !defined.contains(name)).map(String::as_str).collect();
+        let detail = match (errors, missing.is_empty()) {
+            (0, true) => format!("{} defined", required.functions.join(", ")),
+            (0, false) => format!("missing {}", missing.join(", ")),
+            _ => "doesn't compile".to_string(),
+        };
+        check("required functions".to_string(), errors == 0 && missing.is_empty(), required.points, detail);
+    }
+    let interpreter = difftest::Interpreter { gnu_extensions: project.gnu_extensions(), fast_math: false };
+    for (index, test) in spec.tests.iter().enumerate() {
+        let name = test.name.clone().unwrap_or_else(|| format!("test {}", index + 1));
+        let (passed, detail) = if errors > 0 {
+            (false, "doesn't compile".to_string())
+        } else {
+            run_test(&interpreter, &source, test)
+        };
+        check(name, passed, test.points, detail);
+    }
+
+    Grade {
+        file: path.display().to_string(),
+        score: checks.iter().map(|check| check.points).sum(),
+        max_score: checks.iter().map(|check| check.max_points).sum(),
+        checks,
+    }
+}
+
+/// Each of `constructs` that `source` uses, and the first line it is on
+fn forbidden_uses(source: &str, constructs: &[String], options: lexer_regex::LexOptions) -> Vec<(String, usize)> {
+    let tokens = lexer_regex::lex_with_regex_positions(source, options);
+    constructs
+        .iter()
+        .filter_map(|construct| {
+            let used = tokens.iter().find(|spanned| {
+                !matches!(spanned.token, Token::Comment(_) | Token::StringLit(_)) && spanned.token.to_string() == *construct
+            })?;
+            Some((construct.clone(), used.span.line))
+        })
+        .collect()
+}
+
+fn run_test(interpreter: &difftest::Interpreter, source: &str, test: &Test) -> (bool, String) {
+    let outcome = match interpreter.run(source, &test.args) {
+        Ok(outcome) => outcome,
+        Err(error) => return (false, error),
+    };
+    if let Some(expected) = &test.output
+        && outcome.output != *expected
+    {
+        return (false, format!("printed {:?}, expected {:?}", outcome.output, expected));
+    }
+    if let Some(expected) = test.exit_code
+        && outcome.exit_code != expected
+    {
+        return (false, format!("exited with {}, expected {}", outcome.exit_code, expected));
+    }
+    (true, format!("exited with {}", outcome.exit_code))
+}
@@ -0,0 +1,125 @@
+// html.rs: `--emit=html`, the source as a standalone highlighted HTML page.
+//
+// Highlighting follows the regex lexer's tokens and their byte spans, and
+// each diagnostic is shown under the line it refers to, which makes the
+// page usable as written feedback on a submission.
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::lexer_regex::borrowed;
+use crate::parser::ast::TranslationUnit;
+use crate::plugins::EmitTarget;
+use crate::source::Spliced;
+
+pub struct HtmlEmitter;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+pre { font-family: monospace; font-size: 14px; line-height: 1.4; background: #fafafa; padding: 1em; }
+.line { display: block; }
+.lineno { color: #999; display: inline-block; width: 4em; user-select: none; }
+.keyword { color: #0033b3; font-weight: bold; }
+.type { color: #871094; font-weight: bold; }
+.identifier { color: #000000; }
+.number { color: #1750eb; }
+.string { color: #067d17; }
+.comment { color: #8c8c8c; font-style: italic; }
+.preprocessor { color: #9e880d; }
+.operator { color: #555555; }
+.punctuation { color: #555555; }
+.error { color: #ffffff; background: #d32f2f; }
+.diag { display: block; margin-left: 4em; padding: 0.1em 0.5em; font-family: sans-serif; }
+.diag.error-diag { background: #fdecea; border-left: 3px solid #d32f2f; }
+.diag.warning-diag { background: #fff8e1; border-left: 3px solid #f9a825; }
+";
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append `text` to the highlighted lines, splitting it at newlines so no
+/// span is left open across a line break
+fn push_segment(lines: &mut Vec<String>, text: &str, class: Option<&str>) {
+    for (i, piece) in text.split('\n').enumerate() {
+        if i > 0 {
+            lines.push(String::new());
+        }
+        if piece.is_empty() {
+            continue;
+        }
+        let current = lines.last_mut().expect("there is always a current line");
+        match class {
+            Some(class) => current.push_str(&format!("<span class=\"{}\">{}</span>", class, escape(piece))),
+            None => current.push_str(&escape(piece)),
+        }
+    }
+}
+
+fn render_diagnostic(diagnostic: &Diagnostic) -> String {
+    let class = match diagnostic.severity {
+        Severity::Error | Severity::Fatal => "error-diag",
+        Severity::Warning => "warning-diag",
+    };
+    let text = diagnostic.render().replace("\n    ", "; ");
+    format!("<span class=\"diag {}\">{}</span>", class, escape(&text))
+}
+
+impl EmitTarget for HtmlEmitter {
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    fn emit(&self, _unit: &TranslationUnit, source: &str, diagnostics: &[Diagnostic]) -> String {
+        let mut lines = vec![String::new()];
+        let mut pos = 0;
+        let spliced = Spliced::new(source);
+        for (token, span) in borrowed::Tokens::new(&spliced, Default::default()) {
+            push_segment(&mut lines, &source[pos..span.start], None);
+            push_segment(&mut lines, &source[span.clone()], Some(token.category()));
+            pos = span.end;
+        }
+        push_segment(&mut lines, &source[pos..], None);
+        if source.ends_with('\n') {
+            lines.pop();
+        }
+
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>MiniC source</title>\n<style>\n");
+        html.push_str(STYLE);
+        html.push_str("</style>\n</head>\n<body>\n");
+
+        // Diagnostics that can't be tied to a line go above the listing
+        let unplaced: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.line.is_none_or(|line| line == 0 || line > lines.len()))
+            .collect();
+        for diagnostic in unplaced {
+            html.push_str(&render_diagnostic(diagnostic));
+            html.push('\n');
+        }
+
+        html.push_str("<pre>");
+        for (i, line) in lines.iter().enumerate() {
+            html.push_str(&format!(
+                "<span class=\"line\" id=\"L{0}\"><span class=\"lineno\">{0}</span>{1}</span>",
+                i + 1,
+                line
+            ));
+            for diagnostic in diagnostics.iter().filter(|d| d.line == Some(i + 1)) {
+                html.push_str(&render_diagnostic(diagnostic));
+            }
+        }
+        html.push_str("</pre>\n</body>\n</html>\n");
+        html
+    }
+}
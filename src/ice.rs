@@ -0,0 +1,142 @@
+// ice.rs: Internal compiler errors: a panic in one of the phases, caught
+// and reported as a bug in the compiler rather than a Rust backtrace.
+//
+// The driver names the phase it is in as it goes, so a panic can be
+// pinned on one. The input that set it off is then shrunk to a small part
+// of it that still panics at the same place in the same phase, by taking
+// out chunks of tokens (or lines, for a lexer) and running the phases
+// again, and written out with its AST to attach to a bug report. Each run
+// is on a thread of its own with a time limit, as a part of an input can
+// hang a phase the whole of it doesn't.
+
+use crate::lexer_regex::Token;
+use crate::parser::STACK_SIZE;
+use crate::parser::ast::TranslationUnit;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Runs of the phases a reduction may take, so one that barely shrinks
+/// the input still ends
+const MAX_TRIES: usize = 2000;
+
+thread_local! {
+    static PHASE: Cell<&'static str> = const { Cell::new("startup") };
+    static PANIC: RefCell<Option<Ice>> = const { RefCell::new(None) };
+}
+
+/// Say which phase the compiler is in now, e.g. "type checking"
+pub fn set_phase(phase: &'static str) {
+    PHASE.with(|current| current.set(phase));
+}
+
+#[derive(Debug)]
+pub struct Ice {
+    pub message: String,
+    pub location: String, // where in the compiler's source it panicked
+    pub phase: &'static str,
+    pub backtrace: Option<Backtrace>, // with RUST_BACKTRACE set; resolved only if printed
+}
+
+impl Ice {
+    /// Whether two panics are the same bug: the same place in the same phase
+    pub fn same_bug(&self, other: &Ice) -> bool {
+        self.phase == other.phase && self.location == other.location
+    }
+
+    pub fn render(&self, input: &str) -> String {
+        let mut text = format!(
+            "internal compiler error: {}\n  phase: {}\n  input: {}\n  at: {}\n",
+            self.message, self.phase, input, self.location
+        );
+        if let Some(backtrace) = &self.backtrace {
+            text.push_str(&format!("{}\n", backtrace));
+        }
+        text
+    }
+}
+
+/// Run `run`, catching a panic in it as an Ice instead of printing it
+pub fn catch<R>(run: impl FnOnce() -> R) -> Result<R, Ice> {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(|info| {
+        let backtrace = Backtrace::capture();
+        let ice = Ice {
+            message: info.payload_as_str().unwrap_or("(no message)").to_string(),
+            location: info.location().map_or(String::new(), |at| format!("{}:{}:{}", at.file(), at.line(), at.column())),
+            phase: PHASE.with(Cell::get),
+            backtrace: (backtrace.status() == BacktraceStatus::Captured).then_some(backtrace),
+        };
+        PANIC.with(|panic| *panic.borrow_mut() = Some(ice));
+    }));
+    let result = panic::catch_unwind(AssertUnwindSafe(run));
+    panic::set_hook(previous);
+    result.map_err(|_| PANIC.with(|panic| panic.borrow_mut().take()).unwrap_or_else(|| unreachable!()))
+}
+
+/// `catch` on a thread of its own, given up on after `limit`; a phase that
+/// hangs on a reduced input is left running until the compiler exits
+pub fn catch_within<R: Send + 'static>(limit: Duration, run: impl FnOnce() -> R + Send + 'static) -> Option<Result<R, Ice>> {
+    let (sender, receiver) = mpsc::channel();
+    let spawned = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || sender.send(catch(run)));
+    spawned.ok()?;
+    receiver.recv_timeout(limit).ok()
+}
+
+/// The smallest part of `items` found for which `fails` still holds, by
+/// taking out chunks of them, halving the chunks when none can go
+pub fn minimize<T: Clone>(mut items: Vec<T>, mut fails: impl FnMut(&[T]) -> bool) -> Vec<T> {
+    let mut chunk = items.len().div_ceil(2);
+    let mut tries = 0;
+    while chunk > 0 && tries < MAX_TRIES {
+        let mut removed = false;
+        let mut start = 0;
+        while start < items.len() && tries < MAX_TRIES {
+            let end = (start + chunk).min(items.len());
+            let candidate: Vec<T> = items[..start].iter().chain(&items[end..]).cloned().collect();
+            tries += 1;
+            if fails(&candidate) {
+                items = candidate;
+                removed = true;
+            } else {
+                start = end;
+            }
+        }
+        if !removed {
+            chunk /= 2;
+        }
+    }
+    items
+}
+
+/// Tokens written back out as source, a line per statement or brace
+pub fn source_text(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        text.push_str(&token.to_string());
+        let ends_line = matches!(token, Token::Semicolon | Token::BraceL | Token::BraceR | Token::Comment(_) | Token::Pragma(_));
+        text.push(if ends_line { '\n' } else { ' ' });
+    }
+    text
+}
+
+/// What to attach to a bug report: the error, the reduced input (`size`
+/// says how much of the original it is), its tokens, and the AST it parses
+/// to, if it does
+pub fn dump(ice: &Ice, input: &str, reduced: &str, size: &str, tokens: &[Token], ast: Option<&TranslationUnit>) -> String {
+    let mut text = ice.render(input);
+    text.push_str(&format!("\n--- Reduced input ({}) ---\n{}\n", size, reduced));
+    if !tokens.is_empty() {
+        text.push_str("\n--- Tokens ---\n");
+        for token in tokens {
+            text.push_str(&format!("{:?}\n", token));
+        }
+    }
+    if let Some(ast) = ast {
+        text.push_str(&format!("\n--- AST ---\n{:#?}\n", ast));
+    }
+    text
+}
@@ -0,0 +1,399 @@
+// builtins.rs: The C library functions interpreted programs can call, the
+// math.h ones by way of math.rs.
+//
+// Output goes to the machine's buffer, which whoever runs it prints. The
+// heap functions allocate from the machine's memory, so every block knows
+// the call and line that made it, for the heap report at exit.
+//
+// Intrinsics are run here too, though a call to one is an operation of its
+// own rather than a call.
+
+use super::math;
+use super::memory::{Address, Region};
+use super::value::{CType, Typed, Value};
+use super::{Machine, Returned, RuntimeError};
+
+/// Blocks larger than this are refused, as a real malloc would refuse an
+/// absurd size, rather than exhausting the interpreter's own memory
+const MAX_ALLOCATION: i64 = 1 << 30;
+
+const BUILTINS: [&str; 9] = ["printf", "puts", "putchar", "malloc", "calloc", "realloc", "free", "exit", "abort"];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name) || math::is_math_function(name)
+}
+
+pub fn return_type(name: &str) -> CType {
+    match name {
+        "malloc" | "calloc" | "realloc" => CType::Pointer(Box::new(CType::Void)),
+        "free" | "exit" | "abort" => CType::Void,
+        _ if math::is_math_function(name) => CType::Double,
+        _ => CType::Int,
+    }
+}
+
+impl Machine {
+    pub(crate) fn call_builtin(&mut self, name: &str, arguments: Vec<Typed>) -> Result<Returned, RuntimeError> {
+        let argument = |index: usize| arguments.get(index).cloned().unwrap_or_else(|| Typed::int(0));
+        let value = match name {
+            "printf" => {
+                let format = self.read_string(argument(0).as_address())?;
+                let text = self.format(&format, arguments.get(1..).unwrap_or_default())?;
+                self.output.extend_from_slice(&text);
+                Typed::int(text.len() as i64)
+            }
+            "puts" => {
+                let text = self.read_string(argument(0).as_address())?;
+                self.output.extend_from_slice(&text);
+                self.output.push(b'\n');
+                Typed::int(text.len() as i64 + 1)
+            }
+            "putchar" => {
+                let c = argument(0).as_i64() as u8;
+                self.output.push(c);
+                Typed::int(c as i64)
+            }
+            "malloc" => self.allocate(name, argument(0).as_i64())?,
+            "calloc" => self.allocate(name, argument(0).as_i64().saturating_mul(argument(1).as_i64()))?,
+            "realloc" => self.reallocate(argument(0).as_address(), argument(1).as_i64())?,
+            "free" => {
+                let site = self.site();
+                self.memory.free(argument(0).as_address(), site);
+                Typed::void()
+            }
+            "exit" => return Ok(Returned::Exit(argument(0).as_i64())),
+            "abort" => return Ok(Returned::Exit(134)), // as killed by SIGABRT
+            _ if math::is_math_function(name) => {
+                let arguments: Vec<f64> = arguments.iter().map(Typed::as_f64).collect();
+                Typed::double(math::call(name, &arguments, self.softfloat).unwrap_or(f64::NAN))
+            }
+            _ => return Err(self.error(format!("'{}' is not a library function the interpreter knows", name))),
+        };
+        Ok(Returned::Value(value))
+    }
+
+    pub(crate) fn call_intrinsic(&mut self, name: &str, arguments: Vec<Typed>) -> Result<Returned, RuntimeError> {
+        let argument = |index: usize| arguments.get(index).cloned().unwrap_or_else(|| Typed::int(0));
+        let value = match name {
+            "__builtin_abs" => Typed::int((argument(0).as_i64() as i32).wrapping_abs() as i64),
+            "__builtin_labs" => Typed::long(argument(0).as_i64().wrapping_abs()),
+            // Both copy through a buffer, so overlapping blocks copy as memmove would
+            "__builtin_memcpy" | "__builtin_memmove" => {
+                let (destination, size) = (argument(0).as_address(), argument(2).as_i64().max(0) as usize);
+                let bytes = self.read_bytes(argument(1).as_address(), size)?;
+                self.write_bytes(destination, &bytes)?;
+                Typed::pointer(destination, CType::Void)
+            }
+            "__builtin_memset" => {
+                let (destination, size) = (argument(0).as_address(), argument(2).as_i64().max(0) as usize);
+                self.write_bytes(destination, &vec![argument(1).as_i64() as u8; size])?;
+                Typed::pointer(destination, CType::Void)
+            }
+            "__builtin_strlen" => Typed::long(self.read_string(argument(0).as_address())?.len() as i64),
+            "__builtin_expect" => Typed::long(argument(0).as_i64()),
+            "__builtin_unreachable" => return Err(self.error("reached __builtin_unreachable()".to_string())),
+            "__builtin_trap" => return Ok(Returned::Exit(132)), // as killed by SIGILL
+            _ => return Err(self.error(format!("'{}' is not an intrinsic the interpreter knows", name))),
+        };
+        Ok(Returned::Value(value))
+    }
+
+    /// A new heap block, zeroed, or null if the size is unreasonable
+    fn allocate(&mut self, function: &str, size: i64) -> Result<Typed, RuntimeError> {
+        if !(0..=MAX_ALLOCATION).contains(&size) {
+            return Ok(Typed::pointer(Address::NULL, CType::Void));
+        }
+        let site = self.site();
+        let address = self.memory.allocate(Region::Heap, size as usize, function, site);
+        Ok(Typed::pointer(address, CType::Void))
+    }
+
+    /// realloc(): a new block with as much of the old one's contents as
+    /// fits, and the old one freed; realloc(p, 0) frees p
+    fn reallocate(&mut self, old: Address, size: i64) -> Result<Typed, RuntimeError> {
+        if old.is_null() {
+            return self.allocate("realloc", size);
+        }
+        let site = self.site();
+        if size == 0 {
+            self.memory.free(old, site);
+            return Ok(Typed::pointer(Address::NULL, CType::Void));
+        }
+        let live = self.memory.allocation(old.allocation).filter(|allocation| {
+            allocation.region == Region::Heap && allocation.released.is_none() && old.offset == 0
+        });
+        let Some(old_size) = live.map(|allocation| allocation.bytes.len()) else {
+            // Reported as a bad free, and the caller gets nothing
+            self.memory.free(old, site);
+            return Ok(Typed::pointer(Address::NULL, CType::Void));
+        };
+        let new = self.allocate("realloc", size)?;
+        let new_address = new.as_address();
+        if !new_address.is_null() {
+            let contents = self.read_bytes(old, old_size.min(size as usize))?;
+            self.write_bytes(new_address, &contents)?;
+            self.memory.free(old, site);
+        }
+        Ok(new)
+    }
+
+    /// The bytes of a NUL-terminated string, without the NUL
+    pub(crate) fn read_string(&mut self, address: Address) -> Result<Vec<u8>, RuntimeError> {
+        let mut bytes = Vec::new();
+        let mut at = address;
+        loop {
+            let byte = self.read_bytes(at, 1)?[0];
+            if byte == 0 {
+                return Ok(bytes);
+            }
+            bytes.push(byte);
+            at = at.offset_by(1);
+        }
+    }
+
+    /// printf's output for a format and its arguments. Length modifiers are
+    /// accepted and ignored but for %d and friends, where without l the
+    /// value is an int.
+    fn format(&mut self, format: &[u8], arguments: &[Typed]) -> Result<Vec<u8>, RuntimeError> {
+        let mut out = Vec::new();
+        let mut arguments = arguments.iter();
+        let mut next = || arguments.next().cloned().unwrap_or_else(|| Typed::int(0));
+        let mut i = 0;
+        while i < format.len() {
+            if format[i] != b'%' {
+                out.push(format[i]);
+                i += 1;
+                continue;
+            }
+            i += 1;
+            let mut spec = Spec::default();
+            while let Some(&flag) = format.get(i).filter(|c| b"-+ #0".contains(c)) {
+                match flag {
+                    b'-' => spec.left = true,
+                    b'+' => spec.plus = true,
+                    b' ' => spec.space = true,
+                    b'#' => spec.alternate = true,
+                    _ => spec.zero = true,
+                }
+                i += 1;
+            }
+            if format.get(i) == Some(&b'*') {
+                let width = next().as_i64();
+                spec.left |= width < 0;
+                spec.width = width.unsigned_abs() as usize;
+                i += 1;
+            } else {
+                while let Some(digit) = format.get(i).filter(|c| c.is_ascii_digit()) {
+                    spec.width = spec.width * 10 + (digit - b'0') as usize;
+                    i += 1;
+                }
+            }
+            if format.get(i) == Some(&b'.') {
+                i += 1;
+                let mut precision = 0;
+                if format.get(i) == Some(&b'*') {
+                    precision = next().as_i64().max(0) as usize;
+                    i += 1;
+                } else {
+                    while let Some(digit) = format.get(i).filter(|c| c.is_ascii_digit()) {
+                        precision = precision * 10 + (digit - b'0') as usize;
+                        i += 1;
+                    }
+                }
+                spec.precision = Some(precision);
+            }
+            let mut long = false;
+            while let Some(&modifier) = format.get(i).filter(|c| b"hlLqjzt".contains(c)) {
+                long |= modifier != b'h';
+                i += 1;
+            }
+            let Some(&conversion) = format.get(i) else {
+                break;
+            };
+            i += 1;
+            let text = match conversion {
+                b'%' => b"%".to_vec(),
+                b'd' | b'i' => {
+                    let value = next().as_i64();
+                    let value = if long { value } else { value as i32 as i64 };
+                    spec.number(value < 0, value.unsigned_abs().to_string(), true)
+                }
+                b'u' | b'x' | b'X' | b'o' => {
+                    let value = next().as_i64() as u64;
+                    let value = if long { value } else { value as u32 as u64 };
+                    let (digits, prefix) = match conversion {
+                        b'x' => (format!("{:x}", value), "0x"),
+                        b'X' => (format!("{:X}", value), "0X"),
+                        b'o' => (format!("{:o}", value), "0"),
+                        _ => (value.to_string(), ""),
+                    };
+                    let prefix = if spec.alternate && value != 0 { prefix } else { "" };
+                    spec.plus = false;
+                    spec.space = false;
+                    let digits = spec.number(false, digits, true);
+                    [prefix.as_bytes(), &digits].concat()
+                }
+                b'c' => vec![next().as_i64() as u8],
+                b's' => {
+                    let mut text = self.read_string(next().as_address())?;
+                    if let Some(precision) = spec.precision {
+                        text.truncate(precision);
+                    }
+                    text
+                }
+                b'p' => match next().value {
+                    Value::Pointer(address) => address.to_string().into_bytes(),
+                    other => Address::decode(Typed { value: other, ty: CType::Long }.as_i64()).to_string().into_bytes(),
+                },
+                b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                    let value = next().as_f64();
+                    let text = format_float(value, conversion, spec.precision.unwrap_or(6), spec.alternate);
+                    spec.number(value.is_sign_negative() && !value.is_nan(), text, false)
+                }
+                other => return Err(self.error(format!("printf conversion '%{}' isn't supported", other as char))),
+            };
+            out.extend(spec.pad(text));
+        }
+        Ok(out)
+    }
+}
+
+/// A printf conversion's flags, width and precision
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Digits with their sign, zero-padded to the width if asked; a
+    /// precision on an integer is a minimum number of digits
+    fn number(&self, negative: bool, digits: String, is_integer: bool) -> Vec<u8> {
+        let digits = match self.precision {
+            Some(precision) if is_integer && digits.len() < precision => {
+                format!("{}{}", "0".repeat(precision - digits.len()), digits)
+            }
+            _ => digits,
+        };
+        let sign = if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        };
+        // Zero padding goes between the sign and the digits
+        let zeros = if self.zero && !self.left && (self.precision.is_none() || !is_integer) {
+            self.width.saturating_sub(sign.len() + digits.len())
+        } else {
+            0
+        };
+        format!("{}{}{}", sign, "0".repeat(zeros), digits).into_bytes()
+    }
+
+    /// Padded with spaces to the width
+    fn pad(&self, text: Vec<u8>) -> Vec<u8> {
+        let padding = vec![b' '; self.width.saturating_sub(text.len())];
+        if self.left { [text, padding].concat() } else { [padding, text].concat() }
+    }
+}
+
+/// %f, %e or %g of the magnitude of `value`; the sign is added by Spec
+fn format_float(value: f64, conversion: u8, precision: usize, alternate: bool) -> String {
+    let value = value.abs();
+    if !value.is_finite() {
+        let text = if value.is_nan() { "nan" } else { "inf" };
+        return if conversion.is_ascii_uppercase() { text.to_uppercase() } else { text.to_string() };
+    }
+    let text = match conversion.to_ascii_lowercase() {
+        // `#` keeps the point even with no digits after it: 3.
+        b'f' if alternate && precision == 0 => format!("{:.0}.", value),
+        b'f' => format!("{:.*}", precision, value),
+        b'e' if alternate && precision == 0 => exponent_form(value, 0).replacen('e', ".e", 1),
+        b'e' => exponent_form(value, precision),
+        _ => {
+            // %g: %e if the exponent is below -4 or at least the precision,
+            // else %f, with trailing zeros dropped unless `#`
+            let precision = precision.max(1);
+            let exponent = match exponent_form(value, precision - 1).split_once('e') {
+                Some((_, exponent)) if value != 0.0 => exponent.parse().unwrap_or(0),
+                _ => 0,
+            };
+            let text = if exponent < -4 || exponent >= precision as i32 {
+                exponent_form(value, precision - 1)
+            } else {
+                format!("{:.*}", (precision as i32 - 1 - exponent).max(0) as usize, value)
+            };
+            if alternate { text } else { strip_zeros(&text) }
+        }
+    };
+    if conversion.is_ascii_uppercase() { text.to_uppercase() } else { text }
+}
+
+/// 1.500000e+02, as C writes exponents: a sign and at least two digits
+fn exponent_form(value: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    format!("{}e{}{:02}", mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+/// Trailing zeros of the fraction off, and the point if nothing is left
+fn strip_zeros(text: &str) -> String {
+    let (number, exponent) = match text.find('e') {
+        Some(e) => text.split_at(e),
+        None => (text, ""),
+    };
+    let number = if number.contains('.') { number.trim_end_matches('0').trim_end_matches('.') } else { number };
+    format!("{}{}", number, exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_float;
+
+    /// Each case as glibc's printf writes it
+    #[test]
+    fn floats_format_as_printf_writes_them() {
+        let cases = [
+            (0.1f32 as f64, b'f', 6, false, "0.100000"),
+            (0.1f32 as f64, b'e', 6, false, "1.000000e-01"),
+            (0.1f32 as f64, b'g', 6, false, "0.1"),
+            (1234.5678, b'f', 6, false, "1234.567800"),
+            (1234.5678, b'e', 6, false, "1.234568e+03"),
+            (1234.5678, b'E', 6, false, "1.234568E+03"),
+            (1234.5678, b'g', 6, false, "1234.57"),
+            (1234.5678, b'e', 3, false, "1.235e+03"),
+            (0.0001, b'g', 6, false, "0.0001"),
+            (0.00001, b'g', 6, false, "1e-05"),
+            (100000.0, b'g', 6, false, "100000"),
+            (1000000.0, b'g', 6, false, "1e+06"),
+            (1e-300, b'g', 6, false, "1e-300"),
+            (2.5, b'f', 0, false, "2"),
+            (2.5, b'e', 0, false, "2e+00"),
+            (3.0, b'f', 0, true, "3."),
+            (3.0, b'e', 0, true, "3.e+00"),
+            (3.0, b'g', 6, true, "3.00000"),
+            (f64::INFINITY, b'f', 6, false, "inf"),
+            (f64::INFINITY, b'G', 6, false, "INF"),
+            (f64::NAN, b'e', 6, false, "nan"),
+        ];
+        for (value, conversion, precision, alternate, expected) in cases {
+            assert_eq!(
+                format_float(value, conversion, precision, alternate),
+                expected,
+                "%{}.{}{}",
+                if alternate { "#" } else { "" },
+                precision,
+                conversion as char
+            );
+        }
+    }
+}
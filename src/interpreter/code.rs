@@ -0,0 +1,417 @@
+// code.rs: Steps of the control flow graph compiled to stack code.
+//
+// An expression can call a function partway through, and the interpreter
+// has to be able to stop there (to step into the call, or to snapshot its
+// state) and carry on later. So a step isn't evaluated by walking the tree;
+// it is compiled to a list of operations on a stack of operands, and the
+// interpreter keeps its place in that list.
+//
+// An operand is a value or a place (an address and the type stored there).
+// Names, `*p`, `a[i]` and members compile to places; Load turns a place into
+// the value in it, with arrays and functions becoming pointers to their
+// start. && || and ?: jump over what they don't evaluate.
+
+use super::value::{CType, Typed, Value};
+use crate::intrinsics;
+use crate::layout::Layouts;
+use crate::lexer_regex;
+use crate::lower::{Step, Terminator};
+use crate::parser::ast::*;
+
+#[derive(Debug, Clone)]
+pub enum Op {
+    Push(Typed),
+    StringLiteral(Vec<u8>), // the place of a literal, escapes decoded
+    Variable(String),      // the place of a variable or function; an enumerator's value
+    Load,
+    AddressOf,
+    Dereference,
+    Member(String),
+    Unary(UnaryOperator), // + - ! ~
+    Binary(BinaryOperator), // all but && and ||
+    /// Store the value into the place under it and leave the value, after
+    /// combining it with the old value first for compound assignment
+    Assign(Option<BinaryOperator>),
+    /// Add to the value in a place and leave the old value or the new one
+    Increment { delta: i64, postfix: bool },
+    Cast(CType),
+    SizeofExpression(Expression), // worked out from types, never evaluated
+    Call(usize),                  // callee and this many arguments
+    Intrinsic(&'static str, usize), // this many arguments
+    Pop,
+    Jump(usize),
+    JumpIfZero(usize), // takes the value
+    JumpIfNonZero(usize),
+    /// Make a local; lengths of None are taken from the stack, last length
+    /// on top. A static local that already exists jumps to `end`, past its
+    /// initializer.
+    Declare { declaration: Box<VariableDeclaration>, lengths: Vec<Option<usize>>, end: usize },
+    /// Store the value into the place under it and drop both; a string
+    /// fills a char array
+    Initialize,
+    /// The place of an aggregate's nth element or member, in place of the
+    /// aggregate's
+    Element(usize),
+    Duplicate,
+    /// Make the unnamed object of a compound literal and leave its place
+    Temporary(CType),
+    Unsupported(&'static str),
+}
+
+pub struct Compiler<'a> {
+    layouts: &'a Layouts,
+    pub ops: Vec<Op>,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(layouts: &'a Layouts) -> Compiler<'a> {
+        Compiler { layouts, ops: Vec::new() }
+    }
+
+    /// Code for a step; it leaves nothing on the stack
+    pub fn step(mut self, step: &Step) -> Vec<Op> {
+        match step {
+            Step::Declare(declaration) => self.declaration(declaration),
+            Step::Assign(name, value) => {
+                self.ops.push(Op::Variable(name.clone()));
+                self.value(value);
+                self.ops.extend([Op::Assign(None), Op::Pop]);
+            }
+            Step::Evaluate(expression) => {
+                self.value(expression);
+                self.ops.push(Op::Pop);
+            }
+        }
+        self.ops
+    }
+
+    /// Code for a terminator; it leaves the condition, switch value or
+    /// return value, if there is one
+    pub fn terminator(mut self, terminator: &Terminator) -> Vec<Op> {
+        match terminator {
+            Terminator::Branch(value, _, _) | Terminator::Switch { value, .. } | Terminator::Return(Some(value)) => {
+                self.value(value)
+            }
+            Terminator::Goto(_) | Terminator::Return(None) => {}
+        }
+        self.ops
+    }
+
+    fn here(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Point the jump at `at` to the next operation
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.ops[at] {
+            Op::Jump(to) | Op::JumpIfZero(to) | Op::JumpIfNonZero(to) => *to = target,
+            Op::Declare { end, .. } => *end = target,
+            _ => {}
+        }
+    }
+
+    fn declaration(&mut self, declaration: &VariableDeclaration) {
+        let declarator = &declaration.declarator;
+        let mut lengths = Vec::new();
+        for (index, size) in declarator.array_sizes.iter().enumerate() {
+            let length = match size {
+                Some(size) => match size.integer_constant_value_in(self.layouts) {
+                    Some(length) => Some(length.max(0) as usize),
+                    None => {
+                        self.value(size);
+                        None
+                    }
+                },
+                // `int a[] = {1, 2, 3}` takes its length from the initializer
+                None if index == 0 => Some(declaration.initializer.as_ref().map_or(0, initializer_length)),
+                None => Some(0),
+            };
+            lengths.push(length);
+        }
+        let declare = self.here();
+        self.ops.push(Op::Declare { declaration: Box::new(declaration.clone()), lengths, end: 0 });
+        if let Some(initializer) = &declaration.initializer {
+            self.ops.push(Op::Variable(declarator.name.clone()));
+            self.initializer(initializer);
+        }
+        self.patch(declare);
+    }
+
+    /// The place of a compound literal's object, made and initialized as
+    /// a local without a name would be
+    fn compound_literal(&mut self, literal: &CompoundLiteral) {
+        let list = Initializer { kind: InitializerKind::List(literal.initializers.clone()) };
+        let mut ty = CType::from_spelling(&literal.type_name, self.layouts);
+        for (index, size) in literal.array_sizes.iter().enumerate().rev() {
+            // The type checker rejects variable lengths here
+            let length = match size {
+                Some(size) => size.integer_constant_value_in(self.layouts).unwrap_or(1).max(0) as usize,
+                // `(int[]){1, 2, 3}` takes its length from the list
+                None if index == 0 => initializer_length(&list),
+                None => 0,
+            };
+            ty = CType::Array(Box::new(ty), length);
+        }
+        self.ops.push(Op::Temporary(ty));
+        let mut next = 0;
+        for item in &literal.initializers {
+            next = self.element(item, next) + 1;
+        }
+    }
+
+    /// Initialize the place on top of the stack, and take it off
+    fn initializer(&mut self, initializer: &Initializer) {
+        match &initializer.kind {
+            InitializerKind::Assignment(value) => {
+                self.value(value);
+                self.ops.push(Op::Initialize);
+            }
+            InitializerKind::List(items) => {
+                let mut next = 0;
+                for item in items {
+                    next = self.element(item, next) + 1;
+                }
+                self.ops.push(Op::Pop);
+            }
+            // Only inside a list
+            InitializerKind::Designated(..) => {
+                self.element(initializer, 0);
+                self.ops.push(Op::Pop);
+            }
+        }
+    }
+
+    /// Initialize one item of a list, the `next` element unless it says
+    /// which; the element it initialized
+    fn element(&mut self, item: &Initializer, next: usize) -> usize {
+        self.ops.push(Op::Duplicate);
+        match &item.kind {
+            InitializerKind::Designated(designator, value) => {
+                match designator {
+                    Designator::Member(name) => self.ops.push(Op::Member(name.clone())),
+                    Designator::Array(index) => {
+                        let index = index.integer_constant_value_in(self.layouts).unwrap_or(0).max(0) as usize;
+                        self.ops.push(Op::Element(index));
+                        self.initializer(value);
+                        return index;
+                    }
+                }
+                self.initializer(value);
+                next
+            }
+            _ => {
+                self.ops.push(Op::Element(next));
+                self.initializer(item);
+                next
+            }
+        }
+    }
+
+    /// Code leaving the value of an expression
+    pub fn value(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(_)
+            | Expression::StringLiteral(_)
+            | Expression::ArrayAccess(..)
+            | Expression::MemberAccess(..)
+            | Expression::PointerAccess(..)
+            | Expression::UnaryOp(UnaryOperator::Dereference, _)
+            | Expression::CompoundLiteral(_) => {
+                self.place(expression);
+                self.ops.push(Op::Load);
+            }
+            Expression::EncodedStringLiteral(LiteralEncoding::Utf8, text) => {
+                self.ops.extend([Op::StringLiteral(decode_escapes(text)), Op::Load]);
+            }
+            Expression::EncodedStringLiteral(..) => self.ops.push(Op::Unsupported("wide string literals")),
+            Expression::Constant(constant) | Expression::Folded(constant, _) => {
+                self.ops.push(Op::Push(constant_value(constant)))
+            }
+            Expression::BinaryOp(left, BinaryOperator::And, right) => {
+                self.value(left);
+                let left_false = self.here();
+                self.ops.push(Op::JumpIfZero(0));
+                self.value(right);
+                let right_false = self.here();
+                self.ops.push(Op::JumpIfZero(0));
+                self.boolean_result(&[left_false, right_false], 0);
+            }
+            Expression::BinaryOp(left, BinaryOperator::Or, right) => {
+                self.value(left);
+                let left_true = self.here();
+                self.ops.push(Op::JumpIfNonZero(0));
+                self.value(right);
+                let right_true = self.here();
+                self.ops.push(Op::JumpIfNonZero(0));
+                self.boolean_result(&[left_true, right_true], 1);
+            }
+            Expression::BinaryOp(left, op, right) => {
+                self.value(left);
+                self.value(right);
+                self.ops.push(Op::Binary(op.clone()));
+            }
+            Expression::UnaryOp(UnaryOperator::AddressOf, operand) => {
+                self.place(operand);
+                self.ops.push(Op::AddressOf);
+            }
+            Expression::UnaryOp(UnaryOperator::Sizeof, operand) => {
+                self.ops.push(Op::SizeofExpression((**operand).clone()));
+            }
+            Expression::UnaryOp(op @ (UnaryOperator::PreIncrement | UnaryOperator::PreDecrement), operand) => {
+                self.place(operand);
+                let delta = if matches!(op, UnaryOperator::PreIncrement) { 1 } else { -1 };
+                self.ops.push(Op::Increment { delta, postfix: false });
+            }
+            Expression::UnaryOp(op, operand) => {
+                self.value(operand);
+                self.ops.push(Op::Unary(op.clone()));
+            }
+            Expression::PostfixOp(operand, op) => {
+                self.place(operand);
+                let delta = if matches!(op, PostfixOperator::PlusPlus) { 1 } else { -1 };
+                self.ops.push(Op::Increment { delta, postfix: true });
+            }
+            Expression::Assignment(target, op, value) => {
+                self.place(target);
+                self.value(value);
+                self.ops.push(Op::Assign(compound_operator(op)));
+            }
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.value(condition);
+                let to_else = self.here();
+                self.ops.push(Op::JumpIfZero(0));
+                self.value(then_value);
+                let to_end = self.here();
+                self.ops.push(Op::Jump(0));
+                self.patch(to_else);
+                self.value(else_value);
+                self.patch(to_end);
+            }
+            Expression::FunctionCall(callee, arguments) => {
+                // An intrinsic is one operation on its arguments, with no callee
+                let intrinsic = callee.as_function_name().and_then(intrinsics::lookup);
+                if intrinsic.is_none() {
+                    self.value(callee);
+                }
+                for argument in arguments {
+                    self.value(argument);
+                }
+                self.ops.push(match intrinsic {
+                    Some(intrinsic) => Op::Intrinsic(intrinsic.name, arguments.len()),
+                    None => Op::Call(arguments.len()),
+                });
+            }
+            Expression::Cast(type_name, operand) => {
+                self.value(operand);
+                self.ops.push(Op::Cast(CType::from_type_name(type_name, self.layouts)));
+            }
+            Expression::Paren(inner) => self.value(inner),
+            Expression::SizeofType(type_name) => {
+                let size = CType::from_type_name(type_name, self.layouts).size(self.layouts);
+                self.ops.push(Op::Push(Typed::long(size as i64)));
+            }
+            Expression::StatementExpr(_) => self.ops.push(Op::Unsupported("statement expressions")),
+            Expression::Generic(..) => self.ops.push(Op::Unsupported("_Generic")),
+        }
+    }
+
+    /// The end of && and ||: jumps from `jumps` leave `jumped`, falling
+    /// through leaves the other
+    fn boolean_result(&mut self, jumps: &[usize], jumped: i64) {
+        self.ops.push(Op::Push(Typed::int(1 - jumped)));
+        let to_end = self.here();
+        self.ops.push(Op::Jump(0));
+        for &jump in jumps {
+            self.patch(jump);
+        }
+        self.ops.push(Op::Push(Typed::int(jumped)));
+        self.patch(to_end);
+    }
+
+    /// Code leaving the place an expression designates; anything else
+    /// leaves its value, which only Member accepts in place of a place
+    pub fn place(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(name) => self.ops.push(Op::Variable(name.clone())),
+            Expression::StringLiteral(text) => self.ops.push(Op::StringLiteral(decode_escapes(text))),
+            Expression::UnaryOp(UnaryOperator::Dereference, pointer) => {
+                self.value(pointer);
+                self.ops.push(Op::Dereference);
+            }
+            // a[i] is *(a + i)
+            Expression::ArrayAccess(array, index) => {
+                self.value(array);
+                self.value(index);
+                self.ops.extend([Op::Binary(BinaryOperator::Plus), Op::Dereference]);
+            }
+            Expression::MemberAccess(object, member) => {
+                self.place(object);
+                self.ops.push(Op::Member(member.clone()));
+            }
+            Expression::PointerAccess(pointer, member) => {
+                self.value(pointer);
+                self.ops.extend([Op::Dereference, Op::Member(member.clone())]);
+            }
+            Expression::Paren(inner) => self.place(inner),
+            Expression::CompoundLiteral(literal) => self.compound_literal(literal),
+            _ => self.value(expression),
+        }
+    }
+}
+
+fn compound_operator(op: &AssignmentOperator) -> Option<BinaryOperator> {
+    match op {
+        AssignmentOperator::Assign => None,
+        AssignmentOperator::PlusAssign => Some(BinaryOperator::Plus),
+        AssignmentOperator::MinusAssign => Some(BinaryOperator::Minus),
+        AssignmentOperator::MultAssign => Some(BinaryOperator::Mult),
+        AssignmentOperator::DivAssign => Some(BinaryOperator::Div),
+        AssignmentOperator::ModAssign => Some(BinaryOperator::Mod),
+        AssignmentOperator::LShiftAssign => Some(BinaryOperator::LShift),
+        AssignmentOperator::RShiftAssign => Some(BinaryOperator::RShift),
+        AssignmentOperator::AndAssign => Some(BinaryOperator::BitAnd),
+        AssignmentOperator::XorAssign => Some(BinaryOperator::Xor),
+        AssignmentOperator::OrAssign => Some(BinaryOperator::BitOr),
+    }
+}
+
+pub fn constant_value(constant: &Constant) -> Typed {
+    match constant {
+        Constant::Integer(n) => Typed::int(*n),
+        Constant::Float(x, FloatSuffix::Float) => Typed { value: Value::Float(*x), ty: CType::Float },
+        Constant::Float(x, _) => Typed { value: Value::Float(*x), ty: CType::Double },
+        Constant::Char(c) | Constant::EncodedChar(_, c) => Typed::int(*c as i64),
+        Constant::Bool(b) => Typed { value: Value::Int(*b as i64), ty: CType::Bool },
+        Constant::Null => Typed::pointer(super::memory::Address::NULL, CType::Void),
+    }
+}
+
+/// Number of elements an initializer gives an array of unspecified
+/// length: the items of a list, as far as the highest designated index,
+/// or the characters of a string and its terminator
+fn initializer_length(initializer: &Initializer) -> usize {
+    match &initializer.kind {
+        InitializerKind::Assignment(Expression::StringLiteral(text)) => decode_escapes(text).len() + 1,
+        InitializerKind::List(items) => {
+            let mut next = 0;
+            let mut length = 0;
+            for item in items {
+                if let InitializerKind::Designated(Designator::Array(index), _) = &item.kind {
+                    next = index.integer_constant_value().unwrap_or(0).max(0) as usize;
+                }
+                next += 1;
+                length = length.max(next);
+            }
+            length
+        }
+        _ => 1,
+    }
+}
+
+/// A string literal's text as the lexer keeps it, escapes and all, as the
+/// bytes it stands for
+pub fn decode_escapes(text: &str) -> Vec<u8> {
+    // The lexer turned a literal with a bad escape into an error
+    lexer_regex::decode_string(text).unwrap_or_else(|_| text.as_bytes().to_vec())
+}
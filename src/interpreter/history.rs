@@ -0,0 +1,133 @@
+// history.rs: Snapshots of a run, so it can be wound back to any earlier
+// statement.
+//
+// Runs are deterministic, so an earlier point of a run is had again by
+// taking the last snapshot before it and running forward. Snapshots are
+// copies of the machine, taken every `interval` statements; when they add
+// up to more than the budget, every other one is dropped and the interval
+// doubles. A long run keeps snapshots all the way back to its start, just
+// further apart, so winding back always works and only gets slower.
+
+use super::Machine;
+use std::mem::size_of;
+
+/// Statements between snapshots to begin with
+const FIRST_INTERVAL: usize = 1000;
+
+/// Where a run was after some number of statements
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub steps: usize,
+    pub frames: usize,
+    pub line: Option<usize>,
+}
+
+pub struct History {
+    snapshots: Vec<Machine>, // by steps, the first at the start of the run
+    interval: usize,
+    budget: usize, // bytes
+    used: usize,
+}
+
+impl History {
+    /// A history of the run `start` begins, kept within `budget` bytes
+    pub fn new(start: &Machine, budget: usize) -> History {
+        History { snapshots: vec![start.clone()], interval: FIRST_INTERVAL, budget, used: start.footprint() }
+    }
+
+    /// Take a snapshot if it's time to; called after each step
+    pub fn record(&mut self, machine: &Machine) {
+        let last = self.snapshots.last().map_or(0, Machine::steps);
+        if machine.steps() < last + self.interval {
+            return;
+        }
+        self.used += machine.footprint();
+        self.snapshots.push(machine.clone());
+        while self.used > self.budget && self.snapshots.len() > 2 {
+            let mut index = 0;
+            self.snapshots.retain(|_| {
+                index += 1;
+                index % 2 == 1
+            });
+            self.interval *= 2;
+            self.used = self.snapshots.iter().map(Machine::footprint).sum();
+        }
+    }
+
+    /// The machine as it was after `steps` statements. Its output is
+    /// dropped, having been shown the first time round.
+    pub fn rewind(&self, steps: usize) -> Machine {
+        let mut machine = self.before(steps + 1).clone();
+        while machine.steps() < steps && machine.step().is_ok() {
+            machine.take_output();
+        }
+        machine.take_output();
+        machine
+    }
+
+    /// Where the run was before `machine`, latest first, back to its start
+    pub fn earlier<'a>(&'a self, machine: &Machine) -> Earlier<'a> {
+        Earlier { history: self, end: machine.steps(), window: Vec::new() }
+    }
+
+    /// "12 snapshot(s) in 3.1 MB of 64.0 MB, one every 2000 statements"
+    pub fn describe(&self) -> String {
+        let megabytes = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+        format!(
+            "{} snapshot(s) in {:.1} MB of {:.1} MB, one every {} statements\n",
+            self.snapshots.len(),
+            megabytes(self.used),
+            megabytes(self.budget),
+            self.interval
+        )
+    }
+
+    /// The last snapshot from before `steps` statements
+    fn before(&self, steps: usize) -> &Machine {
+        let index = self.snapshots.partition_point(|snapshot| snapshot.steps() < steps);
+        &self.snapshots[index.saturating_sub(1)]
+    }
+}
+
+/// Positions of a run going back from some point, found by replaying it a
+/// stretch between snapshots at a time
+pub struct Earlier<'a> {
+    history: &'a History,
+    end: usize, // positions before this are still to come
+    window: Vec<Position>,
+}
+
+impl Iterator for Earlier<'_> {
+    type Item = Position;
+
+    fn next(&mut self) -> Option<Position> {
+        if self.window.is_empty() && self.end > 0 {
+            let mut machine = self.history.before(self.end).clone();
+            let from = machine.steps();
+            self.window.push(machine.position());
+            while machine.steps() + 1 < self.end && machine.step().is_ok() {
+                machine.take_output();
+                self.window.push(machine.position());
+            }
+            self.end = from;
+        }
+        self.window.pop()
+    }
+}
+
+impl Machine {
+    pub fn position(&self) -> Position {
+        Position { steps: self.steps(), frames: self.frames().len(), line: self.site().line }
+    }
+
+    /// Roughly the bytes a copy of the machine takes up; the program is
+    /// shared between copies, so isn't counted
+    pub fn footprint(&self) -> usize {
+        let frames: usize = self
+            .frames
+            .iter()
+            .map(|frame| size_of::<super::Frame>() + frame.locals.len() * size_of::<(String, super::Local)>())
+            .sum();
+        size_of::<Machine>() + self.memory.footprint() + frames + self.output.len()
+    }
+}
@@ -0,0 +1,178 @@
+// inspect.rs: Values and types of expressions, as a debugger shows them.
+//
+// An expression is evaluated on a copy of the machine cut back to the
+// frame asked about, so looking at a program can't change how it runs:
+// whatever the expression does to memory happens to the copy. Library
+// functions can be called, but not the program's own.
+
+use super::code::{Compiler, Op};
+use super::memory::{Address, Region};
+use super::value::{CType, Typed, Value};
+use super::{Evaluation, Flow, Machine, RuntimeError};
+use crate::parser::ast::*;
+use std::rc::Rc;
+
+/// Elements of an array shown before the rest are left out
+const MAX_ELEMENTS: usize = 100;
+
+impl Machine {
+    /// An expression's value as text, in frame `frame` (main's is 0).
+    /// Arrays and structs are shown whole, as initializers would write
+    /// them, and char pointers with the string they point to.
+    pub fn inspect(&self, frame: usize, expression: &Expression) -> Result<String, RuntimeError> {
+        let mut scratch = self.scratch(frame);
+        let mut compiler = Compiler::new(&self.program.layouts);
+        let object = scratch.is_object(expression);
+        if object {
+            compiler.place(expression);
+            compiler.ops.push(Op::AddressOf);
+        } else {
+            compiler.value(expression);
+        }
+        let mut evaluation = Evaluation { code: Rc::new(compiler.ops), pc: 0, operands: Vec::new() };
+        let value = match scratch.execute(&mut evaluation)? {
+            Flow::Done(Some(value)) => value,
+            Flow::Done(None) => return Ok("void".to_string()),
+            Flow::Call(..) | Flow::Exit(_) => {
+                return Err(scratch.error("the debugger doesn't call the program's functions".to_string()));
+            }
+        };
+        Ok(match (object, &value.ty) {
+            (true, CType::Pointer(ty)) => scratch.show(value.as_address(), ty),
+            _ => scratch.show_value(&value),
+        })
+    }
+
+    /// An expression's type in frame `frame`, from the types of the
+    /// variables it uses
+    pub fn type_of(&self, frame: usize, expression: &Expression) -> Option<CType> {
+        self.scratch(frame).static_type(expression)
+    }
+
+    /// `push (head = 0x100000000, value = 10) at line 10`
+    pub fn describe_frame(&self, index: usize) -> String {
+        let Some(frame) = self.frames.get(index) else {
+            return "No frame".to_string();
+        };
+        let value = |name: &str| match self.inspect(index, &Expression::Identifier(name.to_string())) {
+            Ok(value) => value,
+            Err(error) => format!("<{}>", error.message),
+        };
+        let count = self.parameter_count(frame);
+        let arguments: Vec<String> =
+            frame.locals.iter().take(count).map(|(name, _)| format!("{} = {}", name, value(name))).collect();
+        let at = match self.frame_site(index).line {
+            Some(line) => format!(" at line {}", line),
+            None => String::new(),
+        };
+        format!("{} ({}){}", self.function_name(frame), arguments.join(", "), at)
+    }
+
+    /// The call stack, innermost frame first as #0, a line each
+    pub fn backtrace(&self) -> String {
+        let frames = self.frames.len();
+        (0..frames).rev().map(|index| format!("#{}  {}\n", frames - 1 - index, self.describe_frame(index))).collect()
+    }
+
+    /// A copy of the machine with frame `frame` innermost
+    fn scratch(&self, frame: usize) -> Machine {
+        let mut scratch = self.clone();
+        scratch.frames.truncate(frame + 1);
+        scratch
+    }
+
+    /// Whether an expression designates a variable or part of one
+    fn is_object(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::Identifier(name) => self.lookup(name).is_some(),
+            Expression::ArrayAccess(..)
+            | Expression::MemberAccess(..)
+            | Expression::PointerAccess(..)
+            | Expression::UnaryOp(UnaryOperator::Dereference, _) => true,
+            Expression::Paren(inner) => self.is_object(inner),
+            _ => false,
+        }
+    }
+
+    /// The object of type `ty` at `address`
+    pub(super) fn show(&mut self, address: Address, ty: &CType) -> String {
+        let program = Rc::clone(&self.program);
+        match ty {
+            CType::Array(element, length) if **element == CType::Char => match self.read_bytes(address, *length) {
+                Ok(bytes) => quoted(bytes.split(|&byte| byte == 0).next().unwrap_or_default()),
+                Err(error) => format!("<{}>", error.message),
+            },
+            CType::Array(element, length) => {
+                let size = element.size(&program.layouts);
+                let mut elements: Vec<String> = (0..(*length).min(MAX_ELEMENTS))
+                    .map(|index| self.show(address.offset_by((index * size) as i64), element))
+                    .collect();
+                if *length > MAX_ELEMENTS {
+                    elements.push("...".to_string());
+                }
+                format!("{{{}}}", elements.join(", "))
+            }
+            CType::Struct(tag) => {
+                let Some(definition) = program.structs.get(tag) else {
+                    return format!("<incomplete struct {}>", tag);
+                };
+                let members: Vec<String> = definition
+                    .members
+                    .iter()
+                    .filter_map(|member| {
+                        let name = &member.declarator.name;
+                        let (offset, member_type) = self.member(tag, name)?;
+                        Some(format!("{} = {}", name, self.show(address.offset_by(offset as i64), &member_type)))
+                    })
+                    .collect();
+                format!("{{{}}}", members.join(", "))
+            }
+            CType::Function => self.show_value(&Typed::pointer(address, CType::Function)),
+            CType::Void => "<void>".to_string(),
+            _ => match self.load(address, ty) {
+                Ok(value) => self.show_value(&value),
+                Err(error) => format!("<{}>", error.message),
+            },
+        }
+    }
+
+    pub(super) fn show_value(&mut self, value: &Typed) -> String {
+        match (&value.ty, &value.value) {
+            (CType::Pointer(pointee), Value::Pointer(address)) if !address.is_null() => match &**pointee {
+                CType::Char => match self.read_string(*address) {
+                    Ok(text) => format!("{} {}", address, quoted(&text)),
+                    Err(_) => address.to_string(),
+                },
+                // Functions by name, like gdb's <main>
+                _ => match self.memory.allocation(address.allocation) {
+                    Some(allocation) if allocation.region == Region::Function => {
+                        format!("{} <{}>", address, allocation.name)
+                    }
+                    _ => address.to_string(),
+                },
+            },
+            (CType::Struct(_), Value::Aggregate(bytes)) => {
+                let address = self.memory.allocate_with(Region::Stack, bytes.clone(), "value", self.site());
+                self.show(address, &value.ty)
+            }
+            _ => value.to_string(),
+        }
+    }
+}
+
+/// Bytes as a C string literal
+fn quoted(bytes: &[u8]) -> String {
+    let mut out = String::from('"');
+    for &byte in bytes {
+        match byte {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
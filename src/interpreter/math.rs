@@ -0,0 +1,121 @@
+// math.rs: The math.h functions interpreted programs can call.
+//
+// By default they are the host's own, through Rust's f64 methods, which on
+// a native build call the same libm a compiled program is linked with, so
+// the interpreter and `cc -lm` print the same digits. With softfloat they
+// are computed in software instead, by the libm crate's port of musl's
+// libm, and print the same digits on every machine whatever its libm. The
+// functions IEEE 754 requires to be correctly rounded (sqrt, fabs, floor,
+// fmod and the like) agree either way; the others may differ in the last
+// bit.
+
+/// Each function and how many doubles it takes; all return a double
+pub const FUNCTIONS: [(&str, usize); 28] = [
+    ("sqrt", 1),
+    ("cbrt", 1),
+    ("pow", 2),
+    ("hypot", 2),
+    ("exp", 1),
+    ("exp2", 1),
+    ("log", 1),
+    ("log2", 1),
+    ("log10", 1),
+    ("sin", 1),
+    ("cos", 1),
+    ("tan", 1),
+    ("asin", 1),
+    ("acos", 1),
+    ("atan", 1),
+    ("atan2", 2),
+    ("sinh", 1),
+    ("cosh", 1),
+    ("tanh", 1),
+    ("fabs", 1),
+    ("floor", 1),
+    ("ceil", 1),
+    ("trunc", 1),
+    ("round", 1),
+    ("fmod", 2),
+    ("fmin", 2),
+    ("fmax", 2),
+    ("copysign", 2),
+];
+
+pub fn is_math_function(name: &str) -> bool {
+    FUNCTIONS.iter().any(|(function, _)| *function == name)
+}
+
+/// The function's result for these arguments, missing ones taken as 0
+pub fn call(name: &str, arguments: &[f64], softfloat: bool) -> Option<f64> {
+    let x = arguments.first().copied().unwrap_or(0.0);
+    let y = arguments.get(1).copied().unwrap_or(0.0);
+    if softfloat { software(name, x, y) } else { host(name, x, y) }
+}
+
+fn host(name: &str, x: f64, y: f64) -> Option<f64> {
+    Some(match name {
+        "sqrt" => x.sqrt(),
+        "cbrt" => x.cbrt(),
+        "pow" => x.powf(y),
+        "hypot" => x.hypot(y),
+        "exp" => x.exp(),
+        "exp2" => x.exp2(),
+        "log" => x.ln(),
+        "log2" => x.log2(),
+        "log10" => x.log10(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "asin" => x.asin(),
+        "acos" => x.acos(),
+        "atan" => x.atan(),
+        "atan2" => x.atan2(y),
+        "sinh" => x.sinh(),
+        "cosh" => x.cosh(),
+        "tanh" => x.tanh(),
+        "fabs" => x.abs(),
+        "floor" => x.floor(),
+        "ceil" => x.ceil(),
+        "trunc" => x.trunc(),
+        "round" => x.round(),
+        "fmod" => x % y,
+        "fmin" => x.min(y),
+        "fmax" => x.max(y),
+        "copysign" => x.copysign(y),
+        _ => return None,
+    })
+}
+
+fn software(name: &str, x: f64, y: f64) -> Option<f64> {
+    Some(match name {
+        "sqrt" => libm::sqrt(x),
+        "cbrt" => libm::cbrt(x),
+        "pow" => libm::pow(x, y),
+        "hypot" => libm::hypot(x, y),
+        "exp" => libm::exp(x),
+        "exp2" => libm::exp2(x),
+        "log" => libm::log(x),
+        "log2" => libm::log2(x),
+        "log10" => libm::log10(x),
+        "sin" => libm::sin(x),
+        "cos" => libm::cos(x),
+        "tan" => libm::tan(x),
+        "asin" => libm::asin(x),
+        "acos" => libm::acos(x),
+        "atan" => libm::atan(x),
+        "atan2" => libm::atan2(x, y),
+        "sinh" => libm::sinh(x),
+        "cosh" => libm::cosh(x),
+        "tanh" => libm::tanh(x),
+        "fabs" => libm::fabs(x),
+        "floor" => libm::floor(x),
+        "ceil" => libm::ceil(x),
+        "trunc" => libm::trunc(x),
+        "round" => libm::round(x),
+        "fmod" => libm::fmod(x, y),
+        "fmin" => libm::fmin(x, y),
+        "fmax" => libm::fmax(x, y),
+        "copysign" => libm::copysign(x, y),
+        _ => return None,
+    })
+}
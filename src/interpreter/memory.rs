@@ -0,0 +1,358 @@
+// memory.rs: The interpreter's memory, a numbered list of allocations.
+//
+// Each variable, string literal, function and malloc'd block is an
+// allocation of its own, and a pointer is an allocation number and an
+// offset into it. Nothing is ever reused: a freed block, or the locals of a
+// function that has returned, stay where they were, marked released, so a
+// later access through a dangling pointer can say whose memory it was and
+// where it was allocated and freed.
+//
+// Misuse of the heap is recorded as it happens and the program goes on, as
+// under valgrind; the heap report at exit lists it, with the blocks that
+// were never freed. Null and wild pointers stop the program instead, as do
+// accesses outside an allocation unless memcheck is on, when they are
+// recorded too: reads outside it see zeros, and writes are dropped.
+
+use std::fmt;
+
+pub type AllocationId = usize;
+
+/// A pointer: an allocation and a byte offset into it. Allocation 0 is
+/// never made, so the null pointer is allocation 0, offset 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub allocation: AllocationId,
+    pub offset: i64,
+}
+
+impl Address {
+    pub const NULL: Address = Address { allocation: 0, offset: 0 };
+
+    pub fn is_null(&self) -> bool {
+        self.allocation == 0 && self.offset == 0
+    }
+
+    pub fn offset_by(self, bytes: i64) -> Address {
+        Address { allocation: self.allocation, offset: self.offset.wrapping_add(bytes) }
+    }
+
+    /// As the integer a pointer is stored as: the allocation in the high
+    /// 32 bits, the offset in the low
+    pub fn encode(self) -> i64 {
+        ((self.allocation as i64) << 32) | (self.offset as u32 as i64)
+    }
+
+    pub fn decode(bits: i64) -> Address {
+        Address { allocation: (bits >> 32) as u32 as usize, offset: bits as i32 as i64 }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_null() { write!(f, "(nil)") } else { write!(f, "{:#x}", self.encode()) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Global,
+    Stack,
+    Heap,
+    Literal, // string literals, which can't be written
+    Function,
+}
+
+/// Where something happened: the function, and the line if it is known
+#[derive(Debug, Clone, PartialEq)]
+pub struct Site {
+    pub function: String,
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // No function is file scope, where globals are initialized
+        match (self.line, self.function.is_empty()) {
+            (Some(line), false) => write!(f, "line {} in '{}'", line, self.function),
+            (None, false) => write!(f, "'{}'", self.function),
+            (Some(line), true) => write!(f, "line {}", line),
+            (None, true) => write!(f, "file scope"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub region: Region,
+    pub bytes: Vec<u8>,
+    pub name: String, // the variable, function or allocating call
+    pub site: Site,
+    pub released: Option<Site>, // freed, or its function returned
+}
+
+impl Allocation {
+    /// "16-byte block allocated at line 4 in 'main'", "variable 'buf'
+    /// declared at line 9 in 'fill'"
+    pub fn describe(&self) -> String {
+        match self.region {
+            Region::Heap => format!("{}-byte block allocated by {} at {}", self.bytes.len(), self.name, self.site),
+            Region::Stack | Region::Global => format!("variable '{}' declared at {}", self.name, self.site),
+            Region::Literal => format!("string literal at {}", self.site),
+            Region::Function => format!("function '{}'", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemoryIssueKind {
+    DoubleFree,
+    InvalidFree,        // free() of something malloc didn't return
+    UseAfterFree(bool), // a write if true
+    UseAfterReturn(bool),
+    OutOfBounds(bool),
+}
+
+/// Misuse of memory that doesn't stop the program
+#[derive(Debug, Clone)]
+pub struct MemoryIssue {
+    pub kind: MemoryIssueKind,
+    pub at: Site,
+    pub size: usize, // bytes accessed
+    pub offset: i64, // where, for accesses out of bounds
+    pub allocation: AllocationId,
+}
+
+/// An access that can't go on
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemoryError {
+    NullDereference,
+    WildPointer(Address),
+    OutOfBounds { address: Address, size: usize },
+    WriteToLiteral,
+    NotData(String), // reading or writing a function
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    allocations: Vec<Allocation>, // by id, from 1
+    pub issues: Vec<MemoryIssue>,
+    heap_allocations: usize,
+    heap_frees: usize,
+    heap_bytes: usize,
+    pub memcheck: bool, // record accesses out of bounds and go on
+}
+
+impl Memory {
+    pub fn allocate(&mut self, region: Region, size: usize, name: &str, site: Site) -> Address {
+        self.allocate_with(region, vec![0; size], name, site)
+    }
+
+    /// An allocation holding `bytes` from the start, as string literals do
+    pub fn allocate_with(&mut self, region: Region, bytes: Vec<u8>, name: &str, site: Site) -> Address {
+        if region == Region::Heap {
+            self.heap_allocations += 1;
+            self.heap_bytes += bytes.len();
+        }
+        self.allocations.push(Allocation { region, bytes, name: name.to_string(), site, released: None });
+        Address { allocation: self.allocations.len(), offset: 0 }
+    }
+
+    pub fn allocation(&self, id: AllocationId) -> Option<&Allocation> {
+        id.checked_sub(1).and_then(|index| self.allocations.get(index))
+    }
+
+    /// Roughly the bytes this takes up, allocations' contents included
+    pub fn footprint(&self) -> usize {
+        let allocations: usize = self
+            .allocations
+            .iter()
+            .map(|allocation| std::mem::size_of::<Allocation>() + allocation.bytes.len() + allocation.name.len())
+            .sum();
+        allocations + self.issues.len() * std::mem::size_of::<MemoryIssue>()
+    }
+
+    /// Release a local whose function has returned
+    pub fn release(&mut self, address: Address, site: Site) {
+        if let Some(allocation) = self.allocations.get_mut(address.allocation.wrapping_sub(1)) {
+            allocation.released = Some(site);
+        }
+    }
+
+    /// free(): freeing null does nothing; anything but the start of a live
+    /// heap block is an issue, and the block is left alone
+    pub fn free(&mut self, address: Address, at: Site) {
+        if address.is_null() {
+            return;
+        }
+        let kind = match self.allocation(address.allocation) {
+            Some(allocation) if allocation.region == Region::Heap && address.offset == 0 => {
+                if allocation.released.is_some() {
+                    MemoryIssueKind::DoubleFree
+                } else {
+                    self.heap_frees += 1;
+                    self.allocations[address.allocation - 1].released = Some(at);
+                    return;
+                }
+            }
+            _ => MemoryIssueKind::InvalidFree,
+        };
+        self.issues.push(MemoryIssue { kind, at, size: 0, offset: 0, allocation: address.allocation });
+    }
+
+    /// The allocation `size` bytes at `address` fall in, checked, and
+    /// whether they are all inside it; only under memcheck are they not
+    fn checked(&mut self, address: Address, size: usize, write: bool, at: &Site) -> Result<(usize, bool), MemoryError> {
+        if address.allocation == 0 {
+            return Err(MemoryError::NullDereference);
+        }
+        let Some(allocation) = self.allocation(address.allocation) else {
+            return Err(MemoryError::WildPointer(address));
+        };
+        match allocation.region {
+            Region::Function => return Err(MemoryError::NotData(allocation.name.clone())),
+            Region::Literal if write => return Err(MemoryError::WriteToLiteral),
+            _ => {}
+        }
+        let end = address.offset.checked_add(size as i64);
+        let inside = address.offset >= 0 && end.is_some_and(|end| end <= allocation.bytes.len() as i64);
+        let kind = if !inside {
+            if !self.memcheck {
+                return Err(MemoryError::OutOfBounds { address, size });
+            }
+            MemoryIssueKind::OutOfBounds(write)
+        } else if allocation.released.is_some() {
+            match allocation.region {
+                Region::Heap => MemoryIssueKind::UseAfterFree(write),
+                _ => MemoryIssueKind::UseAfterReturn(write),
+            }
+        } else {
+            return Ok((address.allocation - 1, true));
+        };
+        // Once per place in the program, as a loop would repeat it
+        let repeated = self.issues.iter().any(|issue| {
+            issue.kind == kind && issue.at == *at && issue.allocation == address.allocation
+        });
+        if !repeated {
+            let offset = address.offset;
+            self.issues.push(MemoryIssue { kind, at: at.clone(), size, offset, allocation: address.allocation });
+        }
+        Ok((address.allocation - 1, inside))
+    }
+
+    pub fn read(&mut self, address: Address, size: usize, at: &Site) -> Result<Vec<u8>, MemoryError> {
+        let (index, inside) = self.checked(address, size, false, at)?;
+        let bytes = &self.allocations[index].bytes;
+        if inside {
+            let start = address.offset as usize;
+            return Ok(bytes[start..start + size].to_vec());
+        }
+        Ok((0..size as i64)
+            .map(|i| {
+                let at = address.offset.saturating_add(i);
+                usize::try_from(at).ok().and_then(|at| bytes.get(at)).copied().unwrap_or(0)
+            })
+            .collect())
+    }
+
+    pub fn write(&mut self, address: Address, bytes: &[u8], at: &Site) -> Result<(), MemoryError> {
+        let (index, inside) = self.checked(address, bytes.len(), true, at)?;
+        let object = &mut self.allocations[index].bytes;
+        if inside {
+            let start = address.offset as usize;
+            object[start..start + bytes.len()].copy_from_slice(bytes);
+            return Ok(());
+        }
+        // The bytes that do fall inside are written
+        for (i, &byte) in bytes.iter().enumerate() {
+            let at = address.offset.saturating_add(i as i64);
+            if let Some(slot) = usize::try_from(at).ok().and_then(|at| object.get_mut(at)) {
+                *slot = byte;
+            }
+        }
+        Ok(())
+    }
+
+    /// Heap blocks never freed
+    pub fn leaks(&self) -> Vec<&Allocation> {
+        self.allocations
+            .iter()
+            .filter(|allocation| allocation.region == Region::Heap && allocation.released.is_none())
+            .collect()
+    }
+
+    /// One line per issue, with where the memory came from
+    pub fn render_issues(&self) -> String {
+        let mut out = String::new();
+        for issue in &self.issues {
+            let Some(allocation) = self.allocation(issue.allocation) else {
+                continue;
+            };
+            let what = match issue.kind {
+                MemoryIssueKind::DoubleFree => "double free".to_string(),
+                MemoryIssueKind::InvalidFree => "free of memory malloc didn't return".to_string(),
+                MemoryIssueKind::UseAfterFree(write) => {
+                    format!("use after free: {} of {} bytes", if write { "write" } else { "read" }, issue.size)
+                }
+                MemoryIssueKind::UseAfterReturn(write) => format!(
+                    "use after return: {} of {} bytes",
+                    if write { "write" } else { "read" },
+                    issue.size
+                ),
+                MemoryIssueKind::OutOfBounds(write) => out_of_bounds(write, issue.size, issue.offset, allocation),
+            };
+            out.push_str(&format!("Memory Error ({}): {}\n", issue.at, what));
+            out.push_str(&format!("  {}", allocation.describe()));
+            // Where it went, when that is what made the access wrong
+            let released = matches!(
+                issue.kind,
+                MemoryIssueKind::DoubleFree | MemoryIssueKind::UseAfterFree(_) | MemoryIssueKind::UseAfterReturn(_)
+            );
+            let gone = allocation.released.as_ref().filter(|_| released);
+            if let Some(released) = gone {
+                let how = if allocation.region == Region::Heap { "freed at" } else { "gone since the return at" };
+                out.push_str(&format!(", {} {}", how, released));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The heap at program exit: totals, issues, then leaks
+    pub fn report(&self) -> String {
+        let leaks = self.leaks();
+        let leaked: usize = leaks.iter().map(|allocation| allocation.bytes.len()).sum();
+        let mut out = format!(
+            "Heap: {} allocation(s), {} free(s), {} byte(s) allocated; {} byte(s) in {} block(s) in use at exit\n",
+            self.heap_allocations,
+            self.heap_frees,
+            self.heap_bytes,
+            leaked,
+            leaks.len()
+        );
+        out.push_str(&self.render_issues());
+        // One line per place blocks were allocated, in the order they were
+        let mut sites: Vec<(&Allocation, usize, usize)> = Vec::new();
+        for allocation in leaks {
+            match sites.iter_mut().find(|(first, ..)| first.site == allocation.site && first.name == allocation.name) {
+                Some((_, bytes, blocks)) => {
+                    *bytes += allocation.bytes.len();
+                    *blocks += 1;
+                }
+                None => sites.push((allocation, allocation.bytes.len(), 1)),
+            }
+        }
+        for (allocation, bytes, blocks) in sites {
+            out.push_str(&format!(
+                "Leak: {} byte(s) in {} block(s) allocated by {} at {}, never freed\n",
+                bytes, blocks, allocation.name, allocation.site
+            ));
+        }
+        out
+    }
+}
+
+/// "out-of-bounds write of 4 bytes at offset 12 of a 12-byte object"
+pub fn out_of_bounds(write: bool, size: usize, offset: i64, allocation: &Allocation) -> String {
+    let access = if write { "write" } else { "read" };
+    format!("out-of-bounds {} of {} bytes at offset {} of a {}-byte object", access, size, offset, allocation.bytes.len())
+}
@@ -0,0 +1,1113 @@
+// interpreter: Runs a program by walking the control flow graphs of its
+// functions (lower.rs), one statement at a time.
+//
+// Each statement is compiled to stack code (code.rs) up front, and
+// each call pushes a frame holding its function, where it is in the graph,
+// its locals and the code it is partway through. Nothing is on Rust's own
+// stack between statements, so the state of a run is all in the Machine:
+// `step` stops after each statement, and at the start of each function
+// called.
+//
+// Memory is a list of allocations (memory.rs); pointers are allocation and
+// offset, so every access can be checked. The C library functions the
+// interpreter knows are in builtins.rs, and those of math.h in math.rs.
+//
+// Names are looked up at run time, innermost declaration first. Blocks
+// leave no trace in the graph, so a local is in scope from its declaration
+// to the end of the function, and one declared in a block shadows an outer
+// one with the same name for the rest of the function.
+
+pub mod builtins;
+pub mod code;
+pub mod history;
+mod inspect;
+pub mod math;
+pub mod memory;
+mod overflow;
+mod trace;
+pub mod value;
+
+use crate::intrinsics;
+use crate::layout::Layouts;
+use crate::lower::{lower_function, BlockId, ControlFlowGraph, Step, Terminator};
+use crate::parser::ast::*;
+use crate::source::SourceFile;
+use code::{Compiler, Op};
+use memory::{Address, Memory, MemoryError, Region, Site};
+use std::collections::HashMap;
+use std::rc::Rc;
+use value::{CType, Typed, Value};
+
+/// Calls deeper than this are taken to be runaway recursion
+const MAX_FRAMES: usize = 10_000;
+
+/// What a compound literal's object is called in a frame's locals and in
+/// memory reports; no identifier can be spelled like it
+const COMPOUND_LITERAL: &str = "(compound literal)";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub at: Site,
+    pub note: Option<String>, // the memory involved, for a bad access
+}
+
+impl RuntimeError {
+    pub fn render(&self) -> String {
+        let mut out = format!("Runtime Error ({}): {}", self.at, self.message);
+        if let Some(note) = &self.note {
+            out.push_str(&format!("\n  {}", note));
+        }
+        out
+    }
+}
+
+/// How a run stands after a step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Running,
+    Exited(i64),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Value(Typed),
+    Place(Address, CType),
+}
+
+/// A statement's code, and how far it has got
+#[derive(Debug, Clone)]
+struct Evaluation {
+    code: Rc<Vec<Op>>,
+    pc: usize,
+    operands: Vec<Operand>,
+}
+
+/// What running code stopped for
+enum Flow {
+    Done(Option<Typed>), // the value the code left, if any
+    Call(usize, Vec<Typed>),
+    Exit(i64),
+}
+
+/// What a library function did
+pub(crate) enum Returned {
+    Value(Typed),
+    Exit(i64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub address: Address,
+    pub ty: CType,
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub function: usize,
+    pub block: BlockId,
+    pub step: usize, // the terminator once past the block's steps
+    pub locals: Vec<(String, Local)>, // in declaration order
+    evaluation: Option<Evaluation>,
+}
+
+struct Function {
+    definition: FunctionDefinition,
+    graph: ControlFlowGraph,
+    return_type: CType,
+    parameter_types: Vec<CType>, // array parameters as the pointers they are
+    code: Vec<Vec<Rc<Vec<Op>>>>, // by block: each step's, then the terminator's
+    line: Option<usize>,
+}
+
+/// The parts of a program that don't change as it runs
+struct Program {
+    layouts: Layouts,
+    structs: HashMap<String, StructDefinition>,
+    functions: Vec<Function>,
+    by_name: HashMap<String, usize>,
+    source: SourceFile, // for the trace
+}
+
+#[derive(Clone)]
+pub struct Machine {
+    program: Rc<Program>,
+    pub memory: Memory,
+    frames: Vec<Frame>,
+    globals: HashMap<String, Local>,
+    statics: HashMap<(usize, String), Local>, // static locals, by function
+    designators: HashMap<String, Address>,   // functions, defined and library
+    literals: HashMap<Vec<u8>, Address>,
+    output: Vec<u8>,
+    exit_code: Option<i64>,
+    steps: usize, // calls of step that ran something, for History
+    pub tracing: bool, // record each statement run, for take_trace
+    trace: Vec<String>,
+    pub softfloat: bool, // math.h in software rather than the host's libm
+    pub check_overflow: bool, // stop at what overflow.rs finds rather than wrapping
+    pub fast_math: bool, // float arithmetic kept in double until stored, as -ffast-math allows
+}
+
+impl Machine {
+    /// Set up a run of `unit`: globals initialized, and about to run the
+    /// first statement of main
+    pub fn new(unit: &TranslationUnit, source: &str) -> Result<Machine, RuntimeError> {
+        Machine::with_arguments(unit, source, &[])
+    }
+
+    /// Like `new`, with `arguments` after the program's name in main's argv
+    pub fn with_arguments(unit: &TranslationUnit, source: &str, arguments: &[String]) -> Result<Machine, RuntimeError> {
+        let layouts = Layouts::new(unit);
+        let mut structs = HashMap::new();
+        let mut functions = Vec::new();
+        let mut by_name = HashMap::new();
+        for external in &unit.external_declarations {
+            match external {
+                ExternalDeclaration::Struct(definition) => {
+                    structs.insert(definition.name.clone(), definition.clone());
+                }
+                ExternalDeclaration::Function(definition) => {
+                    by_name.insert(definition.name.clone(), functions.len());
+                    let parameter_types = definition
+                        .parameters
+                        .iter()
+                        .map(|parameter| match CType::from_spelling(&parameter.param_type, &layouts) {
+                            CType::Array(element, _) => CType::Pointer(element),
+                            ty => ty,
+                        })
+                        .collect();
+                    let graph = lower_function(&layouts, definition);
+                    let code = graph
+                        .blocks
+                        .iter()
+                        .map(|block| {
+                            let steps = block.steps.iter().map(|step| Compiler::new(&layouts).step(step));
+                            let terminator = Compiler::new(&layouts).terminator(&block.terminator);
+                            steps.chain([terminator]).map(Rc::new).collect()
+                        })
+                        .collect();
+                    functions.push(Function {
+                        definition: definition.clone(),
+                        graph,
+                        return_type: CType::from_spelling(&definition.return_type, &layouts),
+                        parameter_types,
+                        code,
+                        line: definition.name_span.map(|span| span.line),
+                    });
+                }
+                _ => {}
+            }
+        }
+        let mut machine = Machine {
+            program: Rc::new(Program {
+                layouts,
+                structs,
+                functions,
+                by_name,
+                source: SourceFile::new("", source),
+            }),
+            memory: Memory::default(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            statics: HashMap::new(),
+            designators: HashMap::new(),
+            literals: HashMap::new(),
+            output: Vec::new(),
+            exit_code: None,
+            steps: 0,
+            tracing: false,
+            trace: Vec::new(),
+            softfloat: false,
+            check_overflow: false,
+            fast_math: false,
+        };
+        for external in &unit.external_declarations {
+            if let ExternalDeclaration::Variable(declaration) = external {
+                let code = Compiler::new(&machine.program.layouts).step(&Step::Declare(Box::new(declaration.clone())));
+                let mut evaluation = Evaluation { code: Rc::new(code), pc: 0, operands: Vec::new() };
+                if let Flow::Call(..) | Flow::Exit(_) = machine.execute(&mut evaluation)? {
+                    return Err(machine.error("a global's initializer can't call a function".to_string()));
+                }
+            }
+        }
+        let Some(&main) = machine.program.by_name.get("main") else {
+            return Err(machine.error("the program has no main function".to_string()));
+        };
+        let arguments = match machine.program.functions[main].definition.parameters.len() {
+            0 => Vec::new(),
+            _ => machine.main_arguments(arguments),
+        };
+        machine.enter(main, arguments)?;
+        Ok(machine)
+    }
+
+    /// argc and argv for a main that takes them: the program's name, then
+    /// `arguments`, then a null pointer
+    fn main_arguments(&mut self, arguments: &[String]) -> Vec<Typed> {
+        let site = Site { function: String::new(), line: None };
+        let mut strings = vec![self.literal(b"main".to_vec())];
+        for argument in arguments {
+            strings.push(self.literal(argument.as_bytes().to_vec()));
+        }
+        let argv = self.memory.allocate(Region::Global, 8 * (strings.len() + 1), "argv", site.clone());
+        for (index, string) in strings.iter().enumerate() {
+            let _ = self.memory.write(argv.offset_by(8 * index as i64), &string.encode().to_le_bytes(), &site);
+        }
+        vec![Typed::int(strings.len() as i64), Typed::pointer(argv, CType::Pointer(Box::new(CType::Char)))]
+    }
+
+    /// Where the run is: the statement the innermost frame is at
+    pub fn site(&self) -> Site {
+        self.frame_site(self.frames.len().wrapping_sub(1))
+    }
+
+    /// The statement frame `index` is at, main's frame being 0; a caller's
+    /// is the one making the call
+    pub fn frame_site(&self, index: usize) -> Site {
+        let Some(frame) = self.frames.get(index) else {
+            return Site { function: String::new(), line: None };
+        };
+        let function = &self.program.functions[frame.function];
+        let block = &function.graph.blocks[frame.block];
+        let line = match block.lines.get(frame.step) {
+            Some(line) => *line,
+            None => block.terminator_line,
+        };
+        Site { function: function.definition.name.clone(), line }
+    }
+
+    fn error(&self, message: String) -> RuntimeError {
+        RuntimeError { message, at: self.site(), note: None }
+    }
+
+    /// Program output since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Trace lines since the last call, one per statement run or call made
+    pub fn take_trace(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.trace)
+    }
+
+    /// How many times `step` has run something since the start
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn function_name(&self, frame: &Frame) -> &str {
+        &self.program.functions[frame.function].definition.name
+    }
+
+    /// How many of a frame's locals, from the first, are its parameters
+    pub fn parameter_count(&self, frame: &Frame) -> usize {
+        self.program.functions[frame.function].definition.parameters.len()
+    }
+
+    /// The line a function of the program is defined on
+    pub fn function_line(&self, name: &str) -> Option<usize> {
+        self.program.by_name.get(name).and_then(|&function| self.program.functions[function].line)
+    }
+
+    /// Whether some statement starts on a line, so a run can stop there
+    pub fn has_statement_at(&self, line: usize) -> bool {
+        self.program.functions.iter().flat_map(|function| &function.graph.blocks).any(|block| {
+            block.lines.contains(&Some(line)) || block.terminator_line == Some(line)
+        })
+    }
+
+    /// Run to the end of the next statement, or into the function it calls
+    pub fn step(&mut self) -> Result<Status, RuntimeError> {
+        if let Some(code) = self.exit_code {
+            return Ok(Status::Exited(code));
+        }
+        self.steps += 1;
+        let mut started = false;
+        loop {
+            let program = Rc::clone(&self.program);
+            self.skip_jumps();
+            let Some(frame) = self.frames.last_mut() else {
+                return Ok(Status::Exited(self.exit_code.unwrap_or(0)));
+            };
+            if frame.evaluation.is_none() {
+                if started {
+                    return Ok(Status::Running);
+                }
+                let function = &program.functions[frame.function];
+                let code = Rc::clone(&function.code[frame.block][frame.step]);
+                frame.evaluation = Some(Evaluation { code, pc: 0, operands: Vec::new() });
+                started = true;
+            }
+            let mut evaluation = frame.evaluation.take().unwrap_or_else(|| unreachable!());
+            match self.execute(&mut evaluation)? {
+                Flow::Call(function, arguments) => {
+                    if let Some(frame) = self.frames.last_mut() {
+                        frame.evaluation = Some(evaluation);
+                    }
+                    self.enter(function, arguments)?;
+                    return Ok(Status::Running);
+                }
+                Flow::Exit(code) => {
+                    self.exit_code = Some(code);
+                    return Ok(Status::Exited(code));
+                }
+                Flow::Done(result) => {
+                    if self.tracing {
+                        self.trace_statement(result.as_ref());
+                    }
+                    if let Some(code) = self.finish_statement(result)? {
+                        return Ok(Status::Exited(code));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Follow jumps in the innermost frame, so it stops at a statement. A
+    /// loop of nothing but jumps is left to step round.
+    fn skip_jumps(&mut self) {
+        let program = Rc::clone(&self.program);
+        let Some(frame) = self.frames.last_mut().filter(|frame| frame.evaluation.is_none()) else {
+            return;
+        };
+        let blocks = &program.functions[frame.function].graph.blocks;
+        for _ in 0..blocks.len() {
+            let block = &blocks[frame.block];
+            match &block.terminator {
+                Terminator::Goto(target) if frame.step == block.steps.len() => {
+                    frame.block = *target;
+                    frame.step = 0;
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Run until the program exits; its exit code
+    pub fn run(&mut self) -> Result<i64, RuntimeError> {
+        loop {
+            if let Status::Exited(code) = self.step()? {
+                return Ok(code);
+            }
+        }
+    }
+
+    /// Move past a finished statement: on to the next step, or where the
+    /// block's terminator says. The exit code if main returned.
+    fn finish_statement(&mut self, result: Option<Typed>) -> Result<Option<i64>, RuntimeError> {
+        let program = Rc::clone(&self.program);
+        let Some(frame) = self.frames.last_mut() else {
+            return Ok(None);
+        };
+        let block = &program.functions[frame.function].graph.blocks[frame.block];
+        if frame.step < block.steps.len() {
+            frame.step += 1;
+            return Ok(None);
+        }
+        let target = match &block.terminator {
+            Terminator::Goto(target) => *target,
+            Terminator::Branch(_, then_block, else_block) => {
+                if result.is_some_and(|value| value.is_true()) { *then_block } else { *else_block }
+            }
+            Terminator::Switch { cases, default, .. } => {
+                let value = result.map_or(0, |value| value.as_i64());
+                cases.iter().find(|(case, _)| *case == value).map_or(*default, |(_, target)| *target)
+            }
+            Terminator::Return(_) => return self.leave(result),
+        };
+        frame.block = target;
+        frame.step = 0;
+        Ok(None)
+    }
+
+    /// Call a function defined in the program
+    fn enter(&mut self, function: usize, arguments: Vec<Typed>) -> Result<(), RuntimeError> {
+        let program = Rc::clone(&self.program);
+        let Function { definition, parameter_types, line, .. } = &program.functions[function];
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(self.error(format!("stack overflow: more than {} calls deep", MAX_FRAMES)));
+        }
+        if arguments.len() < definition.parameters.len() {
+            return Err(self.error(format!(
+                "'{}' takes {} argument(s) but was given {}",
+                definition.name,
+                definition.parameters.len(),
+                arguments.len()
+            )));
+        }
+        let site = Site { function: definition.name.clone(), line: *line };
+        let mut locals = Vec::new();
+        for ((parameter, ty), argument) in definition.parameters.iter().zip(parameter_types).zip(arguments) {
+            let value = self.converted(&argument, ty)?;
+            let address = self.memory.allocate(Region::Stack, ty.size(&program.layouts), &parameter.name, site.clone());
+            self.store(address, &value)?;
+            locals.push((parameter.name.clone(), Local { address, ty: ty.clone() }));
+        }
+        self.frames.push(Frame { function, block: 0, step: 0, locals, evaluation: None });
+        if self.tracing {
+            self.trace_call();
+        }
+        self.skip_jumps();
+        Ok(())
+    }
+
+    /// Return from the innermost function, with `result` converted to its
+    /// return type; the exit code if it was main
+    fn leave(&mut self, result: Option<Typed>) -> Result<Option<i64>, RuntimeError> {
+        let program = Rc::clone(&self.program);
+        let site = self.site();
+        let Some(frame) = self.frames.last() else {
+            return Ok(None);
+        };
+        let return_type = &program.functions[frame.function].return_type;
+        let result = match result {
+            Some(result) => self.converted(&result, return_type)?,
+            None => Typed::void(),
+        };
+        let frame = self.frames.pop().unwrap_or_else(|| unreachable!());
+        for (_, local) in &frame.locals {
+            let is_static = self.statics.values().any(|static_local| static_local.address == local.address);
+            if !is_static {
+                self.memory.release(local.address, site.clone());
+            }
+        }
+        match self.frames.last_mut().and_then(|caller| caller.evaluation.as_mut()) {
+            Some(evaluation) => {
+                evaluation.operands.push(Operand::Value(result));
+                Ok(None)
+            }
+            None => {
+                // Falling off the end of main returns 0
+                let code = if result.ty == CType::Void { 0 } else { result.as_i64() };
+                self.exit_code = Some(code);
+                Ok(Some(code))
+            }
+        }
+    }
+
+    fn converted(&self, value: &Typed, ty: &CType) -> Result<Typed, RuntimeError> {
+        if self.check_overflow
+            && let Some(problem) = overflow::conversion(value, ty)
+        {
+            return Err(self.error(problem));
+        }
+        value.convert(ty).ok_or_else(|| self.error(format!("can't convert {} to {}", value.ty, ty)))
+    }
+
+    fn memory_error(&self, error: MemoryError, size: usize, write: bool) -> RuntimeError {
+        let access = if write { "write" } else { "read" };
+        let message = match error {
+            MemoryError::NullDereference => format!("null pointer dereference: {} of {} bytes", access, size),
+            MemoryError::WildPointer(address) => format!("{} of {} bytes through invalid pointer {}", access, size, address),
+            MemoryError::OutOfBounds { address, size } => match self.memory.allocation(address.allocation) {
+                Some(allocation) => {
+                    let mut error = self.error(memory::out_of_bounds(write, size, address.offset, allocation));
+                    error.note = Some(allocation.describe());
+                    return error;
+                }
+                None => format!("{} of {} bytes through invalid pointer {}", access, size, address),
+            },
+            MemoryError::WriteToLiteral => "write to a string literal".to_string(),
+            MemoryError::NotData(function) => format!("{} of function '{}' as data", access, function),
+        };
+        self.error(message)
+    }
+
+    pub(crate) fn read_bytes(&mut self, address: Address, size: usize) -> Result<Vec<u8>, RuntimeError> {
+        let site = self.site();
+        self.memory.read(address, size, &site).map_err(|error| self.memory_error(error, size, false))
+    }
+
+    pub(crate) fn write_bytes(&mut self, address: Address, bytes: &[u8]) -> Result<(), RuntimeError> {
+        let site = self.site();
+        self.memory.write(address, bytes, &site).map_err(|error| self.memory_error(error, bytes.len(), true))
+    }
+
+    /// The value of type `ty` at `address`
+    pub fn load(&mut self, address: Address, ty: &CType) -> Result<Typed, RuntimeError> {
+        match ty {
+            CType::Array(element, _) => Ok(Typed::pointer(address, (**element).clone())),
+            CType::Function => Ok(Typed::pointer(address, CType::Function)),
+            CType::Void => Err(self.error("use of a void value".to_string())),
+            _ => {
+                let bytes = self.read_bytes(address, ty.size(&self.program.layouts))?;
+                Ok(Typed::from_bytes(&bytes, ty))
+            }
+        }
+    }
+
+    fn store(&mut self, address: Address, value: &Typed) -> Result<(), RuntimeError> {
+        let bytes = value.to_bytes(&self.program.layouts);
+        self.write_bytes(address, &bytes)
+    }
+
+    /// The place of a string literal, made the first time it is used
+    fn literal(&mut self, bytes: Vec<u8>) -> Address {
+        if let Some(address) = self.literals.get(&bytes) {
+            return *address;
+        }
+        let mut contents = bytes.clone();
+        contents.push(0);
+        let address = self.memory.allocate_with(Region::Literal, contents, "string literal", self.site());
+        self.literals.insert(bytes, address);
+        address
+    }
+
+    /// The address standing for a function, defined or from the library
+    fn designator(&mut self, name: &str) -> Address {
+        if let Some(address) = self.designators.get(name) {
+            return *address;
+        }
+        let site = Site { function: name.to_string(), line: None };
+        let address = self.memory.allocate(Region::Function, 1, name, site);
+        self.designators.insert(name.to_string(), address);
+        address
+    }
+
+    /// The local or global a name refers to here
+    pub fn lookup(&self, name: &str) -> Option<&Local> {
+        let local = self.frames.last().and_then(|frame| {
+            frame.locals.iter().rev().find(|(local, _)| local == name).map(|(_, local)| local)
+        });
+        local.or_else(|| self.globals.get(name))
+    }
+
+    fn member(&self, tag: &str, name: &str) -> Option<(usize, CType)> {
+        let layout = self.program.layouts.struct_layout(tag)?;
+        let offset = layout.member(name)?.offset;
+        let declaration = self.program.structs.get(tag)?.members.iter().find(|member| member.declarator.name == name)?;
+        Some((offset, self.declared_type(declaration)))
+    }
+
+    /// The type of a struct member or global with constant dimensions;
+    /// unknown ones count as none, like a flexible array member
+    fn declared_type(&self, declaration: &VariableDeclaration) -> CType {
+        let lengths: Vec<usize> = declaration
+            .declarator
+            .array_sizes
+            .iter()
+            .map(|size| {
+                size.as_ref()
+                    .and_then(|size| size.integer_constant_value_in(&self.program.layouts))
+                    .map_or(0, |length| length.max(0) as usize)
+            })
+            .collect();
+        CType::declared(&declaration.type_specifier, &declaration.declarator, &lengths, &self.program.layouts)
+    }
+
+    fn pop(&self, operands: &mut Vec<Operand>) -> Result<Operand, RuntimeError> {
+        operands.pop().ok_or_else(|| self.error("internal error: operand stack underflow".to_string()))
+    }
+
+    fn pop_value(&mut self, operands: &mut Vec<Operand>) -> Result<Typed, RuntimeError> {
+        match self.pop(operands)? {
+            Operand::Value(value) => Ok(value),
+            Operand::Place(address, ty) => self.load(address, &ty),
+        }
+    }
+
+    fn pop_place(&self, operands: &mut Vec<Operand>) -> Result<(Address, CType), RuntimeError> {
+        match self.pop(operands)? {
+            Operand::Place(address, ty) => Ok((address, ty)),
+            Operand::Value(_) => Err(self.error("assignment to something that isn't a variable".to_string())),
+        }
+    }
+
+    /// Run code until it ends, calls a function of the program or exits
+    fn execute(&mut self, evaluation: &mut Evaluation) -> Result<Flow, RuntimeError> {
+        let code = Rc::clone(&evaluation.code);
+        let operands = &mut evaluation.operands;
+        while let Some(op) = code.get(evaluation.pc) {
+            evaluation.pc += 1;
+            match op {
+                Op::Push(value) => operands.push(Operand::Value(value.clone())),
+                Op::StringLiteral(bytes) => {
+                    let address = self.literal(bytes.clone());
+                    operands.push(Operand::Place(address, CType::Array(Box::new(CType::Char), bytes.len() + 1)));
+                }
+                Op::Variable(name) => {
+                    let operand = self.variable(name)?;
+                    operands.push(operand);
+                }
+                Op::Load => {
+                    let value = self.pop_value(operands)?;
+                    operands.push(Operand::Value(value));
+                }
+                Op::AddressOf => match self.pop(operands)? {
+                    Operand::Place(address, ty) => operands.push(Operand::Value(Typed::pointer(address, ty))),
+                    Operand::Value(_) => return Err(self.error("can't take the address of a value".to_string())),
+                },
+                Op::Dereference => {
+                    let pointer = self.pop_value(operands)?;
+                    let Some(pointee) = pointer.ty.pointee() else {
+                        return Err(self.error(format!("dereference of a {}, which isn't a pointer", pointer.ty)));
+                    };
+                    operands.push(Operand::Place(pointer.as_address(), pointee.clone()));
+                }
+                Op::Member(name) => {
+                    let operand = self.pop(operands)?;
+                    let tag = match &operand {
+                        Operand::Place(_, CType::Struct(tag)) => tag.clone(),
+                        Operand::Value(Typed { ty: CType::Struct(tag), .. }) => tag.clone(),
+                        _ => return Err(self.error(format!("member '{}' of something that isn't a struct", name))),
+                    };
+                    let Some((offset, ty)) = self.member(&tag, name) else {
+                        return Err(self.error(format!("struct {} has no member '{}'", tag, name)));
+                    };
+                    operands.push(match operand {
+                        Operand::Place(address, _) => Operand::Place(address.offset_by(offset as i64), ty),
+                        Operand::Value(Typed { value: Value::Aggregate(bytes), .. }) => {
+                            let size = ty.size(&self.program.layouts);
+                            Operand::Value(Typed::from_bytes(&bytes[offset..offset + size], &ty))
+                        }
+                        Operand::Value(_) => unreachable!(),
+                    });
+                }
+                Op::Element(index) => {
+                    let (address, ty) = self.pop_place(operands)?;
+                    let element = match &ty {
+                        CType::Array(element, length) if *index < *length => {
+                            let size = element.size(&self.program.layouts);
+                            Operand::Place(address.offset_by((index * size) as i64), (**element).clone())
+                        }
+                        CType::Struct(tag) => {
+                            let member = self
+                                .program
+                                .structs
+                                .get(tag)
+                                .and_then(|definition| definition.members.get(*index))
+                                .map(|member| member.declarator.name.clone());
+                            match member.and_then(|member| self.member(tag, &member)) {
+                                Some((offset, member_type)) => Operand::Place(address.offset_by(offset as i64), member_type),
+                                None => return Err(self.error(format!("too many initializers for {}", ty))),
+                            }
+                        }
+                        _ if *index == 0 && !matches!(ty, CType::Array(..)) => Operand::Place(address, ty),
+                        _ => return Err(self.error(format!("too many initializers for {}", ty))),
+                    };
+                    operands.push(element);
+                }
+                Op::Duplicate => {
+                    let top = self.pop(operands)?;
+                    operands.extend([top.clone(), top]);
+                }
+                Op::Unary(op) => {
+                    let operand = self.pop_value(operands)?;
+                    let result = self.unary(op, &operand)?;
+                    operands.push(Operand::Value(result));
+                }
+                Op::Binary(op) => {
+                    let right = self.pop_value(operands)?;
+                    let left = self.pop_value(operands)?;
+                    let result = self.binary(op, &left, &right)?;
+                    operands.push(Operand::Value(result));
+                }
+                Op::Assign(op) => {
+                    let mut value = self.pop_value(operands)?;
+                    let (address, ty) = self.pop_place(operands)?;
+                    if let Some(op) = op {
+                        let old = self.load(address, &ty)?;
+                        value = self.binary(op, &old, &value)?;
+                    }
+                    let value = self.converted(&value, &ty)?;
+                    self.store(address, &value)?;
+                    operands.push(Operand::Value(value));
+                }
+                Op::Increment { delta, postfix } => {
+                    let (address, ty) = self.pop_place(operands)?;
+                    let old = self.load(address, &ty)?;
+                    let new = self.binary(&BinaryOperator::Plus, &old, &Typed::int(*delta))?;
+                    let new = self.converted(&new, &ty)?;
+                    self.store(address, &new)?;
+                    operands.push(Operand::Value(if *postfix { old } else { new }));
+                }
+                Op::Cast(ty) => {
+                    let value = self.pop_value(operands)?;
+                    let value = self.converted(&value, ty)?;
+                    operands.push(Operand::Value(value));
+                }
+                Op::SizeofExpression(expression) => {
+                    let Some(ty) = self.static_type(expression) else {
+                        return Err(self.error("can't tell the size of the operand of sizeof".to_string()));
+                    };
+                    operands.push(Operand::Value(Typed::long(ty.size(&self.program.layouts) as i64)));
+                }
+                Op::Call(count) => {
+                    let mut arguments = Vec::with_capacity(*count);
+                    for _ in 0..*count {
+                        arguments.push(self.pop_value(operands)?);
+                    }
+                    arguments.reverse();
+                    let callee = self.pop_value(operands)?;
+                    let address = callee.as_address();
+                    let name = match self.memory.allocation(address.allocation) {
+                        Some(allocation) if allocation.region == Region::Function && address.offset == 0 => {
+                            allocation.name.clone()
+                        }
+                        _ => return Err(self.error(format!("call through {}, which isn't a function", address))),
+                    };
+                    if let Some(&function) = self.program.by_name.get(&name) {
+                        return Ok(Flow::Call(function, arguments));
+                    }
+                    match self.call_builtin(&name, arguments)? {
+                        Returned::Value(value) => operands.push(Operand::Value(value)),
+                        Returned::Exit(code) => return Ok(Flow::Exit(code)),
+                    }
+                }
+                Op::Intrinsic(name, count) => {
+                    let mut arguments = Vec::with_capacity(*count);
+                    for _ in 0..*count {
+                        arguments.push(self.pop_value(operands)?);
+                    }
+                    arguments.reverse();
+                    match self.call_intrinsic(name, arguments)? {
+                        Returned::Value(value) => operands.push(Operand::Value(value)),
+                        Returned::Exit(code) => return Ok(Flow::Exit(code)),
+                    }
+                }
+                Op::Pop => {
+                    self.pop(operands)?;
+                }
+                Op::Jump(target) => evaluation.pc = *target,
+                Op::JumpIfZero(target) => {
+                    if !self.pop_value(operands)?.is_true() {
+                        evaluation.pc = *target;
+                    }
+                }
+                Op::JumpIfNonZero(target) => {
+                    if self.pop_value(operands)?.is_true() {
+                        evaluation.pc = *target;
+                    }
+                }
+                Op::Declare { declaration, lengths, end } => {
+                    let mut dynamic = Vec::new();
+                    for _ in lengths.iter().filter(|length| length.is_none()) {
+                        let length = self.pop_value(operands)?.as_i64();
+                        if length <= 0 {
+                            return Err(self.error(format!("variable length array '{}' of length {}", declaration.declarator.name, length)));
+                        }
+                        dynamic.push(length as usize);
+                    }
+                    let lengths: Vec<usize> =
+                        lengths.iter().map(|length| length.unwrap_or_else(|| dynamic.pop().unwrap_or(1))).collect();
+                    if !self.declare(declaration, &lengths) {
+                        evaluation.pc = *end;
+                    }
+                }
+                Op::Initialize => {
+                    let value = self.pop_value(operands)?;
+                    let (address, ty) = self.pop_place(operands)?;
+                    self.initialize(address, &ty, &value)?;
+                }
+                Op::Temporary(ty) => {
+                    let address = self.temporary(ty);
+                    operands.push(Operand::Place(address, ty.clone()));
+                }
+                Op::Unsupported(what) => return Err(self.error(format!("the interpreter doesn't support {}", what))),
+            }
+        }
+        Ok(Flow::Done(operands.pop().and_then(|operand| match operand {
+            Operand::Value(value) => Some(value),
+            Operand::Place(..) => None,
+        })))
+    }
+
+    fn variable(&mut self, name: &str) -> Result<Operand, RuntimeError> {
+        if let Some(local) = self.lookup(name) {
+            return Ok(Operand::Place(local.address, local.ty.clone()));
+        }
+        if self.program.by_name.contains_key(name) || builtins::is_builtin(name) {
+            return Ok(Operand::Place(self.designator(name), CType::Function));
+        }
+        match self.program.layouts.enumerator_value(name) {
+            Some(value) => Ok(Operand::Value(Typed::int(value))),
+            None => Err(self.error(format!("'{}' is not defined", name))),
+        }
+    }
+
+    /// Make the variable a declaration declares; false if its initializer
+    /// shouldn't run, because it is a static local met before or refers
+    /// to a variable defined elsewhere
+    fn declare(&mut self, declaration: &VariableDeclaration, lengths: &[usize]) -> bool {
+        let name = &declaration.declarator.name;
+        let ty = CType::declared(&declaration.type_specifier, &declaration.declarator, lengths, &self.program.layouts);
+        let site = self.site();
+        let Some(frame_index) = self.frames.len().checked_sub(1) else {
+            // At file scope, a declaration of a global met before is the
+            // same object, which only a definition with an initializer sets
+            if self.globals.contains_key(name) {
+                return declaration.initializer.is_some() && !matches!(declaration.storage_class, Some(StorageClass::Extern));
+            }
+            let address = self.memory.allocate(Region::Global, ty.size(&self.program.layouts), name, site);
+            self.globals.insert(name.clone(), Local { address, ty });
+            return true;
+        };
+        let function = self.frames[frame_index].function;
+        let (local, initialize) = match declaration.storage_class {
+            Some(StorageClass::Typedef) => return false,
+            Some(StorageClass::Extern) => match self.globals.get(name) {
+                Some(global) => (global.clone(), false),
+                None => return false,
+            },
+            Some(StorageClass::Static) => match self.statics.get(&(function, name.clone())) {
+                Some(local) => (local.clone(), false),
+                None => {
+                    let address = self.memory.allocate(Region::Global, ty.size(&self.program.layouts), name, site);
+                    let local = Local { address, ty };
+                    self.statics.insert((function, name.clone()), local.clone());
+                    (local, true)
+                }
+            },
+            _ => {
+                let address = self.memory.allocate(Region::Stack, ty.size(&self.program.layouts), name, site);
+                (Local { address, ty }, true)
+            }
+        };
+        self.frames[frame_index].locals.push((name.clone(), local));
+        initialize
+    }
+
+    /// Make a compound literal's object: it lives as long as the function
+    /// it is in, or the whole run at file scope
+    fn temporary(&mut self, ty: &CType) -> Address {
+        let site = self.site();
+        let size = ty.size(&self.program.layouts);
+        match self.frames.last_mut() {
+            Some(frame) => {
+                let address = self.memory.allocate(Region::Stack, size, COMPOUND_LITERAL, site);
+                frame.locals.push((COMPOUND_LITERAL.to_string(), Local { address, ty: ty.clone() }));
+                address
+            }
+            None => self.memory.allocate(Region::Global, size, COMPOUND_LITERAL, site),
+        }
+    }
+
+    /// Store an initializer's value; a string literal fills a char array
+    fn initialize(&mut self, address: Address, ty: &CType, value: &Typed) -> Result<(), RuntimeError> {
+        if let (CType::Array(element, length), Value::Pointer(source)) = (ty, &value.value)
+            && **element == CType::Char
+        {
+            let available = self.memory.allocation(source.allocation).map_or(0, |allocation| allocation.bytes.len());
+            let count = (*length).min(available.saturating_sub(source.offset.max(0) as usize));
+            let bytes = self.read_bytes(*source, count)?;
+            return self.write_bytes(address, &bytes);
+        }
+        let value = self.converted(value, ty)?;
+        self.store(address, &value)
+    }
+
+    fn unary(&self, op: &UnaryOperator, operand: &Typed) -> Result<Typed, RuntimeError> {
+        Ok(match op {
+            UnaryOperator::Not => Typed::int(!operand.is_true() as i64),
+            UnaryOperator::Plus | UnaryOperator::Minus if operand.ty.is_floating() => {
+                let x = operand.as_f64();
+                Typed { value: Value::Float(if matches!(op, UnaryOperator::Minus) { -x } else { x }), ty: operand.ty.clone() }
+            }
+            UnaryOperator::Plus | UnaryOperator::Minus | UnaryOperator::BitNot if operand.ty.is_integer() => {
+                let n = operand.as_i64();
+                if self.check_overflow
+                    && matches!(op, UnaryOperator::Minus)
+                    && let Some(problem) = overflow::negation(n, &promoted(0, &operand.ty, &operand.ty).ty)
+                {
+                    return Err(self.error(problem));
+                }
+                let result = match op {
+                    UnaryOperator::Minus => n.wrapping_neg(),
+                    UnaryOperator::BitNot => !n,
+                    _ => n,
+                };
+                promoted(result, &operand.ty, &operand.ty)
+            }
+            _ => return Err(self.error(format!("operator '{}' can't be applied to a {}", op.symbol(), operand.ty))),
+        })
+    }
+
+    /// A binary operator applied as C applies it: pointer arithmetic
+    /// scaled by the pointee's size, floating point if either side is, and
+    /// integers otherwise, as wide as the wider side and at least an int
+    fn binary(&self, op: &BinaryOperator, left: &Typed, right: &Typed) -> Result<Typed, RuntimeError> {
+        use BinaryOperator::*;
+        let layouts = &self.program.layouts;
+        let comparison = |ordering: std::cmp::Ordering| {
+            let result = match op {
+                Less => ordering.is_lt(),
+                LessEq => ordering.is_le(),
+                Greater => ordering.is_gt(),
+                GreaterEq => ordering.is_ge(),
+                Equals => ordering.is_eq(),
+                NotEquals => ordering.is_ne(),
+                _ => unreachable!(),
+            };
+            Typed::int(result as i64)
+        };
+        let is_comparison = matches!(op, Less | LessEq | Greater | GreaterEq | Equals | NotEquals);
+        // Pointers move by whole elements, so what they point to needs a size
+        let scale = |pointee: &CType| match pointee {
+            CType::Void | CType::Function => Err(self.error(format!("arithmetic on a '{}*', which has no element size", pointee))),
+            _ => Ok(pointee.size(layouts).max(1) as i64),
+        };
+        match (&left.ty, &right.ty) {
+            (CType::Pointer(pointee), other) | (other, CType::Pointer(pointee))
+                if matches!(op, Plus) && other.is_integer() =>
+            {
+                let (pointer, offset) = if left.ty.pointee().is_some() { (left, right) } else { (right, left) };
+                let address = pointer.as_address().offset_by(offset.as_i64().wrapping_mul(scale(pointee)?));
+                Ok(Typed { value: Value::Pointer(address), ty: pointer.ty.clone() })
+            }
+            (CType::Pointer(pointee), other) if matches!(op, Minus) && other.is_integer() => {
+                let address = left.as_address().offset_by(right.as_i64().wrapping_mul(scale(pointee)?).wrapping_neg());
+                Ok(Typed { value: Value::Pointer(address), ty: left.ty.clone() })
+            }
+            (CType::Pointer(pointee), CType::Pointer(_)) if matches!(op, Minus) => {
+                let (a, b) = (left.as_address(), right.as_address());
+                if a.allocation != b.allocation {
+                    return Err(self.error("subtraction of pointers into different objects".to_string()));
+                }
+                Ok(Typed::long((a.offset - b.offset) / scale(pointee)?))
+            }
+            (CType::Pointer(_), _) | (_, CType::Pointer(_)) if is_comparison => {
+                let (a, b) = (left.as_address(), right.as_address());
+                // Addresses in one object compare by offset; any others just
+                // by their encoding, which is as good as any order
+                Ok(comparison(if a.allocation == b.allocation { a.offset.cmp(&b.offset) } else { a.encode().cmp(&b.encode()) }))
+            }
+            (l, r) if (l.is_floating() || r.is_floating()) && (l.is_integer() || l.is_floating()) && (r.is_integer() || r.is_floating()) => {
+                let (a, b) = (left.as_f64(), right.as_f64());
+                // NaN is unordered: every comparison with it is false but !=
+                if is_comparison {
+                    return Ok(match a.partial_cmp(&b) {
+                        Some(ordering) => comparison(ordering),
+                        None => Typed::int(matches!(op, NotEquals) as i64),
+                    });
+                }
+                let result = match op {
+                    Plus => a + b,
+                    Minus => a - b,
+                    Mult => a * b,
+                    Div => a / b,
+                    _ => return Err(self.error(format!("operator '{}' can't be applied to floating point values", op.symbol()))),
+                };
+                let ty = if *l == CType::Double || *r == CType::Double { CType::Double } else { CType::Float };
+                // Each float operation rounds to float, unless -ffast-math
+                // lets the precision of double carry over
+                let result = if ty == CType::Float && !self.fast_math { result as f32 as f64 } else { result };
+                Ok(Typed { value: Value::Float(result), ty })
+            }
+            (l, r) if l.is_integer() && r.is_integer() => {
+                let (a, b) = (left.as_i64(), right.as_i64());
+                if is_comparison {
+                    return Ok(comparison(a.cmp(&b)));
+                }
+                if self.check_overflow
+                    && let Some(problem) = overflow::arithmetic(op, a, b, &promoted(0, l, r).ty)
+                {
+                    return Err(self.error(problem));
+                }
+                let result = match op {
+                    Plus => a.wrapping_add(b),
+                    Minus => a.wrapping_sub(b),
+                    Mult => a.wrapping_mul(b),
+                    Div | Mod if b == 0 => return Err(self.error("division by zero".to_string())),
+                    Div => a.wrapping_div(b),
+                    Mod => a.wrapping_rem(b),
+                    BitAnd => a & b,
+                    BitOr => a | b,
+                    Xor => a ^ b,
+                    LShift => a.wrapping_shl(b as u32),
+                    RShift => a.wrapping_shr(b as u32),
+                    And => (a != 0 && b != 0) as i64,
+                    Or => (a != 0 || b != 0) as i64,
+                    _ => unreachable!(),
+                };
+                Ok(promoted(result, l, r))
+            }
+            (l, r) => Err(self.error(format!("operator '{}' can't be applied to {} and {}", op.symbol(), l, r))),
+        }
+    }
+
+    /// The type of an expression, from the types of the variables it uses,
+    /// without evaluating it
+    fn static_type(&self, expression: &Expression) -> Option<CType> {
+        let layouts = &self.program.layouts;
+        Some(match expression {
+            Expression::Identifier(name) => match self.lookup(name) {
+                Some(local) => local.ty.clone(),
+                None if self.program.by_name.contains_key(name) => CType::Function,
+                None => layouts.enumerator_value(name).map(|_| CType::Int)?,
+            },
+            Expression::Constant(constant) => code::constant_value(constant).ty,
+            Expression::StringLiteral(text) => CType::Array(Box::new(CType::Char), code::decode_escapes(text).len() + 1),
+            Expression::Paren(inner) => self.static_type(inner)?,
+            Expression::UnaryOp(UnaryOperator::Dereference, operand) => element_type(self.static_type(operand)?)?,
+            Expression::UnaryOp(UnaryOperator::AddressOf, operand) => CType::Pointer(Box::new(self.static_type(operand)?)),
+            Expression::UnaryOp(UnaryOperator::Sizeof, _) | Expression::SizeofType(_) => CType::Long,
+            Expression::UnaryOp(UnaryOperator::Not, _) => CType::Int,
+            Expression::UnaryOp(_, operand) | Expression::PostfixOp(operand, _) | Expression::Assignment(operand, _, _) => {
+                self.static_type(operand)?
+            }
+            Expression::ArrayAccess(array, index) => {
+                element_type(self.static_type(array)?).or_else(|| element_type(self.static_type(index)?))?
+            }
+            Expression::MemberAccess(object, member) => match self.static_type(object)? {
+                CType::Struct(tag) => self.member(&tag, member)?.1,
+                _ => return None,
+            },
+            Expression::PointerAccess(pointer, member) => match element_type(self.static_type(pointer)?)? {
+                CType::Struct(tag) => self.member(&tag, member)?.1,
+                _ => return None,
+            },
+            Expression::Cast(type_name, _) => CType::from_type_name(type_name, layouts),
+            Expression::BinaryOp(left, op, right) => {
+                use BinaryOperator::*;
+                match op {
+                    Less | LessEq | Greater | GreaterEq | Equals | NotEquals | And | Or => CType::Int,
+                    _ => {
+                        let (l, r) = (self.static_type(left)?, self.static_type(right)?);
+                        match (&l, &r) {
+                            (CType::Pointer(_) | CType::Array(..), CType::Pointer(_) | CType::Array(..)) => CType::Long,
+                            (CType::Pointer(_) | CType::Array(..), _) => CType::Pointer(Box::new(element_type(l)?)),
+                            (_, CType::Pointer(_) | CType::Array(..)) => CType::Pointer(Box::new(element_type(r)?)),
+                            _ if l == CType::Double || r == CType::Double => CType::Double,
+                            _ if l.is_floating() || r.is_floating() => CType::Float,
+                            _ if l == CType::Long || r == CType::Long => CType::Long,
+                            _ => CType::Int,
+                        }
+                    }
+                }
+            }
+            Expression::Conditional(_, then_value, _) => self.static_type(then_value)?,
+            Expression::FunctionCall(callee, _) => match callee.as_function_name() {
+                Some(name) => match self.program.by_name.get(name) {
+                    Some(&function) => self.program.functions[function].return_type.clone(),
+                    None => match intrinsics::lookup(name) {
+                        Some(intrinsic) => CType::from_spelling(intrinsic.return_type, &self.program.layouts),
+                        None => builtins::return_type(name),
+                    },
+                },
+                None => return None,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// What a pointer points to, or an array holds
+fn element_type(ty: CType) -> Option<CType> {
+    match ty {
+        CType::Pointer(pointee) | CType::Array(pointee, _) => Some(*pointee),
+        _ => None,
+    }
+}
+
+/// An integer result as the wider of two operand types, at least an int
+fn promoted(result: i64, left: &CType, right: &CType) -> Typed {
+    if *left == CType::Long || *right == CType::Long {
+        Typed::long(result)
+    } else {
+        Typed::int(result as i32 as i64)
+    }
+}
@@ -0,0 +1,83 @@
+// overflow.rs: What --check-overflow stops a run for, where C leaves the
+// behavior undefined and the interpreter would otherwise wrap as Rust's
+// wrapping operations do: signed arithmetic whose result doesn't fit its
+// type, shifts by a negative count or by the width of the type or more,
+// and a floating value converted to a type it is out of the range of.
+//
+// Each check is on the operands before the operation, and says what is
+// wrong in the words of the sanitizers. Unsigned types are run as signed
+// ones (value.rs), so their arithmetic is checked as signed too.
+
+use super::value::{CType, Typed, Value};
+use crate::parser::ast::BinaryOperator;
+
+/// Width in bits of the type an integer operation's result has, int or long
+fn result_bits(ty: &CType) -> i128 {
+    if *ty == CType::Long { 64 } else { 32 }
+}
+
+/// What is wrong with `a op b` computed as `ty`, if anything
+pub fn arithmetic(op: &BinaryOperator, a: i64, b: i64, ty: &CType) -> Option<String> {
+    use BinaryOperator::*;
+    let bits = result_bits(ty);
+    let (a, b) = (a as i128, b as i128);
+    let exact = match op {
+        Plus => a + b,
+        Minus => a - b,
+        Mult => a * b,
+        // The quotient; C leaves `%` undefined too where that doesn't fit.
+        // Division by zero is an error with or without the check.
+        Div | Mod if b != 0 => a / b,
+        LShift | RShift if b < 0 => return Some(format!("shift exponent {} is negative", b)),
+        LShift | RShift if b >= bits => {
+            return Some(format!("shift exponent {} is too large for {}-bit type '{}'", b, bits, ty));
+        }
+        LShift if a < 0 => return Some(format!("left shift of negative value {}", a)),
+        LShift => a << b,
+        _ => return None,
+    };
+    if fits(exact, bits) {
+        return None;
+    }
+    Some(match op {
+        LShift => format!("left shift of {} by {} places cannot be represented in type '{}'", a, b, ty),
+        Div | Mod => format!("division of {} by {} cannot be represented in type '{}'", a, b, ty),
+        _ => format!("signed integer overflow: {} {} {} cannot be represented in type '{}'", a, op.symbol(), b, ty),
+    })
+}
+
+/// What is wrong with `-n` computed as `ty`, if anything
+pub fn negation(n: i64, ty: &CType) -> Option<String> {
+    (!fits(-(n as i128), result_bits(ty)))
+        .then(|| format!("negation of {} cannot be represented in type '{}'", n, ty))
+}
+
+/// What is wrong with converting `value` to `ty`, if anything: a floating
+/// value must be in the range of the integer type it is truncated to, or
+/// of float when it is narrowed to one
+pub fn conversion(value: &Typed, ty: &CType) -> Option<String> {
+    let Value::Float(x) = value.value else {
+        return None;
+    };
+    let in_range = match ty {
+        CType::Char | CType::Short | CType::Int | CType::Long => {
+            let bits = match ty {
+                CType::Char => 8,
+                CType::Short => 16,
+                CType::Int => 32,
+                _ => 64,
+            };
+            // Both bounds are powers of two, so exact as doubles; NaN is in
+            // neither
+            let bound = 2f64.powi(bits - 1);
+            x.trunc() >= -bound && x.trunc() < bound
+        }
+        CType::Float => !x.is_finite() || x.abs() <= f32::MAX as f64,
+        _ => true,
+    };
+    (!in_range).then(|| format!("{} is outside the range of representable values of type '{}'", x, ty))
+}
+
+fn fits(n: i128, bits: i128) -> bool {
+    (-(1i128 << (bits - 1))..1i128 << (bits - 1)).contains(&n)
+}
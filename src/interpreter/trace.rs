@@ -0,0 +1,157 @@
+// trace.rs: A line for each statement a run finishes, for following how a
+// program goes.
+//
+// Each line is the statement's source line, the values of the variables it
+// uses once it has run, and where it sent the run: the way a condition
+// went, the value a switch picked a case by, or what a function returned.
+// Calls get a line of their own with the arguments.
+//
+// Looking at values mustn't change the run, so anything reading them
+// records as a memory issue (a freed block, say) is taken back.
+
+use super::value::Typed;
+use super::Machine;
+use crate::lower::{Step, Terminator};
+use crate::parser::ast::*;
+use std::rc::Rc;
+
+/// Values longer than this, like big arrays, are cut short
+const MAX_VALUE: usize = 60;
+
+impl Machine {
+    /// The statement the innermost frame has just run, which left `result`
+    pub(super) fn trace_statement(&mut self, result: Option<&Typed>) {
+        let program = Rc::clone(&self.program);
+        let Some(frame) = self.frames.last() else {
+            return;
+        };
+        let block = &program.functions[frame.function].graph.blocks[frame.block];
+        let mut names = Vec::new();
+        let outcome = match block.steps.get(frame.step) {
+            Some(Step::Declare(declaration)) => {
+                names.push(declaration.declarator.name.clone());
+                for expression in declaration.initializer.iter().flat_map(Initializer::expressions) {
+                    variables(expression, &mut names);
+                }
+                None
+            }
+            Some(Step::Assign(name, value)) => {
+                names.push(name.clone());
+                variables(value, &mut names);
+                None
+            }
+            Some(Step::Evaluate(expression)) => {
+                variables(expression, &mut names);
+                None
+            }
+            None => match &block.terminator {
+                Terminator::Branch(condition, ..) => {
+                    variables(condition, &mut names);
+                    Some(if result.is_some_and(|value| value.is_true()) { "true" } else { "false" }.to_string())
+                }
+                Terminator::Switch { value, .. } => {
+                    variables(value, &mut names);
+                    result.map(|value| format!("case {}", value.as_i64()))
+                }
+                Terminator::Return(value) => {
+                    if let Some(value) = value {
+                        variables(value, &mut names);
+                    }
+                    Some(match result {
+                        Some(result) => format!("returns {}", self.show_value(result)),
+                        None => "returns".to_string(),
+                    })
+                }
+                Terminator::Goto(_) => None,
+            },
+        };
+        let values = self.values(&names);
+        let site = self.site();
+        let text = site
+            .line
+            .and_then(|line| program.source.line(line))
+            .map_or("", |text| text.trim());
+        let mut line = format!("{}: {}", site, text);
+        for part in [values, outcome.unwrap_or_default()] {
+            if !part.is_empty() {
+                line.push_str(&format!("  | {}", part));
+            }
+        }
+        self.trace.push(line);
+    }
+
+    /// A call just made, with the arguments as the parameters got them
+    pub(super) fn trace_call(&mut self) {
+        let program = Rc::clone(&self.program);
+        let Some(frame) = self.frames.last() else {
+            return;
+        };
+        let definition = &program.functions[frame.function].definition;
+        let names: Vec<String> = definition.parameters.iter().map(|parameter| parameter.name.clone()).collect();
+        let values = self.values(&names);
+        self.trace.push(format!("call '{}' ({})", definition.name, values));
+    }
+
+    /// `name = value` for each name that is a variable here
+    fn values(&mut self, names: &[String]) -> String {
+        let issues = self.memory.issues.len();
+        let mut values: Vec<String> = Vec::new();
+        for name in names {
+            if values.iter().any(|value| value.split(" = ").next() == Some(name)) {
+                continue;
+            }
+            let Some(local) = self.lookup(name).cloned() else {
+                continue; // a function or enumerator
+            };
+            let mut value = self.show(local.address, &local.ty);
+            if value.chars().count() > MAX_VALUE {
+                value = format!("{}...", value.chars().take(MAX_VALUE).collect::<String>());
+            }
+            values.push(format!("{} = {}", name, value));
+        }
+        self.memory.issues.truncate(issues);
+        values.join(", ")
+    }
+}
+
+/// The names an expression uses, in order; sizeof's operand isn't run, so
+/// it doesn't count
+fn variables(expression: &Expression, names: &mut Vec<String>) {
+    match expression {
+        Expression::Identifier(name) => names.push(name.clone()),
+        Expression::BinaryOp(left, _, right) | Expression::Assignment(left, _, right) | Expression::ArrayAccess(left, right) => {
+            variables(left, names);
+            variables(right, names);
+        }
+        Expression::UnaryOp(UnaryOperator::Sizeof, _) => {}
+        Expression::UnaryOp(_, operand)
+        | Expression::PostfixOp(operand, _)
+        | Expression::Cast(_, operand)
+        | Expression::Paren(operand)
+        | Expression::MemberAccess(operand, _)
+        | Expression::PointerAccess(operand, _) => variables(operand, names),
+        Expression::Conditional(condition, then_value, else_value) => {
+            variables(condition, names);
+            variables(then_value, names);
+            variables(else_value, names);
+        }
+        Expression::FunctionCall(callee, arguments) => {
+            variables(callee, names);
+            for argument in arguments {
+                variables(argument, names);
+            }
+        }
+        Expression::CompoundLiteral(literal) => {
+            for expression in literal.initializers.iter().flat_map(Initializer::expressions) {
+                variables(expression, names);
+            }
+        }
+        Expression::Generic(controlling, _) => variables(controlling, names),
+        Expression::Constant(_)
+        | Expression::Folded(..)
+        | Expression::StringLiteral(_)
+        | Expression::EncodedStringLiteral(..)
+        | Expression::SizeofType(_)
+        | Expression::StatementExpr(_) => {}
+    }
+}
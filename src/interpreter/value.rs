@@ -0,0 +1,288 @@
+// value.rs: Types and values as the interpreter sees them.
+//
+// Every value carries its C type, so operators do what C does with them
+// (scale pointer arithmetic, convert to double, truncate on store) without
+// the interpreter working out the types of expressions beforehand. There
+// are no unsigned types, as in the type checker.
+
+use super::memory::Address;
+use crate::layout::{Layouts, POINTER_SIZE};
+use crate::parser::ast::*;
+use crate::parser::parse_type_name;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CType {
+    Void,
+    Bool,
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    Pointer(Box<CType>),
+    Array(Box<CType>, usize),
+    Struct(String),
+    Function, // a function designator; calls look the function up by address
+}
+
+impl CType {
+    /// An unsigned type is run as the signed one of its size
+    pub fn from_specifier(type_specifier: &TypeSpecifier) -> CType {
+        match type_specifier {
+            TypeSpecifier::Void => CType::Void,
+            TypeSpecifier::Bool => CType::Bool,
+            TypeSpecifier::Char | TypeSpecifier::UnsignedChar => CType::Char,
+            TypeSpecifier::Short | TypeSpecifier::UnsignedShort => CType::Short,
+            TypeSpecifier::Int | TypeSpecifier::Signed | TypeSpecifier::Unsigned | TypeSpecifier::Enum(_) => CType::Int,
+            TypeSpecifier::Long | TypeSpecifier::UnsignedLong => CType::Long,
+            TypeSpecifier::Float => CType::Float,
+            TypeSpecifier::Double => CType::Double,
+            TypeSpecifier::Struct(tag) => CType::Struct(tag.clone()),
+        }
+    }
+
+    /// The type a declarator gives its name, with the array lengths already
+    /// worked out, outermost first
+    pub fn declared(
+        type_specifier: &TypeSpecifier,
+        declarator: &Declarator,
+        lengths: &[usize],
+        layouts: &Layouts,
+    ) -> CType {
+        let mut ty = match declarator.pointee_type(type_specifier) {
+            Some(pointee) => CType::from_type_name(&pointee, layouts),
+            None => CType::from_specifier(type_specifier),
+        };
+        for _ in 0..declarator.pointer_depth {
+            ty = CType::Pointer(Box::new(ty));
+        }
+        for &length in lengths.iter().rev() {
+            ty = CType::Array(Box::new(ty), length);
+        }
+        ty
+    }
+
+    /// A type name; array lengths that aren't constants count as one
+    /// element, as in layout.rs
+    pub fn from_type_name(type_name: &TypeName, layouts: &Layouts) -> CType {
+        let specifier = type_name.specifier.trim_start_matches("const ");
+        let mut ty = match specifier.strip_prefix("unsigned ").unwrap_or(specifier) {
+            "void" => CType::Void,
+            "bool" | "_Bool" => CType::Bool,
+            "char" => CType::Char,
+            "short" => CType::Short,
+            "long" => CType::Long,
+            "float" => CType::Float,
+            "double" => CType::Double,
+            specifier => match specifier.strip_prefix("struct ") {
+                Some(tag) => CType::Struct(tag.trim().to_string()),
+                None => CType::Int,
+            },
+        };
+        for derivation in type_name.derivations.iter().rev() {
+            ty = match derivation {
+                Derivation::Pointer => CType::Pointer(Box::new(ty)),
+                Derivation::Array(length) => {
+                    let length = length.as_ref().and_then(|length| length.integer_constant_value_in(layouts));
+                    CType::Array(Box::new(ty), length.map_or(1, |length| length.max(0) as usize))
+                }
+                Derivation::Function(..) => CType::Function,
+            };
+        }
+        ty
+    }
+
+    /// A type spelled the way the parser spells parameter and return
+    /// types, like "char*" or "struct node*"
+    pub fn from_spelling(spelling: &str, layouts: &Layouts) -> CType {
+        parse_type_name(spelling).map_or(CType::Int, |type_name| CType::from_type_name(&type_name, layouts))
+    }
+
+    pub fn size(&self, layouts: &Layouts) -> usize {
+        match self {
+            CType::Void | CType::Function => 0,
+            CType::Bool | CType::Char => 1,
+            CType::Short => 2,
+            CType::Int | CType::Float => 4,
+            CType::Long | CType::Double => 8,
+            CType::Pointer(_) => POINTER_SIZE,
+            CType::Array(element, length) => element.size(layouts) * length,
+            CType::Struct(tag) => layouts.struct_layout(tag).map_or(0, |layout| layout.size),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, CType::Bool | CType::Char | CType::Short | CType::Int | CType::Long)
+    }
+
+    pub fn is_floating(&self) -> bool {
+        matches!(self, CType::Float | CType::Double)
+    }
+
+    pub fn pointee(&self) -> Option<&CType> {
+        match self {
+            CType::Pointer(pointee) => Some(pointee),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CType::Void => write!(f, "void"),
+            CType::Bool => write!(f, "bool"),
+            CType::Char => write!(f, "char"),
+            CType::Short => write!(f, "short"),
+            CType::Int => write!(f, "int"),
+            CType::Long => write!(f, "long"),
+            CType::Float => write!(f, "float"),
+            CType::Double => write!(f, "double"),
+            CType::Pointer(pointee) => write!(f, "{}*", pointee),
+            CType::Array(element, length) => write!(f, "{}[{}]", element, length),
+            CType::Struct(tag) => write!(f, "struct {}", tag),
+            CType::Function => write!(f, "function"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Pointer(Address),
+    Aggregate(Vec<u8>), // a struct, copied as a whole
+}
+
+/// A value and its type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Typed {
+    pub value: Value,
+    pub ty: CType,
+}
+
+impl Typed {
+    pub fn int(n: i64) -> Typed {
+        Typed { value: Value::Int(n), ty: CType::Int }
+    }
+
+    pub fn long(n: i64) -> Typed {
+        Typed { value: Value::Int(n), ty: CType::Long }
+    }
+
+    pub fn double(x: f64) -> Typed {
+        Typed { value: Value::Float(x), ty: CType::Double }
+    }
+
+    pub fn void() -> Typed {
+        Typed { value: Value::Int(0), ty: CType::Void }
+    }
+
+    pub fn pointer(address: Address, pointee: CType) -> Typed {
+        Typed { value: Value::Pointer(address), ty: CType::Pointer(Box::new(pointee)) }
+    }
+
+    pub fn is_true(&self) -> bool {
+        match &self.value {
+            Value::Int(n) => *n != 0,
+            Value::Float(x) => *x != 0.0,
+            Value::Pointer(address) => !address.is_null(),
+            Value::Aggregate(_) => true,
+        }
+    }
+
+    /// As an integer, the way a cast to long would have it
+    pub fn as_i64(&self) -> i64 {
+        match &self.value {
+            Value::Int(n) => *n,
+            Value::Float(x) => *x as i64,
+            Value::Pointer(address) => address.encode(),
+            Value::Aggregate(_) => 0,
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match &self.value {
+            Value::Float(x) => *x,
+            _ => self.as_i64() as f64,
+        }
+    }
+
+    pub fn as_address(&self) -> Address {
+        match &self.value {
+            Value::Pointer(address) => *address,
+            _ => Address::decode(self.as_i64()),
+        }
+    }
+
+    /// Converted as by assignment to, or a cast to, `ty`; None if C has no
+    /// such conversion
+    pub fn convert(&self, ty: &CType) -> Option<Typed> {
+        let value = match ty {
+            CType::Void => return Some(Typed::void()),
+            CType::Bool => Value::Int(self.is_true() as i64),
+            CType::Char => Value::Int(self.as_i64() as i8 as i64),
+            CType::Short => Value::Int(self.as_i64() as i16 as i64),
+            CType::Int => Value::Int(self.as_i64() as i32 as i64),
+            CType::Long => Value::Int(self.as_i64()),
+            CType::Float => Value::Float(self.as_f64() as f32 as f64),
+            CType::Double => Value::Float(self.as_f64()),
+            CType::Pointer(_) => match self.value {
+                Value::Float(_) | Value::Aggregate(_) => return None,
+                _ => Value::Pointer(self.as_address()),
+            },
+            CType::Struct(_) => match &self.value {
+                Value::Aggregate(_) if self.ty == *ty => self.value.clone(),
+                _ => return None,
+            },
+            CType::Array(..) | CType::Function => return None,
+        };
+        Some(Typed { value, ty: ty.clone() })
+    }
+
+    /// The bytes of the value stored as its type, little-endian
+    pub fn to_bytes(&self, layouts: &Layouts) -> Vec<u8> {
+        let size = self.ty.size(layouts);
+        match &self.value {
+            Value::Aggregate(bytes) => bytes.clone(),
+            Value::Float(x) if size == 4 => (*x as f32).to_le_bytes().to_vec(),
+            Value::Float(x) => x.to_le_bytes().to_vec(),
+            _ => self.as_i64().to_le_bytes()[..size.min(8)].to_vec(),
+        }
+    }
+
+    /// The value of type `ty` stored in `bytes`
+    pub fn from_bytes(bytes: &[u8], ty: &CType) -> Typed {
+        let integer = || {
+            let mut buffer = [0u8; 8];
+            buffer[..bytes.len().min(8)].copy_from_slice(&bytes[..bytes.len().min(8)]);
+            // Sign-extend from the stored width
+            let shift = 64 - 8 * bytes.len().clamp(1, 8) as u32;
+            (i64::from_le_bytes(buffer) << shift) >> shift
+        };
+        let value = match ty {
+            CType::Float => Value::Float(f32::from_le_bytes(bytes[..4].try_into().unwrap_or([0; 4])) as f64),
+            CType::Double => Value::Float(f64::from_le_bytes(bytes[..8].try_into().unwrap_or([0; 8]))),
+            CType::Pointer(_) => Value::Pointer(Address::decode(integer())),
+            CType::Struct(_) => Value::Aggregate(bytes.to_vec()),
+            _ => Value::Int(integer()),
+        };
+        Typed { value, ty: ty.clone() }
+    }
+}
+
+impl fmt::Display for Typed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            Value::Int(n) if self.ty == CType::Char && (0x20..0x7f).contains(n) => {
+                write!(f, "{} '{}'", n, *n as u8 as char)
+            }
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Pointer(address) => write!(f, "{}", address),
+            Value::Aggregate(bytes) => write!(f, "{{{} bytes}}", bytes.len()),
+        }
+    }
+}
@@ -0,0 +1,204 @@
+// intrinsics.rs: Functions the compiler provides itself, `__builtin_abs`,
+// `__builtin_memcpy` and the like.
+//
+// An intrinsic is called like a library function but is never an external
+// call, and needs no header. The scope analyzer declares every one, so the
+// type checker checks a call's arguments as it would against a prototype.
+// The interpreter compiles a call to one into a single operation instead of
+// a call, and the stack usage report doesn't count one as a call, as gcc
+// expands them in place. `--emit=c` prints them as written, which gcc reads.
+//
+// A plugin adds its own with PluginRegistry::register_intrinsic. Those are
+// expansions: run_passes replaces each call to one with the expression it
+// gives, before any other phase sees the program.
+
+use crate::parser::ast::*;
+use crate::plugins::Intrinsic;
+
+/// The type of an intrinsic, spelled as a prototype would
+#[derive(Debug)]
+pub struct Signature {
+    pub name: &'static str,
+    pub return_type: &'static str,
+    pub parameters: &'static [(&'static str, &'static str)], // (type, name)
+    pub noreturn: bool,
+}
+
+const fn intrinsic(
+    name: &'static str,
+    return_type: &'static str,
+    parameters: &'static [(&'static str, &'static str)],
+) -> Signature {
+    Signature { name, return_type, parameters, noreturn: false }
+}
+
+pub const INTRINSICS: [Signature; 9] = [
+    intrinsic("__builtin_abs", "int", &[("int", "x")]),
+    intrinsic("__builtin_labs", "long", &[("long", "x")]),
+    intrinsic("__builtin_memcpy", "void*", &[("void*", "destination"), ("const void*", "source"), ("long", "size")]),
+    intrinsic("__builtin_memmove", "void*", &[("void*", "destination"), ("const void*", "source"), ("long", "size")]),
+    intrinsic("__builtin_memset", "void*", &[("void*", "destination"), ("int", "value"), ("long", "size")]),
+    intrinsic("__builtin_strlen", "long", &[("const char*", "s")]),
+    // The value, with a hint of what it usually is that only an optimizer uses
+    intrinsic("__builtin_expect", "long", &[("long", "value"), ("long", "expected")]),
+    Signature { noreturn: true, ..intrinsic("__builtin_unreachable", "void", &[]) },
+    Signature { noreturn: true, ..intrinsic("__builtin_trap", "void", &[]) },
+];
+
+pub fn lookup(name: &str) -> Option<&'static Signature> {
+    INTRINSICS.iter().find(|signature| signature.name == name)
+}
+
+pub fn is_intrinsic(name: &str) -> bool {
+    lookup(name).is_some()
+}
+
+/// Replace every call to a registered intrinsic with its expansion
+pub fn expand(unit: &mut TranslationUnit, intrinsics: &[Box<dyn Intrinsic>]) {
+    if intrinsics.is_empty() {
+        return;
+    }
+    let expander = Expander { intrinsics };
+    for external in &mut unit.external_declarations {
+        match external {
+            ExternalDeclaration::Variable(declaration) => expander.declaration(declaration),
+            ExternalDeclaration::Function(function) => expander.statements(&mut function.body),
+            _ => {}
+        }
+    }
+}
+
+struct Expander<'a> {
+    intrinsics: &'a [Box<dyn Intrinsic>],
+}
+
+impl Expander<'_> {
+    fn statements(&self, statements: &mut [Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&self, statement: &mut Statement) {
+        match &mut statement.kind {
+            StatementKind::Declaration(declaration) => self.declaration(declaration),
+            StatementKind::Assignment(_, value)
+            | StatementKind::Expression(value)
+            | StatementKind::Return(Some(value)) => {
+                self.expression(value)
+            }
+            StatementKind::Block(statements) => self.statements(statements),
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementKind::While(value, body)
+            | StatementKind::Switch(value, body)
+            | StatementKind::Case(value, body) => {
+                self.expression(value);
+                self.statement(body);
+            }
+            StatementKind::For(init, condition, update, body) => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                for value in [condition, update].into_iter().flatten() {
+                    self.expression(value);
+                }
+                self.statement(body);
+            }
+            StatementKind::Default(body) | StatementKind::Label(_, body) => self.statement(body),
+            StatementKind::Return(None)
+            | StatementKind::Break
+            | StatementKind::Fallthrough
+            | StatementKind::Goto(_)
+            | StatementKind::Empty => {}
+        }
+    }
+
+    fn declaration(&self, declaration: &mut VariableDeclaration) {
+        for size in declaration.declarator.array_sizes.iter_mut().flatten() {
+            self.expression(size);
+        }
+        if let Some(initializer) = &mut declaration.initializer {
+            self.initializer(initializer);
+        }
+    }
+
+    fn initializer(&self, initializer: &mut Initializer) {
+        match &mut initializer.kind {
+            InitializerKind::Assignment(value) => self.expression(value),
+            InitializerKind::List(items) => {
+                for item in items {
+                    self.initializer(item);
+                }
+            }
+            InitializerKind::Designated(designator, item) => {
+                if let Designator::Array(index) = designator {
+                    self.expression(index);
+                }
+                self.initializer(item);
+            }
+        }
+    }
+
+    /// Arguments are expanded before the call they are passed to
+    fn expression(&self, expression: &mut Expression) {
+        match expression {
+            Expression::FunctionCall(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments.iter_mut() {
+                    self.expression(argument);
+                }
+                let expansion = callee.as_function_name().and_then(|name| {
+                    let intrinsic = self.intrinsics.iter().find(|intrinsic| intrinsic.name() == name)?;
+                    intrinsic.expand(arguments)
+                });
+                if let Some(expansion) = expansion {
+                    *expression = expansion;
+                }
+            }
+            Expression::BinaryOp(left, _, right)
+            | Expression::Assignment(left, _, right)
+            | Expression::ArrayAccess(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.expression(condition);
+                self.expression(then_value);
+                self.expression(else_value);
+            }
+            Expression::UnaryOp(_, operand)
+            | Expression::PostfixOp(operand, _)
+            | Expression::Cast(_, operand)
+            | Expression::Paren(operand)
+            | Expression::MemberAccess(operand, _)
+            | Expression::PointerAccess(operand, _) => self.expression(operand),
+            Expression::StatementExpr(statements) => self.statements(statements),
+            Expression::Generic(controlling, associations) => {
+                self.expression(controlling);
+                for association in associations {
+                    self.expression(&mut association.value);
+                }
+            }
+            Expression::CompoundLiteral(literal) => {
+                for size in literal.array_sizes.iter_mut().flatten() {
+                    self.expression(size);
+                }
+                for item in &mut literal.initializers {
+                    self.initializer(item);
+                }
+            }
+            Expression::Identifier(_)
+            | Expression::Constant(_)
+            | Expression::Folded(..)
+            | Expression::StringLiteral(_)
+            | Expression::EncodedStringLiteral(..)
+            | Expression::SizeofType(_) => {}
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// layout.rs: Sizes, alignments and struct member offsets on a 64-bit target.
+// There is no backend yet, so these are the numbers one would use: members
+// are placed in order at their natural alignment, and a struct is padded to
+// a multiple of its strictest member.
+//
+// A flexible array member (`int data[];` at the end) takes no space of its
+// own. sizeof the struct is the offset it would start at, rounded up to the
+// struct's alignment, and allocations add however many elements they want
+// on top (see StructLayout::size_with_tail).
+//
+// An enum is stored as an int. Enumerators are integer constants, so their
+// values are kept here too, for constant expressions to use.
+//
+// `--emit=layout` prints the layout of every struct in the unit.
+
+use crate::diagnostics::Diagnostic;
+use crate::parser::ast::*;
+use crate::plugins::EmitTarget;
+use std::collections::HashMap;
+
+pub const POINTER_SIZE: usize = 8;
+
+pub fn scalar_size(type_specifier: &TypeSpecifier) -> usize {
+    match type_specifier {
+        TypeSpecifier::Char | TypeSpecifier::UnsignedChar | TypeSpecifier::Bool => 1,
+        TypeSpecifier::Short | TypeSpecifier::UnsignedShort => 2,
+        TypeSpecifier::Int
+        | TypeSpecifier::Float
+        | TypeSpecifier::Signed
+        | TypeSpecifier::Unsigned
+        | TypeSpecifier::Enum(_) => 4,
+        TypeSpecifier::Long | TypeSpecifier::UnsignedLong | TypeSpecifier::Double => 8,
+        TypeSpecifier::Void | TypeSpecifier::Struct(_) => 0,
+    }
+}
+
+pub fn align(offset: usize, alignment: usize) -> usize {
+    if alignment == 0 { offset } else { offset.div_ceil(alignment) * alignment }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberLayout {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize, // zero for a flexible array member
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructLayout {
+    pub members: Vec<MemberLayout>,
+    pub size: usize,
+    pub alignment: usize,
+    /// Element size of a flexible array member, if the struct ends in one
+    pub flexible_element_size: Option<usize>,
+}
+
+impl StructLayout {
+    pub fn member(&self, name: &str) -> Option<&MemberLayout> {
+        self.members.iter().find(|member| member.name == name)
+    }
+
+    /// Bytes to allocate for the struct with `count` elements in its
+    /// flexible array member: the member's offset plus the elements, but
+    /// never less than sizeof the struct itself
+    pub fn size_with_tail(&self, count: usize) -> usize {
+        match (self.flexible_element_size, self.members.last()) {
+            (Some(element), Some(last)) => (last.offset + element * count).max(self.size),
+            _ => self.size,
+        }
+    }
+}
+
+/// Layouts of the structs and values of the enums defined so far; either
+/// has to be defined before it is known, as in C
+#[derive(Debug, Default)]
+pub struct Layouts {
+    structs: HashMap<String, StructLayout>,
+    enums: HashMap<String, Vec<(String, i64)>>, // enumerators by tag, in order
+    enumerators: HashMap<String, i64>,
+}
+
+impl Layouts {
+    pub fn new(unit: &TranslationUnit) -> Layouts {
+        let mut layouts = Layouts::default();
+        for external in &unit.external_declarations {
+            match external {
+                ExternalDeclaration::Struct(definition) => layouts.add(definition),
+                ExternalDeclaration::Enum(definition) => layouts.add_enum(definition),
+                _ => {}
+            }
+        }
+        layouts
+    }
+
+    pub fn add(&mut self, definition: &StructDefinition) {
+        let mut members = Vec::new();
+        let mut offset = 0;
+        let mut alignment = 1;
+        for member in &definition.members {
+            let member_alignment = self.element_alignment(member);
+            offset = align(offset, member_alignment);
+            alignment = alignment.max(member_alignment);
+            let size = if is_flexible_array(member) { 0 } else { self.declaration_size(member) };
+            members.push(MemberLayout { name: member.declarator.name.clone(), offset, size });
+            offset += size;
+        }
+        let size = align(offset, alignment);
+        let flexible_element_size = definition.flexible_member().map(|member| self.element_size(member));
+        self.structs.insert(
+            definition.name.clone(),
+            StructLayout { members, size, alignment, flexible_element_size },
+        );
+    }
+
+    /// Number the enumerators: each is its `= value`, or one more than the
+    /// one before it. A value that isn't an integer constant counts as the
+    /// next number, so the ones after it still get values.
+    pub fn add_enum(&mut self, definition: &EnumDefinition) {
+        let mut members = Vec::new();
+        let mut next = 0i64;
+        for enumerator in &definition.enumerators {
+            let value = enumerator.value.as_ref().and_then(|value| value.integer_constant_value_in(self)).unwrap_or(next);
+            self.enumerators.insert(enumerator.name.clone(), value);
+            members.push((enumerator.name.clone(), value));
+            next = value.wrapping_add(1);
+        }
+        if let Some(tag) = &definition.name {
+            self.enums.insert(tag.clone(), members);
+        }
+    }
+
+    pub fn struct_layout(&self, tag: &str) -> Option<&StructLayout> {
+        self.structs.get(tag)
+    }
+
+    /// The enumerators of an enum and their values, in declaration order
+    pub fn enum_members(&self, tag: &str) -> Option<&[(String, i64)]> {
+        self.enums.get(tag).map(Vec::as_slice)
+    }
+
+    pub fn enumerator_value(&self, name: &str) -> Option<i64> {
+        self.enumerators.get(name).copied()
+    }
+
+    /// Size and alignment of a type specifier on its own; an undefined
+    /// struct has neither
+    pub fn specifier_size(&self, type_specifier: &TypeSpecifier) -> (usize, usize) {
+        match type_specifier {
+            TypeSpecifier::Struct(tag) => {
+                self.structs.get(tag).map_or((0, 0), |layout| (layout.size, layout.alignment))
+            }
+            scalar => (scalar_size(scalar), scalar_size(scalar)),
+        }
+    }
+
+    /// Size of one element of a declaration, i.e. with its array
+    /// dimensions taken off
+    fn element_size(&self, declaration: &VariableDeclaration) -> usize {
+        if declaration.declarator.pointer_depth > 0 {
+            POINTER_SIZE
+        } else {
+            self.specifier_size(&declaration.type_specifier).0
+        }
+    }
+
+    fn element_alignment(&self, declaration: &VariableDeclaration) -> usize {
+        if declaration.declarator.pointer_depth > 0 {
+            POINTER_SIZE
+        } else {
+            self.specifier_size(&declaration.type_specifier).1
+        }
+    }
+
+    pub fn declaration_size(&self, declaration: &VariableDeclaration) -> usize {
+        // Dimensions that aren't integer constants count as one element; the
+        // rest of a variable length array is allocated when it's reached
+        let count: usize = declaration
+            .declarator
+            .array_sizes
+            .iter()
+            .map(|size| match size.as_ref().and_then(|size| size.integer_constant_value_in(self)) {
+                Some(n) if n > 0 => n as usize,
+                _ => 1,
+            })
+            .product();
+        self.element_size(declaration) * count
+    }
+
+    /// sizeof a type name; None for incomplete types (void, undefined
+    /// structs, arrays of unspecified length), variable length arrays and
+    /// functions
+    pub fn size_of(&self, type_name: &TypeName) -> Option<usize> {
+        let mut size = match type_name.specifier.as_str() {
+            "void" => None,
+            specifier => match specifier.strip_prefix("struct ") {
+                Some(tag) => self.struct_layout(tag.trim()).map(|layout| layout.size),
+                None => Some(self.type_name_size(specifier)),
+            },
+        };
+        // From the specifier outwards, so the outermost derivation is last
+        for derivation in type_name.derivations.iter().rev() {
+            size = match derivation {
+                Derivation::Pointer => Some(POINTER_SIZE),
+                Derivation::Array(length) => {
+                    let length = length.as_ref().and_then(|length| length.integer_constant_value_in(self));
+                    match (size, length) {
+                        (Some(size), Some(length)) if length > 0 => size.checked_mul(length as usize),
+                        _ => None,
+                    }
+                }
+                Derivation::Function(..) => None,
+            };
+        }
+        size
+    }
+
+    /// Size of a type spelled like the parser spells parameter types, e.g.
+    /// "char*" or "struct point"
+    pub fn type_name_size(&self, type_name: &str) -> usize {
+        // Signedness doesn't change the size
+        let type_name = type_name.strip_prefix("unsigned ").unwrap_or(type_name);
+        match type_name {
+            _ if type_name.ends_with('*') => POINTER_SIZE,
+            "char" | "bool" | "_Bool" => 1,
+            "short" => 2,
+            "long" | "double" => 8,
+            "void" => 0,
+            _ if type_name.starts_with("enum ") => 4,
+            _ => match type_name.strip_prefix("struct ") {
+                Some(tag) => self.specifier_size(&TypeSpecifier::Struct(tag.trim().to_string())).0,
+                None => 4,
+            },
+        }
+    }
+}
+
+pub struct LayoutEmitter;
+
+impl EmitTarget for LayoutEmitter {
+    fn name(&self) -> &str {
+        "layout"
+    }
+
+    fn emit(&self, unit: &TranslationUnit, _source: &str, _diagnostics: &[Diagnostic]) -> String {
+        let layouts = Layouts::new(unit);
+        let mut out = String::new();
+        for external in &unit.external_declarations {
+            let ExternalDeclaration::Struct(definition) = external else {
+                continue;
+            };
+            let Some(layout) = layouts.struct_layout(&definition.name) else {
+                continue;
+            };
+            out.push_str(&format!(
+                "struct {}: size {}, alignment {}\n",
+                definition.name, layout.size, layout.alignment
+            ));
+            for (member, declaration) in layout.members.iter().zip(&definition.members) {
+                if is_flexible_array(declaration) {
+                    let element = layout.flexible_element_size.unwrap_or(0);
+                    out.push_str(&format!("  offset {}: {}[], flexible, element size {}\n", member.offset, member.name, element));
+                } else {
+                    out.push_str(&format!("  offset {}: {}, size {}\n", member.offset, member.name, member.size));
+                }
+            }
+        }
+        out
+    }
+}
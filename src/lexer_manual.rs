@@ -1,3 +1,11 @@
+use crate::dialect::Feature;
+use crate::lexer_regex::{
+    LexOptions, check_identifier, check_string, describe_char, is_identifier_continue, is_identifier_start,
+    parse_char_literal, parse_float_literal, parse_int_literal,
+};
+use crate::parser::ast::FloatSuffix;
+use crate::source::{self, Spanned, Spliced};
+
 
 
 #[derive(Debug, Clone, PartialEq)]
@@ -10,8 +18,9 @@ pub enum Token {
     Bool,
     Identifier(String),
     IntLit(i64),
-    FloatLit(f64),
+    FloatLit(f64, FloatSuffix),
     StringLit(String),
+    CharLit(char),
     BoolLit(bool),
     Return,
     If,
@@ -39,8 +48,38 @@ pub enum Token {
     Semicolon,
     Quotes,
     Colon,
+    Plus,
+    Minus,
+    Mult,
+    Div,
+    Mod,
+    Xor,
+    Not,
+    BitNot,
+    Question,
+    Dot,
+    Ellipsis,
+    Arrow,
+    PlusPlus,
+    MinusMinus,
+    PlusAssign,
+    MinusAssign,
+    MultAssign,
+    DivAssign,
+    ModAssign,
+    LShiftAssign,
+    RShiftAssign,
+    AndAssign,
+    XorAssign,
+    OrAssign,
+    LShift,
+    RShift,
+    Hash,
     Comment(String),
+    BlockComment(String),
     Error(String),
+    UnterminatedString { line: usize, col: usize }, // where the opening quote is
+    UnterminatedComment { line: usize, col: usize }, // where the `/*` is
 }
 
 fn is_keyword(s: &str) -> Option<Token> {
@@ -49,7 +88,9 @@ fn is_keyword(s: &str) -> Option<Token> {
         "int" => Some(Token::Int),
         "float" => Some(Token::Float),
         "string" => Some(Token::String),
-        "bool" => Some(Token::Bool),
+        "bool" | "_Bool" => Some(Token::Bool),
+        "true" => Some(Token::BoolLit(true)),
+        "false" => Some(Token::BoolLit(false)),
         "return" => Some(Token::Return),
         "if" => Some(Token::If),
         "else" => Some(Token::Else),
@@ -59,35 +100,135 @@ fn is_keyword(s: &str) -> Option<Token> {
     }
 }
 
+/// Operators and delimiters, three-character ones first, then two, then
+/// one, so the longest that matches is taken: `<<=` rather than `<<` and `=`
+const OPERATORS: [(&str, Token); 47] = [
+    ("...", Token::Ellipsis),
+    ("<<=", Token::LShiftAssign),
+    (">>=", Token::RShiftAssign),
+    ("==", Token::EqualsOp),
+    ("!=", Token::NotEqualsOp),
+    ("<=", Token::LessEqOp),
+    (">=", Token::GreaterEqOp),
+    ("&&", Token::AndOp),
+    ("||", Token::OrOp),
+    ("<<", Token::LShift),
+    (">>", Token::RShift),
+    ("++", Token::PlusPlus),
+    ("--", Token::MinusMinus),
+    ("->", Token::Arrow),
+    ("+=", Token::PlusAssign),
+    ("-=", Token::MinusAssign),
+    ("*=", Token::MultAssign),
+    ("/=", Token::DivAssign),
+    ("%=", Token::ModAssign),
+    ("&=", Token::AndAssign),
+    ("^=", Token::XorAssign),
+    ("|=", Token::OrAssign),
+    ("=", Token::AssignOp),
+    ("<", Token::LessOp),
+    (">", Token::GreaterOp),
+    ("&", Token::BitAndOp),
+    ("|", Token::BitOrOp),
+    ("+", Token::Plus),
+    ("-", Token::Minus),
+    ("*", Token::Mult),
+    ("/", Token::Div),
+    ("%", Token::Mod),
+    ("^", Token::Xor),
+    ("!", Token::Not),
+    ("~", Token::BitNot),
+    ("?", Token::Question),
+    (".", Token::Dot),
+    ("#", Token::Hash),
+    ("(", Token::ParenL),
+    (")", Token::ParenR),
+    ("{", Token::BraceL),
+    ("}", Token::BraceR),
+    ("[", Token::BracketL),
+    ("]", Token::BracketR),
+    (",", Token::Comma),
+    (";", Token::Semicolon),
+    (":", Token::Colon),
+];
+
 pub fn lex_manual(input: &str) -> Vec<Token> {
+    lex_manual_options(input, LexOptions::default())
+}
+
+/// Like `lex_manual`, accepting the optional syntax `options` turns on
+pub fn lex_manual_options(input: &str, options: LexOptions) -> Vec<Token> {
+    lex_manual_positions(input, options).into_iter().map(|spanned| spanned.token).collect()
+}
+
+/// Like `lex_manual_options`, each token with its line, column and bytes;
+/// the scan runs over the source spliced, and these are in the original
+pub fn lex_manual_positions(source: &str, options: LexOptions) -> Vec<Spanned<Token>> {
+    let spliced = Spliced::new(source);
+    let input: &str = &spliced.text;
     let mut tokens = Vec::new();
+    let mut ranges = Vec::new();
     let chars: Vec<char> = input.chars().collect();
+    // Byte offset of each char, and of the end
+    let offsets: Vec<usize> = input.char_indices().map(|(offset, _)| offset).chain([input.len()]).collect();
     let mut i = 0;
+    let mut start = 0;
     while i < chars.len() {
+        // Whatever token the last pass made ran from where it started to here
+        if ranges.len() < tokens.len() {
+            ranges.push(offsets[start]..offsets[i]);
+        }
         let c = chars[i];
         if c.is_whitespace() {
             i += 1;
             continue;
         }
+        start = i;
         // Comments
         if c == '/' && i+1 < chars.len() && chars[i+1] == '/' {
-            let start = i;
             i += 2;
             while i < chars.len() && chars[i] != '\n' {
                 i += 1;
             }
-            let comment = &input[start..i];
-            tokens.push(Token::Comment(comment.to_string()));
+            let comment = &input[offsets[start]..offsets[i]];
+            match options.standard.check(Feature::LineComments) {
+                Ok(()) => tokens.push(Token::Comment(comment.to_string())),
+                Err(message) => tokens.push(Token::Error(message)),
+            }
+            continue;
+        }
+        // Block comments, over as many lines as they take; they don't nest,
+        // so a `/*` inside one is taken for a mistake
+        if c == '/' && i+1 < chars.len() && chars[i+1] == '*' {
+            i += 2;
+            let mut nested = false;
+            while i < chars.len() && !(chars[i] == '*' && i+1 < chars.len() && chars[i+1] == '/') {
+                if chars[i] == '/' && i+1 < chars.len() && chars[i+1] == '*' {
+                    nested = true;
+                }
+                i += 1;
+            }
+            if i >= chars.len() {
+                let (line, col) = spliced.line_column(offsets[start]);
+                tokens.push(Token::UnterminatedComment { line, col });
+                continue;
+            }
+            i += 2;
+            let comment = &input[offsets[start]..offsets[i]];
+            if nested {
+                tokens.push(Token::Error(format!("'/*' within block comment {}; block comments don't nest", comment)));
+            } else {
+                tokens.push(Token::BlockComment(comment.to_string()));
+            }
             continue;
         }
         // Identifiers/keywords
-        if c.is_ascii_alphabetic() || c == '_' {
-            let start = i;
+        if is_identifier_start(c) {
             i += 1;
-            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+            while i < chars.len() && is_identifier_continue(chars[i]) {
                 i += 1;
             }
-            let word = &input[start..i];
+            let word = &input[offsets[start]..offsets[i]];
             if let Some(tok) = is_keyword(word) {
                 tokens.push(tok);
             } else {
@@ -95,103 +236,163 @@ pub fn lex_manual(input: &str) -> Vec<Token> {
                 if word.chars().next().unwrap().is_ascii_digit() {
                     tokens.push(Token::Error(format!("Invalid identifier: {}", word)));
                 } else {
-                    tokens.push(Token::Identifier(word.to_string()));
+                    tokens.push(check_identifier(word, options).map_or_else(Token::Error, |()| Token::Identifier(word.to_string())));
                 }
             }
             continue;
         }
-        // Numbers
-        if c.is_ascii_digit() {
-            let start = i;
-            let mut is_float = false;
-            i += 1;
-            while i < chars.len() && chars[i].is_ascii_digit() {
-                i += 1;
-            }
-            if i < chars.len() && chars[i] == '.' {
-                is_float = true;
-                i += 1;
-                while i < chars.len() && chars[i].is_ascii_digit() {
+        // Numbers; `0x` hexadecimal, `0b` binary, `'`/`_` separators between
+        // digits, and floating ones with a fraction, an exponent or both,
+        // then a suffix
+        if c.is_ascii_digit() || (c == '.' && i + 1 < chars.len() && chars[i+1].is_ascii_digit()) {
+            let hex = c == '0' && i + 1 < chars.len() && (chars[i+1] == 'x' || chars[i+1] == 'X');
+            let binary = c == '0'
+                && i + 2 < chars.len()
+                && (chars[i+1] == 'b' || chars[i+1] == 'B')
+                && (chars[i+2] == '0' || chars[i+2] == '1');
+            let is_digit = |ch: char| {
+                if hex {
+                    ch.is_ascii_hexdigit()
+                } else if binary {
+                    ch == '0' || ch == '1'
+                } else {
+                    ch.is_ascii_digit()
+                }
+            };
+            let digits_end = |mut i: usize| {
+                while i < chars.len()
+                    && (is_digit(chars[i])
+                        || ((chars[i] == '\'' || chars[i] == '_') && i+1 < chars.len() && is_digit(chars[i+1])))
+                {
                     i += 1;
                 }
+                i
+            };
+            let mut is_float = c == '.';
+            let radix = hex || binary;
+            i = digits_end(if radix { i + 2 } else { i + 1 });
+            if !radix && !is_float && i < chars.len() && chars[i] == '.' {
+                is_float = true;
+                i = digits_end(i + 1);
             }
-            let num = &input[start..i];
-            if is_float {
-                if let Ok(f) = num.parse() {
-                    tokens.push(Token::FloatLit(f));
-                } else {
-                    tokens.push(Token::Error(format!("Invalid float: {}", num)));
-                }
-            } else {
-                if let Ok(n) = num.parse() {
-                    tokens.push(Token::IntLit(n));
-                } else {
-                    tokens.push(Token::Error(format!("Invalid int: {}", num)));
+            // An `e` with no digits after it stays in the literal, which
+            // parse_float_literal then reports
+            if !radix && i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut exponent = i + 1;
+                if exponent < chars.len() && (chars[exponent] == '+' || chars[exponent] == '-') {
+                    exponent += 1;
                 }
+                is_float = true;
+                i = digits_end(exponent);
             }
+            if is_float && i < chars.len() && FloatSuffix::from_suffix(chars[i]).is_some() {
+                i += 1;
+            }
+            let num = &input[offsets[start]..offsets[i]];
+            let token = if is_float {
+                parse_float_literal(num, options).map(|(f, suffix)| Token::FloatLit(f, suffix))
+            } else {
+                parse_int_literal(num, options).map(Token::IntLit)
+            };
+            tokens.push(token.unwrap_or_else(Token::Error));
             continue;
         }
-        // String literal
+        // String literal, kept as written once its escapes are checked; it
+        // must close on its line, or the rest of the line is taken for it
         if c == '"' {
-            let _start = i;
             i += 1;
-            let mut s = String::new();
-            let mut escape = false;
-            while i < chars.len() {
-                let ch = chars[i];
-                if escape {
-                    match ch {
-                        'n' => s.push('\n'),
-                        't' => s.push('\t'),
-                        '"' => s.push('"'),
-                        '\\' => s.push('\\'),
-                        _ => s.push(ch),
-                    }
-                    escape = false;
-                } else if ch == '\\' {
-                    escape = true;
-                } else if ch == '"' {
-                    i += 1;
-                    break;
-                } else {
-                    s.push(ch);
-                }
+            let body_start = i;
+            while i < chars.len() && chars[i] != '"' && chars[i] != '\n' {
+                i += if chars[i] == '\\' && i+1 < chars.len() && chars[i+1] != '\n' { 2 } else { 1 };
+            }
+            if i >= chars.len() || chars[i] == '\n' {
+                let (line, col) = spliced.line_column(offsets[start]);
+                tokens.push(Token::UnterminatedString { line, col });
+                continue;
+            }
+            let body = &input[offsets[body_start]..offsets[i]];
+            i += 1;
+            let token = check_string(body).map_or_else(
+                |message| Token::Error(format!("{} in string literal \"{}\"", message, body)),
+                |()| Token::StringLit(body.to_string()),
+            );
+            tokens.push(token);
+            continue;
+        }
+        // Character literal; its escapes are decoded as the regex lexer's are
+        if c == '\'' {
+            i += 1;
+            let body_start = i;
+            while i < chars.len() && chars[i] != '\'' && chars[i] != '\n' {
+                i += if chars[i] == '\\' && i+1 < chars.len() && chars[i+1] != '\n' { 2 } else { 1 };
+            }
+            let body = &input[offsets[body_start]..offsets[i]];
+            if i < chars.len() && chars[i] == '\'' {
                 i += 1;
+                tokens.push(parse_char_literal(body).map_or_else(Token::Error, Token::CharLit));
+            } else {
+                tokens.push(Token::Error(format!("unterminated character literal '{}", body)));
             }
-            tokens.push(Token::StringLit(s));
             continue;
         }
         // Operators and delimiters
-        let two = if i+1 < chars.len() { format!("{}{}", chars[i], chars[i+1]) } else { String::new() };
-        match two.as_str() {
-            "==" => { tokens.push(Token::EqualsOp); i += 2; continue; },
-            "!=" => { tokens.push(Token::NotEqualsOp); i += 2; continue; },
-            "<=" => { tokens.push(Token::LessEqOp); i += 2; continue; },
-            ">=" => { tokens.push(Token::GreaterEqOp); i += 2; continue; },
-            "&&" => { tokens.push(Token::AndOp); i += 2; continue; },
-            "||" => { tokens.push(Token::OrOp); i += 2; continue; },
-            _ => {}
-        }
-        match c {
-            '=' => { tokens.push(Token::AssignOp); },
-            '<' => { tokens.push(Token::LessOp); },
-            '>' => { tokens.push(Token::GreaterOp); },
-            '&' => { tokens.push(Token::BitAndOp); },
-            '|' => { tokens.push(Token::BitOrOp); },
-            '*' => { tokens.push(Token::Identifier("*".to_string())); }, // Add star support
-            '(' => { tokens.push(Token::ParenL); },
-            ')' => { tokens.push(Token::ParenR); },
-            '{' => { tokens.push(Token::BraceL); },
-            '}' => { tokens.push(Token::BraceR); },
-            '[' => { tokens.push(Token::BracketL); },
-            ']' => { tokens.push(Token::BracketR); },
-            ',' => { tokens.push(Token::Comma); },
-            ';' => { tokens.push(Token::Semicolon); },
-            ':' => { tokens.push(Token::Colon); },
-            '"' => { tokens.push(Token::Quotes); },
-            _ => { tokens.push(Token::Error(format!("Unknown char: {}", c))); },
+        match OPERATORS.iter().find(|(text, _)| input[offsets[i]..].starts_with(text)) {
+            Some((text, token)) => {
+                tokens.push(token.clone());
+                i += text.len();
+                continue;
+            }
+            None => tokens.push(Token::Error(format!("Unknown char: {}", describe_char(c)))),
         }
         i += 1;
     }
-    tokens
+    if ranges.len() < tokens.len() {
+        ranges.push(offsets[start]..offsets[i]);
+    }
+    let ranges = ranges.into_iter().map(|range| spliced.original_range(range));
+    source::spanned(source, tokens.into_iter().zip(ranges).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OPERATORS, Token, lex_manual};
+
+    fn identifier(name: &str) -> Token {
+        Token::Identifier(name.to_string())
+    }
+
+    #[test]
+    fn each_operator_is_one_token() {
+        for (text, token) in &OPERATORS {
+            assert_eq!(lex_manual(text), vec![token.clone()], "{}", text);
+            // Between operands too, with nothing to set it apart
+            let between = format!("a{}b", text);
+            assert_eq!(lex_manual(&between), vec![identifier("a"), token.clone(), identifier("b")], "{}", between);
+        }
+    }
+
+    #[test]
+    fn longer_operators_are_listed_before_their_prefixes() {
+        for (index, (text, _)) in OPERATORS.iter().enumerate() {
+            let shadowed = OPERATORS[..index].iter().find(|(earlier, _)| text.starts_with(earlier));
+            assert_eq!(shadowed, None, "{} comes after a prefix of it", text);
+        }
+    }
+
+    #[test]
+    fn operators_run_together_are_taken_longest_first() {
+        let cases: [(&str, &[Token]); 8] = [
+            ("a+++b", &[identifier("a"), Token::PlusPlus, Token::Plus, identifier("b")]),
+            ("a---b", &[identifier("a"), Token::MinusMinus, Token::Minus, identifier("b")]),
+            ("a-->b", &[identifier("a"), Token::MinusMinus, Token::GreaterOp, identifier("b")]),
+            ("a>>>=b", &[identifier("a"), Token::RShift, Token::GreaterEqOp, identifier("b")]),
+            ("a<<<=b", &[identifier("a"), Token::LShift, Token::LessEqOp, identifier("b")]),
+            ("a&&&b", &[identifier("a"), Token::AndOp, Token::BitAndOp, identifier("b")]),
+            ("a....b", &[identifier("a"), Token::Ellipsis, Token::Dot, identifier("b")]),
+            ("p->-q", &[identifier("p"), Token::Arrow, Token::Minus, identifier("q")]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(lex_manual(text), expected, "{}", text);
+        }
+    }
 }
@@ -0,0 +1,153 @@
+// borrowed.rs: The regex lexer's tokens, borrowing their text from the source.
+//
+// An owned token holds its identifier, string literal or comment as a
+// String of its own. These borrow it instead, so lexing only allocates for
+// errors; the owned lexer is this one with `to_owned` applied to each
+// token, and a caller that only looks at tokens as they go by (statistics,
+// highlighting) can skip that on large inputs. They borrow from the source
+// spliced (see source::Spliced), which is the source itself unless it has
+// line continuations.
+
+use super::{LexOptions, TOKEN_REGEX, check_identifier, check_string, plain_token};
+use crate::dialect::Feature;
+use crate::parser::ast::LiteralEncoding;
+use crate::source::Spliced;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token<'src> {
+    Identifier(&'src str),
+    StringLit(&'src str), // the body as written, as the owned token keeps it
+    EncodedStringLit(LiteralEncoding, &'src str),
+    Comment(&'src str),
+    BlockComment(&'src str),
+    Preprocessor(&'src str),
+    Pragma(&'src str),
+    Plain(super::Token), // any other token, which holds no text: keywords, punctuation, numbers, errors
+}
+
+impl Token<'_> {
+    /// The owned token, its text copied out of the source
+    pub fn to_owned(&self) -> super::Token {
+        match self {
+            Token::Identifier(name) => super::Token::Identifier(name.to_string()),
+            Token::StringLit(body) => super::Token::StringLit(body.to_string()),
+            Token::EncodedStringLit(encoding, body) => super::Token::EncodedStringLit(*encoding, body.to_string()),
+            Token::Comment(text) => super::Token::Comment(text.to_string()),
+            Token::BlockComment(text) => super::Token::BlockComment(text.to_string()),
+            Token::Preprocessor(text) => super::Token::Preprocessor(text.to_string()),
+            Token::Pragma(text) => super::Token::Pragma(text.to_string()),
+            Token::Plain(token) => token.clone(),
+        }
+    }
+
+    /// Broad class of the token, as `super::Token::category` gives it
+    pub fn category(&self) -> &'static str {
+        match self {
+            Token::Identifier(_) => "identifier",
+            Token::StringLit(_) | Token::EncodedStringLit(..) => "string",
+            Token::Comment(_) | Token::BlockComment(_) => "comment",
+            Token::Preprocessor(_) | Token::Pragma(_) => "preprocessor",
+            Token::Plain(token) => token.category(),
+        }
+    }
+}
+
+/// Every token of a spliced source, borrowing from it, with its byte range
+/// in the original
+pub fn lex_borrowed<'src>(spliced: &'src Spliced<'_>, options: LexOptions) -> Vec<(Token<'src>, Range<usize>)> {
+    Tokens::new(spliced, options).collect()
+}
+
+/// The tokens of a spliced source one at a time, each with its byte range
+/// in the original
+pub struct Tokens<'src> {
+    spliced: &'src Spliced<'src>,
+    options: LexOptions,
+    pos: usize,
+}
+
+impl<'src> Tokens<'src> {
+    pub fn new(spliced: &'src Spliced<'_>, options: LexOptions) -> Self {
+        Tokens { spliced, options, pos: 0 }
+    }
+}
+
+impl<'src> Iterator for Tokens<'src> {
+    type Item = (Token<'src>, Range<usize>);
+
+    fn next(&mut self) -> Option<(Token<'src>, Range<usize>)> {
+        let (token, span) = next_token(self.spliced, &mut self.pos, self.options)?;
+        Some((token, self.spliced.original_range(span)))
+    }
+}
+
+/// The token at or after `*pos` in the spliced text, with its bytes there,
+/// moving `*pos` past it
+pub(super) fn next_token<'src>(
+    spliced: &'src Spliced<'_>,
+    pos: &mut usize,
+    options: LexOptions,
+) -> Option<(Token<'src>, Range<usize>)> {
+    let input: &'src str = &spliced.text;
+    {
+        while *pos < input.len() {
+            let start = *pos;
+            // One search gives both the match and the group it is
+            let Some(caps) = TOKEN_REGEX.captures_at(input, start) else {
+                *pos = input.len();
+                let error = super::Token::Error(format!("Unknown sequence at {}", spliced.original_offset(start)));
+                return Some((Token::Plain(error), start..input.len()));
+            };
+            let m = caps.get(0).expect("group 0 is the whole match");
+            let s = m.as_str();
+            *pos = m.end();
+            let token = if caps.name("ws").is_some() {
+                continue;
+            } else if caps.name("comment").is_some() {
+                match options.standard.check(Feature::LineComments) {
+                    Ok(()) => Token::Comment(s),
+                    Err(message) => Token::Plain(super::Token::Error(message)),
+                }
+            } else if caps.name("blockcomment").is_some() {
+                Token::BlockComment(s)
+            } else if let Some(pragma) = caps.name("pragma") {
+                Token::Pragma(pragma.as_str().trim_end())
+            } else if let Some(pp) = caps.name("preprocessor") {
+                Token::Preprocessor(pp.as_str())
+            } else if let Some(id) = caps.name("identifier") {
+                match check_identifier(id.as_str(), options) {
+                    Ok(()) => Token::Identifier(id.as_str()),
+                    Err(message) => Token::Plain(super::Token::Error(message)),
+                }
+            } else if caps.name("stringlit").is_some() {
+                // Kept as written, for printing the tree back as C; its
+                // escapes are checked here and decoded to bytes where it is
+                // used, since a byte escape needn't be part of a character
+                let body = &s[1..s.len() - 1];
+                match check_string(body) {
+                    Ok(()) => Token::StringLit(body),
+                    Err(message) => {
+                        Token::Plain(super::Token::Error(format!("{} in string literal \"{}\"", message, body)))
+                    }
+                }
+            } else if caps.name("encodedstringlit").is_some() {
+                let encoding = LiteralEncoding::from_prefix(&caps["stringprefix"]).expect("prefix matched by the regex");
+                let body = caps.name("stringbody").expect("part of the literal").as_str();
+                match check_string(body) {
+                    Ok(()) => Token::EncodedStringLit(encoding, body),
+                    Err(message) => Token::Plain(super::Token::Error(format!(
+                        "{} in string literal {}\"{}\"",
+                        message,
+                        encoding.prefix(),
+                        body
+                    ))),
+                }
+            } else {
+                Token::Plain(plain_token(&caps, spliced, start, options))
+            };
+            return Some((token, m.start()..m.end()));
+        }
+        None
+    }
+}
@@ -0,0 +1,793 @@
+
+
+use crate::dialect::Standard;
+use crate::parser::ast::{FloatSuffix, LiteralEncoding};
+use crate::source::{self, Spanned, Spliced};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::fmt;
+use std::ops::Range;
+
+pub mod borrowed;
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(dead_code)]
+pub enum Token {
+    Function,
+    Int,
+    Float,
+    String,
+    Bool,
+    Identifier(String),
+    IntLit(i64),
+    FloatLit(f64, FloatSuffix),
+    StringLit(String),                         // the body as written, its escapes checked
+    EncodedStringLit(LiteralEncoding, String), // L"...", u8"...", u"...", U"..."
+    CharLit(char),                             // 'x', '\n'
+    EncodedCharLit(LiteralEncoding, char),     // L'x', u8'x', u'x', U'x'
+    BoolLit(bool),
+    Return,
+    If,
+    Else,
+    While,
+    For,
+    AssignOp,
+    EqualsOp,
+    NotEqualsOp,
+    LessEqOp,
+    GreaterEqOp,
+    LessOp,
+    GreaterOp,
+    AndOp,
+    OrOp,
+    BitAndOp,
+    BitOrOp,
+    ParenL,
+    ParenR,
+    BraceL,
+    BraceR,
+    BracketL,
+    BracketR,
+    Comma,
+    Semicolon,
+    Quotes,
+    Colon,
+    Plus,
+    Minus,
+    Mult,
+    Div,
+    Mod,
+    Xor,
+    Not,
+    BitNot,
+    Question,
+    Dot,
+    Ellipsis,
+    Arrow,
+    PlusPlus,
+    MinusMinus,
+    PlusAssign,
+    MinusAssign,
+    MultAssign,
+    DivAssign,
+    ModAssign,
+    LShiftAssign,
+    RShiftAssign,
+    AndAssign,
+    XorAssign,
+    OrAssign,
+    LShift,
+    RShift,
+    Hash,
+    Comment(String),
+    BlockComment(String),
+    Preprocessor(String),
+    Pragma(String), // a whole `#pragma ...` line, which the parser skips
+    Enum,
+    Struct,
+    Typedef,
+    Static,
+    Const,
+    Volatile,
+    Restrict,
+    Inline,
+    Noreturn,
+    Extern,
+    Auto,
+    Register,
+    Case,
+    Default,
+    Break,
+    Continue,
+    Goto,
+    Switch,
+    Do,
+    Union,
+    Signed,
+    Unsigned,
+    Short,
+    Long,
+    Double,
+    Char,
+    Void,
+    Error(String),
+    UnterminatedString { line: usize, col: usize }, // where the opening quote is
+    UnterminatedComment { line: usize, col: usize }, // where the `/*` is
+}
+
+impl fmt::Display for Token {
+    /// Source spelling of the token, used in diagnostics
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Token::Function => "fn",
+            Token::Int => "int",
+            Token::Float => "float",
+            Token::String => "string",
+            Token::Bool => "bool",
+            Token::Identifier(s) => return write!(f, "{}", s),
+            Token::IntLit(n) => return write!(f, "{}", n),
+            Token::FloatLit(x, suffix) => return write!(f, "{}{}", x, suffix.suffix()),
+            Token::StringLit(s) => return write!(f, "\"{}\"", s),
+            Token::EncodedStringLit(encoding, s) => return write!(f, "{}\"{}\"", encoding.prefix(), s),
+            Token::CharLit(c) => return write!(f, "'{}'", c.escape_default()),
+            Token::EncodedCharLit(encoding, c) => return write!(f, "{}'{}'", encoding.prefix(), c.escape_default()),
+            Token::BoolLit(b) => return write!(f, "{}", b),
+            Token::Return => "return",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::While => "while",
+            Token::For => "for",
+            Token::AssignOp => "=",
+            Token::EqualsOp => "==",
+            Token::NotEqualsOp => "!=",
+            Token::LessEqOp => "<=",
+            Token::GreaterEqOp => ">=",
+            Token::LessOp => "<",
+            Token::GreaterOp => ">",
+            Token::AndOp => "&&",
+            Token::OrOp => "||",
+            Token::BitAndOp => "&",
+            Token::BitOrOp => "|",
+            Token::ParenL => "(",
+            Token::ParenR => ")",
+            Token::BraceL => "{",
+            Token::BraceR => "}",
+            Token::BracketL => "[",
+            Token::BracketR => "]",
+            Token::Comma => ",",
+            Token::Semicolon => ";",
+            Token::Quotes => "\"",
+            Token::Colon => ":",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Mult => "*",
+            Token::Div => "/",
+            Token::Mod => "%",
+            Token::Xor => "^",
+            Token::Not => "!",
+            Token::BitNot => "~",
+            Token::Question => "?",
+            Token::Dot => ".",
+            Token::Ellipsis => "...",
+            Token::Arrow => "->",
+            Token::PlusPlus => "++",
+            Token::MinusMinus => "--",
+            Token::PlusAssign => "+=",
+            Token::MinusAssign => "-=",
+            Token::MultAssign => "*=",
+            Token::DivAssign => "/=",
+            Token::ModAssign => "%=",
+            Token::LShiftAssign => "<<=",
+            Token::RShiftAssign => ">>=",
+            Token::AndAssign => "&=",
+            Token::XorAssign => "^=",
+            Token::OrAssign => "|=",
+            Token::LShift => "<<",
+            Token::RShift => ">>",
+            Token::Hash => "#",
+            Token::Comment(s) | Token::BlockComment(s) | Token::Preprocessor(s) | Token::Pragma(s) => return write!(f, "{}", s),
+            Token::Enum => "enum",
+            Token::Struct => "struct",
+            Token::Typedef => "typedef",
+            Token::Static => "static",
+            Token::Const => "const",
+            Token::Volatile => "volatile",
+            Token::Restrict => "restrict",
+            Token::Inline => "inline",
+            Token::Noreturn => "_Noreturn",
+            Token::Extern => "extern",
+            Token::Auto => "auto",
+            Token::Register => "register",
+            Token::Case => "case",
+            Token::Default => "default",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Goto => "goto",
+            Token::Switch => "switch",
+            Token::Do => "do",
+            Token::Union => "union",
+            Token::Signed => "signed",
+            Token::Unsigned => "unsigned",
+            Token::Short => "short",
+            Token::Long => "long",
+            Token::Double => "double",
+            Token::Char => "char",
+            Token::Void => "void",
+            Token::Error(s) => return write!(f, "{}", s),
+            Token::UnterminatedString { .. } => "\"",
+            Token::UnterminatedComment { .. } => "/*",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl Token {
+    /// What a token the lexer couldn't make sense of says is wrong; None
+    /// for the others
+    pub fn lex_error(&self) -> Option<String> {
+        match self {
+            Token::Error(message) => Some(message.clone()),
+            Token::UnterminatedString { line, col } => {
+                Some(format!("unterminated string literal starting at line {}, column {}", line, col))
+            }
+            Token::UnterminatedComment { line, col } => {
+                Some(format!("unterminated block comment starting at line {}, column {}", line, col))
+            }
+            _ => None,
+        }
+    }
+
+    /// Broad class of the token, shared by highlighting and statistics
+    pub fn category(&self) -> &'static str {
+        match self {
+            Token::Int | Token::Float | Token::String | Token::Bool | Token::Char | Token::Void
+            | Token::Short | Token::Long | Token::Double | Token::Signed | Token::Unsigned
+            | Token::Struct | Token::Enum | Token::Union => "type",
+            Token::Function | Token::Return | Token::If | Token::Else | Token::While | Token::For
+            | Token::Typedef | Token::Static | Token::Const | Token::Volatile | Token::Restrict
+            | Token::Inline | Token::Noreturn | Token::Extern
+            | Token::Auto | Token::Register | Token::Case | Token::Default | Token::Break
+            | Token::Continue | Token::Goto | Token::Switch | Token::Do => "keyword",
+            Token::Identifier(_) => "identifier",
+            Token::IntLit(_) | Token::FloatLit(..) | Token::BoolLit(_) => "number",
+            Token::StringLit(_)
+            | Token::EncodedStringLit(..)
+            | Token::CharLit(_)
+            | Token::EncodedCharLit(..)
+            | Token::Quotes => "string",
+            Token::Comment(_) | Token::BlockComment(_) => "comment",
+            Token::Preprocessor(_) | Token::Pragma(_) | Token::Hash => "preprocessor",
+            Token::Error(_) | Token::UnterminatedString { .. } | Token::UnterminatedComment { .. } => "error",
+            Token::ParenL | Token::ParenR | Token::BraceL | Token::BraceR | Token::BracketL
+            | Token::BracketR | Token::Comma | Token::Semicolon | Token::Colon => "punctuation",
+            _ => "operator",
+        }
+    }
+}
+
+/// Parse the hex digits of a `\u` (4 digits) or `\U` (8 digits) escape
+fn universal_name(digits: &str) -> Result<char, String> {
+    let value = u32::from_str_radix(digits, 16).map_err(|_| format!("invalid universal character name '{}'", digits))?;
+    // Surrogate halves aren't characters
+    char::from_u32(value).ok_or_else(|| format!("universal character name {:04X} is not a valid character", value))
+}
+
+/// What an escape sequence stands for: a byte (`\x41`, `\101`) or a
+/// character (`\n`, `\u00e9`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escape {
+    Byte(u8),
+    Char(char),
+}
+
+/// The escape sequence at the start of `rest`, the text after a backslash,
+/// and how many bytes of `rest` it takes. Hex escapes take every hex digit
+/// that follows, octal ones up to three digits, as in C.
+pub fn decode_escape(rest: &str) -> Result<(Escape, usize), String> {
+    let Some(kind) = rest.chars().next() else {
+        return Err("'\\' at the end of a literal".to_string());
+    };
+    let simple = match kind {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'f' => Some('\x0c'),
+        'v' => Some('\x0b'),
+        '\\' | '\'' | '"' | '?' => Some(kind),
+        _ => None,
+    };
+    if let Some(c) = simple {
+        return Ok((Escape::Char(c), 1));
+    }
+    match kind {
+        'x' => {
+            let digits = rest[1..].chars().take_while(char::is_ascii_hexdigit).count();
+            if digits == 0 {
+                return Err("'\\x' needs at least one hex digit".to_string());
+            }
+            let value = rest[1..=digits].chars().try_fold(0u32, |value, digit| {
+                let value = value * 16 + digit.to_digit(16)?;
+                (value <= 0xFF).then_some(value)
+            });
+            match value {
+                Some(value) => Ok((Escape::Byte(value as u8), 1 + digits)),
+                None => Err(format!("escape '\\{}' is out of range for a byte", &rest[..=digits])),
+            }
+        }
+        '0'..='7' => {
+            let digits = rest.chars().take(3).take_while(|c| c.is_digit(8)).count();
+            let value = u32::from_str_radix(&rest[..digits], 8).expect("octal digits");
+            match u8::try_from(value) {
+                Ok(value) => Ok((Escape::Byte(value), digits)),
+                Err(_) => Err(format!("escape '\\{}' is out of range for a byte", &rest[..digits])),
+            }
+        }
+        'u' | 'U' => {
+            let width = if kind == 'u' { 4 } else { 8 };
+            let digits = rest.get(1..1 + width).unwrap_or(&rest[1..]);
+            if digits.len() < width || !digits.chars().all(|d| d.is_ascii_hexdigit()) {
+                return Err(format!("'\\{}' needs {} hex digits", kind, width));
+            }
+            Ok((Escape::Char(universal_name(digits)?), 1 + width))
+        }
+        other => Err(format!("unknown escape sequence '\\{}'", other)),
+    }
+}
+
+/// Check a string literal's escapes without decoding them, as the lexers
+/// do: the body is kept as written
+pub fn check_string(body: &str) -> Result<(), String> {
+    let mut rest = body;
+    while let Some(backslash) = rest.find('\\') {
+        let (_, length) = decode_escape(&rest[backslash + 1..])?;
+        rest = &rest[backslash + 1 + length..];
+    }
+    Ok(())
+}
+
+/// The bytes a string literal's body stands for, its escapes decoded and
+/// its other characters in UTF-8
+pub fn decode_string(body: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(backslash) = rest.find('\\') {
+        bytes.extend_from_slice(&rest.as_bytes()[..backslash]);
+        let (escape, length) = decode_escape(&rest[backslash + 1..])?;
+        match escape {
+            Escape::Byte(byte) => bytes.push(byte),
+            Escape::Char(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+        rest = &rest[backslash + 1 + length..];
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+    Ok(bytes)
+}
+
+/// A string literal's body as text, escapes decoded; a byte escape that
+/// isn't part of a UTF-8 character becomes U+FFFD, so this is for reading
+/// the literal, not for the bytes it stands for
+pub fn decode_string_text(body: &str) -> Result<String, String> {
+    decode_string(body).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The character a plain character literal stands for, given what is
+/// between its quotes; a `char` holds one byte, so it must be ASCII or a
+/// byte escape
+pub fn parse_char_literal(body: &str) -> Result<char, String> {
+    let literal = format!("'{}'", body);
+    match decode_char(body, &literal)? {
+        Escape::Byte(byte) => Ok(char::from(byte)),
+        Escape::Char(c) if c.is_ascii() => Ok(c),
+        Escape::Char(_) => Err(format!("character literal {} doesn't fit in a char; use L'...'", literal)),
+    }
+}
+
+/// The character a prefixed character literal stands for
+fn decode_char_literal(body: &str, encoding: LiteralEncoding) -> Result<char, String> {
+    let literal = format!("{}'{}'", encoding.prefix(), body);
+    let c = match decode_char(body, &literal)? {
+        Escape::Byte(byte) => return Ok(char::from(byte)),
+        Escape::Char(c) => c,
+    };
+    // Each element holds one code unit
+    let fits = match encoding {
+        LiteralEncoding::Utf8 => c.is_ascii(),
+        LiteralEncoding::Utf16 => c.len_utf16() == 1,
+        LiteralEncoding::Wide | LiteralEncoding::Utf32 => true,
+    };
+    if !fits {
+        return Err(format!("character literal {} doesn't fit in one {}-byte element", literal, encoding.element_size()));
+    }
+    Ok(c)
+}
+
+/// The one character, or escape, between a character literal's quotes
+fn decode_char(body: &str, literal: &str) -> Result<Escape, String> {
+    let (escape, length) = match body.strip_prefix('\\') {
+        Some(rest) => {
+            let (escape, length) = decode_escape(rest).map_err(|message| format!("{} in character literal {}", message, literal))?;
+            (escape, 1 + length)
+        }
+        None => match body.chars().next() {
+            Some(c) => (Escape::Char(c), c.len_utf8()),
+            None => return Err(format!("empty character literal {}", literal)),
+        },
+    };
+    if length < body.len() {
+        return Err(format!("character literal {} holds more than one character", literal));
+    }
+    Ok(escape)
+}
+
+/// Optional literal syntax, shared by all the lexers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LexOptions {
+    /// `1'000'000` and `1_000_000` (-fdigit-separators)
+    pub digit_separators: bool,
+    /// `//` comments need C99 (--std)
+    pub standard: Standard,
+    /// Identifiers may hold any UAX #31 identifier characters, not just
+    /// ASCII ones (-funicode-identifiers)
+    pub unicode_identifiers: bool,
+}
+
+lazy_static! {
+    static ref XID_START: Regex = Regex::new(r"^\p{XID_Start}$").unwrap();
+    static ref XID_CONTINUE: Regex = Regex::new(r"^\p{XID_Continue}$").unwrap();
+}
+
+/// Whether an identifier may start with `c`: `_` or a character that is
+/// XID_Start in UAX #31, which among ASCII ones are the letters
+pub fn is_identifier_start(c: char) -> bool {
+    c == '_' || c.is_ascii_alphabetic() || (!c.is_ascii() && XID_START.is_match(c.encode_utf8(&mut [0; 4])))
+}
+
+/// Whether an identifier may go on with `c`: XID_Continue, which adds
+/// digits, `_` and combining marks to XID_Start
+pub fn is_identifier_continue(c: char) -> bool {
+    c == '_' || c.is_ascii_alphanumeric() || (!c.is_ascii() && XID_CONTINUE.is_match(c.encode_utf8(&mut [0; 4])))
+}
+
+/// A character with its code point, as diagnostics show it: `'é' (U+00E9)`
+pub fn describe_char(c: char) -> String {
+    format!("'{}' (U+{:04X})", c.escape_debug(), c as u32)
+}
+
+/// Check an identifier the lexers read by the UAX #31 rules against the
+/// ones `options` set: only ASCII without -funicode-identifiers
+pub fn check_identifier(name: &str, options: LexOptions) -> Result<(), String> {
+    match name.chars().find(|c| !c.is_ascii()) {
+        Some(c) if !options.unicode_identifiers => Err(format!(
+            "identifier '{}' contains the non-ASCII character {}; Unicode identifiers need -funicode-identifiers",
+            name,
+            describe_char(c)
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The digits of a numeric literal with any `'`/`_` separators removed
+fn strip_digit_separators(text: &str, options: LexOptions) -> Result<String, String> {
+    if !text.contains(['\'', '_']) {
+        return Ok(text.to_string());
+    }
+    if !options.digit_separators {
+        return Err(format!("digit separators in {} need -fdigit-separators", text));
+    }
+    Ok(text.replace(['\'', '_'], ""))
+}
+
+/// Value of a decimal, `0x` hexadecimal or `0b` binary integer literal
+pub fn parse_int_literal(text: &str, options: LexOptions) -> Result<i64, String> {
+    let digits = strip_digit_separators(text, options)?;
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        if hex.is_empty() {
+            return Err(format!("hexadecimal literal {} has no digits", text));
+        }
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2)
+    } else {
+        digits.parse()
+    };
+    value.map_err(|_| format!("integer literal {} is out of range", text))
+}
+
+/// Value and suffix of a decimal floating literal: `3.14`, `.5`, `1.`,
+/// `1e9`, `3.14e-2`, each optionally ending in `f` or `L`. A float's value
+/// is rounded to the nearest float once, from the decimal digits, rather
+/// than through double; long double is only as wide as double. An `e`
+/// with no digits after it, or a value too large for its type, is an error.
+pub fn parse_float_literal(text: &str, options: LexOptions) -> Result<(f64, FloatSuffix), String> {
+    let digits = strip_digit_separators(text, options)?;
+    let (digits, suffix) = match digits.chars().last().and_then(FloatSuffix::from_suffix) {
+        Some(suffix) => (&digits[..digits.len() - 1], suffix),
+        None => (digits.as_str(), FloatSuffix::None),
+    };
+    if let Some((_, exponent)) = digits.split_once(['e', 'E'])
+        && !exponent.trim_start_matches(['+', '-']).starts_with(|c: char| c.is_ascii_digit())
+    {
+        return Err(format!("exponent has no digits in floating literal {}", text));
+    }
+    let invalid = |_| format!("invalid floating literal {}", text);
+    let value = match suffix {
+        FloatSuffix::Float => digits.parse::<f32>().map_err(invalid)? as f64,
+        _ => digits.parse::<f64>().map_err(invalid)?,
+    };
+    if value.is_infinite() {
+        return Err(format!("floating literal {} is out of range", text));
+    }
+    Ok((value, suffix))
+}
+
+pub fn lex_with_regex(input: &str) -> Vec<Token> {
+    lex_with_regex_options(input, LexOptions::default())
+}
+
+/// Like `lex_with_regex`, accepting the optional syntax `options` turns on
+pub fn lex_with_regex_options(input: &str, options: LexOptions) -> Vec<Token> {
+    lex_with_regex_spanned_options(input, options).into_iter().map(|(token, _)| token).collect()
+}
+
+/// Like `lex_with_regex`, paired with the byte range of the source each token came from
+pub fn lex_with_regex_spanned(input: &str) -> Vec<(Token, Range<usize>)> {
+    lex_with_regex_spanned_options(input, LexOptions::default())
+}
+
+/// Like `lex_with_regex_options`, each token with its line, column and bytes
+pub fn lex_with_regex_positions(input: &str, options: LexOptions) -> Vec<Spanned<Token>> {
+    source::spanned(input, lex_with_regex_spanned_options(input, options))
+}
+
+fn lex_with_regex_spanned_options(input: &str, options: LexOptions) -> Vec<(Token, Range<usize>)> {
+    Tokens::new(input, options).collect()
+}
+
+lazy_static! {
+    /// Every token, one alternative each, compiled once for all lexing
+    static ref TOKEN_REGEX: Regex = Regex::new(
+        r#"(?P<ws>\s+)|(?P<comment>//.*)|(?P<blockcomment>(?s:/\*.*?\*/))|(?P<unterminatedcomment>(?s:/\*.*))|(?P<pragma>#[ \t]*pragma\b[^\n]*)|(?P<preprocessor>#[a-zA-Z_][a-zA-Z0-9_]*)|(?P<function>fn)\b|(?P<return>return)\b|(?P<if>if)\b|(?P<else>else)\b|(?P<while>while)\b|(?P<for>for)\b|(?P<int>int)\b|(?P<float>float)\b|(?P<string>string)\b|(?P<bool>_Bool|bool)\b|(?P<enum>enum)\b|(?P<struct>struct)\b|(?P<typedef>typedef)\b|(?P<static>static)\b|(?P<const>const)\b|(?P<volatile>volatile)\b|(?P<restrict>restrict)\b|(?P<inline>inline)\b|(?P<noreturn>_Noreturn)\b|(?P<extern>extern)\b|(?P<auto>auto)\b|(?P<register>register)\b|(?P<case>case)\b|(?P<default>default)\b|(?P<break>break)\b|(?P<continue>continue)\b|(?P<goto>goto)\b|(?P<switch>switch)\b|(?P<do>do)\b|(?P<union>union)\b|(?P<signed>signed)\b|(?P<unsigned>unsigned)\b|(?P<short>short)\b|(?P<long>long)\b|(?P<double>double)\b|(?P<char>char)\b|(?P<void>void)\b|(?P<boollit>true|false)\b|(?P<floatlit>(?:(?:\d+(?:['_]\d+)*)?\.\d+(?:['_]\d+)*(?:[eE][+-]?\d*)?|\d+(?:['_]\d+)*\.(?:[eE][+-]?\d*)?|\d+(?:['_]\d+)*[eE][+-]?\d*)[fFlL]?)|(?P<intlit>0[xX][0-9a-fA-F]*(?:['_][0-9a-fA-F]+)*|0[bB][01]+(?:['_][01]+)*|\d+(?:['_]\d+)*)|(?P<encodedstringlit>(?P<stringprefix>u8|[uUL])"(?P<stringbody>(?:[^\\"\n]|\\.)*)")|(?P<encodedcharlit>(?P<charprefix>u8|[uUL])'(?P<charbody>(?:[^\\']|\\.)*)')|(?P<charlit>'(?P<plaincharbody>(?:[^\\']|\\.)*)')|(?P<stringlit>"([^\\"\n]|\\.)*")|(?P<lshiftassignop><<=)|(?P<rshiftassignop>>>=)|(?P<plusplusop>\+\+)|(?P<minusminusop>--)|(?P<plusassignop>\+=)|(?P<minusassignop>-=)|(?P<multassignop>\*=)|(?P<divassignop>/=)|(?P<modassignop>%=)|(?P<andassignop>&=)|(?P<xorassignop>\^=)|(?P<orassignop>\|=)|(?P<equalsop>==)|(?P<notequalsop>!=)|(?P<lesseqop><=)|(?P<greatereqop>>=)|(?P<andop>&&)|(?P<orop>\|\|)|(?P<assignop>=)|(?P<lshiftop><<)|(?P<rshiftop>>{2})|(?P<lessop><)|(?P<greaterop>>)|(?P<bitandop>&)|(?P<bitorop>\|)|(?P<plusop>\+)|(?P<arrowop>->)|(?P<minusop>-)|(?P<multop>\*)|(?P<divop>/)|(?P<modop>%)|(?P<xorop>\^)|(?P<notop>!)|(?P<bitnotop>~)|(?P<questionop>\?)|(?P<ellipsis>\.\.\.)|(?P<dotop>\.)|(?P<hashop>#)|(?P<identifier>[\p{XID_Start}_]\p{XID_Continue}*)|(?P<parenl>\()|(?P<parenr>\))|(?P<bracel>\{)|(?P<bracer>\})|(?P<bracketl>\[)|(?P<bracketr>\])|(?P<comma>,)|(?P<semicolon>;)|(?P<colon>:)|(?P<unterminatedstring>"[^\n]*)|(?P<unterminatedchar>'[^\n]*)|(?P<unexpected>[^\x00-\x7F])"#
+    ).unwrap();
+}
+
+/// The tokens of a source one at a time, each with its byte range, so a
+/// large file can be lexed while only the tokens still needed are kept
+pub struct Tokens<'a> {
+    spliced: Spliced<'a>,
+    options: LexOptions,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(input: &'a str, options: LexOptions) -> Self {
+        Tokens { spliced: Spliced::new(input), options, pos: 0 }
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = (Token, Range<usize>);
+
+    fn next(&mut self) -> Option<(Token, Range<usize>)> {
+        let (token, span) = borrowed::next_token(&self.spliced, &mut self.pos, self.options)?;
+        Some((token.to_owned(), self.spliced.original_range(span)))
+    }
+}
+
+/// The token a match of `TOKEN_REGEX` at `pos` is, for the groups whose
+/// tokens hold no text of the source; the borrowing lexer takes the others
+fn plain_token(caps: &Captures, spliced: &Spliced, pos: usize, options: LexOptions) -> Token {
+    let s = &caps[0];
+    if caps.name("unterminatedcomment").is_some() {
+        // Runs to the end of the file, as the comment would
+        let (line, col) = spliced.line_column(pos);
+        Token::UnterminatedComment { line, col }
+    } else if let Some(_) = caps.name("function") {
+        Token::Function
+    } else if let Some(_) = caps.name("int") {
+        Token::Int
+    } else if let Some(_) = caps.name("float") {
+        Token::Float
+    } else if let Some(_) = caps.name("string") {
+        Token::String
+    } else if let Some(_) = caps.name("bool") {
+        Token::Bool
+    } else if let Some(_) = caps.name("return") {
+        Token::Return
+    } else if let Some(_) = caps.name("if") {
+        Token::If
+    } else if let Some(_) = caps.name("else") {
+        Token::Else
+    } else if let Some(_) = caps.name("while") {
+        Token::While
+    } else if let Some(_) = caps.name("for") {
+        Token::For
+    } else if let Some(_) = caps.name("enum") {
+        Token::Enum
+    } else if let Some(_) = caps.name("struct") {
+        Token::Struct
+    } else if let Some(_) = caps.name("typedef") {
+        Token::Typedef
+    } else if let Some(_) = caps.name("static") {
+        Token::Static
+    } else if let Some(_) = caps.name("const") {
+        Token::Const
+    } else if let Some(_) = caps.name("volatile") {
+        Token::Volatile
+    } else if caps.name("restrict").is_some() {
+        Token::Restrict
+    } else if caps.name("inline").is_some() {
+        Token::Inline
+    } else if caps.name("noreturn").is_some() {
+        Token::Noreturn
+    } else if let Some(_) = caps.name("extern") {
+        Token::Extern
+    } else if let Some(_) = caps.name("auto") {
+        Token::Auto
+    } else if let Some(_) = caps.name("register") {
+        Token::Register
+    } else if let Some(_) = caps.name("case") {
+        Token::Case
+    } else if let Some(_) = caps.name("default") {
+        Token::Default
+    } else if let Some(_) = caps.name("break") {
+        Token::Break
+    } else if let Some(_) = caps.name("continue") {
+        Token::Continue
+    } else if let Some(_) = caps.name("goto") {
+        Token::Goto
+    } else if let Some(_) = caps.name("switch") {
+        Token::Switch
+    } else if let Some(_) = caps.name("do") {
+        Token::Do
+    } else if let Some(_) = caps.name("union") {
+        Token::Union
+    } else if let Some(_) = caps.name("signed") {
+        Token::Signed
+    } else if let Some(_) = caps.name("unsigned") {
+        Token::Unsigned
+    } else if let Some(_) = caps.name("short") {
+        Token::Short
+    } else if let Some(_) = caps.name("long") {
+        Token::Long
+    } else if let Some(_) = caps.name("double") {
+        Token::Double
+    } else if let Some(_) = caps.name("char") {
+        Token::Char
+    } else if let Some(_) = caps.name("void") {
+        Token::Void
+    } else if let Some(lit) = caps.name("boollit") {
+        Token::BoolLit(lit.as_str() == "true")
+    } else if let Some(lit) = caps.name("intlit") {
+        match parse_int_literal(lit.as_str(), options) {
+            Ok(n) => Token::IntLit(n),
+            Err(message) => Token::Error(message),
+        }
+    } else if let Some(lit) = caps.name("floatlit") {
+        match parse_float_literal(lit.as_str(), options) {
+            Ok((f, suffix)) => Token::FloatLit(f, suffix),
+            Err(message) => Token::Error(message),
+        }
+    } else if caps.name("encodedcharlit").is_some() {
+        let encoding = LiteralEncoding::from_prefix(&caps["charprefix"]).expect("prefix matched by the regex");
+        match decode_char_literal(&caps["charbody"], encoding) {
+            Ok(c) => Token::EncodedCharLit(encoding, c),
+            Err(message) => Token::Error(message),
+        }
+    } else if caps.name("charlit").is_some() {
+        match parse_char_literal(&caps["plaincharbody"]) {
+            Ok(c) => Token::CharLit(c),
+            Err(message) => Token::Error(message),
+        }
+    } else if let Some(_) = caps.name("assignop") {
+        Token::AssignOp
+    } else if let Some(_) = caps.name("equalsop") {
+        Token::EqualsOp
+    } else if let Some(_) = caps.name("notequalsop") {
+        Token::NotEqualsOp
+    } else if let Some(_) = caps.name("lesseqop") {
+        Token::LessEqOp
+    } else if let Some(_) = caps.name("greatereqop") {
+        Token::GreaterEqOp
+    } else if let Some(_) = caps.name("lessop") {
+        Token::LessOp
+    } else if let Some(_) = caps.name("greaterop") {
+        Token::GreaterOp
+    } else if let Some(_) = caps.name("andop") {
+        Token::AndOp
+    } else if let Some(_) = caps.name("orop") {
+        Token::OrOp
+    } else if let Some(_) = caps.name("bitandop") {
+        Token::BitAndOp
+    } else if let Some(_) = caps.name("bitorop") {
+        Token::BitOrOp
+    } else if let Some(_) = caps.name("parenl") {
+        Token::ParenL
+    } else if let Some(_) = caps.name("parenr") {
+        Token::ParenR
+    } else if let Some(_) = caps.name("bracel") {
+        Token::BraceL
+    } else if let Some(_) = caps.name("bracer") {
+        Token::BraceR
+    } else if let Some(_) = caps.name("bracketl") {
+        Token::BracketL
+    } else if let Some(_) = caps.name("bracketr") {
+        Token::BracketR
+    } else if let Some(_) = caps.name("comma") {
+        Token::Comma
+    } else if let Some(_) = caps.name("semicolon") {
+        Token::Semicolon
+    } else if let Some(_) = caps.name("colon") {
+        Token::Colon
+    } else if let Some(_) = caps.name("plusop") {
+        Token::Plus
+    } else if let Some(_) = caps.name("minusop") {
+        Token::Minus
+    } else if let Some(_) = caps.name("multop") {
+        Token::Mult
+    } else if let Some(_) = caps.name("divop") {
+        Token::Div
+    } else if let Some(_) = caps.name("modop") {
+        Token::Mod
+    } else if let Some(_) = caps.name("xorop") {
+        Token::Xor
+    } else if let Some(_) = caps.name("notop") {
+        Token::Not
+    } else if caps.name("bitnotop").is_some() {
+        Token::BitNot
+    } else if let Some(_) = caps.name("questionop") {
+        Token::Question
+    } else if caps.name("ellipsis").is_some() {
+        Token::Ellipsis
+    } else if let Some(_) = caps.name("dotop") {
+        Token::Dot
+    } else if let Some(_) = caps.name("arrowop") {
+        Token::Arrow
+    } else if let Some(_) = caps.name("plusplusop") {
+        Token::PlusPlus
+    } else if let Some(_) = caps.name("minusminusop") {
+        Token::MinusMinus
+    } else if let Some(_) = caps.name("plusassignop") {
+        Token::PlusAssign
+    } else if let Some(_) = caps.name("minusassignop") {
+        Token::MinusAssign
+    } else if let Some(_) = caps.name("multassignop") {
+        Token::MultAssign
+    } else if let Some(_) = caps.name("divassignop") {
+        Token::DivAssign
+    } else if let Some(_) = caps.name("modassignop") {
+        Token::ModAssign
+    } else if let Some(_) = caps.name("lshiftassignop") {
+        Token::LShiftAssign
+    } else if let Some(_) = caps.name("rshiftassignop") {
+        Token::RShiftAssign
+    } else if let Some(_) = caps.name("andassignop") {
+        Token::AndAssign
+    } else if let Some(_) = caps.name("xorassignop") {
+        Token::XorAssign
+    } else if let Some(_) = caps.name("orassignop") {
+        Token::OrAssign
+    } else if let Some(_) = caps.name("lshiftop") {
+        Token::LShift
+    } else if let Some(_) = caps.name("rshiftop") {
+        Token::RShift
+    } else if let Some(_) = caps.name("hashop") {
+        Token::Hash
+    } else if caps.name("unterminatedstring").is_some() {
+        // No closing quote before the line ends; the rest of the
+        // line is taken as its text, and lexing goes on after it
+        let (line, col) = spliced.line_column(pos);
+        Token::UnterminatedString { line, col }
+    } else if caps.name("unterminatedchar").is_some() {
+        // As for strings, the rest of the line goes with it
+        Token::Error(format!("unterminated character literal {}", s))
+    } else if caps.name("unexpected").is_some() {
+        let c = s.chars().next().expect("the group matches one character");
+        Token::Error(format!("Unexpected character: {}", describe_char(c)))
+    } else {
+        Token::Error(format!("Unknown token: {}", s))
+    }
+}
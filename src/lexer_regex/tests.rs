@@ -0,0 +1,64 @@
+// tests.rs: The operators `TOKEN_REGEX` matches, each of more than one
+// character taken whole rather than split at a shorter operator that
+// comes first in the alternation.
+
+use super::{Token, lex_with_regex};
+
+/// Every operator of more than one character, with its token
+const MULTI_CHAR_OPERATORS: [(&str, Token); 22] = [
+    ("<<=", Token::LShiftAssign),
+    (">>=", Token::RShiftAssign),
+    ("...", Token::Ellipsis),
+    ("++", Token::PlusPlus),
+    ("--", Token::MinusMinus),
+    ("+=", Token::PlusAssign),
+    ("-=", Token::MinusAssign),
+    ("*=", Token::MultAssign),
+    ("/=", Token::DivAssign),
+    ("%=", Token::ModAssign),
+    ("&=", Token::AndAssign),
+    ("^=", Token::XorAssign),
+    ("|=", Token::OrAssign),
+    ("==", Token::EqualsOp),
+    ("!=", Token::NotEqualsOp),
+    ("<=", Token::LessEqOp),
+    (">=", Token::GreaterEqOp),
+    ("&&", Token::AndOp),
+    ("||", Token::OrOp),
+    ("<<", Token::LShift),
+    (">>", Token::RShift),
+    ("->", Token::Arrow),
+];
+
+fn identifier(name: &str) -> Token {
+    Token::Identifier(name.to_string())
+}
+
+#[test]
+fn each_multi_char_operator_is_one_token() {
+    for (text, token) in &MULTI_CHAR_OPERATORS {
+        assert_eq!(lex_with_regex(text), vec![token.clone()], "{}", text);
+        // Between operands too, with nothing to set it apart
+        let between = format!("a{}b", text);
+        assert_eq!(lex_with_regex(&between), vec![identifier("a"), token.clone(), identifier("b")], "{}", between);
+    }
+}
+
+#[test]
+fn operators_run_together_are_taken_longest_first() {
+    let cases: [(&str, &[Token]); 10] = [
+        ("a+++b", &[identifier("a"), Token::PlusPlus, Token::Plus, identifier("b")]),
+        ("a---b", &[identifier("a"), Token::MinusMinus, Token::Minus, identifier("b")]),
+        ("a-->b", &[identifier("a"), Token::MinusMinus, Token::GreaterOp, identifier("b")]),
+        ("a>>>=b", &[identifier("a"), Token::RShift, Token::GreaterEqOp, identifier("b")]),
+        ("a<<<=b", &[identifier("a"), Token::LShift, Token::LessEqOp, identifier("b")]),
+        ("a&&&b", &[identifier("a"), Token::AndOp, Token::BitAndOp, identifier("b")]),
+        ("a||=b", &[identifier("a"), Token::OrOp, Token::AssignOp, identifier("b")]),
+        ("a===b", &[identifier("a"), Token::EqualsOp, Token::AssignOp, identifier("b")]),
+        ("a....b", &[identifier("a"), Token::Ellipsis, Token::Dot, identifier("b")]),
+        ("p->-q", &[identifier("p"), Token::Arrow, Token::Minus, identifier("q")]),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(lex_with_regex(text), expected, "{}", text);
+    }
+}
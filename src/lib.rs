@@ -0,0 +1,44 @@
+// lib.rs: The MiniC compiler as a library, so other crates can drive the
+// pipeline and hook their own lints, passes and emit targets into it.
+
+pub mod artifact;
+pub mod ast_text;
+pub mod bench;
+pub mod callgraph;
+pub mod debugger;
+pub mod dialect;
+pub mod doc;
+pub mod diagnostics;
+pub mod difftest;
+pub mod explorer;
+pub mod fold;
+pub mod fuzz;
+pub mod grade;
+pub mod html;
+pub mod ice;
+pub mod interpreter;
+pub mod intrinsics;
+pub mod layout;
+pub mod lexer_manual;
+pub mod lexer_regex;
+pub mod link;
+pub mod lints;
+pub mod lower;
+pub mod parser;
+pub mod plugins;
+pub mod policy;
+pub mod precedence;
+pub mod pretty;
+pub mod project;
+pub mod query;
+pub mod reduce;
+pub mod refactor;
+pub mod rules;
+pub mod scope;
+pub mod semantic;
+pub mod semantic_tokens;
+pub mod source;
+pub mod stats;
+pub mod stream;
+pub mod suppress;
+pub mod type_checker;
@@ -0,0 +1,323 @@
+// link.rs: The translation units of a project merged into one module, as a
+// linker merges object files (`project --link`).
+//
+// Each file is compiled on its own; linking puts their definitions into a
+// single TranslationUnit the interpreter and the emitters can be handed
+// whole. The rules are C's:
+//
+// - A function is defined by at most one unit. The prototypes other units
+//   and headers have of it resolve to that definition and are dropped. A
+//   prototype nothing defines is an undefined reference if anything uses
+//   it, unless the library provides it.
+// - A global follows the tentative-definition rules. Within a unit, `int
+//   x;` and `int x = 1;` are one definition, the initialized one, and `int
+//   x;` alone is a definition initialized to zero. Across units, two
+//   definitions of a name are an error even if both are tentative, as with
+//   gcc's -fno-common. `extern int x;` resolves to the definition.
+// - Every declaration of a name must give it the same type.
+// - A struct, enum or typedef from a shared header is kept once; two
+//   different ones of one name are an error, as the module has one
+//   namespace for each.
+// - A static global is private to its unit, which one module can't keep
+//   apart, so a static and another unit's global of the same name are an
+//   error rather than renamed.
+//
+// A program must define main. The module has the types first, then the
+// globals, then a prototype of every function and the functions, so each
+// can use any of the others wherever it was defined.
+
+use crate::ast_text::type_name;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::interpreter::builtins;
+use crate::lints::dead_globals::referenced_names;
+use crate::parser::ast::*;
+use crate::source::{FileId, SourceMap, TokenSpan};
+use std::collections::{HashMap, HashSet};
+
+/// The units linked into one
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub unit: TranslationUnit,
+    pub files: Vec<FileId>, // the file each of unit's external declarations is from
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkError {
+    MultipleDefinition { name: String, first: FileId, second: FileId },
+    /// `file` uses it; None for main, which the program itself needs
+    UndefinedReference { name: String, file: Option<FileId> },
+    ConflictingTypes { name: String, first: (FileId, String), second: (FileId, String) },
+    ConflictingTag { tag: String, first: FileId, second: FileId },
+    StaticConflict { name: String, first: FileId, second: FileId },
+}
+
+impl LinkError {
+    /// The error as a diagnostic of the file it was found in, naming the
+    /// other file from `sources`
+    pub fn diagnostic(&self, sources: &SourceMap) -> Diagnostic {
+        let name_of = |file: &FileId| sources.file(*file).name.clone();
+        let (file, message) = match self {
+            LinkError::MultipleDefinition { name, first, second } => {
+                (Some(*second), format!("Multiple definition of '{}', first defined in {}", name, name_of(first)))
+            }
+            LinkError::UndefinedReference { name, file } => (*file, format!("Undefined reference to '{}'", name)),
+            LinkError::ConflictingTypes { name, first, second } => {
+                let (first_file, first_type) = first;
+                let (ty, first_name) = (&second.1, name_of(first_file));
+                let message =
+                    format!("Conflicting types for '{}': '{}' here, '{}' in {}", name, ty, first_type, first_name);
+                (Some(second.0), message)
+            }
+            LinkError::ConflictingTag { tag, first, second } => {
+                (Some(*second), format!("'{}' defined differently than in {}", tag, name_of(first)))
+            }
+            LinkError::StaticConflict { name, first, second } => (
+                Some(*second),
+                format!("'{}' is also a global of {}, and one module can't keep them apart", name, name_of(first)),
+            ),
+        };
+        Diagnostic { file, ..Diagnostic::new(Severity::Error, None, message) }
+    }
+}
+
+/// `int*`, `char[]`, `int (*)(int)`
+fn variable_type(declaration: &VariableDeclaration) -> String {
+    let declarator = &declaration.declarator;
+    if !declarator.pointee.is_empty() {
+        // Array lengths left out, as for the others
+        let mut type_name = declarator.type_name(&declaration.type_specifier);
+        for derivation in type_name.derivations.iter_mut().take(declarator.array_sizes.len()) {
+            *derivation = Derivation::Array(None);
+        }
+        return type_name.spelling();
+    }
+    format!(
+        "{}{}{}",
+        type_name(&declaration.type_specifier),
+        "*".repeat(declarator.pointer_depth as usize),
+        "[]".repeat(declarator.array_sizes.len())
+    )
+}
+
+/// `int(int, char*)`, or `int(const char*, ...)`
+fn function_type(return_type: &str, parameters: &[Parameter], variadic: bool) -> String {
+    let mut parameters: Vec<&str> = parameters.iter().map(|parameter| parameter.param_type.as_str()).collect();
+    if variadic {
+        parameters.push("...");
+    }
+    format!("{}({})", return_type, parameters.join(", "))
+}
+
+/// Links units, given in the order their files were added
+#[derive(Default)]
+struct Linker {
+    // The declarations kept, of each file, in the module's order
+    types: Vec<(FileId, ExternalDeclaration)>,
+    globals: Vec<(FileId, ExternalDeclaration)>,
+    functions: Vec<(FileId, ExternalDeclaration)>,
+    preprocessor_list: Vec<PreprocessorDirective>,
+    preprocessor_spans: Vec<Option<TokenSpan>>,
+    directives: HashSet<String>,
+    tags: HashMap<String, (FileId, String)>,           // the definition kept, as {:?}
+    declared_types: HashMap<String, (FileId, String)>, // the first declaration's
+    defined: HashMap<String, (FileId, bool)>,          // and whether it was static
+    declared: HashMap<String, FileId>,                 // by a prototype or extern
+    errors: Vec<LinkError>,
+}
+
+impl Linker {
+    fn check_type(&mut self, name: &str, file: FileId, ty: String) {
+        match self.declared_types.get(name) {
+            Some(first) if first.1 != ty => {
+                let (name, first) = (name.to_string(), first.clone());
+                let error = LinkError::ConflictingTypes { name, first, second: (file, ty) };
+                if !self.errors.contains(&error) {
+                    self.errors.push(error);
+                }
+            }
+            Some(_) => {}
+            None => {
+                self.declared_types.insert(name.to_string(), (file, ty));
+            }
+        }
+    }
+
+    fn define(&mut self, name: &str, file: FileId, is_static: bool) -> bool {
+        let Some(&(first, first_static)) = self.defined.get(name) else {
+            self.defined.insert(name.to_string(), (file, is_static));
+            return true;
+        };
+        let name = name.to_string();
+        self.errors.push(if is_static || first_static {
+            LinkError::StaticConflict { name, first, second: file }
+        } else {
+            LinkError::MultipleDefinition { name, first, second: file }
+        });
+        false
+    }
+
+    fn tag(&mut self, tag: String, file: FileId, definition: String) -> bool {
+        match self.tags.get(&tag) {
+            Some((first, kept)) => {
+                if *kept != definition {
+                    self.errors.push(LinkError::ConflictingTag { tag, first: *first, second: file });
+                }
+                false
+            }
+            None => {
+                self.tags.insert(tag, (file, definition));
+                true
+            }
+        }
+    }
+
+    fn add(&mut self, file: FileId, unit: &TranslationUnit) {
+        for (directive, span) in unit.preprocessor_list.iter().zip(&unit.preprocessor_spans) {
+            if self.directives.insert(format!("{:?}", directive)) {
+                self.preprocessor_list.push(directive.clone());
+                self.preprocessor_spans.push(*span);
+            }
+        }
+
+        // A unit's own tentative definitions first come down to one each
+        let mut chosen: HashMap<&str, &VariableDeclaration> = HashMap::new();
+        for external in &unit.external_declarations {
+            if let ExternalDeclaration::Variable(declaration) = external
+                && !matches!(declaration.storage_class, Some(StorageClass::Extern | StorageClass::Typedef))
+                && declaration.declarator.function_params.is_none()
+            {
+                let name = declaration.declarator.name.as_str();
+                if chosen.get(name).is_none_or(|kept| kept.initializer.is_none() && declaration.initializer.is_some()) {
+                    chosen.insert(name, declaration);
+                }
+            }
+        }
+
+        for external in &unit.external_declarations {
+            let keep = match external {
+                ExternalDeclaration::Variable(declaration) => {
+                    let name = &declaration.declarator.name;
+                    match declaration.storage_class {
+                        Some(StorageClass::Typedef) => {
+                            self.tag(format!("typedef {}", name), file, format!("{:?}", declaration))
+                        }
+                        // A prototype written as a declarator, `int f(int);`
+                        _ if declaration.declarator.function_params.is_some() => {
+                            self.declared.entry(name.clone()).or_insert(file);
+                            false
+                        }
+                        Some(StorageClass::Extern) => {
+                            self.check_type(name, file, variable_type(declaration));
+                            self.declared.entry(name.clone()).or_insert(file);
+                            false
+                        }
+                        _ => {
+                            self.check_type(name, file, variable_type(declaration));
+                            let is_static = matches!(declaration.storage_class, Some(StorageClass::Static));
+                            std::ptr::eq(chosen[name.as_str()], declaration) && self.define(name, file, is_static)
+                        }
+                    }
+                }
+                ExternalDeclaration::Function(function) => {
+                    let ty = function_type(&function.return_type, &function.parameters, function.variadic);
+                    self.check_type(&function.name, file, ty);
+                    self.define(&function.name, file, false)
+                }
+                ExternalDeclaration::FunctionDeclaration(prototype) => {
+                    let ty = function_type(&prototype.return_type, &prototype.parameters, prototype.variadic);
+                    self.check_type(&prototype.name, file, ty);
+                    self.declared.entry(prototype.name.clone()).or_insert(file);
+                    false
+                }
+                ExternalDeclaration::Struct(definition) => {
+                    self.tag(format!("struct {}", definition.name), file, format!("{:?}", definition))
+                }
+                // Like a prototype, the definition kept stands for it
+                ExternalDeclaration::StructDeclaration(..) => false,
+                // One without a tag is known by its enumerators, so a header's
+                // included twice is kept once
+                ExternalDeclaration::Enum(definition) => {
+                    let tag = match &definition.name {
+                        Some(_) => definition.head(),
+                        None => {
+                            let names: Vec<&str> = definition.enumerators.iter().map(|enumerator| enumerator.name.as_str()).collect();
+                            format!("enum {{ {} }}", names.join(", "))
+                        }
+                    };
+                    self.tag(tag, file, format!("{:?}", definition))
+                }
+            };
+            if !keep {
+                continue;
+            }
+            let section = match external {
+                ExternalDeclaration::Variable(declaration)
+                    if !matches!(declaration.storage_class, Some(StorageClass::Typedef)) =>
+                {
+                    &mut self.globals
+                }
+                ExternalDeclaration::Function(_) => &mut self.functions,
+                _ => &mut self.types,
+            };
+            section.push((file, external.clone()));
+        }
+    }
+}
+
+fn prototype(function: &FunctionDefinition) -> FunctionDeclaration {
+    FunctionDeclaration {
+        attributes: function.attributes.clone(),
+        specifiers: function.specifiers.clone(),
+        return_type: function.return_type.clone(),
+        name: function.name.clone(),
+        parameters: function.parameters.clone(),
+        variadic: function.variadic,
+        span: None, // written out, not read from anywhere
+    }
+}
+
+/// Merge `units` into one module, or say why they can't be
+pub fn link(units: &[(FileId, &TranslationUnit)]) -> Result<Module, Vec<LinkError>> {
+    let mut linker = Linker::default();
+    for &(file, unit) in units {
+        linker.add(file, unit);
+    }
+
+    // Declared and used, but defined nowhere
+    for &(file, unit) in units {
+        let mut undefined: Vec<String> = referenced_names(unit)
+            .into_iter()
+            .filter(|name| linker.declared.contains_key(name) && !linker.defined.contains_key(name))
+            .filter(|name| !builtins::is_builtin(name))
+            .collect();
+        undefined.sort();
+        for name in undefined {
+            linker.errors.push(LinkError::UndefinedReference { name, file: Some(file) });
+        }
+    }
+    if !linker.defined.contains_key("main") {
+        linker.errors.push(LinkError::UndefinedReference { name: "main".to_string(), file: None });
+    }
+
+    if !linker.errors.is_empty() {
+        return Err(linker.errors);
+    }
+    let prototypes: Vec<(FileId, ExternalDeclaration)> = linker
+        .functions
+        .iter()
+        .filter_map(|(file, external)| match external {
+            ExternalDeclaration::Function(function) => {
+                Some((*file, ExternalDeclaration::FunctionDeclaration(prototype(function))))
+            }
+            _ => None,
+        })
+        .collect();
+    let module = linker.types.into_iter().chain(linker.globals).chain(prototypes).chain(linker.functions);
+    let (files, external_declarations) = module.unzip();
+    let unit = TranslationUnit {
+        preprocessor_list: linker.preprocessor_list,
+        preprocessor_spans: linker.preprocessor_spans,
+        external_declarations,
+    };
+    Ok(Module { unit, files })
+}
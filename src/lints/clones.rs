@@ -0,0 +1,433 @@
+// clones.rs: Duplicate-code detection.
+//
+// Every run of consecutive statements in a function body or block is
+// normalized (variables renamed by order of first use, literal values
+// dropped) and hashed; runs above a size threshold that normalize the same
+// way in two places are reported. Renamed copies of a loop therefore still
+// match, while calls to different functions don't.
+
+use crate::parser::ast::*;
+use crate::plugins::{AstLint, LintDiagnostic};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Default minimum size of a reported clone, in AST nodes
+pub const DEFAULT_MIN_SIZE: usize = 20;
+
+pub struct CloneLint {
+    min_size: usize,
+}
+
+impl CloneLint {
+    pub fn new(min_size: usize) -> Self {
+        CloneLint { min_size }
+    }
+}
+
+impl Default for CloneLint {
+    fn default() -> Self {
+        CloneLint::new(DEFAULT_MIN_SIZE)
+    }
+}
+
+/// A statement list and where it is
+struct StatementList<'a> {
+    function: &'a str,
+    parent: Option<(usize, usize)>, // enclosing list and statement; None for a function body
+    statements: &'a [Statement],
+}
+
+/// A run of statements `start..end` in one statement list
+#[derive(Clone, Copy, PartialEq)]
+struct Window {
+    list: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Window {
+    fn overlaps(&self, other: &Window) -> bool {
+        self.list == other.list && self.start < other.end && other.start < self.end
+    }
+
+    /// Whether `other` lies inside this run, directly or in a nested block
+    fn contains(&self, other: &Window, lists: &[StatementList]) -> bool {
+        let (mut list, mut start, mut end) = (other.list, other.start, other.end);
+        loop {
+            if list == self.list {
+                return self.start <= start && end <= self.end;
+            }
+            match lists[list].parent {
+                Some((parent, statement)) => (list, start, end) = (parent, statement, statement + 1),
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Writes a canonical form of statements, counting nodes as it goes
+#[derive(Default)]
+struct Normalizer {
+    names: HashMap<String, usize>,
+    out: String,
+    size: usize,
+}
+
+impl Normalizer {
+    fn name(&mut self, name: &str) {
+        let next = self.names.len();
+        let index = *self.names.entry(name.to_string()).or_insert(next);
+        write!(self.out, "${} ", index).unwrap();
+    }
+
+    fn node(&mut self, label: &str) {
+        self.size += 1;
+        self.out.push_str(label);
+        self.out.push(' ');
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        self.out.push('(');
+        match &statement.kind {
+            StatementKind::Declaration(declaration) => self.declaration(declaration),
+            StatementKind::Assignment(name, value) => {
+                self.node("assign");
+                self.name(name);
+                self.expression(value);
+            }
+            StatementKind::Return(value) => {
+                self.node("return");
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            StatementKind::Expression(expression) => {
+                self.node("expr");
+                self.expression(expression);
+            }
+            StatementKind::Block(statements) => {
+                self.node("block");
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.node("if");
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementKind::While(condition, body) => {
+                self.node("while");
+                self.expression(condition);
+                self.statement(body);
+            }
+            StatementKind::For(init, condition, update, body) => {
+                self.node("for");
+                match init {
+                    Some(init) => self.statement(init),
+                    None => self.out.push_str("_ "),
+                }
+                for part in [condition, update] {
+                    match part {
+                        Some(expression) => self.expression(expression),
+                        None => self.out.push_str("_ "),
+                    }
+                }
+                self.statement(body);
+            }
+            StatementKind::Break => self.node("break"),
+            StatementKind::Switch(value, body) => {
+                self.node("switch");
+                self.expression(value);
+                self.statement(body);
+            }
+            StatementKind::Case(value, statement) => {
+                self.node("case");
+                self.expression(value);
+                self.statement(statement);
+            }
+            StatementKind::Default(statement) => {
+                self.node("default");
+                self.statement(statement);
+            }
+            StatementKind::Fallthrough => self.node("fallthrough"),
+            StatementKind::Empty => self.node("empty"),
+            StatementKind::Label(name, statement) => {
+                self.node("label");
+                self.name(name);
+                self.statement(statement);
+            }
+            StatementKind::Goto(name) => {
+                self.node("goto");
+                self.name(name);
+            }
+        }
+        self.out.push(')');
+    }
+
+    fn declaration(&mut self, declaration: &VariableDeclaration) {
+        self.node(&format!("decl {:?}", declaration.type_specifier));
+        let declarator = &declaration.declarator;
+        self.name(&declarator.name);
+        write!(self.out, "*{} ", declarator.pointer_depth).unwrap();
+        for size in &declarator.array_sizes {
+            self.out.push('[');
+            if let Some(size) = size {
+                self.expression(size);
+            }
+            self.out.push(']');
+        }
+        if let Some(initializer) = &declaration.initializer {
+            self.initializer(initializer);
+        }
+    }
+
+    fn initializer(&mut self, initializer: &Initializer) {
+        match &initializer.kind {
+            InitializerKind::Assignment(value) => self.expression(value),
+            InitializerKind::List(items) => {
+                self.node("{");
+                for item in items {
+                    self.initializer(item);
+                }
+                self.out.push_str("} ");
+            }
+            InitializerKind::Designated(designator, value) => {
+                match designator {
+                    Designator::Member(member) => self.node(&format!(".{}", member)),
+                    Designator::Array(index) => {
+                        self.node("[]=");
+                        self.expression(index);
+                    }
+                }
+                self.initializer(value);
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        self.out.push('(');
+        match expression {
+            Expression::Identifier(name) => {
+                self.size += 1;
+                self.name(name);
+            }
+            // Only the kind of a literal matters, not its value
+            Expression::Constant(constant) | Expression::Folded(constant, _) => match constant {
+                Constant::Integer(_) => self.node("int"),
+                Constant::Float(..) => self.node("float"),
+                Constant::Char(_) => self.node("char"),
+                Constant::Bool(_) => self.node("bool"),
+                Constant::Null => self.node("null"),
+                Constant::EncodedChar(encoding, _) => self.node(&format!("{}char", encoding.prefix())),
+            },
+            Expression::StringLiteral(_) => self.node("str"),
+            Expression::EncodedStringLiteral(encoding, _) => self.node(&format!("{}str", encoding.prefix())),
+            Expression::BinaryOp(left, op, right) => {
+                self.node(&format!("{:?}", op));
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::UnaryOp(op, operand) => {
+                self.node(&format!("{:?}", op));
+                self.expression(operand);
+            }
+            Expression::Assignment(target, op, value) => {
+                self.node(op.symbol());
+                self.expression(target);
+                self.expression(value);
+            }
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.node("?:");
+                self.expression(condition);
+                self.expression(then_value);
+                self.expression(else_value);
+            }
+            Expression::FunctionCall(callee, arguments) => {
+                // Calls to different functions are different code
+                match callee.as_function_name() {
+                    Some(name) => self.node(&format!("call {}", name)),
+                    None => {
+                        self.node("call");
+                        self.expression(callee);
+                    }
+                }
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::ArrayAccess(array, index) => {
+                self.node("[]");
+                self.expression(array);
+                self.expression(index);
+            }
+            Expression::MemberAccess(object, member) => {
+                self.node(&format!(".{}", member));
+                self.expression(object);
+            }
+            Expression::PointerAccess(object, member) => {
+                self.node(&format!("->{}", member));
+                self.expression(object);
+            }
+            Expression::PostfixOp(operand, op) => {
+                self.node(&format!("post{:?}", op));
+                self.expression(operand);
+            }
+            Expression::Cast(target, operand) => {
+                self.node(&format!("cast {}", target.spelling()));
+                self.expression(operand);
+            }
+            Expression::SizeofType(operand) => {
+                self.node(&format!("sizeof {}", operand.spelling()));
+                for size in operand.expressions() {
+                    self.expression(size);
+                }
+            }
+            Expression::Paren(inner) => self.expression(inner),
+            Expression::StatementExpr(statements) => {
+                self.node("stmt-expr");
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            Expression::Generic(controlling, associations) => {
+                self.node("generic");
+                self.expression(controlling);
+                for association in associations {
+                    self.node(association.type_name.as_deref().unwrap_or("default"));
+                    self.expression(&association.value);
+                }
+            }
+            Expression::CompoundLiteral(literal) => {
+                self.node(&format!("compound {}[{}]", literal.type_name, literal.array_sizes.len()));
+                for size in literal.array_sizes.iter().flatten() {
+                    self.expression(size);
+                }
+                for item in &literal.initializers {
+                    self.initializer(item);
+                }
+            }
+        }
+        self.out.push(')');
+    }
+}
+
+fn collect_lists<'a>(
+    function: &'a str,
+    statements: &'a [Statement],
+    parent: Option<(usize, usize)>,
+    lists: &mut Vec<StatementList<'a>>,
+) {
+    let list = lists.len();
+    lists.push(StatementList { function, parent, statements });
+    for (index, statement) in statements.iter().enumerate() {
+        collect_nested_lists(function, statement, (list, index), lists);
+    }
+}
+
+fn collect_nested_lists<'a>(
+    function: &'a str,
+    statement: &'a Statement,
+    parent: (usize, usize),
+    lists: &mut Vec<StatementList<'a>>,
+) {
+    match &statement.kind {
+        StatementKind::Block(statements) => collect_lists(function, statements, Some(parent), lists),
+        StatementKind::If(_, then_branch, else_branch) => {
+            collect_nested_lists(function, then_branch, parent, lists);
+            if let Some(else_branch) = else_branch {
+                collect_nested_lists(function, else_branch, parent, lists);
+            }
+        }
+        StatementKind::While(_, body)
+        | StatementKind::For(_, _, _, body)
+        | StatementKind::Switch(_, body)
+        | StatementKind::Case(_, body)
+        | StatementKind::Default(body)
+        | StatementKind::Label(_, body) => collect_nested_lists(function, body, parent, lists),
+        _ => {}
+    }
+}
+
+fn describe(list: &StatementList, window: &Window) -> String {
+    let place = if list.parent.is_none() { "the body" } else { "a block" };
+    if window.end - window.start == 1 {
+        format!("statement {} of {} of '{}'", window.start + 1, place, list.function)
+    } else {
+        format!("statements {}-{} of {} of '{}'", window.start + 1, window.end, place, list.function)
+    }
+}
+
+impl AstLint for CloneLint {
+    fn name(&self) -> &str {
+        "duplicate-code"
+    }
+
+    fn check(&self, unit: &TranslationUnit) -> Vec<LintDiagnostic> {
+        let mut lists = Vec::new();
+        for declaration in &unit.external_declarations {
+            if let ExternalDeclaration::Function(function) = declaration {
+                collect_lists(&function.name, &function.body, None, &mut lists);
+            }
+        }
+
+        // Every run of statements big enough to report, by normalized form
+        let mut windows: HashMap<String, (usize, Vec<Window>)> = HashMap::new();
+        for (list_index, list) in lists.iter().enumerate() {
+            for start in 0..list.statements.len() {
+                let mut normalizer = Normalizer::default();
+                for end in start + 1..=list.statements.len() {
+                    // Extending the run keeps the names numbered so far
+                    normalizer.statement(&list.statements[end - 1]);
+                    if normalizer.size >= self.min_size {
+                        let window = Window { list: list_index, start, end };
+                        let entry = windows.entry(normalizer.out.clone()).or_insert((normalizer.size, Vec::new()));
+                        entry.1.push(window);
+                    }
+                }
+            }
+        }
+
+        // Copies that don't overlap each other, biggest clones first
+        let mut groups: Vec<(usize, Vec<Window>)> = windows
+            .into_values()
+            .filter_map(|(size, occurrences)| {
+                let mut copies: Vec<Window> = Vec::new();
+                for window in occurrences {
+                    if !copies.iter().any(|copy| copy.overlaps(&window)) {
+                        copies.push(window);
+                    }
+                }
+                (copies.len() > 1).then_some((size, copies))
+            })
+            .collect();
+        groups.sort_by_key(|(size, copies)| (std::cmp::Reverse(*size), copies[0].list, copies[0].start, copies[0].end));
+
+        // Report only maximal clones, not every smaller run inside one
+        let mut reported: Vec<Window> = Vec::new();
+        let mut diagnostics = Vec::new();
+        for (size, copies) in groups {
+            if copies.iter().all(|copy| reported.iter().any(|outer| outer.contains(copy, &lists))) {
+                continue;
+            }
+            let first = &copies[0];
+            for copy in &copies[1..] {
+                diagnostics.push(LintDiagnostic {
+                    lint: self.name().to_string(),
+                    line: lists[copy.list].statements[copy.start].line(),
+                    message: format!(
+                        "code in {} duplicates {} ({} AST nodes)",
+                        describe(&lists[copy.list], copy),
+                        describe(&lists[first.list], first),
+                        size
+                    ),
+                });
+            }
+            reported.extend(copies);
+        }
+        diagnostics
+    }
+}
@@ -0,0 +1,306 @@
+// dead_globals.rs: Globals and functions nothing uses.
+//
+// Every global refers to the globals its body or initializer mentions
+// (locals and parameters of the same name shadow them). What can't be
+// reached from the roots is dead: from `main` in a program, or from every
+// non-static global in a unit compiled without one, since another unit
+// may use those. The lint reports dead globals and `--gc-unused` removes
+// them from the AST, much like a linker's --gc-sections.
+
+use crate::parser::ast::*;
+use crate::plugins::{AstLint, AstPass, LintDiagnostic};
+use std::collections::{BTreeMap, HashSet};
+
+pub struct DeadGlobalsLint;
+
+pub struct StripDeadGlobals;
+
+/// A global: what it is and which globals it refers to
+struct Global {
+    kind: &'static str,
+    is_static: bool,
+    marked_unused: bool, // __attribute__((unused)) or [[maybe_unused]]: don't warn
+    references: HashSet<String>,
+    line: Option<usize>, // where it is defined, when the tree was parsed with spans
+}
+
+/// Collects the names an expression or statement refers to that aren't
+/// declared locally
+#[derive(Default)]
+struct References {
+    scopes: Vec<HashSet<String>>,
+    found: HashSet<String>,
+}
+
+impl References {
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+
+    fn name(&mut self, name: &str) {
+        if !self.is_local(name) {
+            self.found.insert(name.to_string());
+        }
+    }
+
+    fn declaration(&mut self, declaration: &VariableDeclaration) {
+        for size in declaration.declarator.array_sizes.iter().flatten() {
+            self.expression(size);
+        }
+        // In `int x = x;` the initializer already sees the new x
+        self.declare(&declaration.declarator.name);
+        if let Some(initializer) = &declaration.initializer {
+            self.initializer(initializer);
+        }
+    }
+
+    fn initializer(&mut self, initializer: &Initializer) {
+        match &initializer.kind {
+            InitializerKind::Assignment(value) => self.expression(value),
+            InitializerKind::List(items) => {
+                for item in items {
+                    self.initializer(item);
+                }
+            }
+            InitializerKind::Designated(designator, value) => {
+                if let Designator::Array(index) = designator {
+                    self.expression(index);
+                }
+                self.initializer(value);
+            }
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.scopes.push(HashSet::new());
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Declaration(declaration) => self.declaration(declaration),
+            StatementKind::Assignment(name, value) => {
+                self.name(name);
+                self.expression(value);
+            }
+            StatementKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            StatementKind::Expression(expression) => self.expression(expression),
+            StatementKind::Block(statements) => self.block(statements),
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementKind::While(condition, body)
+            | StatementKind::Switch(condition, body)
+            | StatementKind::Case(condition, body) => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            StatementKind::Default(body) | StatementKind::Label(_, body) => self.statement(body),
+            StatementKind::For(init, condition, update, body) => {
+                self.scopes.push(HashSet::new());
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                for expression in [condition, update].into_iter().flatten() {
+                    self.expression(expression);
+                }
+                self.statement(body);
+                self.scopes.pop();
+            }
+            StatementKind::Break | StatementKind::Fallthrough | StatementKind::Goto(_) | StatementKind::Empty => {}
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(name) => self.name(name),
+            Expression::Constant(_)
+            | Expression::Folded(..)
+            | Expression::StringLiteral(_)
+            | Expression::EncodedStringLiteral(..) => {}
+            Expression::BinaryOp(left, _, right)
+            | Expression::Assignment(left, _, right)
+            | Expression::ArrayAccess(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::UnaryOp(_, operand)
+            | Expression::PostfixOp(operand, _)
+            | Expression::Cast(_, operand)
+            | Expression::Paren(operand)
+            | Expression::MemberAccess(operand, _)
+            | Expression::PointerAccess(operand, _) => self.expression(operand),
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.expression(condition);
+                self.expression(then_value);
+                self.expression(else_value);
+            }
+            Expression::FunctionCall(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::StatementExpr(statements) => self.block(statements),
+            Expression::SizeofType(operand) => {
+                for size in operand.expressions() {
+                    self.expression(size);
+                }
+            }
+            Expression::CompoundLiteral(literal) => {
+                for size in literal.array_sizes.iter().flatten() {
+                    self.expression(size);
+                }
+                for item in &literal.initializers {
+                    self.initializer(item);
+                }
+            }
+            Expression::Generic(controlling, associations) => {
+                self.expression(controlling);
+                for association in associations {
+                    self.expression(&association.value);
+                }
+            }
+        }
+    }
+}
+
+/// Every global defined in the unit, by name
+fn collect_globals(unit: &TranslationUnit) -> BTreeMap<String, Global> {
+    let mut globals = BTreeMap::new();
+    for external in &unit.external_declarations {
+        match external {
+            ExternalDeclaration::Variable(declaration) => {
+                // `extern int x;` and prototypes only declare; typedefs aren't objects
+                if matches!(declaration.storage_class, Some(StorageClass::Extern | StorageClass::Typedef))
+                    || declaration.declarator.function_params.is_some()
+                {
+                    continue;
+                }
+                let mut references = References::default();
+                if let Some(initializer) = &declaration.initializer {
+                    references.initializer(initializer);
+                }
+                let is_static = matches!(declaration.storage_class, Some(StorageClass::Static));
+                let global = Global {
+                    kind: "global variable",
+                    is_static,
+                    marked_unused: find_attribute(&declaration.attributes, "unused").is_some(),
+                    references: references.found,
+                    line: declaration.span.map(|span| span.line),
+                };
+                globals.insert(declaration.declarator.name.clone(), global);
+            }
+            ExternalDeclaration::Function(function) => {
+                let mut references = References::default();
+                let parameters = function.parameters.iter().map(|parameter| parameter.name.clone()).collect();
+                references.scopes.push(parameters);
+                references.block(&function.body);
+                // The AST keeps no storage class for functions
+                let global = Global {
+                    kind: "function",
+                    is_static: false,
+                    marked_unused: find_attribute(&function.attributes, "unused").is_some(),
+                    references: references.found,
+                    line: function.name_span.map(|span| span.line),
+                };
+                globals.insert(function.name.clone(), global);
+            }
+            ExternalDeclaration::FunctionDeclaration(_)
+            | ExternalDeclaration::Struct(_)
+            | ExternalDeclaration::StructDeclaration(..)
+            | ExternalDeclaration::Enum(_) => {}
+        }
+    }
+    globals
+}
+
+/// Names the unit's definitions refer to that no local declares: the
+/// globals and functions it uses, its own or another unit's
+pub(crate) fn referenced_names(unit: &TranslationUnit) -> HashSet<String> {
+    collect_globals(unit).into_values().flat_map(|global| global.references).collect()
+}
+
+/// Names of the globals nothing reachable refers to
+fn dead_globals(globals: &BTreeMap<String, Global>) -> Vec<&str> {
+    let mut pending: Vec<&str> = if globals.contains_key("main") {
+        vec!["main"]
+    } else {
+        globals.iter().filter(|(_, global)| !global.is_static).map(|(name, _)| name.as_str()).collect()
+    };
+    let mut live: HashSet<&str> = pending.iter().copied().collect();
+    while let Some(name) = pending.pop() {
+        for reference in &globals[name].references {
+            if let Some((referenced, _)) = globals.get_key_value(reference)
+                && live.insert(referenced.as_str())
+            {
+                pending.push(referenced.as_str());
+            }
+        }
+    }
+    globals.keys().map(|name| name.as_str()).filter(|name| !live.contains(name)).collect()
+}
+
+impl AstLint for DeadGlobalsLint {
+    fn name(&self) -> &str {
+        "dead-globals"
+    }
+
+    fn check(&self, unit: &TranslationUnit) -> Vec<LintDiagnostic> {
+        let globals = collect_globals(unit);
+        let dead = dead_globals(&globals);
+        dead.iter()
+            .filter(|&&name| !globals[name].marked_unused)
+            .map(|&name| {
+                // Say so when the only users are themselves unused
+                let used_by_dead = dead.iter().any(|&other| other != name && globals[other].references.contains(name));
+                let message = if used_by_dead {
+                    format!("{} '{}' is only used by unused code", globals[name].kind, name)
+                } else {
+                    format!("{} '{}' is never used", globals[name].kind, name)
+                };
+                LintDiagnostic { lint: self.name().to_string(), line: globals[name].line, message }
+            })
+            .collect()
+    }
+}
+
+impl AstPass for StripDeadGlobals {
+    fn name(&self) -> &str {
+        "gc-unused"
+    }
+
+    fn run(&self, unit: &mut TranslationUnit) {
+        let globals = collect_globals(unit);
+        let dead: HashSet<String> = dead_globals(&globals).into_iter().map(str::to_string).collect();
+        unit.external_declarations.retain(|external| {
+            let name = match external {
+                ExternalDeclaration::Variable(declaration) => &declaration.declarator.name,
+                ExternalDeclaration::Function(function) => &function.name,
+                ExternalDeclaration::FunctionDeclaration(prototype) => &prototype.name,
+                // Tags, not objects; enumerators are constants
+                ExternalDeclaration::Struct(_)
+                | ExternalDeclaration::StructDeclaration(..)
+                | ExternalDeclaration::Enum(_) => return true,
+            };
+            !dead.contains(name)
+        });
+    }
+}
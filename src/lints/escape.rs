@@ -0,0 +1,306 @@
+// escape.rs: Escape analysis for locals.
+//
+// For every local and parameter this records whether its address is taken
+// and where that address goes. A local whose address is never taken can
+// live in a register once there is a backend to do so; one whose address
+// leaves the function (returned, stored in a global or through a pointer
+// parameter) dangles once the function returns, which the lint reports.
+//
+// Addresses are followed through local pointer variables in statement
+// order (`p = &x; return p;`), but not through loops or calls.
+
+use crate::parser::ast::*;
+use crate::plugins::{AstLint, LintDiagnostic};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+pub struct EscapeLint;
+
+#[derive(Debug, Clone, Default)]
+pub struct LocalEscape {
+    pub address_taken: bool,
+    pub passed_to_call: bool, // its address is an argument of some call
+    pub escapes: bool,        // its address outlives the function
+}
+
+impl LocalEscape {
+    /// Whether the local could be kept in a register instead of memory
+    pub fn promotable(&self) -> bool {
+        !self.address_taken
+    }
+}
+
+/// Where the address of a local ended up
+struct Escape {
+    local: String,
+    how: String,
+    line: Option<usize>,
+}
+
+fn unparen(expression: &Expression) -> &Expression {
+    match expression {
+        Expression::Paren(inner) => unparen(inner),
+        _ => expression,
+    }
+}
+
+/// How a compound literal is named in reports, as it has no name of its own
+fn compound_literal_name(literal: &CompoundLiteral) -> String {
+    format!("({}{}){{...}}", literal.type_name, "[]".repeat(literal.array_sizes.len()))
+}
+
+struct Analysis<'a> {
+    parameters: HashSet<&'a str>,
+    locals: BTreeMap<String, LocalEscape>,
+    aliases: HashMap<String, String>, // pointer variable -> local it points to
+    escapes: Vec<Escape>,
+    line: Option<usize>, // of the statement being looked at
+}
+
+impl<'a> Analysis<'a> {
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.contains_key(name)
+    }
+
+    /// The local whose address `expression` evaluates to, if any
+    fn pointee(&self, expression: &Expression) -> Option<String> {
+        match expression {
+            Expression::UnaryOp(UnaryOperator::AddressOf, operand) => match unparen(operand) {
+                Expression::Identifier(name) if self.is_local(name) => Some(name.clone()),
+                Expression::CompoundLiteral(literal) => Some(compound_literal_name(literal)),
+                _ => None,
+            },
+            Expression::Identifier(name) => self.aliases.get(name).cloned(),
+            Expression::Paren(inner) | Expression::Cast(_, inner) => self.pointee(inner),
+            // An array compound literal decays to its address
+            Expression::CompoundLiteral(literal) if !literal.array_sizes.is_empty() => {
+                Some(compound_literal_name(literal))
+            }
+            Expression::Conditional(_, then_value, else_value) => {
+                self.pointee(then_value).or_else(|| self.pointee(else_value))
+            }
+            _ => None,
+        }
+    }
+
+    fn escape(&mut self, local: String, how: String) {
+        if let Some(info) = self.locals.get_mut(&local) {
+            info.escapes = true;
+        }
+        self.escapes.push(Escape { local, how, line: self.line });
+    }
+
+    /// `target = value`, where target is a plain variable
+    fn assign(&mut self, target: &str, value: &Expression) {
+        match self.pointee(value) {
+            Some(local) if self.is_local(target) => {
+                self.aliases.insert(target.to_string(), local);
+            }
+            Some(local) => self.escape(local, format!("stored in global '{}'", target)),
+            None => {
+                self.aliases.remove(target);
+            }
+        }
+    }
+
+    fn declaration(&mut self, declaration: &VariableDeclaration) {
+        let name = declaration.declarator.name.clone();
+        if matches!(declaration.storage_class, Some(StorageClass::Static)) {
+            // Outlives the call, so its address is fine to hand out
+            self.locals.remove(&name);
+        } else {
+            self.locals.entry(name.clone()).or_default();
+        }
+        self.aliases.remove(&name);
+        if let Some(Initializer { kind: InitializerKind::Assignment(value) }) = &declaration.initializer {
+            self.expression(value);
+            self.assign(&name, value);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let outer = self.line;
+        self.line = statement.line().or(outer);
+        self.statement_node(statement);
+        self.line = outer;
+    }
+
+    fn statement_node(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Declaration(declaration) => self.declaration(declaration),
+            StatementKind::Assignment(name, value) => {
+                self.expression(value);
+                self.assign(name, value);
+            }
+            StatementKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                    if let Some(local) = self.pointee(value) {
+                        self.escape(local, "returned".to_string());
+                    }
+                }
+            }
+            StatementKind::Expression(expression) => self.expression(expression),
+            StatementKind::Block(statements) => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementKind::While(condition, body)
+            | StatementKind::Switch(condition, body)
+            | StatementKind::Case(condition, body) => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            StatementKind::Default(body) | StatementKind::Label(_, body) => self.statement(body),
+            StatementKind::For(init, condition, update, body) => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                for expression in [condition, update].into_iter().flatten() {
+                    self.expression(expression);
+                }
+                self.statement(body);
+            }
+            StatementKind::Break | StatementKind::Fallthrough | StatementKind::Goto(_) | StatementKind::Empty => {}
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::UnaryOp(UnaryOperator::AddressOf, operand) => {
+                if let Expression::Identifier(name) = unparen(operand)
+                    && let Some(info) = self.locals.get_mut(name)
+                {
+                    info.address_taken = true;
+                }
+                self.expression(operand);
+            }
+            Expression::Assignment(target, op, value) => {
+                self.expression(target);
+                self.expression(value);
+                if !matches!(op, AssignmentOperator::Assign) {
+                    return;
+                }
+                match target.as_ref() {
+                    Expression::Identifier(name) => self.assign(name, value),
+                    // `*out = &x;` hands the address back to the caller
+                    Expression::UnaryOp(UnaryOperator::Dereference, pointer) => {
+                        if let Expression::Identifier(pointer) = pointer.as_ref()
+                            && self.parameters.contains(pointer.as_str())
+                            && let Some(local) = self.pointee(value)
+                        {
+                            self.escape(local, format!("stored through parameter '{}'", pointer));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Expression::FunctionCall(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                    if let Some(local) = self.pointee(argument)
+                        && let Some(info) = self.locals.get_mut(&local)
+                    {
+                        info.passed_to_call = true;
+                    }
+                }
+            }
+            Expression::BinaryOp(left, _, right) | Expression::ArrayAccess(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::UnaryOp(_, operand)
+            | Expression::PostfixOp(operand, _)
+            | Expression::Cast(_, operand)
+            | Expression::Paren(operand)
+            | Expression::MemberAccess(operand, _)
+            | Expression::PointerAccess(operand, _) => self.expression(operand),
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.expression(condition);
+                self.expression(then_value);
+                self.expression(else_value);
+            }
+            Expression::StatementExpr(statements) => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            // Nothing in a type's array sizes can take an address that outlives them
+            Expression::SizeofType(_) => {}
+            Expression::CompoundLiteral(literal) => {
+                for size in literal.array_sizes.iter().flatten() {
+                    self.expression(size);
+                }
+                for value in literal.initializers.iter().flat_map(Initializer::expressions) {
+                    self.expression(value);
+                }
+            }
+            Expression::Generic(controlling, associations) => {
+                self.expression(controlling);
+                for association in associations {
+                    self.expression(&association.value);
+                }
+            }
+            Expression::Identifier(_)
+        | Expression::Constant(_)
+        | Expression::Folded(..)
+        | Expression::StringLiteral(_)
+        | Expression::EncodedStringLiteral(..) => {}
+        }
+    }
+}
+
+fn analyze(function: &FunctionDefinition) -> Analysis<'_> {
+    let mut analysis = Analysis {
+        parameters: function.parameters.iter().map(|parameter| parameter.name.as_str()).collect(),
+        locals: BTreeMap::new(),
+        aliases: HashMap::new(),
+        escapes: Vec::new(),
+        line: function.name_span.map(|span| span.line),
+    };
+    for parameter in &function.parameters {
+        analysis.locals.insert(parameter.name.clone(), LocalEscape::default());
+    }
+    for statement in &function.body {
+        analysis.statement(statement);
+    }
+    analysis
+}
+
+/// Escape facts for every parameter and local of a function, by name
+pub fn analyze_function(function: &FunctionDefinition) -> BTreeMap<String, LocalEscape> {
+    analyze(function).locals
+}
+
+impl AstLint for EscapeLint {
+    fn name(&self) -> &str {
+        "escaping-local"
+    }
+
+    fn check(&self, unit: &TranslationUnit) -> Vec<LintDiagnostic> {
+        let mut diagnostics = Vec::new();
+        for external in &unit.external_declarations {
+            if let ExternalDeclaration::Function(function) = external {
+                for escape in analyze(function).escapes {
+                    diagnostics.push(LintDiagnostic {
+                        lint: self.name().to_string(),
+                        line: escape.line,
+                        message: format!(
+                            "address of local '{}' in '{}' is {} and dangles once the function returns",
+                            escape.local, function.name, escape.how
+                        ),
+                    });
+                }
+            }
+        }
+        diagnostics
+    }
+}
@@ -0,0 +1,7 @@
+// lints/mod.rs: Built-in lints, run through the plugin registry like any
+// other AstLint.
+
+pub mod clones;
+pub mod dead_globals;
+pub mod escape;
+pub mod uninit_fields;
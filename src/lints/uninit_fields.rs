@@ -0,0 +1,434 @@
+// uninit_fields.rs: Struct locals used before all their fields are set.
+//
+// A struct local declared without an initializer starts out with every
+// field indeterminate. Assigning `s.x = ...` sets one field (or, for a
+// struct member, everything under it) and `s = ...` sets them all. When the
+// whole struct is then used as a value (passed to a function, copied or
+// returned) every field has to be set on every path that gets there;
+// otherwise the lint names the fields that may not be.
+//
+// Paths are merged like a definite-assignment analysis: both branches of
+// an if, and a loop or switch body only if it must run, which this doesn't
+// try to prove. Taking the address of the struct or a field counts as
+// setting it, since whatever receives the pointer may well do so.
+
+use crate::parser::ast::*;
+use crate::plugins::{AstLint, LintDiagnostic};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+pub struct UninitFieldsLint;
+
+/// Members of each struct: name, and the tag for a member that is itself
+/// a struct (not a pointer or array of one)
+type Structs = HashMap<String, Vec<(String, Option<String>)>>;
+
+/// A tracked struct local: its tag and the field paths set so far, like
+/// "x" or "pos.y"; the empty path is the whole struct
+#[derive(Debug, Clone, PartialEq)]
+struct Local {
+    tag: String,
+    assigned: BTreeSet<String>,
+}
+
+type State = BTreeMap<String, Local>;
+
+/// Whether `path`, or a field or struct it is part of, has been set
+fn covered(assigned: &BTreeSet<String>, path: &str) -> bool {
+    assigned.contains("")
+        || assigned.contains(path)
+        || path.match_indices('.').any(|(end, _)| assigned.contains(&path[..end]))
+}
+
+/// What is set in both states; a local missing from either is out of scope
+fn intersect(a: &State, b: &State) -> State {
+    let mut merged = State::new();
+    for (name, local) in a {
+        let Some(other) = b.get(name) else {
+            continue;
+        };
+        let assigned = local
+            .assigned
+            .iter()
+            .chain(&other.assigned)
+            .filter(|path| covered(&local.assigned, path) && covered(&other.assigned, path))
+            .cloned()
+            .collect();
+        merged.insert(name.clone(), Local { tag: local.tag.clone(), assigned });
+    }
+    merged
+}
+
+fn unparen(expression: &Expression) -> &Expression {
+    match expression {
+        Expression::Paren(inner) => unparen(inner),
+        _ => expression,
+    }
+}
+
+/// The variable and field path an lvalue names: `s.pos.x` is ("s",
+/// "pos.x"), and an element of an array member counts as the member
+fn field_path(expression: &Expression) -> Option<(&str, String)> {
+    match expression {
+        Expression::Identifier(name) => Some((name, String::new())),
+        Expression::Paren(inner) | Expression::ArrayAccess(inner, _) => field_path(inner),
+        Expression::MemberAccess(object, member) => {
+            let (name, path) = field_path(object)?;
+            Some((name, if path.is_empty() { member.clone() } else { format!("{}.{}", path, member) }))
+        }
+        _ => None,
+    }
+}
+
+struct Analysis<'a> {
+    structs: &'a Structs,
+    function: &'a str,
+    state: State,
+    switch_entries: Vec<State>, // state on entry to each enclosing switch
+    reported: HashSet<String>,  // report each local once
+    diagnostics: Vec<(Option<usize>, String)>, // line and message
+    line: Option<usize>,                       // of the statement being looked at
+}
+
+impl<'a> Analysis<'a> {
+    /// Field paths of a struct not covered by `assigned`, in member order
+    fn unassigned(&self, tag: &str, prefix: &str, assigned: &BTreeSet<String>, depth: usize, out: &mut Vec<String>) {
+        for (member, member_tag) in self.structs.get(tag).into_iter().flatten() {
+            let path = if prefix.is_empty() { member.clone() } else { format!("{}.{}", prefix, member) };
+            if covered(assigned, &path) {
+                continue;
+            }
+            match member_tag {
+                // A struct can't contain itself, but a broken program may say so
+                Some(member_tag) if depth < 16 && self.structs.get(member_tag).is_some_and(|members| !members.is_empty()) => {
+                    self.unassigned(member_tag, &path, assigned, depth + 1, out);
+                }
+                _ => out.push(path),
+            }
+        }
+    }
+
+    fn set(&mut self, lvalue: &Expression) {
+        if let Some((name, path)) = field_path(lvalue)
+            && let Some(local) = self.state.get_mut(name)
+        {
+            local.assigned.insert(path);
+        }
+    }
+
+    /// The whole struct is read as a value
+    fn use_value(&mut self, value: &Expression, how: &str) {
+        let Expression::Identifier(name) = unparen(value) else {
+            return;
+        };
+        let Some(local) = self.state.get(name) else {
+            return;
+        };
+        let mut fields = Vec::new();
+        self.unassigned(&local.tag, "", &local.assigned, 0, &mut fields);
+        if fields.is_empty() || !self.reported.insert(name.clone()) {
+            return;
+        }
+        let fields: Vec<String> = fields.iter().map(|field| format!("'{}'", field)).collect();
+        let (noun, verb) = if fields.len() == 1 { ("field", "is") } else { ("fields", "are") };
+        let message = format!(
+            "struct '{}' in '{}' is {} before {} {} {} set on every path",
+            name,
+            self.function,
+            how,
+            noun,
+            fields.join(", "),
+            verb
+        );
+        self.diagnostics.push((self.line, message));
+    }
+
+    fn declaration(&mut self, declaration: &VariableDeclaration) {
+        let name = declaration.declarator.name.clone();
+        if let Some(Initializer { kind: InitializerKind::Assignment(value) }) = &declaration.initializer {
+            self.expression(value);
+            self.use_value(value, "copied");
+        } else if let Some(initializer) = &declaration.initializer {
+            for value in initializer.expressions() {
+                self.expression(value);
+            }
+        }
+        let tracked = match &declaration.type_specifier {
+            TypeSpecifier::Struct(tag)
+                if declaration.declarator.pointer_depth == 0
+                    && declaration.declarator.array_sizes.is_empty()
+                    && declaration.storage_class.is_none()
+                    && self.structs.contains_key(tag) =>
+            {
+                Some(tag.clone())
+            }
+            _ => None,
+        };
+        match tracked {
+            Some(tag) => {
+                // Any initializer sets the fields it leaves out to zero
+                let assigned = declaration.initializer.iter().map(|_| String::new()).collect();
+                self.state.insert(name, Local { tag, assigned });
+            }
+            None => {
+                self.state.remove(&name);
+            }
+        }
+    }
+
+    /// Run statements in a scope of their own: what they declare goes out
+    /// of scope at the end, and the outer locals of those names come back
+    fn scoped(&mut self, statements: &[&Statement]) -> bool {
+        let shadowed: Vec<(String, Option<Local>)> = statements
+            .iter()
+            .filter_map(|statement| match &statement.kind {
+                StatementKind::Declaration(declaration) => Some(&declaration.declarator.name),
+                _ => None,
+            })
+            .map(|name| (name.clone(), self.state.get(name).cloned()))
+            .collect();
+        let mut completes = true;
+        for statement in statements {
+            completes &= self.statement(statement);
+        }
+        for (name, outer) in shadowed {
+            match outer {
+                Some(outer) => self.state.insert(name, outer),
+                None => self.state.remove(&name),
+            };
+        }
+        completes
+    }
+
+    /// The body of a loop, and its update, may not run at all: what they
+    /// set doesn't count afterwards
+    fn loop_body(&mut self, body: &Statement, update: Option<&Expression>) {
+        let before = self.state.clone();
+        self.statement(body);
+        if let Some(update) = update {
+            self.expression(update);
+        }
+        self.state = intersect(&before, &self.state);
+    }
+
+    /// Whether control can go on past the statement
+    fn statement(&mut self, statement: &Statement) -> bool {
+        let outer = self.line;
+        self.line = statement.line().or(outer);
+        let goes_on = self.statement_node(statement);
+        self.line = outer;
+        goes_on
+    }
+
+    fn statement_node(&mut self, statement: &Statement) -> bool {
+        match &statement.kind {
+            StatementKind::Declaration(declaration) => self.declaration(declaration),
+            StatementKind::Assignment(name, value) => {
+                self.expression(value);
+                self.use_value(value, "copied");
+                if let Some(local) = self.state.get_mut(name) {
+                    local.assigned.insert(String::new());
+                }
+            }
+            StatementKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                    self.use_value(value, "returned");
+                }
+                return false;
+            }
+            StatementKind::Expression(expression) => self.expression(expression),
+            StatementKind::Block(statements) => return self.scoped(&statements.iter().collect::<Vec<_>>()),
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                let before = self.state.clone();
+                let then_completes = self.statement(then_branch);
+                let after_then = std::mem::replace(&mut self.state, before.clone());
+                let else_completes = else_branch.as_ref().is_none_or(|else_branch| self.statement(else_branch));
+                self.state = match (then_completes, else_completes) {
+                    (true, true) => intersect(&after_then, &self.state),
+                    (true, false) => after_then,
+                    (false, true) => std::mem::take(&mut self.state),
+                    (false, false) => before,
+                };
+                return then_completes || else_completes;
+            }
+            StatementKind::While(condition, body) => {
+                self.expression(condition);
+                self.loop_body(body, None);
+            }
+            StatementKind::For(init, condition, update, body) => {
+                // A declaration in the init goes out of scope with the loop
+                let shadowed = match init.as_deref().map(|init| &init.kind) {
+                    Some(StatementKind::Declaration(declaration)) => {
+                        let name = &declaration.declarator.name;
+                        Some((name.clone(), self.state.get(name).cloned()))
+                    }
+                    _ => None,
+                };
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                }
+                self.loop_body(body, update.as_ref());
+                if let Some((name, outer)) = shadowed {
+                    match outer {
+                        Some(outer) => self.state.insert(name, outer),
+                        None => self.state.remove(&name),
+                    };
+                }
+            }
+            StatementKind::Switch(value, body) => {
+                self.expression(value);
+                let entry = self.state.clone();
+                self.switch_entries.push(entry.clone());
+                self.statement(body);
+                self.switch_entries.pop();
+                self.state = intersect(&entry, &self.state);
+            }
+            StatementKind::Case(value, body) => {
+                self.expression(value);
+                // Reached from the switch as well as from the case above
+                if let Some(entry) = self.switch_entries.last() {
+                    self.state = intersect(entry, &self.state);
+                }
+                return self.statement(body);
+            }
+            StatementKind::Default(body) => {
+                if let Some(entry) = self.switch_entries.last() {
+                    self.state = intersect(entry, &self.state);
+                }
+                return self.statement(body);
+            }
+            // Where a goto goes isn't followed; a label is taken to be
+            // reached only from the code above it
+            StatementKind::Label(_, body) => return self.statement(body),
+            StatementKind::Break | StatementKind::Goto(_) => return false,
+            StatementKind::Fallthrough | StatementKind::Empty => {}
+        }
+        true
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::UnaryOp(UnaryOperator::AddressOf, operand) => {
+                self.expression(operand);
+                self.set(operand);
+            }
+            // Only its type matters
+            Expression::UnaryOp(UnaryOperator::Sizeof, _) | Expression::SizeofType(_) => {}
+            Expression::Assignment(target, op, value) => {
+                self.expression(value);
+                self.expression(target);
+                if matches!(op, AssignmentOperator::Assign) {
+                    self.use_value(value, "copied");
+                    self.set(target);
+                }
+            }
+            Expression::FunctionCall(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                    self.use_value(argument, "passed to a function");
+                }
+            }
+            Expression::BinaryOp(left, _, right) | Expression::ArrayAccess(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::UnaryOp(_, operand)
+            | Expression::PostfixOp(operand, _)
+            | Expression::Cast(_, operand)
+            | Expression::Paren(operand)
+            | Expression::MemberAccess(operand, _)
+            | Expression::PointerAccess(operand, _) => self.expression(operand),
+            // Either value may be the one used, so neither sets anything for sure
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.expression(condition);
+                let before = self.state.clone();
+                self.expression(then_value);
+                let after_then = std::mem::replace(&mut self.state, before);
+                self.expression(else_value);
+                self.state = intersect(&after_then, &self.state);
+            }
+            Expression::StatementExpr(statements) => {
+                self.scoped(&statements.iter().collect::<Vec<_>>());
+            }
+            Expression::CompoundLiteral(literal) => {
+                for value in literal.initializers.iter().flat_map(Initializer::expressions) {
+                    self.expression(value);
+                }
+            }
+            Expression::Generic(controlling, associations) => {
+                self.expression(controlling);
+                for association in associations {
+                    self.expression(&association.value);
+                }
+            }
+            Expression::Identifier(_)
+            | Expression::Constant(_)
+            | Expression::Folded(..)
+            | Expression::StringLiteral(_)
+            | Expression::EncodedStringLiteral(..) => {}
+        }
+    }
+}
+
+fn collect_structs(unit: &TranslationUnit) -> Structs {
+    let mut structs = Structs::new();
+    for external in &unit.external_declarations {
+        if let ExternalDeclaration::Struct(definition) = external {
+            let members = definition
+                .members
+                .iter()
+                .filter(|member| !is_flexible_array(member))
+                .map(|member| {
+                    let tag = match &member.type_specifier {
+                        TypeSpecifier::Struct(tag)
+                            if member.declarator.pointer_depth == 0 && member.declarator.array_sizes.is_empty() =>
+                        {
+                            Some(tag.clone())
+                        }
+                        _ => None,
+                    };
+                    (member.declarator.name.clone(), tag)
+                })
+                .collect();
+            structs.insert(definition.name.clone(), members);
+        }
+    }
+    structs
+}
+
+impl AstLint for UninitFieldsLint {
+    fn name(&self) -> &str {
+        "uninitialized-field"
+    }
+
+    fn check(&self, unit: &TranslationUnit) -> Vec<LintDiagnostic> {
+        let structs = collect_structs(unit);
+        let mut diagnostics = Vec::new();
+        for external in &unit.external_declarations {
+            let ExternalDeclaration::Function(function) = external else {
+                continue;
+            };
+            let mut analysis = Analysis {
+                structs: &structs,
+                function: &function.name,
+                state: State::new(),
+                switch_entries: Vec::new(),
+                reported: HashSet::new(),
+                diagnostics: Vec::new(),
+                line: function.name_span.map(|span| span.line),
+            };
+            analysis.scoped(&function.body.iter().collect::<Vec<_>>());
+            diagnostics.extend(analysis.diagnostics.into_iter().map(|(line, message)| LintDiagnostic {
+                lint: self.name().to_string(),
+                line,
+                message,
+            }));
+        }
+        diagnostics
+    }
+}
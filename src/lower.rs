@@ -0,0 +1,423 @@
+// lower.rs: Structured control flow lowered to a control flow graph.
+//
+// Anything that runs or translates a function (the interpreter, and a VM or
+// code generator once there are any) works from this form, so they all
+// agree on what if, while, for, switch and break mean. Each function becomes
+// basic blocks: straight-line steps ending in exactly one terminator, a
+// goto, a two-way branch, a switch on an integer or a return. Block 0 is
+// the entry.
+//
+// Loops and switches are lowered through a stack of break targets; a loop's
+// continue target would be its condition (while) or its update (for).
+// Blocks only group statements here, so a block statement leaves no trace:
+// names are resolved by the scope analyzer before this runs. Expressions
+// are kept whole, with their own short-circuit && || and ?: inside.
+//
+// Steps and terminators remember the source line of the statement they
+// came from, as its span gives it.
+//
+// `--emit=cfg` prints the graph of every function.
+
+use crate::ast_text;
+use crate::diagnostics::Diagnostic;
+use crate::layout::Layouts;
+use crate::parser::ast::*;
+use crate::plugins::EmitTarget;
+use std::collections::HashMap;
+
+pub type BlockId = usize;
+
+/// A statement without control flow of its own
+#[derive(Debug, Clone)]
+pub enum Step {
+    Declare(Box<VariableDeclaration>),
+    Assign(String, Expression),
+    Evaluate(Expression),
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Goto(BlockId),
+    /// Go to the first block if the condition is nonzero, else the second
+    Branch(Expression, BlockId, BlockId),
+    /// Go to the block of the case equal to the value, or else the default
+    /// (the block after the switch, if it has none)
+    Switch { value: Expression, cases: Vec<(i64, BlockId)>, default: BlockId },
+    Return(Option<Expression>),
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub label: &'static str, // the construct it came from, like "while.body"
+    pub steps: Vec<Step>,
+    pub lines: Vec<Option<usize>>, // source line of each step, from 1
+    pub terminator: Terminator,
+    pub terminator_line: Option<usize>, // None for jumps nothing was written for
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub function: String,
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    /// Blocks each block can go to next
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        match &self.blocks[block].terminator {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::Branch(_, then_block, else_block) => vec![*then_block, *else_block],
+            Terminator::Switch { cases, default, .. } => {
+                cases.iter().map(|(_, target)| *target).chain([*default]).collect()
+            }
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    /// The graph as text, one block after another
+    pub fn render(&self) -> String {
+        let mut out = format!("function {}\n", self.function);
+        for (index, block) in self.blocks.iter().enumerate() {
+            out.push_str(&format!("  bb{} ({}):\n", index, block.label));
+            for step in &block.steps {
+                out.push_str(&format!("    {}\n", render_step(step)));
+            }
+            out.push_str(&format!("    {}\n", render_terminator(&block.terminator)));
+        }
+        out
+    }
+}
+
+/// Case labels found so far in a switch being lowered
+#[derive(Default)]
+struct SwitchTargets {
+    cases: Vec<(i64, BlockId)>,
+    default: Option<BlockId>,
+}
+
+struct PendingBlock {
+    label: &'static str,
+    steps: Vec<Step>,
+    lines: Vec<Option<usize>>,
+    terminator: Option<Terminator>,
+    terminator_line: Option<usize>,
+}
+
+struct Builder<'a> {
+    layouts: &'a Layouts,
+    blocks: Vec<PendingBlock>,
+    current: Option<BlockId>, // None after a jump, until the next block starts
+    breaks: Vec<BlockId>,     // where break goes, innermost last
+    switches: Vec<SwitchTargets>,
+    labels: HashMap<String, BlockId>, // the block of each label, made by its definition or the first goto to it
+}
+
+impl Builder<'_> {
+    fn new_block(&mut self, label: &'static str) -> BlockId {
+        self.blocks.push(PendingBlock { label, steps: Vec::new(), lines: Vec::new(), terminator: None, terminator_line: None });
+        self.blocks.len() - 1
+    }
+
+    /// The block label `name` starts, made on first sight so a goto can
+    /// come before it
+    fn label_block(&mut self, name: &str) -> BlockId {
+        if let Some(&block) = self.labels.get(name) {
+            return block;
+        }
+        let block = self.new_block("label");
+        self.labels.insert(name.to_string(), block);
+        block
+    }
+
+    fn start(&mut self, block: BlockId) {
+        self.current = Some(block);
+    }
+
+    /// The block being filled; code after a jump gets one of its own, which
+    /// nothing reaches
+    fn current_block(&mut self) -> BlockId {
+        match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block("unreachable");
+                self.start(block);
+                block
+            }
+        }
+    }
+
+    fn step(&mut self, step: Step, line: Option<usize>) {
+        let block = self.current_block();
+        self.blocks[block].steps.push(step);
+        self.blocks[block].lines.push(line);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.terminate_at(terminator, None);
+    }
+
+    fn terminate_at(&mut self, terminator: Terminator, line: Option<usize>) {
+        if let Some(block) = self.current.take() {
+            self.blocks[block].terminator = Some(terminator);
+            self.blocks[block].terminator_line = line;
+        }
+    }
+
+    /// End the current block by going on to `next`, and continue there
+    fn continue_at(&mut self, next: BlockId) {
+        self.terminate(Terminator::Goto(next));
+        self.start(next);
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let line = statement.line();
+        match &statement.kind {
+            StatementKind::Declaration(declaration) => {
+                self.step(Step::Declare(Box::new(declaration.clone())), line);
+            }
+            StatementKind::Assignment(name, value) => {
+                self.step(Step::Assign(name.clone(), value.clone()), line);
+            }
+            StatementKind::Expression(expression) => {
+                self.step(Step::Evaluate(expression.clone()), line);
+            }
+            StatementKind::Return(value) => {
+                self.current_block();
+                self.terminate_at(Terminator::Return(value.clone()), line);
+            }
+            StatementKind::Block(statements) => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.current_block();
+                let then_block = self.new_block("if.then");
+                let else_block = else_branch.as_ref().map(|_| self.new_block("if.else"));
+                let end = self.new_block("if.end");
+                self.terminate_at(Terminator::Branch(condition.clone(), then_block, else_block.unwrap_or(end)), line);
+                self.start(then_block);
+                self.statement(then_branch);
+                self.terminate(Terminator::Goto(end));
+                if let (Some(else_block), Some(else_branch)) = (else_block, else_branch) {
+                    self.start(else_block);
+                    self.statement(else_branch);
+                    self.terminate(Terminator::Goto(end));
+                }
+                self.start(end);
+            }
+            StatementKind::While(condition, body) => {
+                let test = self.new_block("while.cond");
+                let body_block = self.new_block("while.body");
+                let end = self.new_block("while.end");
+                self.continue_at(test);
+                self.terminate_at(Terminator::Branch(condition.clone(), body_block, end), line);
+                self.start(body_block);
+                self.breaks.push(end);
+                self.statement(body);
+                self.breaks.pop();
+                self.terminate(Terminator::Goto(test));
+                self.start(end);
+            }
+            StatementKind::For(init, condition, update, body) => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                let test = self.new_block("for.cond");
+                let body_block = self.new_block("for.body");
+                let next = self.new_block("for.step");
+                let end = self.new_block("for.end");
+                self.continue_at(test);
+                // A missing condition is always true
+                match condition {
+                    Some(condition) => self.terminate_at(Terminator::Branch(condition.clone(), body_block, end), line),
+                    None => self.terminate(Terminator::Goto(body_block)),
+                }
+                self.start(body_block);
+                self.breaks.push(end);
+                self.statement(body);
+                self.breaks.pop();
+                self.continue_at(next);
+                if let Some(update) = update {
+                    self.step(Step::Evaluate(update.clone()), line);
+                }
+                self.terminate(Terminator::Goto(test));
+                self.start(end);
+            }
+            StatementKind::Switch(value, body) => {
+                let dispatch = self.current_block();
+                let end = self.new_block("switch.end");
+                // Until the first label, the body is only reached by jumping in
+                self.current = None;
+                self.breaks.push(end);
+                self.switches.push(SwitchTargets::default());
+                self.statement(body);
+                let targets = self.switches.pop().unwrap_or_default();
+                self.breaks.pop();
+                self.terminate(Terminator::Goto(end));
+                self.blocks[dispatch].terminator = Some(Terminator::Switch {
+                    value: value.clone(),
+                    cases: targets.cases,
+                    default: targets.default.unwrap_or(end),
+                });
+                self.blocks[dispatch].terminator_line = line;
+                self.start(end);
+            }
+            StatementKind::Case(value, statement) => {
+                let block = self.new_block("switch.case");
+                self.continue_at(block);
+                // The type checker reports values that aren't constants
+                if let (Some(targets), Some(value)) =
+                    (self.switches.last_mut(), value.integer_constant_value_in(self.layouts))
+                {
+                    targets.cases.push((value, block));
+                }
+                self.statement(statement);
+            }
+            StatementKind::Default(statement) => {
+                let block = self.new_block("switch.default");
+                self.continue_at(block);
+                if let Some(targets) = self.switches.last_mut() {
+                    targets.default = Some(block);
+                }
+                self.statement(statement);
+            }
+            StatementKind::Break => {
+                if let Some(&target) = self.breaks.last() {
+                    self.terminate(Terminator::Goto(target));
+                }
+            }
+            StatementKind::Label(name, statement) => {
+                let block = self.label_block(name);
+                self.continue_at(block);
+                self.statement(statement);
+            }
+            StatementKind::Goto(name) => {
+                let target = self.label_block(name);
+                self.current_block();
+                self.terminate_at(Terminator::Goto(target), line);
+            }
+            StatementKind::Fallthrough | StatementKind::Empty => {}
+        }
+    }
+
+    /// The finished blocks, without those nothing reaches from the entry,
+    /// renumbered in order
+    fn finish(self) -> Vec<BasicBlock> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = vec![0];
+        while let Some(block) = pending.pop() {
+            if std::mem::replace(&mut reachable[block], true) {
+                continue;
+            }
+            match &self.blocks[block].terminator {
+                Some(Terminator::Goto(target)) => pending.push(*target),
+                Some(Terminator::Branch(_, then_block, else_block)) => pending.extend([*then_block, *else_block]),
+                Some(Terminator::Switch { cases, default, .. }) => {
+                    pending.extend(cases.iter().map(|(_, target)| *target).chain([*default]))
+                }
+                Some(Terminator::Return(_)) | None => {}
+            }
+        }
+        let mut renumbered = vec![0; self.blocks.len()];
+        let mut next = 0;
+        for (block, &reached) in reachable.iter().enumerate() {
+            renumbered[block] = next;
+            next += reached as usize;
+        }
+        let renumber = |terminator: Terminator| match terminator {
+            Terminator::Goto(target) => Terminator::Goto(renumbered[target]),
+            Terminator::Branch(condition, then_block, else_block) => {
+                Terminator::Branch(condition, renumbered[then_block], renumbered[else_block])
+            }
+            Terminator::Switch { value, cases, default } => Terminator::Switch {
+                value,
+                cases: cases.into_iter().map(|(case, target)| (case, renumbered[target])).collect(),
+                default: renumbered[default],
+            },
+            Terminator::Return(value) => Terminator::Return(value),
+        };
+        self.blocks
+            .into_iter()
+            .zip(reachable)
+            .filter(|(_, reached)| *reached)
+            .map(|(block, _)| BasicBlock {
+                label: block.label,
+                steps: block.steps,
+                lines: block.lines,
+                // Falling off the end of the function returns
+                terminator: renumber(block.terminator.unwrap_or(Terminator::Return(None))),
+                terminator_line: block.terminator_line,
+            })
+            .collect()
+    }
+}
+
+/// Lower one function; `layouts` gives the values of case labels
+pub fn lower_function(layouts: &Layouts, function: &FunctionDefinition) -> ControlFlowGraph {
+    let mut builder = Builder {
+        layouts,
+        blocks: Vec::new(),
+        current: None,
+        breaks: Vec::new(),
+        switches: Vec::new(),
+        labels: HashMap::new(),
+    };
+    let entry = builder.new_block("entry");
+    builder.start(entry);
+    for statement in &function.body {
+        builder.statement(statement);
+    }
+    ControlFlowGraph { function: function.name.clone(), blocks: builder.finish() }
+}
+
+/// Every function defined in the unit, in source order
+pub fn lower_unit(unit: &TranslationUnit) -> Vec<ControlFlowGraph> {
+    let layouts = Layouts::new(unit);
+    unit.external_declarations
+        .iter()
+        .filter_map(|external| match external {
+            ExternalDeclaration::Function(function) => Some(lower_function(&layouts, function)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn render_step(step: &Step) -> String {
+    match step {
+        Step::Declare(declaration) => ast_text::declaration(declaration),
+        Step::Assign(name, value) => format!("(set {} {})", name, ast_text::expression(value)),
+        Step::Evaluate(expression) => format!("(expr {})", ast_text::expression(expression)),
+    }
+}
+
+fn render_terminator(terminator: &Terminator) -> String {
+    match terminator {
+        Terminator::Goto(target) => format!("goto bb{}", target),
+        Terminator::Branch(condition, then_block, else_block) => {
+            format!("branch {} bb{} bb{}", ast_text::expression(condition), then_block, else_block)
+        }
+        Terminator::Switch { value, cases, default } => {
+            let mut text = format!("switch {}", ast_text::expression(value));
+            for (case, target) in cases {
+                text.push_str(&format!(" {}:bb{}", case, target));
+            }
+            text.push_str(&format!(" default:bb{}", default));
+            text
+        }
+        Terminator::Return(Some(value)) => format!("return {}", ast_text::expression(value)),
+        Terminator::Return(None) => "return".to_string(),
+    }
+}
+
+pub struct CfgEmitter;
+
+impl EmitTarget for CfgEmitter {
+    fn name(&self) -> &str {
+        "cfg"
+    }
+
+    fn emit(&self, unit: &TranslationUnit, _source: &str, _diagnostics: &[Diagnostic]) -> String {
+        lower_unit(unit).iter().map(ControlFlowGraph::render).collect::<Vec<_>>().join("\n")
+    }
+}
@@ -1,51 +1,18 @@
-mod lexer_manual;
-mod lexer_regex;
-mod parser;
-mod rules;
-mod scope;
-mod type_checker;
-
-use regex::Regex;
-use rules::{RULES, Token};
+use hello_rust::dialect::Standard;
+use hello_rust::diagnostics::{self, Diagnostic, Report};
+use hello_rust::scope::ScopeError;
+use hello_rust::source::SourceFile;
+use hello_rust::type_checker::{TypeError, TypeWarning};
+use hello_rust::{
+    artifact, ast_text, bench, callgraph, debugger, difftest, doc, explorer, fuzz, grade, html, ice, interpreter, layout, lexer_manual, lexer_regex, lints, lower, parser, plugins, policy, precedence, pretty, reduce, refactor, rules,
+    project, scope, semantic, semantic_tokens, stats, stream, suppress, type_checker,
+};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::io::Write;
-
-// Rules-based lexer using rules.rs
-fn lex(mut input: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    while !input.is_empty() {
-        input = input.trim_start();
-        if input.is_empty() {
-            break;
-        }
-        let mut matched = false;
-        for rule in RULES.iter() {
-            if let Some(m) = rule.regex.find(input) {
-                let lexeme = m.as_str();
-                // Special check: invalid identifier like `2abc`
-                if Regex::new(r"^\d+[a-zA-Z_]").unwrap().is_match(input) {
-                    tokens.push(Token::Error(format!("Invalid identifier: {}", lexeme)));
-                    input = &input[m.end()..];
-                    matched = true;
-                    break;
-                }
-                tokens.push((rule.token_type)(lexeme));
-                input = &input[m.end()..];
-                matched = true;
-                break;
-            }
-        }
-        if !matched {
-            tokens.push(Token::Error(format!(
-                "Unexpected character: {}",
-                &input[..1]
-            )));
-            input = &input[1..];
-        }
-    }
-    tokens
-}
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 fn write_regex_tokens_to_file(tokens: &[lexer_regex::Token], filename: &str) {
     let mut file = fs::File::create(filename).expect("Failed to create file");
@@ -58,8 +25,15 @@ fn write_regex_tokens_to_file(tokens: &[lexer_regex::Token], filename: &str) {
             lexer_regex::Token::Bool => "T_BOOL".to_string(),
             lexer_regex::Token::Identifier(s) => format!("T_IDENTIFIER(\"{}\")", s),
             lexer_regex::Token::IntLit(n) => format!("T_INTLIT({})", n),
-            lexer_regex::Token::FloatLit(f) => format!("T_FLOATLIT({})", f),
+            lexer_regex::Token::FloatLit(f, suffix) => format!("T_FLOATLIT({}{})", f, suffix.suffix()),
             lexer_regex::Token::StringLit(s) => format!("T_STRINGLIT(\"{}\")", s),
+            lexer_regex::Token::EncodedStringLit(encoding, s) => {
+                format!("T_STRINGLIT({}\"{}\")", encoding.prefix(), s)
+            }
+            lexer_regex::Token::CharLit(c) => format!("T_CHARLIT('{}')", c.escape_default()),
+            lexer_regex::Token::EncodedCharLit(encoding, c) => {
+                format!("T_CHARLIT({}'{}')", encoding.prefix(), c.escape_default())
+            }
             lexer_regex::Token::BoolLit(b) => format!("T_BOOLLIT({})", b),
             lexer_regex::Token::Return => "T_RETURN".to_string(),
             lexer_regex::Token::If => "T_IF".to_string(),
@@ -94,8 +68,10 @@ fn write_regex_tokens_to_file(tokens: &[lexer_regex::Token], filename: &str) {
             lexer_regex::Token::Mod => "T_MOD".to_string(),
             lexer_regex::Token::Xor => "T_XOR".to_string(),
             lexer_regex::Token::Not => "T_NOT".to_string(),
+            lexer_regex::Token::BitNot => "T_BITNOT".to_string(),
             lexer_regex::Token::Question => "T_QUESTION".to_string(),
             lexer_regex::Token::Dot => "T_DOT".to_string(),
+            lexer_regex::Token::Ellipsis => "T_ELLIPSIS".to_string(),
             lexer_regex::Token::Arrow => "T_ARROW".to_string(),
             lexer_regex::Token::PlusPlus => "T_PLUSPLUS".to_string(),
             lexer_regex::Token::MinusMinus => "T_MINUSMINUS".to_string(),
@@ -115,12 +91,16 @@ fn write_regex_tokens_to_file(tokens: &[lexer_regex::Token], filename: &str) {
             lexer_regex::Token::Comment(s) => format!("T_COMMENT(\"{}\")", s),
             lexer_regex::Token::BlockComment(s) => format!("T_BLOCKCOMMENT(\"{}\")", s),
             lexer_regex::Token::Preprocessor(s) => format!("T_PREPROCESSOR(\"{}\")", s),
+            lexer_regex::Token::Pragma(s) => format!("T_PRAGMA(\"{}\")", s),
             lexer_regex::Token::Enum => "T_ENUM".to_string(),
             lexer_regex::Token::Struct => "T_STRUCT".to_string(),
             lexer_regex::Token::Typedef => "T_TYPEDEF".to_string(),
             lexer_regex::Token::Static => "T_STATIC".to_string(),
             lexer_regex::Token::Const => "T_CONST".to_string(),
             lexer_regex::Token::Volatile => "T_VOLATILE".to_string(),
+            lexer_regex::Token::Restrict => "T_RESTRICT".to_string(),
+            lexer_regex::Token::Inline => "T_INLINE".to_string(),
+            lexer_regex::Token::Noreturn => "T_NORETURN".to_string(),
             lexer_regex::Token::Extern => "T_EXTERN".to_string(),
             lexer_regex::Token::Auto => "T_AUTO".to_string(),
             lexer_regex::Token::Register => "T_REGISTER".to_string(),
@@ -140,6 +120,8 @@ fn write_regex_tokens_to_file(tokens: &[lexer_regex::Token], filename: &str) {
             lexer_regex::Token::Char => "T_CHAR".to_string(),
             lexer_regex::Token::Void => "T_VOID".to_string(),
             lexer_regex::Token::Error(s) => format!("T_ERROR(\"{}\")", s),
+            lexer_regex::Token::UnterminatedString { line, col } => format!("T_UNTERMINATED_STRING({}:{})", line, col),
+            lexer_regex::Token::UnterminatedComment { line, col } => format!("T_UNTERMINATED_COMMENT({}:{})", line, col),
         };
         writeln!(file, "{}", token_str).expect("Failed to write to file");
     }
@@ -156,8 +138,9 @@ fn write_manual_tokens_to_file(tokens: &[lexer_manual::Token], filename: &str) {
             lexer_manual::Token::Bool => "T_BOOL".to_string(),
             lexer_manual::Token::Identifier(s) => format!("T_IDENTIFIER(\"{}\")", s),
             lexer_manual::Token::IntLit(n) => format!("T_INTLIT({})", n),
-            lexer_manual::Token::FloatLit(f) => format!("T_FLOATLIT({})", f),
+            lexer_manual::Token::FloatLit(f, suffix) => format!("T_FLOATLIT({}{})", f, suffix.suffix()),
             lexer_manual::Token::StringLit(s) => format!("T_STRINGLIT(\"{}\")", s),
+            lexer_manual::Token::CharLit(c) => format!("T_CHARLIT('{}')", c.escape_default()),
             lexer_manual::Token::BoolLit(b) => format!("T_BOOLLIT({})", b),
             lexer_manual::Token::Return => "T_RETURN".to_string(),
             lexer_manual::Token::If => "T_IF".to_string(),
@@ -185,42 +168,864 @@ fn write_manual_tokens_to_file(tokens: &[lexer_manual::Token], filename: &str) {
             lexer_manual::Token::Semicolon => "T_SEMICOLON".to_string(),
             lexer_manual::Token::Quotes => "T_QUOTES".to_string(),
             lexer_manual::Token::Colon => "T_COLON".to_string(),
+            lexer_manual::Token::Plus => "T_PLUS".to_string(),
+            lexer_manual::Token::Minus => "T_MINUS".to_string(),
+            lexer_manual::Token::Mult => "T_MULT".to_string(),
+            lexer_manual::Token::Div => "T_DIV".to_string(),
+            lexer_manual::Token::Mod => "T_MOD".to_string(),
+            lexer_manual::Token::Xor => "T_XOR".to_string(),
+            lexer_manual::Token::Not => "T_NOT".to_string(),
+            lexer_manual::Token::BitNot => "T_BITNOT".to_string(),
+            lexer_manual::Token::Question => "T_QUESTION".to_string(),
+            lexer_manual::Token::Dot => "T_DOT".to_string(),
+            lexer_manual::Token::Ellipsis => "T_ELLIPSIS".to_string(),
+            lexer_manual::Token::Arrow => "T_ARROW".to_string(),
+            lexer_manual::Token::PlusPlus => "T_PLUSPLUS".to_string(),
+            lexer_manual::Token::MinusMinus => "T_MINUSMINUS".to_string(),
+            lexer_manual::Token::PlusAssign => "T_PLUSASSIGN".to_string(),
+            lexer_manual::Token::MinusAssign => "T_MINUSASSIGN".to_string(),
+            lexer_manual::Token::MultAssign => "T_MULTASSIGN".to_string(),
+            lexer_manual::Token::DivAssign => "T_DIVASSIGN".to_string(),
+            lexer_manual::Token::ModAssign => "T_MODASSIGN".to_string(),
+            lexer_manual::Token::LShiftAssign => "T_LSHIFTASSIGN".to_string(),
+            lexer_manual::Token::RShiftAssign => "T_RSHIFTASSIGN".to_string(),
+            lexer_manual::Token::AndAssign => "T_ANDASSIGN".to_string(),
+            lexer_manual::Token::XorAssign => "T_XORASSIGN".to_string(),
+            lexer_manual::Token::OrAssign => "T_ORASSIGN".to_string(),
+            lexer_manual::Token::LShift => "T_LSHIFT".to_string(),
+            lexer_manual::Token::RShift => "T_RSHIFT".to_string(),
+            lexer_manual::Token::Hash => "T_HASH".to_string(),
             lexer_manual::Token::Comment(s) => format!("T_COMMENT(\"{}\")", s),
+            lexer_manual::Token::BlockComment(s) => format!("T_BLOCKCOMMENT(\"{}\")", s),
             lexer_manual::Token::Error(s) => format!("T_ERROR(\"{}\")", s),
+            lexer_manual::Token::UnterminatedString { line, col } => format!("T_UNTERMINATED_STRING({}:{})", line, col),
+            lexer_manual::Token::UnterminatedComment { line, col } => format!("T_UNTERMINATED_COMMENT({}:{})", line, col),
         };
         writeln!(file, "{}", token_str).expect("Failed to write to file");
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: {} <source_file>", args[0]);
+/// Value of a `--name=value` flag, if given
+fn flag_value<'a>(flags: &[&'a str], name: &str) -> Option<&'a str> {
+    flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')))
+}
+
+/// `--max-errors=N`: how many errors to print before stopping; 0 or none
+/// for no limit
+fn max_errors_flag(flags: &[&str]) -> Result<Option<usize>, String> {
+    match flag_value(flags, "--max-errors") {
+        None => Ok(None),
+        Some(value) => match value.parse::<usize>() {
+            Ok(0) => Ok(None),
+            Ok(max) => Ok(Some(max)),
+            Err(_) => Err(format!("Invalid --max-errors value '{}' (expected a number)", value)),
+        },
+    }
+}
+
+/// `--timeout=SECONDS`: how long a run may take; none for no limit
+fn timeout_flag(flags: &[&str]) -> Result<Option<Duration>, String> {
+    match flag_value(flags, "--timeout") {
+        None => Ok(None),
+        Some(value) => value
+            .parse::<f64>()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .map(Some)
+            .ok_or_else(|| format!("Invalid --timeout value '{}' (expected a number of seconds)", value)),
+    }
+}
+
+/// `--std=<name>`: the standard the program is held to; Mini-C without it
+fn standard_flag(flags: &[&str]) -> Result<Standard, String> {
+    match flag_value(flags, "--std") {
+        None => Ok(Standard::default()),
+        Some(name) => Standard::from_name(name)
+            .ok_or_else(|| format!("Unknown standard '{}' (available: c89, c99, c11, minic)", name)),
+    }
+}
+
+/// `--policy=<file>`: the features a course bans; none without the flag
+fn policy_flag(flags: &[&str]) -> Result<policy::Policy, String> {
+    match flag_value(flags, "--policy") {
+        None => Ok(policy::Policy::default()),
+        Some(path) => fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| policy::Policy::parse(&text))
+            .map_err(|error| format!("Policy Error: {}: {}", path, error)),
+    }
+}
+
+fn print_artifact(path: &str) {
+    match artifact::Artifact::load(path) {
+        Ok(artifact) => {
+            println!("--- Artifact {} (format v{}) ---", path, artifact.version);
+            println!("Source file: {}", artifact.source_file);
+            println!("AST: {:#?}", artifact.ast);
+            println!("Scope errors: {}", artifact.scope_errors.len());
+            for error in &artifact.scope_errors {
+                println!("  {:?}", error);
+            }
+            println!("Type errors: {}", artifact.type_errors.len());
+            for error in &artifact.type_errors {
+                println!("  {:?}", error);
+            }
+            println!("Type warnings: {}", artifact.type_warnings.len());
+            for warning in &artifact.type_warnings {
+                println!("  {:?}", warning);
+            }
+        }
+        Err(error) => {
+            println!("Artifact Error: could not load {}: {}", path, error);
+        }
+    }
+}
+
+/// `rename <file> --at LINE:COL --to NAME [--apply]`: print the edits, or
+/// write them back to the file with --apply
+fn rename_command(args: &[String]) {
+    let filename = &args[2];
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + 1))
+    };
+    let position = option("--at").and_then(|at| {
+        let (line, column) = at.split_once(':')?;
+        Some((line.parse::<usize>().ok()?, column.parse::<usize>().ok()?))
+    });
+    let (Some((line, column)), Some(new_name)) = (position, option("--to")) else {
+        println!("Usage: {} rename <source_file> --at <line>:<column> --to <name> [--apply]", args[0]);
         return;
+    };
+    let code = fs::read_to_string(filename).expect("Failed to read file");
+    match refactor::rename(&code, line, column, new_name) {
+        Ok(edits) if args.iter().any(|arg| arg == "--apply") => {
+            fs::write(filename, refactor::apply_edits(&code, &edits)).expect("Failed to write file");
+            println!("Renamed {} occurrence(s) in {}", edits.len(), filename);
+        }
+        Ok(edits) => {
+            for edit in &edits {
+                let old = &code[edit.span.clone()];
+                println!("{}:{}:{}: {} -> {}", filename, edit.line, edit.column, old, edit.replacement);
+            }
+        }
+        Err(error) => println!("Rename Error: {}", error),
+    }
+}
+
+/// `type-at <file> --at <line>:<column>`: the innermost expression there
+/// and its type, as an editor shows on hover
+fn type_at_command(args: &[String]) {
+    let position = args
+        .iter()
+        .position(|arg| arg == "--at")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|at| {
+            let (line, column) = at.split_once(':')?;
+            Some((line.parse::<usize>().ok()?, column.parse::<usize>().ok()?))
+        });
+    let Some((line, column)) = position else {
+        println!("Usage: {} type-at <source_file> --at <line>:<column>", args[0]);
+        return;
+    };
+    match semantic::type_at(&args[2], line, column) {
+        Ok(found) => println!("{}:{}:{}: {}: {}", args[2], found.line, found.column, found.text, found.ty),
+        Err(error) => println!("Type-at Error: {}", error),
+    }
+}
+
+/// `project <file>... [-I<dir>]`: each file's diagnostics, checked as
+/// parts of one program, with --symbols what they declare, with --tokens
+/// each file's tokens with its quoted #includes spliced in, and with
+/// --link the files linked into one program
+fn project_command(args: &[String], flags: &[&str]) {
+    let mut project = project::Project::new();
+    for directory in flags.iter().filter_map(|flag| flag.strip_prefix("-I")) {
+        project.add_include_path(directory);
+    }
+    project.set_gnu_extensions(flags.contains(&"-fgnu-extensions"));
+    project.set_unicode_identifiers(flags.contains(&"-funicode-identifiers"));
+    match policy_flag(flags) {
+        Ok(policy) => project.set_policy(policy),
+        Err(message) => {
+            println!("{}", message);
+            return;
+        }
+    }
+    // What follows `--` is the linked program's arguments
+    let end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+    let files: Vec<&String> = args[2..end].iter().filter(|arg| !arg.starts_with('-')).collect();
+    for file in &files {
+        if let Err(error) = project.open(file.as_str()) {
+            println!("Project Error: {}: {}", file, error);
+        }
+    }
+    let max_errors = match max_errors_flag(flags) {
+        Ok(max_errors) => max_errors,
+        Err(message) => {
+            println!("{}", message);
+            return;
+        }
+    };
+    let paths: Vec<std::path::PathBuf> = project.files().map(|path| path.to_path_buf()).collect();
+    if flags.contains(&"--tokens") {
+        for path in &paths {
+            for (token, span) in project.expanded_tokens(path).unwrap_or_default() {
+                println!("{}: {:?}", project.source_map().describe(&span), token);
+            }
+        }
+    }
+    let mut all_diagnostics = Vec::new();
+    let mut functions = HashMap::new();
+    for path in &paths {
+        if let Some(id) = project.file_id(path)
+            && let Some(unit) = project.spanned_unit(path)
+        {
+            functions.insert(id, diagnostics::function_spans(unit));
+        }
+        all_diagnostics.extend(project.diagnostics(path).unwrap_or_default().iter().cloned());
+    }
+    let mut report = Report::new(max_errors);
+    report.add_files(project.source_map(), &all_diagnostics, &functions);
+    print!("{}", report.render());
+    if flags.contains(&"--link") {
+        link_project(&mut project, args, flags);
+    }
+    if flags.contains(&"--symbols") {
+        for symbol in project.symbols() {
+            let kind = match symbol.kind {
+                project::GlobalKind::Function { defined: true } => "function",
+                project::GlobalKind::Function { defined: false } => "function declaration",
+                project::GlobalKind::Variable => "variable",
+            };
+            println!("{}:{}: {} {}", symbol.file.display(), symbol.line.unwrap_or_default(), kind, symbol.name);
+        }
+    }
+}
+
+/// The project's files linked into one program: with --emit, given to an
+/// emitter; with --run, run after any arguments after `--`
+fn link_project(project: &mut project::Project, args: &[String], flags: &[&str]) {
+    let module = match project.link() {
+        Ok(module) => module,
+        Err(errors) => {
+            let mut report = Report::new(None);
+            report.add_files(project.source_map(), &errors, &HashMap::new());
+            print!("Link failed:\n{}", report.render());
+            std::process::exit(1);
+        }
+    };
+    // The module has no one source text, so the backends see none
+    if let Some(target) = flag_value(flags, "--emit") {
+        let registry = plugin_registry("", flags);
+        match registry.emit(target, &module.unit, "", &[]) {
+            Some(output) => print!("{}", output),
+            None => println!("Unknown emit target '{}' (available: {})", target, registry.emit_target_names().join(", ")),
+        }
+    } else if flags.contains(&"--run") {
+        let arguments = match args.iter().position(|arg| arg == "--") {
+            Some(end) => &args[end + 1..],
+            None => &args[args.len()..],
+        };
+        match interpreter::Machine::with_arguments(&module.unit, "", arguments) {
+            Ok(machine) => run_machine(machine, flags),
+            Err(error) => {
+                println!("{}", error.render());
+                std::process::exit(1);
+            }
+        }
+    } else {
+        let files: std::collections::HashSet<_> = module.files.iter().collect();
+        println!("Linked {} file(s) into {} declaration(s)", files.len(), module.unit.external_declarations.len());
     }
-    let filename = &args[1];
+}
+
+/// `reduce <file> --check "<command>"`: shrink the file to a small one the
+/// command still succeeds on, `%s` in it standing for the file, and write it
+/// next to the original
+fn reduce_command(args: &[String]) {
+    let filename = &args[2];
+    let check = args.iter().position(|arg| arg == "--check").and_then(|i| args.get(i + 1));
+    let Some(check) = check else {
+        println!("Usage: {} reduce <source_file> --check \"<command using %s>\"", args[0]);
+        return;
+    };
     let code = fs::read_to_string(filename).expect("Failed to read file");
-    let source_lines: Vec<String> = code.lines().map(|line| line.to_string()).collect();
+    // Candidates go by the same name, for checks that look for it
+    let path = std::path::Path::new(filename);
+    let scratch_dir = env::temp_dir().join(format!("minic-reduce-{}", std::process::id()));
+    let scratch = scratch_dir.join(path.file_name().unwrap_or_default());
+    fs::create_dir_all(&scratch_dir).expect("Failed to create a scratch directory");
+    let interesting = |text: &str| {
+        fs::write(&scratch, text).is_ok()
+            && std::process::Command::new("sh")
+                .arg("-c")
+                .arg(check.replace("%s", &scratch.display().to_string()))
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success())
+    };
+    if !interesting(&code) {
+        println!("Reduce Error: the check doesn't succeed on {} to begin with", filename);
+        let _ = fs::remove_dir_all(&scratch_dir);
+        return;
+    }
+    let reduction = reduce::reduce(&code, interesting);
+    let _ = fs::remove_dir_all(&scratch_dir);
+    let output = path.with_extension("reduced.c");
+    fs::write(&output, &reduction.text).expect("Failed to write file");
+    println!(
+        "Reduced {} from {} to {} line(s) in {} check(s); written to {}",
+        filename,
+        code.lines().count(),
+        reduction.text.lines().count(),
+        reduction.checks,
+        output.display()
+    );
+}
+
+/// `gen-fuzz [--seed=N] [--count=N] [--check]`: print random programs, or
+/// with --check test the compiler on them, keeping the ones that fail
+fn gen_fuzz_command(args: &[String], flags: &[&str]) {
+    let number = |name: &str| match flag_value(flags, name) {
+        None => Ok(None),
+        Some(value) => value
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| format!("Invalid {} value '{}' (expected a number)", name, value)),
+    };
+    let (seed, count) = match (number("--seed"), number("--count")) {
+        (Ok(seed), Ok(count)) => (seed, count),
+        (Err(error), _) | (_, Err(error)) => {
+            println!("Usage: {} gen-fuzz [--seed=<n>] [--count=<n>] [--check]", args[0]);
+            println!("{}", error);
+            return;
+        }
+    };
+    let seed = seed.unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        now.as_secs()
+    });
+    if !flags.contains(&"--check") {
+        for seed in seed..seed + count.unwrap_or(1) {
+            print!("{}", fuzz::generate(seed));
+        }
+        return;
+    }
+    let count = count.unwrap_or(100);
+    let scratch = env::temp_dir().join(format!("minic-fuzz-{}", std::process::id()));
+    let native = difftest::Native::find(scratch.clone());
+    if native.is_none() {
+        println!("No C compiler found (set CC); runs are only checked to finish in the interpreter");
+    }
+    let mut failed = 0;
+    for seed in seed..seed + count {
+        let program = fuzz::generate(seed);
+        let divergences = fuzz::check(&program, native.as_ref().map(|native| native as &dyn difftest::Backend));
+        if divergences.is_empty() {
+            continue;
+        }
+        failed += 1;
+        let path = format!("fuzz-{}.c", seed);
+        fs::write(&path, &program).expect("Failed to write file");
+        println!("seed {} ({}):", seed, path);
+        for divergence in &divergences {
+            println!("  {}: {}", divergence.check, divergence.detail);
+        }
+    }
+    let _ = fs::remove_dir_all(&scratch);
+    println!("{} of {} program(s) from seed {} failed a check", failed, count, seed);
+}
+
+/// `difftest <file>... [-- <arguments>...]`: run each program in the
+/// interpreter and built by the system C compiler, with the same arguments,
+/// and report where they differ
+fn difftest_command(args: &[String], flags: &[&str]) {
+    let (files, arguments) = match args.iter().position(|arg| arg == "--") {
+        Some(end) => (&args[2..end], &args[end + 1..]),
+        None => (&args[2..], &args[args.len()..]),
+    };
+    let files: Vec<&String> = files.iter().filter(|arg| !arg.starts_with('-')).collect();
+    let scratch = env::temp_dir().join(format!("minic-difftest-{}", std::process::id()));
+    let Some(mut native) = difftest::Native::find(scratch.clone()) else {
+        println!("Difftest Error: no C compiler found (set CC) to compare the interpreter with");
+        return;
+    };
+    native.fast_math = flags.contains(&"-ffast-math");
+    let interpreter = difftest::Interpreter {
+        gnu_extensions: flags.contains(&"-fgnu-extensions"),
+        fast_math: flags.contains(&"-ffast-math"),
+    };
+    let mut diverged = 0;
+    for file in &files {
+        let code = fs::read_to_string(file).expect("Failed to read file");
+        let comparison = difftest::compare(&code, arguments, &[&native, &interpreter]);
+        if comparison.diverges() {
+            diverged += 1;
+            println!("{}: runs differ", file);
+            for line in comparison.render().lines() {
+                println!("  {}", line);
+            }
+        } else if let Some((_, Ok(outcome))) = comparison.runs.first() {
+            println!("{}: same output, exit code {}", file, outcome.exit_code);
+        }
+    }
+    let _ = fs::remove_dir_all(&scratch);
+    println!("{} of {} program(s) ran differently", diverged, files.len());
+}
+
+/// `doc <file> [--format=markdown|html] [--output=<file>]`: the file's
+/// declarations and their doc comments
+fn doc_command(args: &[String], flags: &[&str]) {
+    let filename = &args[2];
+    let source = Rc::new(SourceFile::new(filename, fs::read_to_string(filename).expect("Failed to read file")));
+    let mut parser = parser::Parser::with_spans(lexer_regex::lex_with_regex_positions(source.text(), Default::default()));
+    parser.set_gnu_extensions(flags.contains(&"-fgnu-extensions"));
+    let ast = match parser.parse() {
+        Ok(ast) => ast,
+        Err(errors) => {
+            print_parse_errors(&errors, &parser);
+            return;
+        }
+    };
+    let items = doc::extract(&ast, &source);
+    let output = match flag_value(flags, "--format").unwrap_or("markdown") {
+        "markdown" | "md" => doc::render_markdown(filename, &items),
+        "html" => doc::render_html(filename, &items),
+        format => {
+            println!("Unknown doc format '{}' (available: markdown, html)", format);
+            return;
+        }
+    };
+    match flag_value(flags, "--output") {
+        Some(path) => match fs::write(path, output) {
+            Ok(()) => println!("Documentation written to {}", path),
+            Err(error) => println!("Doc Error: could not write {}: {}", path, error),
+        },
+        None => print!("{}", output),
+    }
+}
+
+/// `grade <file> --spec <spec.toml> [--json]`: mark a program against the
+/// checks a spec lists, and print the score
+fn grade_command(args: &[String], flags: &[&str]) {
+    let filename = &args[2];
+    let spec_path = match args.iter().position(|arg| arg == "--spec").and_then(|i| args.get(i + 1)) {
+        Some(path) => path.as_str(),
+        None => match flag_value(flags, "--spec") {
+            Some(path) => path,
+            None => {
+                println!("Usage: {} grade <source_file> --spec <spec.toml> [--json] [-fgnu-extensions]", args[0]);
+                return;
+            }
+        },
+    };
+    let spec = match fs::read_to_string(spec_path).map_err(|error| error.to_string()).and_then(|text| grade::Spec::parse(&text)) {
+        Ok(spec) => spec,
+        Err(error) => {
+            println!("Grade Error: {}: {}", spec_path, error);
+            return;
+        }
+    };
+    let mut project = project::Project::new();
+    project.set_gnu_extensions(flags.contains(&"-fgnu-extensions"));
+    *project.lints_mut() = plugin_registry(filename, flags);
+    if let Err(error) = project.open(filename.as_str()) {
+        println!("Grade Error: {}: {}", filename, error);
+        return;
+    }
+    let grade = grade::grade(&mut project, std::path::Path::new(filename), &spec);
+    if flags.contains(&"--json") {
+        println!("{}", grade.to_json());
+    } else {
+        print!("{}", grade.render());
+    }
+}
+
+/// `bench [--scaling-size=<KB>] [--lexers]`: time each front-end phase
+/// over the bundled inputs, then check none slows down faster than its
+/// input grows; exits with 1 if one does. With `--lexers`, time each lexer
+/// instead
+fn bench_command(args: &[String], flags: &[&str]) {
+    let bytes = match flag_value(flags, "--scaling-size") {
+        None => bench::DEFAULT_SCALING_BYTES,
+        Some(size) => match size.parse::<usize>() {
+            Ok(kilobytes) if kilobytes > 0 => kilobytes * 1024,
+            _ => {
+                println!("Usage: {} bench [--scaling-size=<KB>] [--lexers]", args[0]);
+                println!("Invalid --scaling-size value '{}' (expected a positive number)", size);
+                return;
+            }
+        },
+    };
+    if cfg!(debug_assertions) {
+        println!("(a debug build; build with --release for representative numbers)");
+    }
+    if flags.contains(&"--lexers") {
+        print!("{}", bench::render_lexers());
+        return;
+    }
+    print!("{}", bench::render_throughput());
+    let scaling = bench::Scaling::measure(bytes);
+    print!("{}", scaling.render());
+    if !scaling.superlinear().is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// `stream <file> [--emit=cfg]`: lex, parse and lower a file too large to
+/// hold whole a declaration at a time, printing what went through
+fn stream_command(args: &[String], flags: &[&str]) {
+    let emit_cfg = match flag_value(flags, "--emit") {
+        None => false,
+        Some("cfg") => true,
+        Some(target) => {
+            println!("Unknown emit target '{}' for stream (available: cfg)", target);
+            return;
+        }
+    };
+    let code = fs::read_to_string(&args[2]).expect("Failed to read file");
+    let options = lexer_regex::LexOptions {
+        digit_separators: flags.contains(&"-fdigit-separators"),
+        standard: Standard::default(),
+        unicode_identifiers: flags.contains(&"-funicode-identifiers"),
+    };
+    let summary = stream::compile(&code, options, flags.contains(&"-fgnu-extensions"), |graph| {
+        if emit_cfg {
+            println!("{}", graph.render());
+        }
+    });
+    for error in &summary.errors {
+        println!("Parse Error: {:?}", error);
+    }
+    print!("{}", summary.render());
+}
+
+/// `run <file> [-- <arguments>...]`: interpret the program, printing its
+/// output as it goes, then how it exited and what it left on the heap
+fn run_command(args: &[String], flags: &[&str]) {
+    let standard = match standard_flag(flags) {
+        Ok(standard) => standard,
+        Err(message) => {
+            println!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    let source = Rc::new(SourceFile::new(&args[2], fs::read_to_string(&args[2]).expect("Failed to read file")));
+    let code = source.text();
+    let options = lexer_regex::LexOptions {
+        digit_separators: flags.contains(&"-fdigit-separators"),
+        standard,
+        unicode_identifiers: flags.contains(&"-funicode-identifiers"),
+    };
+    let mut parser = parser::Parser::with_spans(lexer_regex::lex_with_regex_positions(code, options));
+    parser.set_gnu_extensions(flags.contains(&"-fgnu-extensions"));
+    parser.set_standard(standard);
+    let mut ast = match parser.parse() {
+        Ok(ast) => ast,
+        Err(errors) => {
+            print_parse_errors(&errors, &parser);
+            std::process::exit(1);
+        }
+    };
+    // A program the checks reject isn't run, as a compiler wouldn't build it
+    let registry = plugin_registry(&args[2], flags);
+    let mut errors = analyze(&mut ast, &source, &registry, &policy::Policy::default(), flags, standard, false).diagnostics;
+    errors.retain(|diagnostic| diagnostic.severity != diagnostics::Severity::Warning);
+    if !errors.is_empty() {
+        let mut report = Report::new(None);
+        report.add_file(None, &errors, &diagnostics::function_spans(&ast));
+        print!("{}", report.render());
+        std::process::exit(1);
+    }
+    let arguments = match args.iter().position(|arg| arg == "--") {
+        Some(end) => &args[end + 1..],
+        None => &args[args.len()..],
+    };
+    match interpreter::Machine::with_arguments(&ast, code, arguments) {
+        Ok(machine) => run_machine(machine, flags),
+        Err(error) => {
+            println!("{}", error.render());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// What the checks after parsing found in a program
+struct Analysis {
+    diagnostics: Vec<Diagnostic>, // after suppressions, with -Werror=<name> applied
+    scope_errors: Vec<ScopeError>,
+    type_errors: Vec<TypeError>,
+    type_warnings: Vec<TypeWarning>,
+}
+
+/// The phases compile mode, `run` and an ICE replay all put a parsed
+/// program through: the AST passes, which fold constants, then validation,
+/// scope analysis, type checking, lints and the policy. Verbose prints
+/// what each phase finds, as compile mode does.
+fn analyze(
+    ast: &mut parser::ast::TranslationUnit,
+    source: &Rc<SourceFile>,
+    registry: &plugins::PluginRegistry,
+    policy: &policy::Policy,
+    flags: &[&str],
+    standard: Standard,
+    verbose: bool,
+) -> Analysis {
+    ice::set_phase("AST passes");
+    registry.run_passes(ast);
+    if verbose {
+        println!("AST: {:#?}", ast);
+    }
+
+    ice::set_phase("AST validation");
+    validate(ast, verbose);
+
+    if verbose {
+        println!("\n--- Scope Analysis ---");
+    }
+    ice::set_phase("scope analysis");
+    let mut scope_analyzer = scope::ScopeAnalyzer::with_source(source.clone());
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let result = scope_analyzer.analyze_translation_unit(ast);
+    if let Err(errors) = &result {
+        diagnostics.extend(errors.iter().map(Diagnostic::from));
+    }
+    if verbose {
+        match &result {
+            Ok(()) => println!("Scope analysis completed successfully - no errors found!"),
+            Err(errors) => println!("Scope analysis found {} error(s)", errors.len()),
+        }
+        scope_analyzer.print_symbol_table();
+    }
+
+    // Type checking can still find errors even if scope analysis had issues
+    let scope_errors = scope_analyzer.get_errors().to_vec();
+    if verbose {
+        println!("\n--- Type Checking ---");
+    }
+    ice::set_phase("type checking");
+    let mut type_checker = type_checker::TypeChecker::new(scope_analyzer);
+    type_checker.set_require_main(!flags.contains(&"-c"));
+    type_checker.set_standard(standard);
+    type_checker.set_warn_implicit_fallthrough(flags.contains(&"-Wimplicit-fallthrough"));
+    type_checker.set_warn_sign_compare(flags.contains(&"-Wsign-compare"));
+    type_checker.set_warn_parentheses(flags.contains(&"-Wparentheses"));
+    let result = type_checker.check_translation_unit(ast);
+    if let Err(errors) = &result {
+        diagnostics.extend(errors.iter().map(Diagnostic::from));
+    }
+    let warnings = type_checker.get_warnings();
+    diagnostics.extend(warnings.iter().map(Diagnostic::from));
+    if verbose {
+        match &result {
+            Ok(()) => println!("Type checking completed successfully - no errors found!"),
+            Err(errors) => println!("Type checking found {} error(s)", errors.len()),
+        }
+        if !warnings.is_empty() {
+            println!("Type checking produced {} warning(s)", warnings.len());
+        }
+    }
+
+    ice::set_phase("lints");
+    let lint_diagnostics = registry.run_lints(ast);
+    if verbose && !lint_diagnostics.is_empty() {
+        println!("Lints produced {} warning(s)", lint_diagnostics.len());
+    }
+    diagnostics.extend(lint_diagnostics.iter().map(Diagnostic::from));
+
+    ice::set_phase("policy");
+    diagnostics.extend(policy.check(Some(ast), source.text()).iter().map(Diagnostic::from));
+
+    ice::set_phase("diagnostics");
+    suppress::apply(&mut diagnostics, &suppress::suppressions(source.text()));
+    let promoted: Vec<&str> = flags.iter().filter_map(|flag| flag.strip_prefix("-Werror=")).collect();
+    diagnostics::promote_warnings(&mut diagnostics, &promoted);
+    Analysis {
+        diagnostics,
+        scope_errors,
+        type_errors: type_checker.get_errors().to_vec(),
+        type_warnings: warnings.to_vec(),
+    }
+}
+
+/// `Parse Error (line 3, column 14): ...`, with the place when the parser
+/// knows it
+fn print_parse_errors(errors: &[parser::ast::ParseError], parser: &parser::Parser) {
+    for (error, span) in errors.iter().zip(parser.error_spans()) {
+        match span {
+            Some(span) => println!("Parse Error (line {}, column {}): {:?}", span.line, span.col, error),
+            None => println!("Parse Error: {:?}", error),
+        }
+    }
+}
+
+/// Run a program set up to run, as `run` does, then exit with the status
+/// main returned, or 1 if the run stopped on an error
+fn run_machine(mut machine: interpreter::Machine, flags: &[&str]) -> ! {
+    // Out-of-bounds accesses are reported at exit rather than stopping the run
+    machine.memory.memcheck = flags.contains(&"--memcheck");
+    // The same digits from math.h on every machine, rather than the host libm's
+    machine.softfloat = flags.contains(&"--softfloat");
+    // Signed overflow, bad shifts and out-of-range conversions stop the run
+    // where they happen rather than wrapping
+    machine.check_overflow = flags.contains(&"--check-overflow");
+    // Float arithmetic as IEEE 754 has it unless this relaxes it
+    machine.fast_math = flags.contains(&"-ffast-math");
+    // --trace logs each statement run to stdout, between the program's output;
+    // --trace=FILE keeps it apart
+    let mut trace: Option<Box<dyn Write>> = match flag_value(flags, "--trace") {
+        Some(path) => Some(Box::new(fs::File::create(path).expect("Failed to create file"))),
+        None if flags.contains(&"--trace") => Some(Box::new(std::io::stdout())),
+        None => None,
+    };
+    machine.tracing = trace.is_some();
+    // Checked between statements, so a run is never stopped partway through one
+    let timeout = match timeout_flag(flags) {
+        Ok(timeout) => timeout,
+        Err(message) => {
+            println!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let mut stdout = std::io::stdout();
+    let result = loop {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            stdout.write_all(&machine.take_output()).expect("Failed to write output");
+            stdout.flush().expect("Failed to write output");
+            println!("\n--- Program timed out after {} second(s) ---", timeout.unwrap_or_default().as_secs_f64());
+            print!("{}", machine.backtrace());
+            std::process::exit(124); // as timeout(1) exits
+        }
+        let status = machine.step();
+        stdout.write_all(&machine.take_output()).expect("Failed to write output");
+        if let Some(trace) = &mut trace {
+            stdout.flush().expect("Failed to write output");
+            for line in machine.take_trace() {
+                writeln!(trace, "{}", line).expect("Failed to write trace");
+            }
+        }
+        match status {
+            Ok(interpreter::Status::Running) => {}
+            Ok(interpreter::Status::Exited(code)) => break Ok(code),
+            Err(error) => break Err(error),
+        }
+    };
+    stdout.flush().expect("Failed to write output");
+    let status = match result {
+        Ok(code) => {
+            println!("\n--- Program exited with code {} ---", code);
+            print!("{}", machine.memory.report());
+            // The system keeps the low byte, as it does for a compiled program
+            code as i32
+        }
+        Err(error) => {
+            println!("\n{}", error.render());
+            print!("{}", machine.memory.render_issues());
+            1
+        }
+    };
+    stdout.flush().expect("Failed to write output");
+    std::process::exit(status);
+}
+
+/// Lints, passes and emit targets beyond the built-in phases
+fn plugin_registry(filename: &str, flags: &[&str]) -> plugins::PluginRegistry {
+    let mut registry = plugins::PluginRegistry::new();
+    registry.register_emit_target(Box::new(html::HtmlEmitter));
+    registry.register_emit_target(Box::new(ast_text::AstTextEmitter));
+    registry.register_emit_target(Box::new(pretty::CEmitter));
+    registry.register_emit_target(Box::new(layout::LayoutEmitter));
+    registry.register_emit_target(Box::new(callgraph::StackUsageEmitter::new(filename)));
+    registry.register_emit_target(Box::new(lower::CfgEmitter));
+    registry.register_emit_target(Box::new(precedence::PrecedenceEmitter));
+    registry.register_emit_target(Box::new(semantic_tokens::SemanticTokensEmitter));
+    let clone_min_size = flag_value(flags, "--clone-min-size")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(lints::clones::DEFAULT_MIN_SIZE);
+    registry.register_lint(Box::new(lints::clones::CloneLint::new(clone_min_size)));
+    registry.register_lint(Box::new(lints::dead_globals::DeadGlobalsLint));
+    registry.register_lint(Box::new(lints::escape::EscapeLint));
+    registry.register_lint(Box::new(lints::uninit_fields::UninitFieldsLint));
+    if flags.contains(&"--gc-unused") {
+        registry.register_pass(Box::new(lints::dead_globals::StripDeadGlobals));
+    }
+    registry
+}
+
+/// Catch parser bugs before later phases act on a malformed tree
+fn validate(ast: &parser::ast::TranslationUnit, verbose: bool) {
+    if cfg!(debug_assertions)
+        && let Err(violations) = parser::validate::AstValidator::new().validate(ast)
+    {
+        if verbose {
+            for violation in &violations {
+                println!("AST Validation Error ({}): {}", violation.node, violation.message);
+            }
+        }
+        panic!("parser produced an invalid AST ({} violation(s))", violations.len());
+    }
+}
+
+/// Compile a file: lex, parse and check it, printing what each phase finds.
+/// False when it stopped on a bad flag or emitted an error.
+fn compile(filename: &str, flags: &[&str]) -> bool {
+    let standard = match standard_flag(flags) {
+        Ok(standard) => standard,
+        Err(message) => {
+            println!("{}", message);
+            return false;
+        }
+    };
+    let max_errors = match max_errors_flag(flags) {
+        Ok(max_errors) => max_errors,
+        Err(message) => {
+            println!("{}", message);
+            return false;
+        }
+    };
+    let policy = match policy_flag(flags) {
+        Ok(policy) => policy,
+        Err(message) => {
+            println!("{}", message);
+            return false;
+        }
+    };
+    let source = Rc::new(SourceFile::new(filename, fs::read_to_string(filename).expect("Failed to read file")));
+    let code = source.text();
 
     // Run regex lexer
+    ice::set_phase("lexing");
     println!("--- Tokens (Regex Lexer) ---");
-    let tokens_regex = lexer_regex::lex_with_regex(&code);
-    for t in &tokens_regex {
-        println!("{:?}", t);
+    let lex_options = lexer_regex::LexOptions {
+        digit_separators: flags.contains(&"-fdigit-separators"),
+        standard,
+        unicode_identifiers: flags.contains(&"-funicode-identifiers"),
+    };
+    let spanned_regex = lexer_regex::lex_with_regex_positions(code, lex_options);
+    for t in &spanned_regex {
+        println!("{}:{}\t{:?}", t.span.line, t.span.col, t.token);
     }
+    let tokens_regex: Vec<lexer_regex::Token> = spanned_regex.iter().map(|t| t.token.clone()).collect();
 
     // Run manual lexer
     println!("\n--- Tokens (Manual Lexer) ---");
-    let tokens_manual = lexer_manual::lex_manual(&code);
-    for t in &tokens_manual {
-        println!("{:?}", t);
+    let spanned_manual = lexer_manual::lex_manual_positions(code, lex_options);
+    for t in &spanned_manual {
+        println!("{}:{}\t{:?}", t.span.line, t.span.col, t.token);
     }
+    let tokens_manual: Vec<lexer_manual::Token> = spanned_manual.into_iter().map(|t| t.token).collect();
 
     // Run rules-based lexer
     println!("\n--- Tokens (Rules-based Lexer) ---");
-    let tokens_rules = lex(&code);
+    let tokens_rules = rules::lex(code, lex_options);
     for t in &tokens_rules {
-        println!("T_{:?}", t);
+        println!("{}:{}\tT_{:?}", t.span.line, t.span.col, t.token);
     }
 
     // Write tokens to files
@@ -231,117 +1036,324 @@ fn main() {
     println!("- regex_tokens.txt (Regex-based lexer)");
     println!("- manual_tokens.txt (Manual lexer)");
 
+    // A string or comment left open leaves nothing after it worth parsing
+    let unterminated = Diagnostic::unterminated(&spanned_regex);
+    if !unterminated.is_empty() {
+        println!("\n--- Diagnostics ---");
+        let mut report = Report::new(max_errors);
+        report.add_file(None, &unterminated, &[]);
+        print!("{}", report.render());
+        return false;
+    }
+
     // Parse using regex lexer tokens
     println!("\n--- Parsing AST ---");
     println!("Number of tokens: {}", tokens_regex.len());
-    let mut parser = parser::Parser::new(tokens_regex);
+    let registry = plugin_registry(filename, flags);
+
+    ice::set_phase("parsing");
+    let mut parser = parser::Parser::with_spans(spanned_regex);
+    parser.set_gnu_extensions(flags.contains(&"-fgnu-extensions"));
+    parser.set_standard(standard);
     match parser.parse() {
-        Ok(ast) => {
-            println!("AST: {:#?}", ast);
-
-            // Perform scope analysis
-            println!("\n--- Scope Analysis ---");
-            let mut scope_analyzer = scope::ScopeAnalyzer::new();
-            match scope_analyzer.analyze_translation_unit(&ast) {
-                Ok(()) => {
-                    println!("Scope analysis completed successfully - no errors found!");
-                    scope_analyzer.print_symbol_table();
-                }
-                Err(errors) => {
-                    println!("Scope analysis found {} error(s):", errors.len());
-                    for error in errors {
-                        match error {
-                            scope::ScopeError::UndeclaredVariable(name) => {
-                                println!("  ERROR: Undeclared variable '{}' accessed", name);
-                            }
-                            scope::ScopeError::UndefinedFunctionCalled(name) => {
-                                println!("  ERROR: Undefined function '{}' called", name);
-                            }
-                            scope::ScopeError::VariableRedefinition(name) => {
-                                println!("  ERROR: Variable '{}' redefined in same scope", name);
-                            }
-                            scope::ScopeError::FunctionPrototypeRedefinition(name) => {
-                                println!("  ERROR: Function '{}' redefined", name);
-                            }
+        Ok(mut ast) => {
+            let analysis = analyze(&mut ast, &source, &registry, &policy, flags, standard, true);
+            let diagnostics = analysis.diagnostics;
+            println!("\n--- Diagnostics ---");
+            let mut report = Report::new(max_errors);
+            report.add_file(None, &diagnostics, &diagnostics::function_spans(&ast));
+            print!("{}", report.render());
+
+            ice::set_phase("emit");
+            if let Some(target) = flag_value(flags, "--emit") {
+                match registry.emit(target, &ast, code, &diagnostics) {
+                    Some(output) => match flag_value(flags, "--output") {
+                        Some(path) => match fs::write(path, output) {
+                            Ok(()) => println!("\n{} output written to {}", target, path),
+                            Err(error) => println!("\nEmit Error: could not write {}: {}", path, error),
+                        },
+                        None => {
+                            println!("\n--- Emit ({}) ---", target);
+                            print!("{}", output);
                         }
+                    },
+                    None => {
+                        println!("\nUnknown emit target '{}' (available: {})", target, registry.emit_target_names().join(", "));
                     }
-                    scope_analyzer.print_symbol_table();
                 }
             }
-            
-            // Perform type checking regardless of scope analysis errors
-            // (Type checking can still find errors even if scope analysis had issues)
-            println!("\n--- Type Checking ---");
-            let mut type_checker = type_checker::TypeChecker::new(scope_analyzer, source_lines);
-            match type_checker.check_translation_unit(&ast) {
-                Ok(()) => {
-                    println!("Type checking completed successfully - no errors found!");
-                }
-                Err(errors) => {
-                    println!("Type checking found {} error(s):", errors.len());
-                    for type_error in errors {
-                        let line_label = type_error
-                            .line
-                            .map(|line| line.to_string())
-                            .unwrap_or_else(|| "unknown".to_string());
-                        let context_suffix = if type_error.context.is_empty() {
-                            String::new()
-                        } else {
-                            format!(" [context: {}]", type_error.context)
-                        };
-                        match type_error.error {
-                            type_checker::TypeChkError::ErroneousVarDecl => {
-                                println!("  ERROR (line {}): Erroneous variable declaration{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::FnCallParamCount => {
-                                println!("  ERROR (line {}): Function call parameter count mismatch{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::FnCallParamType => {
-                                println!("  ERROR (line {}): Function call parameter type mismatch{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::ErroneousReturnType => {
-                                println!("  ERROR (line {}): Erroneous return type{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::ExpressionTypeMismatch => {
-                                println!("  ERROR (line {}): Expression type mismatch{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::ExpectedBooleanExpression => {
-                                println!("  ERROR (line {}): Expected boolean expression{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::ErroneousBreak => {
-                                println!("  ERROR (line {}): Break statement outside of loop{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::NonBooleanCondStmt => {
-                                println!("  ERROR (line {}): Non-boolean condition in control statement{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::EmptyExpression => {
-                                println!("  ERROR (line {}): Empty expression{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::AttemptedBoolOpOnNonBools => {
-                                println!("  ERROR (line {}): Attempted boolean operation on non-boolean types{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::AttemptedBitOpOnNonNumeric => {
-                                println!("  ERROR (line {}): Attempted bitwise operation on non-numeric types{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::AttemptedShiftOnNonInt => {
-                                println!("  ERROR (line {}): Attempted shift operation on non-integer types{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::AttemptedAddOpOnNonNumeric => {
-                                println!("  ERROR (line {}): Attempted arithmetic operation on non-numeric types{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::AttemptedExponentiationOfNonNumeric => {
-                                println!("  ERROR (line {}): Attempted exponentiation on non-numeric types{}", line_label, context_suffix);
-                            }
-                            type_checker::TypeChkError::ReturnStmtNotFound => {
-                                println!("  ERROR (line {}): Return statement not found in non-void function{}", line_label, context_suffix);
-                            }
-                        }
-                    }
+
+            ice::set_phase("artifact");
+            if let Some(path) = flag_value(flags, "--emit-artifact") {
+                let mut artifact = artifact::Artifact::new(filename, ast);
+                artifact.scope_errors = analysis.scope_errors;
+                artifact.type_errors = analysis.type_errors;
+                artifact.type_warnings = analysis.type_warnings;
+                match artifact.save(path) {
+                    Ok(()) => println!("\nArtifact written to {}", path),
+                    Err(error) => println!("\nArtifact Error: could not write {}: {}", path, error),
                 }
             }
+            !report.has_errors()
+        }
+        Err(errors) => {
+            print_parse_errors(&errors, &parser);
+            let spans = parser.error_spans();
+            let mut diagnostics: Vec<Diagnostic> =
+                errors.iter().zip(spans).map(|(error, span)| Diagnostic::parse_error(error, span)).collect();
+            diagnostics.extend(policy.check(None, code).iter().map(Diagnostic::from));
+            let mut report = Report::new(max_errors);
+            report.add_file(None, &diagnostics, &[]);
+            print!("\n{}", report.render());
+            false
         }
+    }
+}
+
+/// The phases after lexing, run on `tokens` as `compile` runs them but
+/// printing nothing, to see whether an internal compiler error happens again
+fn replay(tokens: &[lexer_regex::Token], filename: &str, flags: &[&str], standard: Standard) {
+    let code = ice::source_text(tokens);
+    let source = Rc::new(SourceFile::new(filename, code.as_str()));
+    ice::set_phase("parsing");
+    let mut parser = parser::Parser::new(tokens.to_vec());
+    parser.set_gnu_extensions(flags.contains(&"-fgnu-extensions"));
+    parser.set_standard(standard);
+    let Ok(mut ast) = parser.parse() else {
+        return;
+    };
+    let registry = plugin_registry(filename, flags);
+    let policy = policy_flag(flags).unwrap_or_default();
+    let analysis = analyze(&mut ast, &source, &registry, &policy, flags, standard, false);
+    ice::set_phase("emit");
+    if let Some(target) = flag_value(flags, "--emit") {
+        registry.emit(target, &ast, &code, &analysis.diagnostics);
+    }
+}
+
+/// How long one run of the phases on a reduced input may take
+const REPLAY_LIMIT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Report an internal compiler error in compiling `filename`, with the input
+/// reduced to what still sets it off written next to it
+fn report_ice(ice: &ice::Ice, filename: &str, flags: &[&str]) {
+    print!("\n{}", ice.render(filename));
+    let Ok(code) = fs::read_to_string(filename) else {
+        return;
+    };
+    let standard = flag_value(flags, "--std").and_then(Standard::from_name).unwrap_or_default();
+    let lex_options = lexer_regex::LexOptions {
+        digit_separators: flags.contains(&"-fdigit-separators"),
+        standard,
+        unicode_identifiers: flags.contains(&"-funicode-identifiers"),
+    };
+    let again = |run: Box<dyn FnOnce() + Send>| {
+        ice::catch_within(REPLAY_LIMIT, run).is_some_and(|result| result.err().is_some_and(|again| again.same_bug(ice)))
+    };
+    let owned: Vec<String> = flags.iter().map(|flag| flag.to_string()).collect();
+    let replay_tokens = |tokens: &[lexer_regex::Token]| -> Box<dyn FnOnce() + Send> {
+        let (tokens, filename, owned) = (tokens.to_vec(), filename.to_string(), owned.clone());
+        Box::new(move || {
+            let flags: Vec<&str> = owned.iter().map(String::as_str).collect();
+            replay(&tokens, &filename, &flags, standard)
+        })
+    };
+    let (reduced, size, tokens) = if ice.phase == "lexing" {
+        let lex_all = |code: String| -> Box<dyn FnOnce() + Send> {
+            Box::new(move || {
+                lexer_regex::lex_with_regex_options(&code, lex_options);
+                lexer_manual::lex_manual_options(&code, lex_options);
+                rules::lex(&code, lex_options);
+            })
+        };
+        let lines: Vec<&str> = code.lines().collect();
+        let reduced = ice::minimize(lines.clone(), |lines| again(lex_all(lines.join("\n"))));
+        (reduced.join("\n"), format!("{} of {} lines", reduced.len(), lines.len()), Vec::new())
+    } else {
+        let tokens = lexer_regex::lex_with_regex_options(&code, lex_options);
+        let original = tokens.len();
+        if again(replay_tokens(&tokens)) {
+            let tokens = ice::minimize(tokens, |tokens| again(replay_tokens(tokens)));
+            (ice::source_text(&tokens), format!("{} of {} tokens", tokens.len(), original), tokens)
+        } else {
+            (code.clone(), "not reduced: it didn't happen again outside the full run".to_string(), tokens)
+        }
+    };
+    let ast = ice::catch(|| {
+        let mut parser = parser::Parser::new(tokens.clone());
+        parser.set_gnu_extensions(flags.contains(&"-fgnu-extensions"));
+        parser.set_standard(standard);
+        parser.parse().ok()
+    });
+    let path = format!("{}.ice.txt", filename);
+    let dump = ice::dump(ice, filename, &reduced, &size, &tokens, ast.ok().flatten().as_ref());
+    match fs::write(&path, dump) {
+        Ok(()) => println!("A reduced reproduction ({}) was written to {}; please attach it to a bug report.", size, path),
+        Err(error) => println!("Could not write a reproduction to {}: {}", path, error),
+    }
+}
+
+fn main() {
+    // The phases recurse down the tree, which can be deeper than the main
+    // thread's stack has room for
+    let driver = std::thread::Builder::new().stack_size(parser::STACK_SIZE).spawn(driver);
+    match driver.map(|driver| driver.join()) {
+        Ok(Ok(())) => {}
+        Ok(Err(_)) => std::process::exit(101), // a panic, already printed
         Err(error) => {
-            println!("Parse Error: {:?}", error);
+            println!("Failed to start the compiler's thread: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn driver() {
+    let args: Vec<String> = env::args().collect();
+    // What follows `--` is the program's arguments, not the driver's flags
+    let flags: Vec<&str> = args[1..]
+        .iter()
+        .take_while(|arg| *arg != "--")
+        .filter(|arg| arg.starts_with('-'))
+        .map(|arg| arg.as_str())
+        .collect();
+    if args.len() > 1 && args[1] == "gen-fuzz" {
+        gen_fuzz_command(&args, &flags);
+        return;
+    }
+    if args.len() > 1 && args[1] == "bench" {
+        bench_command(&args, &flags);
+        return;
+    }
+    if args.len() > 2 && args[1] == "difftest" {
+        difftest_command(&args, &flags);
+        return;
+    }
+    if args.len() > 1 && args[1] == "check-precedence" {
+        let (count, failures) = precedence::check();
+        for failure in &failures {
+            println!("{}", failure.render());
+        }
+        println!("{} of {} expressions parsed as expected", count - failures.len(), count);
+        return;
+    }
+    if args.len() > 2 && args[1] == "explore" {
+        if let Err(error) = explorer::explore(&args[2]) {
+            println!("Explorer Error: {}", error);
+        }
+        return;
+    }
+    if args.len() > 2 && args[1] == "rename" {
+        rename_command(&args);
+        return;
+    }
+    if args.len() > 2 && args[1] == "project" {
+        project_command(&args, &flags);
+        return;
+    }
+    if args.len() > 2 && args[1] == "reduce" {
+        reduce_command(&args);
+        return;
+    }
+    if args.len() > 2 && args[1] == "type-at" {
+        type_at_command(&args);
+        return;
+    }
+    if args.len() > 2 && args[1] == "debug" {
+        let history_budget = flag_value(&flags, "--history-budget")
+            .and_then(|budget| budget.parse().ok())
+            .unwrap_or(debugger::DEFAULT_HISTORY_BUDGET);
+        if let Err(error) = debugger::debug(&args[2], flags.contains(&"-fgnu-extensions"), history_budget) {
+            println!("Debugger Error: {}", error);
+        }
+        return;
+    }
+    if args.len() > 2 && args[1] == "stream" {
+        stream_command(&args, &flags);
+        return;
+    }
+    if args.len() > 2 && args[1] == "run" {
+        run_command(&args, &flags);
+        return;
+    }
+    if args.len() > 2 && args[1] == "callgraph" {
+        let code = fs::read_to_string(&args[2]).expect("Failed to read file");
+        let mut parser = parser::Parser::with_spans(lexer_regex::lex_with_regex_positions(&code, Default::default()));
+        parser.set_gnu_extensions(flags.contains(&"-fgnu-extensions"));
+        match parser.parse() {
+            Ok(ast) => print!("{}", callgraph::CallGraph::build(&ast).render()),
+            Err(errors) => print_parse_errors(&errors, &parser),
+        }
+        return;
+    }
+    if args.len() > 2 && args[1] == "doc" {
+        doc_command(&args, &flags);
+        return;
+    }
+    if args.len() > 2 && args[1] == "grade" {
+        grade_command(&args, &flags);
+        return;
+    }
+    if args.len() > 2 && args[1] == "stats" {
+        let code = fs::read_to_string(&args[2]).expect("Failed to read file");
+        let file_stats = stats::FileStats::compute(&code);
+        if flags.contains(&"--json") {
+            println!("{}", file_stats.to_json());
+        } else {
+            print!("{}", file_stats.render());
+        }
+        return;
+    }
+    if let Some(path) = flag_value(&flags, "--load-artifact") {
+        print_artifact(path);
+        return;
+    }
+    let filename = match args[1..].iter().find(|arg| !arg.starts_with('-')) {
+        Some(filename) => filename,
+        None => {
+            println!("Usage: {} [-c] [--emit-artifact=<file>] <source_file>", args[0]);
+            println!("       {} --load-artifact=<file>", args[0]);
+            println!("       {} explore <source_file>", args[0]);
+            println!("       {} rename <source_file> --at <line>:<column> --to <name> [--apply]", args[0]);
+            println!("       {} type-at <source_file> --at <line>:<column>", args[0]);
+            println!("       {} project <source_file>... [-I<dir>] [--symbols] [--tokens] [--link [--run | --emit=<target>]] [--max-errors=<n>] [--policy=<file>] [-fgnu-extensions]", args[0]);
+            println!("       {} reduce <source_file> --check \"<command using %s>\"", args[0]);
+            println!("       {} gen-fuzz [--seed=<n>] [--count=<n>] [--check]", args[0]);
+            println!("       {} difftest <source_file>... [-fgnu-extensions] [-ffast-math] [-- <arguments>...]", args[0]);
+            println!("       {} check-precedence", args[0]);
+            println!("       {} bench [--scaling-size=<KB>] [--lexers]", args[0]);
+            println!("       {} stream <source_file> [--emit=cfg] [-fgnu-extensions]", args[0]);
+            println!("       {} stats <source_file> [--json]", args[0]);
+            println!("       {} doc <source_file> [--format=markdown|html] [--output=<file>] [-fgnu-extensions]", args[0]);
+            println!("       {} grade <source_file> --spec <spec.toml> [--json] [-fgnu-extensions]", args[0]);
+            println!("       {} callgraph <source_file> [-fgnu-extensions]", args[0]);
+            println!("       {} run <source_file> [--memcheck] [--softfloat] [--check-overflow] [-ffast-math] [--timeout=SECONDS] [--trace[=FILE]] [--std=<name>] [-fgnu-extensions] [-fdigit-separators] [-funicode-identifiers] [-- <arguments>...]", args[0]);
+            println!("       {} debug <source_file> [--history-budget=<MB>] [-fgnu-extensions]", args[0]);
+            println!("  -c                      compile only; don't require a main() entry point");
+            println!("  --std=<standard>        language standard: c89, c99, c11 or minic (default)");
+            println!("  --emit=<target>         print the program in another format (html, ast, c, layout, stack-usage, cfg, precedence, semantic-tokens)");
+            println!("  --clone-min-size=<n>    smallest duplicated code to report, in AST nodes (default {})", lints::clones::DEFAULT_MIN_SIZE);
+            println!("  --gc-unused             drop globals and functions nothing reachable uses");
+            println!("  -fgnu-extensions        accept GNU C extensions: statement expressions ({{ ... }})");
+            println!("  -fdigit-separators      accept digit separators in numbers: 1'000'000, 1_000_000");
+            println!("  -funicode-identifiers   accept Unicode letters in identifiers (UAX #31), not just ASCII");
+            println!("  -Wimplicit-fallthrough  warn when a switch case runs into the next label");
+            println!("  -Wsign-compare          warn when a comparison converts a signed operand to unsigned");
+            println!("  -Wparentheses           warn where operators may group differently than they read, as in a & b == c");
+            println!("  -Werror=<name>          report the named warning as an error, e.g. -Werror=overflow for constants that don't fit");
+            println!("  --max-errors=<n>        stop printing diagnostics after n errors (0 for no limit)");
+            println!("  --policy=<file>         report uses of the features a TOML file bans: goto, globals, recursion, pointer-arithmetic");
+            println!("  --output=<file>         write --emit output to a file instead");
+            println!("  --emit-artifact=<file>  save the AST and diagnostics in binary form");
+            println!("  --load-artifact=<file>  print a saved artifact without reparsing");
+            return;
+        }
+    };
+    match ice::catch(|| compile(filename, &flags)) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(ice) => {
+            report_ice(&ice, filename, &flags);
+            std::process::exit(101);
         }
     }
 }
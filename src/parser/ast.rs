@@ -1,12 +1,17 @@
 // ast.rs: Defines the Abstract Syntax Tree (AST) structures for the MiniC parser.
 
-#[derive(Debug, Clone)]
+use crate::layout::Layouts;
+use crate::source::TokenSpan;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TranslationUnit {
     pub preprocessor_list: Vec<PreprocessorDirective>,
+    pub preprocessor_spans: Vec<Option<TokenSpan>>, // of each directive in the list
     pub external_declarations: Vec<ExternalDeclaration>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PreprocessorDirective {
     Include(String),                      // #include <stdio.h>
     Define(String, Vec<ReplacementItem>), // #define IDENTIFIER replacement_list
@@ -15,44 +20,255 @@ pub enum PreprocessorDirective {
     Endif,                                // #endif
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ReplacementItem {
     Identifier(String),    // Identifier in replacement_list
     Constant(Constant),    // Constant in replacement_list
     StringLiteral(String), // StringLiteral in replacement_list
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Constant {
     Integer(i64), // e.g., 42
-    Float(f64),   // e.g., 3.14
+    Float(f64, FloatSuffix), // e.g., 3.14, 2.5f, 1e9L
     Char(char),   // e.g., 'a'
+    Bool(bool),   // e.g., true
+    Null,         // nullptr, or NULL from <stddef.h>
+    EncodedChar(LiteralEncoding, char), // e.g., L'a', u'\u00e9'
+}
+
+/// Prefix of a string or character literal, which sets its element type
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LiteralEncoding {
+    Utf8,  // u8"...": char
+    Wide,  // L"...": wchar_t
+    Utf16, // u"...": char16_t
+    Utf32, // U"...": char32_t
+}
+
+impl LiteralEncoding {
+    pub fn from_prefix(prefix: &str) -> Option<LiteralEncoding> {
+        match prefix {
+            "u8" => Some(LiteralEncoding::Utf8),
+            "L" => Some(LiteralEncoding::Wide),
+            "u" => Some(LiteralEncoding::Utf16),
+            "U" => Some(LiteralEncoding::Utf32),
+            _ => None,
+        }
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            LiteralEncoding::Utf8 => "u8",
+            LiteralEncoding::Wide => "L",
+            LiteralEncoding::Utf16 => "u",
+            LiteralEncoding::Utf32 => "U",
+        }
+    }
+
+    /// Bytes per element, for layout; wchar_t is 4 bytes as on Linux
+    pub fn element_size(&self) -> usize {
+        match self {
+            LiteralEncoding::Utf8 => 1,
+            LiteralEncoding::Utf16 => 2,
+            LiteralEncoding::Wide | LiteralEncoding::Utf32 => 4,
+        }
+    }
+
+    /// Bytes a string literal with this text takes, terminator included
+    pub fn string_size(&self, text: &str) -> usize {
+        let elements = match self {
+            LiteralEncoding::Utf8 => text.len(),
+            LiteralEncoding::Utf16 => text.encode_utf16().count(),
+            LiteralEncoding::Wide | LiteralEncoding::Utf32 => text.chars().count(),
+        };
+        (elements + 1) * self.element_size()
+    }
+}
+
+/// Suffix of a floating literal, which sets its type: none for double
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FloatSuffix {
+    None,  // 3.14: double
+    Float, // 3.14f: float
+    Long,  // 3.14L: long double
+}
+
+impl FloatSuffix {
+    pub fn from_suffix(suffix: char) -> Option<FloatSuffix> {
+        match suffix {
+            'f' | 'F' => Some(FloatSuffix::Float),
+            'l' | 'L' => Some(FloatSuffix::Long),
+            _ => None,
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            FloatSuffix::None => "",
+            FloatSuffix::Float => "f",
+            FloatSuffix::Long => "L",
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExternalDeclaration {
-    Variable(VariableDeclaration),            // int x = 5;
-    Function(FunctionDefinition),             // int function_name(...) { ... }
-    FunctionDeclaration(FunctionDeclaration), // int func(int x);
+    Variable(VariableDeclaration),                // int x = 5;
+    Function(FunctionDefinition),                 // int function_name(...) { ... }
+    FunctionDeclaration(FunctionDeclaration),     // int func(int x);
+    Struct(StructDefinition),                     // struct point { int x; int y; };
+    StructDeclaration(String, Option<TokenSpan>), // struct node; completed by a later definition
+    Enum(EnumDefinition),                         // enum color { RED, GREEN = 4 };
 }
 
+impl ExternalDeclaration {
+    /// Where it is, from its first token to its last
+    pub fn span(&self) -> Option<TokenSpan> {
+        match self {
+            ExternalDeclaration::Variable(declaration) => declaration.span,
+            ExternalDeclaration::Function(definition) => definition.span,
+            ExternalDeclaration::FunctionDeclaration(declaration) => declaration.span,
+            ExternalDeclaration::Struct(definition) => definition.span,
+            ExternalDeclaration::StructDeclaration(_, span) => *span,
+            ExternalDeclaration::Enum(definition) => definition.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructDefinition {
+    pub name: String,                     // the tag
+    pub members: Vec<VariableDeclaration>, // never initialized
+    pub span: Option<TokenSpan>,           // from `struct` to the `;`, when the tokens had spans
+}
+
+impl StructDefinition {
+    /// The last member, if it is a flexible array member: `int data[];`
+    pub fn flexible_member(&self) -> Option<&VariableDeclaration> {
+        self.members.last().filter(|member| is_flexible_array(member))
+    }
+}
+
+/// An array member whose size is left out, `int data[]`, so the struct
+/// ends in however many elements were allocated for it
+pub fn is_flexible_array(member: &VariableDeclaration) -> bool {
+    member.declarator.array_sizes.first().is_some_and(Option::is_none)
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnumDefinition {
+    pub name: Option<String>, // the tag; None for `enum { A, B };`
+    pub enumerators: Vec<Enumerator>,
+    pub span: Option<TokenSpan>, // from `enum` to the `;`
+}
+
+impl EnumDefinition {
+    /// `enum 'color'`, or `anonymous enum`, for messages
+    pub fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("enum '{}'", name),
+            None => "anonymous enum".to_string(),
+        }
+    }
+
+    /// `enum color` or `enum`, as it begins in C
+    pub fn head(&self) -> String {
+        match &self.name {
+            Some(name) => format!("enum {}", name),
+            None => "enum".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Enumerator {
+    pub name: String,
+    pub value: Option<Expression>, // `= value`; otherwise one more than the previous
+    pub span: Option<TokenSpan>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDefinition {
-    pub return_type: String,        // e.g., "int", "void"
-    pub name: String,               // function name
-    pub parameters: Vec<Parameter>, // function parameters
-    pub body: Vec<Statement>,       // function body statements
+    pub attributes: Vec<Attribute>,         // __attribute__((...)) and [[...]]
+    pub specifiers: Vec<FunctionSpecifier>, // inline, _Noreturn
+    pub return_type: String,                // e.g., "int", "void"
+    pub name: String,                       // function name
+    pub parameters: Vec<Parameter>,         // function parameters
+    pub variadic: bool,                     // true if '...' ends the parameters
+    pub body: Vec<Statement>,               // function body statements
+    pub span: Option<TokenSpan>,            // from the return type to the closing brace
+    pub name_span: Option<TokenSpan>,
+}
+
+impl FunctionDefinition {
+    /// Declared `_Noreturn` or with the noreturn attribute
+    pub fn is_noreturn(&self) -> bool {
+        self.specifiers.contains(&FunctionSpecifier::Noreturn) || find_attribute(&self.attributes, "noreturn").is_some()
+    }
+}
+
+/// A GNU `__attribute__((name(args)))` or C23 `[[name(args)]]` attribute
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attribute {
+    pub name: String,              // as written, e.g. "aligned", "__packed__", "gnu::unused"
+    pub arguments: Vec<Expression>, // e.g. printf, 1, 2 in format(printf, 1, 2)
+}
+
+impl Attribute {
+    /// The name without a `gnu::` namespace or `__x__` underscores, so
+    /// `[[gnu::unused]]` and `__attribute__((__unused__))` are both "unused"
+    pub fn base_name(&self) -> &str {
+        let name = self.name.strip_prefix("gnu::").unwrap_or(&self.name);
+        let name = name.strip_prefix("__").and_then(|inner| inner.strip_suffix("__")).unwrap_or(name);
+        match name {
+            "_Noreturn" => "noreturn",
+            "maybe_unused" => "unused",
+            _ => name,
+        }
+    }
+}
+
+/// The first attribute with this base name
+pub fn find_attribute<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attributes.iter().find(|attribute| attribute.base_name() == name)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FunctionSpecifier {
+    Inline,   // a hint that calls may be inlined
+    Noreturn, // _Noreturn: calls never return to the caller
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parameter {
-    pub param_type: String, // e.g., "int", "float"
-    pub name: String,       // parameter name
+    pub param_type: String,                // e.g., "int", "float"
+    pub name: String,                      // parameter name
+    pub type_qualifiers: Vec<TypeQualifier>, // const, restrict
 }
 
-#[derive(Debug, Clone)]
-pub enum Statement {
+/// A statement and where it is, from its first token to its last, when
+/// the tokens it was parsed from had spans
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Option<TokenSpan>,
+}
+
+impl Statement {
+    /// A statement made up rather than parsed, so not anywhere
+    pub fn new(kind: StatementKind) -> Statement {
+        Statement { kind, span: None }
+    }
+
+    /// The line it starts on, from 1
+    pub fn line(&self) -> Option<usize> {
+        self.span.map(|span| span.line)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StatementKind {
     Declaration(VariableDeclaration),                       // int x = 5;
     Assignment(String, Expression),                         // variable_name, expression
     Return(Option<Expression>),                             // return statement
@@ -67,17 +283,24 @@ pub enum Statement {
         Box<Statement>,
     ), // for (init; cond; update) stmt
     Break,                                                  // break;
+    Switch(Expression, Box<Statement>),                     // switch (value) stmt
+    Case(Expression, Box<Statement>),                       // case constant: stmt
+    Default(Box<Statement>),                                // default: stmt
+    Fallthrough,                                            // [[fallthrough]]; or a /* fallthrough */ comment before a label
+    Label(String, Box<Statement>),                          // name: stmt
+    Goto(String),                                           // goto name;
+    Empty,                                                  // ; on its own, as in while (busy());
 }
 
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SpecifierQualifier {
     TypeSpecifier(TypeSpecifier), // type_specifier
     TypeQualifier(TypeQualifier), // type_qualifier
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TypeSpecifier {
     Int,
     Float,
@@ -85,26 +308,82 @@ pub enum TypeSpecifier {
     Char,
     Short,
     Long,
-    Signed,
-    Unsigned,
+    Signed,   // signed int
+    Unsigned, // unsigned int
+    UnsignedChar,
+    UnsignedShort,
+    UnsignedLong,
     Void,
+    Bool,
+    Struct(String), // struct tag
+    Enum(String),   // enum tag
+}
+
+impl TypeSpecifier {
+    /// As C writes it, and as parameter and return types spell it
+    pub fn spelling(&self) -> String {
+        let name = match self {
+            TypeSpecifier::Int => "int",
+            TypeSpecifier::Float => "float",
+            TypeSpecifier::Double => "double",
+            TypeSpecifier::Char => "char",
+            TypeSpecifier::Short => "short",
+            TypeSpecifier::Long => "long",
+            TypeSpecifier::Signed => "signed",
+            TypeSpecifier::Unsigned => "unsigned",
+            TypeSpecifier::UnsignedChar => "unsigned char",
+            TypeSpecifier::UnsignedShort => "unsigned short",
+            TypeSpecifier::UnsignedLong => "unsigned long",
+            TypeSpecifier::Void => "void",
+            TypeSpecifier::Bool => "bool",
+            TypeSpecifier::Struct(tag) => return format!("struct {}", tag),
+            TypeSpecifier::Enum(tag) => return format!("enum {}", tag),
+        };
+        name.to_string()
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TypeQualifier {
     Const,
+    Restrict, // the pointer is the only way its object is accessed
     // Add more as needed based on grammar expansion
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Declarator {
     pub name: String,                            // Identifier in declarator
     pub pointer_depth: u32,                      // number of * before name
     pub array_sizes: Vec<Option<Expression>>,    // array dimensions
     pub function_params: Option<Vec<Parameter>>, // function parameters
+    pub pointee: Vec<Derivation>,                // what the pointers point to, past the specifier
+}
+
+impl Declarator {
+    /// A dimension's length is only known at run time: `int buf[n]`
+    pub fn is_variable_length(&self) -> bool {
+        self.array_sizes.iter().flatten().any(|size| !size.is_integer_constant())
+    }
+
+    /// The declared type, as a cast would name it: `int (*fp)(int)` is
+    /// int (*)(int), and `char *names[4]` is char*[4]
+    pub fn type_name(&self, type_specifier: &TypeSpecifier) -> TypeName {
+        let arrays = self.array_sizes.iter().cloned().map(Derivation::Array);
+        let pointers = (0..self.pointer_depth).map(|_| Derivation::Pointer);
+        let derivations = arrays.chain(pointers).chain(self.pointee.iter().cloned()).collect();
+        TypeName { specifier: type_specifier.spelling(), derivations }
+    }
+
+    /// What the pointers point to when a parenthesized declarator makes it
+    /// more than the specifier: int (int) for `int (*fp)(int)`, char[10]
+    /// for `char (*pb)[10]`
+    pub fn pointee_type(&self, type_specifier: &TypeSpecifier) -> Option<TypeName> {
+        let derivations = self.pointee.clone();
+        (!derivations.is_empty()).then(|| TypeName { specifier: type_specifier.spelling(), derivations })
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Expression {
     Identifier(String),    // Identifier in expression
     Constant(Constant),    // Constant in expression
@@ -113,15 +392,218 @@ pub enum Expression {
     UnaryOp(UnaryOperator, Box<Expression>), // Unary operations
     Assignment(Box<Expression>, AssignmentOperator, Box<Expression>), // Assignment operations
     Conditional(Box<Expression>, Box<Expression>, Box<Expression>), // Ternary operator: cond ? true_expr : false_expr
-    FunctionCall(String, Vec<Expression>),                          // Function calls: func(args)
+    FunctionCall(Box<Expression>, Vec<Expression>),                 // Function calls: func(args), (*fp)(args)
     ArrayAccess(Box<Expression>, Box<Expression>),                  // Array access: arr[index]
     MemberAccess(Box<Expression>, String),                          // Member access: obj.member
     PointerAccess(Box<Expression>, String),                         // Pointer access: ptr->member
     PostfixOp(Box<Expression>, PostfixOperator), // Postfix operations: expr++, expr--
-    Cast(TypeSpecifier, Box<Expression>),        // (type)expr
+    Cast(TypeName, Box<Expression>),             // (type)expr
+    Paren(Box<Expression>),                      // (expr), kept so lints can see explicit grouping
+    StatementExpr(Vec<Statement>),               // GNU ({ stmts; expr; }), value of the last expression
+    Generic(Box<Expression>, Vec<GenericAssociation>), // _Generic(controlling, type: expr, ...)
+    EncodedStringLiteral(LiteralEncoding, String),     // L"...", u8"...", u"...", U"..."
+    CompoundLiteral(Box<CompoundLiteral>),             // (type){initializers}, an unnamed object
+    SizeofType(TypeName),                              // sizeof(type); sizeof expr is a UnaryOp
+    Folded(Constant, String),                          // the value of a #define or enumerator, and its name
+}
+
+/// A type written without a declared name, as in casts, sizeof and unnamed
+/// parameters: `int`, `const char *`, `int (*)(void)`, `char (*)[10]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypeName {
+    pub specifier: String,            // e.g. "int", "struct point"
+    pub derivations: Vec<Derivation>, // read from where a name would be, outwards
+}
+
+/// One step from a type to the type it is derived from: `int *[3]` is an
+/// array of three pointers, [Array(3), Pointer], and `int (*)[3]` a pointer
+/// to an array of three, [Pointer, Array(3)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Derivation {
+    Pointer,
+    Array(Option<Expression>),
+    Function(Vec<Parameter>, bool), // the parameters, and whether '...' ends them
+}
+
+impl TypeName {
+    /// Spelled the way the parser spells types elsewhere: "char*" for plain
+    /// pointers, "int (*)(void)" and "char (*)[10]" when it takes a
+    /// declarator. The parser reads every spelling back to the same type.
+    pub fn spelling(&self) -> String {
+        let declarator = self.declarator("");
+        // "char*", "int[2]", but "int (*)(void)"
+        if declarator.starts_with('(') {
+            format!("{} {}", self.specifier, declarator)
+        } else {
+            format!("{}{}", self.specifier, declarator)
+        }
+    }
+
+    /// A declaration of `name` of this type, as C writes it: "int (*fp)(int)"
+    pub fn declaring(&self, name: &str) -> String {
+        format!("{} {}", self.specifier, self.declarator(name))
+    }
+
+    /// The derivations written around `name`, which may be left out
+    fn declarator(&self, name: &str) -> String {
+        let mut declarator = name.to_string();
+        for derivation in &self.derivations {
+            if !matches!(derivation, Derivation::Pointer) && declarator.starts_with('*') {
+                declarator = format!("({})", declarator);
+            }
+            match derivation {
+                Derivation::Pointer => declarator.insert(0, '*'),
+                Derivation::Array(size) => {
+                    let size = match size {
+                        None => String::new(),
+                        Some(size) => match size.integer_constant_value() {
+                            Some(n) => n.to_string(),
+                            None => "*".to_string(), // a variable length, as C writes it in prototypes
+                        },
+                    };
+                    declarator.push_str(&format!("[{}]", size));
+                }
+                Derivation::Function(parameters, variadic) => {
+                    let mut types: Vec<&str> = parameters.iter().map(|p| p.param_type.as_str()).collect();
+                    if *variadic {
+                        types.push("...");
+                    }
+                    let list = if types.is_empty() { "void".to_string() } else { types.join(", ") };
+                    declarator.push_str(&format!("({})", list));
+                }
+            }
+        }
+        declarator
+    }
+
+    /// Array sizes written in the type, which are expressions like any other
+    pub fn expressions(&self) -> Vec<&Expression> {
+        self.derivations
+            .iter()
+            .filter_map(|derivation| match derivation {
+                Derivation::Array(Some(size)) => Some(size),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// An array in it has a length only known at run time: `int[n]`
+    pub fn is_variable_length(&self) -> bool {
+        self.expressions().iter().any(|size| !size.is_integer_constant())
+    }
+}
+
+/// `(int){3}`, `(int[]){1, 2, 3}`: an object with no name, initialized
+/// like a declaration of that type would be
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompoundLiteral {
+    pub type_name: String,                     // e.g. "int", "char*"
+    pub array_sizes: Vec<Option<Expression>>,  // as in a declarator
+    pub initializers: Vec<Initializer>,        // the brace list's items
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenericAssociation {
+    pub type_name: Option<String>, // e.g. "int", "char*"; None for `default`
+    pub value: Expression,
+}
+
+impl Expression {
+    /// Name of the function a call expression invokes directly, looking
+    /// through parentheses: `f` in `f(x)` and `(f)(x)`, None for `(*fp)(x)`
+    pub fn as_function_name(&self) -> Option<&str> {
+        match self {
+            Expression::Identifier(name) => Some(name),
+            Expression::Paren(inner) => inner.as_function_name(),
+            _ => None,
+        }
+    }
+
+    /// Value of an integer constant expression, made of integer and
+    /// character literals, `sizeof(type)` and the arithmetic, bitwise,
+    /// comparison and logical operators; None for anything else, if it
+    /// would overflow or divide by zero, or if it takes the size of a struct
+    /// (see integer_constant_value_in)
+    pub fn integer_constant_value(&self) -> Option<i64> {
+        self.integer_constant_value_in(&Layouts::default())
+    }
+
+    /// integer_constant_value, with the sizes of the structs and the values
+    /// of the enumerators in `layouts`
+    pub fn integer_constant_value_in(&self, layouts: &Layouts) -> Option<i64> {
+        match self {
+            Expression::Identifier(name) => layouts.enumerator_value(name),
+            Expression::Constant(Constant::Integer(n)) | Expression::Folded(Constant::Integer(n), _) => Some(*n),
+            Expression::Constant(Constant::Char(c) | Constant::EncodedChar(_, c))
+            | Expression::Folded(Constant::Char(c) | Constant::EncodedChar(_, c), _) => Some(*c as i64),
+            Expression::SizeofType(type_name) => layouts.size_of(type_name).map(|size| size as i64),
+            Expression::Paren(inner) => inner.integer_constant_value_in(layouts),
+            Expression::UnaryOp(op, operand) => {
+                let value = operand.integer_constant_value_in(layouts)?;
+                match op {
+                    UnaryOperator::Plus => Some(value),
+                    UnaryOperator::Minus => value.checked_neg(),
+                    UnaryOperator::Not => Some((value == 0) as i64),
+                    UnaryOperator::BitNot => Some(!value),
+                    _ => None,
+                }
+            }
+            Expression::BinaryOp(left, op, right) => {
+                let (l, r) = (left.integer_constant_value_in(layouts)?, right.integer_constant_value_in(layouts)?);
+                match op {
+                    BinaryOperator::Plus => l.checked_add(r),
+                    BinaryOperator::Minus => l.checked_sub(r),
+                    BinaryOperator::Mult => l.checked_mul(r),
+                    BinaryOperator::Div => l.checked_div(r),
+                    BinaryOperator::Mod => l.checked_rem(r),
+                    BinaryOperator::Less => Some((l < r) as i64),
+                    BinaryOperator::LessEq => Some((l <= r) as i64),
+                    BinaryOperator::Greater => Some((l > r) as i64),
+                    BinaryOperator::GreaterEq => Some((l >= r) as i64),
+                    BinaryOperator::Equals => Some((l == r) as i64),
+                    BinaryOperator::NotEquals => Some((l != r) as i64),
+                    BinaryOperator::And => Some((l != 0 && r != 0) as i64),
+                    BinaryOperator::Or => Some((l != 0 || r != 0) as i64),
+                    BinaryOperator::BitAnd => Some(l & r),
+                    BinaryOperator::BitOr => Some(l | r),
+                    BinaryOperator::Xor => Some(l ^ r),
+                    BinaryOperator::LShift => u32::try_from(r).ok().and_then(|r| l.checked_shl(r)),
+                    BinaryOperator::RShift => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+                }
+            }
+            Expression::Conditional(condition, then_value, else_value) => {
+                if condition.integer_constant_value_in(layouts)? != 0 {
+                    then_value.integer_constant_value_in(layouts)
+                } else {
+                    else_value.integer_constant_value_in(layouts)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this is an integer constant expression, whatever its value
+    /// turns out to be. `sizeof` is one unless it measures a variable length
+    /// array, which for `sizeof expr` would take the operand's type to tell.
+    pub fn is_integer_constant(&self) -> bool {
+        match self {
+            Expression::SizeofType(type_name) => !type_name.is_variable_length(),
+            Expression::UnaryOp(UnaryOperator::Sizeof, _) => true,
+            Expression::Paren(inner) => inner.is_integer_constant(),
+            Expression::UnaryOp(op, operand) => {
+                matches!(op, UnaryOperator::Plus | UnaryOperator::Minus | UnaryOperator::Not | UnaryOperator::BitNot)
+                    && operand.is_integer_constant()
+            }
+            Expression::BinaryOp(left, _, right) => left.is_integer_constant() && right.is_integer_constant(),
+            Expression::Conditional(condition, then_value, else_value) => {
+                condition.is_integer_constant() && then_value.is_integer_constant() && else_value.is_integer_constant()
+            }
+            _ => self.integer_constant_value().is_some(),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BinaryOperator {
     Plus,
     Minus,
@@ -143,7 +625,32 @@ pub enum BinaryOperator {
     RShift,
 }
 
-#[derive(Debug, Clone)]
+impl BinaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Mult => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Mod => "%",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEq => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEq => ">=",
+            BinaryOperator::Equals => "==",
+            BinaryOperator::NotEquals => "!=",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::Xor => "^",
+            BinaryOperator::LShift => "<<",
+            BinaryOperator::RShift => ">>",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UnaryOperator {
     Plus,
     Minus,
@@ -153,9 +660,26 @@ pub enum UnaryOperator {
     Dereference,
     PreIncrement,
     PreDecrement, // ++expr, --expr
+    Sizeof,       // sizeof expr, not evaluated
+}
+
+impl UnaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOperator::Plus => "+",
+            UnaryOperator::Minus => "-",
+            UnaryOperator::Not => "!",
+            UnaryOperator::BitNot => "~",
+            UnaryOperator::AddressOf => "&",
+            UnaryOperator::Dereference => "*",
+            UnaryOperator::PreIncrement => "++",
+            UnaryOperator::PreDecrement => "--",
+            UnaryOperator::Sizeof => "sizeof",
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AssignmentOperator {
     Assign,
     PlusAssign,
@@ -170,37 +694,83 @@ pub enum AssignmentOperator {
     OrAssign,
 }
 
-#[derive(Debug, Clone)]
+impl AssignmentOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            AssignmentOperator::Assign => "=",
+            AssignmentOperator::PlusAssign => "+=",
+            AssignmentOperator::MinusAssign => "-=",
+            AssignmentOperator::MultAssign => "*=",
+            AssignmentOperator::DivAssign => "/=",
+            AssignmentOperator::ModAssign => "%=",
+            AssignmentOperator::LShiftAssign => "<<=",
+            AssignmentOperator::RShiftAssign => ">>=",
+            AssignmentOperator::AndAssign => "&=",
+            AssignmentOperator::XorAssign => "^=",
+            AssignmentOperator::OrAssign => "|=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PostfixOperator {
     PlusPlus,
     MinusMinus,
 }
 
-#[derive(Debug, Clone)]
+impl PostfixOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            PostfixOperator::PlusPlus => "++",
+            PostfixOperator::MinusMinus => "--",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Initializer {
     pub kind: InitializerKind,
 }
 
-#[derive(Debug, Clone)]
+impl Initializer {
+    /// Every expression in the initializer, designator indices included,
+    /// in source order
+    pub fn expressions(&self) -> Vec<&Expression> {
+        match &self.kind {
+            InitializerKind::Assignment(value) => vec![value],
+            InitializerKind::List(items) => items.iter().flat_map(Initializer::expressions).collect(),
+            InitializerKind::Designated(designator, value) => {
+                let mut expressions = match designator {
+                    Designator::Array(index) => vec![index],
+                    Designator::Member(_) => Vec::new(),
+                };
+                expressions.extend(value.expressions());
+                expressions
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum InitializerKind {
     Assignment(Expression),                   // assignment_expression
     List(Vec<Initializer>),                   // { initializer_list } or { initializer_list , }
     Designated(Designator, Box<Initializer>), // .field = value
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParameterTypeList {
     pub parameters: Vec<ParameterDeclaration>, // parameter_list
     pub variadic: bool,                        // true if '...' is present
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParameterDeclaration {
     pub specifiers: Vec<SpecifierQualifier>, // declaration_specifiers
     pub declarator: Option<Declarator>,      // declarator or abstract_declarator
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Comment {
     Line(String),  // // comment_text \n
     Block(String), // /* comment_text */
@@ -208,25 +778,37 @@ pub enum Comment {
 
 // ===== MISSING AST STRUCTURES FOR MINI-C =====
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VariableDeclaration {
+    pub attributes: Vec<Attribute>,
     pub storage_class: Option<StorageClass>,
     pub type_qualifiers: Vec<TypeQualifier>,
     pub type_specifier: TypeSpecifier,
     pub declarator: Declarator,
     pub initializer: Option<Initializer>,
+    pub span: Option<TokenSpan>, // from the first specifier to the `;`
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDeclaration {
+    pub attributes: Vec<Attribute>,
+    pub specifiers: Vec<FunctionSpecifier>,
     pub return_type: String,
     pub name: String,
     pub parameters: Vec<Parameter>,
+    pub variadic: bool,
+    pub span: Option<TokenSpan>,
 }
 
+impl FunctionDeclaration {
+    /// Declared `_Noreturn` or with the noreturn attribute
+    pub fn is_noreturn(&self) -> bool {
+        self.specifiers.contains(&FunctionSpecifier::Noreturn) || find_attribute(&self.attributes, "noreturn").is_some()
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum StorageClass {
     Auto,
     Register,
@@ -235,13 +817,13 @@ pub enum StorageClass {
     Typedef,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Designator {
     Member(String),    // .field
     Array(Expression), // [index]
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ParseError {
     UnexpectedEOF,
     FailedToFindToken(String),
@@ -253,4 +835,7 @@ pub enum ParseError {
     ExpectedStringLit,
     ExpectedBoolLit,
     ExpectedExpr,
+    InvalidAssignmentTarget(String),
+    MissingSemicolon(String),
+    TooDeeplyNested(String),
 }
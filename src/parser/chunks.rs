@@ -0,0 +1,111 @@
+// chunks.rs: A file parsed a piece at a time, for files too large to hold
+// every token and AST node of at once.
+//
+// Tokens are read from the lexer only as far as the end of the next
+// top-level declaration: a `;` outside any braces or parentheses, or the
+// `}` closing a function body. That piece is parsed on its own, by the same
+// parser, so what one declaration sets up (NULL from a header) carries over
+// to the next, and its tokens are dropped before the next piece is read.
+//
+// A `}` at file scope may close a struct or an initializer rather than a
+// function, with the declaration going on after it (`struct s {...} x;`),
+// so the piece only ends there if the next token can't continue it. Two
+// declarations parsed as one piece are still parsed correctly; only a
+// declaration split across two pieces would not be.
+//
+// The tokens come with their spans, so the nodes of every piece say where
+// in the whole file they are.
+
+use super::Parser;
+use crate::lexer_regex::Token;
+use crate::parser::ast::{ParseError, TranslationUnit};
+use crate::source::Spanned;
+use std::ops::Range;
+
+/// One piece of the file
+pub struct Chunk {
+    /// The directives and declarations in it
+    pub unit: Result<TranslationUnit, Vec<ParseError>>,
+    /// The bytes of the source it came from
+    pub span: Range<usize>,
+    pub tokens: usize,
+}
+
+pub struct Chunks<I: Iterator<Item = Spanned<Token>>> {
+    tokens: I,
+    pending: Option<Spanned<Token>>, // read past the end of the last piece
+    parser: Parser,
+    /// Most tokens held at once so far
+    pub peak_tokens: usize,
+}
+
+impl<I: Iterator<Item = Spanned<Token>>> Chunks<I> {
+    /// Pieces of the file `tokens` lex, parsed by `parser` (configured, its
+    /// tokens replaced piece by piece)
+    pub fn new(tokens: I, parser: Parser) -> Self {
+        Chunks { tokens, pending: None, parser, peak_tokens: 0 }
+    }
+}
+
+/// Whether a declaration can go on after a `}` at file scope
+fn continues_declaration(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Semicolon
+            | Token::Identifier(_)
+            | Token::Mult
+            | Token::Comma
+            | Token::AssignOp
+            | Token::BracketL
+            | Token::ParenL
+            | Token::Const
+            | Token::Volatile
+    )
+}
+
+impl<I: Iterator<Item = Spanned<Token>>> Iterator for Chunks<I> {
+    type Item = Chunk;
+
+    fn next(&mut self) -> Option<Chunk> {
+        let mut buffer = Vec::new();
+        let mut span: Option<Range<usize>> = None;
+        let (mut braces, mut parens) = (0usize, 0usize);
+        let mut closed = false; // a `}` just brought the braces back to file scope
+        while let Some(spanned) = self.pending.take().or_else(|| self.tokens.next()) {
+            let token = &spanned.token;
+            let is_comment = matches!(token, Token::Comment(_) | Token::BlockComment(_));
+            if closed && !is_comment && !continues_declaration(token) {
+                self.pending = Some(spanned);
+                break;
+            }
+            if !is_comment {
+                closed = false;
+            }
+            let range = spanned.span.range();
+            span = Some(span.map_or(range.clone(), |span| span.start..range.end));
+            match token {
+                Token::BraceL => braces += 1,
+                Token::BraceR => {
+                    braces = braces.saturating_sub(1);
+                    closed = braces == 0 && parens == 0;
+                }
+                Token::ParenL => parens += 1,
+                Token::ParenR => parens = parens.saturating_sub(1),
+                _ => {}
+            }
+            let ends = *token == Token::Semicolon && braces == 0 && parens == 0;
+            buffer.push(spanned);
+            if ends {
+                break;
+            }
+        }
+        let span = span?;
+        let tokens = buffer.len();
+        self.peak_tokens = self.peak_tokens.max(tokens);
+        self.parser.replace_tokens(buffer);
+        let unit = self.parser.parse();
+        // The piece is done with; don't hold its tokens until the next one
+        self.parser.replace_tokens(Vec::new());
+        Some(Chunk { unit, span, tokens })
+    }
+}
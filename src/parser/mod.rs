@@ -1,33 +1,238 @@
 // parser_new.rs: A clean, robust parser implementation for MiniC
 
 pub mod ast;
+pub mod chunks;
+pub mod validate;
+#[cfg(test)]
+mod tests;
 
+use crate::dialect::{Feature, Standard};
 use crate::lexer_regex::Token;
 use crate::parser::ast::*;
+use crate::source::{Spanned, TokenSpan};
+use std::cell::Cell;
 
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    null_macro_defined: bool, // NULL is available once a standard header defines it
+    spans: Vec<TokenSpan>,    // of each token, when the lexer gave them
+    errors: Vec<(ParseError, ErrorPlace)>, // found while parsing, and where each was found
+    depth: usize,             // current nesting of statements and expressions
+    too_deep: bool,           // the statement being parsed went past a limit on nesting
+    gnu_extensions: bool,     // accept GNU C extensions such as statement expressions
+    standard: Standard,       // language standard features are checked against
+    braces_counted: Cell<(usize, usize)>, // brace depth before a position, to count on from
 }
 
+/// Where the parser found an error: at a token, or just after one, where
+/// something missing belongs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPlace {
+    At(usize),
+    After(usize),
+}
+
+/// Deepest nesting of statements and expressions the parser accepts. Every
+/// later phase walks the AST recursively, so this also bounds their stack use.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
+/// Most operators of one precedence in a row, as in `1 + 1 + ... + 1`. Such
+/// a chain isn't nested as written, so it doesn't count towards
+/// MAX_NESTING_DEPTH, but its tree nests down its left operands one level
+/// per operator, and the later phases recurse down that.
+pub const MAX_CHAIN_LENGTH: usize = 4096;
+
+/// Stack for a thread running the phases, enough for them to walk the
+/// deepest tree the limits above let through in a debug build
+pub const STACK_SIZE: usize = 64 << 20;
+
+/// The assignment operators, all at the loosest level and right-associative
+const ASSIGNMENT_OPERATORS: &[(Token, AssignmentOperator)] = &[
+    (Token::AssignOp, AssignmentOperator::Assign),
+    (Token::PlusAssign, AssignmentOperator::PlusAssign),
+    (Token::MinusAssign, AssignmentOperator::MinusAssign),
+    (Token::MultAssign, AssignmentOperator::MultAssign),
+    (Token::DivAssign, AssignmentOperator::DivAssign),
+    (Token::ModAssign, AssignmentOperator::ModAssign),
+    (Token::LShiftAssign, AssignmentOperator::LShiftAssign),
+    (Token::RShiftAssign, AssignmentOperator::RShiftAssign),
+    (Token::AndAssign, AssignmentOperator::AndAssign),
+    (Token::XorAssign, AssignmentOperator::XorAssign),
+    (Token::OrAssign, AssignmentOperator::OrAssign),
+];
+
+/// The binary operators by level, from the loosest-binding to the tightest;
+/// each level is left-associative. The parser reads binary expressions from
+/// this table, so it is the precedence it actually applies.
+const BINARY_LEVELS: &[(&str, &[(Token, BinaryOperator)])] = &[
+    ("logical or", &[(Token::OrOp, BinaryOperator::Or)]),
+    ("logical and", &[(Token::AndOp, BinaryOperator::And)]),
+    ("bitwise or", &[(Token::BitOrOp, BinaryOperator::BitOr)]),
+    ("bitwise xor", &[(Token::Xor, BinaryOperator::Xor)]),
+    ("bitwise and", &[(Token::BitAndOp, BinaryOperator::BitAnd)]),
+    ("equality", &[(Token::EqualsOp, BinaryOperator::Equals), (Token::NotEqualsOp, BinaryOperator::NotEquals)]),
+    (
+        "relational",
+        &[
+            (Token::LessOp, BinaryOperator::Less),
+            (Token::GreaterOp, BinaryOperator::Greater),
+            (Token::LessEqOp, BinaryOperator::LessEq),
+            (Token::GreaterEqOp, BinaryOperator::GreaterEq),
+        ],
+    ),
+    ("shift", &[(Token::LShift, BinaryOperator::LShift), (Token::RShift, BinaryOperator::RShift)]),
+    ("additive", &[(Token::Plus, BinaryOperator::Plus), (Token::Minus, BinaryOperator::Minus)]),
+    (
+        "multiplicative",
+        &[(Token::Mult, BinaryOperator::Mult), (Token::Div, BinaryOperator::Div), (Token::Mod, BinaryOperator::Mod)],
+    ),
+];
+
+/// The operator `token` stands for in `operators`
+fn operator_for<T: Clone>(operators: &[(Token, T)], token: &Token) -> Option<T> {
+    operators.iter().find(|(candidate, _)| candidate == token).map(|(_, op)| op.clone())
+}
+
+/// How a level's operators group: `a - b - c` is `(a - b) - c`, but
+/// `a = b = c` is `a = (b = c)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// One level of the expression grammar
+#[derive(Debug, Clone)]
+pub struct PrecedenceLevel {
+    pub name: &'static str,
+    pub operators: Vec<&'static str>,
+    pub associativity: Associativity,
+}
+
+/// Every level of the expression grammar as the parser reads it, from the
+/// loosest-binding to the tightest (`--emit=precedence`)
+pub fn precedence_table() -> Vec<PrecedenceLevel> {
+    let mut levels = vec![
+        PrecedenceLevel {
+            name: "assignment",
+            operators: ASSIGNMENT_OPERATORS.iter().map(|(_, op)| op.symbol()).collect(),
+            associativity: Associativity::Right,
+        },
+        PrecedenceLevel { name: "conditional", operators: vec!["?:"], associativity: Associativity::Right },
+    ];
+    for (name, operators) in BINARY_LEVELS {
+        levels.push(PrecedenceLevel {
+            name,
+            operators: operators.iter().map(|(_, op)| op.symbol()).collect(),
+            associativity: Associativity::Left,
+        });
+    }
+    levels.push(PrecedenceLevel {
+        name: "prefix",
+        operators: vec!["+", "-", "!", "~", "&", "*", "++", "--", "sizeof", "(type)"],
+        associativity: Associativity::Right,
+    });
+    levels.push(PrecedenceLevel {
+        name: "postfix",
+        operators: vec!["()", "[]", ".", "->", "++", "--"],
+        associativity: Associativity::Left,
+    });
+    levels
+}
+
+/// Standard headers that define the NULL macro
+const NULL_DEFINING_HEADERS: [&str; 5] = ["stddef.h", "stdio.h", "stdlib.h", "string.h", "time.h"];
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, pos: 0 }
+        Parser {
+            tokens,
+            spans: Vec::new(),
+            pos: 0,
+            null_macro_defined: false,
+            errors: Vec::new(),
+            depth: 0,
+            too_deep: false,
+            gnu_extensions: false,
+            standard: Standard::default(),
+            braces_counted: Cell::new((0, 0)),
+        }
+    }
+
+    /// A parser of tokens that know where they are, so errors can say too
+    pub fn with_spans(tokens: Vec<Spanned<Token>>) -> Self {
+        let (tokens, spans) = tokens.into_iter().map(|spanned| (spanned.token, spanned.span)).unzip();
+        Parser { spans, ..Parser::new(tokens) }
+    }
+
+    /// Where each error `parse` returned was found, by token index; a
+    /// token at the number of tokens is the end of the file
+    pub fn error_places(&self) -> Vec<ErrorPlace> {
+        self.errors.iter().map(|(_, place)| *place).collect()
+    }
+
+    /// Where each error `parse` returned was found: the token it was found
+    /// at, or the last one at the end of the file, or the end of the token
+    /// it belongs after. None for a parser made with `new`, whose tokens
+    /// have no spans.
+    pub fn error_spans(&self) -> Vec<Option<TokenSpan>> {
+        self.error_places().into_iter().map(|place| error_span(&self.spans, place)).collect()
+    }
+
+    fn error(&mut self, error: ParseError) {
+        self.errors.push((error, ErrorPlace::At(self.pos)));
+    }
+
+    /// Record an error for something missing after token `pos`
+    fn error_after(&mut self, error: ParseError, pos: usize) {
+        self.errors.push((error, ErrorPlace::After(pos)));
+    }
+
+    fn error_list(&self) -> Vec<ParseError> {
+        self.errors.iter().map(|(error, _)| error.clone()).collect()
+    }
+
+    /// Start on another piece of the same file (see chunks), keeping what
+    /// the pieces before it set up
+    fn replace_tokens(&mut self, tokens: Vec<Spanned<Token>>) {
+        (self.tokens, self.spans) = tokens.into_iter().map(|spanned| (spanned.token, spanned.span)).unzip();
+        self.pos = 0;
+        self.errors.clear();
+        self.braces_counted.set((0, 0));
+    }
+
+    /// Accept GNU C extensions (`-fgnu-extensions`)
+    pub fn set_gnu_extensions(&mut self, gnu_extensions: bool) {
+        self.gnu_extensions = gnu_extensions;
+    }
+
+    /// Check features against a language standard (`--std`)
+    pub fn set_standard(&mut self, standard: Standard) {
+        self.standard = standard;
+    }
+
+    /// Record an error if the selected standard lacks `feature`
+    fn require(&mut self, feature: Feature) {
+        self.require_at(feature, self.pos);
+    }
+
+    /// Record an error at token `pos` if the selected standard lacks
+    /// `feature`, for a construct already parsed past
+    fn require_at(&mut self, feature: Feature, pos: usize) {
+        if let Err(message) = self.standard.check(feature) {
+            self.errors.push((ParseError::UnexpectedToken(message), ErrorPlace::At(pos)));
+        }
     }
 
     // ============================================
     // Helper Methods
     // ============================================
 
-    /// Skip whitespace-like tokens (comments, errors)
+    /// Skip whitespace-like tokens (comments, pragmas, errors)
     fn skip_whitespace(&mut self) {
-        while self.pos < self.tokens.len() {
-            match &self.tokens[self.pos] {
-                Token::Comment(_) | Token::BlockComment(_) | Token::Error(_) => {
-                    self.pos += 1;
-                }
-                _ => break,
-            }
+        while self.tokens.get(self.pos).is_some_and(is_trivia) {
+            self.pos += 1;
         }
     }
 
@@ -70,27 +275,117 @@ impl Parser {
         }
     }
 
+    /// Consume the ';' ending a statement or declaration. When it's missing,
+    /// record an error naming the token it should be inserted after and keep
+    /// going as if it were there.
+    fn expect_semicolon(&mut self, what: &str) -> bool {
+        self.skip_whitespace();
+        if self.consume(&Token::Semicolon) {
+            return true;
+        }
+
+        // The last token written, not a comment between it and this one
+        let previous = self.last_consumed();
+        let insert_after = match previous {
+            Some(i) => format!(" (insert after '{}')", self.tokens[i]),
+            None => String::new(),
+        };
+        let found = match self.peek() {
+            Some(token) => format!("'{}'", token),
+            None => "end of file".to_string(),
+        };
+        let message = format!("expected ';' after {}{}, found {}", what, insert_after, found);
+        let error = ParseError::MissingSemicolon(message);
+        match previous {
+            Some(i) => self.error_after(error, i),
+            None => self.error(error),
+        }
+        false
+    }
+
+    /// Index of the last token consumed, skipping comments and the like
+    fn last_consumed(&self) -> Option<usize> {
+        self.tokens[..self.pos.min(self.tokens.len())].iter().rposition(|token| !is_trivia(token))
+    }
+
+    /// Where a construct starting at token `start` and running through the
+    /// last token consumed is; None for a parser made with `new`
+    fn span_since(&self, start: usize) -> Option<TokenSpan> {
+        let last = self.last_consumed().filter(|&last| last >= start).unwrap_or(start);
+        Some(self.spans.get(start)?.to(self.spans.get(last)?))
+    }
+
+    /// Go one nesting level deeper; past MAX_NESTING_DEPTH this reports an
+    /// error and returns false so the caller can give up on the construct
+    fn descend(&mut self, what: &str) -> bool {
+        self.depth += 1;
+        if self.depth <= MAX_NESTING_DEPTH {
+            return true;
+        }
+        // Report it once per statement, not at every level being unwound
+        if !self.too_deep {
+            self.too_deep = true;
+            self.error(ParseError::TooDeeplyNested(format!(
+                "{} too deeply nested (limit {})",
+                what, MAX_NESTING_DEPTH
+            )));
+        }
+        false
+    }
+
+    /// Run `parse` one nesting level deeper
+    fn nested<T>(&mut self, what: &str, parse: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        let depth = self.depth;
+        let result = if self.descend(what) { parse(self) } else { None };
+        self.depth = depth;
+        result
+    }
+
     /// Check if we're at top level (no unmatched braces)
     fn is_at_top_level(&self) -> bool {
-        let mut brace_count = 0;
-        for i in 0..self.pos {
-            match &self.tokens[i] {
+        self.brace_depth() == 0
+    }
+
+    /// Braces open before the current token; a `}` with none open to close
+    /// is left out. Counted on from where it was last asked for, as long as
+    /// the parser hasn't backed up past there, so that asking before every
+    /// top-level declaration doesn't go back over the whole file each time.
+    fn brace_depth(&self) -> usize {
+        let (counted, mut brace_count) = self.braces_counted.get();
+        let start = if counted <= self.pos {
+            counted
+        } else {
+            brace_count = 0;
+            0
+        };
+        for token in &self.tokens[start..self.pos] {
+            match token {
                 Token::BraceL => brace_count += 1,
-                Token::BraceR => brace_count -= 1,
+                Token::BraceR => brace_count = brace_count.saturating_sub(1),
                 _ => {}
             }
         }
-        brace_count == 0
+        self.braces_counted.set((self.pos, brace_count));
+        brace_count
     }
 
     // ============================================
     // Main Entry Point
     // ============================================
 
-    pub fn parse(&mut self) -> Result<TranslationUnit, ParseError> {
+    pub fn parse(&mut self) -> Result<TranslationUnit, Vec<ParseError>> {
         let mut preprocessor_list = Vec::new();
+        let mut preprocessor_spans = Vec::new();
         let mut external_declarations = Vec::new();
 
+        // Error tokens are skipped like comments while parsing, so report
+        // them all up front; the program is still rejected
+        for (pos, token) in self.tokens.iter().enumerate() {
+            if let Some(msg) = token.lex_error() {
+                self.errors.push((ParseError::UnexpectedToken(format!("Lexer error: {}", msg)), ErrorPlace::At(pos)));
+            }
+        }
+
         while self.pos < self.tokens.len() {
             self.skip_whitespace();
 
@@ -100,12 +395,20 @@ impl Parser {
 
             match self.peek() {
                 Some(Token::Preprocessor(_)) => {
+                    let start = self.pos;
                     if let Ok(directive) = self.parse_preprocessor_directive() {
                         preprocessor_list.push(directive);
+                        preprocessor_spans.push(self.span_since(start));
                     }
                 }
                 Some(Token::Error(msg)) => {
-                    return Err(ParseError::UnexpectedToken(format!("Lexer error: {}", msg)));
+                    let error = ParseError::UnexpectedToken(format!("Lexer error: {}", msg));
+                    self.error(error);
+                    return Err(self.error_list());
+                }
+                Some(Token::BraceR) if self.is_at_top_level() => {
+                    self.error(ParseError::UnexpectedToken("Unmatched '}' at file scope".to_string()));
+                    self.pos += 1;
                 }
                 _ => {
                     if self.is_at_top_level() {
@@ -114,7 +417,8 @@ impl Parser {
                         } else {
                             // Check for specific errors
                             if let Err(e) = self.check_for_specific_errors() {
-                                return Err(e);
+                                self.error(e);
+                                return Err(self.error_list());
                             }
                             // Skip unrecognized token
                             self.pos += 1;
@@ -127,22 +431,20 @@ impl Parser {
             }
         }
 
+        if !self.errors.is_empty() {
+            return Err(self.error_list());
+        }
+
         Ok(TranslationUnit {
             preprocessor_list,
+            preprocessor_spans,
             external_declarations,
         })
     }
 
     /// Skip tokens until we're back at top level
     fn skip_to_top_level(&mut self) {
-        let mut brace_count = 0;
-        for i in 0..self.pos {
-            match &self.tokens[i] {
-                Token::BraceL => brace_count += 1,
-                Token::BraceR => brace_count -= 1,
-                _ => {}
-            }
-        }
+        let mut brace_count = self.brace_depth();
         while self.pos < self.tokens.len() && brace_count > 0 {
             match &self.tokens[self.pos] {
                 Token::BraceL => brace_count += 1,
@@ -162,7 +464,15 @@ impl Parser {
             Some(Token::Preprocessor(directive)) => {
                 let directive_type = directive.strip_prefix('#').unwrap_or(&directive).to_string();
                 match directive_type.as_str() {
-                    "include" => self.parse_include(),
+                    "include" => {
+                    let directive = self.parse_include()?;
+                    if let PreprocessorDirective::Include(header) = &directive {
+                        if NULL_DEFINING_HEADERS.iter().any(|h| header.ends_with(h)) {
+                            self.null_macro_defined = true;
+                        }
+                    }
+                    Ok(directive)
+                }
                     "define" => self.parse_define(),
                     "ifdef" => self.parse_ifdef(),
                     "ifndef" => self.parse_ifndef(),
@@ -236,14 +546,18 @@ impl Parser {
                     items.push(ReplacementItem::Constant(Constant::Integer(*n)));
                     self.pos += 1;
                 }
-                Some(Token::FloatLit(f)) => {
-                    items.push(ReplacementItem::Constant(Constant::Float(*f)));
+                Some(Token::FloatLit(f, suffix)) => {
+                    items.push(ReplacementItem::Constant(Constant::Float(*f, *suffix)));
                     self.pos += 1;
                 }
                 Some(Token::StringLit(s)) => {
                     items.push(ReplacementItem::StringLiteral(s.clone()));
                     self.pos += 1;
                 }
+                Some(Token::BoolLit(b)) => {
+                    items.push(ReplacementItem::Constant(Constant::Bool(*b)));
+                    self.pos += 1;
+                }
                 _ => break,
             }
         }
@@ -258,42 +572,104 @@ impl Parser {
         self.skip_whitespace();
         let saved_pos = self.pos;
 
-        // Handle storage class specifiers
-        let storage_class = if self.consume(&Token::Static) {
-            Some(StorageClass::Static)
-        } else {
-            None
-        };
-
-        // Handle type qualifiers
+        // Storage class, qualifiers and function specifiers, in any order
+        let mut storage_class = None;
         let mut type_qualifiers = Vec::new();
-        if self.consume(&Token::Const) {
-            type_qualifiers.push(TypeQualifier::Const);
+        let mut specifiers = Vec::new();
+        let mut attributes = Vec::new();
+        loop {
+            if self.at_attribute() {
+                attributes.extend(self.parse_attributes()?);
+                continue;
+            }
+            match self.peek() {
+                Some(Token::Static) => storage_class = Some(StorageClass::Static),
+                Some(Token::Extern) => storage_class = Some(StorageClass::Extern),
+                Some(Token::Const) => type_qualifiers.push(TypeQualifier::Const),
+                Some(Token::Restrict) => {
+                    self.require(Feature::Restrict);
+                    type_qualifiers.push(TypeQualifier::Restrict);
+                }
+                Some(Token::Inline) => {
+                    self.require(Feature::Inline);
+                    specifiers.push(FunctionSpecifier::Inline);
+                }
+                Some(Token::Noreturn) => {
+                    self.require(Feature::Noreturn);
+                    specifiers.push(FunctionSpecifier::Noreturn);
+                }
+                _ => break,
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+        }
+        let type_pos = self.pos;
+
+        // `struct tag;` only declares the tag, to be defined later
+        if self.peek() == Some(&Token::Struct)
+            && self.peek_at(2) == Some(&Token::Semicolon)
+            && let Some(Token::Identifier(tag)) = self.peek_at(1)
+        {
+            let tag = tag.clone();
+            self.pos += 3;
+            return Some(ExternalDeclaration::StructDeclaration(tag, self.span_since(saved_pos)));
+        }
+        // `struct tag {` defines the struct; `struct tag` alone is a type
+        if self.peek() == Some(&Token::Struct)
+            && matches!(self.peek_at(1), Some(Token::Identifier(_)))
+            && self.peek_at(2) == Some(&Token::BraceL)
+        {
+            return self.parse_struct_definition().map(ExternalDeclaration::Struct);
+        }
+        // `enum tag {` and `enum {` define an enum, the second without a tag
+        if self.peek() == Some(&Token::Enum)
+            && (self.peek_at(1) == Some(&Token::BraceL)
+                || matches!(self.peek_at(1), Some(Token::Identifier(_))) && self.peek_at(2) == Some(&Token::BraceL))
+        {
+            return self.parse_enum_definition().map(ExternalDeclaration::Enum);
         }
 
         // Check if this is a function or variable
         if self.is_type_specifier() {
             if self.is_function_declaration() {
                 // Try function definition first
-                self.pos = saved_pos;
-                if let Some(func) = self.parse_function_definition() {
+                if let Some(mut func) = self.parse_function_definition() {
+                    attributes.append(&mut func.attributes);
+                    func.attributes = attributes;
+                    func.specifiers = specifiers;
+                    func.span = self.span_since(saved_pos);
                     return Some(ExternalDeclaration::Function(func));
                 }
                 // Try function declaration
-                self.pos = saved_pos;
-                if let Some(func_decl) = self.parse_function_declaration() {
+                self.pos = type_pos;
+                if let Some(mut func_decl) = self.parse_function_declaration() {
+                    attributes.append(&mut func_decl.attributes);
+                    func_decl.attributes = attributes;
+                    func_decl.specifiers = specifiers;
+                    func_decl.span = self.span_since(saved_pos);
                     return Some(ExternalDeclaration::FunctionDeclaration(func_decl));
                 }
             }
             // Try variable declaration
-            self.pos = saved_pos;
+            self.pos = type_pos;
             if let Some(mut var_decl) = self.parse_variable_declaration() {
-                if let Some(sc) = storage_class {
-                    var_decl.storage_class = Some(sc);
-                }
-                if !type_qualifiers.is_empty() {
-                    var_decl.type_qualifiers = type_qualifiers;
+                if let Some(specifier) = specifiers.first() {
+                    let keyword = match specifier {
+                        FunctionSpecifier::Inline => "inline",
+                        FunctionSpecifier::Noreturn => "_Noreturn",
+                    };
+                    self.error(ParseError::UnexpectedToken(format!(
+                        "'{}' can only be used on functions, not on '{}'",
+                        keyword, var_decl.declarator.name
+                    )));
                 }
+                attributes.append(&mut var_decl.attributes);
+                var_decl.attributes = attributes;
+                var_decl.storage_class = storage_class;
+                // Qualifiers after the '*'s were already put in by the declarator
+                type_qualifiers.append(&mut var_decl.type_qualifiers);
+                var_decl.type_qualifiers = type_qualifiers;
+                var_decl.span = self.span_since(saved_pos);
                 return Some(ExternalDeclaration::Variable(var_decl));
             }
         }
@@ -304,16 +680,14 @@ impl Parser {
 
     /// Check if current token is a type specifier
     fn is_type_specifier(&self) -> bool {
-        matches!(
-            self.peek(),
-            Some(Token::Int)
-                | Some(Token::Float)
-                | Some(Token::Char)
-                | Some(Token::Double)
-                | Some(Token::Void)
-                | Some(Token::Long)
-                | Some(Token::Short)
-        )
+        is_type_specifier_token(self.peek())
+    }
+
+    /// Whether a type name (in a cast, sizeof or compound literal) starts
+    /// `offset` tokens ahead
+    fn starts_type_name(&self, offset: usize) -> bool {
+        let token = self.peek_at(offset);
+        token == Some(&Token::Const) || is_type_specifier_token(token)
     }
 
     /// Check if this looks like a function (has parentheses after identifier)
@@ -321,22 +695,28 @@ impl Parser {
         let _saved_pos = self.pos;
         let mut check_pos = self.pos;
 
-        // Skip type specifier
+        // Skip type specifier, and the tag after `struct` or `enum`
         if !self.is_type_specifier() {
             return false;
         }
-        check_pos += 1;
+        check_pos += if matches!(self.peek(), Some(Token::Struct | Token::Enum)) { 2 } else { 1 };
 
         // Skip whitespace
         while check_pos < self.tokens.len() {
             match &self.tokens[check_pos] {
-                Token::Comment(_) | Token::BlockComment(_) | Token::Error(_) => {
+                Token::Comment(_) | Token::BlockComment(_) | Token::Pragma(_) | Token::Error(_)
+                | Token::UnterminatedString { .. } | Token::UnterminatedComment { .. } => {
                     check_pos += 1;
                 }
                 _ => break,
             }
         }
 
+        // Skip pointer declarators: char *name(...)
+        while matches!(&self.tokens.get(check_pos), Some(Token::Mult)) {
+            check_pos += 1;
+        }
+
         // Check for identifier
         if !matches!(&self.tokens.get(check_pos), Some(Token::Identifier(_))) {
             return false;
@@ -346,7 +726,8 @@ impl Parser {
         // Skip whitespace
         while check_pos < self.tokens.len() {
             match &self.tokens[check_pos] {
-                Token::Comment(_) | Token::BlockComment(_) | Token::Error(_) => {
+                Token::Comment(_) | Token::BlockComment(_) | Token::Pragma(_) | Token::Error(_)
+                | Token::UnterminatedString { .. } | Token::UnterminatedComment { .. } => {
                     check_pos += 1;
                 }
                 _ => break,
@@ -358,124 +739,560 @@ impl Parser {
     }
 
     // ============================================
-    // Variable Declarations
+    // Attributes
     // ============================================
 
-    fn parse_variable_declaration(&mut self) -> Option<VariableDeclaration> {
-        let type_specifier = self.parse_type_specifier()?;
-        self.skip_whitespace();
-
-        let name = match self.next() {
-            Some(Token::Identifier(id)) => id,
-            _ => return None,
-        };
-
-        // Parse initializer if present
-        let mut initializer = None;
-        if self.consume(&Token::AssignOp) {
-            if let Some(expr) = self.parse_expression() {
-                initializer = Some(Initializer {
-                    kind: InitializerKind::Assignment(expr),
-                });
-            }
+    /// Whether an attribute (`__attribute__((...))` or `[[...]]`) starts here
+    fn at_attribute(&self) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(id)) => id == "__attribute__",
+            Some(Token::BracketL) => self.peek_at(1) == Some(&Token::BracketL),
+            _ => false,
         }
+    }
 
-        // Consume semicolon
-        if !self.consume(&Token::Semicolon) {
-            return None;
+    /// Any number of attribute groups in either syntax. None, with an error
+    /// recorded, if one is malformed.
+    fn parse_attributes(&mut self) -> Option<Vec<Attribute>> {
+        let mut attributes = Vec::new();
+        while self.at_attribute() {
+            let gnu = self.next() != Some(Token::BracketL);
+            let (open, close) = if gnu { (Token::ParenL, Token::ParenR) } else { (Token::BracketL, Token::BracketR) };
+            // `__attribute__` is followed by two parentheses; `[[` already took one bracket
+            if (gnu && !self.consume(&open)) || !self.consume(&open) {
+                return self.malformed_attribute();
+            }
+            while self.peek() != Some(&close) {
+                attributes.push(self.parse_attribute(gnu)?);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+            if !self.consume(&close) || !self.consume(&close) {
+                return self.malformed_attribute();
+            }
         }
-
-        Some(VariableDeclaration {
-            storage_class: None,
-            type_qualifiers: Vec::new(),
-            type_specifier,
-            declarator: Declarator {
-                name,
-                pointer_depth: 0,
-                array_sizes: Vec::new(),
-                function_params: None,
-            },
-            initializer,
-        })
+        Some(attributes)
     }
 
-    fn parse_type_specifier(&mut self) -> Option<TypeSpecifier> {
-        match self.next() {
-            Some(Token::Int) => Some(TypeSpecifier::Int),
-            Some(Token::Float) => Some(TypeSpecifier::Float),
-            Some(Token::Char) => Some(TypeSpecifier::Char),
-            Some(Token::Double) => Some(TypeSpecifier::Double),
-            Some(Token::Void) => Some(TypeSpecifier::Void),
-            Some(Token::Long) => Some(TypeSpecifier::Long),
-            Some(Token::Short) => Some(TypeSpecifier::Short),
-            _ => None,
+    /// `name`, `name(args)`, and in C23 syntax `prefix::name(args)`
+    fn parse_attribute(&mut self, gnu: bool) -> Option<Attribute> {
+        let mut name = match self.next() {
+            Some(Token::Identifier(id)) => id,
+            // Keywords that are also attribute names
+            Some(Token::Const) => "const".to_string(),
+            Some(Token::Noreturn) => "_Noreturn".to_string(),
+            _ => return self.malformed_attribute(),
+        };
+        if !gnu && self.peek() == Some(&Token::Colon) && self.peek_at(1) == Some(&Token::Colon) {
+            self.pos += 2;
+            match self.next() {
+                Some(Token::Identifier(id)) => name = format!("{}::{}", name, id),
+                _ => return self.malformed_attribute(),
+            }
+        }
+        let mut arguments = Vec::new();
+        if self.consume(&Token::ParenL) {
+            while self.peek() != Some(&Token::ParenR) {
+                arguments.push(self.parse_assignment_expression()?);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+            if !self.consume(&Token::ParenR) {
+                return self.malformed_attribute();
+            }
         }
+        Some(Attribute { name, arguments })
     }
 
-    fn parse_type_specifier_string(&mut self) -> Option<String> {
-        match self.next() {
-            Some(Token::Int) => Some("int".to_string()),
-            Some(Token::Float) => Some("float".to_string()),
-            Some(Token::Char) => Some("char".to_string()),
-            Some(Token::Double) => Some("double".to_string()),
-            Some(Token::Void) => Some("void".to_string()),
-            Some(Token::Long) => Some("long".to_string()),
-            Some(Token::Short) => Some("short".to_string()),
-            _ => None,
+    fn malformed_attribute<T>(&mut self) -> Option<T> {
+        let found = match self.tokens.get(self.pos.saturating_sub(1)) {
+            Some(token) => format!("'{}'", token),
+            None => "end of file".to_string(),
+        };
+        let error = ParseError::UnexpectedToken(format!("malformed attribute at {}", found));
+        // A failed function definition is retried as a prototype, which
+        // would report the same attribute again
+        if self.errors.last().map(|(last, _)| last) != Some(&error) {
+            self.error(error);
         }
+        None
     }
 
     // ============================================
-    // Function Declarations
+    // Struct Definitions
     // ============================================
 
-    fn parse_function_declaration(&mut self) -> Option<FunctionDeclaration> {
-        let saved_pos = self.pos;
-        let return_type = self.parse_type_specifier_string()?;
-        self.skip_whitespace();
-
+    /// `struct tag { type member; ... };` Members are declared like
+    /// variables, but without initializers
+    fn parse_struct_definition(&mut self) -> Option<StructDefinition> {
+        let start = self.pos;
+        self.next(); // struct
         let name = match self.next() {
             Some(Token::Identifier(id)) => id,
-            _ => {
-                self.pos = saved_pos;
-                return None;
-            }
+            _ => return None,
         };
+        self.consume(&Token::BraceL);
+        let mut members = Vec::new();
+        while !self.consume(&Token::BraceR) {
+            let member = if self.is_type_specifier() { self.parse_variable_declaration() } else { None };
+            let Some(mut member) = member else {
+                self.error(ParseError::UnexpectedToken(format!("malformed member in struct '{}'", name)));
+                self.find_matching_brace();
+                break;
+            };
+            if member.initializer.take().is_some() {
+                self.error(ParseError::UnexpectedToken(format!(
+                    "struct member '{}' can't have an initializer",
+                    member.declarator.name
+                )));
+            }
+            members.push(member);
+        }
+        self.expect_semicolon(&format!("definition of struct '{}'", name));
+        Some(StructDefinition { name, members, span: self.span_since(start) })
+    }
 
-        if !self.consume(&Token::ParenL) {
-            self.pos = saved_pos;
+    fn parse_enum_definition(&mut self) -> Option<EnumDefinition> {
+        let start = self.pos;
+        self.next(); // enum
+        // The tag may be left out
+        let name = match self.peek() {
+            Some(Token::Identifier(id)) => {
+                let id = id.clone();
+                self.pos += 1;
+                Some(id)
+            }
+            _ => None,
+        };
+        if !self.consume(&Token::BraceL) {
             return None;
         }
+        let mut definition = EnumDefinition { name, enumerators: Vec::new(), span: None };
+        while !self.consume(&Token::BraceR) {
+            let enumerator_start = self.pos;
+            let enumerator = match self.next() {
+                Some(Token::Identifier(enumerator)) => Some(enumerator),
+                _ => None,
+            };
+            let value = match enumerator {
+                Some(_) if self.consume(&Token::AssignOp) => self.parse_conditional_expression().map(Some),
+                Some(_) => Some(None),
+                None => None,
+            };
+            let (Some(enumerator), Some(value)) = (enumerator, value) else {
+                self.error(ParseError::UnexpectedToken(format!("malformed enumerator in {}", definition.describe())));
+                self.find_matching_brace();
+                break;
+            };
+            let span = self.span_since(enumerator_start);
+            definition.enumerators.push(Enumerator { name: enumerator, value, span });
+            // A comma may follow the last enumerator too
+            if !self.consume(&Token::Comma) && self.peek() != Some(&Token::BraceR) {
+                self.error(ParseError::UnexpectedToken(format!("expected ',' or '}}' in {}", definition.describe())));
+                self.find_matching_brace();
+                break;
+            }
+        }
+        if definition.enumerators.is_empty() {
+            self.error(ParseError::UnexpectedToken(format!("{} has no enumerators", definition.describe())));
+        }
+        self.expect_semicolon(&format!("definition of {}", definition.describe()));
+        definition.span = self.span_since(start);
+        Some(definition)
+    }
 
-        let parameters = self.parse_parameter_list();
+    // ============================================
+    // Declarators and Type Names
+    // ============================================
 
-        if !self.consume(&Token::ParenR) {
-            self.pos = saved_pos;
-            return None;
+    /// The declarator after a type specifier, named or abstract: pointers,
+    /// then a name or a parenthesized declarator (either may be left out),
+    /// then array and function suffixes. Returns the name, the derivations
+    /// from the name outwards, and the qualifiers of the outermost pointers.
+    fn parse_declarator_parts(&mut self) -> Option<(Option<String>, Vec<Derivation>, Vec<TypeQualifier>)> {
+        let (pointers, qualifiers) = self.parse_pointer_qualified();
+        let mut name = None;
+        let mut derivations = Vec::new();
+        match self.peek() {
+            Some(Token::Identifier(id)) => {
+                name = Some(id.clone());
+                self.pos += 1;
+            }
+            // `(*)` and `(*name)` nest a declarator; otherwise the parenthesis
+            // starts a parameter list, as in `int (int)`
+            Some(Token::ParenL) if matches!(self.peek_at(1), Some(Token::Mult | Token::ParenL | Token::Identifier(_))) => {
+                self.pos += 1;
+                let (inner_name, inner, _) = self.nested("declarator", Self::parse_declarator_parts)?;
+                if !self.consume(&Token::ParenR) {
+                    return None;
+                }
+                name = inner_name;
+                derivations = inner;
+            }
+            _ => {}
         }
-
-        // Must have semicolon for declaration
-        if !self.consume(&Token::Semicolon) {
-            self.pos = saved_pos;
-            return None;
+        loop {
+            match self.peek() {
+                Some(Token::BracketL) => {
+                    derivations.extend(self.parse_array_dimensions()?.into_iter().map(Derivation::Array));
+                }
+                Some(Token::ParenL) => {
+                    self.pos += 1;
+                    let (parameters, variadic) = self.parse_parameter_list();
+                    if !self.consume(&Token::ParenR) {
+                        return None;
+                    }
+                    derivations.push(Derivation::Function(parameters, variadic));
+                }
+                _ => break,
+            }
         }
+        derivations.extend((0..pointers).map(|_| Derivation::Pointer));
+        Some((name, derivations, qualifiers))
+    }
 
-        Some(FunctionDeclaration {
-            return_type,
-            name,
-            parameters,
-        })
+    /// A type without a name, as in casts and sizeof: `const char *`,
+    /// `int (*)(void)`, `char (*)[10]`
+    fn parse_type_name(&mut self) -> Option<TypeName> {
+        // Qualifiers don't change how values are checked, as for parameters
+        while self.consume(&Token::Const) {}
+        let specifier = self.parse_type_specifier_string()?;
+        let (name, derivations, _) = self.parse_declarator_parts()?;
+        // `(int x)` is reported, then parsed on as if the name weren't
+        // there; anything else, like `(int x = 0`, isn't a type name at all
+        if let Some(name) = name {
+            if self.peek() != Some(&Token::ParenR) {
+                return None;
+            }
+            self.error(ParseError::UnexpectedToken(format!(
+                "unexpected name '{}' in a type name; a cast or sizeof takes a type without one",
+                name
+            )));
+        }
+        Some(TypeName { specifier, derivations })
     }
 
     // ============================================
-    // Function Definitions
+    // Variable Declarations
     // ============================================
 
-    fn parse_function_definition(&mut self) -> Option<FunctionDefinition> {
-        let saved_pos = self.pos;
-        let return_type = self.parse_type_specifier_string()?;
+    fn parse_variable_declaration(&mut self) -> Option<VariableDeclaration> {
+        let start = self.pos;
+        let type_specifier = self.parse_type_specifier()?;
         self.skip_whitespace();
-
+        let (name, derivations, type_qualifiers) = self.parse_declarator_parts()?;
+        let name = name?;
+
+        // From the name outwards: its array dimensions, then its pointers,
+        // then, for a parenthesized declarator, what those point to
+        let mut derivations = derivations.into_iter().peekable();
+        let mut array_sizes = Vec::new();
+        while let Some(Derivation::Array(size)) = derivations.next_if(|d| matches!(d, Derivation::Array(_))) {
+            array_sizes.push(size);
+        }
+        let mut pointer_depth = 0;
+        while derivations.next_if(|d| matches!(d, Derivation::Pointer)).is_some() {
+            pointer_depth += 1;
+        }
+        let pointee: Vec<Derivation> = derivations.collect();
+        if pointer_depth == 0 && !pointee.is_empty() {
+            let what = if array_sizes.is_empty() { "a function" } else { "an array of functions" };
+            self.error(ParseError::UnexpectedToken(format!(
+                "'{}' declares {}; only a pointer to one can be declared here",
+                name, what
+            )));
+            return None;
+        }
+        let attributes = self.parse_attributes()?;
+
+        // Parse initializer if present
+        let mut initializer = None;
+        if self.consume(&Token::AssignOp) {
+            let kind = if self.peek() == Some(&Token::BraceL) {
+                InitializerKind::List(self.nested("initializer", Self::parse_initializer_list)?)
+            } else {
+                // A broken initializer fails the declaration so that
+                // check_for_specific_errors can explain what's missing
+                let expr = self.parse_expression()?;
+                if !array_sizes.is_empty()
+                    && !matches!(expr, Expression::StringLiteral(_) | Expression::EncodedStringLiteral(..))
+                {
+                    self.error(ParseError::UnexpectedToken(format!(
+                        "array '{}' can only be initialized with a string literal or a brace list",
+                        name
+                    )));
+                }
+                InitializerKind::Assignment(expr)
+            };
+            initializer = Some(Initializer { kind });
+        }
+
+        let what = format!("declaration of '{}'", name);
+        self.expect_semicolon(&what);
+
+        Some(VariableDeclaration {
+            attributes,
+            storage_class: None,
+            type_qualifiers,
+            type_specifier,
+            declarator: Declarator {
+                name,
+                pointer_depth,
+                array_sizes,
+                function_params: None,
+                pointee,
+            },
+            initializer,
+            span: self.span_since(start),
+        })
+    }
+
+    /// The `[size]` dimensions after a declarator's name; `[]` leaves the
+    /// size out. A size that isn't a constant makes a variable length array.
+    fn parse_array_dimensions(&mut self) -> Option<Vec<Option<Expression>>> {
+        let mut sizes = Vec::new();
+        while self.consume(&Token::BracketL) {
+            if self.consume(&Token::BracketR) {
+                sizes.push(None);
+                continue;
+            }
+            let size = self.parse_assignment_expression()?;
+            if !self.consume(&Token::BracketR) {
+                return None;
+            }
+            if !size.is_integer_constant() {
+                self.require(Feature::VariableLengthArrays);
+            }
+            sizes.push(Some(size));
+        }
+        Some(sizes)
+    }
+
+    /// One item of a brace list: an expression, a nested list, or either
+    /// after a designator (`[2] = x`, `.field = x`, `[1].y = x`)
+    fn parse_initializer(&mut self) -> Option<Initializer> {
+        if matches!(self.peek(), Some(Token::BracketL | Token::Dot)) {
+            self.require(Feature::DesignatedInitializers);
+            return self.parse_designation();
+        }
+        let kind = if self.peek() == Some(&Token::BraceL) {
+            InitializerKind::List(self.nested("initializer", Self::parse_initializer_list)?)
+        } else {
+            InitializerKind::Assignment(self.parse_assignment_expression()?)
+        };
+        Some(Initializer { kind })
+    }
+
+    /// A designator and what follows it: another designator, which
+    /// reaches further into the same object, or `=` and the value
+    fn parse_designation(&mut self) -> Option<Initializer> {
+        let designator = if self.consume(&Token::BracketL) {
+            let index = self.parse_assignment_expression()?;
+            if !self.consume(&Token::BracketR) {
+                return None;
+            }
+            Designator::Array(index)
+        } else {
+            self.pos += 1;
+            match self.next() {
+                Some(Token::Identifier(member)) => Designator::Member(member),
+                _ => return None,
+            }
+        };
+        let value = if matches!(self.peek(), Some(Token::BracketL | Token::Dot)) {
+            self.nested("initializer", Self::parse_designation)?
+        } else {
+            if !self.consume(&Token::AssignOp) {
+                return None;
+            }
+            self.parse_initializer()?
+        };
+        Some(Initializer { kind: InitializerKind::Designated(designator, Box::new(value)) })
+    }
+
+    /// `{ item, item, ... }`, a trailing comma allowed
+    fn parse_initializer_list(&mut self) -> Option<Vec<Initializer>> {
+        if !self.consume(&Token::BraceL) {
+            return None;
+        }
+        let mut items = Vec::new();
+        while !self.consume(&Token::BraceR) {
+            items.push(self.parse_initializer()?);
+            if !self.consume(&Token::Comma) {
+                return self.consume(&Token::BraceR).then_some(items);
+            }
+        }
+        Some(items)
+    }
+
+    fn parse_type_specifier(&mut self) -> Option<TypeSpecifier> {
+        match self.next() {
+            Some(Token::Int) => Some(TypeSpecifier::Int),
+            Some(Token::Float) => Some(TypeSpecifier::Float),
+            Some(Token::Char) => Some(TypeSpecifier::Char),
+            Some(Token::Double) => Some(TypeSpecifier::Double),
+            Some(Token::Void) => Some(TypeSpecifier::Void),
+            Some(Token::Long) => Some(TypeSpecifier::Long),
+            Some(Token::Short) => Some(TypeSpecifier::Short),
+            Some(Token::Bool) => Some(TypeSpecifier::Bool),
+            Some(Token::Signed) => Some(self.parse_signedness(false)),
+            Some(Token::Unsigned) => Some(self.parse_signedness(true)),
+            Some(Token::Struct) => match self.next() {
+                Some(Token::Identifier(tag)) => Some(TypeSpecifier::Struct(tag)),
+                _ => None,
+            },
+            Some(Token::Enum) => match self.next() {
+                Some(Token::Identifier(tag)) => Some(TypeSpecifier::Enum(tag)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The type `signed` or `unsigned`, just taken, makes of the one after
+    /// it: `unsigned` alone is `unsigned int`, and `signed` changes nothing
+    /// but plain `int`
+    fn parse_signedness(&mut self, unsigned: bool) -> TypeSpecifier {
+        let base = match self.peek() {
+            Some(Token::Int) => Some(TypeSpecifier::Int),
+            Some(Token::Char) => Some(TypeSpecifier::Char),
+            Some(Token::Short) => Some(TypeSpecifier::Short),
+            Some(Token::Long) => Some(TypeSpecifier::Long),
+            _ => None,
+        };
+        if base.is_some() {
+            self.pos += 1;
+        }
+        match (unsigned, base) {
+            (true, Some(TypeSpecifier::Char)) => TypeSpecifier::UnsignedChar,
+            (true, Some(TypeSpecifier::Short)) => TypeSpecifier::UnsignedShort,
+            (true, Some(TypeSpecifier::Long)) => TypeSpecifier::UnsignedLong,
+            (true, _) => TypeSpecifier::Unsigned,
+            (false, Some(TypeSpecifier::Int) | None) => TypeSpecifier::Signed,
+            (false, Some(base)) => base,
+        }
+    }
+
+    /// Consume the `*`s of a pointer declarator and return how many there
+    /// were, along with the qualifiers around them (`char *const p`)
+    fn parse_pointer_qualified(&mut self) -> (u32, Vec<TypeQualifier>) {
+        let mut depth = 0;
+        let mut qualifiers = Vec::new();
+        loop {
+            if self.consume(&Token::Const) {
+                qualifiers.push(TypeQualifier::Const);
+            } else if self.consume(&Token::Restrict) {
+                self.require(Feature::Restrict);
+                qualifiers.push(TypeQualifier::Restrict);
+            } else if self.consume(&Token::Mult) {
+                depth += 1;
+            } else {
+                break;
+            }
+        }
+        (depth, qualifiers)
+    }
+
+    /// Consume the `*`s of a pointer declarator and return how many there were
+    fn parse_pointer_depth(&mut self) -> u32 {
+        self.parse_pointer_qualified().0
+    }
+
+    /// Parse a type specifier followed by pointer stars, e.g. "char*"
+    fn parse_pointer_type_string(&mut self) -> Option<String> {
+        let mut type_str = self.parse_type_specifier_string()?;
+        for _ in 0..self.parse_pointer_depth() {
+            type_str.push('*');
+        }
+        Some(type_str)
+    }
+
+    fn parse_type_specifier_string(&mut self) -> Option<String> {
+        match self.next() {
+            Some(Token::Int) => Some("int".to_string()),
+            Some(Token::Float) => Some("float".to_string()),
+            Some(Token::Char) => Some("char".to_string()),
+            Some(Token::Double) => Some("double".to_string()),
+            Some(Token::Void) => Some("void".to_string()),
+            Some(Token::Long) => Some("long".to_string()),
+            Some(Token::Short) => Some("short".to_string()),
+            Some(Token::Bool) => Some("bool".to_string()),
+            Some(Token::Signed) => Some(self.parse_signedness(false).spelling()),
+            Some(Token::Unsigned) => Some(self.parse_signedness(true).spelling()),
+            Some(Token::Struct) => match self.next() {
+                Some(Token::Identifier(tag)) => Some(format!("struct {}", tag)),
+                _ => None,
+            },
+            Some(Token::Enum) => match self.next() {
+                Some(Token::Identifier(tag)) => Some(format!("enum {}", tag)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // ============================================
+    // Function Declarations
+    // ============================================
+
+    fn parse_function_declaration(&mut self) -> Option<FunctionDeclaration> {
+        let saved_pos = self.pos;
+        let return_type = self.parse_pointer_type_string()?;
+        self.skip_whitespace();
+
+        let name = match self.next() {
+            Some(Token::Identifier(id)) => id,
+            _ => {
+                self.pos = saved_pos;
+                return None;
+            }
+        };
+
+        if !self.consume(&Token::ParenL) {
+            self.pos = saved_pos;
+            return None;
+        }
+
+        let (parameters, variadic) = self.parse_parameter_list();
+
+        if !self.consume(&Token::ParenR) {
+            self.pos = saved_pos;
+            return None;
+        }
+        let attributes = self.parse_attributes()?;
+
+        // Must have semicolon for declaration; a body means this was a
+        // definition that failed to parse
+        if self.peek() == Some(&Token::BraceL) {
+            self.pos = saved_pos;
+            return None;
+        }
+        let what = format!("declaration of function '{}'", name);
+        self.expect_semicolon(&what);
+
+        Some(FunctionDeclaration {
+            attributes,
+            specifiers: Vec::new(),
+            return_type,
+            name,
+            parameters,
+            variadic,
+            span: self.span_since(saved_pos),
+        })
+    }
+
+    // ============================================
+    // Function Definitions
+    // ============================================
+
+    fn parse_function_definition(&mut self) -> Option<FunctionDefinition> {
+        let saved_pos = self.pos;
+        let return_type = self.parse_pointer_type_string()?;
+        self.skip_whitespace();
+        let name_pos = self.pos;
+
         let name = match self.next() {
             Some(Token::Identifier(id)) => id,
             _ => {
@@ -489,18 +1306,29 @@ impl Parser {
             return None;
         }
 
-        let parameters = self.parse_parameter_list();
+        let (parameters, variadic) = self.parse_parameter_list();
 
         if !self.consume(&Token::ParenR) {
             self.pos = saved_pos;
             return None;
         }
+        let attributes = self.parse_attributes()?;
 
         // Must have body for definition
         if !self.consume(&Token::BraceL) {
             self.pos = saved_pos;
             return None;
         }
+        // Only a prototype may leave its parameters unnamed
+        for (index, parameter) in parameters.iter().enumerate() {
+            if parameter.name.is_empty() {
+                self.error(ParseError::UnexpectedToken(format!(
+                    "parameter {} of '{}' needs a name in a function definition",
+                    index + 1,
+                    name
+                )));
+            }
+        }
 
         // Parse function body
         let body = self.parse_statement_list();
@@ -512,21 +1340,51 @@ impl Parser {
         }
 
         Some(FunctionDefinition {
+            attributes,
+            specifiers: Vec::new(),
             return_type,
             name,
             parameters,
+            variadic,
             body,
+            span: self.span_since(saved_pos),
+            name_span: self.spans.get(name_pos).copied(),
         })
     }
 
-    fn parse_parameter_list(&mut self) -> Vec<Parameter> {
+    /// The parameters, and whether `...` ends them: `(const char *format, ...)`
+    fn parse_parameter_list(&mut self) -> (Vec<Parameter>, bool) {
         let mut parameters = Vec::new();
 
+        // f(void) declares an empty parameter list
+        if self.peek() == Some(&Token::Void) && self.peek_at(1) == Some(&Token::ParenR) {
+            self.pos += 1;
+            return (parameters, false);
+        }
+
         while self.pos < self.tokens.len() && self.tokens[self.pos] != Token::ParenR {
             self.skip_whitespace();
             if self.pos >= self.tokens.len() || self.tokens[self.pos] == Token::ParenR {
                 break;
             }
+            // Nothing may follow the ellipsis; parameters after it are
+            // reported, then parsed so the rest of the list is in step. A
+            // declaration and a definition both read the list, so it is
+            // reported once.
+            if self.consume(&Token::Ellipsis) {
+                if self.peek() != Some(&Token::Comma) {
+                    return (parameters, true);
+                }
+                let error = ParseError::UnexpectedToken("'...' must be the last parameter".to_string());
+                let error = (error, ErrorPlace::At(self.pos - 1));
+                if !self.errors.contains(&error) {
+                    self.errors.push(error);
+                }
+                self.pos += 1;
+                let (rest, _) = self.parse_parameter_list();
+                parameters.extend(rest);
+                return (parameters, true);
+            }
 
             if let Some(param) = self.parse_parameter() {
                 parameters.push(param);
@@ -541,19 +1399,30 @@ impl Parser {
             }
         }
 
-        parameters
+        (parameters, false)
     }
 
+    /// `type name`, or just `type` in a prototype: `int`, `char *argv[]`,
+    /// `int (*compare)(int, int)`
     fn parse_parameter(&mut self) -> Option<Parameter> {
-        let param_type = self.parse_type_specifier_string()?;
-        self.skip_whitespace();
-
-        let name = match self.next() {
-            Some(Token::Identifier(id)) => id,
-            _ => return None,
-        };
+        // Qualifiers don't change how the argument is checked: const char* accepts char*
+        let mut type_qualifiers = Vec::new();
+        if self.consume(&Token::Const) {
+            type_qualifiers.push(TypeQualifier::Const);
+        }
+        let specifier = self.parse_type_specifier_string()?;
+        let (name, mut derivations, pointer_qualifiers) = self.parse_declarator_parts()?;
+        type_qualifiers.extend(pointer_qualifiers);
 
-        Some(Parameter { param_type, name })
+        // Array and function parameters are adjusted to pointers: char *argv[]
+        // is char**, and int compare(int, int) is int (*)(int, int)
+        match derivations.first() {
+            Some(Derivation::Array(_)) => derivations[0] = Derivation::Pointer,
+            Some(Derivation::Function(..)) => derivations.insert(0, Derivation::Pointer),
+            _ => {}
+        }
+        let param_type = TypeName { specifier, derivations }.spelling();
+        Some(Parameter { param_type, name: name.unwrap_or_default(), type_qualifiers })
     }
 
     /// Find matching closing brace and advance position
@@ -589,46 +1458,198 @@ impl Parser {
     // ============================================
 
     fn parse_statement_list(&mut self) -> Vec<Statement> {
-        let mut statements = Vec::new();
+        let mut statements: Vec<Statement> = Vec::new();
 
         while self.pos < self.tokens.len() && self.tokens[self.pos] != Token::BraceR {
+            // A comment only marks a fall through when a label follows it
+            let fallthrough_comment = self.at_fallthrough_comment().then_some(self.pos);
             self.skip_whitespace();
+            if let Some(comment) = fallthrough_comment
+                && matches!(self.peek(), Some(Token::Case | Token::Default))
+            {
+                statements.push(Statement { kind: StatementKind::Fallthrough, span: self.spans.get(comment).copied() });
+            }
             if self.pos >= self.tokens.len() || self.tokens[self.pos] == Token::BraceR {
                 break;
             }
 
-            if let Some(stmt) = self.parse_statement() {
+            let start = self.pos;
+            let errors_before = self.errors.len();
+            let statement = self.parse_statement();
+            if std::mem::take(&mut self.too_deep) {
+                // Whatever was parsed is cut short, and retrying inside the
+                // same nest would only fail again; errors found after the
+                // cutoff are just its side effects
+                let cutoff = self.errors[errors_before..]
+                    .iter()
+                    .position(|(error, _)| matches!(error, ParseError::TooDeeplyNested(_)));
+                if let Some(cutoff) = cutoff {
+                    self.errors.truncate(errors_before + cutoff + 1);
+                }
+                self.pos = start;
+                self.skip_statement();
+            } else if let Some(stmt) = statement {
+                let follows_statement =
+                    statements.last().is_some_and(|last| !matches!(last.kind, StatementKind::Declaration(_)));
+                if matches!(stmt.kind, StatementKind::Declaration(_)) && follows_statement {
+                    // At the declaration's first token, not the one after it
+                    let first = start + self.tokens[start..].iter().take_while(|token| is_trivia(token)).count();
+                    self.require_at(Feature::MixedDeclarations, first);
+                }
                 statements.push(stmt);
             } else {
-                // Skip unrecognized token
-                self.pos += 1;
+                // Say where the statement stopped making sense, unless what
+                // failed or the lexer already did, then go on after its ';'
+                // or at the '}' closing the block
+                self.pos = self.pos.max(start);
+                if self.errors.len() == errors_before && self.peek().and_then(Token::lex_error).is_none() {
+                    let found = match self.peek() {
+                        Some(token) => format!("'{}'", token),
+                        None => "end of file".to_string(),
+                    };
+                    self.error(ParseError::UnexpectedToken(format!("unexpected {} in statement", found)));
+                }
+                self.pos = start;
+                self.resynchronize();
+                if self.pos == start {
+                    self.pos += 1;
+                }
             }
         }
 
         statements
     }
 
+    /// Skip past a statement that failed to parse: up to the next ';' or
+    /// to the '}' closing the block it is in. Only braces are counted,
+    /// since the parentheses of a broken statement may not be balanced.
+    fn resynchronize(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(self.pos) {
+            match token {
+                Token::BraceL => depth += 1,
+                Token::BraceR if depth == 0 => return,
+                Token::BraceR => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return;
+                    }
+                }
+                Token::Semicolon if depth == 0 => {
+                    self.pos += 1;
+                    return;
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Skip past the statement starting here: up to a ';' outside any
+    /// brackets, the '}' closing a block that starts it, or the end of the
+    /// enclosing block
+    fn skip_statement(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(self.pos) {
+            match token {
+                Token::ParenL | Token::BracketL | Token::BraceL => depth += 1,
+                Token::ParenR | Token::BracketR | Token::BraceR => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                    if depth == 0 && *token == Token::BraceR {
+                        self.pos += 1;
+                        return;
+                    }
+                }
+                Token::Semicolon if depth == 0 => {
+                    self.pos += 1;
+                    return;
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
     fn parse_statement(&mut self) -> Option<Statement> {
         self.skip_whitespace();
+        let start = self.pos;
 
-        match self.peek() {
+        let kind = match self.peek() {
             Some(Token::Return) => self.parse_return_statement(),
-            Some(Token::If) => self.parse_if_statement(),
-            Some(Token::While) => self.parse_while_statement(),
-            Some(Token::For) => self.parse_for_statement(),
+            Some(Token::If) => self.nested("statement", Self::parse_if_statement),
+            Some(Token::While) => self.nested("statement", Self::parse_while_statement),
+            Some(Token::For) => self.nested("statement", Self::parse_for_statement),
             Some(Token::Break) => self.parse_break_statement(),
-            Some(Token::BraceL) => self.parse_block_statement(),
+            Some(Token::Goto) => self.parse_goto_statement(),
+            Some(Token::Identifier(_)) if self.peek_at(1) == Some(&Token::Colon) => {
+                self.nested("statement", Self::parse_label)
+            }
+            Some(Token::Switch) => self.nested("statement", Self::parse_switch_statement),
+            Some(Token::Case) => self.nested("statement", Self::parse_case_label),
+            Some(Token::Default) => self.nested("statement", Self::parse_default_label),
+            Some(Token::BraceL) => self.nested("statement", Self::parse_block_statement),
+            Some(Token::Semicolon) => {
+                self.pos += 1;
+                Some(StatementKind::Empty)
+            }
+            _ if self.at_attribute() => self.parse_attributed_declaration(),
+            Some(Token::Static) => self.parse_static_declaration(),
             Some(Token::Int)
             | Some(Token::Float)
             | Some(Token::Char)
             | Some(Token::Double)
             | Some(Token::Long)
-            | Some(Token::Short) => self.parse_declaration_statement(),
+            | Some(Token::Short)
+            | Some(Token::Signed)
+            | Some(Token::Unsigned)
+            | Some(Token::Bool)
+            | Some(Token::Struct)
+            | Some(Token::Enum) => self.parse_declaration_statement(),
             _ => self.parse_expression_statement(),
+        }?;
+        Some(Statement { kind, span: self.span_since(start) })
+    }
+
+    /// A local declaration with leading attributes: `[[maybe_unused]] int x;`,
+    /// or the attribute statement `[[fallthrough]];`
+    fn parse_attributed_declaration(&mut self) -> Option<StatementKind> {
+        let mut attributes = self.parse_attributes()?;
+        if self.consume(&Token::Semicolon) {
+            if attributes.iter().any(|attribute| attribute.base_name() == "fallthrough") {
+                return Some(StatementKind::Fallthrough);
+            }
+            self.error(ParseError::UnexpectedToken(
+                "expected a declaration after attributes; only [[fallthrough]] can stand alone".to_string(),
+            ));
+            return None;
+        }
+        match self.parse_declaration_statement()? {
+            StatementKind::Declaration(mut var_decl) => {
+                attributes.append(&mut var_decl.attributes);
+                var_decl.attributes = attributes;
+                Some(StatementKind::Declaration(var_decl))
+            }
+            _ => None,
+        }
+    }
+
+    /// A local that keeps its value between calls: `static int calls = 0;`
+    fn parse_static_declaration(&mut self) -> Option<StatementKind> {
+        self.pos += 1;
+        match self.parse_declaration_statement()? {
+            StatementKind::Declaration(mut var_decl) => {
+                var_decl.storage_class = Some(StorageClass::Static);
+                Some(StatementKind::Declaration(var_decl))
+            }
+            _ => None,
         }
     }
 
-    fn parse_return_statement(&mut self) -> Option<Statement> {
+    fn parse_return_statement(&mut self) -> Option<StatementKind> {
         if !self.consume(&Token::Return) {
             return None;
         }
@@ -640,11 +1661,11 @@ impl Parser {
             None
         };
 
-        self.consume(&Token::Semicolon);
-        Some(Statement::Return(expr))
+        self.expect_semicolon("return statement");
+        Some(StatementKind::Return(expr))
     }
 
-    fn parse_if_statement(&mut self) -> Option<Statement> {
+    fn parse_if_statement(&mut self) -> Option<StatementKind> {
         if !self.consume(&Token::If) {
             return None;
         }
@@ -667,14 +1688,14 @@ impl Parser {
             None
         };
 
-        Some(Statement::If(
+        Some(StatementKind::If(
             condition,
             Box::new(then_stmt),
             else_stmt.map(Box::new),
         ))
     }
 
-    fn parse_while_statement(&mut self) -> Option<Statement> {
+    fn parse_while_statement(&mut self) -> Option<StatementKind> {
         if !self.consume(&Token::While) {
             return None;
         }
@@ -691,10 +1712,10 @@ impl Parser {
 
         let body = self.parse_statement()?;
 
-        Some(Statement::While(condition, Box::new(body)))
+        Some(StatementKind::While(condition, Box::new(body)))
     }
 
-    fn parse_for_statement(&mut self) -> Option<Statement> {
+    fn parse_for_statement(&mut self) -> Option<StatementKind> {
         if !self.consume(&Token::For) {
             return None;
         }
@@ -703,14 +1724,16 @@ impl Parser {
             return None;
         }
 
-        // Parse init (optional)
-        let init = if self.peek() != Some(&Token::Semicolon) {
-            self.parse_statement()
-        } else {
+        // Parse init (optional); a declaration or expression statement
+        // consumes its own ';'
+        let init = if self.consume(&Token::Semicolon) {
             None
+        } else {
+            self.parse_statement()
         };
-
-        self.consume(&Token::Semicolon);
+        if init.as_ref().is_some_and(|init| matches!(init.kind, StatementKind::Declaration(_))) {
+            self.require(Feature::ForLoopDeclarations);
+        }
 
         // Parse condition (optional)
         let condition = if self.peek() != Some(&Token::Semicolon) {
@@ -719,7 +1742,7 @@ impl Parser {
             None
         };
 
-        self.consume(&Token::Semicolon);
+        self.expect_semicolon("for loop condition");
 
         // Parse update (optional)
         let update = if self.peek() != Some(&Token::ParenR) {
@@ -734,7 +1757,7 @@ impl Parser {
 
         let body = self.parse_statement()?;
 
-        Some(Statement::For(
+        Some(StatementKind::For(
             init.map(Box::new),
             condition,
             update,
@@ -742,16 +1765,98 @@ impl Parser {
         ))
     }
 
-    fn parse_break_statement(&mut self) -> Option<Statement> {
+    fn parse_break_statement(&mut self) -> Option<StatementKind> {
         if self.consume(&Token::Break) {
-            self.consume(&Token::Semicolon);
-            Some(Statement::Break)
+            self.expect_semicolon("break statement");
+            Some(StatementKind::Break)
         } else {
             None
         }
     }
 
-    fn parse_block_statement(&mut self) -> Option<Statement> {
+    /// `goto name;`; whether the function has the label is for scope
+    /// analysis to say
+    fn parse_goto_statement(&mut self) -> Option<StatementKind> {
+        self.pos += 1;
+        let Some(Token::Identifier(name)) = self.next() else {
+            self.error(ParseError::UnexpectedToken("expected a label name after 'goto'".to_string()));
+            return None;
+        };
+        self.expect_semicolon("goto statement");
+        Some(StatementKind::Goto(name))
+    }
+
+    fn parse_switch_statement(&mut self) -> Option<StatementKind> {
+        if !self.consume(&Token::Switch) || !self.consume(&Token::ParenL) {
+            return None;
+        }
+        let value = self.parse_expression()?;
+        if !self.consume(&Token::ParenR) {
+            return None;
+        }
+        let body = self.parse_statement()?;
+        Some(StatementKind::Switch(value, Box::new(body)))
+    }
+
+    /// `case constant: statement`; the constant is checked later, since
+    /// sizeof needs struct layouts
+    fn parse_case_label(&mut self) -> Option<StatementKind> {
+        self.pos += 1;
+        let value = self.parse_conditional_expression()?;
+        let statement = self.parse_labeled_statement("case")?;
+        Some(StatementKind::Case(value, Box::new(statement)))
+    }
+
+    fn parse_default_label(&mut self) -> Option<StatementKind> {
+        self.pos += 1;
+        let statement = self.parse_labeled_statement("default")?;
+        Some(StatementKind::Default(Box::new(statement)))
+    }
+
+    /// `name: statement`, a label `goto` can jump to
+    fn parse_label(&mut self) -> Option<StatementKind> {
+        let Some(Token::Identifier(name)) = self.next() else {
+            return None;
+        };
+        let statement = self.parse_labeled_statement(&format!("'{}'", name))?;
+        Some(StatementKind::Label(name, Box::new(statement)))
+    }
+
+    /// The `:` after a label and the statement it labels
+    fn parse_labeled_statement(&mut self, label: &str) -> Option<Statement> {
+        if !self.consume(&Token::Colon) {
+            self.error(ParseError::UnexpectedToken(format!("expected ':' after {} label", label)));
+            return None;
+        }
+        if self.peek() == Some(&Token::BraceR) {
+            self.error(ParseError::UnexpectedToken(format!(
+                "{} label at the end of a block needs a statement after it, such as 'break;'",
+                label
+            )));
+            return None;
+        }
+        self.parse_statement()
+    }
+
+    /// Whether the comments at the current position say the code above
+    /// falls through on purpose: `/* fallthrough */`, `// falls through`,
+    /// `// Intentional fall-thru.`
+    fn at_fallthrough_comment(&self) -> bool {
+        self.tokens[self.pos..]
+            .iter()
+            .take_while(|token| matches!(token, Token::Comment(_) | Token::BlockComment(_)))
+            .any(|token| {
+                let text: String = token.to_string().chars().filter(char::is_ascii_alphabetic).collect();
+                let text = text.to_ascii_lowercase();
+                let text = ["intentionally", "intentional", "else"]
+                    .iter()
+                    .find_map(|prefix| text.strip_prefix(prefix))
+                    .unwrap_or(&text);
+                ["fallthrough", "fallsthrough", "fallthru", "fallsthru"].iter().any(|marker| text.starts_with(marker))
+            })
+    }
+
+    fn parse_block_statement(&mut self) -> Option<StatementKind> {
         if !self.consume(&Token::BraceL) {
             return None;
         }
@@ -759,24 +1864,24 @@ impl Parser {
         let stmts = self.parse_statement_list();
 
         if self.consume(&Token::BraceR) {
-            Some(Statement::Block(stmts))
+            Some(StatementKind::Block(stmts))
         } else {
             None
         }
     }
 
-    fn parse_declaration_statement(&mut self) -> Option<Statement> {
+    fn parse_declaration_statement(&mut self) -> Option<StatementKind> {
         if let Some(var_decl) = self.parse_variable_declaration() {
-            Some(Statement::Declaration(var_decl))
+            Some(StatementKind::Declaration(var_decl))
         } else {
             None
         }
     }
 
-    fn parse_expression_statement(&mut self) -> Option<Statement> {
+    fn parse_expression_statement(&mut self) -> Option<StatementKind> {
         if let Some(expr) = self.parse_expression() {
-            self.consume(&Token::Semicolon);
-            Some(Statement::Expression(expr))
+            self.expect_semicolon("expression statement");
+            Some(StatementKind::Expression(expr))
         } else {
             None
         }
@@ -790,42 +1895,58 @@ impl Parser {
         self.parse_assignment_expression()
     }
 
+    /// assignment_expression: conditional_expression
+    ///                      | unary_expression assignment_operator assignment_expression
+    ///
+    /// Assignment is right-associative: `a = b = c` is `a = (b = c)`.
     fn parse_assignment_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_conditional_expression()?;
-
-        while let Some(op) = self.peek() {
-            let assignment_op = match op {
-                Token::AssignOp => Some(AssignmentOperator::Assign),
-                Token::PlusAssign => Some(AssignmentOperator::PlusAssign),
-                Token::MinusAssign => Some(AssignmentOperator::MinusAssign),
-                Token::MultAssign => Some(AssignmentOperator::MultAssign),
-                Token::DivAssign => Some(AssignmentOperator::DivAssign),
-                Token::ModAssign => Some(AssignmentOperator::ModAssign),
-                _ => None,
-            };
+        let left = self.parse_conditional_expression()?;
 
-            if let Some(op) = assignment_op {
-                self.pos += 1;
-                if let Some(right) = self.parse_assignment_expression() {
-                    left = Expression::Assignment(Box::new(left), op, Box::new(right));
-                } else {
-                    return None;
-                }
-            } else {
-                break;
-            }
-        }
+        let Some(op) = self.peek().and_then(|token| operator_for(ASSIGNMENT_OPERATORS, token)) else {
+            return Some(left);
+        };
+        self.pos += 1;
+
+        // `a && b = c` is `(a && b) = c`; an assignment in an operand needs
+        // parentheses of its own
+        if let Expression::BinaryOp(_, inner, _) = &left {
+            self.error(ParseError::InvalidAssignmentTarget(format!(
+                "left operand of '{}' is the whole '{}' expression, which binds more tightly; put the assignment in parentheses",
+                op.symbol(),
+                inner.symbol()
+            )));
+        } else if !Self::is_lvalue(&left) {
+            self.error(ParseError::InvalidAssignmentTarget(format!(
+                "left operand of '{}' is not assignable",
+                op.symbol()
+            )));
+        }
+
+        let right = self.nested("expression", Self::parse_assignment_expression)?;
+        Some(Expression::Assignment(Box::new(left), op, Box::new(right)))
+    }
 
-        Some(left)
+    /// Expressions that designate an object and so may appear left of `=`
+    fn is_lvalue(expr: &Expression) -> bool {
+        match expr {
+            Expression::Identifier(_)
+            | Expression::ArrayAccess(_, _)
+            | Expression::MemberAccess(_, _)
+            | Expression::PointerAccess(_, _)
+            | Expression::CompoundLiteral(_)
+            | Expression::UnaryOp(UnaryOperator::Dereference, _) => true,
+            Expression::Paren(inner) => Self::is_lvalue(inner),
+            _ => false,
+        }
     }
 
     fn parse_conditional_expression(&mut self) -> Option<Expression> {
         let condition = self.parse_logical_or_expression()?;
 
         if self.consume(&Token::Question) {
-            let true_expr = self.parse_expression()?;
+            let true_expr = self.nested("expression", Self::parse_expression)?;
             if self.consume(&Token::Colon) {
-                let false_expr = self.parse_conditional_expression()?;
+                let false_expr = self.nested("expression", Self::parse_conditional_expression)?;
                 Some(Expression::Conditional(
                     Box::new(condition),
                     Box::new(true_expr),
@@ -840,208 +1961,33 @@ impl Parser {
     }
 
     fn parse_logical_or_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_logical_and_expression()?;
-
-        while self.consume(&Token::OrOp) {
-            if let Some(right) = self.parse_logical_and_expression() {
-                left = Expression::BinaryOp(Box::new(left), BinaryOperator::Or, Box::new(right));
-            } else {
-                return None;
-            }
-        }
-
-        Some(left)
-    }
-
-    fn parse_logical_and_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_bitwise_or_expression()?;
-
-        while self.consume(&Token::AndOp) {
-            if let Some(right) = self.parse_bitwise_or_expression() {
-                left = Expression::BinaryOp(Box::new(left), BinaryOperator::And, Box::new(right));
-            } else {
-                return None;
-            }
-        }
-
-        Some(left)
-    }
-
-    fn parse_bitwise_or_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_bitwise_xor_expression()?;
-
-        while self.consume(&Token::BitOrOp) {
-            if let Some(right) = self.parse_bitwise_xor_expression() {
-                left = Expression::BinaryOp(Box::new(left), BinaryOperator::BitOr, Box::new(right));
-            } else {
-                return None;
-            }
-        }
-
-        Some(left)
-    }
-
-    fn parse_bitwise_xor_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_bitwise_and_expression()?;
-
-        while self.consume(&Token::Xor) {
-            if let Some(right) = self.parse_bitwise_and_expression() {
-                left = Expression::BinaryOp(Box::new(left), BinaryOperator::Xor, Box::new(right));
-            } else {
-                return None;
-            }
-        }
-
-        Some(left)
-    }
-
-    fn parse_bitwise_and_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_equality_expression()?;
-
-        while self.consume(&Token::BitAndOp) {
-            if let Some(right) = self.parse_equality_expression() {
-                left = Expression::BinaryOp(Box::new(left), BinaryOperator::BitAnd, Box::new(right));
-            } else {
-                return None;
-            }
-        }
-
-        Some(left)
-    }
-
-    fn parse_equality_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_relational_expression()?;
-
-        loop {
-            let op = if self.consume(&Token::EqualsOp) {
-                Some(BinaryOperator::Equals)
-            } else if self.consume(&Token::NotEqualsOp) {
-                Some(BinaryOperator::NotEquals)
-            } else {
-                None
-            };
-
-            if let Some(op) = op {
-                if let Some(right) = self.parse_relational_expression() {
-                    left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
-                } else {
-                    return None;
-                }
-            } else {
-                break;
-            }
-        }
-
-        Some(left)
-    }
-
-    fn parse_relational_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_shift_expression()?;
-
-        loop {
-            let op = if self.consume(&Token::LessOp) {
-                Some(BinaryOperator::Less)
-            } else if self.consume(&Token::GreaterOp) {
-                Some(BinaryOperator::Greater)
-            } else if self.consume(&Token::LessEqOp) {
-                Some(BinaryOperator::LessEq)
-            } else if self.consume(&Token::GreaterEqOp) {
-                Some(BinaryOperator::GreaterEq)
-            } else {
-                None
-            };
-
-            if let Some(op) = op {
-                if let Some(right) = self.parse_shift_expression() {
-                    left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
-                } else {
-                    return None;
-                }
-            } else {
-                break;
-            }
-        }
-
-        Some(left)
+        self.parse_binary_expression(0)
     }
 
-    fn parse_shift_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_additive_expression()?;
-
-        loop {
-            let op = if self.consume(&Token::LShift) {
-                Some(BinaryOperator::LShift)
-            } else if self.consume(&Token::RShift) {
-                Some(BinaryOperator::RShift)
-            } else {
-                None
-            };
-
-            if let Some(op) = op {
-                if let Some(right) = self.parse_additive_expression() {
-                    left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
-                } else {
-                    return None;
-                }
-            } else {
-                break;
-            }
-        }
-
-        Some(left)
-    }
-
-    fn parse_additive_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_multiplicative_expression()?;
-
-        loop {
-            let op = if self.consume(&Token::Plus) {
-                Some(BinaryOperator::Plus)
-            } else if self.consume(&Token::Minus) {
-                Some(BinaryOperator::Minus)
-            } else {
-                None
-            };
-
-            if let Some(op) = op {
-                if let Some(right) = self.parse_multiplicative_expression() {
-                    left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
-                } else {
-                    return None;
-                }
-            } else {
-                break;
-            }
-        }
-
-        Some(left)
-    }
-
-    fn parse_multiplicative_expression(&mut self) -> Option<Expression> {
-        let mut left = self.parse_unary_expression()?;
-
-        loop {
-            let op = if self.consume(&Token::Mult) {
-                Some(BinaryOperator::Mult)
-            } else if self.consume(&Token::Div) {
-                Some(BinaryOperator::Div)
-            } else if self.consume(&Token::Mod) {
-                Some(BinaryOperator::Mod)
-            } else {
-                None
-            };
-
-            if let Some(op) = op {
-                if let Some(right) = self.parse_unary_expression() {
-                    left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
-                } else {
-                    return None;
+    /// A binary expression at BINARY_LEVELS[level] or tighter; past the
+    /// last level, a unary expression
+    fn parse_binary_expression(&mut self, level: usize) -> Option<Expression> {
+        let Some((_, operators)) = BINARY_LEVELS.get(level) else {
+            return self.parse_unary_expression();
+        };
+        let mut left = self.parse_binary_expression(level + 1)?;
+        let mut length = 0;
+        while let Some(op) = self.peek().and_then(|token| operator_for(operators, token)) {
+            length += 1;
+            if length > MAX_CHAIN_LENGTH {
+                if !self.too_deep {
+                    self.too_deep = true;
+                    self.error(ParseError::TooDeeplyNested(format!(
+                        "expression has too many operators in a row (limit {})",
+                        MAX_CHAIN_LENGTH
+                    )));
                 }
-            } else {
-                break;
+                return None;
             }
+            self.pos += 1;
+            let right = self.parse_binary_expression(level + 1)?;
+            left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
         }
-
         Some(left)
     }
 
@@ -1050,34 +1996,56 @@ impl Parser {
             match op {
                 Token::Plus => {
                     self.pos += 1;
-                    if let Some(expr) = self.parse_unary_expression() {
+                    if let Some(expr) = self.nested("expression", Self::parse_unary_expression) {
                         return Some(Expression::UnaryOp(UnaryOperator::Plus, Box::new(expr)));
                     }
                 }
                 Token::Minus => {
                     self.pos += 1;
-                    if let Some(expr) = self.parse_unary_expression() {
+                    if let Some(expr) = self.nested("expression", Self::parse_unary_expression) {
                         return Some(Expression::UnaryOp(UnaryOperator::Minus, Box::new(expr)));
                     }
                 }
                 Token::Not => {
                     self.pos += 1;
-                    if let Some(expr) = self.parse_unary_expression() {
+                    if let Some(expr) = self.nested("expression", Self::parse_unary_expression) {
                         return Some(Expression::UnaryOp(UnaryOperator::Not, Box::new(expr)));
                     }
                 }
+                Token::BitNot => {
+                    self.pos += 1;
+                    if let Some(expr) = self.nested("expression", Self::parse_unary_expression) {
+                        return Some(Expression::UnaryOp(UnaryOperator::BitNot, Box::new(expr)));
+                    }
+                }
                 Token::BitAndOp => {
                     self.pos += 1;
-                    if let Some(expr) = self.parse_unary_expression() {
+                    if let Some(expr) = self.nested("expression", Self::parse_unary_expression) {
                         return Some(Expression::UnaryOp(UnaryOperator::AddressOf, Box::new(expr)));
                     }
                 }
                 Token::Mult => {
                     self.pos += 1;
-                    if let Some(expr) = self.parse_unary_expression() {
+                    if let Some(expr) = self.nested("expression", Self::parse_unary_expression) {
                         return Some(Expression::UnaryOp(UnaryOperator::Dereference, Box::new(expr)));
                     }
                 }
+                Token::PlusPlus => {
+                    self.pos += 1;
+                    if let Some(expr) = self.nested("expression", Self::parse_unary_expression) {
+                        return Some(Expression::UnaryOp(UnaryOperator::PreIncrement, Box::new(expr)));
+                    }
+                }
+                Token::MinusMinus => {
+                    self.pos += 1;
+                    if let Some(expr) = self.nested("expression", Self::parse_unary_expression) {
+                        return Some(Expression::UnaryOp(UnaryOperator::PreDecrement, Box::new(expr)));
+                    }
+                }
+                Token::Identifier(id) if id == "sizeof" => {
+                    self.pos += 1;
+                    return self.nested("sizeof", Self::parse_sizeof);
+                }
                 _ => {}
             }
         }
@@ -1087,92 +2055,237 @@ impl Parser {
 
     fn parse_postfix_expression(&mut self) -> Option<Expression> {
         let mut expr = self.parse_primary_expression()?;
+        let depth = self.depth;
 
+        // A call, subscript or member access that isn't finished fails the
+        // whole expression rather than leaving the rest for the statement
         loop {
-            match self.peek() {
+            let finished = match self.peek() {
                 Some(Token::ParenL) => {
                     self.pos += 1;
                     let mut args = Vec::new();
-
+                    let mut complete = true;
                     if self.peek() != Some(&Token::ParenR) {
-                        if let Some(arg) = self.parse_expression() {
-                            args.push(arg);
-                        }
-
-                        while self.consume(&Token::Comma) {
-                            if let Some(arg) = self.parse_expression() {
-                                args.push(arg);
+                        loop {
+                            match self.nested("expression", Self::parse_expression) {
+                                Some(arg) => args.push(arg),
+                                None => {
+                                    complete = false;
+                                    break;
+                                }
+                            }
+                            if !self.consume(&Token::Comma) {
+                                break;
                             }
                         }
                     }
-
-                    if self.consume(&Token::ParenR) {
-                        if let Expression::Identifier(name) = expr {
-                            expr = Expression::FunctionCall(name, args);
-                        }
+                    if complete && self.consume(&Token::ParenR) {
+                        expr = Expression::FunctionCall(Box::new(expr), args);
+                        true
                     } else {
-                        break;
+                        false
                     }
                 }
                 Some(Token::BracketL) => {
                     self.pos += 1;
-                    if let Some(index) = self.parse_expression() {
-                        if self.consume(&Token::BracketR) {
+                    match self.nested("expression", Self::parse_expression) {
+                        Some(index) if self.consume(&Token::BracketR) => {
                             expr = Expression::ArrayAccess(Box::new(expr), Box::new(index));
-                        } else {
-                            break;
+                            true
                         }
-                    } else {
-                        break;
+                        _ => false,
                     }
                 }
                 Some(Token::Dot) => {
                     self.pos += 1;
-                    if let Some(Token::Identifier(member)) = self.next() {
-                        expr = Expression::MemberAccess(Box::new(expr), member);
-                    } else {
-                        break;
+                    match self.peek().cloned() {
+                        Some(Token::Identifier(member)) => {
+                            self.pos += 1;
+                            expr = Expression::MemberAccess(Box::new(expr), member);
+                            true
+                        }
+                        _ => false,
                     }
                 }
                 Some(Token::Arrow) => {
                     self.pos += 1;
-                    if let Some(Token::Identifier(member)) = self.next() {
-                        expr = Expression::PointerAccess(Box::new(expr), member);
-                    } else {
-                        break;
+                    match self.peek().cloned() {
+                        Some(Token::Identifier(member)) => {
+                            self.pos += 1;
+                            expr = Expression::PointerAccess(Box::new(expr), member);
+                            true
+                        }
+                        _ => false,
                     }
                 }
                 Some(Token::PlusPlus) => {
                     self.pos += 1;
                     expr = Expression::PostfixOp(Box::new(expr), PostfixOperator::PlusPlus);
+                    true
                 }
                 Some(Token::MinusMinus) => {
                     self.pos += 1;
                     expr = Expression::PostfixOp(Box::new(expr), PostfixOperator::MinusMinus);
+                    true
                 }
                 _ => break,
+            };
+            if !finished {
+                self.depth = depth;
+                return None;
+            }
+            if !self.descend("expression") {
+                return None;
             }
         }
 
+        self.depth = depth;
         Some(expr)
     }
 
     fn parse_primary_expression(&mut self) -> Option<Expression> {
         match self.next() {
+            Some(Token::Identifier(id)) if id == "nullptr" => Some(Expression::Constant(Constant::Null)),
+            Some(Token::Identifier(id)) if id == "NULL" && self.null_macro_defined => {
+                Some(Expression::Constant(Constant::Null))
+            }
+            Some(Token::Identifier(id)) if id == "_Generic" => {
+                self.require(Feature::GenericSelections);
+                self.nested("_Generic", Self::parse_generic_selection)
+            }
             Some(Token::Identifier(id)) => Some(Expression::Identifier(id)),
             Some(Token::IntLit(n)) => Some(Expression::Constant(Constant::Integer(n))),
-            Some(Token::FloatLit(f)) => Some(Expression::Constant(Constant::Float(f))),
+            Some(Token::FloatLit(f, suffix)) => Some(Expression::Constant(Constant::Float(f, suffix))),
+            Some(Token::BoolLit(b)) => Some(Expression::Constant(Constant::Bool(b))),
             Some(Token::StringLit(s)) => Some(Expression::StringLiteral(s)),
+            Some(Token::EncodedStringLit(encoding, s)) => Some(Expression::EncodedStringLiteral(encoding, s)),
+            Some(Token::CharLit(c)) => Some(Expression::Constant(Constant::Char(c))),
+            Some(Token::EncodedCharLit(encoding, c)) => Some(Expression::Constant(Constant::EncodedChar(encoding, c))),
+            Some(Token::ParenL) if self.peek() == Some(&Token::BraceL) => {
+                // GNU statement expression: ({ stmt; ...; expr; })
+                if !self.gnu_extensions {
+                    self.error(ParseError::UnexpectedToken(
+                        "statement expressions need -fgnu-extensions".to_string(),
+                    ));
+                }
+                let statements = match self.nested("statement expression", Self::parse_block_statement)? {
+                    StatementKind::Block(statements) => statements,
+                    _ => return None,
+                };
+                if self.consume(&Token::ParenR) {
+                    Some(Expression::StatementExpr(statements))
+                } else {
+                    None
+                }
+            }
+            Some(Token::ParenL) if self.starts_type_name(0) => {
+                self.nested("cast", Self::parse_cast_or_compound_literal)
+            }
             Some(Token::ParenL) => {
-                let expr = self.parse_expression()?;
+                let expr = self.nested("expression", Self::parse_expression)?;
                 if self.consume(&Token::ParenR) {
-                    Some(expr)
+                    Some(Expression::Paren(Box::new(expr)))
                 } else {
                     None
                 }
             }
-            _ => None,
+            // Left unread, so the error is reported where the expression
+            // was missing
+            Some(_) => {
+                self.pos -= 1;
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// `sizeof expr` or `sizeof(type)`, after the keyword
+    fn parse_sizeof(&mut self) -> Option<Expression> {
+        if self.peek() == Some(&Token::ParenL) && self.starts_type_name(1) {
+            let start = self.pos;
+            self.pos += 1;
+            let type_name = self.parse_type_name()?;
+            if !self.consume(&Token::ParenR) {
+                return None;
+            }
+            if self.peek() != Some(&Token::BraceL) {
+                return Some(Expression::SizeofType(type_name));
+            }
+            // `sizeof (int[]){1, 2}` measures a compound literal
+            self.pos = start;
         }
+        let operand = self.parse_unary_expression()?;
+        Some(Expression::UnaryOp(UnaryOperator::Sizeof, Box::new(operand)))
+    }
+
+    /// `_Generic(controlling, type: expr, ..., default: expr)`, after the keyword
+    fn parse_generic_selection(&mut self) -> Option<Expression> {
+        if !self.consume(&Token::ParenL) {
+            return None;
+        }
+        let controlling = self.parse_assignment_expression()?;
+        let mut associations = Vec::new();
+        while self.consume(&Token::Comma) {
+            let type_name = if self.consume(&Token::Default) {
+                None
+            } else {
+                Some(self.parse_type_name()?.spelling())
+            };
+            if !self.consume(&Token::Colon) {
+                return None;
+            }
+            let value = self.parse_assignment_expression()?;
+            associations.push(GenericAssociation { type_name, value });
+        }
+        if associations.is_empty() {
+            self.error(ParseError::UnexpectedToken(
+                "_Generic needs at least one 'type: expression' association".to_string(),
+            ));
+            return None;
+        }
+        if !self.consume(&Token::ParenR) {
+            return None;
+        }
+        Some(Expression::Generic(Box::new(controlling), associations))
+    }
+
+    /// `(type)` followed by a brace list is a compound literal; followed by
+    /// anything else it casts the expression that follows
+    fn parse_cast_or_compound_literal(&mut self) -> Option<Expression> {
+        let type_name = self.parse_type_name()?;
+        if !self.consume(&Token::ParenR) {
+            return None;
+        }
+        if self.peek() == Some(&Token::BraceL) {
+            return self.parse_compound_literal(type_name);
+        }
+        let operand = self.parse_unary_expression()?;
+        Some(Expression::Cast(type_name, Box::new(operand)))
+    }
+
+    /// The brace list after `(type)`. Compound literals are arrays of, or
+    /// plain, scalars and pointers, so the type must be one of those.
+    fn parse_compound_literal(&mut self, type_name: TypeName) -> Option<Expression> {
+        let spelling = type_name.spelling();
+        let mut array_sizes = Vec::new();
+        let mut pointers = 0;
+        for derivation in type_name.derivations {
+            match derivation {
+                Derivation::Array(size) if pointers == 0 => array_sizes.push(size),
+                Derivation::Pointer => pointers += 1,
+                _ => {
+                    self.error(ParseError::UnexpectedToken(format!(
+                        "compound literals of type '{}' are not supported",
+                        spelling
+                    )));
+                    return None;
+                }
+            }
+        }
+        self.require(Feature::CompoundLiterals);
+        let initializers = self.parse_initializer_list()?;
+        let type_name = format!("{}{}", type_name.specifier, "*".repeat(pointers));
+        Some(Expression::CompoundLiteral(Box::new(CompoundLiteral { type_name, array_sizes, initializers })))
     }
 
     // ============================================
@@ -1185,9 +2298,9 @@ impl Parser {
         }
 
         // Check for missing identifier after type: int = 5;
-        if let (Some(Token::Int | Token::Float | Token::Char | Token::Double | Token::Long | Token::Short | Token::Void),
+        if let (Some(Token::Int | Token::Float | Token::Char | Token::Double | Token::Long | Token::Short | Token::Bool | Token::Void),
                 Some(Token::AssignOp),
-                Some(Token::IntLit(_) | Token::FloatLit(_) | Token::StringLit(_) | Token::BoolLit(_))) =
+                Some(Token::IntLit(_) | Token::FloatLit(..) | Token::StringLit(_) | Token::BoolLit(_))) =
             (self.peek(), self.peek_at(1), self.peek_at(2))
         {
             return Err(ParseError::ExpectedIdentifier);
@@ -1218,7 +2331,7 @@ impl Parser {
         if let (Some(Token::Int | Token::Float | Token::Char | Token::Double),
                 Some(Token::Identifier(_)),
                 Some(Token::AssignOp),
-                Some(Token::IntLit(_) | Token::FloatLit(_)),
+                Some(Token::IntLit(_) | Token::FloatLit(..)),
                 Some(Token::Plus | Token::Minus | Token::Mult | Token::Div),
                 Some(Token::Semicolon)) =
             (self.peek(), self.peek_at(1), self.peek_at(2), self.peek_at(3), self.peek_at(4), self.peek_at(5))
@@ -1230,3 +2343,69 @@ impl Parser {
     }
 }
 
+/// Read back a type name spelled by `TypeName::spelling`, such as
+/// "int (*)(void)"; None if it doesn't parse as one
+pub fn parse_type_name(text: &str) -> Option<TypeName> {
+    let mut parser = Parser::new(crate::lexer_regex::lex_with_regex(text));
+    let type_name = parser.parse_type_name()?;
+    (parser.pos == parser.tokens.len() && parser.errors.is_empty()).then_some(type_name)
+}
+
+/// An expression on its own, as the debugger's print command takes one;
+/// None if the text isn't exactly one expression
+pub fn parse_expression(text: &str) -> Option<Expression> {
+    parse_expression_tokens(crate::lexer_regex::lex_with_regex(text))
+}
+
+/// An expression that is exactly `tokens`, as in parse_expression
+pub fn parse_expression_tokens(tokens: Vec<Token>) -> Option<Expression> {
+    let mut parser = Parser::new(tokens);
+    let expression = parser.parse_expression()?;
+    (parser.pos == parser.tokens.len() && parser.errors.is_empty()).then_some(expression)
+}
+
+/// Whether a token is a type specifier keyword
+fn is_type_specifier_token(token: Option<&Token>) -> bool {
+    matches!(
+        token,
+        Some(Token::Int)
+            | Some(Token::Float)
+            | Some(Token::Char)
+            | Some(Token::Double)
+            | Some(Token::Void)
+            | Some(Token::Long)
+            | Some(Token::Short)
+            | Some(Token::Signed)
+            | Some(Token::Unsigned)
+            | Some(Token::Bool)
+            | Some(Token::Struct)
+            | Some(Token::Enum)
+    )
+}
+
+/// Tokens the parser skips as whitespace: comments, pragmas and errors
+fn is_trivia(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Comment(_)
+            | Token::BlockComment(_)
+            | Token::Pragma(_)
+            | Token::Error(_)
+            | Token::UnterminatedString { .. }
+            | Token::UnterminatedComment { .. }
+    )
+}
+
+/// The span of token `pos`, or of the last token if it is past the end
+pub fn token_span(spans: &[TokenSpan], pos: usize) -> Option<TokenSpan> {
+    spans.get(pos).or(spans.last()).copied()
+}
+
+/// The span an error was found at: its token's, or the empty one just
+/// past the token it belongs after
+pub fn error_span(spans: &[TokenSpan], place: ErrorPlace) -> Option<TokenSpan> {
+    match place {
+        ErrorPlace::At(pos) => token_span(spans, pos),
+        ErrorPlace::After(pos) => token_span(spans, pos).map(|span| span.end()),
+    }
+}
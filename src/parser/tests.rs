@@ -0,0 +1,180 @@
+// tests.rs: The shape of the trees the parser builds for expressions,
+// written as `--emit=ast` writes them, so each case reads as its grouping.
+
+use super::{MAX_CHAIN_LENGTH, MAX_NESTING_DEPTH, Parser, STACK_SIZE, parse_expression};
+use crate::ast_text;
+use crate::dialect::Standard;
+use crate::lexer_regex;
+use crate::parser::ast::{ExternalDeclaration, ParseError};
+use std::thread;
+
+/// An expression's tree in the AST text form
+fn shape(text: &str) -> String {
+    let expression = parse_expression(text).unwrap_or_else(|| panic!("{:?} should parse as an expression", text));
+    ast_text::expression(&expression)
+}
+
+/// The errors parsing a program gives, or none
+fn errors(program: &str) -> Vec<ParseError> {
+    Parser::new(lexer_regex::lex_with_regex(program)).parse().err().unwrap_or_default()
+}
+
+#[test]
+fn binary_operators_bind_by_precedence() {
+    let cases = [
+        ("1 + 2 * 3", "(+ (int 1) (* (int 2) (int 3)))"),
+        ("1 * 2 + 3", "(+ (* (int 1) (int 2)) (int 3))"),
+        ("a << 1 + 2", "(<< (id a) (+ (int 1) (int 2)))"),
+        ("a < b == c > d", "(== (< (id a) (id b)) (> (id c) (id d)))"),
+        ("a & b == c", "(& (id a) (== (id b) (id c)))"),
+        ("a | b ^ c & d", "(| (id a) (^ (id b) (& (id c) (id d))))"),
+        ("a || b && c", "(|| (id a) (&& (id b) (id c)))"),
+        ("-a * b", "(* (prefix - (id a)) (id b))"),
+        ("a + b ? c : d", "(?: (+ (id a) (id b)) (id c) (id d))"),
+        ("a = b + c", "(assign = (id a) (+ (id b) (id c)))"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(shape(text), expected, "{}", text);
+    }
+}
+
+#[test]
+fn binary_operators_group_left_to_right() {
+    let cases = [
+        ("a - b - c", "(- (- (id a) (id b)) (id c))"),
+        ("a / b * c", "(* (/ (id a) (id b)) (id c))"),
+        ("a << b >> c", "(>> (<< (id a) (id b)) (id c))"),
+        ("a && b && c", "(&& (&& (id a) (id b)) (id c))"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(shape(text), expected, "{}", text);
+    }
+}
+
+#[test]
+fn assignment_and_conditional_group_right_to_left() {
+    let cases = [
+        ("a = b = c", "(assign = (id a) (assign = (id b) (id c)))"),
+        ("a += b -= c", "(assign += (id a) (assign -= (id b) (id c)))"),
+        ("a <<= b |= c", "(assign <<= (id a) (assign |= (id b) (id c)))"),
+        ("a ? b : c ? d : e", "(?: (id a) (id b) (?: (id c) (id d) (id e)))"),
+        ("a = b ? c : d", "(assign = (id a) (?: (id b) (id c) (id d)))"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(shape(text), expected, "{}", text);
+    }
+}
+
+#[test]
+fn parentheses_are_kept_as_paren_nodes() {
+    let cases = [
+        ("(1 + 2) * 3", "(* (paren (+ (int 1) (int 2))) (int 3))"),
+        ("a - (b - c)", "(- (id a) (paren (- (id b) (id c))))"),
+        ("((a))", "(paren (paren (id a)))"),
+        ("(a) = 1", "(assign = (paren (id a)) (int 1))"),
+        ("(a = b)", "(paren (assign = (id a) (id b)))"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(shape(text), expected, "{}", text);
+    }
+}
+
+#[test]
+fn assignment_needs_an_lvalue_target() {
+    for target in ["a", "a[0]", "s.x", "p->x", "*p", "(a)"] {
+        let program = format!("int main() {{ {} = 1; return 0; }}", target);
+        assert_eq!(errors(&program), Vec::new(), "{}", target);
+    }
+    for target in ["1", "a + b", "f()", "a && b"] {
+        let program = format!("int main() {{ {} = 1; return 0; }}", target);
+        let errors = errors(&program);
+        assert!(
+            matches!(errors.as_slice(), [ParseError::InvalidAssignmentTarget(_), ..]),
+            "{}: {:?}",
+            target,
+            errors
+        );
+    }
+}
+
+#[test]
+fn ellipsis_must_be_the_last_parameter() {
+    assert_eq!(errors("int f(int n, ...);"), Vec::new());
+    for program in ["int h(..., int n);", "int h(int a, ..., int n) { return n; }"] {
+        assert_eq!(
+            errors(program),
+            vec![ParseError::UnexpectedToken("'...' must be the last parameter".to_string())],
+            "{}",
+            program
+        );
+    }
+}
+
+#[test]
+fn only_real_nesting_counts_towards_the_limit() {
+    // Trees this deep need the stack the driver parses on, even to be dropped
+    let checks = thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+        let chain = |terms: usize| format!("int main() {{ int x = {}; return x; }}", vec!["1"; terms].join(" + "));
+        // Written flat, however deep its tree nests down its left operands
+        assert_eq!(errors(&chain(MAX_NESTING_DEPTH + 4)), Vec::new());
+        assert_eq!(errors(&chain(MAX_CHAIN_LENGTH + 1)), Vec::new());
+        let limit = format!("expression has too many operators in a row (limit {})", MAX_CHAIN_LENGTH);
+        assert_eq!(errors(&chain(MAX_CHAIN_LENGTH + 2)), vec![ParseError::TooDeeplyNested(limit)]);
+        let parenthesized = |depth: usize| format!("int main() {{ return {}1{}; }}", "(".repeat(depth), ")".repeat(depth));
+        let too_deep = format!("expression too deeply nested (limit {})", MAX_NESTING_DEPTH);
+        assert_eq!(errors(&parenthesized(MAX_NESTING_DEPTH + 4)), vec![ParseError::TooDeeplyNested(too_deep)]);
+    });
+    checks.unwrap().join().unwrap();
+}
+
+#[test]
+fn a_missing_semicolon_is_placed_after_the_last_token_written() {
+    let program = "int main() {\n    int a = 1;\n    if (a > 0) {\n        return a // done\n    }\n    return 0;\n}\n";
+    let mut parser = Parser::with_spans(lexer_regex::lex_with_regex_positions(program, Default::default()));
+    assert!(parser.parse().is_err());
+    let places: Vec<_> = parser.error_spans().into_iter().flatten().map(|span| (span.line, span.col)).collect();
+    assert_eq!(places, vec![(4, 17)]);
+}
+
+#[test]
+fn a_declaration_after_a_statement_is_reported_where_it_starts() {
+    let program = "int main() {\n    int a = 1;\n    a = a + 1;\n    int b =\n        a;\n    return b;\n}\n";
+    let mut parser = Parser::with_spans(lexer_regex::lex_with_regex_positions(program, Default::default()));
+    parser.set_standard(Standard::C89);
+    assert!(parser.parse().is_err());
+    let places: Vec<_> = parser.error_spans().into_iter().flatten().map(|span| (span.line, span.col)).collect();
+    assert_eq!(places, vec![(4, 5)]);
+}
+
+#[test]
+fn named_declarators_nest_like_abstract_ones() {
+    let program = "int (*fp)(int); char (*pb)[10]; int *(*table[3])(void);";
+    let unit = Parser::new(lexer_regex::lex_with_regex(program)).parse().expect("the declarations should parse");
+    let declared: Vec<String> = unit
+        .external_declarations
+        .iter()
+        .map(|external| match external {
+            ExternalDeclaration::Variable(declaration) => {
+                declaration.declarator.type_name(&declaration.type_specifier).declaring(&declaration.declarator.name)
+            }
+            other => panic!("{:?} should be a variable declaration", other),
+        })
+        .collect();
+    assert_eq!(declared, ["int (*fp)(int)", "char (*pb)[10]", "int *(*table[3])(void)"]);
+    let function = "'f' declares a function; only a pointer to one can be declared here".to_string();
+    assert_eq!(errors("int main() { int f(int); return 0; }"), vec![ParseError::UnexpectedToken(function)]);
+}
+
+#[test]
+fn statements_span_from_their_first_token_to_their_last() {
+    let program = "int main(void) {\n    int x = 1;\n    if (x > 0)\n        x = 2;\n    return x;\n}\n";
+    let tokens = lexer_regex::lex_with_regex_positions(program, Default::default());
+    let unit = Parser::with_spans(tokens).parse().expect("program should parse");
+    let Some(ExternalDeclaration::Function(main)) = unit.external_declarations.first() else {
+        panic!("main should be a function definition");
+    };
+    assert_eq!(main.name_span.map(|span| (span.line, span.col)), Some((1, 5)));
+    let lines: Vec<Option<(usize, usize)>> =
+        main.body.iter().map(|statement| statement.span.map(|span| (span.line, span.end_line))).collect();
+    assert_eq!(lines, vec![Some((2, 2)), Some((3, 4)), Some((5, 5))]);
+}
@@ -0,0 +1,385 @@
+// validate.rs: Debug-mode checks of AST invariants the parser should guarantee.
+//
+// A violation here means the parser built a tree later phases can't trust,
+// so it is reported as a parser bug rather than as an error in the program.
+
+use crate::parser::ast::*;
+
+#[derive(Debug, Clone)]
+pub struct ValidationError {
+    pub node: String,    // path to the offending node, e.g. "function 'main' > for > init"
+    pub message: String, // which invariant was broken
+}
+
+#[derive(Default)]
+pub struct AstValidator {
+    path: Vec<String>,
+    errors: Vec<ValidationError>,
+}
+
+impl AstValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Walk the whole translation unit, returning every invariant violation found
+    pub fn validate(mut self, unit: &TranslationUnit) -> Result<(), Vec<ValidationError>> {
+        for directive in &unit.preprocessor_list {
+            match directive {
+                PreprocessorDirective::Define(name, _)
+                | PreprocessorDirective::Ifdef(name)
+                | PreprocessorDirective::Ifndef(name) => {
+                    self.check_name(name, "macro name");
+                }
+                PreprocessorDirective::Include(path) => {
+                    if path.is_empty() {
+                        self.report("include directive has an empty path");
+                    }
+                }
+                PreprocessorDirective::Endif => {}
+            }
+        }
+
+        for declaration in &unit.external_declarations {
+            match declaration {
+                ExternalDeclaration::Variable(var) => self.validate_variable(var),
+                ExternalDeclaration::Function(func) => {
+                    self.enter(format!("function '{}'", func.name));
+                    self.check_name(&func.name, "function name");
+                    self.check_type_name(&func.return_type, "return type");
+                    self.validate_parameters(&func.parameters, true);
+                    for stmt in &func.body {
+                        self.validate_statement(stmt);
+                    }
+                    self.leave();
+                }
+                ExternalDeclaration::FunctionDeclaration(decl) => {
+                    self.enter(format!("prototype '{}'", decl.name));
+                    self.check_name(&decl.name, "function name");
+                    self.check_type_name(&decl.return_type, "return type");
+                    self.validate_parameters(&decl.parameters, false);
+                    self.leave();
+                }
+                ExternalDeclaration::Struct(definition) => {
+                    self.enter(format!("struct '{}'", definition.name));
+                    self.check_name(&definition.name, "struct tag");
+                    for member in &definition.members {
+                        if member.initializer.is_some() {
+                            self.report(&format!("member '{}' has an initializer", member.declarator.name));
+                        }
+                        self.validate_variable(member);
+                    }
+                    self.leave();
+                }
+                ExternalDeclaration::StructDeclaration(tag, _) => self.check_name(tag, "struct tag"),
+                ExternalDeclaration::Enum(definition) => {
+                    self.enter(definition.describe());
+                    if let Some(tag) = &definition.name {
+                        self.check_name(tag, "enum tag");
+                    }
+                    if definition.enumerators.is_empty() {
+                        self.report("enum has no enumerators");
+                    }
+                    for enumerator in &definition.enumerators {
+                        self.check_name(&enumerator.name, "enumerator name");
+                        if let Some(value) = &enumerator.value {
+                            self.validate_expression(value);
+                        }
+                    }
+                    self.leave();
+                }
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn enter(&mut self, node: String) {
+        self.path.push(node);
+    }
+
+    fn leave(&mut self) {
+        self.path.pop();
+    }
+
+    fn report(&mut self, message: &str) {
+        let node = if self.path.is_empty() {
+            "translation unit".to_string()
+        } else {
+            self.path.join(" > ")
+        };
+        self.errors.push(ValidationError { node, message: message.to_string() });
+    }
+
+    fn check_name(&mut self, name: &str, what: &str) {
+        if name.is_empty() {
+            self.report(&format!("{} is empty", what));
+        }
+    }
+
+    fn check_type_name(&mut self, type_name: &str, what: &str) {
+        if type_name.trim_end_matches('*').is_empty() {
+            self.report(&format!("{} has no base type", what));
+        }
+    }
+
+    /// Only a function definition needs its parameters named
+    fn validate_parameters(&mut self, parameters: &[Parameter], named: bool) {
+        for (index, param) in parameters.iter().enumerate() {
+            self.enter(format!("parameter {}", index + 1));
+            if named {
+                self.check_name(&param.name, "parameter name");
+            }
+            self.check_type_name(&param.param_type, "parameter type");
+            self.leave();
+        }
+    }
+
+    fn validate_type_name(&mut self, type_name: &TypeName, what: &str) {
+        self.check_type_name(&type_name.specifier, what);
+        for derivation in &type_name.derivations {
+            match derivation {
+                Derivation::Pointer => {}
+                Derivation::Array(size) => {
+                    if let Some(size) = size {
+                        self.validate_expression(size);
+                    }
+                }
+                Derivation::Function(parameters, _) => self.validate_parameters(parameters, false),
+            }
+        }
+    }
+
+    fn validate_variable(&mut self, var: &VariableDeclaration) {
+        let declarator = &var.declarator;
+        self.enter(format!("declaration '{}'", declarator.name));
+        self.check_name(&declarator.name, "declarator name");
+
+        for size in declarator.array_sizes.iter().flatten() {
+            self.validate_expression(size);
+        }
+        if let Some(params) = &declarator.function_params {
+            self.validate_parameters(params, false);
+        }
+        if let Some(pointee) = declarator.pointee_type(&var.type_specifier) {
+            self.validate_type_name(&pointee, "pointee type");
+        }
+
+        if let Some(init) = &var.initializer {
+            if declarator.function_params.is_some() {
+                self.report("function declarator has an initializer");
+            }
+            match &init.kind {
+                InitializerKind::Assignment(expr) => {
+                    // Only a string literal may initialize an array as a whole
+                    if !declarator.array_sizes.is_empty()
+                        && !matches!(expr, Expression::StringLiteral(_) | Expression::EncodedStringLiteral(..))
+                    {
+                        self.report("array declarator initialized with a scalar expression");
+                    }
+                }
+                InitializerKind::List(_) => {
+                    if declarator.array_sizes.is_empty() && declarator.pointer_depth > 0 {
+                        self.report("pointer declarator initialized with a brace list");
+                    }
+                }
+                InitializerKind::Designated(_, _) => {
+                    self.report("designated initializer outside of a brace list");
+                }
+            }
+            self.validate_initializer(init);
+        }
+        self.leave();
+    }
+
+    fn validate_initializer(&mut self, init: &Initializer) {
+        match &init.kind {
+            InitializerKind::Assignment(expr) => self.validate_expression(expr),
+            InitializerKind::List(items) => {
+                for item in items {
+                    self.validate_initializer(item);
+                }
+            }
+            InitializerKind::Designated(designator, value) => {
+                match designator {
+                    Designator::Member(name) => self.check_name(name, "designated member"),
+                    Designator::Array(index) => self.validate_expression(index),
+                }
+                self.validate_initializer(value);
+            }
+        }
+    }
+
+    fn validate_statement(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::Declaration(var) => self.validate_variable(var),
+            StatementKind::Assignment(name, expr) => {
+                self.check_name(name, "assignment target");
+                self.validate_expression(expr);
+            }
+            StatementKind::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.validate_expression(expr);
+                }
+            }
+            StatementKind::Expression(expr) => self.validate_expression(expr),
+            StatementKind::Block(stmts) => {
+                self.enter("block".to_string());
+                for stmt in stmts {
+                    self.validate_statement(stmt);
+                }
+                self.leave();
+            }
+            StatementKind::If(cond, then_stmt, else_stmt) => {
+                self.enter("if".to_string());
+                self.validate_expression(cond);
+                self.validate_substatement(then_stmt, "then");
+                if let Some(else_stmt) = else_stmt {
+                    self.validate_substatement(else_stmt, "else");
+                }
+                self.leave();
+            }
+            StatementKind::While(cond, body) => {
+                self.enter("while".to_string());
+                self.validate_expression(cond);
+                self.validate_substatement(body, "body");
+                self.leave();
+            }
+            StatementKind::For(init, cond, update, body) => {
+                self.enter("for".to_string());
+                if let Some(init) = init {
+                    self.enter("init".to_string());
+                    match &init.kind {
+                        StatementKind::Declaration(_) | StatementKind::Expression(_) => {}
+                        _ => self.report("for-loop init must be a declaration or an expression"),
+                    }
+                    self.validate_statement(init);
+                    self.leave();
+                }
+                if let Some(cond) = cond {
+                    self.validate_expression(cond);
+                }
+                if let Some(update) = update {
+                    self.validate_expression(update);
+                }
+                self.validate_substatement(body, "body");
+                self.leave();
+            }
+            StatementKind::Switch(value, body) => {
+                self.enter("switch".to_string());
+                self.validate_expression(value);
+                self.validate_substatement(body, "body");
+                self.leave();
+            }
+            // C23 lets a label stand before a declaration too
+            StatementKind::Case(value, stmt) => {
+                self.enter("case".to_string());
+                self.validate_expression(value);
+                self.validate_statement(stmt);
+                self.leave();
+            }
+            StatementKind::Default(stmt) => {
+                self.enter("default".to_string());
+                self.validate_statement(stmt);
+                self.leave();
+            }
+            StatementKind::Label(name, stmt) => {
+                self.enter(format!("label {}", name));
+                self.check_name(name, "label");
+                self.validate_statement(stmt);
+                self.leave();
+            }
+            StatementKind::Goto(name) => self.check_name(name, "goto target"),
+            StatementKind::Break | StatementKind::Fallthrough | StatementKind::Empty => {}
+        }
+    }
+
+    /// The body of if/while/for is a statement, never a bare declaration
+    fn validate_substatement(&mut self, stmt: &Statement, role: &str) {
+        self.enter(role.to_string());
+        if matches!(stmt.kind, StatementKind::Declaration(_)) {
+            self.report("declaration used as a statement body");
+        }
+        self.validate_statement(stmt);
+        self.leave();
+    }
+
+    fn validate_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Identifier(name) => self.check_name(name, "identifier"),
+            Expression::Constant(_)
+            | Expression::Folded(..)
+            | Expression::StringLiteral(_)
+            | Expression::EncodedStringLiteral(..) => {}
+            Expression::BinaryOp(left, _, right) => {
+                self.validate_expression(left);
+                self.validate_expression(right);
+            }
+            Expression::UnaryOp(_, operand)
+            | Expression::PostfixOp(operand, _)
+            | Expression::Paren(operand) => self.validate_expression(operand),
+            Expression::Cast(target, operand) => {
+                self.validate_type_name(target, "cast type");
+                self.validate_expression(operand);
+            }
+            Expression::SizeofType(operand) => self.validate_type_name(operand, "sizeof type"),
+            Expression::Assignment(target, _, value) => {
+                self.validate_expression(target);
+                self.validate_expression(value);
+            }
+            Expression::Conditional(cond, then_expr, else_expr) => {
+                self.validate_expression(cond);
+                self.validate_expression(then_expr);
+                self.validate_expression(else_expr);
+            }
+            Expression::FunctionCall(callee, args) => {
+                self.validate_expression(callee);
+                for arg in args {
+                    self.validate_expression(arg);
+                }
+            }
+            Expression::ArrayAccess(array, index) => {
+                self.validate_expression(array);
+                self.validate_expression(index);
+            }
+            Expression::MemberAccess(object, member) | Expression::PointerAccess(object, member) => {
+                self.validate_expression(object);
+                self.check_name(member, "member name");
+            }
+            Expression::CompoundLiteral(literal) => {
+                self.enter("compound literal".to_string());
+                self.check_type_name(&literal.type_name, "compound literal type");
+                for size in literal.array_sizes.iter().flatten() {
+                    self.validate_expression(size);
+                }
+                for item in &literal.initializers {
+                    self.validate_initializer(item);
+                }
+                self.leave();
+            }
+            Expression::StatementExpr(stmts) => {
+                self.enter("statement expression".to_string());
+                for stmt in stmts {
+                    self.validate_statement(stmt);
+                }
+                self.leave();
+            }
+            Expression::Generic(controlling, associations) => {
+                self.validate_expression(controlling);
+                let defaults = associations.iter().filter(|a| a.type_name.is_none()).count();
+                if associations.is_empty() {
+                    self.report("_Generic without associations");
+                } else if defaults > 1 {
+                    self.report("_Generic with more than one default association");
+                }
+                for association in associations {
+                    self.validate_expression(&association.value);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// plugins.rs: Registry for extra lints, passes, emit targets and intrinsics.
+//
+// A course or downstream tool registers its own checks here instead of
+// forking the compiler:
+//
+//     let mut registry = PluginRegistry::new();
+//     registry.register_lint(Box::new(NoGlobalsLint));
+//     let diagnostics = registry.run_lints(&ast);
+//
+// There is no IR yet, so passes transform the AST between parsing and
+// scope analysis. Constants are folded and intrinsics expanded then too,
+// before the passes run.
+
+use crate::diagnostics::Diagnostic;
+use crate::fold;
+use crate::intrinsics;
+use crate::parser::ast::{Expression, TranslationUnit};
+
+#[derive(Debug, Clone)]
+pub struct LintDiagnostic {
+    pub lint: String,        // name of the lint that reported it
+    pub line: Option<usize>, // source line, when the lint can tell
+    pub message: String,
+}
+
+/// A read-only check over the parsed program
+pub trait AstLint {
+    fn name(&self) -> &str;
+    fn check(&self, unit: &TranslationUnit) -> Vec<LintDiagnostic>;
+}
+
+/// A transformation applied to the AST before semantic analysis
+pub trait AstPass {
+    fn name(&self) -> &str;
+    fn run(&self, unit: &mut TranslationUnit);
+}
+
+/// An output format selected with `--emit=<name>`
+pub trait EmitTarget {
+    fn name(&self) -> &str;
+    fn emit(&self, unit: &TranslationUnit, source: &str, diagnostics: &[Diagnostic]) -> String;
+}
+
+/// A function the compiler expands in place of each call to it
+pub trait Intrinsic {
+    fn name(&self) -> &str;
+    /// What a call with these arguments becomes, or None to leave the call
+    fn expand(&self, arguments: &[Expression]) -> Option<Expression>;
+}
+
+#[derive(Default)]
+pub struct PluginRegistry {
+    lints: Vec<Box<dyn AstLint>>,
+    passes: Vec<Box<dyn AstPass>>,
+    emit_targets: Vec<Box<dyn EmitTarget>>,
+    intrinsics: Vec<Box<dyn Intrinsic>>,
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_lint(&mut self, lint: Box<dyn AstLint>) {
+        self.lints.push(lint);
+    }
+
+    /// Passes run in registration order
+    pub fn register_pass(&mut self, pass: Box<dyn AstPass>) {
+        self.passes.push(pass);
+    }
+
+    /// A target registered later replaces an earlier one with the same name
+    pub fn register_emit_target(&mut self, target: Box<dyn EmitTarget>) {
+        self.emit_targets.retain(|existing| existing.name() != target.name());
+        self.emit_targets.push(target);
+    }
+
+    /// An intrinsic registered later replaces an earlier one with the same name
+    pub fn register_intrinsic(&mut self, intrinsic: Box<dyn Intrinsic>) {
+        self.intrinsics.retain(|existing| existing.name() != intrinsic.name());
+        self.intrinsics.push(intrinsic);
+    }
+
+    pub fn run_passes(&self, unit: &mut TranslationUnit) {
+        fold::fold_constants(unit);
+        intrinsics::expand(unit, &self.intrinsics);
+        for pass in &self.passes {
+            pass.run(unit);
+        }
+    }
+
+    pub fn run_lints(&self, unit: &TranslationUnit) -> Vec<LintDiagnostic> {
+        self.lints.iter().flat_map(|lint| lint.check(unit)).collect()
+    }
+
+    /// Output of the named emit target, or None if nothing by that name is registered
+    pub fn emit(&self, name: &str, unit: &TranslationUnit, source: &str, diagnostics: &[Diagnostic]) -> Option<String> {
+        self.emit_targets
+            .iter()
+            .find(|target| target.name() == name)
+            .map(|target| target.emit(unit, source, diagnostics))
+    }
+
+    pub fn emit_target_names(&self) -> Vec<&str> {
+        self.emit_targets.iter().map(|target| target.name()).collect()
+    }
+}
@@ -0,0 +1,318 @@
+// policy.rs: Language features a course bans, and where a program uses
+// them (`--policy=<file>`).
+//
+// A policy is a TOML file listing what is banned:
+//
+//     banned = ["goto", "globals", "recursion", "pointer-arithmetic"]
+//
+// A use of a banned feature is an error, which can't be silenced the way
+// a warning can. The parser has no goto statement, so goto is found among
+// the tokens and is reported even when the file doesn't parse; the others
+// need the tree. A global is any variable declared at file scope that
+// isn't const. Recursion is a cycle in the call graph, a function calling
+// itself or functions calling each other. Pointer arithmetic is `+`, `-`,
+// `++`, `--`, `+=` or `-=` on a variable declared as a pointer or array,
+// as a parameter, a local or a global; shadowing isn't followed.
+//
+// Each use is put on the line the tree says: the global's declaration,
+// the recursive function's name, or the first statement where the pointer
+// meets one of those operators.
+
+use crate::callgraph::CallGraph;
+use crate::lexer_regex::{self, Token};
+use crate::parser::ast::*;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Banned {
+    Goto,
+    Globals,
+    Recursion,
+    PointerArithmetic,
+}
+
+impl Banned {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Banned::Goto => "goto",
+            Banned::Globals => "globals",
+            Banned::Recursion => "recursion",
+            Banned::PointerArithmetic => "pointer-arithmetic",
+        }
+    }
+}
+
+impl fmt::Display for Banned {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    pub banned: Vec<Banned>,
+}
+
+/// A use of a banned feature
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub banned: Banned,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Policy {
+    pub fn parse(text: &str) -> Result<Policy, String> {
+        toml::from_str(text).map_err(|error| error.to_string())
+    }
+
+    pub fn bans(&self, banned: Banned) -> bool {
+        self.banned.contains(&banned)
+    }
+
+    /// The uses in `source` of what the policy bans; only those found in
+    /// the tokens without `unit`, for a file that didn't parse
+    pub fn check(&self, unit: Option<&TranslationUnit>, source: &str) -> Vec<Violation> {
+        if self.banned.is_empty() {
+            return Vec::new();
+        }
+        let tokens = lexer_regex::lex_with_regex_positions(source, Default::default());
+        let mut violations = Vec::new();
+        if self.bans(Banned::Goto) {
+            for token in tokens.iter().filter(|token| token.token == Token::Goto) {
+                violations.push(Violation {
+                    banned: Banned::Goto,
+                    line: Some(token.span.line),
+                    message: "'goto' is not allowed".to_string(),
+                });
+            }
+        }
+        let Some(unit) = unit else {
+            return violations;
+        };
+        if self.bans(Banned::Globals) {
+            for external in &unit.external_declarations {
+                if let ExternalDeclaration::Variable(declaration) = external
+                    && !declaration.type_qualifiers.contains(&TypeQualifier::Const)
+                {
+                    let name = &declaration.declarator.name;
+                    violations.push(Violation {
+                        banned: Banned::Globals,
+                        line: declaration.span.map(|span| span.line),
+                        message: format!("global variable '{}' is not allowed", name),
+                    });
+                }
+            }
+        }
+        if self.bans(Banned::Recursion) {
+            for group in CallGraph::build(unit).recursive_groups() {
+                let message = match group.as_slice() {
+                    [name] => format!("'{}' calls itself; recursion is not allowed", name),
+                    names => format!("{} call each other; recursion is not allowed", quoted(names)),
+                };
+                let line = unit.external_declarations.iter().find_map(|external| match external {
+                    ExternalDeclaration::Function(function) if function.name == group[0] => {
+                        function.name_span.map(|span| span.line)
+                    }
+                    _ => None,
+                });
+                violations.push(Violation { banned: Banned::Recursion, line, message });
+            }
+        }
+        if self.bans(Banned::PointerArithmetic) {
+            violations.extend(pointer_arithmetic(unit));
+        }
+        violations.sort_by_key(|violation| violation.line);
+        violations
+    }
+}
+
+fn quoted(names: &[String]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+    quoted.join(", ")
+}
+
+fn pointer_arithmetic(unit: &TranslationUnit) -> Vec<Violation> {
+    let mut globals = BTreeSet::new();
+    for external in &unit.external_declarations {
+        if let ExternalDeclaration::Variable(declaration) = external
+            && is_pointer(&declaration.declarator)
+        {
+            globals.insert(declaration.declarator.name.clone());
+        }
+    }
+    let mut violations = Vec::new();
+    for external in &unit.external_declarations {
+        let ExternalDeclaration::Function(function) = external else {
+            continue;
+        };
+        let line = function.name_span.map(|span| span.line);
+        let mut finder = Finder { pointers: globals.clone(), used: BTreeMap::new(), line };
+        for parameter in &function.parameters {
+            if parameter.param_type.contains(['*', '[']) {
+                finder.pointers.insert(parameter.name.clone());
+            }
+        }
+        for statement in &function.body {
+            finder.statement(statement);
+        }
+        for (name, line) in finder.used {
+            violations.push(Violation {
+                banned: Banned::PointerArithmetic,
+                line,
+                message: format!("pointer arithmetic on '{}' in '{}' is not allowed", name, function.name),
+            });
+        }
+    }
+    violations
+}
+
+fn is_pointer(declarator: &Declarator) -> bool {
+    declarator.pointer_depth > 0 || !declarator.array_sizes.is_empty()
+}
+
+/// The pointers a function does arithmetic on, each with the line of the
+/// first statement that does
+struct Finder {
+    pointers: BTreeSet<String>,
+    used: BTreeMap<String, Option<usize>>,
+    line: Option<usize>, // of the statement being looked through
+}
+
+impl Finder {
+    fn pointer(&self, expression: &Expression) -> Option<String> {
+        match expression {
+            Expression::Identifier(name) if self.pointers.contains(name) => Some(name.clone()),
+            Expression::Paren(inner) => self.pointer(inner),
+            _ => None,
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let outer = self.line;
+        self.line = statement.line().or(outer);
+        self.statement_node(statement);
+        self.line = outer;
+    }
+
+    fn statement_node(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Declaration(declaration) => {
+                if is_pointer(&declaration.declarator) {
+                    self.pointers.insert(declaration.declarator.name.clone());
+                }
+                if let Some(initializer) = &declaration.initializer {
+                    for expression in initializer.expressions() {
+                        self.expression(expression);
+                    }
+                }
+            }
+            StatementKind::Assignment(_, value) | StatementKind::Expression(value) => self.expression(value),
+            StatementKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            StatementKind::Block(statements) => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StatementKind::While(condition, body)
+            | StatementKind::Switch(condition, body)
+            | StatementKind::Case(condition, body) => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            StatementKind::Default(body) | StatementKind::Label(_, body) => self.statement(body),
+            StatementKind::For(init, condition, update, body) => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                for expression in [condition, update].into_iter().flatten() {
+                    self.expression(expression);
+                }
+                self.statement(body);
+            }
+            StatementKind::Break | StatementKind::Fallthrough | StatementKind::Goto(_) | StatementKind::Empty => {}
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        let operand = match expression {
+            Expression::BinaryOp(left, BinaryOperator::Plus | BinaryOperator::Minus, right) => {
+                self.pointer(left).or_else(|| self.pointer(right))
+            }
+            Expression::UnaryOp(UnaryOperator::PreIncrement | UnaryOperator::PreDecrement, operand)
+            | Expression::PostfixOp(operand, _)
+            | Expression::Assignment(operand, AssignmentOperator::PlusAssign | AssignmentOperator::MinusAssign, _) => {
+                self.pointer(operand)
+            }
+            _ => None,
+        };
+        if let Some(operand) = operand {
+            self.used.entry(operand).or_insert(self.line);
+        }
+        match expression {
+            Expression::BinaryOp(left, _, right)
+            | Expression::Assignment(left, _, right)
+            | Expression::ArrayAccess(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::UnaryOp(_, operand)
+            | Expression::PostfixOp(operand, _)
+            | Expression::Cast(_, operand)
+            | Expression::Paren(operand)
+            | Expression::MemberAccess(operand, _)
+            | Expression::PointerAccess(operand, _) => self.expression(operand),
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.expression(condition);
+                self.expression(then_value);
+                self.expression(else_value);
+            }
+            Expression::FunctionCall(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::StatementExpr(statements) => {
+                for statement in statements {
+                    self.statement(statement);
+                }
+            }
+            Expression::Generic(controlling, associations) => {
+                self.expression(controlling);
+                for association in associations {
+                    self.expression(&association.value);
+                }
+            }
+            Expression::CompoundLiteral(literal) => {
+                for item in &literal.initializers {
+                    for expression in item.expressions() {
+                        self.expression(expression);
+                    }
+                }
+            }
+            Expression::Identifier(_)
+            | Expression::Constant(_)
+            | Expression::Folded(..)
+            | Expression::StringLiteral(_)
+            | Expression::EncodedStringLiteral(..)
+            | Expression::SizeofType(_) => {}
+        }
+    }
+}
@@ -0,0 +1,173 @@
+// precedence.rs: The expression grammar's precedence and associativity, as
+// the parser applies it.
+//
+// `--emit=precedence` prints the parser's table, a level per line from the
+// loosest-binding to the tightest. `check-precedence` parses a matrix of
+// small expressions, every binary operator against every other plus the
+// assignment, conditional, prefix and postfix forms around them, and checks
+// the shape of each tree against the table. A lexer that splits `<<=` into
+// `<<` `=`, or a level that groups the wrong way, shows up as an expression
+// that parses differently from what the table says, or not at all.
+
+use crate::ast_text;
+use crate::diagnostics::Diagnostic;
+use crate::parser::ast::TranslationUnit;
+use crate::parser::{self, Associativity, PrecedenceLevel};
+use crate::plugins::EmitTarget;
+
+pub struct PrecedenceEmitter;
+
+impl EmitTarget for PrecedenceEmitter {
+    fn name(&self) -> &str {
+        "precedence"
+    }
+
+    fn emit(&self, _unit: &TranslationUnit, _source: &str, _diagnostics: &[Diagnostic]) -> String {
+        render(&parser::precedence_table())
+    }
+}
+
+/// A line per level: `3  logical or      left   ||`
+pub fn render(levels: &[PrecedenceLevel]) -> String {
+    let mut out = String::new();
+    for (rank, level) in levels.iter().enumerate() {
+        let associativity = match level.associativity {
+            Associativity::Left => "left",
+            Associativity::Right => "right",
+        };
+        out.push_str(&format!("{:<3}{:<16}{:<7}{}\n", rank + 1, level.name, associativity, level.operators.join(" ")));
+    }
+    out
+}
+
+/// An expression from the matrix and the tree it should parse to, in
+/// `--emit=ast` form; None if it should be rejected
+pub struct Case {
+    pub text: String,
+    pub expected: Option<String>,
+}
+
+/// A case that parsed to something other than what was expected
+pub struct Failure {
+    pub text: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl Failure {
+    pub fn render(&self) -> String {
+        let shape = |tree: &Option<String>| tree.clone().unwrap_or("a parse error".to_string());
+        format!("{}: expected {}, got {}", self.text, shape(&self.expected), shape(&self.actual))
+    }
+}
+
+fn id(name: &str) -> String {
+    format!("(id {})", name)
+}
+
+fn binary(op: &str, left: &str, right: &str) -> String {
+    format!("({} {} {})", op, left, right)
+}
+
+fn assign(op: &str, target: &str, value: &str) -> String {
+    format!("(assign {} {} {})", op, target, value)
+}
+
+fn conditional(condition: &str, then_value: &str, else_value: &str) -> String {
+    format!("(?: {} {} {})", condition, then_value, else_value)
+}
+
+fn case(text: String, expected: Option<String>) -> Case {
+    Case { text, expected }
+}
+
+/// The operators of every level the table names `name`
+fn operators(levels: &[PrecedenceLevel], name: &str) -> Vec<&'static str> {
+    levels.iter().filter(|level| level.name == name).flat_map(|level| level.operators.clone()).collect()
+}
+
+/// The matrix of expressions the table predicts the shape of
+pub fn cases(levels: &[PrecedenceLevel]) -> Vec<Case> {
+    let (a, b, c, d) = (id("a"), id("b"), id("c"), id("d"));
+    let mut binaries = Vec::new();
+    for (rank, level) in levels.iter().enumerate() {
+        if level.associativity == Associativity::Left && level.name != "postfix" {
+            binaries.extend(level.operators.iter().map(|op| (rank, *op)));
+        }
+    }
+    let assignments = operators(levels, "assignment");
+    let mut cases = Vec::new();
+
+    // Every binary operator against every other: the tighter one binds
+    // first, and on a tie the left one does
+    for &(outer_rank, outer) in &binaries {
+        for &(inner_rank, inner) in &binaries {
+            let expected = if inner_rank > outer_rank {
+                binary(outer, &a, &binary(inner, &b, &c))
+            } else {
+                binary(inner, &binary(outer, &a, &b), &c)
+            };
+            cases.push(case(format!("a {} b {} c", outer, inner), Some(expected)));
+        }
+    }
+
+    // Assignment is the loosest level, groups to the right, and needs an
+    // lvalue on its left
+    for &op in &assignments {
+        for &(_, inner) in &binaries {
+            cases.push(case(format!("a {} b {} c", op, inner), Some(assign(op, &a, &binary(inner, &b, &c)))));
+            cases.push(case(format!("a {} b {} c", inner, op), None));
+        }
+        for &inner in &assignments {
+            let expected = assign(op, &a, &assign(inner, &b, &c));
+            cases.push(case(format!("a {} b {} c", op, inner), Some(expected)));
+        }
+        let expected = assign(op, &a, &conditional(&b, &c, &d));
+        cases.push(case(format!("a {} b ? c : d", op), Some(expected)));
+        cases.push(case(format!("a ? b : c {} d", op), None));
+    }
+
+    // The conditional sits between assignment and the binary operators
+    for &(_, op) in &binaries {
+        let expected = conditional(&a, &b, &binary(op, &c, &d));
+        cases.push(case(format!("a ? b : c {} d", op), Some(expected)));
+        let expected = conditional(&binary(op, &a, &b), &c, &d);
+        cases.push(case(format!("a {} b ? c : d", op), Some(expected)));
+    }
+    let expected = conditional(&a, &b, &conditional(&c, &d, &id("e")));
+    cases.push(case("a ? b : c ? d : e".to_string(), Some(expected)));
+
+    // Prefix operators bind tighter than any binary one, and postfix ones
+    // tighter still
+    for &prefix in &operators(levels, "prefix") {
+        if prefix == "sizeof" || prefix.starts_with('(') {
+            continue;
+        }
+        for &(_, op) in &binaries {
+            let expected = binary(op, &format!("(prefix {} {})", prefix, a), &b);
+            cases.push(case(format!("{}a {} b", prefix, op), Some(expected)));
+        }
+    }
+    for postfix in ["++", "--"] {
+        cases.push(case(format!("-a{}", postfix), Some(format!("(prefix - (postfix {} {}))", postfix, a))));
+        for &(_, op) in &binaries {
+            let expected = binary(op, &format!("(postfix {} {})", postfix, a), &b);
+            cases.push(case(format!("a{} {} b", postfix, op), Some(expected)));
+        }
+    }
+    cases
+}
+
+/// Parse every case, returning how many there were and the ones that came
+/// out differently from the table
+pub fn check() -> (usize, Vec<Failure>) {
+    let cases = cases(&parser::precedence_table());
+    let mut failures = Vec::new();
+    for case in &cases {
+        let actual = parser::parse_expression(&case.text).map(|tree| ast_text::expression(&tree));
+        if actual != case.expected {
+            failures.push(Failure { text: case.text.clone(), expected: case.expected.clone(), actual });
+        }
+    }
+    (cases.len(), failures)
+}
@@ -0,0 +1,412 @@
+// pretty.rs: The AST printed back out as C source.
+//
+// The parser keeps every pair of parentheses written as an Expression::Paren,
+// so an expression is printed with none of its own: the tree the parser
+// built already follows the precedence the text will be read back with.
+// Directives come first, as the AST keeps them apart from the declarations.
+// What it prints (`--emit=c`) parses back to the same AST, which
+// `gen-fuzz --check` holds it to.
+
+use crate::ast_text::{storage_class_name, type_name};
+use crate::diagnostics::Diagnostic;
+use crate::parser::ast::*;
+use crate::plugins::EmitTarget;
+
+pub struct CEmitter;
+
+/// `'c'`, escaped as C needs it
+fn char_literal(c: char) -> String {
+    match c {
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        '\r' => "'\\r'".to_string(),
+        '\0' => "'\\0'".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\'' => "'\\''".to_string(),
+        _ => format!("'{}'", c),
+    }
+}
+
+fn constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Integer(value) => value.to_string(),
+        // A literal too large for its type is infinite; C has no spelling
+        // for that but one too large again
+        Constant::Float(value, suffix) if value.is_infinite() => {
+            format!("{}1e999{}", if *value < 0.0 { "-" } else { "" }, suffix.suffix())
+        }
+        Constant::Float(value, FloatSuffix::Float) => format!("{:?}f", *value as f32),
+        Constant::Float(value, suffix) => format!("{:?}{}", value, suffix.suffix()),
+        Constant::Char(value) => char_literal(*value),
+        Constant::Bool(value) => value.to_string(),
+        Constant::Null => "nullptr".to_string(),
+        Constant::EncodedChar(encoding, value) => format!("{}{}", encoding.prefix(), char_literal(*value)),
+    }
+}
+
+/// `name` declared with a type as the parser spells it: "char* p",
+/// "int (*fp)(void)", "int a[2]"
+fn declared(spelling: &str, name: &str) -> String {
+    if name.is_empty() {
+        spelling.to_string()
+    } else if spelling.contains("(*)") {
+        spelling.replacen("(*)", &format!("(*{})", name), 1)
+    } else if let Some(bracket) = spelling.find('[') {
+        format!("{} {}{}", &spelling[..bracket], name, &spelling[bracket..])
+    } else {
+        format!("{} {}", spelling, name)
+    }
+}
+
+/// `const ` for each const, which goes before the type
+fn consts(qualifiers: &[TypeQualifier]) -> String {
+    qualifiers.iter().filter(|qualifier| **qualifier == TypeQualifier::Const).map(|_| "const ").collect()
+}
+
+/// `restrict ` if it is there, which goes after the type, by the name:
+/// `int *restrict p`
+fn restrict(qualifiers: &[TypeQualifier]) -> &'static str {
+    if qualifiers.contains(&TypeQualifier::Restrict) { "restrict " } else { "" }
+}
+
+fn parameters(parameters: &[Parameter], variadic: bool) -> String {
+    if parameters.is_empty() && !variadic {
+        return "(void)".to_string();
+    }
+    let mut parameters: Vec<String> = parameters
+        .iter()
+        .map(|parameter| {
+            let name = format!("{}{}", restrict(&parameter.type_qualifiers), parameter.name);
+            format!("{}{}", consts(&parameter.type_qualifiers), declared(&parameter.param_type, name.trim_end()))
+        })
+        .collect();
+    if variadic {
+        parameters.push("...".to_string());
+    }
+    format!("({})", parameters.join(", "))
+}
+
+/// Attributes in the syntax their name was written for: `[[gnu::x]]` for a
+/// namespaced one, `__attribute__((x))` otherwise; each followed by a space
+fn attributes(attributes: &[Attribute]) -> String {
+    let mut text = String::new();
+    for attribute in attributes {
+        let mut inner = attribute.name.clone();
+        if !attribute.arguments.is_empty() {
+            let arguments: Vec<String> = attribute.arguments.iter().map(expression).collect();
+            inner.push_str(&format!("({})", arguments.join(", ")));
+        }
+        if attribute.name.contains("::") {
+            text.push_str(&format!("[[{}]] ", inner));
+        } else {
+            text.push_str(&format!("__attribute__(({})) ", inner));
+        }
+    }
+    text
+}
+
+fn specifiers(specifiers: &[FunctionSpecifier]) -> String {
+    specifiers
+        .iter()
+        .map(|specifier| match specifier {
+            FunctionSpecifier::Inline => "inline ",
+            FunctionSpecifier::Noreturn => "_Noreturn ",
+        })
+        .collect()
+}
+
+/// `int add(int a, int b)`: a function's prototype without its `;`
+pub fn signature(return_type: &str, name: &str, function_parameters: &[Parameter], variadic: bool) -> String {
+    format!("{}{}", declared(return_type, name), parameters(function_parameters, variadic))
+}
+
+fn arguments(arguments: &[Expression]) -> String {
+    let arguments: Vec<String> = arguments.iter().map(expression).collect();
+    arguments.join(", ")
+}
+
+pub fn expression(expr: &Expression) -> String {
+    match expr {
+        Expression::Identifier(name) => name.clone(),
+        Expression::Constant(value) => constant(value),
+        // By name, so the #define or enum it came from still gives it
+        Expression::Folded(_, name) => name.clone(),
+        // Literals keep their escapes as written
+        Expression::StringLiteral(text) => format!("\"{}\"", text),
+        Expression::EncodedStringLiteral(encoding, text) => format!("{}\"{}\"", encoding.prefix(), text),
+        Expression::BinaryOp(left, op, right) => {
+            format!("{} {} {}", expression(left), op.symbol(), expression(right))
+        }
+        Expression::UnaryOp(op, operand) => {
+            let operand = expression(operand);
+            let symbol = op.symbol();
+            // `- -x`, not `--x`; `sizeof x`
+            let apart = symbol.chars().all(char::is_alphabetic)
+                || operand.starts_with(|c: char| symbol.ends_with(c) && "+-&".contains(c));
+            format!("{}{}{}", symbol, if apart { " " } else { "" }, operand)
+        }
+        Expression::Assignment(target, op, value) => {
+            format!("{} {} {}", expression(target), op.symbol(), expression(value))
+        }
+        Expression::Conditional(condition, then_value, else_value) => {
+            format!("{} ? {} : {}", expression(condition), expression(then_value), expression(else_value))
+        }
+        Expression::FunctionCall(callee, args) => format!("{}({})", expression(callee), arguments(args)),
+        Expression::ArrayAccess(array, index) => format!("{}[{}]", expression(array), expression(index)),
+        Expression::MemberAccess(object, member) => format!("{}.{}", expression(object), member),
+        Expression::PointerAccess(object, member) => format!("{}->{}", expression(object), member),
+        Expression::PostfixOp(operand, op) => format!("{}{}", expression(operand), op.symbol()),
+        Expression::Cast(target, operand) => format!("({}){}", target.spelling(), expression(operand)),
+        Expression::SizeofType(operand) => format!("sizeof({})", operand.spelling()),
+        Expression::Paren(inner) => format!("({})", expression(inner)),
+        Expression::StatementExpr(statements) => {
+            let mut printer = Printer { out: String::new() };
+            printer.statements(0, statements);
+            format!("({{{} }})", printer.out.replace('\n', " "))
+        }
+        Expression::Generic(controlling, associations) => {
+            let mut text = format!("_Generic({}", expression(controlling));
+            for association in associations {
+                let type_name = association.type_name.as_deref().unwrap_or("default");
+                text.push_str(&format!(", {}: {}", type_name, expression(&association.value)));
+            }
+            text.push(')');
+            text
+        }
+        Expression::CompoundLiteral(literal) => {
+            let items: Vec<String> = literal.initializers.iter().map(initializer).collect();
+            format!("({}{}){{{}}}", literal.type_name, array_sizes(&literal.array_sizes), items.join(", "))
+        }
+    }
+}
+
+/// `[n]...`, with `[]` for a size left out
+fn array_sizes(sizes: &[Option<Expression>]) -> String {
+    sizes.iter().map(|size| format!("[{}]", size.as_ref().map_or(String::new(), expression))).collect()
+}
+
+fn initializer(init: &Initializer) -> String {
+    match &init.kind {
+        InitializerKind::Assignment(value) => expression(value),
+        InitializerKind::List(items) => {
+            let items: Vec<String> = items.iter().map(initializer).collect();
+            format!("{{{}}}", items.join(", "))
+        }
+        InitializerKind::Designated(Designator::Member(member), value) => {
+            format!(".{} = {}", member, initializer(value))
+        }
+        InitializerKind::Designated(Designator::Array(index), value) => {
+            format!("[{}] = {}", expression(index), initializer(value))
+        }
+    }
+}
+
+/// A declaration without its `;`
+pub(crate) fn declaration(declaration: &VariableDeclaration) -> String {
+    let mut text = attributes(&declaration.attributes);
+    if let Some(storage_class) = &declaration.storage_class {
+        text.push_str(storage_class_name(storage_class));
+        text.push(' ');
+    }
+    text.push_str(&consts(&declaration.type_qualifiers));
+    let declarator = &declaration.declarator;
+    if declarator.pointee.is_empty() {
+        text.push_str(&format!(
+            "{} {}{}{}{}",
+            type_name(&declaration.type_specifier),
+            "*".repeat(declarator.pointer_depth as usize),
+            restrict(&declaration.type_qualifiers),
+            declarator.name,
+            array_sizes(&declarator.array_sizes)
+        ));
+    } else {
+        // `int (*fp)(int)`, `char (*pb)[10]`
+        text.push_str(&declarator.type_name(&declaration.type_specifier).declaring(&declarator.name));
+    }
+    if let Some(params) = &declarator.function_params {
+        text.push_str(&parameters(params, false));
+    }
+    if let Some(init) = &declaration.initializer {
+        text.push_str(&format!(" = {}", initializer(init)));
+    }
+    text
+}
+
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    fn line(&mut self, depth: usize, text: &str) {
+        self.out.push('\n');
+        self.out.push_str(&"    ".repeat(depth));
+        self.out.push_str(text);
+    }
+
+    /// A statement, or a block's braces around its statements, as the body
+    /// of `head` (`if (x)`, `else`, ...)
+    fn body(&mut self, depth: usize, head: &str, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Block(statements) => {
+                self.line(depth, &format!("{} {{", head));
+                self.statements(depth + 1, statements);
+                self.line(depth, "}");
+            }
+            _ => {
+                self.line(depth, head);
+                self.statement(depth + 1, statement);
+            }
+        }
+    }
+
+    fn statement(&mut self, depth: usize, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Declaration(decl) => self.line(depth, &format!("{};", declaration(decl))),
+            StatementKind::Assignment(name, value) => self.line(depth, &format!("{} = {};", name, expression(value))),
+            StatementKind::Return(Some(value)) => self.line(depth, &format!("return {};", expression(value))),
+            StatementKind::Return(None) => self.line(depth, "return;"),
+            StatementKind::Expression(expr) => self.line(depth, &format!("{};", expression(expr))),
+            StatementKind::Block(statements) => {
+                self.line(depth, "{");
+                self.statements(depth + 1, statements);
+                self.line(depth, "}");
+            }
+            StatementKind::If(condition, then_branch, else_branch) => {
+                self.body(depth, &format!("if ({})", expression(condition)), then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.body(depth, "else", else_branch);
+                }
+            }
+            StatementKind::While(condition, body) => {
+                self.body(depth, &format!("while ({})", expression(condition)), body)
+            }
+            StatementKind::For(init, condition, update, body) => {
+                let init = match init.as_deref().map(|init| &init.kind) {
+                    Some(StatementKind::Declaration(decl)) => declaration(decl),
+                    Some(StatementKind::Expression(expr)) => expression(expr),
+                    Some(StatementKind::Assignment(name, value)) => format!("{} = {}", name, expression(value)),
+                    _ => String::new(),
+                };
+                let condition = condition.as_ref().map_or(String::new(), expression);
+                let update = update.as_ref().map_or(String::new(), expression);
+                self.body(depth, &format!("for ({}; {}; {})", init, condition, update), body);
+            }
+            StatementKind::Break => self.line(depth, "break;"),
+            StatementKind::Switch(value, body) => self.body(depth, &format!("switch ({})", expression(value)), body),
+            StatementKind::Case(value, statement) => {
+                self.line(depth, &format!("case {}:", expression(value)));
+                self.statement(depth + 1, statement);
+            }
+            StatementKind::Default(statement) => {
+                self.line(depth, "default:");
+                self.statement(depth + 1, statement);
+            }
+            StatementKind::Fallthrough => self.line(depth, "[[fallthrough]];"),
+            StatementKind::Empty => self.line(depth, ";"),
+            // Labels stand out a level to the left of what they label
+            StatementKind::Label(name, statement) => {
+                self.line(depth.saturating_sub(1), &format!("{}:", name));
+                self.statement(depth, statement);
+            }
+            StatementKind::Goto(name) => self.line(depth, &format!("goto {};", name)),
+        }
+    }
+
+    fn statements(&mut self, depth: usize, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(depth, statement);
+        }
+    }
+
+    fn directive(&mut self, directive: &PreprocessorDirective) {
+        let text = match directive {
+            PreprocessorDirective::Include(path) => format!("#include <{}>", path),
+            PreprocessorDirective::Define(name, replacement) => {
+                let mut text = format!("#define {}", name);
+                for item in replacement {
+                    text.push(' ');
+                    match item {
+                        ReplacementItem::Identifier(name) => text.push_str(name),
+                        ReplacementItem::Constant(value) => text.push_str(&constant(value)),
+                        ReplacementItem::StringLiteral(literal) => text.push_str(&format!("\"{}\"", literal)),
+                    }
+                }
+                text
+            }
+            PreprocessorDirective::Ifdef(name) => format!("#ifdef {}", name),
+            PreprocessorDirective::Ifndef(name) => format!("#ifndef {}", name),
+            PreprocessorDirective::Endif => "#endif".to_string(),
+        };
+        self.line(0, &text);
+    }
+
+    fn external_declaration(&mut self, external: &ExternalDeclaration) {
+        match external {
+            ExternalDeclaration::Variable(decl) => self.line(0, &format!("{};", declaration(decl))),
+            ExternalDeclaration::FunctionDeclaration(function) => {
+                let text = format!(
+                    "{}{}{}{};",
+                    attributes(&function.attributes),
+                    specifiers(&function.specifiers),
+                    declared(&function.return_type, &function.name),
+                    parameters(&function.parameters, function.variadic)
+                );
+                self.line(0, &text);
+            }
+            ExternalDeclaration::Function(function) => {
+                let text = format!(
+                    "{}{}{}{} {{",
+                    attributes(&function.attributes),
+                    specifiers(&function.specifiers),
+                    declared(&function.return_type, &function.name),
+                    parameters(&function.parameters, function.variadic)
+                );
+                self.line(0, &text);
+                self.statements(1, &function.body);
+                self.line(0, "}");
+            }
+            ExternalDeclaration::Struct(definition) => {
+                self.line(0, &format!("struct {} {{", definition.name));
+                for member in &definition.members {
+                    self.line(1, &format!("{};", declaration(member)));
+                }
+                self.line(0, "};");
+            }
+            ExternalDeclaration::StructDeclaration(tag, _) => self.line(0, &format!("struct {};", tag)),
+            ExternalDeclaration::Enum(definition) => {
+                self.line(0, &format!("{} {{", definition.head()));
+                for enumerator in &definition.enumerators {
+                    match &enumerator.value {
+                        Some(value) => self.line(1, &format!("{} = {},", enumerator.name, expression(value))),
+                        None => self.line(1, &format!("{},", enumerator.name)),
+                    }
+                }
+                self.line(0, "};");
+            }
+        }
+    }
+}
+
+/// The whole translation unit as C, ending in a newline
+pub fn to_c(unit: &TranslationUnit) -> String {
+    let mut printer = Printer { out: String::new() };
+    for directive in &unit.preprocessor_list {
+        printer.directive(directive);
+    }
+    for external in &unit.external_declarations {
+        printer.external_declaration(external);
+    }
+    let mut text = printer.out.trim_start_matches('\n').to_string();
+    text.push('\n');
+    text
+}
+
+impl EmitTarget for CEmitter {
+    fn name(&self) -> &str {
+        "c"
+    }
+
+    fn emit(&self, unit: &TranslationUnit, _source: &str, _diagnostics: &[Diagnostic]) -> String {
+        to_c(unit)
+    }
+}
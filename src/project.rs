@@ -0,0 +1,737 @@
+// project.rs: A program made of several source files, as an editor or a
+// build sees it.
+//
+// A Project holds the text of each file in memory, so an editor can hand
+// it what is in its buffers, unsaved edits and all, without anything being
+// written to disk. Each file is a translation unit of its own, and its
+// phases are memoized queries (query.rs): its tokens, its AST, what scope
+// analysis and type checking find, its lints, its #includes, and the
+// types of expressions asked about. A query is only computed again when
+// something it depends on has changed, so an edit to one file costs only
+// that file's queries, and an edit that leaves its tokens as they were
+// isn't parsed again. Lines are a tree's spans, though, so what reports
+// them takes the file parsed again with its positions, which an edit
+// moving tokens changes.
+//
+// There is no preprocessor, but a quoted #include is looked for next to
+// the file, then along the include paths, among the project's files before
+// the disk; one that can't be found is an error. Standard headers are
+// built in. A file's tokens can be had with its includes spliced in, each
+// token's span naming the file it is from, and scope analysis and type
+// checking see the file that way, so what a header declares is declared
+// where it is included. What they find in a header is put back on the
+// header's own lines.
+//
+// Every file, and every header read from disk for those tokens, has a
+// FileId in the project's SourceMap that it keeps across edits, and each
+// diagnostic is marked with its file's.
+//
+// Each file's functions and globals go into one index for the project, so
+// a name can be looked up across the program, and the files can be linked
+// into one module for the backends (link.rs).
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::dialect::Standard;
+use crate::fold;
+use crate::lexer_regex::{self, LexOptions, Token, Tokens};
+use crate::link::{self, Module};
+use crate::parser::{ErrorPlace, Parser, error_span};
+use crate::parser::ast::{ParseError, TranslationUnit};
+use crate::plugins::PluginRegistry;
+use crate::policy::Policy;
+use crate::query::{self, Executions, Memo, Revision};
+use crate::scope::{ScopeAnalyzer, SymbolKind};
+use crate::semantic::{self, ExpressionType, TypeAtError};
+use crate::source::{FileId, SourceFile, SourceMap, Span, TokenSpan, spanned};
+use crate::suppress;
+use crate::type_checker::TypeChecker;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Something a file declares at file scope
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlobalKind {
+    Function { defined: bool },
+    Variable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalSymbol {
+    pub name: String,
+    pub kind: GlobalKind,
+    pub file: PathBuf,
+    pub line: Option<usize>,
+}
+
+/// What scope analysis, type checking and the policy find in a file
+#[derive(PartialEq)]
+struct Semantics {
+    diagnostics: Vec<Diagnostic>,
+    symbols: Vec<GlobalSymbol>,
+}
+
+impl Semantics {
+    /// Lines of the include-expanded text (expanded_text) put back as the
+    /// lines of the files they came from. What is found in a header stays
+    /// marked with it, unless the header is a project file, which reports
+    /// its own; a note in another file than its diagnostic names it.
+    /// Symbols declared in a header aren't this file's.
+    fn map_lines(&mut self, id: FileId, lines: &[(FileId, usize)], project_ids: &[FileId], sources: &SourceMap) {
+        let origin = |line: Option<usize>| line.and_then(|line| lines.get(line.checked_sub(1)?)).copied();
+        self.diagnostics.retain_mut(|diagnostic| {
+            if let Some((file, line)) = origin(diagnostic.line) {
+                if file != id && project_ids.contains(&file) {
+                    return false;
+                }
+                diagnostic.line = Some(line);
+                diagnostic.file = (file != id).then_some(file);
+            }
+            let diagnostic_file = diagnostic.file.unwrap_or(id);
+            for note in &mut diagnostic.notes {
+                let found = origin(note.line);
+                note.line = found.map(|(_, line)| line);
+                note.file = found
+                    .filter(|(file, _)| *file != diagnostic_file)
+                    .map(|(file, _)| sources.file(file).name.clone());
+            }
+            true
+        });
+        self.symbols.retain_mut(|symbol| match origin(symbol.line) {
+            Some((file, line)) if file == id => {
+                symbol.line = Some(line);
+                true
+            }
+            _ => false,
+        });
+    }
+}
+
+/// A tree, or the errors and the index of the token each was found at
+type Parsed = Result<TranslationUnit, Vec<(ParseError, ErrorPlace)>>;
+
+struct File {
+    source: Rc<SourceFile>, // as the SourceMap has it
+    text_changed: Revision,
+    tokens: Option<Memo<Vec<Token>>>,
+    unit: Option<Memo<Parsed>>,
+    spanned: Option<Memo<Option<TranslationUnit>>>, // the tree with spans, if it parses
+    semantics: Option<Memo<Semantics>>,
+    lints: Option<Memo<Vec<Diagnostic>>>,
+    includes: Option<Memo<Vec<Diagnostic>>>,
+    diagnostics: Option<Memo<Vec<Diagnostic>>>,
+    types: HashMap<(usize, usize), Memo<Result<ExpressionType, TypeAtError>>>, // by line and column
+}
+
+#[derive(Default)]
+pub struct Project {
+    files: BTreeMap<PathBuf, File>,
+    sources: SourceMap,
+    include_paths: Vec<PathBuf>,
+    standard: Standard,
+    gnu_extensions: bool,
+    digit_separators: bool,
+    unicode_identifiers: bool,
+    policy: Policy,
+    lints: PluginRegistry, // only its lints are run
+    revision: Revision,
+    options_changed: Revision, // the standard, an extension or the policy
+    files_changed: Revision,   // which files there are, or the include paths
+    lints_changed: Revision,
+    executions: Executions,
+}
+
+impl Project {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where quoted #includes are looked for after the including file's
+    /// directory, in the order added (`-I`)
+    pub fn add_include_path(&mut self, path: impl Into<PathBuf>) {
+        self.include_paths.push(path.into());
+        self.files_changed = self.change();
+    }
+
+    pub fn include_paths(&self) -> &[PathBuf] {
+        &self.include_paths
+    }
+
+    /// Check files against a language standard (`--std`)
+    pub fn set_standard(&mut self, standard: Standard) {
+        if self.standard != standard {
+            self.standard = standard;
+            self.options_changed = self.change();
+        }
+    }
+
+    /// Accept GNU C extensions (`-fgnu-extensions`)
+    pub fn set_gnu_extensions(&mut self, gnu_extensions: bool) {
+        if self.gnu_extensions != gnu_extensions {
+            self.gnu_extensions = gnu_extensions;
+            self.options_changed = self.change();
+        }
+    }
+
+    /// Accept digit separators in numbers (`-fdigit-separators`)
+    pub fn set_digit_separators(&mut self, digit_separators: bool) {
+        if self.digit_separators != digit_separators {
+            self.digit_separators = digit_separators;
+            self.options_changed = self.change();
+        }
+    }
+
+    /// Allow Unicode letters in identifiers (`-funicode-identifiers`)
+    pub fn set_unicode_identifiers(&mut self, unicode_identifiers: bool) {
+        if self.unicode_identifiers != unicode_identifiers {
+            self.unicode_identifiers = unicode_identifiers;
+            self.options_changed = self.change();
+        }
+    }
+
+    /// Report uses of what `policy` bans as errors (`--policy`)
+    pub fn set_policy(&mut self, policy: Policy) {
+        if self.policy != policy {
+            self.policy = policy;
+            self.options_changed = self.change();
+        }
+    }
+
+    pub fn gnu_extensions(&self) -> bool {
+        self.gnu_extensions
+    }
+
+    /// How the project's files are lexed, as the options set say
+    pub fn lex_options(&self) -> LexOptions {
+        LexOptions {
+            digit_separators: self.digit_separators,
+            standard: self.standard,
+            unicode_identifiers: self.unicode_identifiers,
+        }
+    }
+
+    /// The lints run on every file; register more here
+    pub fn lints_mut(&mut self) -> &mut PluginRegistry {
+        self.lints_changed = self.change();
+        &mut self.lints
+    }
+
+    /// Add a file from disk, or read it again if it is already in
+    pub fn open(&mut self, path: impl Into<PathBuf>) -> io::Result<()> {
+        let path = path.into();
+        let text = fs::read_to_string(&path)?;
+        self.set_file(path, text);
+        Ok(())
+    }
+
+    /// Add a file with this text, or replace the text it has; nothing is
+    /// read from or written to disk
+    pub fn set_file(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) {
+        let (path, text) = (path.into(), text.into());
+        if self.files.get(&path).is_some_and(|file| file.source.text() == text) {
+            return;
+        }
+        let revision = self.change();
+        match self.files.get_mut(&path) {
+            Some(file) => {
+                self.sources.replace(file.source.id, text);
+                file.source = Rc::clone(self.sources.file(file.source.id));
+                file.text_changed = revision;
+                // Positions mean something else now
+                file.types.clear();
+            }
+            None => {
+                let id = self.sources.add(path.display().to_string(), text);
+                let file = File {
+                    source: Rc::clone(self.sources.file(id)),
+                    text_changed: revision,
+                    tokens: None,
+                    unit: None,
+                    spanned: None,
+                    semantics: None,
+                    lints: None,
+                    includes: None,
+                    diagnostics: None,
+                    types: HashMap::new(),
+                };
+                self.files.insert(path, file);
+                // A file others include may have just appeared
+                self.files_changed = revision;
+            }
+        }
+    }
+
+    /// Take a file out; false if it wasn't in
+    pub fn remove_file(&mut self, path: &Path) -> bool {
+        let removed = self.files.remove(path).is_some();
+        if removed {
+            self.files_changed = self.change();
+        }
+        removed
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(|path| path.as_path())
+    }
+
+    pub fn text(&self, path: &Path) -> Option<&str> {
+        self.files.get(path).map(|file| file.source.text())
+    }
+
+    /// Every file's text under its FileId, with the headers read for
+    /// expanded_tokens
+    pub fn source_map(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    pub fn file_id(&self, path: &Path) -> Option<FileId> {
+        self.files.get(path).map(|file| file.source.id)
+    }
+
+    /// Changes so far; every edit or option set moves it on
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// How many times each query has been computed
+    pub fn executions(&self) -> &Executions {
+        &self.executions
+    }
+
+    pub fn tokens(&mut self, path: &Path) -> Option<Rc<Vec<Token>>> {
+        self.tokens_query(path)?;
+        Some(Rc::clone(self.files.get(path)?.tokens.as_ref()?.value()))
+    }
+
+    /// A file's AST; None if it isn't in or doesn't parse
+    pub fn unit(&mut self, path: &Path) -> Option<&TranslationUnit> {
+        self.unit_query(path)?;
+        self.files.get(path)?.unit.as_ref()?.value().as_ref().as_ref().ok()
+    }
+
+    /// A file's AST with the spans of its nodes, for their lines; None if
+    /// it isn't in or doesn't parse
+    pub fn spanned_unit(&mut self, path: &Path) -> Option<&TranslationUnit> {
+        self.spanned_query(path)?;
+        self.files.get(path)?.spanned.as_ref()?.value().as_ref().as_ref()
+    }
+
+    /// A file's errors and warnings, from every phase; None if it isn't in
+    pub fn diagnostics(&mut self, path: &Path) -> Option<&[Diagnostic]> {
+        self.diagnostics_query(path)?;
+        Some(self.files.get(path)?.diagnostics.as_ref()?.value().as_slice())
+    }
+
+    /// The revision a file's diagnostics last changed at, so an editor
+    /// need only show them again when it moves
+    pub fn diagnostics_revision(&mut self, path: &Path) -> Option<Revision> {
+        self.diagnostics_query(path)
+    }
+
+    /// Every file's functions and globals, by file and then line
+    pub fn symbols(&mut self) -> Vec<&GlobalSymbol> {
+        let paths: Vec<PathBuf> = self.files.keys().cloned().collect();
+        for path in &paths {
+            self.semantics_query(path);
+        }
+        self.files
+            .values()
+            .filter_map(|file| file.semantics.as_ref())
+            .flat_map(|semantics| &semantics.value().symbols)
+            .collect()
+    }
+
+    /// The declarations of `name` across the project: its definition and
+    /// prototypes for a function, each file's for a global
+    pub fn lookup(&mut self, name: &str) -> Vec<&GlobalSymbol> {
+        self.symbols().into_iter().filter(|symbol| symbol.name == name).collect()
+    }
+
+    /// The innermost expression at a position of a file and its type, as
+    /// semantic::type_at finds them; None if the file isn't in
+    pub fn type_at(&mut self, path: &Path, line: usize, column: usize) -> Option<Result<ExpressionType, TypeAtError>> {
+        let inputs = self.unit_query(path)?;
+        let revision = self.revision;
+        let file = self.files.get_mut(path)?;
+        let inputs = inputs.max(file.text_changed);
+        let mut memo = file.types.remove(&(line, column));
+        let unit = Rc::clone(file.unit.as_ref()?.value());
+        let mut ran = false;
+        let text = file.source.text();
+        let compute = || {
+            ran = true;
+            match unit.as_ref() {
+                Ok(unit) => semantic::type_at_in_unit(text, unit, line, column),
+                Err(errors) => Err(TypeAtError::Parse(format!("{} parse error(s)", errors.len()))),
+            }
+        };
+        query::fetch(&mut memo, revision, inputs, compute, PartialEq::eq);
+        let memo = memo?;
+        let found = memo.value().as_ref().clone();
+        file.types.insert((line, column), memo);
+        self.executions.type_at += usize::from(ran);
+        Some(found)
+    }
+
+    /// Every file linked into one module (link.rs), for a build of one
+    /// program; the errors of the files that don't compile if any don't,
+    /// else those the linking finds, each marked with its file
+    pub fn link(&mut self) -> Result<Module, Vec<Diagnostic>> {
+        let paths: Vec<PathBuf> = self.files.keys().cloned().collect();
+        let mut errors = Vec::new();
+        for path in &paths {
+            let diagnostics = self.diagnostics(path).unwrap_or_default();
+            errors.extend(diagnostics.iter().filter(|diagnostic| diagnostic.severity != Severity::Warning).cloned());
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        // In the order the files were added, as a linker takes its inputs;
+        // the diagnostics parsed each with spans, for the module's lines
+        let mut units: Vec<(FileId, &TranslationUnit)> = self
+            .files
+            .values()
+            .filter_map(|file| Some((file.source.id, file.spanned.as_ref()?.value().as_ref().as_ref()?)))
+            .collect();
+        units.sort_by_key(|&(id, _)| id);
+        link::link(&units).map_err(|errors| errors.iter().map(|error| error.diagnostic(&self.sources)).collect())
+    }
+
+    /// A file's tokens with each quoted #include that can be found replaced
+    /// by the tokens of the file it names, as a preprocessor splices them
+    /// in; an #include of a file already being included is left as it is
+    pub fn expanded_tokens(&mut self, path: &Path) -> Option<Vec<(Token, Span)>> {
+        let id = self.file_id(path)?;
+        let mut tokens = Vec::new();
+        self.expand(path, id, &mut vec![path.to_path_buf()], &mut tokens);
+        Some(tokens)
+    }
+
+    fn expand(&mut self, path: &Path, id: FileId, including: &mut Vec<PathBuf>, out: &mut Vec<(Token, Span)>) {
+        let options = self.lex_options();
+        let source = Rc::clone(self.sources.file(id));
+        let mut tokens = Tokens::new(source.text(), options).peekable();
+        while let Some((token, range)) = tokens.next() {
+            let is_include = matches!(&token, Token::Preprocessor(directive) if directive == "#include");
+            let included = match tokens.peek() {
+                Some((Token::StringLit(name), _)) if is_include => self
+                    .resolve_include(path, name)
+                    .filter(|included| !including.contains(included))
+                    .and_then(|included| Some((self.header_id(&included)?, included))),
+                _ => None,
+            };
+            let Some((included_id, included)) = included else {
+                out.push((token, Span { file: id, range }));
+                continue;
+            };
+            tokens.next();
+            including.push(included.clone());
+            self.expand(&included, included_id, including, out);
+            including.pop();
+        }
+    }
+
+    /// The FileId of an included file: the project's, or the one it was
+    /// given when first read from disk
+    fn header_id(&mut self, path: &Path) -> Option<FileId> {
+        if let Some(id) = self.file_id(path) {
+            return Some(id);
+        }
+        let name = path.display().to_string();
+        match self.sources.find(&name) {
+            Some(id) => Some(id),
+            None => Some(self.sources.add(name, fs::read_to_string(path).ok()?)),
+        }
+    }
+
+    /// The file a quoted `#include "name"` in `from` means: next to it, then
+    /// along the include paths, a project file before one on disk
+    pub fn resolve_include(&self, from: &Path, name: &str) -> Option<PathBuf> {
+        let directories = from.parent().map(Path::to_path_buf).into_iter().chain(self.include_paths.iter().cloned());
+        directories
+            .map(|directory| directory.join(name))
+            .find(|candidate| self.files.contains_key(candidate) || candidate.is_file())
+    }
+
+    fn change(&mut self) -> Revision {
+        self.revision += 1;
+        self.revision
+    }
+
+    // Each query below brings a file's memo up to date and returns the
+    // revision its value last changed at
+
+    fn tokens_query(&mut self, path: &Path) -> Option<Revision> {
+        let options = self.lex_options();
+        let (revision, options_changed) = (self.revision, self.options_changed);
+        let file = self.files.get_mut(path)?;
+        let inputs = options_changed.max(file.text_changed);
+        let mut ran = false;
+        let text = file.source.text();
+        let compute = || {
+            ran = true;
+            lexer_regex::lex_with_regex_options(text, options)
+        };
+        let changed = query::fetch(&mut file.tokens, revision, inputs, compute, PartialEq::eq).changed_at();
+        self.executions.tokens += usize::from(ran);
+        Some(changed)
+    }
+
+    fn unit_query(&mut self, path: &Path) -> Option<Revision> {
+        let inputs = self.tokens_query(path)?.max(self.options_changed);
+        let (revision, gnu_extensions, standard) = (self.revision, self.gnu_extensions, self.standard);
+        let file = self.files.get_mut(path)?;
+        let tokens = Rc::clone(file.tokens.as_ref()?.value());
+        let mut ran = false;
+        let compute = || {
+            ran = true;
+            let mut parser = Parser::new(tokens.as_ref().clone());
+            parser.set_gnu_extensions(gnu_extensions);
+            parser.set_standard(standard);
+            let unit = parser.parse().map_err(|errors| errors.into_iter().zip(parser.error_places()).collect());
+            unit.map(|mut unit| {
+                fold::fold_constants(&mut unit);
+                unit
+            })
+        };
+        // Tokens that changed parse to a tree that did, so it isn't compared
+        let changed = query::fetch(&mut file.unit, revision, inputs, compute, |_, _| false).changed_at();
+        self.executions.unit += usize::from(ran);
+        Some(changed)
+    }
+
+    /// Only a file that parses is parsed again, with the positions of its
+    /// tokens, each time its text changes
+    fn spanned_query(&mut self, path: &Path) -> Option<Revision> {
+        let inputs = self.unit_query(path)?;
+        let (revision, gnu_extensions, standard) = (self.revision, self.gnu_extensions, self.standard);
+        let options = self.lex_options();
+        let file = self.files.get_mut(path)?;
+        let inputs = inputs.max(file.text_changed);
+        let unit = Rc::clone(file.unit.as_ref()?.value());
+        let mut ran = false;
+        let text = file.source.text();
+        let compute = || {
+            ran = true;
+            unit.as_ref().as_ref().ok()?;
+            let mut parser = Parser::with_spans(lexer_regex::lex_with_regex_positions(text, options));
+            parser.set_gnu_extensions(gnu_extensions);
+            parser.set_standard(standard);
+            let mut unit = parser.parse().ok()?;
+            fold::fold_constants(&mut unit);
+            Some(unit)
+        };
+        let changed = query::fetch(&mut file.spanned, revision, inputs, compute, |_, _| false).changed_at();
+        self.executions.spanned += usize::from(ran);
+        Some(changed)
+    }
+
+    /// Lines are the spans of the trees, so this depends on the text as
+    /// well as on the tree, and on the project files it includes
+    fn semantics_query(&mut self, path: &Path) -> Option<Revision> {
+        let inputs = self.spanned_query(path)?.max(self.options_changed).max(self.files_changed);
+        let expanded = self.expanded_tokens(path)?;
+        let (revision, standard, gnu_extensions, policy) = (self.revision, self.standard, self.gnu_extensions, &self.policy);
+        let project_ids: Vec<FileId> = self.files.values().map(|file| file.source.id).collect();
+        let included = self.files.values().filter(|file| expanded.iter().any(|(_, span)| span.file == file.source.id));
+        let inputs = included.map(|file| file.text_changed).fold(inputs, Revision::max);
+        let sources = &self.sources;
+        let file = self.files.get_mut(path)?;
+        let unit = Rc::clone(file.spanned.as_ref()?.value());
+        let mut ran = false;
+        let source = &file.source;
+        let compute = || {
+            ran = true;
+            let Some(unit) = unit.as_ref() else {
+                // The policy finds goto in the tokens alone
+                return Semantics {
+                    diagnostics: policy.check(None, source.text()).iter().map(Diagnostic::from).collect(),
+                    symbols: Vec::new(),
+                };
+            };
+            // What the file's #includes declare is seen as a preprocessor
+            // would have it, as if written where they are
+            let (text, tokens, lines) = expanded_text(sources, &expanded);
+            let mut parser = Parser::with_spans(spanned(&text, tokens));
+            parser.set_gnu_extensions(gnu_extensions);
+            parser.set_standard(standard);
+            let Ok(mut expanded_unit) = parser.parse() else {
+                return analyze(path, source, unit, unit, standard, policy);
+            };
+            fold::fold_constants(&mut expanded_unit);
+            let mut expanded_source = SourceFile::new(source.name.clone(), text);
+            expanded_source.id = source.id;
+            let mut semantics = analyze(path, &Rc::new(expanded_source), &expanded_unit, unit, standard, policy);
+            semantics.map_lines(source.id, &lines, &project_ids, sources);
+            semantics
+        };
+        let changed = query::fetch(&mut file.semantics, revision, inputs, compute, PartialEq::eq).changed_at();
+        self.executions.semantics += usize::from(ran);
+        Some(changed)
+    }
+
+    /// Lints report the lines of the tree with spans, so they are run again
+    /// when it is parsed again
+    fn lints_query(&mut self, path: &Path) -> Option<Revision> {
+        let inputs = self.spanned_query(path)?.max(self.lints_changed);
+        let revision = self.revision;
+        let file = self.files.get_mut(path)?;
+        let unit = Rc::clone(file.spanned.as_ref()?.value());
+        let mut ran = false;
+        let lints = &self.lints;
+        let compute = || {
+            ran = true;
+            match unit.as_ref() {
+                Some(unit) => lints.run_lints(unit).iter().map(Diagnostic::from).collect(),
+                None => Vec::new(),
+            }
+        };
+        let changed = query::fetch(&mut file.lints, revision, inputs, compute, PartialEq::eq).changed_at();
+        self.executions.lints += usize::from(ran);
+        Some(changed)
+    }
+
+    fn includes_query(&mut self, path: &Path) -> Option<Revision> {
+        let revision = self.revision;
+        let inputs = self.files.get(path)?.text_changed.max(self.files_changed);
+        // Finding includes looks at the other files, so the memo is taken
+        // out while it does
+        let mut memo = self.files.get_mut(path)?.includes.take();
+        let mut ran = false;
+        let text = self.files.get(path)?.source.text();
+        let compute = || {
+            ran = true;
+            self.include_diagnostics(path, text)
+        };
+        let changed = query::fetch(&mut memo, revision, inputs, compute, PartialEq::eq).changed_at();
+        self.files.get_mut(path)?.includes = memo;
+        self.executions.includes += usize::from(ran);
+        Some(changed)
+    }
+
+    /// The other queries' diagnostics, in the order the phases run, less
+    /// those suppressed in the text
+    fn diagnostics_query(&mut self, path: &Path) -> Option<Revision> {
+        let inputs = [self.includes_query(path)?, self.unit_query(path)?, self.semantics_query(path)?, self.lints_query(path)?];
+        let (revision, options) = (self.revision, self.lex_options());
+        let file = self.files.get_mut(path)?;
+        let inputs = inputs.into_iter().max().unwrap_or_default().max(file.text_changed);
+        let (includes, unit) = (file.includes.as_ref()?.value(), file.unit.as_ref()?.value());
+        let (semantics, lints) = (file.semantics.as_ref()?.value(), file.lints.as_ref()?.value());
+        let mut ran = false;
+        let (text, id) = (file.source.text(), file.source.id);
+        let compute = || {
+            ran = true;
+            let mut diagnostics = includes.as_ref().clone();
+            // Tokens are kept without their positions, so one moved by an
+            // edit doesn't parse the file again; they are found here instead
+            if let Err(errors) = unit.as_ref() {
+                let spans: Vec<TokenSpan> =
+                    lexer_regex::lex_with_regex_positions(text, options).into_iter().map(|token| token.span).collect();
+                let located = errors.iter().map(|(error, place)| Diagnostic::parse_error(error, error_span(&spans, *place)));
+                diagnostics.extend(located);
+            }
+            diagnostics.extend(semantics.diagnostics.iter().cloned());
+            diagnostics.extend(lints.iter().cloned());
+            suppress::apply(&mut diagnostics, &suppress::suppressions(text));
+            for diagnostic in &mut diagnostics {
+                diagnostic.file.get_or_insert(id);
+            }
+            diagnostics
+        };
+        let changed = query::fetch(&mut file.diagnostics, revision, inputs, compute, PartialEq::eq).changed_at();
+        self.executions.diagnostics += usize::from(ran);
+        Some(changed)
+    }
+
+    /// Quoted #includes that can't be found
+    fn include_diagnostics(&self, path: &Path, text: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let Some(rest) = line.trim_start().strip_prefix('#').map(str::trim_start) else {
+                continue;
+            };
+            let Some(name) = rest.strip_prefix("include").map(str::trim).and_then(|rest| rest.strip_prefix('"')) else {
+                continue;
+            };
+            let name = name.split('"').next().unwrap_or_default();
+            if self.resolve_include(path, name).is_none() {
+                let message = format!("Included file \"{}\" not found", name);
+                diagnostics.push(Diagnostic::new(Severity::Error, Some(index + 1), message));
+            }
+        }
+        diagnostics
+    }
+}
+
+/// Scope analysis and type checking of a file compiled on its own (`-c`),
+/// `unit` with its includes spliced in and `source` its text; the policy
+/// looks at the file as written
+fn analyze(
+    path: &Path,
+    source: &Rc<SourceFile>,
+    unit: &TranslationUnit,
+    written: &TranslationUnit,
+    standard: Standard,
+    policy: &Policy,
+) -> Semantics {
+    let mut scope_analyzer = ScopeAnalyzer::with_source(source.clone());
+    let _ = scope_analyzer.analyze_translation_unit(unit);
+    let mut diagnostics: Vec<Diagnostic> = scope_analyzer.get_errors().iter().map(Diagnostic::from).collect();
+    let mut symbols: Vec<GlobalSymbol> = scope_analyzer
+        .get_global_scope()
+        .symbols
+        .borrow()
+        .values()
+        .filter(|symbol| symbol.line.is_some()) // not a header's builtin
+        .filter_map(|symbol| {
+            let kind = match &symbol.kind {
+                SymbolKind::Function { is_defined, .. } => GlobalKind::Function { defined: *is_defined },
+                SymbolKind::Variable { .. } => GlobalKind::Variable,
+                SymbolKind::Parameter { .. } | SymbolKind::Enumerator { .. } => return None,
+            };
+            Some(GlobalSymbol { name: symbol.name.clone(), kind, file: path.to_path_buf(), line: symbol.line })
+        })
+        .collect();
+    symbols.sort_by(|a, b| (a.line, &a.name).cmp(&(b.line, &b.name)));
+
+    let mut type_checker = TypeChecker::new(scope_analyzer);
+    type_checker.set_require_main(false);
+    type_checker.set_standard(standard);
+    let _ = type_checker.check_translation_unit(unit);
+    diagnostics.extend(type_checker.get_errors().iter().map(Diagnostic::from));
+    diagnostics.extend(type_checker.get_warnings().iter().map(Diagnostic::from));
+    diagnostics.extend(policy.check(Some(written), source.text()).iter().map(Diagnostic::from));
+    Semantics { diagnostics, symbols }
+}
+
+/// Include-expanded tokens written out as a text, those from one line of a
+/// file on one line; the tokens with their ranges of that text, and the
+/// file and line each line of it is from
+type ExpandedText = (String, Vec<(Token, Range<usize>)>, Vec<(FileId, usize)>);
+
+fn expanded_text(sources: &SourceMap, tokens: &[(Token, Span)]) -> ExpandedText {
+    let mut text = String::new();
+    let mut ranges = Vec::new();
+    let mut lines: Vec<(FileId, usize)> = Vec::new();
+    for (token, span) in tokens {
+        let (line, _) = sources.line_column(span);
+        if lines.last() != Some(&(span.file, line)) {
+            if !lines.is_empty() {
+                text.push('\n');
+            }
+            lines.push((span.file, line));
+        } else {
+            text.push(' ');
+        }
+        // A line continuation inside a token would start a line of its own
+        let start = text.len();
+        text.push_str(&sources.text(span).replace("\\\n", ""));
+        ranges.push((token.clone(), start..text.len()));
+    }
+    text.push('\n');
+    (text, ranges, lines)
+}
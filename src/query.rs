@@ -0,0 +1,74 @@
+// query.rs: Memoized queries, the way salsa has them, for the phases of a
+// Project (project.rs).
+//
+// Every change to an input, like a file's text or an option, moves the
+// project on to a new revision, and the input remembers the revision it
+// last changed at. A query's value is kept with two revisions: the last one
+// it was checked at, and the last one it changed at. Asked for again, the
+// query first brings the queries it depends on up to date; if none of them
+// has changed since it was last checked, its value still holds. Otherwise it
+// is computed again, and if it comes out as it was, it keeps the revision
+// it changed at, so the queries depending on it still hold too (early
+// cutoff). A whitespace edit relexes the file, but the tokens come out the
+// same and the file isn't parsed again.
+
+use std::rc::Rc;
+
+/// The project's count of changes so far
+pub type Revision = u64;
+
+/// A query's value and when it was checked and changed
+pub struct Memo<T> {
+    value: Rc<T>,
+    verified_at: Revision,
+    changed_at: Revision,
+}
+
+impl<T> Memo<T> {
+    pub fn value(&self) -> &Rc<T> {
+        &self.value
+    }
+
+    pub fn changed_at(&self) -> Revision {
+        self.changed_at
+    }
+}
+
+/// A query's memo, brought up to `revision`. `inputs` is the latest
+/// revision any of its inputs changed at; `compute` runs only if that is
+/// after the memo was last checked, and `same` tells whether what it
+/// computed is the old value again.
+pub fn fetch<T>(
+    memo: &mut Option<Memo<T>>,
+    revision: Revision,
+    inputs: Revision,
+    compute: impl FnOnce() -> T,
+    same: impl Fn(&T, &T) -> bool,
+) -> &Memo<T> {
+    match memo {
+        Some(old) if inputs <= old.verified_at => old.verified_at = revision,
+        Some(old) => {
+            let value = compute();
+            if !same(&old.value, &value) {
+                old.value = Rc::new(value);
+                old.changed_at = revision;
+            }
+            old.verified_at = revision;
+        }
+        None => *memo = Some(Memo { value: Rc::new(compute()), verified_at: revision, changed_at: revision }),
+    }
+    memo.as_ref().unwrap_or_else(|| unreachable!())
+}
+
+/// How many times each query has been computed, to see what an edit cost
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Executions {
+    pub tokens: usize,
+    pub unit: usize,
+    pub spanned: usize,
+    pub semantics: usize,
+    pub lints: usize,
+    pub includes: usize,
+    pub diagnostics: usize,
+    pub type_at: usize,
+}
@@ -0,0 +1,156 @@
+// reduce.rs: Shrink a C file to a small one that still does something,
+// like set off a diagnostic or a crash, for a bug report (`reduce`).
+//
+// The file is taken as a tree of declarations and statements: those at
+// file scope, the statements in their braces, and so on down. One level of
+// the tree at a time, chunks of its nodes are taken out (delta debugging,
+// as ice::minimize does it) and what is left is kept if the check still
+// holds for it. A file that parsed must still parse, so a reduction can't
+// stray into some other, syntax, error. The levels are gone over again
+// until nothing more can be taken out.
+
+use crate::ice;
+use crate::lexer_regex::{self, Token};
+use crate::parser::Parser;
+use crate::suppress::{is_code, statement_end};
+use std::ops::Range;
+
+pub struct Reduction {
+    pub text: String,
+    pub checks: usize, // times the check was run
+}
+
+/// `source` with as many declarations and statements taken out as can be
+/// while `interesting` still holds
+pub fn reduce(source: &str, mut interesting: impl FnMut(&str) -> bool) -> Reduction {
+    let must_parse = parses(source);
+    let mut checks = 0;
+    let mut accept = |candidate: &str| {
+        if must_parse && !parses(candidate) {
+            return false;
+        }
+        checks += 1;
+        interesting(candidate)
+    };
+    let mut text = source.to_string();
+    loop {
+        let before = text.clone();
+        for depth in 0.. {
+            let units = units_at(&text, depth);
+            if units.is_empty() {
+                break;
+            }
+            let removed = |kept: &[Range<usize>]| units.iter().filter(|unit| !kept.contains(unit)).cloned().collect::<Vec<_>>();
+            let kept = ice::minimize(units.clone(), |kept| accept(&without(&text, &removed(kept))));
+            text = without(&text, &removed(&kept));
+        }
+        if text == before {
+            break;
+        }
+    }
+    // Blank lines that were between what was taken out, if the check
+    // doesn't mind the lines moving up
+    let tidy = tidy(&text);
+    if tidy != text && accept(&tidy) {
+        text = tidy;
+    }
+    Reduction { text, checks }
+}
+
+/// `text` with no blank lines at the start and none two in a row
+fn tidy(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        let blank = line.trim().is_empty();
+        if blank && lines.last().is_none_or(|last| last.trim().is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n") + "\n"
+}
+
+fn parses(source: &str) -> bool {
+    Parser::new(lexer_regex::lex_with_regex(source)).parse().is_ok()
+}
+
+/// Byte ranges of the declarations and statements `depth` braces in, each
+/// with the line it is on when nothing else is
+fn units_at(source: &str, depth: usize) -> Vec<Range<usize>> {
+    let spanned = lexer_regex::lex_with_regex_spanned(source);
+    let mut units = Vec::new();
+    collect(source, &spanned, 0..spanned.len(), depth, &mut units);
+    units.into_iter().map(|unit| whole_lines(source, unit)).collect()
+}
+
+fn collect(source: &str, spanned: &[(Token, Range<usize>)], tokens: Range<usize>, depth: usize, units: &mut Vec<Range<usize>>) {
+    let mut index = tokens.start;
+    while let Some(first) = (index..tokens.end).find(|&index| is_code(&spanned[index].0)) {
+        let last = match spanned[first].0 {
+            // A directive runs to the end of its line
+            Token::Preprocessor(_) => {
+                let line_end = source[spanned[first].1.start..].find('\n').map_or(source.len(), |end| spanned[first].1.start + end);
+                (first..tokens.end).take_while(|&index| spanned[index].1.start < line_end).last().unwrap_or(first)
+            }
+            _ => statement_end(spanned, first).min(tokens.end - 1),
+        };
+        if depth == 0 {
+            units.push(spanned[first].1.start..spanned[last].1.end);
+        } else {
+            // The blocks inside it, one level down
+            let mut inner = first;
+            while inner <= last {
+                if matches!(spanned[inner].0, Token::BraceL) {
+                    let close = matching_brace(spanned, inner, last);
+                    collect(source, spanned, inner + 1..close, depth - 1, units);
+                    inner = close;
+                }
+                inner += 1;
+            }
+        }
+        index = last + 1;
+    }
+}
+
+/// The `}` closing the `{` at `open`, or `last` if it isn't closed by then
+fn matching_brace(spanned: &[(Token, Range<usize>)], open: usize, last: usize) -> usize {
+    let mut depth = 0;
+    for (index, (token, _)) in spanned.iter().enumerate().take(last + 1).skip(open) {
+        match token {
+            Token::BraceL => depth += 1,
+            Token::BraceR => {
+                depth -= 1;
+                if depth == 0 {
+                    return index;
+                }
+            }
+            _ => {}
+        }
+    }
+    last
+}
+
+/// `span` grown to the whole lines it is on, newline and all, when there is
+/// only whitespace around it, so taking it out leaves no blank line
+fn whole_lines(source: &str, span: Range<usize>) -> Range<usize> {
+    let line_start = source[..span.start].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = source[span.end..].find('\n').map_or(source.len(), |newline| span.end + newline + 1);
+    let blank = |text: &str| text.trim().is_empty();
+    if blank(&source[line_start..span.start]) && blank(&source[span.end..line_end]) {
+        line_start..line_end
+    } else {
+        span
+    }
+}
+
+/// `source` less `spans`, which are in order and don't overlap
+fn without(source: &str, spans: &[Range<usize>]) -> String {
+    let mut text = String::with_capacity(source.len());
+    let mut from = 0;
+    for span in spans {
+        text.push_str(&source[from..span.start]);
+        from = span.end;
+    }
+    text.push_str(&source[from..]);
+    text
+}
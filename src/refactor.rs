@@ -0,0 +1,349 @@
+// refactor.rs: Source-to-source refactorings, currently symbol renaming.
+//
+// Renaming works on the regex lexer's spanned tokens so the edits land on
+// exact byte ranges. Every identifier is resolved to its declaration with
+// C's block scoping rules, so a rename only touches the symbol under the
+// cursor and leaves same-named variables in other scopes alone.
+
+use crate::lexer_regex::{self, Token};
+use crate::source::LineIndex;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub span: Range<usize>, // byte range in the source to replace
+    pub line: usize,        // 1-based
+    pub column: usize,      // 1-based, in characters
+    pub replacement: String,
+}
+
+#[derive(Debug)]
+pub enum RenameError {
+    NoIdentifierAt { line: usize, column: usize },
+    UndeclaredSymbol(String),
+    InvalidName(String),
+    NameConflict { name: String, line: usize, column: usize },
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenameError::NoIdentifierAt { line, column } => {
+                write!(f, "no identifier at {}:{}", line, column)
+            }
+            RenameError::UndeclaredSymbol(name) => {
+                write!(f, "'{}' is not declared in this file", name)
+            }
+            RenameError::InvalidName(name) => write!(f, "'{}' is not a valid identifier", name),
+            RenameError::NameConflict { name, line, column } => {
+                write!(f, "renaming would make '{}' at {}:{} refer to a different symbol", name, line, column)
+            }
+        }
+    }
+}
+
+/// One identifier (declaration or use) and what it resolved to
+struct Occurrence {
+    token: usize,
+    symbol: Option<usize>,
+    // Innermost symbol the new name resolves to here, with its scope depth
+    new_name_binding: Option<(usize, usize)>,
+    // Whether the symbol being renamed is in scope here
+    target_in_scope: bool,
+}
+
+/// What the next '(' opens a scope for
+#[derive(PartialEq)]
+enum PendingScope {
+    None,
+    Parameters, // after a function name: its parameters
+    ForHeader,  // after 'for': declarations in its init clause
+}
+
+struct Resolver<'a> {
+    tokens: &'a [(Token, Range<usize>)],
+    new_name: &'a str,
+    target: Option<(&'a str, usize)>,
+    scopes: Vec<HashMap<String, usize>>,
+    symbol_depths: Vec<usize>, // scope depth each symbol was declared at
+    occurrences: Vec<Occurrence>,
+}
+
+fn is_type_token(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Int | Token::Float | Token::Char | Token::Void | Token::Double | Token::Short
+            | Token::Long | Token::Signed | Token::Unsigned | Token::Bool | Token::String
+    )
+}
+
+impl<'a> Resolver<'a> {
+    fn new(tokens: &'a [(Token, Range<usize>)], new_name: &'a str, target: Option<(&'a str, usize)>) -> Self {
+        Resolver {
+            tokens,
+            new_name,
+            target,
+            scopes: vec![HashMap::new()],
+            symbol_depths: Vec::new(),
+            occurrences: Vec::new(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<(usize, usize)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, scope)| scope.get(name).map(|&symbol| (symbol, depth)))
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let depth = self.scopes.len() - 1;
+        // Redeclaring in the same scope (a prototype, then its definition)
+        // names the same symbol
+        if let Some(&symbol) = self.scopes[depth].get(name) {
+            return symbol;
+        }
+        let symbol = self.symbol_depths.len();
+        self.symbol_depths.push(depth);
+        self.scopes[depth].insert(name.to_string(), symbol);
+        symbol
+    }
+
+    fn record(&mut self, token: usize, symbol: Option<usize>) {
+        let new_name_binding = self.lookup(self.new_name);
+        let target_in_scope = self
+            .target
+            .is_some_and(|(name, target)| self.lookup(name).is_some_and(|(symbol, _)| symbol == target));
+        self.occurrences.push(Occurrence { token, symbol, new_name_binding, target_in_scope });
+    }
+
+    fn token(&self, index: usize) -> Option<&Token> {
+        self.tokens.get(index).map(|(token, _)| token)
+    }
+
+    /// Index of the next token that isn't a comment
+    fn next_significant(&self, mut index: usize) -> usize {
+        while let Some(Token::Comment(_) | Token::BlockComment(_) | Token::Pragma(_)) = self.token(index) {
+            index += 1;
+        }
+        index
+    }
+
+    fn resolve(&mut self) {
+        let mut in_declaration = false; // the next name at this paren depth is declared
+        let mut declaration_paren_depth = 0;
+        let mut paren_depth = 0;
+        let mut pending = PendingScope::None;
+        // Parens that opened a scope: their depth and what for
+        let mut scoped_parens: Vec<(usize, PendingScope)> = Vec::new();
+        let mut reuse_scope_for_brace = false;
+        // Scopes of for-loops whose body has no braces; closed when it ends
+        let mut unbraced_for_scopes: Vec<usize> = Vec::new();
+        let mut after_member_operator = false;
+
+        for index in 0..self.tokens.len() {
+            let token = &self.tokens[index].0;
+            match token {
+                Token::Comment(_) | Token::BlockComment(_) | Token::Pragma(_) => continue,
+                _ if is_type_token(token) => {
+                    in_declaration = true;
+                    declaration_paren_depth = paren_depth;
+                }
+                Token::Identifier(name) => {
+                    if after_member_operator {
+                        // A struct member, not a scoped name
+                        after_member_operator = false;
+                        continue;
+                    }
+                    if in_declaration && paren_depth == declaration_paren_depth {
+                        let symbol = self.declare(name);
+                        self.record(index, Some(symbol));
+                        let next = self.next_significant(index + 1);
+                        if self.scopes.len() == 1 && self.token(next) == Some(&Token::ParenL) {
+                            pending = PendingScope::Parameters;
+                        }
+                        in_declaration = false;
+                    } else {
+                        let symbol = self.lookup(name).map(|(symbol, _)| symbol);
+                        self.record(index, symbol);
+                    }
+                }
+                Token::Dot | Token::Arrow => after_member_operator = true,
+                Token::For => pending = PendingScope::ForHeader,
+                Token::ParenL => {
+                    paren_depth += 1;
+                    if pending != PendingScope::None {
+                        self.scopes.push(HashMap::new());
+                        scoped_parens.push((paren_depth, std::mem::replace(&mut pending, PendingScope::None)));
+                    }
+                }
+                Token::ParenR => {
+                    if scoped_parens.last().is_some_and(|(depth, _)| *depth == paren_depth) {
+                        let (_, kind) = scoped_parens.pop().unwrap();
+                        let next = self.next_significant(index + 1);
+                        if self.token(next) == Some(&Token::BraceL) {
+                            // A function body or braced loop body shares the scope
+                            reuse_scope_for_brace = true;
+                        } else if kind == PendingScope::ForHeader {
+                            unbraced_for_scopes.push(self.scopes.len() - 1);
+                        } else {
+                            // A prototype: its parameter names go out of scope
+                            self.scopes.pop();
+                        }
+                    }
+                    paren_depth -= 1;
+                    if paren_depth < declaration_paren_depth {
+                        in_declaration = false;
+                    }
+                }
+                Token::Comma => {
+                    // `int a = 1, b;` declares b too
+                    in_declaration = self.declaration_continues(index);
+                    declaration_paren_depth = paren_depth;
+                }
+                Token::BraceL => {
+                    if !std::mem::take(&mut reuse_scope_for_brace) {
+                        self.scopes.push(HashMap::new());
+                    }
+                    in_declaration = false;
+                }
+                Token::BraceR => {
+                    if self.scopes.len() > 1 {
+                        self.scopes.pop();
+                    }
+                    self.close_unbraced_for_scopes(&mut unbraced_for_scopes);
+                }
+                Token::Semicolon => {
+                    in_declaration = false;
+                    if paren_depth == 0 {
+                        self.close_unbraced_for_scopes(&mut unbraced_for_scopes);
+                    }
+                }
+                Token::AssignOp => in_declaration = false,
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the ',' at `index` continues a declaration list like `int a = 1, b;`
+    fn declaration_continues(&self, index: usize) -> bool {
+        // Walk back to the start of this declaration or argument list
+        let mut depth = 0;
+        for i in (0..index).rev() {
+            match self.token(i) {
+                Some(Token::ParenR | Token::BracketR) => depth += 1,
+                Some(Token::ParenL | Token::BracketL) => {
+                    if depth == 0 {
+                        // A parameter list: each parameter brings its own type
+                        return false;
+                    }
+                    depth -= 1;
+                }
+                Some(Token::Semicolon | Token::BraceL | Token::BraceR) => return false,
+                Some(token) if depth == 0 && is_type_token(token) => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// A statement just ended; close for-loop scopes whose unbraced body it was
+    fn close_unbraced_for_scopes(&mut self, unbraced_for_scopes: &mut Vec<usize>) {
+        while unbraced_for_scopes.last() == Some(&(self.scopes.len() - 1)) {
+            self.scopes.pop();
+            unbraced_for_scopes.pop();
+        }
+    }
+}
+
+/// For each identifier token, the token declaring what it names: itself
+/// for a declaration, None for a name never declared. Members of structs,
+/// after `.` or `->`, aren't names in scope, so aren't in it.
+pub(crate) fn declarations(tokens: &[(Token, Range<usize>)]) -> HashMap<usize, Option<usize>> {
+    let mut resolver = Resolver::new(tokens, "", None);
+    resolver.resolve();
+    let mut declared_at: HashMap<usize, usize> = HashMap::new();
+    for occurrence in &resolver.occurrences {
+        if let Some(symbol) = occurrence.symbol {
+            declared_at.entry(symbol).or_insert(occurrence.token);
+        }
+    }
+    resolver
+        .occurrences
+        .iter()
+        .map(|occurrence| (occurrence.token, occurrence.symbol.map(|symbol| declared_at[&symbol])))
+        .collect()
+}
+
+/// Edits that rename the symbol at `line`:`column` to `new_name` everywhere it is referenced
+pub fn rename(source: &str, line: usize, column: usize, new_name: &str) -> Result<Vec<Edit>, RenameError> {
+    match lexer_regex::lex_with_regex(new_name).as_slice() {
+        [Token::Identifier(name)] if name == new_name => {}
+        _ => return Err(RenameError::InvalidName(new_name.to_string())),
+    }
+
+    let tokens = lexer_regex::lex_with_regex_spanned(source);
+    let mut resolver = Resolver::new(&tokens, new_name, None);
+    resolver.resolve();
+
+    let index = LineIndex::new(source);
+    let cursor = index.offset_of(source, line, column).ok_or(RenameError::NoIdentifierAt { line, column })?;
+    let target = resolver
+        .occurrences
+        .iter()
+        .find(|occurrence| tokens[occurrence.token].1.contains(&cursor))
+        .ok_or(RenameError::NoIdentifierAt { line, column })?;
+    let old_name = &source[tokens[target.token].1.clone()];
+    let symbol = target
+        .symbol
+        .ok_or_else(|| RenameError::UndeclaredSymbol(old_name.to_string()))?;
+    if old_name == new_name {
+        return Ok(Vec::new());
+    }
+
+    // Resolve again, now tracking where the renamed symbol is visible
+    let mut resolver = Resolver::new(&tokens, new_name, Some((old_name, symbol)));
+    resolver.resolve();
+    let symbol_depth = resolver.symbol_depths[symbol];
+
+    let mut edits = Vec::new();
+    for occurrence in &resolver.occurrences {
+        let span = tokens[occurrence.token].1.clone();
+        let (line, column) = index.line_column(source, span.start);
+        if occurrence.symbol == Some(symbol) {
+            // The new name mustn't already mean something at least as close
+            if let Some((other, depth)) = occurrence.new_name_binding
+                && other != symbol
+                && depth >= symbol_depth
+            {
+                return Err(RenameError::NameConflict { name: new_name.to_string(), line, column });
+            }
+            edits.push(Edit { span, line, column, replacement: new_name.to_string() });
+        } else if occurrence.target_in_scope
+            && &source[span.clone()] == new_name
+            && occurrence.symbol.is_some_and(|other| resolver.symbol_depths[other] < symbol_depth)
+        {
+            // An outer symbol named like the new name would be shadowed here
+            return Err(RenameError::NameConflict { name: new_name.to_string(), line, column });
+        }
+    }
+    Ok(edits)
+}
+
+/// The source with every edit applied
+pub fn apply_edits(source: &str, edits: &[Edit]) -> String {
+    let mut sorted: Vec<&Edit> = edits.iter().collect();
+    sorted.sort_by_key(|edit| edit.span.start);
+    let mut result = String::with_capacity(source.len());
+    let mut pos = 0;
+    for edit in sorted {
+        result.push_str(&source[pos..edit.span.start]);
+        result.push_str(&edit.replacement);
+        pos = edit.span.end;
+    }
+    result.push_str(&source[pos..]);
+    result
+}
@@ -1,5 +1,12 @@
 use regex::Regex;
 use lazy_static::lazy_static;
+use crate::dialect::{Feature, Standard};
+use crate::lexer_regex::{
+    LexOptions, check_identifier, check_string, describe_char, is_identifier_start, parse_char_literal,
+    parse_float_literal, parse_int_literal,
+};
+use crate::parser::ast::FloatSuffix;
+use crate::source::{self, Spanned};
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -15,13 +22,15 @@ pub enum Token {
     KeywordWhile,
     KeywordFor,
     KeywordStruct,
+    KeywordBool,
 
     // Literals
     Identifier(String),
     Int(i64),
-    Float(f64),
+    Float(f64, FloatSuffix),
     StringLit(String),
     CharLit(char),
+    BoolLit(bool),
 
     // Symbols
     ParenL,
@@ -32,6 +41,7 @@ pub enum Token {
     BracketR,
     Semicolon,
     Comma,
+    Ellipsis,
 
     // Operators
     Plus,
@@ -62,6 +72,10 @@ pub enum Token {
     Error(String),
 }
 
+/// Numbers are matched with separators allowed; the caller rejects them
+/// when its options don't
+const ANY_NUMBER: LexOptions = LexOptions { digit_separators: true, standard: Standard::MiniC, unicode_identifiers: false };
+
 pub struct Rule {
     pub regex: Regex,
     pub token_type: fn(&str) -> Token,
@@ -75,7 +89,7 @@ lazy_static! {
             token_type: |s| Token::Comment(s.to_string()),
         },
         Rule {
-            regex: Regex::new(r"^/\*.*?\*/").unwrap(),
+            regex: Regex::new(r"^(?s:/\*.*?\*/)").unwrap(),
             token_type: |s| Token::Comment(s.to_string()),
         },
         
@@ -90,30 +104,35 @@ lazy_static! {
         Rule { regex: Regex::new(r"^\bwhile\b").unwrap(),  token_type: |_| Token::KeywordWhile },
         Rule { regex: Regex::new(r"^\bfor\b").unwrap(),    token_type: |_| Token::KeywordFor },
         Rule { regex: Regex::new(r"^\bstruct\b").unwrap(), token_type: |_| Token::KeywordStruct },
+        Rule { regex: Regex::new(r"^\b(_Bool|bool)\b").unwrap(), token_type: |_| Token::KeywordBool },
+        Rule { regex: Regex::new(r"^\b(true|false)\b").unwrap(), token_type: |s| Token::BoolLit(s == "true") },
 
         // ===== Literals =====
         Rule {
             regex: Regex::new(r#"^"([^"\\]|\\.)*""#).unwrap(),
             token_type: |s| {
-                // Remove surrounding quotes
+                // Remove surrounding quotes; the body is kept as written
                 let content = &s[1..s.len()-1];
-                Token::StringLit(content.to_string())
+                check_string(content).map_or_else(
+                    |message| Token::Error(format!("{} in string literal {}", message, s)),
+                    |()| Token::StringLit(content.to_string()),
+                )
             },
         },
         Rule {
-            regex: Regex::new(r"^'([^'\\]|\\.)'").unwrap(),
-            token_type: |s| Token::CharLit(s.chars().nth(1).unwrap()),
+            regex: Regex::new(r"^'([^'\\]|\\.)*'").unwrap(),
+            token_type: |s| parse_char_literal(&s[1..s.len()-1]).map_or_else(Token::Error, Token::CharLit),
         },
         Rule {
-            regex: Regex::new(r"^\d+\.\d+").unwrap(),
-            token_type: |s| Token::Float(s.parse::<f64>().unwrap()),
+            regex: Regex::new(r"^(?:(?:\d+(?:['_]\d+)*)?\.\d+(?:['_]\d+)*(?:[eE][+-]?\d*)?|\d+(?:['_]\d+)*\.(?:[eE][+-]?\d*)?|\d+(?:['_]\d+)*[eE][+-]?\d*)[fFlL]?").unwrap(),
+            token_type: |s| parse_float_literal(s, ANY_NUMBER).map_or_else(Token::Error, |(f, suffix)| Token::Float(f, suffix)),
         },
         Rule {
-            regex: Regex::new(r"^\d+").unwrap(),
-            token_type: |s| Token::Int(s.parse::<i64>().unwrap()),
+            regex: Regex::new(r"^(?:0[xX][0-9a-fA-F]*(?:['_][0-9a-fA-F]+)*|0[bB][01]+(?:['_][01]+)*|\d+(?:['_]\d+)*)").unwrap(),
+            token_type: |s| parse_int_literal(s, ANY_NUMBER).map_or_else(Token::Error, Token::Int),
         },
         Rule {
-            regex: Regex::new(r"^[a-zA-Z_]\w*").unwrap(),
+            regex: Regex::new(r"^[\p{XID_Start}_]\p{XID_Continue}*").unwrap(),
             token_type: |s| Token::Identifier(s.to_string()),
         },
 
@@ -148,5 +167,53 @@ lazy_static! {
         Rule { regex: Regex::new(r"^\]").unwrap(), token_type: |_| Token::BracketR },
         Rule { regex: Regex::new(r"^;").unwrap(),  token_type: |_| Token::Semicolon },
         Rule { regex: Regex::new(r"^,").unwrap(),  token_type: |_| Token::Comma },
+        Rule { regex: Regex::new(r"^\.\.\.").unwrap(), token_type: |_| Token::Ellipsis },
     ];
 }
+
+/// Lex `source` with the rules, each token with its line, column and bytes
+pub fn lex(source: &str, options: LexOptions) -> Vec<Spanned<Token>> {
+    let mut tokens = Vec::new();
+    let mut input = source;
+    while !input.is_empty() {
+        input = input.trim_start();
+        if input.is_empty() {
+            break;
+        }
+        let start = source.len() - input.len();
+        let mut matched = false;
+        for rule in RULES.iter() {
+            if let Some(m) = rule.regex.find(input) {
+                let lexeme = m.as_str();
+                let is_number = lexeme.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+                // Special check: invalid identifier like `2abc`
+                let token = if is_number && input[m.end()..].starts_with(is_identifier_start) {
+                    Token::Error(format!("Invalid identifier: {}", lexeme))
+                } else if is_number && !options.digit_separators && lexeme.contains(['\'', '_']) {
+                    Token::Error(format!("digit separators in {} need -fdigit-separators", lexeme))
+                } else if let (true, Err(message)) =
+                    (lexeme.starts_with("//"), options.standard.check(Feature::LineComments))
+                {
+                    Token::Error(message)
+                } else if let (true, Err(message)) =
+                    (lexeme.starts_with(is_identifier_start), check_identifier(lexeme, options))
+                {
+                    Token::Error(message)
+                } else {
+                    (rule.token_type)(lexeme)
+                };
+                tokens.push((token, start..start + m.end()));
+                input = &input[m.end()..];
+                matched = true;
+                break;
+            }
+        }
+        if !matched {
+            let c = input.chars().next().expect("input is not empty");
+            let length = c.len_utf8();
+            tokens.push((Token::Error(format!("Unexpected character: {}", describe_char(c))), start..start + length));
+            input = &input[length..];
+        }
+    }
+    source::spanned(source, tokens)
+}
@@ -1,30 +1,78 @@
+use crate::interpreter::math;
+use crate::intrinsics;
 use crate::parser::ast::*;
+use crate::source::{FileId, SourceFile, TokenSpan};
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ScopeError {
-    UndeclaredVariable(String),
-    UndefinedFunctionCalled(String),
-    VariableRedefinition(String),
-    FunctionPrototypeRedefinition(String),
+    UndeclaredVariable {
+        name: String,
+        line: Option<usize>,
+    },
+    UndefinedFunctionCalled {
+        name: String,
+        line: Option<usize>,
+    },
+    VariableRedefinition {
+        name: String,
+        line: Option<usize>,
+        previous_line: Option<usize>,
+    },
+    FunctionPrototypeRedefinition {
+        name: String,
+        line: Option<usize>,
+        previous_line: Option<usize>,
+    },
+    ConflictingFunctionDeclaration {
+        name: String,
+        line: Option<usize>,
+        previous_line: Option<usize>,
+    },
+    LabelRedefinition {
+        name: String,
+        line: Option<usize>,
+        previous_line: Option<usize>,
+    },
+    UndefinedLabel {
+        name: String,
+        line: Option<usize>, // of the first goto to it
+    },
+    TagKindMismatch {
+        name: String,
+        kind: TagKind,          // as it is used here
+        previous_kind: TagKind, // as it was declared
+        line: Option<usize>,
+        previous_line: Option<usize>,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub enum SymbolKind {
     Variable {
         type_spec: TypeSpecifier,
+        pointer_depth: u32,
         storage_class: Option<StorageClass>,
+        array_dimensions: usize,
+        variable_length: bool, // some dimension is only known at run time
+        initialized: bool,
+        pointee: Option<TypeName>, // what the pointers point to, past the specifier, as Declarator has it
     },
     Function {
         return_type: String,
         parameters: Vec<Parameter>,
+        variadic: bool,
         is_defined: bool,
     },
     Parameter {
         param_type: String,
     },
+    Enumerator {
+        tag: Option<String>, // the enum it belongs to, if it has a tag
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -32,12 +80,73 @@ pub struct Symbol {
     pub name: String,
     pub kind: SymbolKind,
     pub scope_level: usize,
+    pub line: Option<usize>, // None for builtins
+    pub file: FileId,        // the file declaring it, or including the header of a builtin
+}
+
+/// The kind of type a tag names
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TagKind {
+    Struct,
+    Enum,
+}
+
+impl TagKind {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            TagKind::Struct => "struct",
+            TagKind::Enum => "enum",
+        }
+    }
+
+    /// The tag a type specifier names, if it names one
+    pub fn of(type_spec: &TypeSpecifier) -> Option<(TagKind, &str)> {
+        match type_spec {
+            TypeSpecifier::Struct(tag) => Some((TagKind::Struct, tag)),
+            TypeSpecifier::Enum(tag) => Some((TagKind::Enum, tag)),
+            _ => None,
+        }
+    }
+
+    /// The tag a type spelled out, like "struct node*", starts with
+    pub fn named(spelling: &str) -> Option<(TagKind, &str)> {
+        let (kind, rest) = match spelling.trim_start().strip_prefix("struct ") {
+            Some(rest) => (TagKind::Struct, rest),
+            None => (TagKind::Enum, spelling.trim_start().strip_prefix("enum ")?),
+        };
+        let rest = rest.trim_start();
+        let tag = &rest[..rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len())];
+        (!tag.is_empty()).then_some((kind, tag))
+    }
+}
+
+/// A struct or enum tag. Tags are a namespace of their own, apart from
+/// variables and functions, so `struct list list;` declares a variable
+/// `list` of type `struct list`.
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub name: String,
+    pub kind: TagKind,
+    pub complete: bool,      // defined, not only declared (`struct node;`) or named
+    pub line: Option<usize>, // of its definition, or else where it was first named
+}
+
+/// A label of a function. Labels are a namespace of their own, apart from
+/// variables and functions, and one per function: a goto can jump to any
+/// label of its function, whatever block either is in.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub name: String,
+    pub line: Option<usize>,       // where it is defined, if it is
+    pub defined: bool,
+    pub gotos: Vec<Option<usize>>, // the line of each goto to it
 }
 
 #[derive(Debug)]
 pub struct ScopeNode{
 
     pub symbols: RefCell<HashMap<String,Symbol>>,
+    pub tags: RefCell<HashMap<String, Tag>>,
     pub parent: Option<Rc<ScopeNode>>,
     pub scope_level: usize
 }
@@ -50,6 +159,7 @@ impl ScopeNode{
         ScopeNode{
 
             symbols: RefCell:: new (HashMap::new()),
+            tags: RefCell::new(HashMap::new()),
             parent,
             scope_level,
         }
@@ -67,6 +177,14 @@ impl ScopeNode{
         }
     }
 
+    /// The tag `name`, in this scope or the nearest one around it
+    pub fn lookup_tag(&self, name: &str) -> Option<Tag> {
+        match self.tags.borrow().get(name) {
+            Some(tag) => Some(tag.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.lookup_tag(name)),
+        }
+    }
+
     pub fn lookup_current_scope(&self, name: &str) -> Option<Symbol> {
         self.symbols.borrow().get(name).cloned()
     }
@@ -76,6 +194,69 @@ impl ScopeNode{
     }
 }
 
+/// Whether declaring `kind` where `existing` is declares the same object
+/// again: its type the same, no two initializers, and not a definition of
+/// a name a `static` gave internal linkage without one. Only `extern`
+/// declarations can be repeated in a block; at file scope, `int x;` is a
+/// tentative definition and may be.
+fn redeclares_object(existing: &Symbol, kind: &SymbolKind) -> bool {
+    let (
+        SymbolKind::Variable {
+            type_spec,
+            pointer_depth,
+            storage_class,
+            array_dimensions,
+            variable_length: false,
+            initialized,
+            pointee,
+        },
+        SymbolKind::Variable {
+            type_spec: new_type_spec,
+            pointer_depth: new_pointer_depth,
+            storage_class: new_storage_class,
+            array_dimensions: new_array_dimensions,
+            variable_length: false,
+            initialized: new_initialized,
+            pointee: new_pointee,
+        },
+    ) = (&existing.kind, kind)
+    else {
+        return false;
+    };
+    let is_extern = |storage_class: &Option<StorageClass>| matches!(storage_class, Some(StorageClass::Extern));
+    let linkage_agrees = match (storage_class, new_storage_class) {
+        (Some(StorageClass::Typedef), _) | (_, Some(StorageClass::Typedef)) => false,
+        (first, second) if first == second => true,
+        // `static int x;` then `extern int x;` keeps x static
+        (_, Some(StorageClass::Extern)) => true,
+        (Some(StorageClass::Extern), second) => second.is_none(),
+        _ => false,
+    };
+    let in_block = existing.scope_level > 0;
+    type_spec == new_type_spec
+        && pointer_depth == new_pointer_depth
+        && array_dimensions == new_array_dimensions
+        && pointee.as_ref().map(TypeName::spelling) == new_pointee.as_ref().map(TypeName::spelling)
+        && !(*initialized && *new_initialized)
+        && linkage_agrees
+        && (!in_block || (is_extern(storage_class) && is_extern(new_storage_class)))
+}
+
+/// Whether a redeclaration should stand for the object rather than the
+/// declaration before it: it defines what was only declared `extern`, or
+/// initializes what was only tentatively defined
+fn initializes_first(existing: &SymbolKind, kind: &SymbolKind) -> bool {
+    match (existing, kind) {
+        (
+            SymbolKind::Variable { storage_class, initialized, .. },
+            SymbolKind::Variable { storage_class: new_storage_class, initialized: new_initialized, .. },
+        ) => {
+            let is_extern = |storage_class: &Option<StorageClass>| matches!(storage_class, Some(StorageClass::Extern));
+            (*new_initialized && !*initialized) || (is_extern(storage_class) && !is_extern(new_storage_class))
+        }
+        _ => false,
+    }
+}
 
 pub struct ScopeAnalyzer{
 
@@ -83,23 +264,43 @@ pub struct ScopeAnalyzer{
     global_scope : Rc<ScopeNode>,
     errors: Vec<ScopeError>,
     all_scopes: Vec<Rc<ScopeNode>>,
+    source: Rc<SourceFile>,
+    line: Option<usize>,                                       // where the node being analyzed starts
+    labels: Vec<Label>,                                        // of the function being analyzed
+    function_labels: Vec<(String, Vec<Label>)>,                // of each function analyzed, in order
+    function_scopes: Vec<(String, Rc<ScopeNode>)>,             // the parameter scope of each, in order
 }
 
 
 
 
+impl Default for ScopeAnalyzer {
+    fn default() -> Self {
+        Self::with_source(Rc::new(SourceFile::default()))
+    }
+}
+
 impl ScopeAnalyzer{
 
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like `new`, with each symbol saying which file it is from
+    pub fn with_source(source: Rc<SourceFile>) -> Self {
         let global_scope = Rc::new(ScopeNode::new(None));
-        let mut all_scopes = Vec::new();
-        all_scopes.push(global_scope.clone());
+        let all_scopes = vec![global_scope.clone()];
 
         ScopeAnalyzer {
             current_scope: global_scope.clone(),
             global_scope,
             errors: Vec::new(),
             all_scopes,
+            source,
+            line: None,
+            labels: Vec::new(),
+            function_labels: Vec::new(),
+            function_scopes: Vec::new(),
         }
     }
 
@@ -116,21 +317,78 @@ impl ScopeAnalyzer{
         }
     }
 
+    /// Run `analyze` with what it declares placed on the line `span`
+    /// starts on; a node made up rather than parsed keeps the line around it
+    fn at(&mut self, span: Option<TokenSpan>, analyze: impl FnOnce(&mut Self)) {
+        let outer = self.line;
+        if let Some(span) = span {
+            self.line = Some(span.line);
+        }
+        analyze(self);
+        self.line = outer;
+    }
+
+    /// Declare `name` in the current scope, on the line of the declaration
+    /// being analyzed
     pub fn declare_symbol(&mut self, name:String, kind: SymbolKind)->Result<(),ScopeError>{
+        let line = self.line;
+
       //check for redefination in current scope_level
-        if self.current_scope.lookup_current_scope(&name).is_some(){
-            let error = match kind{
-                SymbolKind::Function{..}=> ScopeError::FunctionPrototypeRedefinition(name),
-                _=> ScopeError::VariableRedefinition(name),
+        if let Some(existing) = self.current_scope.lookup_current_scope(&name){
+            // Builtins from headers may be redeclared by the program
+            let redeclares_builtin = existing.line.is_none() && existing.scope_level == 0;
+            let error = match (&existing.kind, &kind) {
+                _ if redeclares_builtin => None,
+                (
+                    SymbolKind::Function { return_type, parameters, variadic, is_defined },
+                    SymbolKind::Function {
+                        return_type: new_return_type,
+                        parameters: new_parameters,
+                        variadic: new_variadic,
+                        is_defined: new_is_defined,
+                    },
+                ) => {
+                    let same_signature = return_type == new_return_type
+                        && variadic == new_variadic
+                        && parameters.len() == new_parameters.len()
+                        && parameters.iter().zip(new_parameters).all(|(a, b)| a.param_type == b.param_type);
+                    if !same_signature {
+                        Some(ScopeError::ConflictingFunctionDeclaration { name: name.clone(), line, previous_line: existing.line })
+                    } else if *is_defined && *new_is_defined {
+                        Some(ScopeError::FunctionPrototypeRedefinition { name: name.clone(), line, previous_line: existing.line })
+                    } else if !*new_is_defined {
+                        // Repeated prototype: keep the symbol we already have
+                        return Ok(());
+                    } else {
+                        // Definition completing an earlier prototype
+                        None
+                    }
+                }
+                (_, SymbolKind::Function { .. }) => {
+                    Some(ScopeError::FunctionPrototypeRedefinition { name: name.clone(), line, previous_line: existing.line })
+                }
+                // `extern int x;` and `int x = 1;`, or `int x;` twice at file
+                // scope: declarations of one object, at most one initialized
+                (SymbolKind::Variable { .. }, SymbolKind::Variable { .. }) if redeclares_object(&existing, &kind) => {
+                    if !initializes_first(&existing.kind, &kind) {
+                        return Ok(());
+                    }
+                    None
+                }
+                _ => Some(ScopeError::VariableRedefinition { name: name.clone(), line, previous_line: existing.line }),
             };
-            self.errors.push(error.clone());
-            return Err(error);
+            if let Some(error) = error {
+                self.errors.push(error.clone());
+                return Err(error);
+            }
         }
     
          let symbol=Symbol{
         name:name.clone(),
         kind,
         scope_level:self.current_scope.scope_level,
+        line,
+        file: self.source.id,
         };
 
         self.current_scope.insert_symbol(name,symbol);
@@ -138,6 +396,126 @@ impl ScopeAnalyzer{
 
     }
 
+    /// Declare tag `name` in the current scope, defining it if `complete`.
+    /// A tag can be declared any number of times but only as one kind, so
+    /// `struct node;` is completed by a later `struct node { ... };`. A tag
+    /// defined twice is left to the type checker, which knows both bodies.
+    pub fn declare_tag(&mut self, name: &str, kind: TagKind, complete: bool) -> Result<(), ScopeError> {
+        let line = self.line;
+        let mut tags = self.current_scope.tags.borrow_mut();
+        match tags.get_mut(name) {
+            Some(tag) if tag.kind != kind => {
+                let error = ScopeError::TagKindMismatch {
+                    name: name.to_string(),
+                    kind,
+                    previous_kind: tag.kind,
+                    line,
+                    previous_line: tag.line,
+                };
+                self.errors.push(error.clone());
+                Err(error)
+            }
+            Some(tag) => {
+                if complete && !tag.complete {
+                    (tag.complete, tag.line) = (true, line);
+                }
+                Ok(())
+            }
+            None => {
+                tags.insert(name.to_string(), Tag { name: name.to_string(), kind, complete, line });
+                Ok(())
+            }
+        }
+    }
+
+    /// A type naming tag `name`. One not declared yet is declared by being
+    /// named, incomplete; tags are only defined at file scope here, so it
+    /// goes there too.
+    pub fn reference_tag(&mut self, name: &str, kind: TagKind) -> Result<(), ScopeError> {
+        match self.current_scope.lookup_tag(name) {
+            Some(tag) if tag.kind != kind => {
+                let error = ScopeError::TagKindMismatch {
+                    name: name.to_string(),
+                    kind,
+                    previous_kind: tag.kind,
+                    line: self.line,
+                    previous_line: tag.line,
+                };
+                self.errors.push(error.clone());
+                Err(error)
+            }
+            Some(_) => Ok(()),
+            None => {
+                let tag = Tag { name: name.to_string(), kind, complete: false, line: self.line };
+                self.global_scope.tags.borrow_mut().insert(name.to_string(), tag);
+                Ok(())
+            }
+        }
+    }
+
+    /// Reference the tag a type spelled out names, if it names one
+    fn reference_tag_in(&mut self, spelling: &str) {
+        if let Some((kind, name)) = TagKind::named(spelling) {
+            let _ = self.reference_tag(name, kind);
+        }
+    }
+
+    /// Define a label of the function being analyzed
+    pub fn declare_label(&mut self, name: &str) -> Result<(), ScopeError> {
+        let line = self.line;
+        match self.labels.iter_mut().find(|label| label.name == name) {
+            Some(label) if label.defined => {
+                let error = ScopeError::LabelRedefinition { name: name.to_string(), line, previous_line: label.line };
+                self.errors.push(error.clone());
+                Err(error)
+            }
+            // Gone to before it was defined
+            Some(label) => {
+                (label.line, label.defined) = (line, true);
+                Ok(())
+            }
+            None => {
+                self.labels.push(Label { name: name.to_string(), line, defined: true, gotos: Vec::new() });
+                Ok(())
+            }
+        }
+    }
+
+    /// A goto to a label of the function being analyzed, which may be
+    /// defined before or after it
+    pub fn reference_label(&mut self, name: &str) {
+        let line = self.line;
+        match self.labels.iter_mut().find(|label| label.name == name) {
+            Some(label) => label.gotos.push(line),
+            None => self.labels.push(Label { name: name.to_string(), line: None, defined: false, gotos: vec![line] }),
+        }
+    }
+
+    /// Close the labels of `function`, whose body has been analyzed: each
+    /// one gone to must be defined somewhere in it
+    fn finish_labels(&mut self, function: &str) {
+        let labels = std::mem::take(&mut self.labels);
+        for label in labels.iter().filter(|label| !label.defined) {
+            let line = label.gotos.first().copied().flatten();
+            self.errors.push(ScopeError::UndefinedLabel { name: label.name.clone(), line });
+        }
+        self.function_labels.push((function.to_string(), labels));
+    }
+
+    /// The labels of a function analyzed, defined or only gone to
+    pub fn labels(&self, function: &str) -> &[Label] {
+        self.function_labels
+            .iter()
+            .find(|(name, _)| name == function)
+            .map_or(&[], |(_, labels)| labels.as_slice())
+    }
+
+    /// The scope of the `nth` definition analyzed of a function, holding
+    /// its parameters and the declarations at the top of its body
+    pub fn function_scope(&self, function: &str, nth: usize) -> Option<&Rc<ScopeNode>> {
+        self.function_scopes.iter().filter(|(name, _)| name == function).nth(nth).map(|(_, scope)| scope)
+    }
+
     pub fn lookup_symbol(&self, name: &str) -> Option<Symbol> {
         self.current_scope.lookup(name)
     }
@@ -159,7 +537,7 @@ impl ScopeAnalyzer{
         match self.lookup_symbol(name) {
             Some(_symbol) => Ok(()),
             None => {
-                let error = ScopeError::UndeclaredVariable(name.to_string());
+                let error = ScopeError::UndeclaredVariable { name: name.to_string(), line: self.line };
                 self.errors.push(error.clone());
                 Err(error)
             }
@@ -171,14 +549,23 @@ impl ScopeAnalyzer{
         match self.lookup_symbol(name) {
             Some(symbol) => match &symbol.kind {
                 SymbolKind::Function { .. } => Ok(()),
+                // A pointer to a function, spelled like "int (*)(int)"
+                SymbolKind::Parameter { param_type } if param_type.contains("(*") => Ok(()),
+                SymbolKind::Variable { pointer_depth: 1, pointee: Some(pointee), .. }
+                    if matches!(pointee.derivations.first(), Some(Derivation::Function(..))) =>
+                {
+                    Ok(())
+                }
                 _ => {
-                    let error = ScopeError::UndefinedFunctionCalled(name.to_string());
+                    let error = ScopeError::UndefinedFunctionCalled { name: name.to_string(), line: self.line };
                     self.errors.push(error.clone());
                     Err(error)
                 }
             },
+            // Intrinsics need no declaration
+            None if intrinsics::is_intrinsic(name) => Ok(()),
             None => {
-                let error = ScopeError::UndefinedFunctionCalled(name.to_string());
+                let error = ScopeError::UndefinedFunctionCalled { name: name.to_string(), line: self.line };
                 self.errors.push(error.clone());
                 Err(error)
             }
@@ -201,27 +588,77 @@ impl ScopeAnalyzer{
     }
 
     fn add_builtin_functions_from_includes(&mut self, preprocessor_list: &[PreprocessorDirective]) {
-        // Check if stdio.h is included
-        let has_stdio = preprocessor_list.iter().any(|directive| {
-            if let PreprocessorDirective::Include(header) = directive {
-                header.contains("stdio.h")
-            } else {
-                false
-            }
-        });
-
-        if has_stdio {
-            // Add printf as a built-in function when stdio.h is included
-            let printf_symbol = SymbolKind::Function {
-                return_type: "int".to_string(),
-                parameters: vec![], // Variadic function - simplified
-                is_defined: true,
-            };
-            let _ = self.declare_symbol("printf".to_string(), printf_symbol);
+        let includes = |name: &str| {
+            preprocessor_list.iter().any(|directive| {
+                if let PreprocessorDirective::Include(header) = directive {
+                    header.contains(name)
+                } else {
+                    false
+                }
+            })
+        };
+
+        if includes("stdio.h") {
+            self.add_variadic_builtin("printf", "int", &[("const char*", "format")]);
+            self.add_builtin("puts", "int", &[("const char*", "s")]);
+            self.add_builtin("putchar", "int", &[("int", "c")]);
+        }
+
+        // The heap, and leaving the program, which the interpreter runs
+        if includes("stdlib.h") {
+            self.add_builtin("malloc", "void*", &[("long", "size")]);
+            self.add_builtin("calloc", "void*", &[("long", "count"), ("long", "size")]);
+            self.add_builtin("realloc", "void*", &[("void*", "pointer"), ("long", "size")]);
+            self.add_builtin("free", "void", &[("void*", "pointer")]);
+            self.add_builtin("exit", "void", &[("int", "status")]);
+            self.add_builtin("abort", "void", &[]);
+        }
+
+        // Doubles in and out, all of them; the interpreter computes them
+        if includes("math.h") {
+            for (name, arity) in math::FUNCTIONS {
+                self.add_builtin(name, "double", &[("double", "x"), ("double", "y")][..arity]);
+            }
         }
     }
 
+    /// Declare a library function, with its parameters as (type, name)
+    fn add_builtin(&mut self, name: &str, return_type: &str, parameters: &[(&str, &str)]) {
+        self.declare_builtin(name, return_type, parameters, false);
+    }
+
+    /// Declare a library function that takes more arguments after its
+    /// parameters, as `printf(const char *format, ...)` does
+    fn add_variadic_builtin(&mut self, name: &str, return_type: &str, parameters: &[(&str, &str)]) {
+        self.declare_builtin(name, return_type, parameters, true);
+    }
+
+    fn declare_builtin(&mut self, name: &str, return_type: &str, parameters: &[(&str, &str)], variadic: bool) {
+        let parameters = parameters
+            .iter()
+            .map(|(param_type, name)| Parameter {
+                param_type: param_type.to_string(),
+                name: name.to_string(),
+                type_qualifiers: Vec::new(),
+            })
+            .collect();
+        let kind = SymbolKind::Function { return_type: return_type.to_string(), parameters, variadic, is_defined: true };
+        self.current_scope.insert_symbol(
+            name.to_string(),
+            Symbol { name: name.to_string(), kind, scope_level: 0, line: None, file: self.source.id },
+        );
+    }
+
     fn analyze_external_declaration(&mut self, decl: &ExternalDeclaration) {
+        // A function is declared where its name is
+        let span = match decl {
+            ExternalDeclaration::Function(definition) => definition.name_span,
+            _ => decl.span(),
+        };
+        self.at(span, |analyzer| analyzer.analyze_external_declaration_node(decl));
+    }
+
+    fn analyze_external_declaration_node(&mut self, decl: &ExternalDeclaration) {
         match decl {
             ExternalDeclaration::Variable(var_decl) => {
                 self.analyze_variable_declaration(var_decl);
@@ -232,40 +669,95 @@ impl ScopeAnalyzer{
             ExternalDeclaration::FunctionDeclaration(func_decl) => {
                 self.analyze_function_declaration(func_decl);
             }
+            ExternalDeclaration::Struct(definition) => {
+                // In scope from its own brace, so members can point to one
+                let _ = self.declare_tag(&definition.name, TagKind::Struct, true);
+                // Members aren't ordinary identifiers; only their types and sizes refer to any
+                for member in &definition.members {
+                    self.at(member.span, |analyzer| {
+                        if let Some((kind, tag)) = TagKind::of(&member.type_specifier) {
+                            let _ = analyzer.reference_tag(tag, kind);
+                        }
+                        for size in member.declarator.array_sizes.iter().flatten() {
+                            analyzer.analyze_expression(size);
+                        }
+                    });
+                }
+            }
+            ExternalDeclaration::StructDeclaration(tag, _) => {
+                let _ = self.declare_tag(tag, TagKind::Struct, false);
+            }
+            ExternalDeclaration::Enum(definition) => {
+                if let Some(tag) = &definition.name {
+                    let _ = self.declare_tag(tag, TagKind::Enum, true);
+                }
+                // Each enumerator is in scope from its own value onwards
+                for enumerator in &definition.enumerators {
+                    self.at(enumerator.span, |analyzer| {
+                        if let Some(value) = &enumerator.value {
+                            analyzer.analyze_expression(value);
+                        }
+                        let kind = SymbolKind::Enumerator { tag: definition.name.clone() };
+                        let _ = analyzer.declare_symbol(enumerator.name.clone(), kind);
+                    });
+                }
+            }
         }
     }
     fn analyze_variable_declaration(&mut self, var_decl: &VariableDeclaration) {
+        if let Some((kind, tag)) = TagKind::of(&var_decl.type_specifier) {
+            let _ = self.reference_tag(tag, kind);
+        }
+        let pointee = var_decl.declarator.pointee_type(&var_decl.type_specifier);
+        let pointee_sizes = pointee.iter().flat_map(TypeName::expressions);
+        for size in var_decl.declarator.array_sizes.iter().flatten().chain(pointee_sizes) {
+            self.analyze_expression(size);
+        }
         let symbol_kind = SymbolKind::Variable {
             type_spec: var_decl.type_specifier.clone(),
+            pointer_depth: var_decl.declarator.pointer_depth,
             storage_class: var_decl.storage_class.clone(),
+            array_dimensions: var_decl.declarator.array_sizes.len(),
+            variable_length: var_decl.declarator.is_variable_length(),
+            initialized: var_decl.initializer.is_some(),
+            pointee,
         };
         if let Err(_) = self.declare_symbol(var_decl.declarator.name.clone(), symbol_kind) {
             // Error already recorded
         }
         if let Some(initializer) = &var_decl.initializer {
-            match &initializer.kind {
-                InitializerKind::Assignment(expr) => {
-                    self.analyze_expression(expr);
-                }
-                InitializerKind::List(initializers) => {
-                    for init in initializers {
-                        if let InitializerKind::Assignment(expr) = &init.kind {
-                            self.analyze_expression(expr);
-                        }
-                    }
+            self.analyze_initializer(initializer);
+        }
+    }
+
+    fn analyze_initializer(&mut self, initializer: &Initializer) {
+        match &initializer.kind {
+            InitializerKind::Assignment(expr) => {
+                self.analyze_expression(expr);
+            }
+            InitializerKind::List(initializers) => {
+                for init in initializers {
+                    self.analyze_initializer(init);
                 }
-                InitializerKind::Designated(_designator, init) => {
-                    if let InitializerKind::Assignment(expr) = &init.kind {
-                        self.analyze_expression(expr);
-                    }
+            }
+            InitializerKind::Designated(designator, init) => {
+                if let Designator::Array(index) = designator {
+                    self.analyze_expression(index);
                 }
+                self.analyze_initializer(init);
             }
         }
-    } 
+    }
+
     fn analyze_function_declaration(&mut self, func_decl: &FunctionDeclaration) {
+        self.reference_tag_in(&func_decl.return_type);
+        for param in &func_decl.parameters {
+            self.reference_tag_in(&param.param_type);
+        }
         let symbol_kind = SymbolKind::Function {
             return_type: func_decl.return_type.clone(),
             parameters: func_decl.parameters.clone(),
+            variadic: func_decl.variadic,
             is_defined: false,
         };
 
@@ -274,10 +766,14 @@ impl ScopeAnalyzer{
         }
     }
     fn analyze_function_definition(&mut self, func_def: &FunctionDefinition) {
-        
+        self.reference_tag_in(&func_def.return_type);
+        for param in &func_def.parameters {
+            self.reference_tag_in(&param.param_type);
+        }
         let symbol_kind = SymbolKind::Function {
             return_type: func_def.return_type.clone(),
             parameters: func_def.parameters.clone(),
+            variadic: func_def.variadic,
             is_defined: true,
         };
 
@@ -295,8 +791,8 @@ impl ScopeAnalyzer{
 
       
         self.enter_scope();
+        self.function_scopes.push((func_def.name.clone(), self.current_scope.clone()));
 
-        
         for param in &func_def.parameters {
             let param_kind = SymbolKind::Parameter {
                 param_type: param.param_type.clone(),
@@ -310,6 +806,7 @@ impl ScopeAnalyzer{
         for stmt in &func_def.body {
             self.analyze_statement(stmt);
         }
+        self.finish_labels(&func_def.name);
 
         // Exit function scope
         self.exit_scope();
@@ -322,9 +819,14 @@ impl ScopeAnalyzer{
                     // Error already recorded
                 }
             }
-            Expression::FunctionCall(name, args) => {
-                if let Err(_) = self.check_function_call(name) {
-                    // Error already recorded
+            Expression::FunctionCall(callee, args) => {
+                if let Some(name) = callee.as_function_name() {
+                    if let Err(_) = self.check_function_call(name) {
+                        // Error already recorded
+                    }
+                } else {
+                    // Calls through pointers, array elements, etc.
+                    self.analyze_expression(callee);
                 }
                 for arg in args {
                     self.analyze_expression(arg);
@@ -359,53 +861,101 @@ impl ScopeAnalyzer{
             Expression::PostfixOp(expr, _op) => {
                 self.analyze_expression(expr);
             }
-            Expression::Cast(_type, expr) => {
+            Expression::Cast(target, expr) => {
+                self.reference_tag_in(&target.specifier);
+                for size in target.expressions() {
+                    self.analyze_expression(size);
+                }
                 self.analyze_expression(expr);
             }
-            Expression::Constant(_) | Expression::StringLiteral(_) => {
+            Expression::SizeofType(operand) => {
+                self.reference_tag_in(&operand.specifier);
+                for size in operand.expressions() {
+                    self.analyze_expression(size);
+                }
+            }
+            Expression::Paren(expr) => {
+                self.analyze_expression(expr);
+            }
+            Expression::CompoundLiteral(literal) => {
+                self.reference_tag_in(&literal.type_name);
+                for size in literal.array_sizes.iter().flatten() {
+                    self.analyze_expression(size);
+                }
+                for item in &literal.initializers {
+                    self.analyze_initializer(item);
+                }
+            }
+            Expression::StatementExpr(statements) => {
+                // Declarations inside ({ ... }) are local to it, like a block
+                self.enter_scope();
+                for stmt in statements {
+                    self.analyze_statement(stmt);
+                }
+                self.exit_scope();
+            }
+            Expression::Generic(controlling, associations) => {
+                self.analyze_expression(controlling);
+                for association in associations {
+                    self.analyze_expression(&association.value);
+                }
+            }
+            Expression::Constant(_)
+            | Expression::Folded(..)
+            | Expression::StringLiteral(_)
+            | Expression::EncodedStringLiteral(..) => {
                 // No scope analysis needed for literals
             }
         }
     }
     fn analyze_statement(&mut self, stmt: &Statement) {
-        match stmt {
-            Statement::Declaration(var_decl) => {
+        self.at(stmt.span, |analyzer| analyzer.analyze_statement_node(stmt));
+    }
+
+    fn analyze_statement_node(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::Declaration(var_decl) => {
                 self.analyze_variable_declaration(var_decl);
             }
-            Statement::Assignment(var_name, expr) => {
+            StatementKind::Assignment(var_name, expr) => {
                 // Check if variable exists
                 if let Err(_) = self.check_variable_access(var_name) {
                     // Error already recorded
                 }
                 self.analyze_expression(expr);
             }
-            Statement::Return(expr_opt) => {
+            StatementKind::Return(expr_opt) => {
                 if let Some(expr) = expr_opt {
                     self.analyze_expression(expr);
                 }
             }
-            Statement::Expression(expr) => {
+            StatementKind::Expression(expr) => {
                 self.analyze_expression(expr);
             }
-            Statement::Block(statements) => {
+            StatementKind::Block(statements) => {
                 self.enter_scope();
                 for stmt in statements {
                     self.analyze_statement(stmt);
                 }
                 self.exit_scope();
             }
-            Statement::If(condition, then_stmt, else_stmt) => {
+            StatementKind::If(condition, then_stmt, else_stmt) => {
                 self.analyze_expression(condition);
                 self.analyze_statement(then_stmt);
                 if let Some(else_stmt) = else_stmt {
                     self.analyze_statement(else_stmt);
                 }
             }
-            Statement::While(condition, body) => {
+            StatementKind::While(condition, body)
+            | StatementKind::Switch(condition, body)
+            | StatementKind::Case(condition, body) => {
                 self.analyze_expression(condition);
                 self.analyze_statement(body);
             }
-            Statement::For(init, condition, update, body) => {
+            StatementKind::Default(body) => {
+                self.analyze_statement(body);
+            }
+            StatementKind::For(init, condition, update, body) => {
                 self.enter_scope(); // For loop creates its own scope
                 if let Some(init) = init {
                     self.analyze_statement(init);
@@ -419,7 +969,12 @@ impl ScopeAnalyzer{
                 self.analyze_statement(body);
                 self.exit_scope();
             }
-            Statement::Break => {
+            StatementKind::Label(name, body) => {
+                let _ = self.declare_label(name);
+                self.analyze_statement(body);
+            }
+            StatementKind::Goto(name) => self.reference_label(name),
+            StatementKind::Break | StatementKind::Fallthrough | StatementKind::Empty => {
                 // No scope analysis needed
             }
         }
@@ -434,8 +989,14 @@ impl ScopeAnalyzer{
 
     pub fn print_symbol_table(&self) {
         println!("--- Symbol Table (All Scopes) ---");
+        for line in self.symbol_table_lines() {
+            println!("{}", line);
+        }
+    }
 
-       
+    /// The symbol table as printable lines, one scope heading per non-empty scope
+    pub fn symbol_table_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
         for scope in &self.all_scopes {
             let scope_name = match scope.scope_level {
                 0 => "Global".to_string(),
@@ -443,43 +1004,80 @@ impl ScopeAnalyzer{
                 level => format!("Block-{}", level - 1),
             };
 
-            self.print_scope_symbols(scope, &scope_name);
+            self.scope_symbol_lines(scope, &scope_name, &mut lines);
+        }
+        for (function, labels) in self.function_labels.iter().filter(|(_, labels)| !labels.is_empty()) {
+            lines.push(format!("Labels of {}:", function));
+            for label in labels {
+                match (label.defined, label.line) {
+                    (true, Some(line)) => lines.push(format!("  Label: {} (line {})", label.name, line)),
+                    (true, None) => lines.push(format!("  Label: {}", label.name)),
+                    (false, _) => lines.push(format!("  Label: {} (undefined)", label.name)),
+                }
+            }
+            lines.push(String::new());
         }
+        lines
     }
 
-    fn print_scope_symbols(&self, scope: &ScopeNode, scope_name: &str) {
+    fn scope_symbol_lines(&self, scope: &ScopeNode, scope_name: &str, lines: &mut Vec<String>) {
         let symbols = scope.symbols.borrow();
-        if !symbols.is_empty() {
-            println!("{} Scope (Level {}):", scope_name, scope.scope_level);
+        let tags = scope.tags.borrow();
+        if !symbols.is_empty() || !tags.is_empty() {
+            lines.push(format!("{} Scope (Level {}):", scope_name, scope.scope_level));
+            for tag in tags.values() {
+                let state = if tag.complete { "" } else { " (incomplete)" };
+                lines.push(format!("  Tag: {} {}{}", tag.kind.keyword(), tag.name, state));
+            }
             for (name, symbol) in symbols.iter() {
                 match &symbol.kind {
-                    SymbolKind::Variable { type_spec, .. } => {
-                        println!("  Variable: {} : {:?}", name, type_spec);
+                    SymbolKind::Variable { type_spec, pointer_depth, array_dimensions, variable_length, pointee, .. } => {
+                        let type_name = match type_spec {
+                            TypeSpecifier::Struct(tag) => format!("struct {}", tag),
+                            TypeSpecifier::Enum(tag) => format!("enum {}", tag),
+                            other => format!("{:?}", other),
+                        };
+                        // As a cast spells it, with the array lengths left out
+                        let arrays = (0..*array_dimensions).map(|_| Derivation::Array(None));
+                        let pointers = (0..*pointer_depth).map(|_| Derivation::Pointer);
+                        let pointed = pointee.iter().flat_map(|pointee| pointee.derivations.iter().cloned());
+                        let derivations = arrays.chain(pointers).chain(pointed).collect();
+                        lines.push(format!(
+                            "  Variable: {} : {}{}",
+                            name,
+                            TypeName { specifier: type_name, derivations }.spelling(),
+                            if *variable_length { " (variable length)" } else { "" }
+                        ));
                     }
                     SymbolKind::Function {
                         return_type,
                         parameters,
+                        variadic,
                         is_defined,
                     } => {
-                        let param_types: Vec<String> =
+                        let mut param_types: Vec<String> =
                             parameters.iter().map(|p| p.param_type.clone()).collect();
-                        println!(
+                        if *variadic {
+                            param_types.push("...".to_string());
+                        }
+                        lines.push(format!(
                             "  Function: {} : ({}) -> {} (defined: {})",
                             name,
                             param_types.join(", "),
                             return_type,
                             is_defined
-                        );
+                        ));
                     }
                     SymbolKind::Parameter { param_type } => {
-                        println!("  Parameter: {} : {}", name, param_type);
+                        lines.push(format!("  Parameter: {} : {}", name, param_type));
+                    }
+                    SymbolKind::Enumerator { tag } => {
+                        let tag = tag.as_deref().unwrap_or("(anonymous)");
+                        lines.push(format!("  Enumerator: {} : enum {}", name, tag));
                     }
                 }
             }
-            println!();
+            lines.push(String::new());
         }
     }
-
-
-
 }
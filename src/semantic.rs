@@ -0,0 +1,259 @@
+// semantic.rs: Questions about what is at a place in a program, for
+// editors: hover text and tooltips.
+//
+// The AST doesn't remember where things came from, so the expression at a
+// position is found again from the tokens: the shortest run of tokens around
+// the position that parses as an expression and is part of the expression
+// the statement holds there. Names in it are resolved to their declarations
+// with the same block scoping renaming uses (refactor.rs), and the type
+// checker works out the type with just those declarations in scope.
+
+use crate::lexer_regex::{self, Token};
+use crate::parser::ast::*;
+use crate::parser::{self, Parser};
+use crate::refactor::declarations;
+use crate::scope::{ScopeAnalyzer, Symbol, SymbolKind};
+use crate::source::SourceFile;
+use crate::type_checker::TypeChecker;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Tokens of a statement looked at around the position, on each side; runs
+/// of them are parsed over and over, so a huge initializer is cut short
+const MAX_REACH: usize = 64;
+
+/// The innermost expression at a position and its type
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionType {
+    pub span: Range<usize>, // byte range of the expression in the source
+    pub line: usize,        // where it starts, 1-based
+    pub column: usize,      // 1-based, in characters
+    pub text: String,       // as written
+    pub ty: String,         // e.g. "int", "struct point*"
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeAtError {
+    Io(String),
+    Parse(String),
+    NoExpressionAt { line: usize, column: usize },
+    UnknownType(String),
+}
+
+impl fmt::Display for TypeAtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeAtError::Io(message) | TypeAtError::Parse(message) => write!(f, "{}", message),
+            TypeAtError::NoExpressionAt { line, column } => write!(f, "no expression at {}:{}", line, column),
+            TypeAtError::UnknownType(text) => write!(f, "can't tell the type of '{}'", text),
+        }
+    }
+}
+
+/// The type of the innermost expression at `line`:`column` of a file
+pub fn type_at(file: &str, line: usize, column: usize) -> Result<ExpressionType, TypeAtError> {
+    let source = fs::read_to_string(file).map_err(|error| TypeAtError::Io(format!("{}: {}", file, error)))?;
+    type_at_in_source(&source, line, column)
+}
+
+/// type_at on source text, as an editor holds it before saving
+pub fn type_at_in_source(source: &str, line: usize, column: usize) -> Result<ExpressionType, TypeAtError> {
+    let mut parser = Parser::with_spans(lexer_regex::lex_with_regex_positions(source, Default::default()));
+    let unit = parser.parse().map_err(|errors| {
+        TypeAtError::Parse(errors.iter().map(|error| format!("Parse Error: {:?}", error)).collect::<Vec<_>>().join("\n"))
+    })?;
+    type_at_in_unit(source, &unit, line, column)
+}
+
+/// type_at on source text already parsed to `unit`
+pub fn type_at_in_unit(source: &str, unit: &TranslationUnit, line: usize, column: usize) -> Result<ExpressionType, TypeAtError> {
+    let not_found = || TypeAtError::NoExpressionAt { line, column };
+    let spanned: Vec<(Token, Range<usize>)> = lexer_regex::lex_with_regex_spanned(source);
+    let file = Rc::new(SourceFile::new("", source));
+    let cursor = file.offset_of(line, column).ok_or_else(not_found)?;
+    // Comments aren't part of any expression
+    let code: Vec<usize> = (0..spanned.len())
+        .filter(|&index| !matches!(spanned[index].0, Token::Comment(_) | Token::BlockComment(_) | Token::Pragma(_)))
+        .collect();
+    let at = code.iter().position(|&index| spanned[index].1.contains(&cursor)).ok_or_else(not_found)?;
+    let (first, last) = statement_around(&spanned, &code, at);
+    // The parser gives up quietly on an unclosed call or subscript, taking
+    // `a[` for `a`, so only balanced runs are tried
+    let parse = |from: usize, to: usize| {
+        let tokens: Vec<Token> = code[from..=to].iter().map(|&index| spanned[index].0.clone()).collect();
+        balanced(&tokens).then(|| parser::parse_expression_tokens(tokens)).flatten()
+    };
+
+    // The whole expression there, then the smallest part of it around the position
+    let whole = (first..=at)
+        .flat_map(|from| (at..=last).rev().map(move |to| (from, to)))
+        .find_map(|(from, to)| parse(from, to))
+        .ok_or_else(not_found)?;
+    let mut parts = Vec::new();
+    subexpressions(&whole, &mut parts);
+    let parts: HashSet<String> = parts.iter().map(|part| format!("{:?}", part)).collect();
+    let mut ranges: Vec<(usize, usize)> = (first..=at).flat_map(|from| (at..=last).map(move |to| (from, to))).collect();
+    ranges.sort_by_key(|&(from, to)| to - from);
+    let (expression, from, to) = ranges
+        .into_iter()
+        .find_map(|(from, to)| {
+            let part = parse(from, to).map(|part| (format!("{:?}", part), part))?;
+            // Nor does it mind a member access without the member
+            let ignores_last = to > from && parse(from, to - 1).is_some_and(|shorter| format!("{:?}", shorter) == part.0);
+            (parts.contains(&part.0) && !ignores_last).then_some((part.1, from, to))
+        })
+        .ok_or_else(not_found)?;
+
+    let span = spanned[code[from]].1.start..spanned[code[to]].1.end;
+    let text = source[span.clone()].to_string();
+    let ty = expression_type(&file, unit, &spanned, &code[from..=to], &expression)
+        .ok_or_else(|| TypeAtError::UnknownType(text.clone()))?;
+    let (line, column) = file.line_column(span.start);
+    Ok(ExpressionType { span, line, column, text, ty })
+}
+
+/// The first and last of `code` in the statement (or clause of a for
+/// loop's header) around `at`, no further than MAX_REACH either side
+fn statement_around(spanned: &[(Token, Range<usize>)], code: &[usize], at: usize) -> (usize, usize) {
+    let boundary = |position: usize| matches!(spanned[code[position]].0, Token::Semicolon | Token::BraceL | Token::BraceR);
+    let mut first = at;
+    while first > 0 && at - first < MAX_REACH && !boundary(first - 1) {
+        first -= 1;
+    }
+    let mut last = at;
+    while last + 1 < code.len() && last - at < MAX_REACH && !boundary(last + 1) {
+        last += 1;
+    }
+    (first, last)
+}
+
+fn balanced(tokens: &[Token]) -> bool {
+    let mut depth: usize = 0;
+    for token in tokens {
+        match token {
+            Token::ParenL | Token::BracketL => depth += 1,
+            Token::ParenR | Token::BracketR => match depth.checked_sub(1) {
+                Some(outer) => depth = outer,
+                None => return false,
+            },
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+/// The type of `expression`, made of the tokens `tokens`, with the
+/// declarations its names resolve to in scope
+fn expression_type(
+    file: &Rc<SourceFile>,
+    unit: &TranslationUnit,
+    spanned: &[(Token, Range<usize>)],
+    tokens: &[usize],
+    expression: &Expression,
+) -> Option<String> {
+    let mut scope_analyzer = ScopeAnalyzer::with_source(file.clone());
+    let _ = scope_analyzer.analyze_translation_unit(unit);
+    let global = scope_analyzer.get_global_scope().clone();
+    let candidates: Vec<Symbol> = scope_analyzer
+        .get_all_scopes()
+        .iter()
+        .filter(|scope| !std::rc::Rc::ptr_eq(scope, &global))
+        .flat_map(|scope| scope.symbols.borrow().values().cloned().collect::<Vec<_>>())
+        .filter(|symbol| matches!(symbol.kind, SymbolKind::Variable { .. } | SymbolKind::Parameter { .. }))
+        .collect();
+
+    // Each local the expression uses: the symbol declared on the line its
+    // declaration is on, or failing that the last one declared before. The
+    // resolver doesn't know struct types, so it misses `struct point p;`.
+    let declared = declarations(spanned);
+    let mut locals: Vec<Symbol> = Vec::new();
+    for &index in tokens {
+        let Token::Identifier(name) = &spanned[index].0 else {
+            continue;
+        };
+        let Some(declaration) = declared.get(&index) else {
+            continue; // a member
+        };
+        let line = |index: usize| file.index().line(spanned[index].1.start);
+        let declared_on = declaration.map(line);
+        if global.lookup_current_scope(name).is_some_and(|symbol| declared_on.is_some() && symbol.line == declared_on) {
+            continue; // the globals are in scope already
+        }
+        let before = declared_on.unwrap_or_else(|| line(index));
+        let named: Vec<&Symbol> = candidates.iter().filter(|symbol| &symbol.name == name).collect();
+        let symbol = named
+            .iter()
+            .find(|symbol| declared_on.is_some() && symbol.line == declared_on)
+            .or_else(|| named.iter().filter(|symbol| symbol.line.is_some_and(|at| at <= before)).max_by_key(|symbol| symbol.line))
+            .or_else(|| named.first().filter(|_| named.len() == 1));
+        if let Some(symbol) = symbol.filter(|symbol| !locals.iter().any(|local| local.name == symbol.name)) {
+            locals.push((*symbol).clone());
+        }
+    }
+
+    // A function named on its own, as in a call, shows its signature
+    if let Expression::Identifier(name) = expression
+        && locals.is_empty()
+        && let Some(SymbolKind::Function { return_type, parameters, variadic, .. }) = global.lookup(name).map(|symbol| symbol.kind)
+    {
+        let mut parameters: Vec<&str> = parameters.iter().map(|parameter| parameter.param_type.as_str()).collect();
+        if variadic {
+            parameters.push("...");
+        }
+        return Some(format!("{} ({})", return_type, parameters.join(", ")));
+    }
+
+    let mut type_checker = TypeChecker::new(scope_analyzer);
+    type_checker.set_require_main(false);
+    let _ = type_checker.check_translation_unit(unit);
+    type_checker.expression_type(expression, locals).map(|ty| ty.to_string())
+}
+
+/// An expression and all those inside it
+fn subexpressions<'a>(expression: &'a Expression, out: &mut Vec<&'a Expression>) {
+    out.push(expression);
+    match expression {
+        Expression::BinaryOp(left, _, right) | Expression::Assignment(left, _, right) | Expression::ArrayAccess(left, right) => {
+            subexpressions(left, out);
+            subexpressions(right, out);
+        }
+        Expression::UnaryOp(_, operand)
+        | Expression::PostfixOp(operand, _)
+        | Expression::Cast(_, operand)
+        | Expression::Paren(operand)
+        | Expression::MemberAccess(operand, _)
+        | Expression::PointerAccess(operand, _) => subexpressions(operand, out),
+        Expression::Conditional(condition, then_value, else_value) => {
+            subexpressions(condition, out);
+            subexpressions(then_value, out);
+            subexpressions(else_value, out);
+        }
+        Expression::FunctionCall(callee, arguments) => {
+            subexpressions(callee, out);
+            for argument in arguments {
+                subexpressions(argument, out);
+            }
+        }
+        Expression::CompoundLiteral(literal) => {
+            for expression in literal.initializers.iter().flat_map(Initializer::expressions) {
+                subexpressions(expression, out);
+            }
+        }
+        Expression::Generic(controlling, associations) => {
+            subexpressions(controlling, out);
+            for association in associations {
+                subexpressions(&association.value, out);
+            }
+        }
+        Expression::Identifier(_)
+        | Expression::Constant(_)
+        | Expression::Folded(..)
+        | Expression::StringLiteral(_)
+        | Expression::EncodedStringLiteral(..)
+        | Expression::SizeofType(_)
+        | Expression::StatementExpr(_) => {}
+    }
+}
@@ -0,0 +1,270 @@
+// semantic_tokens.rs: `--emit=semantic-tokens`, the source's tokens
+// classified for an editor, as the data of an LSP semanticTokens response.
+//
+// Keywords, literals, comments and operators are classified by the regex
+// lexer alone. Identifiers need more: each one is resolved to the token
+// that declared it (refactor.rs does this with C's block scoping), and that
+// declaration is classified by what scope analysis recorded for the name on
+// its line, a function, a parameter, a variable or an enumerator. A name
+// after `#define` is a macro wherever it is used, one after `struct`,
+// `union` or `enum` is a type, and one declared in a struct's braces or
+// used after `.` or `->` a member. Builtins, the functions a header
+// declares, are marked as the default library's.
+//
+// The output is one JSON object: the legend of token types and modifiers,
+// and `data`, five numbers per token as LSP encodes them: the line and
+// start relative to the previous token, the length, the type's index in
+// the legend and the modifiers as bits. Lines count from 0 and columns in
+// UTF-16 code units, as LSP's default position encoding does; a token over
+// several lines, like a block comment, is given line by line.
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer_regex::{self, Token};
+use crate::parser::ast::TranslationUnit;
+use crate::plugins::EmitTarget;
+use crate::refactor;
+use crate::scope::{ScopeAnalyzer, Symbol, SymbolKind};
+use crate::source::SourceFile;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::rc::Rc;
+
+pub const TOKEN_TYPES: [&str; 14] = [
+    "function",
+    "parameter",
+    "variable",
+    "enumMember",
+    "property",
+    "type",
+    "struct",
+    "enum",
+    "macro",
+    "keyword",
+    "comment",
+    "string",
+    "number",
+    "operator",
+];
+
+pub const TOKEN_MODIFIERS: [&str; 2] = ["declaration", "defaultLibrary"];
+
+const DECLARATION: u32 = 1;
+const DEFAULT_LIBRARY: u32 = 1 << 1;
+
+/// What an editor is told a token is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Classification {
+    pub token_type: &'static str,
+    pub modifiers: u32,
+}
+
+impl Classification {
+    fn new(token_type: &'static str) -> Self {
+        Classification { token_type, modifiers: 0 }
+    }
+
+    fn with(self, modifier: u32) -> Self {
+        Classification { modifiers: self.modifiers | modifier, ..self }
+    }
+}
+
+/// Each token of `source`, parsed as `unit`, that has a classification,
+/// with its byte range
+pub fn classify(unit: &TranslationUnit, source: &str) -> Vec<(Range<usize>, Classification)> {
+    let tokens = lexer_regex::lex_with_regex_spanned(source);
+    let declared_by = refactor::declarations(&tokens);
+    let symbols = symbols(unit, source);
+    let macros: HashSet<&str> = tokens
+        .windows(2)
+        .filter_map(|pair| match (&pair[0].0, &pair[1].0) {
+            (Token::Preprocessor(directive), Token::Identifier(name)) if directive == "#define" => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let line_starts = line_starts(source);
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset);
+
+    let mut classified = Vec::new();
+    // The brace depth inside each struct or union body open here, whose
+    // names are members
+    let (mut depth, mut member_lists) = (0usize, Vec::new());
+    // Where the header name of an `#include <...>` ends
+    let mut header_end = 0;
+    for (index, (token, span)) in tokens.iter().enumerate() {
+        if span.start < header_end {
+            classified.push((span.clone(), Classification::new("string")));
+            continue;
+        }
+        if matches!(token, Token::Preprocessor(directive) if directive == "#include") {
+            header_end = source[span.end..].find('\n').map_or(source.len(), |newline| span.end + newline);
+        }
+        let before = index.checked_sub(1).map(|before| &tokens[before].0);
+        match token {
+            Token::BraceL => {
+                depth += 1;
+                let tagged = matches!(index.checked_sub(2).map(|before| &tokens[before].0), Some(Token::Struct | Token::Union))
+                    && matches!(before, Some(Token::Identifier(_)));
+                if tagged || matches!(before, Some(Token::Struct | Token::Union)) {
+                    member_lists.push(depth);
+                }
+            }
+            Token::BraceR => {
+                if member_lists.last() == Some(&depth) {
+                    member_lists.pop();
+                }
+                depth = depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+        let classification = match token {
+            Token::Identifier(name) => {
+                match before {
+                    _ if macros.contains(name.as_str()) => {
+                        let declaration = matches!(before, Some(Token::Preprocessor(directive)) if directive == "#define");
+                        Some(Classification::new("macro").with(if declaration { DECLARATION } else { 0 }))
+                    }
+                    Some(Token::Struct | Token::Union) => Some(Classification::new("struct")),
+                    Some(Token::Enum) => Some(Classification::new("enum")),
+                    Some(Token::Dot | Token::Arrow) => Some(Classification::new("property")),
+                    _ if member_lists.last() == Some(&depth) => Some(Classification::new("property").with(DECLARATION)),
+                    _ => {
+                        let declaration = declared_by.get(&index).copied().flatten();
+                        let line = declaration.map(|declaration| line_of(tokens[declaration].1.start));
+                        let mut classification = identifier(symbols.get(name.as_str()), line);
+                        if declaration == Some(index) {
+                            classification = classification.map(|classification| classification.with(DECLARATION));
+                        }
+                        classification
+                    }
+                }
+            }
+            _ => match token.category() {
+                "type" => Some(Classification::new("type").with(DEFAULT_LIBRARY)),
+                "keyword" => Some(Classification::new("keyword")),
+                "comment" => Some(Classification::new("comment")),
+                "string" => Some(Classification::new("string")),
+                "number" => Some(Classification::new("number")),
+                "preprocessor" => Some(Classification::new("macro")),
+                "operator" => Some(Classification::new("operator")),
+                _ => None,
+            },
+        };
+        if let Some(classification) = classification {
+            classified.push((span.clone(), classification));
+        }
+    }
+    classified
+}
+
+/// Byte offset of the start of each line
+fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0).chain(source.match_indices('\n').map(|(offset, _)| offset + 1)).collect()
+}
+
+/// Every symbol scope analysis found, in any scope, by name
+fn symbols(unit: &TranslationUnit, source: &str) -> HashMap<String, Vec<Symbol>> {
+    let mut symbols: HashMap<String, Vec<Symbol>> = HashMap::new();
+    let mut scope_analyzer = ScopeAnalyzer::with_source(Rc::new(SourceFile::new("", source)));
+    let _ = scope_analyzer.analyze_translation_unit(unit);
+    for scope in scope_analyzer.get_all_scopes() {
+        for (name, symbol) in scope.symbols.borrow().iter() {
+            symbols.entry(name.clone()).or_default().push(symbol.clone());
+        }
+    }
+    symbols
+}
+
+/// An identifier's classification from the symbols of its name: the one
+/// declared on `line`, the line of the declaration it resolved to, or any
+/// one if they are all of a kind; None for a name that isn't declared
+fn identifier(symbols: Option<&Vec<Symbol>>, line: Option<usize>) -> Option<Classification> {
+    let symbols = symbols?;
+    let symbol = match line.and_then(|line| symbols.iter().find(|symbol| symbol.line == Some(line))) {
+        Some(symbol) => symbol,
+        None => {
+            let first = symbols.first()?;
+            let same_kind = symbols.iter().all(|symbol| type_of(symbol) == type_of(first));
+            // A name resolved to no declaration in this file can only be a
+            // builtin's
+            match (same_kind, line) {
+                (true, _) => first,
+                (false, None) => symbols.iter().find(|symbol| symbol.line.is_none())?,
+                (false, Some(_)) => return Some(Classification::new("variable")),
+            }
+        }
+    };
+    let classification = Classification::new(type_of(symbol));
+    // Builtins are the functions declared with no line, by a header
+    let builtin = matches!(symbol.kind, SymbolKind::Function { .. }) && symbol.line.is_none() && symbol.scope_level == 0;
+    Some(if builtin { classification.with(DEFAULT_LIBRARY) } else { classification })
+}
+
+fn type_of(symbol: &Symbol) -> &'static str {
+    match symbol.kind {
+        SymbolKind::Function { .. } => "function",
+        SymbolKind::Parameter { .. } => "parameter",
+        SymbolKind::Variable { .. } => "variable",
+        SymbolKind::Enumerator { .. } => "enumMember",
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Legend {
+    token_types: Vec<&'static str>,
+    token_modifiers: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct SemanticTokens {
+    legend: Legend,
+    data: Vec<u32>,
+}
+
+/// The classified tokens of `source` encoded as LSP's relative positions
+pub fn encode(source: &str, classified: &[(Range<usize>, Classification)]) -> Vec<u32> {
+    let line_starts = line_starts(source);
+    let mut data = Vec::with_capacity(classified.len() * 5);
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for (span, classification) in classified {
+        let token_type = TOKEN_TYPES
+            .iter()
+            .position(|name| *name == classification.token_type)
+            .expect("every classification is in the legend") as u32;
+        let mut offset = span.start;
+        for piece in source[span.clone()].split('\n') {
+            let line = line_starts.partition_point(|&start| start <= offset) - 1;
+            let start = utf16_len(&source[line_starts[line]..offset]);
+            let line = line as u32;
+            let piece_text = piece.strip_suffix('\r').unwrap_or(piece);
+            if !piece_text.is_empty() {
+                let delta_start = if line == previous_line { start - previous_start } else { start };
+                data.extend([line - previous_line, delta_start, utf16_len(piece_text), token_type, classification.modifiers]);
+                (previous_line, previous_start) = (line, start);
+            }
+            offset += piece.len() + 1;
+        }
+    }
+    data
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.chars().map(char::len_utf16).sum::<usize>() as u32
+}
+
+pub struct SemanticTokensEmitter;
+
+impl EmitTarget for SemanticTokensEmitter {
+    fn name(&self) -> &str {
+        "semantic-tokens"
+    }
+
+    fn emit(&self, unit: &TranslationUnit, source: &str, _diagnostics: &[Diagnostic]) -> String {
+        let tokens = SemanticTokens {
+            legend: Legend { token_types: TOKEN_TYPES.to_vec(), token_modifiers: TOKEN_MODIFIERS.to_vec() },
+            data: encode(source, &classify(unit, source)),
+        };
+        serde_json::to_string(&tokens).expect("semantic tokens are always serializable") + "\n"
+    }
+}
@@ -0,0 +1,339 @@
+// source.rs: The text of a file being compiled, and where its lines are.
+//
+// Every phase that reports a line takes the one SourceFile (behind an Rc)
+// rather than its own copy of the text split into lines. The line index is
+// built the first time a line is asked for and kept; after that a line's
+// text, or the line and column of a byte offset, is a binary search away.
+//
+// A SourceMap holds every file of a compilation, the main files and the
+// headers they include, each under a FileId it keeps for as long as the
+// map lives. A Span names its file by that id, so a token spliced in from
+// a header, a diagnostic or a symbol says which file it is from without
+// carrying the file's name or text around. A file compiled on its own is
+// the first of its map, FileId(0).
+//
+// A lexer's tokens carry a TokenSpan, their line and column worked out
+// once as they are lexed, so the parser can say where an error is without
+// the text at hand.
+//
+// Before lexing, a `\` ending a line joins it to the next, as C's second
+// translation phase does. The lexers read the Spliced text and map every
+// offset back to the original, so tokens and diagnostics keep the lines
+// they were written on.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Which file of a SourceMap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FileId(pub u32);
+
+/// Bytes of one file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub range: Range<usize>,
+}
+
+/// Where a token is in its file: 1-based line and character column, and
+/// its bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TokenSpan {
+    pub line: usize,
+    pub col: usize,
+    pub byte_offset: usize,
+    pub len: usize,
+    pub end_line: usize, // line and column just past the token's last character
+    pub end_col: usize,
+}
+
+impl TokenSpan {
+    pub fn range(&self) -> Range<usize> {
+        self.byte_offset..self.byte_offset + self.len
+    }
+
+    /// The empty span just past the token, where something missing after
+    /// it would go
+    pub fn end(&self) -> TokenSpan {
+        let (line, col) = (self.end_line, self.end_col);
+        TokenSpan { line, col, byte_offset: self.byte_offset + self.len, len: 0, end_line: line, end_col: col }
+    }
+
+    /// The span from the start of this one to the end of `last`, as of a
+    /// construct running from its first token to its last
+    pub fn to(&self, last: &TokenSpan) -> TokenSpan {
+        TokenSpan {
+            len: (last.byte_offset + last.len).saturating_sub(self.byte_offset),
+            end_line: last.end_line,
+            end_col: last.end_col,
+            ..*self
+        }
+    }
+}
+
+/// A token and where it was lexed from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub token: T,
+    pub span: TokenSpan,
+}
+
+/// Tokens paired with their byte ranges in `text`, given their line and
+/// column too. The tokens are in order, so each position is counted on
+/// from the one before.
+pub fn spanned<T>(text: &str, tokens: Vec<(T, Range<usize>)>) -> Vec<Spanned<T>> {
+    let mut counter = SpanCounter::default();
+    tokens.into_iter().map(|(token, range)| Spanned { span: counter.span(text, range), token }).collect()
+}
+
+/// Lines and columns counted through a text as ranges of it in order are
+/// given their TokenSpans, so tokens read a few at a time are spanned as
+/// they come
+#[derive(Debug, Clone)]
+pub struct SpanCounter {
+    at: usize, // byte offset counted to, at `line` and `col`
+    line: usize,
+    col: usize,
+}
+
+impl Default for SpanCounter {
+    fn default() -> Self {
+        SpanCounter { at: 0, line: 1, col: 1 }
+    }
+}
+
+impl SpanCounter {
+    /// The span of `range`, which starts at or after the last one given
+    pub fn span(&mut self, text: &str, range: Range<usize>) -> TokenSpan {
+        self.count(text, range.start);
+        let (line, col) = (self.line, self.col);
+        self.count(text, range.end);
+        TokenSpan { line, col, byte_offset: range.start, len: range.len(), end_line: self.line, end_col: self.col }
+    }
+
+    fn count(&mut self, text: &str, to: usize) {
+        for c in text.get(self.at..to).unwrap_or("").chars() {
+            (self.line, self.col) = if c == '\n' { (self.line + 1, 1) } else { (self.line, self.col + 1) };
+        }
+        self.at = self.at.max(to);
+    }
+}
+
+/// Where each line of a text starts
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    starts: Vec<usize>, // byte offset of each line, the first at 0
+    len: usize,
+    ends_with_newline: bool,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        let starts = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        LineIndex { starts, len: text.len(), ends_with_newline: text.is_empty() || text.ends_with('\n') }
+    }
+
+    /// How many lines there are, counted as `str::lines` counts them: a
+    /// newline at the very end doesn't start another
+    pub fn line_count(&self) -> usize {
+        self.starts.len() - usize::from(self.ends_with_newline)
+    }
+
+    /// Bytes of line `line` (from 1), without its newline
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        if line == 0 || line > self.line_count() {
+            return None;
+        }
+        let start = self.starts[line - 1];
+        let end = self.starts.get(line).map_or(self.len, |next| next - 1);
+        Some(start..end)
+    }
+
+    /// 1-based line of a byte offset
+    pub fn line(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset)
+    }
+
+    /// 1-based line and character column of a byte offset in `text`, the
+    /// text this index was built from
+    pub fn line_column(&self, text: &str, offset: usize) -> (usize, usize) {
+        let line = self.line(offset);
+        (line, text[self.starts[line - 1]..offset].chars().count() + 1)
+    }
+
+    /// Byte offset of a 1-based line and character column in `text`
+    pub fn offset_of(&self, text: &str, line: usize, column: usize) -> Option<usize> {
+        let line_start = *self.starts.get(line.checked_sub(1)?)?;
+        let line_text = text[line_start..].split('\n').next().unwrap_or("");
+        let (column_offset, _) = line_text.char_indices().nth(column.checked_sub(1)?)?;
+        Some(line_start + column_offset)
+    }
+}
+
+/// A text with its line continuations, a `\` right before a newline,
+/// taken out, and where the bytes of what is left were in the original
+pub struct Spliced<'a> {
+    pub original: &'a str,
+    pub text: Cow<'a, str>, // borrowed when there is nothing to splice
+    splices: Vec<(usize, usize)>, // offset in `text` where one was taken out, and bytes taken out up to there
+}
+
+impl<'a> Spliced<'a> {
+    pub fn new(original: &'a str) -> Spliced<'a> {
+        if !original.contains("\\\n") && !original.contains("\\\r\n") {
+            return Spliced { original, text: Cow::Borrowed(original), splices: Vec::new() };
+        }
+        let mut text = String::with_capacity(original.len());
+        let mut splices = Vec::new();
+        let mut removed = 0;
+        let mut rest = original;
+        while let Some(backslash) = rest.find('\\') {
+            text.push_str(&rest[..backslash]);
+            let after = &rest[backslash + 1..];
+            let newline = if after.starts_with('\n') {
+                1
+            } else if after.starts_with("\r\n") {
+                2
+            } else {
+                text.push('\\');
+                rest = after;
+                continue;
+            };
+            removed += 1 + newline;
+            splices.push((text.len(), removed));
+            rest = &after[newline..];
+        }
+        text.push_str(rest);
+        Spliced { original, text: Cow::Owned(text), splices }
+    }
+
+    /// Bytes taken out before `offset` of the spliced text; with `at_end`,
+    /// a splice right at it isn't counted, so a range ends where its last
+    /// byte was rather than after the continuation that follows it
+    fn removed_before(&self, offset: usize, at_end: bool) -> usize {
+        let splices = self.splices.partition_point(|&(at, _)| at < offset || (at == offset && !at_end));
+        splices.checked_sub(1).map_or(0, |last| self.splices[last].1)
+    }
+
+    /// Where byte `offset` of the spliced text was in the original
+    pub fn original_offset(&self, offset: usize) -> usize {
+        offset + self.removed_before(offset, false)
+    }
+
+    /// The bytes of the original a range of the spliced text came from
+    pub fn original_range(&self, range: Range<usize>) -> Range<usize> {
+        let start = self.original_offset(range.start);
+        start..(range.end + self.removed_before(range.end, true)).max(start)
+    }
+
+    /// 1-based line and character column in the original of byte `offset`
+    /// of the spliced text
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        LineIndex::new(self.original).line_column(self.original, self.original_offset(offset))
+    }
+}
+
+/// One file's text, with its lines indexed on first use
+#[derive(Debug, Default)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub name: String,
+    text: String,
+    index: OnceCell<LineIndex>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> SourceFile {
+        SourceFile { id: FileId::default(), name: name.into(), text: text.into(), index: OnceCell::new() }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn index(&self) -> &LineIndex {
+        self.index.get_or_init(|| LineIndex::new(&self.text))
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.index().line_count()
+    }
+
+    /// Text of line `line` (from 1), without its line ending
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let range = self.index().line_range(line)?;
+        let text = &self.text[range];
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    /// Every line in order, as `str::lines` gives them
+    pub fn lines(&self) -> std::str::Lines<'_> {
+        self.text.lines()
+    }
+
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        self.index().line_column(&self.text, offset)
+    }
+
+    pub fn offset_of(&self, line: usize, column: usize) -> Option<usize> {
+        self.index().offset_of(&self.text, line, column)
+    }
+}
+
+/// Every file of a compilation, by FileId
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<Rc<SourceFile>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file under the next FileId; one already in under the same
+    /// name is added again, with an id of its own
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> FileId {
+        let id = FileId(self.files.len() as u32);
+        self.files.push(Rc::new(SourceFile { id, ..SourceFile::new(name, text) }));
+        id
+    }
+
+    /// Give a file new text, keeping its id; spans into the old text
+    /// mean nothing after this
+    pub fn replace(&mut self, id: FileId, text: impl Into<String>) {
+        let file = &mut self.files[id.0 as usize];
+        *file = Rc::new(SourceFile { id, ..SourceFile::new(file.name.clone(), text) });
+    }
+
+    pub fn file(&self, id: FileId) -> &Rc<SourceFile> {
+        &self.files[id.0 as usize]
+    }
+
+    /// The first file added under `name`
+    pub fn find(&self, name: &str) -> Option<FileId> {
+        self.files.iter().find(|file| file.name == name).map(|file| file.id)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Rc<SourceFile>> {
+        self.files.iter()
+    }
+
+    pub fn text(&self, span: &Span) -> &str {
+        &self.file(span.file).text()[span.range.clone()]
+    }
+
+    /// 1-based line and character column where a span starts
+    pub fn line_column(&self, span: &Span) -> (usize, usize) {
+        self.file(span.file).line_column(span.range.start)
+    }
+
+    /// `name:line:column` of where a span starts
+    pub fn describe(&self, span: &Span) -> String {
+        let (line, column) = self.line_column(span);
+        format!("{}:{}:{}", self.file(span.file).name, line, column)
+    }
+}
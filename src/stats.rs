@@ -0,0 +1,117 @@
+// stats.rs: `stats <file>`, size and style figures for a source file.
+//
+// Everything is computed from the regex lexer's spanned tokens, so a file
+// that doesn't parse still gets statistics. They borrow from the source,
+// since each is only looked at once.
+
+use crate::lexer_regex::borrowed::{self, Token};
+use crate::source::{LineIndex, Spliced};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Identifiers listed in the text report; the JSON has all of them
+const TOP_IDENTIFIERS: usize = 10;
+
+#[derive(Debug, Serialize)]
+pub struct LineCounts {
+    pub total: usize,
+    pub blank: usize,
+    pub code: usize,         // lines with at least one non-comment token
+    pub comment: usize,      // lines with a comment, alone or after code
+    pub comment_only: usize, // lines with a comment and no code
+}
+
+#[derive(Debug, Serialize)]
+pub struct IdentifierCount {
+    pub name: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileStats {
+    pub lines: LineCounts,
+    pub comment_ratio: f64, // comment lines over non-blank lines
+    pub tokens: usize,      // comments excluded
+    pub tokens_by_category: BTreeMap<String, usize>,
+    pub identifiers: Vec<IdentifierCount>, // most used first
+}
+
+impl FileStats {
+    pub fn compute(source: &str) -> FileStats {
+        let index = LineIndex::new(source);
+        let line_of = |offset: usize| index.line(offset) - 1;
+
+        let mut has_code = vec![false; index.line_count()];
+        let mut has_comment = vec![false; index.line_count()];
+        let mut tokens = 0;
+        let mut tokens_by_category: BTreeMap<String, usize> = BTreeMap::new();
+        let spliced = Spliced::new(source);
+        let mut identifier_counts: BTreeMap<&str, usize> = BTreeMap::new();
+
+        for (token, span) in borrowed::Tokens::new(&spliced, Default::default()) {
+            let category = token.category();
+            let lines = line_of(span.start)..=line_of(span.end.saturating_sub(1).max(span.start));
+            if category == "comment" {
+                for line in lines {
+                    has_comment[line] = true;
+                }
+                continue;
+            }
+            for line in lines {
+                has_code[line] = true;
+            }
+            tokens += 1;
+            *tokens_by_category.entry(category.to_string()).or_default() += 1;
+            if let Token::Identifier(name) = token {
+                *identifier_counts.entry(name).or_default() += 1;
+            }
+        }
+
+        let blank = (1..=index.line_count())
+            .filter_map(|line| index.line_range(line))
+            .filter(|range| source[range.clone()].trim().is_empty())
+            .count();
+        let code = has_code.iter().filter(|&&code| code).count();
+        let comment = has_comment.iter().filter(|&&comment| comment).count();
+        let comment_only = has_comment.iter().zip(&has_code).filter(|&(&comment, &code)| comment && !code).count();
+        let non_blank = index.line_count() - blank;
+
+        let mut identifiers: Vec<IdentifierCount> = identifier_counts
+            .into_iter()
+            .map(|(name, count)| IdentifierCount { name: name.to_string(), count })
+            .collect();
+        // Stable sort keeps equally used names alphabetical
+        identifiers.sort_by_key(|identifier| std::cmp::Reverse(identifier.count));
+
+        FileStats {
+            lines: LineCounts { total: index.line_count(), blank, code, comment, comment_only },
+            comment_ratio: if non_blank == 0 { 0.0 } else { comment as f64 / non_blank as f64 },
+            tokens,
+            tokens_by_category,
+            identifiers,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("statistics are always serializable")
+    }
+
+    /// Human-readable report
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&format!(
+            "Lines: {} total, {} code, {} comment ({} comment-only), {} blank\n",
+            self.lines.total, self.lines.code, self.lines.comment, self.lines.comment_only, self.lines.blank
+        ));
+        text.push_str(&format!("Comment ratio: {:.1}%\n", self.comment_ratio * 100.0));
+        text.push_str(&format!("Tokens: {}\n", self.tokens));
+        for (category, count) in &self.tokens_by_category {
+            text.push_str(&format!("  {:<12} {}\n", category, count));
+        }
+        text.push_str(&format!("Identifiers: {} distinct\n", self.identifiers.len()));
+        for identifier in self.identifiers.iter().take(TOP_IDENTIFIERS) {
+            text.push_str(&format!("  {:<20} {}\n", identifier.name, identifier.count));
+        }
+        text
+    }
+}
@@ -0,0 +1,94 @@
+// stream.rs: Compiling a file too large to hold whole (`stream`).
+//
+// The source is lexed lazily and parsed a top-level declaration at a time
+// (see parser::chunks). Each function is lowered to its control flow graph
+// as soon as it is parsed, the graph handed on, and its statements dropped,
+// so at any moment only one declaration's tokens and AST are held, however
+// large the file.
+//
+// The scope analyzer and type checker still want the whole unit, so a file
+// compiled this way is parsed and lowered but not checked.
+
+use crate::layout::Layouts;
+use crate::lexer_regex::{LexOptions, Tokens};
+use crate::lower::{self, ControlFlowGraph};
+use crate::parser::ast::{ExternalDeclaration, ParseError};
+use crate::parser::chunks::Chunks;
+use crate::parser::Parser;
+use crate::source::{SpanCounter, Spanned};
+
+/// What streaming a file went through
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub bytes: usize,
+    pub tokens: usize,
+    /// Most tokens held at once
+    pub peak_tokens: usize,
+    pub declarations: usize,
+    pub functions: usize,
+    pub blocks: usize,
+    pub errors: Vec<ParseError>,
+}
+
+impl Summary {
+    pub fn render(&self) -> String {
+        format!(
+            "{} bytes, {} tokens, at most {} held at once\n\
+             {} declaration(s), {} function(s) lowered to {} block(s)\n\
+             {} parse error(s)\n",
+            self.bytes,
+            self.tokens,
+            self.peak_tokens,
+            self.declarations,
+            self.functions,
+            self.blocks,
+            self.errors.len()
+        )
+    }
+}
+
+/// Lex, parse and lower `source` a declaration at a time, giving each
+/// function's graph to `lowered` as it is built
+pub fn compile(
+    source: &str,
+    options: LexOptions,
+    gnu_extensions: bool,
+    mut lowered: impl FnMut(ControlFlowGraph),
+) -> Summary {
+    let mut parser = Parser::new(Vec::new());
+    parser.set_gnu_extensions(gnu_extensions);
+    parser.set_standard(options.standard);
+    let mut counter = SpanCounter::default();
+    let tokens =
+        Tokens::new(source, options).map(|(token, range)| Spanned { span: counter.span(source, range), token });
+    let mut chunks = Chunks::new(tokens, parser);
+    let mut summary = Summary { bytes: source.len(), ..Summary::default() };
+    let mut layouts = Layouts::default();
+    for chunk in chunks.by_ref() {
+        summary.tokens += chunk.tokens;
+        let unit = match chunk.unit {
+            Ok(unit) => unit,
+            Err(errors) => {
+                summary.errors.extend(errors);
+                continue;
+            }
+        };
+        for external in unit.external_declarations {
+            summary.declarations += 1;
+            match external {
+                ExternalDeclaration::Struct(definition) => layouts.add(&definition),
+                ExternalDeclaration::Enum(definition) => layouts.add_enum(&definition),
+                ExternalDeclaration::Function(function) => {
+                    let graph = lower::lower_function(&layouts, &function);
+                    drop(function);
+                    summary.functions += 1;
+                    summary.blocks += graph.blocks.len();
+                    lowered(graph);
+                }
+                _ => {}
+            }
+        }
+    }
+    summary.peak_tokens = chunks.peak_tokens;
+    summary
+}
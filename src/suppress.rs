@@ -0,0 +1,141 @@
+// suppress.rs: Comments and pragmas that silence named warnings for the
+// declaration or statement after them.
+//
+//     // minic: allow(pointer-to-int, dead-globals)
+//     int address = (int) pointer;
+//
+//     #pragma minic allow(format)
+//     printf("%d\n", name);
+//
+// Each is attached to the span of the code that follows it: from its first
+// token to the `;` ending it, or to its closing brace for a function, block
+// or compound statement, taking in an `else` or do-while's condition after
+// that. A warning whose line falls in the span and whose name is allowed
+// isn't reported. Errors can't be silenced.
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::lexer_regex::{self, Token};
+use crate::source::LineIndex;
+use std::ops::{Range, RangeInclusive};
+
+/// Warnings allowed over some lines
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suppression {
+    pub names: Vec<String>,
+    pub lines: RangeInclusive<usize>,
+}
+
+impl Suppression {
+    pub fn allows(&self, diagnostic: &Diagnostic) -> bool {
+        diagnostic.severity == Severity::Warning
+            && diagnostic.line.is_some_and(|line| self.lines.contains(&line))
+            && diagnostic.name.as_ref().is_some_and(|name| self.names.contains(name))
+    }
+}
+
+/// The suppressions written in `source`
+pub fn suppressions(source: &str) -> Vec<Suppression> {
+    let spanned = lexer_regex::lex_with_regex_spanned(source);
+    let line_index = LineIndex::new(source);
+    let mut found = Vec::new();
+    for (index, (token, _)) in spanned.iter().enumerate() {
+        let names = match token {
+            Token::Comment(text) | Token::BlockComment(text) => allowed_in_comment(text),
+            Token::Pragma(text) => allowed_in_pragma(text),
+            _ => None,
+        };
+        if let Some(names) = names
+            && let Some(span) = statement_after(&spanned, index + 1)
+        {
+            let lines = line_index.line(span.start)..=line_index.line(span.end.saturating_sub(1));
+            found.push(Suppression { names, lines });
+        }
+    }
+    found
+}
+
+/// Drop the warnings some suppression allows
+pub fn apply(diagnostics: &mut Vec<Diagnostic>, suppressions: &[Suppression]) {
+    diagnostics.retain(|diagnostic| !suppressions.iter().any(|suppression| suppression.allows(diagnostic)));
+}
+
+/// `// minic: allow(a, b)` or `/* minic: allow(a) */`
+fn allowed_in_comment(text: &str) -> Option<Vec<String>> {
+    let text = text.strip_prefix("//").or_else(|| text.strip_prefix("/*")?.strip_suffix("*/"))?;
+    allow_list(text.trim().strip_prefix("minic:")?)
+}
+
+/// `#pragma minic allow(a, b)`
+fn allowed_in_pragma(text: &str) -> Option<Vec<String>> {
+    let text = text.strip_prefix('#')?.trim_start().strip_prefix("pragma")?;
+    allow_list(text.trim_start().strip_prefix("minic")?)
+}
+
+fn allow_list(text: &str) -> Option<Vec<String>> {
+    let list = text.trim().strip_prefix("allow")?.trim_start().strip_prefix('(')?;
+    let list = &list[..list.find(')')?];
+    let names: Vec<String> = list.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect();
+    (!names.is_empty()).then_some(names)
+}
+
+/// Byte range of the declaration or statement starting at or after `from`
+fn statement_after(spanned: &[(Token, Range<usize>)], from: usize) -> Option<Range<usize>> {
+    let first = (from..spanned.len()).find(|&index| is_code(&spanned[index].0))?;
+    let last = statement_end(spanned, first);
+    Some(spanned[first].1.start..spanned[last].1.end)
+}
+
+pub(crate) fn is_code(token: &Token) -> bool {
+    !matches!(token, Token::Comment(_) | Token::BlockComment(_) | Token::Pragma(_))
+}
+
+/// Index of the last token of the declaration or statement `first` starts
+pub(crate) fn statement_end(spanned: &[(Token, Range<usize>)], first: usize) -> usize {
+    let next_code = |from: usize| (from..spanned.len()).find(|&index| is_code(&spanned[index].0));
+    // Braces of a type or an initializer are followed by more, up to a `;`
+    let mut braces_end = !matches!(spanned[first].0, Token::Struct | Token::Union | Token::Enum | Token::Typedef);
+    let mut awaiting_while = matches!(spanned[first].0, Token::Do);
+    let mut depth = 0usize;
+    let mut index = first;
+    while index < spanned.len() {
+        let ends = match spanned[index].0 {
+            Token::ParenL | Token::BracketL | Token::BraceL => {
+                depth += 1;
+                false
+            }
+            Token::ParenR | Token::BracketR => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            Token::BraceR => {
+                depth = depth.saturating_sub(1);
+                depth == 0 && braces_end
+            }
+            Token::AssignOp if depth == 0 => {
+                braces_end = false;
+                false
+            }
+            Token::Semicolon => depth == 0,
+            _ => false,
+        };
+        if ends {
+            // An if's else, or the condition after a do's body, is part of it
+            let next = next_code(index + 1);
+            let continues = next.is_some_and(|next| match spanned[next].0 {
+                Token::Else => true,
+                Token::While => std::mem::take(&mut awaiting_while),
+                _ => false,
+            });
+            match next.filter(|_| continues) {
+                Some(next) => {
+                    index = next;
+                    braces_end = true;
+                    continue;
+                }
+                None => return index,
+            }
+        }
+        index += 1;
+    }
+    spanned.len() - 1
+}
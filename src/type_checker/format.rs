@@ -0,0 +1,47 @@
+// format.rs: printf-style format strings, for calls to functions declared
+// with __attribute__((format(printf, m, n))).
+
+/// What a conversion expects of its argument
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormatArgument {
+    Integer,  // %d %i %u %o %x %X %c, and `*` widths and precisions
+    Floating, // %f %e %g %a and their capitals
+    String,   // %s
+    Pointer,  // %p %n
+}
+
+/// The arguments a printf format string consumes, in order, or None if it
+/// has an incomplete or unknown conversion
+pub fn printf_arguments(format: &str) -> Option<Vec<FormatArgument>> {
+    let mut arguments = Vec::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        if chars.next_if_eq(&'%').is_some() {
+            continue;
+        }
+        while chars.next_if(|c| "-+ #0".contains(*c)).is_some() {}
+        // Width, then precision; either may be `*`, taken from an argument
+        for precision in [false, true] {
+            if precision && chars.next_if_eq(&'.').is_none() {
+                break;
+            }
+            if chars.next_if_eq(&'*').is_some() {
+                arguments.push(FormatArgument::Integer);
+            }
+            while chars.next_if(char::is_ascii_digit).is_some() {}
+        }
+        while chars.next_if(|c| "hlLqjzt".contains(*c)).is_some() {}
+        let argument = match chars.next()? {
+            'd' | 'i' | 'u' | 'o' | 'x' | 'X' | 'c' => FormatArgument::Integer,
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A' => FormatArgument::Floating,
+            's' => FormatArgument::String,
+            'p' | 'n' => FormatArgument::Pointer,
+            _ => return None,
+        };
+        arguments.push(argument);
+    }
+    Some(arguments)
+}
@@ -1,10 +1,25 @@
 // type_checker/mod.rs: Type checking implementation for MiniC compiler
 
+mod format;
+#[cfg(test)]
+mod tests;
+
+use crate::dialect::Standard;
+use crate::intrinsics;
+use crate::layout::Layouts;
+use crate::lexer_regex;
 use crate::parser::ast::*;
-use crate::scope::{ScopeAnalyzer, SymbolKind, ScopeNode};
+use crate::parser::{self, MAX_NESTING_DEPTH};
+use crate::pretty;
+use crate::scope::{ScopeAnalyzer, Symbol, SymbolKind, ScopeNode};
+use crate::source::TokenSpan;
+use format::FormatArgument;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::rc::Rc;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TypeChkError {
     ErroneousVarDecl,
     FnCallParamCount,
@@ -21,6 +36,58 @@ pub enum TypeChkError {
     AttemptedAddOpOnNonNumeric,
     AttemptedExponentiationOfNonNumeric,
     ReturnStmtNotFound,
+    MissingMainFunction,
+    InvalidMainSignature,
+    InvalidMainReturnType,
+    TooDeeplyNested,
+    GenericNoMatch,
+    GenericDuplicateAssociation,
+    NoreturnFunctionReturns,
+    RestrictOnNonPointer,
+    InvalidAttributeArgument,
+    ArraySizeNotInteger,
+    ArraySizeNotPositive,
+    VariableLengthArrayAtFileScope,
+    VariableLengthArrayInitialized,
+    UndefinedStruct,
+    StructRedefined,
+    DuplicateMember,
+    NoSuchMember,
+    MemberAccessOnNonStruct,
+    VariableLengthMember,
+    FlexibleArrayNotLast,
+    FlexibleArrayOnlyMember,
+    FlexibleArrayStructNested,
+    InvalidCast,
+    SizeofIncompleteType,
+    ExcessInitializers,
+    InvalidDesignator,
+    NonConstantInitializer,
+    SwitchOnNonInteger,
+    CaseOutsideSwitch,
+    CaseNotConstant,
+    DuplicateCase,
+    DuplicateDefault,
+    EnumRedefined,
+    UndefinedEnum,
+    EnumeratorNotConstant,
+    ArithmeticOnVoidPointer,
+    ArithmeticOnFunctionPointer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TypeChkWarning {
+    IntToPointerConversion,
+    PointerToIntConversion,
+    PointerIntComparison,
+    AssignmentInCondition,
+    IgnoredAttribute,
+    FormatMismatch,
+    ImplicitFallthrough,
+    UnhandledEnumerator,
+    SignCompare { signed: String, unsigned: String }, // the operand's type, and the one it is converted to
+    ConstantOutOfRange { target: String, value: i64, stored: i64, range: (i64, i64) }, // the range it holds
+    Parentheses { inner: String, outer: String, grouped: String }, // the operators, and the expression as it groups
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -31,46 +98,450 @@ pub enum Type {
     Char,
     Short,
     Long,
+    UnsignedChar,
+    UnsignedShort,
+    UnsignedInt,
+    UnsignedLong,
     Void,
     Bool, // For boolean expressions
-    String, // For string literals (char arrays/pointers)
+    Pointer(Box<Type>), // T*, string literals are char*
+    Function, // only ever pointed to; parameters and return type aren't modeled
+    Struct(String), // struct tag
     Unknown, // For error cases
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Double => write!(f, "double"),
+            Type::Char => write!(f, "char"),
+            Type::Short => write!(f, "short"),
+            Type::Long => write!(f, "long"),
+            Type::UnsignedChar => write!(f, "unsigned char"),
+            Type::UnsignedShort => write!(f, "unsigned short"),
+            Type::UnsignedInt => write!(f, "unsigned int"),
+            Type::UnsignedLong => write!(f, "unsigned long"),
+            Type::Void => write!(f, "void"),
+            Type::Bool => write!(f, "bool"),
+            Type::Pointer(pointee) => write!(f, "{}*", pointee),
+            Type::Struct(tag) => write!(f, "struct {}", tag),
+            Type::Function => write!(f, "function"),
+            Type::Unknown => write!(f, "<unknown>"),
+        }
+    }
+}
+
 pub struct TypeChecker {
     scope_analyzer: ScopeAnalyzer,
     errors: Vec<TypeError>,
+    warnings: Vec<TypeWarning>,
     current_return_type: Option<Type>,
     in_loop: bool, // Track if we're inside a loop (for break statements)
     current_scope: Option<Rc<ScopeNode>>, // Track current scope during type checking
-    source_lines: Vec<String>,
+    require_main: bool, // Building an executable needs an entry point
+    standard: Standard, // Language standard; C99 and later let main fall off its end
+    depth: usize, // Current nesting of statements and expressions being checked
+    noreturn_functions: HashSet<String>, // Calls to these never come back
+    format_functions: HashMap<String, (usize, usize)>, // printf-like: format and first variadic argument, from 1
+    structs: HashMap<String, StructDefinition>, // Defined so far, by tag
+    unit_structs: HashSet<String>, // Tags of every struct the unit defines, however far on
+    layouts: Layouts, // Sizes of the structs defined so far, for sizeof
+    switches: Vec<SwitchLabels>, // Labels of the enclosing switches, innermost last
+    warn_implicit_fallthrough: bool, // -Wimplicit-fallthrough
+    warn_sign_compare: bool,         // -Wsign-compare
+    warn_parentheses: bool,          // -Wparentheses
+    line: Option<usize>, // where the statement or declaration being checked starts, for its errors
+    definitions_checked: HashMap<String, usize>, // function definitions checked so far, by name
+    child_scopes: HashMap<*const ScopeNode, Vec<Rc<ScopeNode>>>, // the scopes opened inside each scope, in order
+    children_entered: HashMap<*const ScopeNode, usize>, // how many of those have been checked so far
+}
+
+/// Where an object is declared, which decides by when its type must be complete
+#[derive(Clone, Copy, PartialEq)]
+enum ObjectPlace {
+    Member,
+    Block,
+    FileScope,
+}
+
+/// The labels of a switch seen so far
+#[derive(Default)]
+struct SwitchLabels {
+    values: HashSet<i64>,
+    has_default: bool,
+}
+
+/// The parser's nesting limit counts operators and brackets while this one
+/// counts every node, leaves included, so it needs some headroom. It only
+/// matters for ASTs that didn't come from the parser: plugin passes, artifacts.
+const MAX_CHECK_DEPTH: usize = 2 * MAX_NESTING_DEPTH;
+
+/// Attributes that are understood, or safely ignored, by their base name
+const KNOWN_ATTRIBUTES: [&str; 18] = [
+    "noreturn", "unused", "used", "packed", "aligned", "format", "deprecated", "nodiscard", "fallthrough",
+    "always_inline", "noinline", "const", "pure", "cold", "hot", "weak", "visibility", "section",
+];
+
+/// An object being initialized, down to its scalars
+enum ObjectShape {
+    Scalar(Type),
+    Array(Box<ObjectShape>, Option<usize>), // Length, if known
+    Struct(String),
+}
+
+/// What an attribute is attached to
+#[derive(Clone, Copy, PartialEq)]
+enum AttributeTarget {
+    Variable,
+    Function { parameter_count: usize },
+}
+
+fn is_string_literal(expr: &Expression) -> bool {
+    matches!(expr, Expression::StringLiteral(_) | Expression::EncodedStringLiteral(..))
+}
+
+/// The integer an attribute argument spells, if it is a literal
+fn integer_argument(argument: Option<&Expression>) -> Option<i64> {
+    match argument {
+        Some(Expression::Constant(Constant::Integer(value))) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Library functions that never return, on top of the unit's own `_Noreturn` ones
+const NORETURN_LIBRARY_FUNCTIONS: [&str; 4] = ["exit", "abort", "_Exit", "quick_exit"];
+
+/// Integer conversion rank of an integer type, 0 for char up to 3 for
+/// long, and whether it is unsigned; None for any other type
+fn integer_rank(t: &Type) -> Option<(u8, bool)> {
+    match t {
+        Type::Char => Some((0, false)),
+        Type::UnsignedChar => Some((0, true)),
+        Type::Short => Some((1, false)),
+        Type::UnsignedShort => Some((1, true)),
+        Type::Int => Some((2, false)),
+        Type::UnsignedInt => Some((2, true)),
+        Type::Long => Some((3, false)),
+        Type::UnsignedLong => Some((3, true)),
+        _ => None,
+    }
+}
+
+/// The type a value of `t` takes part in arithmetic as: a _Bool, or the
+/// result of a comparison, is promoted to int
+fn promoted(t: Type) -> Type {
+    if t == Type::Bool { Type::Int } else { t }
+}
+
+/// Whether `inner`, unparenthesized as an operand of `outer`, groups in a
+/// way often misread: `a & b == c` is `a & (b == c)`, `a << b + c` is
+/// `a << (b + c)`, and `a && b || c` is `(a && b) || c`
+fn is_misread_grouping(outer: &BinaryOperator, inner: &BinaryOperator) -> bool {
+    use BinaryOperator::*;
+    match outer {
+        BitAnd | BitOr | Xor => matches!(inner, Equals | NotEquals | Less | LessEq | Greater | GreaterEq | Plus | Minus),
+        LShift | RShift => matches!(inner, Plus | Minus),
+        Or => matches!(inner, And),
+        _ => false,
+    }
+}
+
+/// Whether a condition is a constant that is always true
+fn always_true(condition: &Expression) -> bool {
+    match condition {
+        Expression::Constant(Constant::Bool(value)) | Expression::Folded(Constant::Bool(value), _) => *value,
+        Expression::Constant(Constant::Integer(value)) | Expression::Folded(Constant::Integer(value), _) => *value != 0,
+        Expression::Paren(inner) => always_true(inner),
+        _ => false,
+    }
+}
+
+/// Whether a `break` in these statements leaves the loop they are the body of
+fn breaks_out(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Break => true,
+        StatementKind::Block(statements) => statements.iter().any(breaks_out),
+        StatementKind::If(_, then_branch, else_branch) => {
+            breaks_out(then_branch) || else_branch.as_deref().is_some_and(breaks_out)
+        }
+        StatementKind::Case(_, statement) | StatementKind::Default(statement) | StatementKind::Label(_, statement) => {
+            breaks_out(statement)
+        }
+        // A break inside a nested loop or switch only leaves that one
+        _ => false,
+    }
+}
+
+/// The label a statement starts with: Some(Some(value)) for a case,
+/// Some(None) for default
+fn statement_label(statement: &Statement) -> Option<Option<&Expression>> {
+    match &statement.kind {
+        StatementKind::Case(value, _) => Some(Some(value)),
+        StatementKind::Default(_) => Some(None),
+        _ => None,
+    }
+}
+
+/// A label as written, as far as it can be told, to find its line
+fn label_context(label: Option<Option<&Expression>>) -> String {
+    match label {
+        Some(Some(Expression::Constant(Constant::Integer(value)))) => format!("case {}:", value),
+        Some(Some(Expression::Folded(_, name))) => format!("case {}:", name),
+        Some(Some(_)) => "case".to_string(),
+        Some(None) => "default".to_string(),
+        None => String::new(),
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Whether a `return` appears anywhere in a statement
+fn contains_return(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(_) => true,
+        StatementKind::Block(statements) => statements.iter().any(contains_return),
+        StatementKind::If(_, then_branch, else_branch) => {
+            contains_return(then_branch) || else_branch.as_deref().is_some_and(contains_return)
+        }
+        StatementKind::While(_, body)
+        | StatementKind::For(_, _, _, body)
+        | StatementKind::Switch(_, body)
+        | StatementKind::Case(_, body)
+        | StatementKind::Default(body)
+        | StatementKind::Label(_, body) => contains_return(body),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TypeError {
     pub error: TypeChkError,
     pub line: Option<usize>,
     pub context: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypeWarning {
+    pub warning: TypeChkWarning,
+    pub line: Option<usize>,
+    pub context: String,
+}
+
+impl TypeChkError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            TypeChkError::ErroneousVarDecl => "Erroneous variable declaration",
+            TypeChkError::FnCallParamCount => "Function call parameter count mismatch",
+            TypeChkError::FnCallParamType => "Function call parameter type mismatch",
+            TypeChkError::ErroneousReturnType => "Erroneous return type",
+            TypeChkError::ExpressionTypeMismatch => "Expression type mismatch",
+            TypeChkError::ExpectedBooleanExpression => "Expected boolean expression",
+            TypeChkError::ErroneousBreak => "Break statement outside of a loop or switch",
+            TypeChkError::NonBooleanCondStmt => "Non-boolean condition in control statement",
+            TypeChkError::EmptyExpression => "Empty expression",
+            TypeChkError::AttemptedBoolOpOnNonBools => "Attempted boolean operation on non-boolean types",
+            TypeChkError::AttemptedBitOpOnNonNumeric => "Attempted bitwise operation on non-numeric types",
+            TypeChkError::AttemptedShiftOnNonInt => "Attempted shift operation on non-integer types",
+            TypeChkError::AttemptedAddOpOnNonNumeric => "Attempted arithmetic operation on non-numeric types",
+            TypeChkError::AttemptedExponentiationOfNonNumeric => "Attempted exponentiation on non-numeric types",
+            TypeChkError::ReturnStmtNotFound => "Return statement not found in non-void function",
+            TypeChkError::MissingMainFunction => "No 'main' function defined; an executable needs an entry point (use -c to only compile)",
+            TypeChkError::InvalidMainSignature => "'main' must take no parameters (void) or (int argc, char **argv)",
+            TypeChkError::InvalidMainReturnType => "'main' must return int",
+            TypeChkError::TooDeeplyNested => "Statements or expressions nested too deeply to check",
+            TypeChkError::GenericNoMatch => "No _Generic association matches the controlling expression's type",
+            TypeChkError::GenericDuplicateAssociation => "_Generic has two associations for the same type",
+            TypeChkError::NoreturnFunctionReturns => "Function declared '_Noreturn' can return",
+            TypeChkError::RestrictOnNonPointer => "'restrict' can only qualify a pointer",
+            TypeChkError::InvalidAttributeArgument => "Invalid attribute argument",
+            TypeChkError::ArraySizeNotInteger => "Array size must have integer type",
+            TypeChkError::ArraySizeNotPositive => "Array size must be greater than zero",
+            TypeChkError::VariableLengthArrayAtFileScope => "Variable length arrays can't be declared at file scope",
+            TypeChkError::VariableLengthArrayInitialized => "Variable length arrays can't be initialized",
+            TypeChkError::UndefinedStruct => "Struct used as a complete type before it is defined",
+            TypeChkError::StructRedefined => "Struct defined twice",
+            TypeChkError::DuplicateMember => "Struct has two members with the same name",
+            TypeChkError::NoSuchMember => "Struct has no member of that name",
+            TypeChkError::MemberAccessOnNonStruct => "Member access on something that isn't a struct, or with the wrong operator",
+            TypeChkError::VariableLengthMember => "Struct members can't be variable length arrays",
+            TypeChkError::FlexibleArrayNotLast => "A flexible array member must be the last member of its struct",
+            TypeChkError::FlexibleArrayOnlyMember => "A flexible array member needs at least one other member before it",
+            TypeChkError::FlexibleArrayStructNested => "A struct ending in a flexible array member can't be a member of another struct or an array element",
+            TypeChkError::InvalidCast => "Only scalar values can be cast, and only to a scalar type or void",
+            TypeChkError::SizeofIncompleteType => "sizeof applied to void, a function or an incomplete type",
+            TypeChkError::ExcessInitializers => "Excess elements in initializer",
+            TypeChkError::InvalidDesignator => "Designator doesn't name an element or member of the object it initializes",
+            TypeChkError::NonConstantInitializer => "Initializer of a global or static variable must be a constant expression",
+            TypeChkError::SwitchOnNonInteger => "Switch value must have integer type",
+            TypeChkError::CaseOutsideSwitch => "Case or default label outside of a switch",
+            TypeChkError::CaseNotConstant => "Case value must be an integer constant",
+            TypeChkError::DuplicateCase => "Two case labels of a switch have the same value",
+            TypeChkError::DuplicateDefault => "Switch has more than one default label",
+            TypeChkError::EnumRedefined => "Enum defined twice",
+            TypeChkError::UndefinedEnum => "Enum used as a type before it is defined",
+            TypeChkError::EnumeratorNotConstant => "Enumerator value must be an integer constant",
+            TypeChkError::ArithmeticOnVoidPointer => "Arithmetic on a void pointer; void has no size to move by",
+            TypeChkError::ArithmeticOnFunctionPointer => "Arithmetic on a pointer to a function",
+        }
+    }
+}
+
+impl TypeChkWarning {
+    pub fn message(&self) -> &'static str {
+        match self {
+            TypeChkWarning::IntToPointerConversion => "Integer converted to pointer without a cast",
+            TypeChkWarning::PointerToIntConversion => "Pointer converted to integer without a cast",
+            TypeChkWarning::PointerIntComparison => "Comparison between pointer and integer",
+            TypeChkWarning::AssignmentInCondition => "Assignment used as a condition; use '==' to compare, or add parentheses around the assignment to silence this",
+            TypeChkWarning::IgnoredAttribute => "Attribute ignored; it is unknown or has no effect here",
+            TypeChkWarning::FormatMismatch => "Format string doesn't match the arguments",
+            TypeChkWarning::ImplicitFallthrough => "Code above this label falls through into it; end it with 'break' or mark it [[fallthrough]]",
+            TypeChkWarning::UnhandledEnumerator => "Enumerator not handled by a switch on its enum that has no default",
+            TypeChkWarning::SignCompare { .. } => "Comparison between signed and unsigned integers",
+            TypeChkWarning::ConstantOutOfRange { .. } => "Constant doesn't fit in the type it initializes",
+            TypeChkWarning::Parentheses { .. } => "Operators may not group as they read; add parentheses to make it explicit",
+        }
+    }
+
+    /// What the warning's diagnostic adds under it, if anything
+    pub fn note(&self) -> Option<String> {
+        match self {
+            TypeChkWarning::SignCompare { signed, unsigned } => Some(format!(
+                "the '{}' operand is converted to '{}', so a negative value compares as a large positive one",
+                signed, unsigned
+            )),
+            TypeChkWarning::ConstantOutOfRange { target, value, stored, range: (low, high) } => Some(format!(
+                "'{}' holds {} to {}, so {} is stored as {}",
+                target, low, high, value, stored
+            )),
+            TypeChkWarning::Parentheses { inner, outer, grouped } => {
+                Some(format!("'{}' binds more tightly than '{}', so this is '{}'", inner, outer, grouped))
+            }
+            _ => None,
+        }
+    }
+
+    /// What `// minic: allow(...)` calls it
+    pub fn name(&self) -> &'static str {
+        match self {
+            TypeChkWarning::IntToPointerConversion => "int-to-pointer",
+            TypeChkWarning::PointerToIntConversion => "pointer-to-int",
+            TypeChkWarning::PointerIntComparison => "pointer-int-comparison",
+            TypeChkWarning::AssignmentInCondition => "assignment-in-condition",
+            TypeChkWarning::IgnoredAttribute => "ignored-attribute",
+            TypeChkWarning::FormatMismatch => "format",
+            TypeChkWarning::ImplicitFallthrough => "implicit-fallthrough",
+            TypeChkWarning::UnhandledEnumerator => "unhandled-enumerator",
+            TypeChkWarning::SignCompare { .. } => "sign-compare",
+            TypeChkWarning::ConstantOutOfRange { .. } => "overflow",
+            TypeChkWarning::Parentheses { .. } => "parentheses",
+        }
+    }
+}
+
 impl TypeChecker {
-    pub fn new(scope_analyzer: ScopeAnalyzer, source_lines: Vec<String>) -> Self {
+    pub fn new(scope_analyzer: ScopeAnalyzer) -> Self {
         let global_scope = scope_analyzer.get_global_scope().clone();
+        let mut child_scopes: HashMap<*const ScopeNode, Vec<Rc<ScopeNode>>> = HashMap::new();
+        for scope in scope_analyzer.get_all_scopes() {
+            if let Some(parent) = &scope.parent {
+                child_scopes.entry(Rc::as_ptr(parent)).or_default().push(scope.clone());
+            }
+        }
+        let noreturn_intrinsics =
+            intrinsics::INTRINSICS.iter().filter(|intrinsic| intrinsic.noreturn).map(|intrinsic| intrinsic.name);
         TypeChecker {
             scope_analyzer,
             errors: Vec::new(),
+            warnings: Vec::new(),
             current_return_type: None,
             in_loop: false,
             current_scope: Some(global_scope),
-            source_lines,
+            require_main: true,
+            standard: Standard::default(),
+            depth: 0,
+            noreturn_functions: NORETURN_LIBRARY_FUNCTIONS
+                .into_iter()
+                .chain(noreturn_intrinsics)
+                .map(|name| name.to_string())
+                .collect(),
+            format_functions: HashMap::new(),
+            structs: HashMap::new(),
+            unit_structs: HashSet::new(),
+            layouts: Layouts::default(),
+            switches: Vec::new(),
+            warn_implicit_fallthrough: false,
+            warn_sign_compare: false,
+            warn_parentheses: false,
+            line: None,
+            definitions_checked: HashMap::new(),
+            child_scopes,
+            children_entered: HashMap::new(),
         }
     }
 
+    /// Turn the `main` entry point check on or off (off when only compiling a unit)
+    pub fn set_require_main(&mut self, require_main: bool) {
+        self.require_main = require_main;
+    }
+
+    /// Warn about switch cases that run into the next label (`-Wimplicit-fallthrough`)
+    pub fn set_warn_implicit_fallthrough(&mut self, warn: bool) {
+        self.warn_implicit_fallthrough = warn;
+    }
+
+    /// Warn about comparisons that convert a signed operand to unsigned (`-Wsign-compare`)
+    pub fn set_warn_sign_compare(&mut self, warn: bool) {
+        self.warn_sign_compare = warn;
+    }
+
+    /// Warn about operators whose precedence is often misread (`-Wparentheses`)
+    pub fn set_warn_parentheses(&mut self, warn: bool) {
+        self.warn_parentheses = warn;
+    }
+
+    /// Apply a language standard's rules (`--std`)
+    pub fn set_standard(&mut self, standard: Standard) {
+        self.standard = standard;
+    }
+
     pub fn check_translation_unit(&mut self, unit: &TranslationUnit) -> Result<(), Vec<TypeError>> {
+        for external_decl in &unit.external_declarations {
+            let (name, noreturn, attributes) = match external_decl {
+                ExternalDeclaration::Function(func_def) => (&func_def.name, func_def.is_noreturn(), &func_def.attributes),
+                ExternalDeclaration::FunctionDeclaration(func_decl) => {
+                    (&func_decl.name, func_decl.is_noreturn(), &func_decl.attributes)
+                }
+                ExternalDeclaration::Struct(definition) => {
+                    self.unit_structs.insert(definition.name.clone());
+                    continue;
+                }
+                ExternalDeclaration::Variable(_)
+                | ExternalDeclaration::StructDeclaration(..)
+                | ExternalDeclaration::Enum(_) => continue,
+            };
+            if noreturn {
+                self.noreturn_functions.insert(name.clone());
+            }
+            if let Some(format) = find_attribute(attributes, "format")
+                && matches!(format.arguments.first(), Some(Expression::Identifier(archetype)) if archetype.trim_matches('_') == "printf")
+                && let (Some(format_index), Some(first_argument)) =
+                    (integer_argument(format.arguments.get(1)), integer_argument(format.arguments.get(2)))
+                && format_index > 0
+                && first_argument >= 0
+            {
+                self.format_functions.insert(name.clone(), (format_index as usize, first_argument as usize));
+            }
+        }
+
         for external_decl in &unit.external_declarations {
             self.check_external_declaration(external_decl);
         }
 
+        if self.require_main {
+            self.check_entry_point(unit);
+        }
+
         if self.errors.is_empty() {
             Ok(())
         } else {
@@ -79,80 +550,671 @@ impl TypeChecker {
     }
 
     fn check_external_declaration(&mut self, decl: &ExternalDeclaration) {
+        self.at(decl.span(), |checker| checker.check_external_declaration_node(decl));
+    }
+
+    fn check_external_declaration_node(&mut self, decl: &ExternalDeclaration) {
         match decl {
             ExternalDeclaration::Variable(var_decl) => {
-                self.check_variable_declaration(var_decl);
+                // A variable length array is allocated on entry to its block
+                if var_decl.declarator.is_variable_length() {
+                    self.record_error(TypeChkError::VariableLengthArrayAtFileScope, &var_decl.declarator.name);
+                }
+                self.check_variable_declaration(var_decl, ObjectPlace::FileScope);
             }
             ExternalDeclaration::Function(func_def) => {
                 self.check_function_definition(func_def);
             }
-            ExternalDeclaration::FunctionDeclaration(_func_decl) => {
-                // Function declarations don't need type checking, only definitions
+            ExternalDeclaration::FunctionDeclaration(func_decl) => {
+                // Only the attributes of a prototype need checking
+                let target = AttributeTarget::Function { parameter_count: func_decl.parameters.len() };
+                self.check_attributes(&func_decl.attributes, target, &func_decl.name);
+            }
+            ExternalDeclaration::Struct(definition) => {
+                self.check_struct_definition(definition);
+            }
+            // Incomplete until its definition, which `structs` waits for
+            ExternalDeclaration::StructDeclaration(..) => {}
+            ExternalDeclaration::Enum(definition) => {
+                self.check_enum_definition(definition);
+            }
+        }
+    }
+
+    /// Enumerator values must be integer constants; they may use the
+    /// enumerators before them
+    fn check_enum_definition(&mut self, definition: &EnumDefinition) {
+        if let Some(tag) = &definition.name
+            && self.layouts.enum_members(tag).is_some()
+        {
+            self.record_error(TypeChkError::EnumRedefined, &definition.head());
+            return;
+        }
+        self.layouts.add_enum(definition);
+        for enumerator in &definition.enumerators {
+            let Some(value) = &enumerator.value else {
+                continue;
+            };
+            if value.integer_constant_value_in(&self.layouts).is_none() {
+                self.at(enumerator.span, |checker| {
+                    checker.check_expression(value);
+                    checker.record_error(TypeChkError::EnumeratorNotConstant, &enumerator.name);
+                });
+            }
+        }
+    }
+
+    /// Members need complete types and constant sizes, with a flexible array
+    /// member, if any, last and after at least one other member
+    fn check_struct_definition(&mut self, definition: &StructDefinition) {
+        if self.structs.contains_key(&definition.name) {
+            self.record_error(TypeChkError::StructRedefined, &format!("struct {}", definition.name));
+            return;
+        }
+        let mut names = HashSet::new();
+        for (index, member) in definition.members.iter().enumerate() {
+            self.at(member.span, |checker| {
+                let name = &member.declarator.name;
+                checker.check_attributes(&member.attributes, AttributeTarget::Variable, name);
+                if !names.insert(name.as_str()) {
+                    checker.record_error(TypeChkError::DuplicateMember, name);
+                }
+                checker.check_object_type(member, ObjectPlace::Member);
+                checker.check_array_sizes(&member.declarator.array_sizes, name);
+                if is_flexible_array(member) {
+                    let context = format!("{}[]", name);
+                    if index + 1 < definition.members.len() {
+                        checker.record_error(TypeChkError::FlexibleArrayNotLast, &context);
+                    } else if index == 0 {
+                        checker.record_error(TypeChkError::FlexibleArrayOnlyMember, &context);
+                    }
+                } else if member.declarator.is_variable_length() {
+                    checker.record_error(TypeChkError::VariableLengthMember, name);
+                }
+            });
+        }
+        // Defined from the closing brace on, so a member can't be of its own type
+        self.structs.insert(definition.name.clone(), definition.clone());
+        self.layouts.add(definition);
+    }
+
+    /// An object of struct type needs the struct defined, and one ending in
+    /// a flexible array member can only be a standalone object. A tentative
+    /// definition at file scope, `struct node head;`, only needs the struct
+    /// defined by the end of the unit.
+    fn check_object_type(&mut self, declaration: &VariableDeclaration, place: ObjectPlace) {
+        if declaration.declarator.pointer_depth > 0
+            || matches!(declaration.storage_class, Some(StorageClass::Extern | StorageClass::Typedef))
+        {
+            return;
+        }
+        // An enum is complete once its enumerators are in
+        if let TypeSpecifier::Enum(tag) = &declaration.type_specifier
+            && self.layouts.enum_members(tag).is_none()
+        {
+            self.record_error(TypeChkError::UndefinedEnum, &format!("enum {} {}", tag, declaration.declarator.name));
+        }
+        let TypeSpecifier::Struct(tag) = &declaration.type_specifier else {
+            return;
+        };
+        // Spelled as in the source, to find its line
+        let context = format!("struct {} {}", tag, declaration.declarator.name);
+        let tentative = place == ObjectPlace::FileScope
+            && declaration.storage_class.is_none()
+            && declaration.initializer.is_none()
+            && self.unit_structs.contains(tag);
+        if !self.structs.contains_key(tag) {
+            if !tentative {
+                self.record_error(TypeChkError::UndefinedStruct, &context);
+            }
+        } else if (place == ObjectPlace::Member || !declaration.declarator.array_sizes.is_empty())
+            && self.has_flexible_array(&declaration.type_specifier)
+        {
+            self.record_error(TypeChkError::FlexibleArrayStructNested, &context);
+        }
+    }
+
+    /// Whether a type is a struct that ends in a flexible array member
+    fn has_flexible_array(&self, type_specifier: &TypeSpecifier) -> bool {
+        match type_specifier {
+            TypeSpecifier::Struct(tag) => {
+                self.structs.get(tag).is_some_and(|definition| definition.flexible_member().is_some())
+            }
+            _ => false,
+        }
+    }
+
+    /// Diagnose attributes that are unknown, misplaced or given bad arguments;
+    /// `owner` names what they are attached to
+    fn check_attributes(&mut self, attributes: &[Attribute], target: AttributeTarget, owner: &str) {
+        for attribute in attributes {
+            let name = attribute.base_name();
+            let on_function = matches!(target, AttributeTarget::Function { .. });
+            let applies = match name {
+                "noreturn" | "format" | "always_inline" | "noinline" | "const" | "pure" | "cold" | "hot" => on_function,
+                // Structs take no attributes yet, so `packed` has nothing to lay out
+                "packed" => false,
+                _ => KNOWN_ATTRIBUTES.contains(&name),
+            };
+            if !applies {
+                self.record_warning(TypeChkWarning::IgnoredAttribute, &attribute.name);
+                continue;
+            }
+            let valid = match (name, target) {
+                ("aligned", _) => match attribute.arguments.len() {
+                    0 => true,
+                    1 => integer_argument(attribute.arguments.first())
+                        .is_some_and(|alignment| alignment > 0 && (alignment as u64).is_power_of_two()),
+                    _ => false,
+                },
+                ("format", AttributeTarget::Function { parameter_count }) => {
+                    let archetype = matches!(attribute.arguments.first(), Some(Expression::Identifier(_)));
+                    let format_index = integer_argument(attribute.arguments.get(1));
+                    let first_argument = integer_argument(attribute.arguments.get(2));
+                    match (format_index, first_argument) {
+                        (Some(format_index), Some(first_argument)) => {
+                            archetype
+                                && attribute.arguments.len() == 3
+                                && format_index >= 1
+                                && format_index as usize <= parameter_count
+                                && (first_argument == 0 || first_argument > format_index)
+                        }
+                        _ => false,
+                    }
+                }
+                _ => true,
+            };
+            if !valid {
+                self.record_error(TypeChkError::InvalidAttributeArgument, owner);
             }
         }
     }
 
-    fn check_variable_declaration(&mut self, var_decl: &VariableDeclaration) {
-        let var_type = self.type_specifier_to_type(&var_decl.type_specifier);
+    fn check_variable_declaration(&mut self, var_decl: &VariableDeclaration, place: ObjectPlace) {
+        self.check_attributes(&var_decl.attributes, AttributeTarget::Variable, &var_decl.declarator.name);
+        // An array is checked as the pointer it decays to, which a string
+        // literal initializer also is
+        let pointee = var_decl.declarator.pointee_type(&var_decl.type_specifier);
+        let var_type = self.object_type(
+            &var_decl.type_specifier,
+            pointee.as_ref(),
+            var_decl.declarator.pointer_depth + var_decl.declarator.array_sizes.len() as u32,
+        );
         
         // Check if variable type is valid
         if var_type == Type::Unknown {
             self.record_error(TypeChkError::ErroneousVarDecl, &var_decl.declarator.name);
         }
+        self.check_object_type(var_decl, place);
+        if var_decl.type_qualifiers.contains(&TypeQualifier::Restrict) && var_decl.declarator.pointer_depth == 0 {
+            self.record_error(TypeChkError::RestrictOnNonPointer, &format!("restrict {}", var_decl.declarator.name));
+        }
+        self.check_array_sizes(&var_decl.declarator.array_sizes, &var_decl.declarator.name);
+        if var_decl.declarator.is_variable_length() && var_decl.initializer.is_some() {
+            self.record_error(TypeChkError::VariableLengthArrayInitialized, &var_decl.declarator.name);
+        }
 
         // Check initializer if present
-        if let Some(initializer) = &var_decl.initializer {
-            if let Some(init_type) = self.check_initializer(initializer) {
-                // Check if initializer type is compatible with variable type
-                if init_type != Type::Unknown && !self.are_types_compatible(&var_type, &init_type) {
-                    self.record_error(TypeChkError::ExpressionTypeMismatch, &var_decl.declarator.name);
+        match var_decl.initializer.as_ref().map(|initializer| &initializer.kind) {
+            Some(InitializerKind::Assignment(expr)) => {
+                self.check_initializer_value(&var_type, expr, &var_decl.declarator.name);
+            }
+            Some(InitializerKind::List(items)) => {
+                let element =
+                    self.object_type(&var_decl.type_specifier, pointee.as_ref(), var_decl.declarator.pointer_depth);
+                let target = self.object_shape(element, &var_decl.declarator.array_sizes);
+                self.check_brace_initializer(&target, items, &var_decl.declarator.name);
+            }
+            // The parser only makes these inside a brace list
+            Some(InitializerKind::Designated(..)) | None => {}
+        }
+
+        // Objects with static storage are initialized before the program
+        // runs, so their initializers go into the data section as they are
+        if let Some(initializer) = &var_decl.initializer
+            && (self.at_file_scope() || matches!(var_decl.storage_class, Some(StorageClass::Static)))
+            && !initializer.expressions().into_iter().all(|expr| self.is_constant_initializer(expr))
+        {
+            self.record_error(TypeChkError::NonConstantInitializer, &var_decl.declarator.name);
+        }
+    }
+
+    fn at_file_scope(&self) -> bool {
+        self.current_scope
+            .as_ref()
+            .is_some_and(|scope| Rc::ptr_eq(scope, self.scope_analyzer.get_global_scope()))
+    }
+
+    /// Whether an expression can initialize an object with static storage:
+    /// an arithmetic constant, or an address known before the program runs
+    fn is_constant_initializer(&self, expr: &Expression) -> bool {
+        self.is_arithmetic_constant(expr) || self.is_address_constant(expr)
+    }
+
+    /// Literals, enumerators, sizeof, and casts to arithmetic types,
+    /// combined by operators
+    fn is_arithmetic_constant(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Constant(_) | Expression::Folded(..) => true,
+            Expression::Identifier(name) => self.is_enumerator(name),
+            Expression::SizeofType(_) | Expression::UnaryOp(UnaryOperator::Sizeof, _) => expr.is_integer_constant(),
+            Expression::Paren(inner) => self.is_arithmetic_constant(inner),
+            Expression::UnaryOp(UnaryOperator::Plus | UnaryOperator::Minus | UnaryOperator::Not | UnaryOperator::BitNot, operand) => {
+                self.is_arithmetic_constant(operand)
+            }
+            Expression::BinaryOp(left, _, right) => self.is_arithmetic_constant(left) && self.is_arithmetic_constant(right),
+            Expression::Conditional(condition, then_value, else_value) => {
+                self.is_arithmetic_constant(condition)
+                    && self.is_arithmetic_constant(then_value)
+                    && self.is_arithmetic_constant(else_value)
+            }
+            Expression::Cast(target, operand) => target.derivations.is_empty() && self.is_arithmetic_constant(operand),
+            _ => false,
+        }
+    }
+
+    /// String literals, functions, and arrays or the address of objects
+    /// with static storage, give or take an integer constant
+    fn is_address_constant(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::StringLiteral(_) | Expression::EncodedStringLiteral(..) => true,
+            Expression::Identifier(name) => match self.current_scope.as_ref().and_then(|scope| scope.lookup(name)) {
+                Some(symbol) => match symbol.kind {
+                    SymbolKind::Function { .. } => true,
+                    SymbolKind::Variable { array_dimensions, .. } => array_dimensions > 0 && self.is_static(name),
+                    SymbolKind::Parameter { .. } | SymbolKind::Enumerator { .. } => false,
+                },
+                None => false,
+            },
+            Expression::UnaryOp(UnaryOperator::AddressOf, operand) => self.has_static_storage(operand),
+            Expression::Paren(inner) => self.is_address_constant(inner),
+            Expression::Cast(_, operand) => self.is_constant_initializer(operand),
+            Expression::BinaryOp(left, BinaryOperator::Plus, right) => {
+                (self.is_address_constant(left) && right.is_integer_constant())
+                    || (left.is_integer_constant() && self.is_address_constant(right))
+            }
+            Expression::BinaryOp(left, BinaryOperator::Minus, right) => {
+                self.is_address_constant(left) && right.is_integer_constant()
+            }
+            // Only a literal outside any function has static storage itself
+            Expression::CompoundLiteral(literal) => {
+                self.at_file_scope()
+                    && literal.initializers.iter().all(|item| {
+                        item.expressions().into_iter().all(|expr| self.is_constant_initializer(expr))
+                    })
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether an lvalue designates (part of) an object with static storage
+    fn has_static_storage(&self, lvalue: &Expression) -> bool {
+        match lvalue {
+            Expression::Identifier(name) => self.is_static(name),
+            Expression::Paren(inner) | Expression::MemberAccess(inner, _) => self.has_static_storage(inner),
+            Expression::ArrayAccess(array, index) => index.is_integer_constant() && self.has_static_storage(array),
+            Expression::StringLiteral(_) | Expression::EncodedStringLiteral(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Functions, globals and `static` locals, by the name in scope here
+    fn is_static(&self, name: &str) -> bool {
+        match self.current_scope.as_ref().and_then(|scope| scope.lookup(name)) {
+            Some(symbol) => match symbol.kind {
+                SymbolKind::Function { .. } => true,
+                SymbolKind::Variable { storage_class, .. } => {
+                    symbol.scope_level == 0 || matches!(storage_class, Some(StorageClass::Static | StorageClass::Extern))
+                }
+                SymbolKind::Parameter { .. } | SymbolKind::Enumerator { .. } => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Array dimensions: constant ones must be positive, the rest integers
+    fn check_array_sizes(&mut self, sizes: &[Option<Expression>], owner: &str) {
+        for size in sizes.iter().flatten() {
+            match size.integer_constant_value_in(&self.layouts) {
+                Some(length) if length <= 0 => {
+                    self.record_error(TypeChkError::ArraySizeNotPositive, owner);
                 }
+                Some(_) => {}
+                // Evaluated at run time, each time the declaration is reached
+                None => match self.check_expression(size) {
+                    Some(t) if t != Type::Unknown && !self.is_integer_type(&t) => {
+                        self.record_error(TypeChkError::ArraySizeNotInteger, owner);
+                    }
+                    _ => {}
+                },
             }
-            // If check_initializer returns None, error was already reported in check_expression
         }
     }
 
-    fn check_initializer(&mut self, initializer: &Initializer) -> Option<Type> {
-        match &initializer.kind {
-            InitializerKind::Assignment(expr) => {
-                self.check_expression(expr)
+    /// The shape of an object of `element` type with array dimensions
+    /// `sizes`; lengths that aren't positive constants are unknown
+    fn object_shape(&self, element: Type, sizes: &[Option<Expression>]) -> ObjectShape {
+        let base = match element {
+            Type::Struct(tag) => ObjectShape::Struct(tag),
+            scalar => ObjectShape::Scalar(scalar),
+        };
+        sizes.iter().rev().fold(base, |shape, size| {
+            let length = size
+                .as_ref()
+                .and_then(|size| size.integer_constant_value_in(&self.layouts))
+                .filter(|&length| length > 0)
+                .map(|length| length as usize);
+            ObjectShape::Array(Box::new(shape), length)
+        })
+    }
+
+    /// Members a brace list initializes, in order; a flexible array member
+    /// can't be initialized. None if the struct isn't defined.
+    fn member_shapes(&self, tag: &str) -> Option<Vec<(String, ObjectShape)>> {
+        let definition = self.structs.get(tag)?;
+        let members = definition
+            .members
+            .iter()
+            .filter(|member| !is_flexible_array(member))
+            .map(|member| {
+                let element = self.pointer_to(
+                    self.type_specifier_to_type(&member.type_specifier),
+                    member.declarator.pointer_depth,
+                );
+                (member.declarator.name.clone(), self.object_shape(element, &member.declarator.array_sizes))
+            })
+            .collect();
+        Some(members)
+    }
+
+    /// Check a brace list against the object it initializes, element by
+    /// element and member by member
+    fn check_brace_initializer(&mut self, target: &ObjectShape, items: &[Initializer], owner: &str) {
+        // `char s[4] = {"abc"}` is the string with braces around it
+        if let (ObjectShape::Array(element, _), [item]) = (target, items)
+            && matches!(**element, ObjectShape::Scalar(_))
+            && matches!(&item.kind, InitializerKind::Assignment(expr) if is_string_literal(expr))
+        {
+            self.fill_element(target, items, &mut 0, owner);
+            return;
+        }
+        self.fill_object(target, items, &mut 0, true, owner);
+    }
+
+    /// Initialize `target` from `items[*position..]`. A braced object takes
+    /// the rest of its list and reports what doesn't fit; one whose braces
+    /// were left out (`int m[2][2] = {1, 2, 3, 4}`) takes only the items it
+    /// has room for, and a designator ends it, leaving the rest to the
+    /// enclosing object.
+    fn fill_object(&mut self, target: &ObjectShape, items: &[Initializer], position: &mut usize, braced: bool, owner: &str) {
+        match target {
+            // `int x = {1};`
+            ObjectShape::Scalar(_) => self.fill_element(target, items, position, owner),
+            ObjectShape::Array(element, length) => {
+                let mut next = 0;
+                while let Some(item) = items.get(*position) {
+                    if let InitializerKind::Designated(designator, value) = &item.kind {
+                        if !braced {
+                            return;
+                        }
+                        *position += 1;
+                        let index = match designator {
+                            Designator::Array(index) => {
+                                self.check_expression(index);
+                                index.integer_constant_value_in(&self.layouts)
+                            }
+                            Designator::Member(_) => None,
+                        };
+                        match index {
+                            Some(index) if index >= 0 && length.is_none_or(|length| (index as usize) < length) => {
+                                self.fill_designated(element, value, owner);
+                                next = index as usize + 1;
+                            }
+                            _ => {
+                                self.record_error(TypeChkError::InvalidDesignator, owner);
+                                self.skip_initializer(value);
+                            }
+                        }
+                        continue;
+                    }
+                    if length.is_some_and(|length| next >= length) {
+                        if !braced {
+                            return;
+                        }
+                        break;
+                    }
+                    self.fill_element(element, items, position, owner);
+                    next += 1;
+                }
             }
-            InitializerKind::List(initializers) => {
-                // For list initializers, check all elements
-                for init in initializers {
-                    self.check_initializer(init);
+            ObjectShape::Struct(tag) => {
+                let Some(members) = self.member_shapes(tag) else {
+                    // Already reported where the object was declared; only
+                    // the expressions themselves can be checked
+                    let end = if braced { items.len() } else { *position + 1 };
+                    for item in &items[*position..end] {
+                        self.skip_initializer(item);
+                    }
+                    *position = end;
+                    return;
+                };
+                let mut next = 0;
+                while let Some(item) = items.get(*position) {
+                    if let InitializerKind::Designated(designator, value) = &item.kind {
+                        if !braced {
+                            return;
+                        }
+                        *position += 1;
+                        match designator {
+                            Designator::Member(name) => match members.iter().position(|(member, _)| member == name) {
+                                Some(index) => {
+                                    self.fill_designated(&members[index].1, value, owner);
+                                    next = index + 1;
+                                }
+                                None => {
+                                    self.record_error(TypeChkError::NoSuchMember, &format!(".{}", name));
+                                    self.skip_initializer(value);
+                                }
+                            },
+                            Designator::Array(index) => {
+                                self.check_expression(index);
+                                self.record_error(TypeChkError::InvalidDesignator, owner);
+                                self.skip_initializer(value);
+                            }
+                        }
+                        continue;
+                    }
+                    let Some((_, member)) = members.get(next) else {
+                        if !braced {
+                            return;
+                        }
+                        break;
+                    };
+                    self.fill_element(member, items, position, owner);
+                    next += 1;
                 }
-                // Return type of first element or None
-                if let Some(first) = initializers.first() {
-                    self.check_initializer(first)
-                } else {
-                    None
+            }
+        }
+        if braced && *position < items.len() {
+            self.record_error(TypeChkError::ExcessInitializers, owner);
+            for item in &items[*position..] {
+                self.skip_initializer(item);
+            }
+            *position = items.len();
+        }
+    }
+
+    /// Initialize one element or member from `items[*position]`, or, when
+    /// that is a bare value for an aggregate, from as many items as it takes
+    fn fill_element(&mut self, target: &ObjectShape, items: &[Initializer], position: &mut usize, owner: &str) {
+        let item = &items[*position];
+        match (&item.kind, target) {
+            (InitializerKind::List(inner), _) => {
+                *position += 1;
+                self.check_brace_initializer(target, inner, owner);
+            }
+            (InitializerKind::Designated(..), _) => {
+                *position += 1;
+                self.record_error(TypeChkError::InvalidDesignator, owner);
+                self.skip_initializer(item);
+            }
+            (InitializerKind::Assignment(expr), ObjectShape::Scalar(element)) => {
+                *position += 1;
+                self.check_initializer_value(element, expr, owner);
+            }
+            // A string literal fills a whole char array
+            (InitializerKind::Assignment(expr), ObjectShape::Array(element, _))
+                if is_string_literal(expr) && matches!(**element, ObjectShape::Scalar(_)) =>
+            {
+                *position += 1;
+                if let ObjectShape::Scalar(element) = &**element {
+                    self.check_initializer_value(&self.pointer_to(element.clone(), 1), expr, owner);
                 }
             }
-            InitializerKind::Designated(_designator, init) => {
-                self.check_initializer(init)
+            (InitializerKind::Assignment(expr), ObjectShape::Struct(tag)) if self.is_struct_value(expr, tag) => {
+                *position += 1;
+            }
+            (InitializerKind::Assignment(_), _) => self.fill_object(target, items, position, false, owner),
+        }
+    }
+
+    /// The initializer after a designator, which may be another designator:
+    /// `[1].x = 3`
+    fn fill_designated(&mut self, target: &ObjectShape, value: &Initializer, owner: &str) {
+        let items = std::slice::from_ref(value);
+        if let InitializerKind::Designated(..) = value.kind {
+            self.fill_object(target, items, &mut 0, true, owner);
+        } else {
+            self.fill_element(target, items, &mut 0, owner);
+        }
+    }
+
+    /// Whether a bare value initializes a whole struct member or element,
+    /// rather than its first scalar. Whatever checking it reports is dropped
+    /// when it doesn't, since the value is checked again as that scalar.
+    fn is_struct_value(&mut self, expr: &Expression, tag: &str) -> bool {
+        let (errors, warnings) = (self.errors.len(), self.warnings.len());
+        let whole = self.check_expression(expr) == Some(Type::Struct(tag.to_string()));
+        if !whole {
+            self.errors.truncate(errors);
+            self.warnings.truncate(warnings);
+        }
+        whole
+    }
+
+    fn check_initializer_value(&mut self, target: &Type, expr: &Expression, owner: &str) {
+        // None means the error was already reported in check_expression
+        if let Some(value_type) = self.check_expression(expr)
+            && value_type != Type::Unknown
+        {
+            if !self.is_assignable(target, expr, &value_type, owner) {
+                self.record_error(TypeChkError::ExpressionTypeMismatch, owner);
+            } else {
+                self.check_constant_range(target, expr, owner);
+            }
+        }
+    }
+
+    /// `char c = 300;`: an integer constant the initialized type is too
+    /// small for, going by its size in the data layout. An unsigned type
+    /// also takes a negative value down to its signed counterpart's least,
+    /// as `unsigned char mask = -1;` does, since that keeps every bit.
+    fn check_constant_range(&mut self, target: &Type, expr: &Expression, owner: &str) {
+        let (Some((_, unsigned)), Some(value)) = (integer_rank(target), expr.integer_constant_value_in(&self.layouts)) else {
+            return;
+        };
+        let bits = 8 * self.layouts.type_name_size(&target.to_string()) as u32;
+        // Constants are 64 bits, so a 64-bit type holds any of them
+        if bits >= 64 {
+            return;
+        }
+        let least = -(1i64 << (bits - 1));
+        let (low, high) = if unsigned { (0, (1i64 << bits) - 1) } else { (least, -least - 1) };
+        if value < least || value > high {
+            let wrapped = value.rem_euclid(1i64 << bits);
+            let stored = if wrapped > high { wrapped - (1i64 << bits) } else { wrapped };
+            let warning = TypeChkWarning::ConstantOutOfRange { target: target.to_string(), value, stored, range: (low, high) };
+            self.record_warning(warning, owner);
+        }
+    }
+
+    /// Check the expressions of an initializer that has nothing to initialize
+    fn skip_initializer(&mut self, item: &Initializer) {
+        for expr in item.expressions() {
+            self.check_expression(expr);
+        }
+    }
+
+    /// `(type){items}`: checked like a declaration of that type, and
+    /// typed like one used in an expression, so arrays decay
+    fn check_compound_literal(&mut self, literal: &CompoundLiteral) -> Option<Type> {
+        // Spelled as in the source, to find its line
+        let owner = format!("({}{}", literal.type_name, if literal.array_sizes.is_empty() { "){" } else { "[" });
+        let element = self.string_to_type(&literal.type_name);
+        if element == Type::Unknown {
+            self.record_error(TypeChkError::ErroneousVarDecl, &owner);
+            return Some(Type::Unknown);
+        }
+        self.check_array_sizes(&literal.array_sizes, &owner);
+        if literal.array_sizes.iter().flatten().any(|size| !size.is_integer_constant()) {
+            self.record_error(TypeChkError::VariableLengthArrayInitialized, &owner);
+        }
+        let dimensions = literal.array_sizes.len();
+        let target = self.object_shape(element.clone(), &literal.array_sizes);
+        self.check_brace_initializer(&target, &literal.initializers, &owner);
+        Some(self.pointer_to(element, dimensions as u32))
+    }
+
+    /// An executable needs `int main(void)` or `int main(int argc, char **argv)`
+    fn check_entry_point(&mut self, unit: &TranslationUnit) {
+        let main_def = unit.external_declarations.iter().find_map(|decl| match decl {
+            ExternalDeclaration::Function(func_def) if func_def.name == "main" => Some(func_def),
+            _ => None,
+        });
+
+        let main_def = match main_def {
+            Some(func_def) => func_def,
+            None => {
+                self.errors.push(TypeError {
+                    error: TypeChkError::MissingMainFunction,
+                    line: None,
+                    context: "main".to_string(),
+                });
+                return;
             }
+        };
+
+        self.line = main_def.name_span.map(|span| span.line);
+        if self.string_to_type(&main_def.return_type) != Type::Int {
+            self.record_error(TypeChkError::InvalidMainReturnType, "main");
+        }
+
+        let param_types: Vec<Type> = main_def.parameters
+            .iter()
+            .map(|param| self.string_to_type(&param.param_type))
+            .collect();
+        let char_ptr_ptr = Type::Pointer(Box::new(Type::Pointer(Box::new(Type::Char))));
+        let valid_params = param_types.is_empty() || param_types == [Type::Int, char_ptr_ptr];
+        if !valid_params {
+            self.record_error(TypeChkError::InvalidMainSignature, "main");
         }
     }
 
+    /// Errors about the function as a whole are placed at its name
     fn check_function_definition(&mut self, func_def: &FunctionDefinition) {
+        self.at(func_def.name_span, |checker| checker.check_function_definition_node(func_def));
+    }
+
+    fn check_function_definition_node(&mut self, func_def: &FunctionDefinition) {
         // Set current return type for return statement checking
         let return_type_str = &func_def.return_type;
         self.current_return_type = Some(self.string_to_type(return_type_str));
 
-        // Find function scope from all_scopes (function scope has level 1 and contains all parameters)
-        // The function scope can contain parameters AND variables declared in the function body
-        let function_scope = self.scope_analyzer.get_all_scopes()
-            .iter()
-            .find(|scope| {
-                scope.scope_level == 1 && {
-                    let symbols = scope.symbols.borrow();
-                    // Check if this scope contains all the function's parameters
-                    // (it can also contain other symbols like variables)
-                    func_def.parameters.iter().all(|param| symbols.contains_key(&param.name))
-                }
-            })
-            .cloned();
+        // The scope the analyzer made for this definition; a function
+        // defined twice has one for each
+        let nth = self.definitions_checked.entry(func_def.name.clone()).or_default();
+        let function_scope = self.scope_analyzer.function_scope(&func_def.name, *nth).cloned();
+        *nth += 1;
 
         // Save current scope and set to function scope
         let saved_scope = self.current_scope.clone();
@@ -160,6 +1222,24 @@ impl TypeChecker {
             self.current_scope = Some(func_scope);
         }
 
+        let target = AttributeTarget::Function { parameter_count: func_def.parameters.len() };
+        self.check_attributes(&func_def.attributes, target, &func_def.name);
+        // A definition passes and returns structs by value, so needs them complete
+        let by_value = std::iter::once((&func_def.return_type, &func_def.name))
+            .chain(func_def.parameters.iter().map(|param| (&param.param_type, &param.name)));
+        for (type_str, name) in by_value {
+            if let Type::Struct(tag) = self.string_to_type(type_str)
+                && !self.structs.contains_key(&tag)
+            {
+                self.record_error(TypeChkError::UndefinedStruct, &format!("struct {} {}", tag, name));
+            }
+        }
+        for param in &func_def.parameters {
+            if param.type_qualifiers.contains(&TypeQualifier::Restrict) && !param.param_type.ends_with('*') {
+                self.record_error(TypeChkError::RestrictOnNonPointer, &format!("restrict {}", param.name));
+            }
+        }
+
         // Check function body
         let saved_in_loop = self.in_loop;
         self.in_loop = false;
@@ -171,9 +1251,16 @@ impl TypeChecker {
             }
         }
 
-        // Check if non-void function has return statement
-        if let Some(ref ret_type) = self.current_return_type {
-            if *ret_type != Type::Void && !has_return {
+        if func_def.is_noreturn() {
+            // Neither returning nor falling off the end is allowed; the
+            // missing return below is expected
+            if func_def.body.iter().any(contains_return) || self.completes_normally(&func_def.body) {
+                self.record_error(TypeChkError::NoreturnFunctionReturns, &func_def.name);
+            }
+        } else if let Some(ref ret_type) = self.current_return_type {
+            // Check if non-void function has return statement
+            let implicit_return = func_def.name == "main" && self.standard.main_returns_zero();
+            if *ret_type != Type::Void && !has_return && !implicit_return {
                 self.record_error(TypeChkError::ReturnStmtNotFound, &func_def.name);
             }
         }
@@ -183,18 +1270,186 @@ impl TypeChecker {
         self.current_scope = saved_scope;
     }
 
+    /// Whether control can reach the end of these statements: it can't past
+    /// a return, a call to a noreturn function or a loop that never exits
+    fn completes_normally(&self, statements: &[Statement]) -> bool {
+        statements.iter().all(|statement| self.statement_completes(statement))
+    }
+
+    fn statement_completes(&self, statement: &Statement) -> bool {
+        match &statement.kind {
+            StatementKind::Return(_) => false,
+            StatementKind::Expression(Expression::FunctionCall(callee, _)) => {
+                !callee.as_function_name().is_some_and(|name| self.noreturn_functions.contains(name))
+            }
+            StatementKind::Block(statements) => self.completes_normally(statements),
+            StatementKind::If(_, then_branch, Some(else_branch)) => {
+                self.statement_completes(then_branch) || self.statement_completes(else_branch)
+            }
+            StatementKind::While(condition, body) => !always_true(condition) || breaks_out(body),
+            StatementKind::For(_, condition, _, body) => {
+                !condition.as_ref().is_none_or(always_true) || breaks_out(body)
+            }
+            _ => true,
+        }
+    }
+
+    /// Warn at each label the statements since the previous one can run
+    /// into. Statements before the first label never run, so they can't.
+    fn check_fallthrough(&mut self, statements: &[Statement]) {
+        let mut section_falls_through = None;
+        for statement in statements {
+            let label = statement_label(statement);
+            if label.is_some() && section_falls_through == Some(true) {
+                self.record_warning(TypeChkWarning::ImplicitFallthrough, &label_context(label));
+            }
+            // Consecutive labels share the statements after the last one
+            let mut labeled = statement;
+            while let StatementKind::Case(_, inner) | StatementKind::Default(inner) | StatementKind::Label(_, inner) =
+                &labeled.kind
+            {
+                labeled = inner;
+            }
+            if label.is_some() || section_falls_through == Some(true) {
+                section_falls_through = Some(self.falls_through(labeled));
+            }
+        }
+    }
+
+    /// Warn about each enumerator no case handles, when switching on an
+    /// enum without a default
+    fn check_enum_coverage(&mut self, value: &Expression, labels: &SwitchLabels) {
+        let Some(tag) = self.enum_tag(value) else {
+            return;
+        };
+        let unhandled: Vec<String> = self
+            .layouts
+            .enum_members(&tag)
+            .unwrap_or_default()
+            .iter()
+            .filter(|(_, value)| !labels.values.contains(value))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in unhandled {
+            self.record_warning(TypeChkWarning::UnhandledEnumerator, &name);
+        }
+    }
+
+    /// The enum an expression's type is, if it is one: a variable, parameter
+    /// or enumerator of that enum, or a cast to it
+    fn enum_tag(&self, expr: &Expression) -> Option<String> {
+        match expr {
+            Expression::Paren(inner) => self.enum_tag(inner),
+            Expression::Cast(type_name, _) if type_name.derivations.is_empty() => {
+                type_name.specifier.strip_prefix("enum ").map(str::to_string)
+            }
+            Expression::Identifier(name) => {
+                let symbol = self.current_scope.as_ref()?.lookup(name)?;
+                match symbol.kind {
+                    SymbolKind::Variable {
+                        type_spec: TypeSpecifier::Enum(tag),
+                        pointer_depth: 0,
+                        array_dimensions: 0,
+                        ..
+                    } => Some(tag),
+                    // Spelled "enum color", or "enum color*" for a pointer
+                    SymbolKind::Parameter { param_type } => param_type
+                        .strip_prefix("enum ")
+                        .filter(|tag| tag.chars().all(|c| c.is_alphanumeric() || c == '_'))
+                        .map(str::to_string),
+                    SymbolKind::Enumerator { tag } => tag,
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `name` names an enumerator here, rather than a variable
+    fn is_enumerator(&self, name: &str) -> bool {
+        self.current_scope
+            .as_ref()
+            .and_then(|scope| scope.lookup(name))
+            .is_some_and(|symbol| matches!(symbol.kind, SymbolKind::Enumerator { .. }))
+    }
+
+    fn is_function(&self, name: &str) -> bool {
+        self.current_scope
+            .as_ref()
+            .and_then(|scope| scope.lookup(name))
+            .is_some_and(|symbol| matches!(symbol.kind, SymbolKind::Function { .. }))
+    }
+
+    /// Whether control can reach the end of a statement and go on into the
+    /// next case; break and an explicit fallthrough both stop it
+    fn falls_through(&self, statement: &Statement) -> bool {
+        match &statement.kind {
+            StatementKind::Break | StatementKind::Fallthrough | StatementKind::Goto(_) => false,
+            StatementKind::Block(statements) => statements.iter().all(|statement| self.falls_through(statement)),
+            StatementKind::If(_, then_branch, Some(else_branch)) => {
+                self.falls_through(then_branch) || self.falls_through(else_branch)
+            }
+            StatementKind::Case(_, statement)
+            | StatementKind::Default(statement)
+            | StatementKind::Label(_, statement) => {
+                self.falls_through(statement)
+            }
+            _ => self.statement_completes(statement),
+        }
+    }
+
+    /// Run `check` one nesting level deeper, or report the tree as too deep
+    /// (once) and return `fallback` instead of overflowing the stack
+    fn nested<T>(&mut self, fallback: T, check: impl FnOnce(&mut Self) -> T) -> T {
+        if self.depth >= MAX_CHECK_DEPTH {
+            if !self.errors.iter().any(|e| matches!(e.error, TypeChkError::TooDeeplyNested)) {
+                self.errors.push(TypeError {
+                    error: TypeChkError::TooDeeplyNested,
+                    line: None,
+                    context: String::new(),
+                });
+            }
+            return fallback;
+        }
+        self.depth += 1;
+        let result = check(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Enter the scope of a block, for loop or statement expression,
+    /// returning the scope to restore when leaving it. Blocks are checked
+    /// in the order the analyzer opened their scopes, so each is the next
+    /// child of the current scope not yet entered.
+    fn enter_block_scope(&mut self) -> Option<Rc<ScopeNode>> {
+        let saved_scope = self.current_scope.clone();
+        if let Some(current) = &self.current_scope {
+            let parent = Rc::as_ptr(current);
+            let entered = self.children_entered.entry(parent).or_default();
+            if let Some(child) = self.child_scopes.get(&parent).and_then(|children| children.get(*entered)).cloned() {
+                *entered += 1;
+                self.current_scope = Some(child);
+            }
+        }
+        saved_scope
+    }
+
     fn check_statement(&mut self, stmt: &Statement) -> bool {
+        self.at(stmt.span, |checker| checker.nested(false, |checker| checker.check_statement_node(stmt)))
+    }
+
+    fn check_statement_node(&mut self, stmt: &Statement) -> bool {
         // Returns true if statement is a return statement
-        match stmt {
-            Statement::Declaration(var_decl) => {
-                self.check_variable_declaration(var_decl);
+        match &stmt.kind {
+            StatementKind::Declaration(var_decl) => {
+                self.check_variable_declaration(var_decl, ObjectPlace::Block);
                 false
             }
-            Statement::Assignment(var_name, expr) => {
+            StatementKind::Assignment(var_name, expr) => {
                 // Get variable type from symbol table
                 if let Some(var_type) = self.get_variable_type(var_name) {
                     if let Some(expr_type) = self.check_expression(expr) {
-                        if expr_type != Type::Unknown && !self.are_types_compatible(&var_type, &expr_type) {
+                        if expr_type != Type::Unknown && !self.is_assignable(&var_type, expr, &expr_type, var_name) {
                             self.record_error(TypeChkError::ExpressionTypeMismatch, var_name);
                         }
                     }
@@ -202,7 +1457,7 @@ impl TypeChecker {
                 }
                 false
             }
-            Statement::Return(expr_opt) => {
+            StatementKind::Return(expr_opt) => {
                 if let Some(ret_type) = &self.current_return_type {
                     let ret_type_clone = ret_type.clone();
                     if ret_type_clone == Type::Void {
@@ -214,7 +1469,7 @@ impl TypeChecker {
                         // Non-void function must return a value
                         if let Some(expr) = expr_opt {
                             if let Some(expr_type) = self.check_expression(expr) {
-                                if expr_type != Type::Unknown && !self.are_types_compatible(&ret_type_clone, &expr_type) {
+                                if expr_type != Type::Unknown && !self.is_assignable(&ret_type_clone, expr, &expr_type, "return") {
                                     self.record_error(TypeChkError::ErroneousReturnType, "return");
                                 }
                             }
@@ -226,26 +1481,12 @@ impl TypeChecker {
                 }
                 true
             }
-            Statement::Expression(expr) => {
+            StatementKind::Expression(expr) => {
                 self.check_expression(expr);
                 false
             }
-            Statement::Block(statements) => {
-                // Enter block scope - find child scope of current scope
-                let saved_scope = self.current_scope.clone();
-                if let Some(current) = &self.current_scope {
-                    // Find a child scope (one level deeper)
-                    let child_scope = self.scope_analyzer.get_all_scopes()
-                        .iter()
-                        .find(|scope| {
-                            scope.scope_level == current.scope_level + 1 &&
-                            scope.parent.as_ref().map(|p| Rc::ptr_eq(p, current)).unwrap_or(false)
-                        })
-                        .cloned();
-                    if let Some(child) = child_scope {
-                        self.current_scope = Some(child);
-                    }
-                }
+            StatementKind::Block(statements) => {
+                let saved_scope = self.enter_block_scope();
 
                 let mut has_return = false;
                 for stmt in statements {
@@ -258,7 +1499,8 @@ impl TypeChecker {
                 self.current_scope = saved_scope;
                 has_return
             }
-            Statement::If(condition, then_stmt, else_stmt) => {
+            StatementKind::If(condition, then_stmt, else_stmt) => {
+                self.check_assignment_in_condition(condition);
                 // Condition must be boolean
                 if let Some(cond_type) = self.check_expression(condition) {
                     if cond_type != Type::Bool {
@@ -275,7 +1517,8 @@ impl TypeChecker {
                 };
                 then_returns && else_returns
             }
-            Statement::While(condition, body) => {
+            StatementKind::While(condition, body) => {
+                self.check_assignment_in_condition(condition);
                 // Condition must be boolean
                 if let Some(cond_type) = self.check_expression(condition) {
                     if cond_type != Type::Bool {
@@ -290,21 +1533,9 @@ impl TypeChecker {
                 self.in_loop = saved_in_loop;
                 false
             }
-            Statement::For(init, condition, update, body) => {
+            StatementKind::For(init, condition, update, body) => {
                 // Enter for loop scope
-                let saved_scope = self.current_scope.clone();
-                if let Some(current) = &self.current_scope {
-                    let for_scope = self.scope_analyzer.get_all_scopes()
-                        .iter()
-                        .find(|scope| {
-                            scope.scope_level == current.scope_level + 1 &&
-                            scope.parent.as_ref().map(|p| Rc::ptr_eq(p, current)).unwrap_or(false)
-                        })
-                        .cloned();
-                    if let Some(scope) = for_scope {
-                        self.current_scope = Some(scope);
-                    }
-                }
+                let saved_scope = self.enter_block_scope();
 
                 // Check initialization
                 if let Some(init_stmt) = init {
@@ -313,6 +1544,7 @@ impl TypeChecker {
 
                 // Condition must be boolean (if present)
                 if let Some(cond) = condition {
+                    self.check_assignment_in_condition(cond);
                     if let Some(cond_type) = self.check_expression(cond) {
                         if cond_type != Type::Bool {
                             self.record_error(TypeChkError::NonBooleanCondStmt, "for");
@@ -336,27 +1568,87 @@ impl TypeChecker {
                 self.current_scope = saved_scope;
                 false
             }
-            Statement::Break => {
-                if !self.in_loop {
+            StatementKind::Break => {
+                if !self.in_loop && self.switches.is_empty() {
                     self.record_error(TypeChkError::ErroneousBreak, "break");
                 }
                 false
             }
+            StatementKind::Switch(value, body) => {
+                if let Some(value_type) = self.check_expression(value)
+                    && value_type != Type::Unknown
+                    && !self.is_integer_type(&value_type)
+                {
+                    self.record_error(TypeChkError::SwitchOnNonInteger, "switch");
+                }
+                self.switches.push(SwitchLabels::default());
+                let body_returns = self.check_statement(body);
+                let labels = self.switches.pop().unwrap_or_default();
+                if !labels.has_default {
+                    self.check_enum_coverage(value, &labels);
+                }
+                if self.warn_implicit_fallthrough
+                    && let StatementKind::Block(statements) = &body.kind
+                {
+                    self.check_fallthrough(statements);
+                }
+                // Without a default, a value no case matches skips the body
+                labels.has_default && body_returns
+            }
+            StatementKind::Case(value, statement) => {
+                let constant = value.integer_constant_value_in(&self.layouts);
+                if constant.is_none() {
+                    self.check_expression(value);
+                    self.record_error(TypeChkError::CaseNotConstant, "case");
+                }
+                match self.switches.last_mut() {
+                    None => self.record_error(TypeChkError::CaseOutsideSwitch, "case"),
+                    Some(labels) => {
+                        if let Some(constant) = constant
+                            && !labels.values.insert(constant)
+                        {
+                            self.record_error(TypeChkError::DuplicateCase, &label_context(statement_label(stmt)));
+                        }
+                    }
+                }
+                self.check_statement(statement)
+            }
+            StatementKind::Default(statement) => {
+                match self.switches.last_mut() {
+                    None => self.record_error(TypeChkError::CaseOutsideSwitch, "default"),
+                    Some(labels) if labels.has_default => self.record_error(TypeChkError::DuplicateDefault, "default"),
+                    Some(labels) => labels.has_default = true,
+                }
+                self.check_statement(statement)
+            }
+            StatementKind::Label(_, statement) => self.check_statement(statement),
+            StatementKind::Goto(_) | StatementKind::Fallthrough | StatementKind::Empty => false,
         }
     }
 
     fn check_expression(&mut self, expr: &Expression) -> Option<Type> {
+        self.nested(Some(Type::Unknown), |checker| checker.check_expression_node(expr))
+    }
+
+    fn check_expression_node(&mut self, expr: &Expression) -> Option<Type> {
         match expr {
             Expression::Identifier(name) => {
-                // If variable not found, return Unknown (scope analyzer should have caught this)
+                // A function's name decays to a pointer to it; if variable
+                // not found, return Unknown (scope analyzer should have caught this)
+                if self.is_function(name) {
+                    return Some(Type::Pointer(Box::new(Type::Function)));
+                }
                 self.get_variable_type(name).or(Some(Type::Unknown))
             }
-            Expression::Constant(constant) => {
+            Expression::Constant(constant) | Expression::Folded(constant, _) => {
                 Some(self.constant_to_type(constant))
             }
             Expression::StringLiteral(_) => {
-                // String literals are char arrays/pointers, not simple char values
-                Some(Type::String)
+                // String literals are char arrays, which decay to char*
+                Some(Type::Pointer(Box::new(Type::Char)))
+            }
+            Expression::EncodedStringLiteral(encoding, _) => {
+                Some(Type::Pointer(Box::new(self.element_type(*encoding))))
             }
             Expression::BinaryOp(left, op, right) => {
                 self.check_binary_operation(left, op, right)
@@ -370,42 +1662,161 @@ impl TypeChecker {
             Expression::Conditional(condition, true_expr, false_expr) => {
                 self.check_conditional_expression(condition, true_expr, false_expr)
             }
-            Expression::FunctionCall(name, args) => {
-                self.check_function_call(name, args)
+            Expression::FunctionCall(callee, args) => {
+                match callee.as_function_name() {
+                    // A function pointer parameter shadows any function of that name
+                    Some(name) if self.get_variable_type(name).is_some() => self.check_indirect_call(callee, args),
+                    Some(name) => self.check_function_call(name, args),
+                    None => self.check_indirect_call(callee, args),
+                }
             }
             Expression::ArrayAccess(array, index) => {
                 self.check_array_access(array, index)
             }
-            Expression::MemberAccess(obj, _member) => {
-                self.check_expression(obj)
+            Expression::MemberAccess(obj, member) => {
+                let object_type = self.check_expression(obj)?;
+                self.check_member_access(object_type, member, false)
             }
-            Expression::PointerAccess(ptr, _member) => {
-                self.check_expression(ptr)
+            Expression::PointerAccess(ptr, member) => {
+                let pointer_type = self.check_expression(ptr)?;
+                self.check_member_access(pointer_type, member, true)
             }
             Expression::PostfixOp(expr, _op) => {
                 self.check_expression(expr)
             }
-            Expression::Cast(target_type, expr) => {
-                if let Some(_expr_type) = self.check_expression(expr) {
-                    Some(self.type_specifier_to_type(target_type))
-                } else {
-                    None
+            Expression::Paren(expr) => {
+                self.check_expression(expr)
+            }
+            Expression::Cast(target_type, expr) => self.check_cast(target_type, expr),
+            Expression::SizeofType(type_name) => {
+                for size in type_name.expressions() {
+                    self.check_expression(size);
                 }
+                if self.layouts.size_of(type_name).is_none() && !type_name.is_variable_length() {
+                    self.record_error(TypeChkError::SizeofIncompleteType, &format!("sizeof({})", type_name.spelling()));
+                }
+                Some(Type::Long)
+            }
+            Expression::StatementExpr(statements) => {
+                // The value is that of the last statement, if it is an expression
+                let saved_scope = self.enter_block_scope();
+                let mut value_type = Some(Type::Void);
+                for (i, stmt) in statements.iter().enumerate() {
+                    match &stmt.kind {
+                        StatementKind::Expression(expr) if i + 1 == statements.len() => {
+                            value_type = self.at(stmt.span, |checker| checker.check_expression(expr));
+                        }
+                        _ => {
+                            self.check_statement(stmt);
+                        }
+                    }
+                }
+                self.current_scope = saved_scope;
+                value_type
+            }
+            Expression::CompoundLiteral(literal) => self.check_compound_literal(literal),
+            Expression::Generic(controlling, associations) => {
+                self.check_generic_selection(controlling, associations)
             }
         }
     }
 
-    fn check_binary_operation(&mut self, left: &Expression, op: &BinaryOperator, right: &Expression) -> Option<Type> {
-        let left_type = match self.check_expression(left) {
-            Some(t) => t,
-            None => return None, // Error already reported
+    /// `(type)expr`: scalars convert to any scalar type, and anything can
+    /// be cast to void to discard it
+    fn check_cast(&mut self, target_type: &TypeName, expr: &Expression) -> Option<Type> {
+        let expr_type = self.check_expression(expr)?;
+        let target = self.type_name_to_type(target_type);
+        let is_scalar = |t: &Type| !matches!(t, Type::Struct(_) | Type::Void);
+        let valid_target = match target_type.derivations.first() {
+            Some(Derivation::Array(_) | Derivation::Function(..)) => false,
+            _ => target == Type::Void || target == Type::Unknown || is_scalar(&target),
         };
+        if !valid_target || (target != Type::Void && expr_type != Type::Unknown && !is_scalar(&expr_type)) {
+            self.record_error(TypeChkError::InvalidCast, &format!("({})", target_type.spelling()));
+            return Some(Type::Unknown);
+        }
+        Some(target)
+    }
+
+    /// The type of the association `_Generic` selects. Every association is
+    /// checked, though only the selected one is evaluated.
+    fn check_generic_selection(&mut self, controlling: &Expression, associations: &[GenericAssociation]) -> Option<Type> {
+        let controlling_type = self.check_expression(controlling)?;
+        let mut seen: Vec<Type> = Vec::new();
+        let mut selected = None;
+        let mut default = None;
+        for association in associations {
+            let value_type = self.check_expression(&association.value);
+            let Some(type_name) = &association.type_name else {
+                if default.is_some() {
+                    self.record_error(TypeChkError::GenericDuplicateAssociation, "default:");
+                }
+                default = Some(value_type);
+                continue;
+            };
+            let association_type = self.string_to_type(type_name);
+            if seen.contains(&association_type) {
+                self.record_error(TypeChkError::GenericDuplicateAssociation, &format!("{}:", type_name));
+                continue;
+            }
+            if association_type == controlling_type {
+                selected = Some(value_type);
+            }
+            seen.push(association_type);
+        }
+        if controlling_type == Type::Unknown {
+            return Some(Type::Unknown);
+        }
+        match selected.or(default) {
+            Some(value_type) => value_type,
+            None => {
+                self.record_error(TypeChkError::GenericNoMatch, "_Generic");
+                Some(Type::Unknown)
+            }
+        }
+    }
+
+    /// A chain like `a + b + c` nests down its left operands, one level per
+    /// operator however flat it is written, so it is checked from its
+    /// innermost operation out rather than by recursing down the chain
+    fn check_binary_operation(&mut self, left: &Expression, op: &BinaryOperator, right: &Expression) -> Option<Type> {
+        let mut operations = vec![(left, op, right)];
+        let mut innermost = left;
+        while let Expression::BinaryOp(left, op, right) = innermost {
+            operations.push((left, op, right));
+            innermost = left;
+        }
+        let mut value_type = self.check_expression(innermost)?; // None: error already reported
+        for (left, op, right) in operations.into_iter().rev() {
+            value_type = self.binary_operation_type(value_type, left, op, right)?;
+        }
+        Some(value_type)
+    }
+
+    /// The type of `left op right`, `left` being of `left_type`
+    fn binary_operation_type(
+        &mut self,
+        left_type: Type,
+        left: &Expression,
+        op: &BinaryOperator,
+        right: &Expression,
+    ) -> Option<Type> {
         let right_type = match self.check_expression(right) {
             Some(t) => t,
             None => return None, // Error already reported
         };
+        self.check_parentheses(left, op, right);
+        let (left_type, right_type) = match op {
+            BinaryOperator::And | BinaryOperator::Or => (left_type, right_type),
+            _ => (promoted(left_type), promoted(right_type)),
+        };
 
         match op {
+            BinaryOperator::Plus | BinaryOperator::Minus
+                if matches!((&left_type, &right_type), (Type::Pointer(_), _) | (_, Type::Pointer(_))) =>
+            {
+                self.check_pointer_arithmetic(op, left_type, right_type)
+            }
             // Arithmetic operators (require numeric types)
             BinaryOperator::Plus | BinaryOperator::Minus | BinaryOperator::Mult | BinaryOperator::Div => {
                 if !self.is_numeric_type(&left_type) || !self.is_numeric_type(&right_type) {
@@ -429,14 +1840,27 @@ impl TypeChecker {
                     self.record_error(TypeChkError::ExpressionTypeMismatch, "comparison");
                     return Some(Type::Unknown);
                 }
+                self.check_sign_compare(left, &left_type, op, right, &right_type);
                 Some(Type::Bool)
             }
             BinaryOperator::Equals | BinaryOperator::NotEquals => {
+                // Pointers compare against null pointer constants; other
+                // pointer/integer mixes are allowed with a warning
+                match (&left_type, &right_type) {
+                    (Type::Pointer(_), _) if self.is_null_pointer_constant(right) => return Some(Type::Bool),
+                    (_, Type::Pointer(_)) if self.is_null_pointer_constant(left) => return Some(Type::Bool),
+                    (Type::Pointer(_), t) | (t, Type::Pointer(_)) if self.is_integer_type(t) => {
+                        self.record_warning(TypeChkWarning::PointerIntComparison, "==");
+                        return Some(Type::Bool);
+                    }
+                    _ => {}
+                }
                 // Equality can work on any compatible types
                 if !self.are_types_compatible(&left_type, &right_type) {
                     self.record_error(TypeChkError::ExpressionTypeMismatch, "==");
                     return Some(Type::Unknown);
                 }
+                self.check_sign_compare(left, &left_type, op, right, &right_type);
                 Some(Type::Bool)
             }
             // Logical operators (require boolean operands)
@@ -466,14 +1890,80 @@ impl TypeChecker {
         }
     }
 
+    /// `p + n`, `n + p` and `p - n` are pointers like `p`, moved by `n`
+    /// elements; `p - q`, of two pointers to the same type, is the number of
+    /// elements between them, a ptrdiff_t, which is long
+    fn check_pointer_arithmetic(&mut self, op: &BinaryOperator, left_type: Type, right_type: Type) -> Option<Type> {
+        let context = op.symbol();
+        match (&left_type, &right_type, op) {
+            (Type::Pointer(pointee), Type::Pointer(other), BinaryOperator::Minus) => {
+                if pointee != other {
+                    self.record_error(TypeChkError::ExpressionTypeMismatch, context);
+                    return Some(Type::Unknown);
+                }
+                Some(if self.is_steppable(pointee, context) { Type::Long } else { Type::Unknown })
+            }
+            (Type::Pointer(pointee), t, _) | (t, Type::Pointer(pointee), BinaryOperator::Plus) if self.is_integer_type(t) => {
+                let pointer = if matches!(left_type, Type::Pointer(_)) { &left_type } else { &right_type };
+                Some(if self.is_steppable(pointee, context) { pointer.clone() } else { Type::Unknown })
+            }
+            (Type::Unknown, _, _) | (_, Type::Unknown, _) => Some(Type::Unknown),
+            _ => {
+                self.record_error(TypeChkError::AttemptedAddOpOnNonNumeric, context);
+                Some(Type::Unknown)
+            }
+        }
+    }
+
+    /// Pointer arithmetic moves by whole elements, so a pointer can only be
+    /// moved if what it points to has a size: not void, a function or a
+    /// struct that isn't defined yet
+    fn is_steppable(&mut self, pointee: &Type, context: &str) -> bool {
+        match pointee {
+            Type::Void => self.record_error(TypeChkError::ArithmeticOnVoidPointer, context),
+            Type::Function => self.record_error(TypeChkError::ArithmeticOnFunctionPointer, context),
+            _ => return self.is_complete_pointee(pointee, context),
+        }
+        false
+    }
+
+    /// Reading through a pointer, or indexing it, needs what it points to
+    /// complete: a struct only declared so far has no members or size yet
+    fn is_complete_pointee(&mut self, pointee: &Type, context: &str) -> bool {
+        match pointee {
+            Type::Struct(tag) if !self.structs.contains_key(tag) => {
+                self.record_error(TypeChkError::UndefinedStruct, context);
+                false
+            }
+            _ => true,
+        }
+    }
+
     fn check_unary_operation(&mut self, op: &UnaryOperator, expr: &Expression) -> Option<Type> {
+        // `&*p` is `p` itself, so what it points to needn't be complete
+        if let (UnaryOperator::AddressOf, Expression::UnaryOp(UnaryOperator::Dereference, pointer)) = (op, expr) {
+            return self.check_expression(pointer);
+        }
         let expr_type = match self.check_expression(expr) {
             Some(t) => t,
             None => return None, // Error already reported
         };
 
         match op {
+            UnaryOperator::Sizeof => {
+                // Arrays have decayed to pointers by now, so this is only
+                // wrong for values without a size
+                match &expr_type {
+                    Type::Void => self.record_error(TypeChkError::SizeofIncompleteType, "sizeof"),
+                    Type::Struct(tag) if !self.structs.contains_key(tag) => {
+                        self.record_error(TypeChkError::SizeofIncompleteType, "sizeof");
+                    }
+                    _ => {}
+                }
+                Some(Type::Long)
+            }
             UnaryOperator::Plus | UnaryOperator::Minus => {
+                let expr_type = promoted(expr_type);
                 if !self.is_numeric_type(&expr_type) {
                     self.record_error(TypeChkError::AttemptedAddOpOnNonNumeric, "unary +/-");
                     return Some(Type::Unknown);
@@ -488,17 +1978,42 @@ impl TypeChecker {
                 Some(Type::Bool)
             }
             UnaryOperator::BitNot => {
+                let expr_type = promoted(expr_type);
                 if !self.is_integer_type(&expr_type) {
                     self.record_error(TypeChkError::AttemptedBitOpOnNonNumeric, "~");
                     return Some(Type::Unknown);
                 }
                 Some(expr_type)
             }
-            UnaryOperator::AddressOf | UnaryOperator::Dereference => {
-                // Pointer operations - simplified, return the type
-                Some(expr_type)
+            UnaryOperator::AddressOf => {
+                if expr_type == Type::Unknown {
+                    return Some(Type::Unknown);
+                }
+                // `&f` is the pointer `f` already decays to
+                if matches!(expr, Expression::Identifier(name) if self.is_function(name)) {
+                    return Some(expr_type);
+                }
+                Some(Type::Pointer(Box::new(expr_type)))
+            }
+            UnaryOperator::Dereference => {
+                match expr_type {
+                    // The function `*fp` designates decays straight back to `fp`
+                    Type::Pointer(pointee) if *pointee == Type::Function => Some(Type::Pointer(pointee)),
+                    Type::Pointer(pointee) => {
+                        let context = format!("*{}", pretty::expression(expr));
+                        Some(if self.is_complete_pointee(&pointee, &context) { *pointee } else { Type::Unknown })
+                    }
+                    Type::Unknown => Some(Type::Unknown),
+                    _ => {
+                        self.record_error(TypeChkError::ExpressionTypeMismatch, "*");
+                        Some(Type::Unknown)
+                    }
+                }
             }
             UnaryOperator::PreIncrement | UnaryOperator::PreDecrement => {
+                if let Type::Pointer(pointee) = &expr_type {
+                    return Some(if self.is_steppable(pointee, "++/--") { expr_type } else { Type::Unknown });
+                }
                 if !self.is_numeric_type(&expr_type) {
                     self.record_error(TypeChkError::AttemptedAddOpOnNonNumeric, "++/--");
                     return Some(Type::Unknown);
@@ -520,12 +2035,24 @@ impl TypeChecker {
 
         match op {
             AssignmentOperator::Assign => {
-                if !self.are_types_compatible(&left_type, &right_type) {
+                if !self.is_assignable(&left_type, right, &right_type, "=") {
                     self.record_error(TypeChkError::ExpressionTypeMismatch, "=");
                     return Some(Type::Unknown);
                 }
                 Some(left_type)
             }
+            AssignmentOperator::PlusAssign | AssignmentOperator::MinusAssign if matches!(left_type, Type::Pointer(_)) => {
+                let op = if matches!(op, AssignmentOperator::PlusAssign) { BinaryOperator::Plus } else { BinaryOperator::Minus };
+                match self.check_pointer_arithmetic(&op, left_type.clone(), right_type)? {
+                    // `p -= q` would store a distance in a pointer
+                    Type::Pointer(_) => Some(left_type),
+                    Type::Unknown => Some(Type::Unknown),
+                    _ => {
+                        self.record_error(TypeChkError::ExpressionTypeMismatch, "-=");
+                        Some(Type::Unknown)
+                    }
+                }
+            }
             AssignmentOperator::PlusAssign | AssignmentOperator::MinusAssign |
             AssignmentOperator::MultAssign | AssignmentOperator::DivAssign => {
                 if !self.is_numeric_type(&left_type) || !self.is_numeric_type(&right_type) {
@@ -559,6 +2086,7 @@ impl TypeChecker {
     }
 
     fn check_conditional_expression(&mut self, condition: &Expression, true_expr: &Expression, false_expr: &Expression) -> Option<Type> {
+        self.check_assignment_in_condition(condition);
         // Condition must be boolean
         let cond_type = match self.check_expression(condition) {
             Some(t) => t,
@@ -591,35 +2119,192 @@ impl TypeChecker {
     fn check_function_call(&mut self, name: &str, args: &[Expression]) -> Option<Type> {
         // Look up function in symbol table - functions are always in global scope
         let global_scope = self.scope_analyzer.get_global_scope();
-        if let Some(symbol) = global_scope.lookup(name) {
-            if let SymbolKind::Function { parameters, return_type, .. } = &symbol.kind {
-                // Check parameter count
-                if args.len() != parameters.len() {
-                    self.record_error(TypeChkError::FnCallParamCount, name);
-                    // Still check parameter types for the parameters we have
-                }
-
-                // Check parameter types (check up to min of args.len() and parameters.len())
-                let min_len = args.len().min(parameters.len());
-                for i in 0..min_len {
-                    if let Some(arg_type) = self.check_expression(&args[i]) {
-                        let param_type = self.string_to_type(&parameters[i].param_type);
-                        if arg_type != Type::Unknown && !self.are_types_compatible(&param_type, &arg_type) {
-                            self.record_error(TypeChkError::FnCallParamType, name);
-                        }
-                    }
-                    // If check_expression returns None, error was already reported
+        let (parameters, variadic, return_type) = match global_scope.lookup(name).map(|symbol| symbol.kind) {
+            Some(SymbolKind::Function { parameters, variadic, return_type, .. }) => (parameters, variadic, return_type),
+            // Not a function
+            Some(_) => return None,
+            // Intrinsics are known without a declaration
+            None => match intrinsics::lookup(name) {
+                Some(intrinsic) => {
+                    let parameters = intrinsic
+                        .parameters
+                        .iter()
+                        .map(|(param_type, name)| Parameter {
+                            param_type: param_type.to_string(),
+                            name: name.to_string(),
+                            type_qualifiers: Vec::new(),
+                        })
+                        .collect();
+                    (parameters, false, intrinsic.return_type.to_string())
                 }
+                // Function not found (should have been caught by scope analyzer)
+                None => return None,
+            },
+        };
 
-                // Return function's return type
-                Some(self.string_to_type(return_type))
-            } else {
-                // Not a function
+        // Check parameter count; a variadic function takes any number more
+        if args.len() < parameters.len() || (!variadic && args.len() > parameters.len()) {
+            self.record_error(TypeChkError::FnCallParamCount, name);
+            // Still check parameter types for the parameters we have
+        }
+
+        // Check parameter types; extra arguments are still checked themselves
+        let mut arg_types = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let arg_type = self.check_expression(arg);
+            if let (Some(arg_type), Some(param)) = (&arg_type, parameters.get(i)) {
+                let param_type = self.string_to_type(&param.param_type);
+                if *arg_type != Type::Unknown
+                    && param_type != Type::Unknown
+                    && !self.is_assignable(&param_type, arg, arg_type, name)
+                {
+                    self.record_error(TypeChkError::FnCallParamType, name);
+                }
+            }
+            // If check_expression returns None, error was already reported
+            arg_types.push(arg_type);
+        }
+
+        if let Some(&(format_index, first_argument)) = self.format_functions.get(name) {
+            self.check_format_call(args, &arg_types, format_index, first_argument);
+        }
+
+        // Return function's return type
+        Some(self.string_to_type(&return_type))
+    }
+
+    /// Match a literal format string against the arguments that follow it,
+    /// for a function declared with the printf format attribute
+    fn check_format_call(&mut self, args: &[Expression], arg_types: &[Option<Type>], format_index: usize, first_argument: usize) {
+        let Some(Expression::StringLiteral(format)) = args.get(format_index - 1) else {
+            return; // Only literal formats can be checked
+        };
+        // The literal as written finds the line of the call; its conversions
+        // are read from the text it stands for
+        let context = format!("\"{}\"", format);
+        let text = lexer_regex::decode_string_text(format).unwrap_or_else(|_| format.clone());
+        let Some(expected) = format::printf_arguments(&text) else {
+            self.record_warning(TypeChkWarning::FormatMismatch, &context);
+            return;
+        };
+        // With a va_list (first_argument 0) the arguments aren't visible here
+        if first_argument == 0 {
+            return;
+        }
+        let given = arg_types.get(first_argument - 1..).unwrap_or(&[]);
+        let matches = expected.len() == given.len()
+            && expected.iter().zip(given).all(|(expected, given)| match given {
+                None | Some(Type::Unknown) => true,
+                Some(given) => match expected {
+                    FormatArgument::Integer => self.is_integer_type(given) || *given == Type::Bool,
+                    FormatArgument::Floating => matches!(given, Type::Float | Type::Double),
+                    FormatArgument::String => *given == Type::Pointer(Box::new(Type::Char)),
+                    FormatArgument::Pointer => matches!(given, Type::Pointer(_)),
+                },
+            });
+        if !matches {
+            self.record_warning(TypeChkWarning::FormatMismatch, &context);
+        }
+    }
+
+    /// `-Wsign-compare`: the operands of a comparison convert to an unsigned
+    /// type from a signed one, so a negative value compares as a huge one.
+    /// A constant that isn't negative converts to the same value, and an
+    /// unsigned type narrower than int is promoted to int, so neither warns.
+    fn check_sign_compare(&mut self, left: &Expression, left_type: &Type, op: &BinaryOperator, right: &Expression, right_type: &Type) {
+        if !self.warn_sign_compare {
+            return;
+        }
+        let (Some((_, left_unsigned)), Some((_, right_unsigned))) = (integer_rank(left_type), integer_rank(right_type)) else {
+            return;
+        };
+        let common = self.wider_type(left_type, right_type);
+        if left_unsigned == right_unsigned || !integer_rank(&common).is_some_and(|(rank, unsigned)| unsigned && rank >= 2) {
+            return;
+        }
+        let (signed, signed_type) = if left_unsigned { (right, right_type) } else { (left, left_type) };
+        if signed.integer_constant_value_in(&self.layouts).is_some_and(|value| value >= 0) {
+            return;
+        }
+        let warning = TypeChkWarning::SignCompare { signed: signed_type.to_string(), unsigned: common.to_string() };
+        let context = format!("{} {} {}", pretty::expression(left), op.symbol(), pretty::expression(right));
+        self.record_warning(warning, &context);
+    }
+
+    /// `-Wparentheses`: an operand that is itself an operation, unparenthesized,
+    /// of the kind that is often taken to group the other way
+    fn check_parentheses(&mut self, left: &Expression, op: &BinaryOperator, right: &Expression) {
+        if !self.warn_parentheses {
+            return;
+        }
+        for operand in [left, right] {
+            let Expression::BinaryOp(_, inner, _) = operand else {
+                continue;
+            };
+            if !is_misread_grouping(op, inner) {
+                continue;
+            }
+            // The expression with the grouping it has written out
+            let parenthesized = |side: &Expression| {
+                if std::ptr::eq(side, operand) {
+                    Box::new(Expression::Paren(Box::new(side.clone())))
+                } else {
+                    Box::new(side.clone())
+                }
+            };
+            let grouped = Expression::BinaryOp(parenthesized(left), op.clone(), parenthesized(right));
+            let warning = TypeChkWarning::Parentheses {
+                inner: inner.symbol().to_string(),
+                outer: op.symbol().to_string(),
+                grouped: pretty::expression(&grouped),
+            };
+            let context = format!("{} {} {}", pretty::expression(left), op.symbol(), pretty::expression(right));
+            self.record_warning(warning, &context);
+        }
+    }
+
+    fn check_indirect_call(&mut self, callee: &Expression, args: &[Expression]) -> Option<Type> {
+        // Function pointer types aren't modeled yet, so the signature is
+        // unknown: check the callee and arguments on their own
+        self.check_expression(callee);
+        for arg in args {
+            self.check_expression(arg);
+        }
+        Some(Type::Unknown)
+    }
+
+    /// `s.member`, or `p->member` when `through_pointer`; array members
+    /// decay like any other array. None once an error is reported.
+    fn check_member_access(&mut self, object_type: Type, member: &str, through_pointer: bool) -> Option<Type> {
+        let tag = match (object_type, through_pointer) {
+            (Type::Struct(tag), false) => Some(tag),
+            (Type::Pointer(pointee), true) => match *pointee {
+                Type::Struct(tag) => Some(tag),
+                Type::Unknown => return Some(Type::Unknown),
+                _ => None,
+            },
+            (Type::Unknown, _) => return Some(Type::Unknown),
+            _ => None,
+        };
+        let Some(tag) = tag else {
+            let operator = if through_pointer { "->" } else { "." };
+            self.record_error(TypeChkError::MemberAccessOnNonStruct, &format!("{}{}", operator, member));
+            return None;
+        };
+        let Some(definition) = self.structs.get(&tag) else {
+            self.record_error(TypeChkError::UndefinedStruct, member);
+            return None;
+        };
+        match definition.members.iter().find(|declaration| declaration.declarator.name == member) {
+            Some(declaration) => {
+                let depth = declaration.declarator.pointer_depth + declaration.declarator.array_sizes.len() as u32;
+                let pointee = declaration.declarator.pointee_type(&declaration.type_specifier);
+                Some(self.object_type(&declaration.type_specifier, pointee.as_ref(), depth))
+            }
+            None => {
+                self.record_error(TypeChkError::NoSuchMember, member);
                 None
             }
-        } else {
-            // Function not found (should have been caught by scope analyzer)
-            None
         }
     }
 
@@ -634,8 +2319,15 @@ impl TypeChecker {
             self.record_error(TypeChkError::ExpressionTypeMismatch, "[]");
         }
 
-        // Array access returns element type (simplified - assumes array type)
-        self.check_expression(array)
+        // Indexing a pointer yields its element type; other bases are treated
+        // as arrays of their own type until array declarators are typed
+        match self.check_expression(array) {
+            Some(Type::Pointer(element)) => {
+                let context = format!("{}[{}]", pretty::expression(array), pretty::expression(index));
+                Some(if self.is_complete_pointee(&element, &context) { *element } else { Type::Unknown })
+            }
+            other => other,
+        }
     }
 
     // Helper functions
@@ -644,12 +2336,15 @@ impl TypeChecker {
         let scope = self.current_scope.as_ref()?;
         if let Some(symbol) = scope.lookup(name) {
             match &symbol.kind {
-                SymbolKind::Variable { type_spec, .. } => {
-                    Some(self.type_specifier_to_type(type_spec))
+                SymbolKind::Variable { type_spec, pointer_depth, array_dimensions, pointee, .. } => {
+                    // Arrays, fixed or variable length, decay to pointers
+                    let depth = *pointer_depth + *array_dimensions as u32;
+                    Some(self.object_type(type_spec, pointee.as_ref(), depth))
                 }
                 SymbolKind::Parameter { param_type } => {
                     Some(self.string_to_type(param_type))
                 }
+                SymbolKind::Enumerator { .. } => Some(Type::Int),
                 _ => None,
             }
         } else {
@@ -666,68 +2361,189 @@ impl TypeChecker {
             TypeSpecifier::Short => Type::Short,
             TypeSpecifier::Long => Type::Long,
             TypeSpecifier::Void => Type::Void,
-            TypeSpecifier::Signed | TypeSpecifier::Unsigned => Type::Int, // Simplified
+            TypeSpecifier::Bool => Type::Bool,
+            TypeSpecifier::Signed => Type::Int,
+            TypeSpecifier::Unsigned => Type::UnsignedInt,
+            TypeSpecifier::UnsignedChar => Type::UnsignedChar,
+            TypeSpecifier::UnsignedShort => Type::UnsignedShort,
+            TypeSpecifier::UnsignedLong => Type::UnsignedLong,
+            TypeSpecifier::Struct(tag) => Type::Struct(tag.clone()),
+            TypeSpecifier::Enum(_) => Type::Int, // enums are stored as ints
         }
     }
 
+    fn pointer_to(&self, base: Type, depth: u32) -> Type {
+        (0..depth).fold(base, |t, _| Type::Pointer(Box::new(t)))
+    }
+
+    /// An object's type under `depth` pointers: to its specifier's type, or
+    /// to what a parenthesized declarator makes them point to
+    fn object_type(&self, type_spec: &TypeSpecifier, pointee: Option<&TypeName>, depth: u32) -> Type {
+        let base = match pointee {
+            Some(pointee) => self.type_name_to_type(pointee),
+            None => self.type_specifier_to_type(type_spec),
+        };
+        self.pointer_to(base, depth)
+    }
+
+    /// A function type is just Function, whatever its parameters and return
+    /// type; a pointer to one is a pointer to Function
+    fn type_name_to_type(&self, type_name: &TypeName) -> Type {
+        let mut t = self.string_to_type(&type_name.specifier);
+        for derivation in type_name.derivations.iter().rev() {
+            t = match (derivation, t) {
+                (_, Type::Unknown) => Type::Unknown,
+                (Derivation::Function(..), _) => Type::Function,
+                // Pointers and arrays alike; arrays decay where they're used
+                (Derivation::Pointer | Derivation::Array(_), t) => Type::Pointer(Box::new(t)),
+            };
+        }
+        t
+    }
+
     fn string_to_type(&self, type_str: &str) -> Type {
+        // Declarators that aren't just pointers are spelled like "int (*)(void)"
+        if type_str.contains(['(', '[']) {
+            return parser::parse_type_name(type_str).map_or(Type::Unknown, |t| self.type_name_to_type(&t));
+        }
+        // Parameter and return types are spelled like "char*"
+        if let Some(pointee) = type_str.strip_suffix('*') {
+            return match self.string_to_type(pointee.trim_end()) {
+                Type::Unknown => Type::Unknown,
+                t => Type::Pointer(Box::new(t)),
+            };
+        }
         match type_str {
-            "int" => Type::Int,
+            "int" | "signed" => Type::Int,
+            "unsigned" | "unsigned int" => Type::UnsignedInt,
+            "unsigned char" => Type::UnsignedChar,
+            "unsigned short" => Type::UnsignedShort,
+            "unsigned long" => Type::UnsignedLong,
             "float" => Type::Float,
             "double" => Type::Double,
             "char" => Type::Char,
             "short" => Type::Short,
             "long" => Type::Long,
             "void" => Type::Void,
-            _ => Type::Unknown,
+            "bool" | "_Bool" => Type::Bool,
+            _ if type_str.starts_with("enum ") => Type::Int,
+            _ => match type_str.strip_prefix("struct ") {
+                Some(tag) => Type::Struct(tag.trim().to_string()),
+                None => Type::Unknown,
+            },
         }
     }
 
     fn constant_to_type(&self, constant: &Constant) -> Type {
         match constant {
             Constant::Integer(_) => Type::Int,
-            Constant::Float(_) => Type::Float,
+            // There is no long double; it is checked as double
+            Constant::Float(_, FloatSuffix::Float) => Type::Float,
+            Constant::Float(..) => Type::Double,
             Constant::Char(_) => Type::Char,
+            Constant::Bool(_) => Type::Bool,
+            Constant::Null => Type::Pointer(Box::new(Type::Void)),
+            Constant::EncodedChar(encoding, _) => self.element_type(*encoding),
+        }
+    }
+
+    /// Element type of a prefixed literal: char16_t and char32_t are
+    /// unsigned, and wchar_t is int, as on Linux
+    fn element_type(&self, encoding: LiteralEncoding) -> Type {
+        match encoding {
+            LiteralEncoding::Utf8 => Type::Char,
+            LiteralEncoding::Utf16 => Type::UnsignedShort,
+            LiteralEncoding::Utf32 => Type::UnsignedInt,
+            LiteralEncoding::Wide => Type::Int,
         }
     }
 
     fn is_numeric_type(&self, t: &Type) -> bool {
-        matches!(t, Type::Int | Type::Float | Type::Double | Type::Char | Type::Short | Type::Long)
+        self.is_integer_type(t) || matches!(t, Type::Float | Type::Double)
     }
 
     fn is_integer_type(&self, t: &Type) -> bool {
-        matches!(t, Type::Int | Type::Char | Type::Short | Type::Long)
+        integer_rank(t).is_some()
     }
 
-    fn find_line_for_context(&self, context: &str) -> Option<usize> {
-        if context.is_empty() {
-            return None;
+    /// Run `check` with its errors placed on the line `span` starts on,
+    /// that of the statement or declaration it checks. A node made up
+    /// rather than parsed has no span and keeps the line around it.
+    fn at<T>(&mut self, span: Option<TokenSpan>, check: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = self.line;
+        if let Some(span) = span {
+            self.line = Some(span.line);
         }
-        self.source_lines
-            .iter()
-            .position(|line| line.contains(context))
-            .map(|idx| idx + 1)
+        let result = check(self);
+        self.line = outer;
+        result
     }
 
     fn record_error(&mut self, kind: TypeChkError, context: &str) {
-        let line = self.find_line_for_context(context);
         self.errors.push(TypeError {
             error: kind,
-            line,
+            line: self.line,
+            context: context.to_string(),
+        });
+    }
+
+    fn record_warning(&mut self, kind: TypeChkWarning, context: &str) {
+        self.warnings.push(TypeWarning {
+            warning: kind,
+            line: self.line,
             context: context.to_string(),
         });
     }
 
+    /// `if (x = 5)` is almost always a typo for `==`. Writing `if ((x = 5))`
+    /// wraps the assignment in a Paren node and says it was intended.
+    fn check_assignment_in_condition(&mut self, condition: &Expression) {
+        if let Expression::Assignment(_, AssignmentOperator::Assign, _) = condition {
+            let text = pretty::expression(condition);
+            let warning = TypeWarning { warning: TypeChkWarning::AssignmentInCondition, line: self.line, context: text };
+            self.warnings.push(warning);
+        }
+    }
+
+    /// `nullptr`, `NULL`, or a literal integer zero, or a #define of one
+    fn is_null_pointer_constant(&self, expr: &Expression) -> bool {
+        matches!(
+            expr,
+            Expression::Constant(Constant::Null | Constant::Integer(0)) | Expression::Folded(Constant::Null | Constant::Integer(0), _)
+        )
+    }
+
+    /// Can `expr` (of type `expr_type`) be stored into a `target`?
+    /// Null pointer constants convert to any pointer; other integer/pointer
+    /// mixes are accepted like C compilers do, but produce a warning.
+    fn is_assignable(&mut self, target: &Type, expr: &Expression, expr_type: &Type, context: &str) -> bool {
+        match (target, expr_type) {
+            (Type::Pointer(_), _) if self.is_null_pointer_constant(expr) => true,
+            (Type::Pointer(_), t) if self.is_integer_type(t) => {
+                self.record_warning(TypeChkWarning::IntToPointerConversion, context);
+                true
+            }
+            (t, Type::Pointer(_)) if self.is_integer_type(t) => {
+                self.record_warning(TypeChkWarning::PointerToIntConversion, context);
+                true
+            }
+            _ => self.are_types_compatible(target, expr_type),
+        }
+    }
+
     fn are_types_compatible(&self, t1: &Type, t2: &Type) -> bool {
         // Types are compatible if they're the same
         if t1 == t2 {
             return true;
         }
 
-        // String literals are not compatible with numeric types
-        if t1 == &Type::String || t2 == &Type::String {
-            // String can only be compatible with String or Char (for char*)
-            return t1 == &Type::String && t2 == &Type::String;
+        // Pointers are only compatible with pointers to the same type, or void*
+        match (t1, t2) {
+            (Type::Pointer(p1), Type::Pointer(p2)) => {
+                return **p1 == Type::Void || **p2 == Type::Void || p1 == p2;
+            }
+            (Type::Pointer(_), _) | (_, Type::Pointer(_)) => return false,
+            _ => {}
         }
 
         // Allow implicit conversions between numeric types
@@ -735,6 +2551,13 @@ impl TypeChecker {
             return true;
         }
 
+        // bool converts to and from integers (true == 1, nonzero -> true)
+        if (t1 == &Type::Bool && self.is_integer_type(t2))
+            || (t2 == &Type::Bool && self.is_integer_type(t1))
+        {
+            return true;
+        }
+
         false
     }
 
@@ -744,13 +2567,35 @@ impl TypeChecker {
         match (t1, t2) {
             (Type::Double, _) | (_, Type::Double) => Type::Double,
             (Type::Float, _) | (_, Type::Float) => Type::Float,
-            (Type::Long, _) | (_, Type::Long) => Type::Long,
-            (Type::Int, _) | (_, Type::Int) => Type::Int,
-            (Type::Short, _) | (_, Type::Short) => Type::Short,
-            _ => Type::Char,
+            // The higher rank, and unsigned over signed at the same one:
+            // each rank is wider than the one below, so a signed type holds
+            // every value of an unsigned one of lower rank
+            _ => match (integer_rank(t1), integer_rank(t2)) {
+                (Some(r1), Some(r2)) => if r1 >= r2 { t1.clone() } else { t2.clone() },
+                (Some(_), None) => t1.clone(),
+                (None, Some(_)) => t2.clone(),
+                (None, None) => Type::Char,
+            },
         }
     }
 
+    /// The type of an expression with `locals` in scope over the globals,
+    /// for a unit already checked; None if it can't be told. Nothing it
+    /// finds wrong is recorded.
+    pub fn expression_type(&mut self, expression: &Expression, locals: Vec<Symbol>) -> Option<Type> {
+        let scope = ScopeNode::new(Some(self.scope_analyzer.get_global_scope().clone()));
+        for symbol in locals {
+            scope.insert_symbol(symbol.name.clone(), symbol);
+        }
+        let saved_scope = self.current_scope.replace(Rc::new(scope));
+        let (errors, warnings) = (self.errors.len(), self.warnings.len());
+        let ty = self.check_expression(expression);
+        self.errors.truncate(errors);
+        self.warnings.truncate(warnings);
+        self.current_scope = saved_scope;
+        ty.filter(|ty| *ty != Type::Unknown)
+    }
+
     pub fn get_errors(&self) -> &[TypeError] {
         &self.errors
     }
@@ -758,5 +2603,9 @@ impl TypeChecker {
     pub fn has_errors(&self) -> bool {
         !self.errors.is_empty()
     }
+
+    pub fn get_warnings(&self) -> &[TypeWarning] {
+        &self.warnings
+    }
 }
 
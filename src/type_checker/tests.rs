@@ -0,0 +1,95 @@
+// tests.rs: Programs the type checker must accept or reject, each checked
+// as the driver checks it: parsed, scope-analyzed, then type-checked.
+
+use super::TypeChecker;
+use crate::lexer_regex;
+use crate::parser::Parser;
+use crate::scope::ScopeAnalyzer;
+use crate::source::SourceFile;
+use std::rc::Rc;
+
+/// The type errors a program gives, each as its kind and context
+fn type_errors(program: &str) -> Vec<String> {
+    let unit = Parser::new(lexer_regex::lex_with_regex(program)).parse().expect("program should parse");
+    let source = Rc::new(SourceFile::new("test.c", program));
+    let mut scope_analyzer = ScopeAnalyzer::with_source(source.clone());
+    let _ = scope_analyzer.analyze_translation_unit(&unit);
+    let mut type_checker = TypeChecker::new(scope_analyzer);
+    match type_checker.check_translation_unit(&unit) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors.iter().map(|error| format!("{:?}: {}", error.error, error.context)).collect(),
+    }
+}
+
+/// The line and kind of each type error, then of each warning, a program
+/// gives when parsed with its spans
+fn diagnostic_lines(program: &str) -> Vec<(Option<usize>, String)> {
+    let tokens = lexer_regex::lex_with_regex_positions(program, Default::default());
+    let unit = Parser::with_spans(tokens).parse().expect("program should parse");
+    let source = Rc::new(SourceFile::new("test.c", program));
+    let mut scope_analyzer = ScopeAnalyzer::with_source(source.clone());
+    let _ = scope_analyzer.analyze_translation_unit(&unit);
+    let mut type_checker = TypeChecker::new(scope_analyzer);
+    let _ = type_checker.check_translation_unit(&unit);
+    let errors = type_checker.get_errors().iter().map(|error| (error.line, format!("{:?}", error.error)));
+    let warnings = type_checker.get_warnings().iter().map(|warning| (warning.line, format!("{:?}", warning.warning)));
+    errors.chain(warnings).collect()
+}
+
+#[test]
+fn each_function_body_is_checked_in_its_own_scope() {
+    let programs = [
+        // No parameters at all, so every function's scope would do
+        "int h(void) { return 1; }\nint main(void) { int s = 5; return s + 1; }\n",
+        "int h() { int t = 2; return t; }\nint main() { int s = 5; return s + h(); }\n",
+        // The same parameter names, so both functions' scopes would do
+        "int f(int x) { int z = x; return z; }\nint g(int x) { int y = x; return y + 1; }\nint main(void) { return f(1) + g(2); }\n",
+        "int helper(int n) { return n * 2; }\n\
+         int main(void) { int total = 0; for (int i = 0; i < 3; i++) { total = total + helper(i); } return total; }\n",
+    ];
+    for program in programs {
+        assert_eq!(type_errors(program), Vec::<String>::new(), "{}", program);
+    }
+}
+
+#[test]
+fn locals_of_the_same_name_keep_their_own_function_types() {
+    // In f's scope, main's `s` would be an int and no condition
+    let program = "int f(void) { int s = 1; return s; }\nint main(void) { bool s = true; if (s) { return 1; } return 0; }\n";
+    assert_eq!(type_errors(program), Vec::<String>::new());
+}
+
+#[test]
+fn sibling_blocks_are_checked_in_their_own_scopes() {
+    let programs = [
+        // In the then block's scope, the else block's `w` is undeclared
+        "int main(void) { int p = 1; if (p > 0) { int a = 1; p = a; } else { int w = 0; while (w < 4) { w = w + 1; } } return p; }\n",
+        // The second loop's `b` would be the first's int
+        "int main(void) { for (int i = 0; i < 2; i++) { int b = i; } for (int j = 0; j < 2; j++) { bool b = j > 0; if (b) { return 1; } } return 0; }\n",
+    ];
+    for program in programs {
+        assert_eq!(type_errors(program), Vec::<String>::new(), "{}", program);
+    }
+}
+
+#[test]
+fn a_long_flat_chain_is_checked_without_nesting() {
+    let chain = vec!["1"; 2 * crate::parser::MAX_NESTING_DEPTH + 10].join(" + ");
+    let program = format!("int main(void) {{ int x = {}; return x; }}\n", chain);
+    assert_eq!(type_errors(&program), Vec::<String>::new());
+}
+
+#[test]
+fn diagnostics_are_at_the_lines_of_what_has_them() {
+    // `%` on a float, in a function after one that uses the same operator
+    let program = "int f(int a) {\n    return a % 2;\n}\nint main(void) {\n    float x = 1.5;\n    int y = x % 2;\n    return y;\n}\n";
+    assert_eq!(diagnostic_lines(program), vec![(Some(6), "AttemptedAddOpOnNonNumeric".to_string())]);
+
+    // The bad struct, not a valid one before it
+    let program = "struct S { int n; int data[]; };\nstruct Bad {\n    int data[];\n    int n;\n};\nint main(void) { return 0; }\n";
+    assert_eq!(diagnostic_lines(program), vec![(Some(3), "FlexibleArrayNotLast".to_string())]);
+
+    // The switch that misses one, not the enum
+    let program = "enum E { A, B };\nint main(void) {\n    enum E e = A;\n    switch (e) {\n    case A: return 1;\n    }\n    return 0;\n}\n";
+    assert_eq!(diagnostic_lines(program), vec![(Some(4), "UnhandledEnumerator".to_string())]);
+}
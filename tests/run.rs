@@ -0,0 +1,180 @@
+// run.rs: `hello_rust run`, driven as a user drives it: a program is
+// written to a file, run, and what it printed and its exit status looked at.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// What running a program printed, and the status the driver exited with
+fn run(name: &str, program: &str, flags: &[&str]) -> (String, i32) {
+    let path: PathBuf = std::env::temp_dir().join(format!("hello_rust_run_{}_{}.c", std::process::id(), name));
+    fs::write(&path, program).expect("Failed to write program");
+    let output = Command::new(env!("CARGO_BIN_EXE_hello_rust"))
+        .arg("run")
+        .arg(&path)
+        .args(flags)
+        .output()
+        .expect("Failed to run hello_rust");
+    fs::remove_file(&path).ok();
+    (String::from_utf8_lossy(&output.stdout).into_owned(), output.status.code().unwrap_or(-1))
+}
+
+#[test]
+fn defines_are_folded_before_the_checks() {
+    let program = r#"#include <stdio.h>
+#define N 3
+int main(void) {
+    int a[N];
+    int i;
+    for (i = 0; i < N; i++) {
+        a[i] = i * 2;
+    }
+    printf("%d\n", a[N - 1]);
+    return 0;
+}
+"#;
+    let (output, status) = run("defines", program, &[]);
+    assert!(output.starts_with("4\n"), "{}", output);
+    assert_eq!(status, 0, "{}", output);
+}
+
+#[test]
+fn a_program_with_several_functions_runs() {
+    let program = r#"#include <stdio.h>
+int helper(int n) {
+    return n * 2;
+}
+int main(void) {
+    int total = 0;
+    for (int i = 0; i < 3; i++) {
+        total = total + helper(i);
+    }
+    printf("%d\n", total * 2);
+    return 0;
+}
+"#;
+    let (output, status) = run("functions", program, &[]);
+    assert!(output.starts_with("12\n"), "{}", output);
+    assert_eq!(status, 0, "{}", output);
+}
+
+#[test]
+fn a_program_the_checks_reject_is_not_run() {
+    let program = r#"#include <stdio.h>
+int main(void) {
+    printf("ran\n");
+    int x = 1;
+    if (x) {
+        return 1;
+    }
+    return 0;
+}
+"#;
+    let (output, status) = run("rejected", program, &[]);
+    assert!(output.contains("Non-boolean condition"), "{}", output);
+    assert!(!output.contains("ran"), "{}", output);
+    assert_eq!(status, 1);
+}
+
+#[test]
+fn warnings_made_errors_stop_the_run() {
+    let program = "int main(void) {\n    int x = 3000000000;\n    return 0;\n}\n";
+    let (output, status) = run("werror", program, &[]);
+    assert_eq!(status, 0, "{}", output);
+    let (output, status) = run("werror", program, &["-Werror=overflow"]);
+    assert!(output.contains("[-Werror=overflow]"), "{}", output);
+    assert_eq!(status, 1);
+}
+
+#[test]
+fn heap_misuse_is_reported_with_its_allocation_site() {
+    let program = r#"#include <stdlib.h>
+int main(void) {
+    int *p = malloc(4 * sizeof(int));
+    int *q = malloc(8);
+    p[0] = 7;
+    free(p);
+    free(p);
+    return 0;
+}
+"#;
+    let (output, status) = run("heap", program, &[]);
+    assert!(output.contains("Memory Error (line 7 in 'main'): double free"), "{}", output);
+    assert!(output.contains("allocated by malloc at line 3 in 'main', freed at line 6 in 'main'"), "{}", output);
+    assert!(output.contains("Leak: 8 byte(s) in 1 block(s) allocated by malloc at line 4 in 'main', never freed"), "{}", output);
+    assert_eq!(status, 0, "{}", output);
+}
+
+#[test]
+fn an_out_of_bounds_write_is_reported_at_its_own_line() {
+    // The statements around the write name the same arrays
+    let program = r#"int main(void) {
+    int a[4]; int b[4];
+    int i = 4;
+    b[0] = 1;
+    a[i] = b[0];
+    a[0] = 2;
+    return 0;
+}
+"#;
+    let (output, _) = run("oob", program, &["--memcheck"]);
+    assert!(output.contains("Memory Error (line 5 in 'main'): out-of-bounds write"), "{}", output);
+}
+
+#[test]
+fn byte_escapes_in_strings_keep_their_values() {
+    let program = r#"#include <stdio.h>
+int main(void) {
+    char t[] = "\xff";
+    char e[] = "\351t";
+    printf("%d %d %d %d\n", (int)sizeof(t), t[0], (int)sizeof(e), e[0]);
+    return 0;
+}
+"#;
+    let (output, status) = run("escapes", program, &[]);
+    assert!(output.starts_with("2 -1 3 -23\n"), "{}", output);
+    assert_eq!(status, 0, "{}", output);
+}
+
+#[test]
+fn redeclared_globals_are_one_object() {
+    let program = r#"#include <stdio.h>
+extern int counter;
+int counter = 1;
+int total = 5;
+int total;
+int main(void) {
+    printf("%d %d\n", counter, total);
+    return 0;
+}
+"#;
+    let (output, status) = run("globals", program, &[]);
+    assert!(output.starts_with("1 5\n"), "{}", output);
+    assert_eq!(status, 0, "{}", output);
+    let (output, status) = run("globals_twice", "int x = 1;\nint x = 2;\nint main(void) {\n    return x;\n}\n", &[]);
+    assert!(output.contains("Variable 'x' redefined in same scope"), "{}", output);
+    assert_eq!(status, 1);
+}
+
+#[test]
+fn named_declarators_can_point_to_functions_and_arrays() {
+    let program = r#"#include <stdio.h>
+int twice(int a) { return 2 * a; }
+int square(int a) { return a * a; }
+int (*chosen)(int) = square;
+int main(void) {
+    int (*fp)(int) = twice;
+    int (*table[2])(int);
+    char buf[10];
+    char (*pb)[10] = &buf;
+    table[0] = square;
+    (*pb)[0] = 'h';
+    (*pb)[1] = 0;
+    printf("%d %d %d %d %s %d\n", fp(3), (*fp)(4), table[0](5), chosen(6), buf, (int)sizeof(*pb));
+    return 0;
+}
+"#;
+    let (output, status) = run("declarators", program, &[]);
+    assert!(output.starts_with("6 8 25 36 h 10\n"), "{}", output);
+    assert_eq!(status, 0, "{}", output);
+}